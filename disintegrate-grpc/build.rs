@@ -0,0 +1,20 @@
+//! Generates the server and the client of the `EventSubscription` service.
+//!
+//! The messages are defined in `src/proto.rs` with the prost derives, mirroring `proto/subscription.proto`,
+//! so the crate is built without `protoc`.
+fn main() {
+    let subscribe = tonic_build::manual::Method::builder()
+        .name("subscribe")
+        .route_name("Subscribe")
+        .input_type("crate::proto::SubscribeRequest")
+        .output_type("crate::proto::SubscribedEvent")
+        .codec_path("tonic::codec::ProstCodec")
+        .server_streaming()
+        .build();
+    let service = tonic_build::manual::Service::builder()
+        .name("EventSubscription")
+        .package("disintegrate.subscription")
+        .method(subscribe)
+        .build();
+    tonic_build::manual::Builder::new().compile(&[service]);
+}
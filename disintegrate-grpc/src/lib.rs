@@ -0,0 +1,18 @@
+//! # gRPC Disintegrate Integration Library
+//!
+//! This library exposes the events of a Disintegrate event store to other services through gRPC.
+//! `EventSubscriptionService` implements the `EventSubscription` service defined in `proto/subscription.proto`:
+//! a client subscribes to the events matching its event types and domain identifiers, and resumes the
+//! subscription after the last event it has received.
+mod generated {
+    include!(concat!(
+        env!("OUT_DIR"),
+        "/disintegrate.subscription.EventSubscription.rs"
+    ));
+}
+pub mod proto;
+mod subscription;
+
+pub use crate::generated::event_subscription_client::EventSubscriptionClient;
+pub use crate::generated::event_subscription_server::{EventSubscription, EventSubscriptionServer};
+pub use crate::subscription::EventSubscriptionService;
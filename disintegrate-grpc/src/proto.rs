@@ -0,0 +1,48 @@
+//! The messages of the `EventSubscription` service, defined in `proto/subscription.proto`.
+use std::collections::HashMap;
+
+/// The request of a subscription.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SubscribeRequest {
+    /// The types of the streamed events, or all the types if empty.
+    #[prost(string, repeated, tag = "1")]
+    pub event_types: Vec<String>,
+    /// The domain identifiers the streamed events are equal to.
+    #[prost(message, repeated, tag = "2")]
+    pub identifiers: Vec<IdentifierFilter>,
+    /// The ID of the last event received, to resume the subscription after it, or empty to start from the
+    /// beginning of the event stream.
+    #[prost(string, tag = "3")]
+    pub cursor: String,
+}
+
+/// A domain identifier the streamed events are equal to.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct IdentifierFilter {
+    /// The name of the domain identifier.
+    #[prost(string, tag = "1")]
+    pub name: String,
+    /// The value of the domain identifier.
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+/// An event streamed by a subscription.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SubscribedEvent {
+    /// The ID of the event, which is the cursor resuming the subscription after it.
+    #[prost(string, tag = "1")]
+    pub id: String,
+    /// The type of the event.
+    #[prost(string, tag = "2")]
+    pub event_type: String,
+    /// The version of the event.
+    #[prost(uint32, tag = "3")]
+    pub version: u32,
+    /// The domain identifiers of the event.
+    #[prost(map = "string, string", tag = "4")]
+    pub identifiers: HashMap<String, String>,
+    /// The event serialized by the serde of the service.
+    #[prost(bytes = "vec", tag = "5")]
+    pub payload: Vec<u8>,
+}
@@ -0,0 +1,398 @@
+//! Event subscription service
+//!
+//! The service streams the events of an event store matching the query of a client: the types of the events
+//! and the values of their domain identifiers. The events are streamed in order, first those already in the
+//! event store and then the ones appended afterwards, which are found by polling the event store. Each event
+//! carries its ID, which the client passes as the cursor of a new subscription to resume after it.
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use disintegrate::{
+    query, Cursor, DomainIdentifierSet, Event, EventId, EventStore, IdentifierType, PersistedEvent,
+    StreamFilter, StreamQuery,
+};
+use disintegrate_serde::Serializer;
+use futures::stream::BoxStream;
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+use crate::proto::{SubscribeRequest, SubscribedEvent};
+use crate::{EventSubscription, EventSubscriptionServer};
+
+/// The `EventSubscription` service backed by an event store.
+pub struct EventSubscriptionService<ID, E, ES, S> {
+    event_store: Arc<ES>,
+    serde: Arc<S>,
+    poll_interval: Duration,
+    page_size: usize,
+    event_type: PhantomData<fn() -> (ID, E)>,
+}
+
+impl<ID, E, ES, S> std::fmt::Debug for EventSubscriptionService<ID, E, ES, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventSubscriptionService")
+            .field("poll_interval", &self.poll_interval)
+            .field("page_size", &self.page_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<ID, E, ES, S> EventSubscriptionService<ID, E, ES, S>
+where
+    ID: EventId + FromStr,
+    E: Event + Clone + Send + Sync + 'static,
+    ES: EventStore<ID, E> + Send + Sync + 'static,
+    ES::Error: Display,
+    S: Serializer<E> + Send + Sync + 'static,
+{
+    /// Creates a new `EventSubscriptionService`.
+    ///
+    /// The event store is polled every second for the appended events, which are read in pages of 100 events.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_store` - The event store of the streamed events.
+    /// * `serde` - The serde serializing the payloads of the streamed events.
+    pub fn new(event_store: ES, serde: S) -> Self {
+        Self {
+            event_store: Arc::new(event_store),
+            serde: Arc::new(serde),
+            poll_interval: Duration::from_secs(1),
+            page_size: 100,
+            event_type: PhantomData,
+        }
+    }
+
+    /// Sets the interval between the polls of the event store, once a subscription has streamed all the events.
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_interval` - The interval between the polls.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets the number of events read from the event store at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `page_size` - The number of events of a page.
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Wraps the service in a server, to be added to a `tonic` router.
+    pub fn into_server(self) -> EventSubscriptionServer<Self> {
+        EventSubscriptionServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl<ID, E, ES, S> EventSubscription for EventSubscriptionService<ID, E, ES, S>
+where
+    ID: EventId + FromStr,
+    E: Event + Clone + Send + Sync + 'static,
+    ES: EventStore<ID, E> + Send + Sync + 'static,
+    ES::Error: Display,
+    S: Serializer<E> + Send + Sync + 'static,
+{
+    type SubscribeStream = BoxStream<'static, Result<SubscribedEvent, Status>>;
+
+    async fn subscribe(
+        &self,
+        request: Request<SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let request = request.into_inner();
+        let query = subscription_query::<ID, E>(&request).map_err(Status::invalid_argument)?;
+        let mut cursor = parse_cursor::<ID>(&request.cursor).map_err(Status::invalid_argument)?;
+        let event_store = Arc::clone(&self.event_store);
+        let serde = Arc::clone(&self.serde);
+        let poll_interval = self.poll_interval;
+        let page_size = self.page_size;
+        let events = async_stream::try_stream! {
+            loop {
+                let (events, next) = event_store
+                    .stream_page(&query, cursor, page_size)
+                    .await
+                    .map_err(|err| Status::internal(err.to_string()))?;
+                for event in events {
+                    cursor = Cursor::After(event.id());
+                    yield subscribed_event(event, serde.as_ref());
+                }
+                if next.is_end() {
+                    tokio::time::sleep(poll_interval).await;
+                }
+            }
+        };
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+/// Builds the stream query of a subscription from the event types and the domain identifiers of the request.
+///
+/// Returns the reason why the request is invalid on failure.
+fn subscription_query<ID, E>(request: &SubscribeRequest) -> Result<StreamQuery<ID, E>, String>
+where
+    ID: EventId,
+    E: Event + Clone,
+{
+    let mut filter = StreamFilter::<ID, E>::new(DomainIdentifierSet::new(BTreeMap::new()));
+    for identifier in &request.identifiers {
+        let info = E::SCHEMA
+            .domain_identifiers
+            .iter()
+            .find(|info| *info.ident == identifier.name)
+            .ok_or_else(|| format!("unknown domain identifier: {}", identifier.name))?;
+        let invalid_value = |err: &dyn Display| {
+            format!(
+                "invalid value of the domain identifier {}: {err}",
+                identifier.name
+            )
+        };
+        filter = match info.type_info {
+            IdentifierType::String => filter.with_identifier(info.ident, identifier.value.as_str()),
+            IdentifierType::i64 => filter.with_identifier(
+                info.ident,
+                identifier
+                    .value
+                    .parse::<i64>()
+                    .map_err(|err| invalid_value(&err))?,
+            ),
+            IdentifierType::Uuid => filter.with_identifier(
+                info.ident,
+                Uuid::parse_str(&identifier.value).map_err(|err| invalid_value(&err))?,
+            ),
+        };
+    }
+    if let Some(unknown) = request
+        .event_types
+        .iter()
+        .find(|event_type| !E::SCHEMA.events.contains(&event_type.as_str()))
+    {
+        return Err(format!("unknown event type: {unknown}"));
+    }
+    let query = query(Some(filter));
+    if request.event_types.is_empty() {
+        return Ok(query);
+    }
+    let excluded_events: Vec<&'static str> = E::SCHEMA
+        .events
+        .iter()
+        .filter(|event| {
+            !request
+                .event_types
+                .iter()
+                .any(|event_type| event_type == *event)
+        })
+        .copied()
+        .collect();
+    Ok(query.extend_excluded_events(&excluded_events))
+}
+
+/// Parses the cursor of a request: the ID of the last event received, or empty to start from the beginning.
+fn parse_cursor<ID: EventId + FromStr>(cursor: &str) -> Result<Cursor<ID>, String> {
+    if cursor.is_empty() {
+        return Ok(Cursor::Start);
+    }
+    cursor
+        .parse()
+        .map(Cursor::After)
+        .map_err(|_| format!("invalid cursor: {cursor}"))
+}
+
+fn subscribed_event<ID, E, S>(event: PersistedEvent<ID, E>, serde: &S) -> SubscribedEvent
+where
+    ID: EventId,
+    E: Event + Clone,
+    S: Serializer<E>,
+{
+    SubscribedEvent {
+        id: event.id().to_string(),
+        event_type: event.name().to_string(),
+        version: event.version(),
+        identifiers: event
+            .domain_identifiers()
+            .iter()
+            .map(|(ident, value)| (ident.to_string(), value.to_string()))
+            .collect(),
+        payload: serde.serialize(event.into_inner()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use disintegrate::{InMemoryEventStore, UncheckedAppend};
+    use disintegrate_serde::serde::json::Json;
+    use disintegrate_serde::Deserializer;
+    use futures::StreamExt;
+    use serde::{Deserialize, Serialize};
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::{Channel, Server};
+    use tonic::Code;
+
+    use super::*;
+    use crate::proto::IdentifierFilter;
+    use crate::EventSubscriptionClient;
+
+    #[derive(Event, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(tag = "event_type")]
+    enum CourseEvent {
+        CourseCreated {
+            #[id]
+            course_id: String,
+            seats: u32,
+        },
+        StudentSubscribed {
+            #[id]
+            course_id: String,
+            #[id]
+            student_id: i64,
+        },
+    }
+
+    fn created(course_id: &str) -> CourseEvent {
+        CourseEvent::CourseCreated {
+            course_id: course_id.to_string(),
+            seats: 10,
+        }
+    }
+
+    fn subscribed(course_id: &str, student_id: i64) -> CourseEvent {
+        CourseEvent::StudentSubscribed {
+            course_id: course_id.to_string(),
+            student_id,
+        }
+    }
+
+    async fn serve(
+        event_store: InMemoryEventStore<i64, CourseEvent>,
+    ) -> EventSubscriptionClient<Channel> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let service = EventSubscriptionService::new(event_store, Json::<CourseEvent>::default())
+            .with_poll_interval(Duration::from_millis(10))
+            .with_page_size(2);
+        tokio::spawn(
+            Server::builder()
+                .add_service(service.into_server())
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        EventSubscriptionClient::connect(format!("http://{address}"))
+            .await
+            .unwrap()
+    }
+
+    fn append(event_store: &InMemoryEventStore<i64, CourseEvent>, events: Vec<CourseEvent>) {
+        event_store
+            .append_without_validation(events, UncheckedAppend::because("seeding the test events"))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_streams_the_matching_events_and_the_appended_ones() {
+        let event_store = InMemoryEventStore::new();
+        append(
+            &event_store,
+            vec![
+                created("c1"),
+                created("c2"),
+                subscribed("c1", 1),
+                subscribed("c2", 2),
+                subscribed("c1", 3),
+            ],
+        );
+        let mut client = serve(event_store.clone()).await;
+
+        let mut events = client
+            .subscribe(SubscribeRequest {
+                event_types: vec!["StudentSubscribed".to_string()],
+                identifiers: vec![IdentifierFilter {
+                    name: "course_id".to_string(),
+                    value: "c1".to_string(),
+                }],
+                cursor: String::new(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(event.id, "3");
+        assert_eq!(event.event_type, "StudentSubscribed");
+        assert_eq!(event.version, 1);
+        assert_eq!(event.identifiers["course_id"], "c1");
+        assert_eq!(event.identifiers["student_id"], "1");
+        assert_eq!(
+            Json::<CourseEvent>::default()
+                .deserialize(event.payload)
+                .unwrap(),
+            subscribed("c1", 1)
+        );
+        assert_eq!(events.next().await.unwrap().unwrap().id, "5");
+
+        append(&event_store, vec![subscribed("c2", 4), subscribed("c1", 5)]);
+
+        assert_eq!(events.next().await.unwrap().unwrap().id, "7");
+    }
+
+    #[tokio::test]
+    async fn it_resumes_a_subscription_after_the_cursor() {
+        let event_store = InMemoryEventStore::new();
+        append(
+            &event_store,
+            vec![created("c1"), created("c2"), created("c3")],
+        );
+        let mut client = serve(event_store).await;
+
+        let mut events = client
+            .subscribe(SubscribeRequest {
+                cursor: "1".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(events.next().await.unwrap().unwrap().id, "2");
+        assert_eq!(events.next().await.unwrap().unwrap().id, "3");
+    }
+
+    #[tokio::test]
+    async fn it_rejects_the_invalid_queries() {
+        let mut client = serve(InMemoryEventStore::new()).await;
+
+        for request in [
+            SubscribeRequest {
+                event_types: vec!["CourseClosed".to_string()],
+                ..Default::default()
+            },
+            SubscribeRequest {
+                identifiers: vec![IdentifierFilter {
+                    name: "teacher_id".to_string(),
+                    value: "t1".to_string(),
+                }],
+                ..Default::default()
+            },
+            SubscribeRequest {
+                identifiers: vec![IdentifierFilter {
+                    name: "student_id".to_string(),
+                    value: "s1".to_string(),
+                }],
+                ..Default::default()
+            },
+            SubscribeRequest {
+                cursor: "last".to_string(),
+                ..Default::default()
+            },
+        ] {
+            let status = client.subscribe(request).await.unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument);
+        }
+    }
+}
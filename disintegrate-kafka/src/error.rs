@@ -0,0 +1,9 @@
+use thiserror::Error;
+
+/// Represents all the ways a method can fail within Disintegrate Kafka.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Error returned from Kafka.
+    #[error(transparent)]
+    Kafka(#[from] rdkafka::error::KafkaError),
+}
@@ -0,0 +1,210 @@
+//! Kafka event forwarder
+//!
+//! The forwarder produces each event to a Kafka topic, serialized with the configured `Serde`.
+//! The ID and the type of the event are sent as the `event_id` and `event_type` headers of the message,
+//! so that the consumers can discard the duplicates and dispatch the events without deserializing them.
+use std::time::Duration;
+
+use async_trait::async_trait;
+use disintegrate::{Event, EventId, EventListener, Identifier, PersistedEvent, StreamQuery};
+use disintegrate_serde::Serializer;
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+
+use crate::Error;
+
+/// An `EventListener` producing the events of its query to a Kafka topic.
+///
+/// The events are delivered at least once: an event may be produced again if the listener fails before
+/// its checkpoint is updated.
+pub struct KafkaEventForwarder<ID: EventId, E: Event + Clone, S> {
+    id: &'static str,
+    query: StreamQuery<ID, E>,
+    producer: FutureProducer,
+    topic: String,
+    serde: S,
+    partition_key: Option<Identifier>,
+    queue_timeout: Duration,
+}
+
+impl<ID, E, S> std::fmt::Debug for KafkaEventForwarder<ID, E, S>
+where
+    ID: EventId,
+    E: Event + Clone,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaEventForwarder")
+            .field("id", &self.id)
+            .field("topic", &self.topic)
+            .field("partition_key", &self.partition_key)
+            .field("queue_timeout", &self.queue_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<ID, E, S> KafkaEventForwarder<ID, E, S>
+where
+    ID: EventId,
+    E: Event + Clone,
+    S: Serializer<E>,
+{
+    /// Creates a new `KafkaEventForwarder`.
+    ///
+    /// The messages have no key, so they are spread over the partitions of the topic. Use
+    /// `with_partition_key` to keep the events of the same entity in order.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the event listener.
+    /// * `query` - The query of the forwarded events.
+    /// * `producer` - The Kafka producer.
+    /// * `topic` - The topic the events are produced to.
+    /// * `serde` - The serializer of the event payloads.
+    pub fn new(
+        id: &'static str,
+        query: StreamQuery<ID, E>,
+        producer: FutureProducer,
+        topic: impl Into<String>,
+        serde: S,
+    ) -> Self {
+        Self {
+            id,
+            query,
+            producer,
+            topic: topic.into(),
+            serde,
+            partition_key: None,
+            queue_timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Partitions the messages by the value of a domain identifier.
+    ///
+    /// The value of the domain identifier is the key of the message, so the events of the same entity are
+    /// produced to the same partition. The events without the domain identifier have no key.
+    ///
+    /// # Arguments
+    ///
+    /// * `partition_key` - The domain identifier used as the key of the messages.
+    pub fn with_partition_key(mut self, partition_key: Identifier) -> Self {
+        self.partition_key = Some(partition_key);
+        self
+    }
+
+    /// Sets how long a message waits for a free slot in the queue of the producer before failing.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue_timeout` - The maximum waiting time.
+    pub fn with_queue_timeout(mut self, queue_timeout: Duration) -> Self {
+        self.queue_timeout = queue_timeout;
+        self
+    }
+
+    /// Returns the key of the message of an event.
+    fn key(&self, event: &E) -> Option<String> {
+        let partition_key = self.partition_key.as_ref()?;
+        event
+            .domain_identifiers()
+            .get(partition_key)
+            .map(ToString::to_string)
+    }
+}
+
+#[async_trait]
+impl<ID, E, S> EventListener<ID, E> for KafkaEventForwarder<ID, E, S>
+where
+    ID: EventId,
+    E: Event + Clone + Send + Sync,
+    S: Serializer<E> + Send + Sync,
+{
+    type Error = Error;
+
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn query(&self) -> &StreamQuery<ID, E> {
+        &self.query
+    }
+
+    async fn handle(&self, event: PersistedEvent<ID, E>) -> Result<(), Self::Error> {
+        let event_id = event.id().to_string();
+        let event_type = event.name();
+        let key = self.key(&event);
+        let payload = self.serde.serialize(event.into_inner());
+        let headers = OwnedHeaders::new()
+            .insert(Header {
+                key: "event_id",
+                value: Some(&event_id),
+            })
+            .insert(Header {
+                key: "event_type",
+                value: Some(event_type),
+            });
+        let mut record = FutureRecord::to(&self.topic)
+            .payload(&payload)
+            .headers(headers);
+        if let Some(key) = &key {
+            record = record.key(key);
+        }
+        self.producer
+            .send(record, self.queue_timeout)
+            .await
+            .map_err(|(err, _)| Error::Kafka(err))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use disintegrate::{ident, query};
+    use disintegrate_serde::serde::json::Json;
+    use rdkafka::ClientConfig;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, disintegrate::Event)]
+    #[serde(tag = "event_type", rename_all = "snake_case")]
+    enum CourseEvent {
+        CourseCreated {
+            #[id]
+            course_id: String,
+        },
+        StudentRegistered {
+            #[id]
+            student_id: String,
+        },
+    }
+
+    fn forwarder() -> KafkaEventForwarder<i64, CourseEvent, Json<CourseEvent>> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", "localhost:9092")
+            .create()
+            .unwrap();
+        KafkaEventForwarder::new(
+            "courses_forwarder",
+            query!(CourseEvent),
+            producer,
+            "courses",
+            Json::default(),
+        )
+    }
+
+    #[test]
+    fn it_keys_the_messages_by_the_partition_key() {
+        let course_created = CourseEvent::CourseCreated {
+            course_id: "c1".to_string(),
+        };
+        let student_registered = CourseEvent::StudentRegistered {
+            student_id: "s1".to_string(),
+        };
+
+        let forwarder = forwarder();
+        assert_eq!(forwarder.key(&course_created), None);
+
+        let forwarder = forwarder.with_partition_key(ident!(#course_id));
+        assert_eq!(forwarder.key(&course_created), Some("c1".to_string()));
+        assert_eq!(forwarder.key(&student_registered), None);
+    }
+}
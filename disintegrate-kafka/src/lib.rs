@@ -0,0 +1,11 @@
+//! # Kafka Disintegrate Integration Library
+//!
+//! This library forwards the events of a Disintegrate event store to Kafka topics, so that other services
+//! can consume them. `KafkaEventForwarder` is an `EventListener`: it is registered to an event listener
+//! runner of the event store, e.g. the `PgEventListener`, which tracks the forwarded events and retries
+//! the failed ones.
+mod error;
+mod forwarder;
+
+pub use crate::error::Error;
+pub use crate::forwarder::KafkaEventForwarder;
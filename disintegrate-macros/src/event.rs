@@ -1,18 +1,23 @@
+mod attributes;
+mod constructors;
 mod stream;
 
+use attributes::{description_tokens, EventAttributes};
+use constructors::impl_constructors;
 use proc_macro2::TokenStream;
 use quote::quote;
 use stream::{impl_stream, streams};
 use syn::{AngleBracketedGenericArguments, Data, DeriveInput, Error, Result};
 use syn::{DataEnum, DataStruct, Fields};
 
+use crate::identifier::event_identifier_fields;
 use crate::reserved_identifier_names;
-use crate::symbol::ID;
 
 pub fn event_inner(ast: &DeriveInput) -> Result<TokenStream> {
+    let attributes = EventAttributes::parse(&ast.attrs)?;
     match ast.data {
         Data::Enum(ref data) => {
-            let derive_event = impl_enum(ast, data)?;
+            let derive_event = impl_enum(ast, data, &attributes)?;
             let streams = streams(ast)?;
             let impl_streams = streams
                 .iter()
@@ -22,26 +27,54 @@ pub fn event_inner(ast: &DeriveInput) -> Result<TokenStream> {
                 .iter()
                 .map(|g| {
                     if let Data::Enum(ref enum_data) = g.data {
-                        impl_enum(g, enum_data)
+                        impl_enum(g, enum_data, &attributes)
                     } else {
                         Err(Error::new(g.ident.span(), "Expect to be an enum"))
                     }
                 })
                 .collect::<Result<Vec<TokenStream>>>()?;
+            let constructors = if attributes.constructors {
+                impl_constructors(ast, data)?
+            } else {
+                quote!()
+            };
 
             Ok(quote! {
                   #derive_event
                   #(#impl_streams)*
                   #(#derive_event_streams)*
+                  #constructors
             })
         }
-        Data::Struct(ref data) => impl_struct(ast, data),
+        Data::Struct(ref data) => impl_struct(ast, data, &attributes),
         _ => panic!("Not supported type"),
     }
 }
 
-fn impl_enum(ast: &DeriveInput, data: &DataEnum) -> Result<TokenStream> {
+fn impl_enum(
+    ast: &DeriveInput,
+    data: &DataEnum,
+    attributes: &EventAttributes,
+) -> Result<TokenStream> {
     let name = ast.ident.clone();
+    if let Some(alias) = attributes.aliases.first() {
+        return Err(Error::new(
+            alias.span(),
+            "the aliases must be set on the event variants",
+        ));
+    }
+    if let Some(version) = &attributes.version {
+        return Err(Error::new(
+            version.span(),
+            "the versions must be set on the event variants",
+        ));
+    }
+    if let Some(stream) = attributes.streams.first() {
+        return Err(Error::new(
+            stream.span(),
+            "the streams must be set on the event variants, or listed with the `#[stream]` attribute",
+        ));
+    }
     let no_variants_deref = if data.variants.is_empty() {
         quote!(*)
     } else {
@@ -56,25 +89,31 @@ fn impl_enum(ast: &DeriveInput, data: &DataEnum) -> Result<TokenStream> {
         }
     });
 
-    let impl_domain_identifiers = data.variants.iter().map(|variant| {
+    let variants_identifiers = data
+        .variants
+        .iter()
+        .map(|variant| event_identifier_fields(&variant.fields))
+        .collect::<Result<Vec<_>>>()?;
+
+    let impl_domain_identifiers = data.variants.iter().zip(&variants_identifiers).map(|(variant, identifiers)| {
         let event_type = &variant.ident;
 
         match &variant.fields {
             Fields::Unnamed(_fields) => quote!{
-                  #name::#event_type(payload) => payload.domain_identifiers(),
+                  #name::#event_type(payload) => {
+                      use disintegrate::Event as _;
+                      payload.domain_identifiers()
+                  },
             },
-            Fields::Named(fields) => {
-                let identifiers_fields : Vec<_> = fields.named
-                    .iter()
-                    .filter(|f| f.attrs.iter().any(|attr| attr.path() == ID))
-                    .flat_map(|f| f.ident.as_ref())
-                    .collect();
+            Fields::Named(_fields) => {
+                let identifiers_members: Vec<_> = identifiers.iter().map(|id| id.member).collect();
+                let identifiers_names: Vec<_> = identifiers.iter().map(|id| &id.name).collect();
 
-                let reserved_identifiers = reserved_identifier_names(&identifiers_fields);
+                let reserved_identifiers = reserved_identifier_names(&identifiers_names);
                 quote! {
-                    #name::#event_type{#(#identifiers_fields,)*..} => {
+                    #name::#event_type{#(#identifiers_members,)*..} => {
                         #reserved_identifiers
-                        disintegrate::domain_identifiers!{#(#identifiers_fields: #identifiers_fields),*}
+                        disintegrate::domain_identifiers!{#(#identifiers_names: #identifiers_members),*}
                     },
                 }
             },
@@ -88,7 +127,8 @@ fn impl_enum(ast: &DeriveInput, data: &DataEnum) -> Result<TokenStream> {
     let domain_identifiers_slice =
         data.variants
             .iter()
-            .fold(quote!(&[]), |acc, variant| match &variant.fields {
+            .zip(&variants_identifiers)
+            .fold(quote!(&[]), |acc, (variant, identifiers)| match &variant.fields {
                 Fields::Unnamed(fields) => {
                     let payload_field = fields.unnamed.first().unwrap();
                     let payload_type = enum_unnamed_field_type(payload_field);
@@ -96,23 +136,13 @@ fn impl_enum(ast: &DeriveInput, data: &DataEnum) -> Result<TokenStream> {
                         disintegrate::const_slices_concat!(
                             &disintegrate::DomainIdentifierInfo,
                             #acc,
-                            #payload_type::SCHEMA.domain_identifiers
+                            <#payload_type as disintegrate::Event>::SCHEMA.domain_identifiers
                         )
                     }
                 }
-                Fields::Named(fields) => {
-                    let identifiers_fields =  fields
-                        .named
-                        .iter()
-                        .filter(|f| f.attrs.iter().any(|attr| attr.path() == ID));
-
-                    let identifiers_idents: Vec<_> = identifiers_fields.clone()
-                        .map(|f| f.ident.as_ref())
-                        .collect();
-
-                    let identifiers_types: Vec<_> = identifiers_fields
-                        .map(|f| f.ty.clone())
-                        .collect();
+                Fields::Named(_fields) => {
+                    let identifiers_idents: Vec<_> = identifiers.iter().map(|id| &id.name).collect();
+                    let identifiers_types: Vec<_> = identifiers.iter().map(|id| id.ty).collect();
 
                     quote! {
                         disintegrate::const_slices_concat!(&disintegrate::DomainIdentifierInfo, #acc, &[#(&disintegrate::DomainIdentifierInfo{ident: disintegrate::ident!(##identifiers_idents), type_info: <#identifiers_types as disintegrate::IntoIdentifierValue>::TYPE},)*])
@@ -126,22 +156,64 @@ fn impl_enum(ast: &DeriveInput, data: &DataEnum) -> Result<TokenStream> {
         .iter()
         .map(|variant| variant.ident.to_string());
 
+    let enum_owner = attributes.owner_tokens();
+    let variants_attributes = data
+        .variants
+        .iter()
+        .map(|variant| EventAttributes::parse(&variant.attrs))
+        .collect::<Result<Vec<_>>>()?;
+
     let events_info= data
         .variants
         .iter()
-        .fold(quote!(&[]), |acc, variant| {
+        .zip(variants_attributes)
+        .zip(&variants_identifiers)
+        .fold(quote!(&[]), |acc, ((variant, variant_attributes), identifiers)| {
            let variant_ident = &variant.ident.to_string();
+           let description = description_tokens(&variant.attrs);
+           let aliases = variant_attributes.aliases_tokens();
+           let version = variant_attributes.version_tokens();
+           let owner = if variant_attributes.owner.is_some() {
+               variant_attributes.owner_tokens()
+           } else {
+               enum_owner.clone()
+           };
             match &variant.fields {
             Fields::Unnamed(fields) => {
                 let payload_field = fields.unnamed.first().unwrap();
                 let payload_type = enum_unnamed_field_type(payload_field);
+                let description = if variant.attrs.iter().any(|attr| attr.path().is_ident("doc")) {
+                    description
+                } else {
+                    quote!(<#payload_type as disintegrate::Event>::SCHEMA.events_info[0].description)
+                };
+                let owner = if variant_attributes.owner.is_some() {
+                    owner
+                } else {
+                    quote! {
+                        match <#payload_type as disintegrate::Event>::SCHEMA.events_info[0].owner {
+                            Some(owner) => Some(owner),
+                            None => #enum_owner,
+                        }
+                    }
+                };
+                let aliases = if variant_attributes.aliases.is_empty() {
+                    quote!(<#payload_type as disintegrate::Event>::SCHEMA.events_info[0].aliases)
+                } else {
+                    aliases
+                };
+                let version = if variant_attributes.version.is_none() {
+                    quote!(<#payload_type as disintegrate::Event>::SCHEMA.events_info[0].version)
+                } else {
+                    version
+                };
                 quote! {
                     {
                         const EVENT_INFO: &[&disintegrate::EventInfo] = {
-                            if #payload_type::SCHEMA.events_info.len() != 1 {
+                            if <#payload_type as disintegrate::Event>::SCHEMA.events_info.len() != 1 {
                                 panic!(concat!("Event variant ", #variant_ident, " must contain a struct"));
                             }
-                            &[&disintegrate::EventInfo{name: #variant_ident, domain_identifiers: #payload_type::SCHEMA.events_info[0].domain_identifiers}]
+                            &[&disintegrate::EventInfo{name: #variant_ident, domain_identifiers: <#payload_type as disintegrate::Event>::SCHEMA.events_info[0].domain_identifiers, description: #description, owner: #owner, aliases: #aliases, version: #version}]
                         };
                         disintegrate::const_slices_concat!(
                             &disintegrate::EventInfo,
@@ -151,19 +223,14 @@ fn impl_enum(ast: &DeriveInput, data: &DataEnum) -> Result<TokenStream> {
                     }
                 }
             }
-            Fields::Named(fields) => {
-                let identifiers_idents: Vec<_> = fields
-                    .named
-                    .iter()
-                    .filter(|f| f.attrs.iter().any(|attr| attr.path() == ID))
-                    .map(|f| f.ident.as_ref())
-                    .collect();
+            Fields::Named(_fields) => {
+                let identifiers_idents: Vec<_> = identifiers.iter().map(|id| &id.name).collect();
                 quote! {
-                    disintegrate::const_slices_concat!(&disintegrate::EventInfo, #acc, &[&disintegrate::EventInfo{name: #variant_ident, domain_identifiers: &[#(&disintegrate::ident!(##identifiers_idents),)*]}])
+                    disintegrate::const_slices_concat!(&disintegrate::EventInfo, #acc, &[&disintegrate::EventInfo{name: #variant_ident, domain_identifiers: &[#(&disintegrate::ident!(##identifiers_idents),)*], description: #description, owner: #owner, aliases: #aliases, version: #version}])
                 }
             }
             Fields::Unit => quote!(
-                disintegrate::const_slices_concat!(&disintegrate::EventInfo, #acc, &[&disintegrate::EventInfo{name: #variant_ident, domain_identifiers: &[]}])
+                disintegrate::const_slices_concat!(&disintegrate::EventInfo, #acc, &[&disintegrate::EventInfo{name: #variant_ident, domain_identifiers: &[], description: #description, owner: #owner, aliases: #aliases, version: #version}])
             ),
         }});
 
@@ -219,21 +286,22 @@ fn enum_unnamed_field_type(payload_field: &syn::Field) -> &syn::Type {
     &payload_field.ty
 }
 
-fn impl_struct(ast: &DeriveInput, data: &DataStruct) -> Result<TokenStream> {
+fn impl_struct(
+    ast: &DeriveInput,
+    data: &DataStruct,
+    attributes: &EventAttributes,
+) -> Result<TokenStream> {
     let name = ast.ident.clone();
     let impl_type = name.to_string();
+    let description = description_tokens(&ast.attrs);
+    let owner = attributes.owner_tokens();
+    let aliases = attributes.aliases_tokens();
+    let version = attributes.version_tokens();
 
-    let identifiers_fields = data
-        .fields
-        .iter()
-        .filter(|f| f.attrs.iter().any(|attr| attr.path() == ID));
-
-    let identifiers_idents: Vec<_> = identifiers_fields
-        .clone()
-        .filter_map(|f| f.ident.as_ref())
-        .collect();
-
-    let identifiers_types: Vec<_> = identifiers_fields.clone().map(|f| f.ty.clone()).collect();
+    let identifiers = event_identifier_fields(&data.fields)?;
+    let identifiers_idents: Vec<_> = identifiers.iter().map(|id| &id.name).collect();
+    let identifiers_members: Vec<_> = identifiers.iter().map(|id| id.member).collect();
+    let identifiers_types: Vec<_> = identifiers.iter().map(|id| id.ty).collect();
 
     let reserved_identifiers = reserved_identifier_names(&identifiers_idents);
 
@@ -242,7 +310,7 @@ fn impl_struct(ast: &DeriveInput, data: &DataStruct) -> Result<TokenStream> {
         impl disintegrate::Event for #name {
             const SCHEMA: disintegrate::EventSchema = disintegrate::EventSchema{
                 events: &[#impl_type],
-                events_info: &[&disintegrate::EventInfo{name: #impl_type, domain_identifiers: &[#(&disintegrate::ident!(##identifiers_idents),)*]}],
+                events_info: &[&disintegrate::EventInfo{name: #impl_type, domain_identifiers: &[#(&disintegrate::ident!(##identifiers_idents),)*], description: #description, owner: #owner, aliases: #aliases, version: #version}],
                 domain_identifiers:&[#(&disintegrate::DomainIdentifierInfo{ident: disintegrate::ident!(##identifiers_idents), type_info: <#identifiers_types as disintegrate::IntoIdentifierValue>::TYPE},)*]
             };
 
@@ -252,7 +320,7 @@ fn impl_struct(ast: &DeriveInput, data: &DataStruct) -> Result<TokenStream> {
 
             fn domain_identifiers(&self) -> disintegrate::DomainIdentifierSet {
                 #reserved_identifiers
-                disintegrate::domain_identifiers!{#(#identifiers_idents: self.#identifiers_idents),*}
+                disintegrate::domain_identifiers!{#(#identifiers_idents: self.#identifiers_members),*}
             }
         }
     })
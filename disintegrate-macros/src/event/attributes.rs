@@ -0,0 +1,99 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Attribute, Expr, ExprLit, Ident, Lit, LitInt, LitStr, Meta, Result};
+
+use crate::symbol::{ALIAS, CONSTRUCTORS, EVENT, OWNER, STREAM, VERSION};
+
+/// The options set with the `#[event(...)]` attribute.
+#[derive(Default)]
+pub struct EventAttributes {
+    pub constructors: bool,
+    pub owner: Option<LitStr>,
+    pub aliases: Vec<LitStr>,
+    pub streams: Vec<Ident>,
+    pub version: Option<LitInt>,
+}
+
+impl EventAttributes {
+    /// Parses the `#[event(...)]` attributes of an event type or of an event variant.
+    pub fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut attributes = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path() == EVENT) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path == CONSTRUCTORS {
+                    attributes.constructors = true;
+                    Ok(())
+                } else if meta.path == OWNER {
+                    attributes.owner = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path == ALIAS {
+                    attributes.aliases.push(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path == STREAM {
+                    attributes.streams.push(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path == VERSION {
+                    let version: LitInt = meta.value()?.parse()?;
+                    if version.base10_parse::<u32>()? == 0 {
+                        return Err(meta.error("the version must be greater than 0"));
+                    }
+                    attributes.version = Some(version);
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported event attribute"))
+                }
+            })?;
+        }
+        Ok(attributes)
+    }
+
+    /// Returns the owner as an `Option<&'static str>` expression.
+    pub fn owner_tokens(&self) -> TokenStream {
+        option_tokens(self.owner.as_ref().map(LitStr::value))
+    }
+
+    /// Returns the aliases as a `&'static [&'static str]` expression.
+    pub fn aliases_tokens(&self) -> TokenStream {
+        let aliases = &self.aliases;
+        quote!(&[#(#aliases),*])
+    }
+
+    /// Returns the version as a `u32` expression, defaulting to 1.
+    pub fn version_tokens(&self) -> TokenStream {
+        match &self.version {
+            Some(version) => quote!(#version),
+            None => quote!(1),
+        }
+    }
+}
+
+/// Collects the doc comments into the description of the event, as an `Option<&'static str>` expression.
+pub fn description_tokens(attrs: &[Attribute]) -> TokenStream {
+    option_tokens(description(attrs))
+}
+
+fn description(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(doc) => match &doc.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(line),
+                    ..
+                }) => Some(line.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    let description = lines.join("\n").trim().to_string();
+    (!description.is_empty()).then_some(description)
+}
+
+fn option_tokens(value: Option<String>) -> TokenStream {
+    match value {
+        Some(value) => quote!(Some(#value)),
+        None => quote!(None),
+    }
+}
@@ -0,0 +1,61 @@
+use heck::ToSnakeCase;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{DataEnum, DeriveInput, Error, Fields, Result};
+
+/// Generates a constructor function for each variant of the event enum.
+///
+/// The constructor is named after the variant in snake case and takes the variant fields as arguments.
+pub fn impl_constructors(ast: &DeriveInput, data: &DataEnum) -> Result<TokenStream> {
+    let name = &ast.ident;
+    let vis = &ast.vis;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    let constructors = data
+        .variants
+        .iter()
+        .map(|variant| {
+            let variant_ident = &variant.ident;
+            let constructor_ident = format_ident!("{}", variant_ident.to_string().to_snake_case());
+            let doc = format!("Creates a new `{variant_ident}` event.");
+            match &variant.fields {
+                Fields::Named(fields) => {
+                    let idents: Vec<_> = fields.named.iter().map(|f| &f.ident).collect();
+                    let types = fields.named.iter().map(|f| &f.ty);
+                    Ok(quote! {
+                        #[doc = #doc]
+                        #vis fn #constructor_ident(#(#idents: #types),*) -> Self {
+                            #name::#variant_ident { #(#idents),* }
+                        }
+                    })
+                }
+                Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                    let payload_type = &fields.unnamed.first().unwrap().ty;
+                    Ok(quote! {
+                        #[doc = #doc]
+                        #vis fn #constructor_ident(payload: #payload_type) -> Self {
+                            #name::#variant_ident(payload)
+                        }
+                    })
+                }
+                Fields::Unnamed(fields) => Err(Error::new_spanned(
+                    fields,
+                    "Event variant must contain a single payload",
+                )),
+                Fields::Unit => Ok(quote! {
+                    #[doc = #doc]
+                    #vis fn #constructor_ident() -> Self {
+                        #name::#variant_ident
+                    }
+                }),
+            }
+        })
+        .collect::<Result<Vec<TokenStream>>>()?;
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#constructors)*
+        }
+    })
+}
@@ -9,6 +9,9 @@ use syn::{
     Data, DeriveInput, Error, Field, Ident, Result, Token, Type, Variant,
 };
 
+use super::attributes::EventAttributes;
+use crate::symbol::EVENT;
+
 #[derive(Debug)]
 pub struct QueryArgs {
     name: Ident,
@@ -34,31 +37,34 @@ impl Parse for QueryArgs {
 }
 
 pub fn streams(ast: &DeriveInput) -> Result<Vec<DeriveInput>> {
-    ast.attrs
+    let event_data = match ast.data {
+        Data::Enum(ref enum_data) => Ok(enum_data),
+        _ => Err(Error::new(ast.ident.span(), "Can only derive from an enum")),
+    }?;
+
+    let mut mapping: Vec<(Ident, Vec<Ident>)> = vec![];
+    for attr in ast
+        .attrs
         .iter()
         .filter(|attr| attr.path().is_ident("stream"))
-        .map(|g| {
-            let args: QueryArgs = g.parse_args().unwrap();
-            let stream_ident = args.name;
-            let selected_variants: Vec<_> = args.variants;
-
-            let event_data = match ast.data {
-                Data::Enum(ref enum_data) => Ok(enum_data),
-                _ => Err(Error::new(
-                    stream_ident.span(),
-                    "Can only derive from an enum",
-                )),
-            }?;
+    {
+        let args: QueryArgs = attr.parse_args()?;
+        stream_variants(&mut mapping, args.name).extend(args.variants);
+    }
+    for variant in &event_data.variants {
+        for stream_ident in EventAttributes::parse(&variant.attrs)?.streams {
+            stream_variants(&mut mapping, stream_ident).push(variant.ident.clone());
+        }
+    }
 
+    Ok(mapping
+        .into_iter()
+        .map(|(stream_ident, selected_variants)| {
             let mut stream_data = event_data.clone();
             stream_data.variants = event_data
                 .variants
                 .iter()
-                .filter(|variant| {
-                    selected_variants
-                        .iter()
-                        .any(|selected| variant.ident == *selected)
-                })
+                .filter(|variant| selected_variants.contains(&variant.ident))
                 .cloned()
                 .collect();
 
@@ -66,10 +72,21 @@ pub fn streams(ast: &DeriveInput) -> Result<Vec<DeriveInput>> {
             stream.ident = stream_ident;
             stream.data = Data::Enum(stream_data);
             stream.attrs = vec![];
-
-            Ok(stream)
+            stream
         })
-        .collect()
+        .collect())
+}
+
+/// Returns the variants mapped to the stream, adding the stream to the mapping if missing.
+fn stream_variants(mapping: &mut Vec<(Ident, Vec<Ident>)>, stream_ident: Ident) -> &mut Vec<Ident> {
+    let index = match mapping.iter().position(|(ident, _)| *ident == stream_ident) {
+        Some(index) => index,
+        None => {
+            mapping.push((stream_ident, vec![]));
+            mapping.len() - 1
+        }
+    };
+    &mut mapping[index].1
 }
 
 pub fn impl_stream(parent: &DeriveInput, stream: &DeriveInput) -> Result<TokenStream> {
@@ -87,6 +104,11 @@ pub fn impl_stream(parent: &DeriveInput, stream: &DeriveInput) -> Result<TokenSt
         )),
     }?;
 
+    stream_data
+        .variants
+        .iter_mut()
+        .for_each(|variant| variant.attrs.retain(|attr| attr.path() != EVENT));
+
     stream_data
         .variants
         .iter_mut()
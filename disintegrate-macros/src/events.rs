@@ -0,0 +1,206 @@
+use heck::ToUpperCamelCase;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    braced,
+    parse::{Parse, ParseStream},
+    parse_quote,
+    punctuated::Punctuated,
+    Attribute, Data, DataEnum, DataStruct, DeriveInput, Field, Fields, FieldsNamed, Generics,
+    Ident, Result, Token, Variant, Visibility,
+};
+
+use crate::event::event_inner;
+use crate::symbol::{EVENT, ID};
+
+/// The input of the `events!` macro: an event enum whose events are grouped into modules.
+pub struct EventsInput {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    ident: Ident,
+    modules: Vec<EventModule>,
+}
+
+/// A module of events, generating a stream of the event enum.
+struct EventModule {
+    attrs: Vec<Attribute>,
+    ident: Ident,
+    events: Vec<EventStruct>,
+}
+
+/// An event of a module, generating a struct.
+struct EventStruct {
+    attrs: Vec<Attribute>,
+    ident: Ident,
+    fields: Fields,
+}
+
+impl Parse for EventsInput {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        input.parse::<Token![enum]>()?;
+        let ident = input.parse()?;
+        let content;
+        braced!(content in input);
+        let mut modules = vec![];
+        while !content.is_empty() {
+            modules.push(content.parse()?);
+        }
+        Ok(Self {
+            attrs,
+            vis,
+            ident,
+            modules,
+        })
+    }
+}
+
+impl Parse for EventModule {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        input.parse::<Token![mod]>()?;
+        let ident = input.parse()?;
+        let content;
+        braced!(content in input);
+        let events: Punctuated<EventStruct, Token![,]> =
+            content.parse_terminated(EventStruct::parse, Token![,])?;
+        Ok(Self {
+            attrs,
+            ident,
+            events: events.into_iter().collect(),
+        })
+    }
+}
+
+impl Parse for EventStruct {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let ident = input.parse()?;
+        let fields = if input.peek(syn::token::Brace) {
+            let mut fields: FieldsNamed = input.parse()?;
+            for field in fields.named.iter_mut() {
+                if let Visibility::Inherited = field.vis {
+                    field.vis = parse_quote!(pub);
+                }
+            }
+            Fields::Named(fields)
+        } else {
+            Fields::Unit
+        };
+        Ok(Self {
+            attrs,
+            ident,
+            fields,
+        })
+    }
+}
+
+pub fn events_inner(input: EventsInput) -> Result<TokenStream> {
+    let vis = &input.vis;
+    let derives: Vec<&Attribute> = input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("derive"))
+        .collect();
+
+    let modules = input
+        .modules
+        .iter()
+        .map(|module| {
+            let attrs = &module.attrs;
+            let ident = &module.ident;
+            let structs = module
+                .events
+                .iter()
+                .map(|event| impl_event_struct(event, &derives))
+                .collect::<Result<Vec<TokenStream>>>()?;
+            Ok(quote! {
+                #(#attrs)*
+                #vis mod #ident {
+                    #[allow(unused_imports)]
+                    use super::*;
+
+                    #(#structs)*
+                }
+            })
+        })
+        .collect::<Result<Vec<TokenStream>>>()?;
+
+    let variants: Punctuated<Variant, Token![,]> = input
+        .modules
+        .iter()
+        .flat_map(|module| {
+            let module_ident = &module.ident;
+            module.events.iter().map(move |event| {
+                let event_ident = &event.ident;
+                let variant: Variant = parse_quote!(#event_ident(#module_ident::#event_ident));
+                variant
+            })
+        })
+        .collect();
+
+    let streams = input.modules.iter().map(|module| {
+        let stream_ident = format_ident!(
+            "{}Event",
+            module.ident.to_string().to_upper_camel_case(),
+            span = module.ident.span()
+        );
+        let events = module.events.iter().map(|event| &event.ident);
+        let attr: Attribute = parse_quote!(#[stream(#stream_ident, [#(#events),*])]);
+        attr
+    });
+
+    let mut event_enum = DeriveInput {
+        attrs: input.attrs.clone(),
+        vis: input.vis.clone(),
+        ident: input.ident.clone(),
+        generics: Generics::default(),
+        data: Data::Enum(DataEnum {
+            enum_token: Default::default(),
+            brace_token: Default::default(),
+            variants,
+        }),
+    };
+    event_enum.attrs.extend(streams);
+    let impl_event_enum = event_inner(&event_enum)?;
+    event_enum
+        .attrs
+        .retain(|attr| !attr.path().is_ident("stream") && attr.path() != EVENT);
+
+    Ok(quote! {
+        #(#modules)*
+
+        #event_enum
+
+        #impl_event_enum
+    })
+}
+
+/// Generates the struct of an event, deriving the `Event` trait and the derives of the event enum.
+fn impl_event_struct(event: &EventStruct, derives: &[&Attribute]) -> Result<TokenStream> {
+    let mut event_struct = DeriveInput {
+        attrs: event.attrs.clone(),
+        vis: parse_quote!(pub),
+        ident: event.ident.clone(),
+        generics: Generics::default(),
+        data: Data::Struct(DataStruct {
+            struct_token: Default::default(),
+            fields: event.fields.clone(),
+            semi_token: matches!(event.fields, Fields::Unit).then(Default::default),
+        }),
+    };
+    let impl_event = event_inner(&event_struct)?;
+    event_struct.attrs.retain(|attr| attr.path() != EVENT);
+    if let Data::Struct(ref mut data) = event_struct.data {
+        data.fields
+            .iter_mut()
+            .for_each(|field: &mut Field| field.attrs.retain(|attr| attr.path() != ID));
+    }
+    Ok(quote! {
+        #(#derives)*
+        #event_struct
+
+        #impl_event
+    })
+}
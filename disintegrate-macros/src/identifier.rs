@@ -0,0 +1,74 @@
+use syn::{Error, Field, Ident, LitStr, Meta, Result, Type};
+
+use crate::symbol::{GROUP, ID, OPTIONAL, RENAME};
+
+/// A field marked with the `#[id]` attribute.
+pub struct IdentifierField<'a> {
+    /// The name of the field.
+    pub member: &'a Ident,
+    /// The name of the domain identifier: the name of the field, or the one set with `#[id(rename = "...")]`.
+    pub name: Ident,
+    /// The type of the field.
+    pub ty: &'a Type,
+    /// Whether the field is an `Option`, filtering the events only when it is set, with `#[id(optional)]`.
+    pub optional: bool,
+    /// The group of identifiers of the field, set with `#[id(group = "...")]`.
+    pub group: Option<LitStr>,
+}
+
+/// Collects the named fields marked with the `#[id]` attribute.
+pub fn identifier_fields<'a>(
+    fields: impl IntoIterator<Item = &'a Field>,
+) -> Result<Vec<IdentifierField<'a>>> {
+    let mut identifiers = vec![];
+    for field in fields {
+        let Some(member) = field.ident.as_ref() else {
+            continue;
+        };
+        let Some(attr) = field.attrs.iter().find(|attr| attr.path() == ID) else {
+            continue;
+        };
+        let mut identifier = IdentifierField {
+            member,
+            name: member.clone(),
+            ty: &field.ty,
+            optional: false,
+            group: None,
+        };
+        if let Meta::List(_) = attr.meta {
+            attr.parse_nested_meta(|meta| {
+                if meta.path == RENAME {
+                    identifier.name = meta.value()?.parse::<LitStr>()?.parse()?;
+                    Ok(())
+                } else if meta.path == OPTIONAL {
+                    identifier.optional = true;
+                    Ok(())
+                } else if meta.path == GROUP {
+                    identifier.group = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported id attribute"))
+                }
+            })?;
+        }
+        identifiers.push(identifier);
+    }
+    Ok(identifiers)
+}
+
+/// Collects the named fields of an event marked with the `#[id]` attribute, which are always set and ungrouped.
+pub fn event_identifier_fields<'a>(
+    fields: impl IntoIterator<Item = &'a Field>,
+) -> Result<Vec<IdentifierField<'a>>> {
+    let identifiers = identifier_fields(fields)?;
+    match identifiers
+        .iter()
+        .find(|identifier| identifier.optional || identifier.group.is_some())
+    {
+        Some(identifier) => Err(Error::new(
+            identifier.member.span(),
+            "the `optional` and `group` options of the `id` attribute are only supported by state queries",
+        )),
+        None => Ok(identifiers),
+    }
+}
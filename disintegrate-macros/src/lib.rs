@@ -1,4 +1,6 @@
 mod event;
+mod events;
+mod identifier;
 mod state_query;
 mod symbol;
 
@@ -53,7 +55,143 @@ use syn::{parse_macro_input, DeriveInput};
 /// In this example, the `OrderEvent` enum is marked as an event by deriving the `Event` trait. The
 /// `#[stream]` attribute specifies the event stream name and the list of variants to include in the stream, while the `#[id]` attribute is used
 /// to specify the domain identifiers of each variant.
-#[proc_macro_derive(Event, attributes(stream, id))]
+///
+/// The domain identifier is named after its field, unless it is renamed with `#[id(rename = "...")]`, e.g. to keep
+/// a clean column name in the event store while the field name cannot be changed:
+///
+/// ```rust
+/// use disintegrate::{domain_identifiers, Event};
+///
+/// #[derive(Event)]
+/// enum DomainEvent {
+///     UserRegistered {
+///         #[id(rename = "user_id")]
+///         uid: String,
+///     },
+/// }
+///
+/// let event = DomainEvent::UserRegistered { uid: "alice".into() };
+/// assert_eq!(event.domain_identifiers(), domain_identifiers! {user_id: "alice"});
+/// ```
+///
+/// When a large event enum is split into bounded contexts, the variants can be mapped to their streams one by one
+/// with the `#[event(stream = ...)]` attribute, which can be repeated to include a variant in several streams.
+/// The variants mapped this way are added to the streams listed with the `#[stream]` attribute, if any:
+///
+/// ```rust
+/// use disintegrate::Event;
+///
+/// #[derive(Event)]
+/// enum DomainEvent {
+///     #[event(stream = BillingEvent)]
+///     InvoiceIssued {
+///         #[id]
+///         invoice_id: String,
+///     },
+///     #[event(stream = BillingEvent, stream = ShippingEvent)]
+///     OrderPaid {
+///         #[id]
+///         order_id: String,
+///     },
+///     #[event(stream = ShippingEvent)]
+///     OrderShipped {
+///         #[id]
+///         order_id: String,
+///     },
+/// }
+///
+/// assert_eq!(BillingEvent::SCHEMA.events, &["InvoiceIssued", "OrderPaid"]);
+/// assert_eq!(ShippingEvent::SCHEMA.events, &["OrderPaid", "OrderShipped"]);
+/// ```
+///
+/// The `#[event(constructors)]` attribute generates a constructor function for each variant, named after the
+/// variant in snake case and taking the variant fields as arguments:
+///
+/// ```rust
+/// use disintegrate::Event;
+///
+/// #[derive(Event)]
+/// #[event(constructors)]
+/// enum DomainEvent {
+///     TransferSent {
+///         #[id]
+///         account_id: String,
+///         to: String,
+///         amount: u32,
+///     },
+/// }
+///
+/// let event = DomainEvent::transfer_sent("alice".into(), "bob".into(), 10);
+/// ```
+///
+/// The doc comments of the variants are captured in the `description` of their `EventInfo`, and the
+/// `#[event(owner = "...")]` attribute sets their `owner`, so that catalog tooling can describe each event type.
+/// The owner set on a variant takes precedence over the one set on the enum:
+///
+/// ```rust
+/// use disintegrate::Event;
+///
+/// #[derive(Event)]
+/// #[event(owner = "payments")]
+/// enum DomainEvent {
+///     /// A payment was received.
+///     PaymentReceived {
+///         #[id]
+///         payment_id: String,
+///     },
+///     #[event(owner = "risk")]
+///     PaymentFlagged,
+/// }
+///
+/// let info = DomainEvent::SCHEMA.event_info("PaymentReceived").unwrap();
+/// assert_eq!(info.description, Some("A payment was received."));
+/// assert_eq!(info.owner, Some("payments"));
+/// ```
+///
+/// A renamed variant keeps its former names with the `#[event(alias = "...")]` attribute, which can be repeated.
+/// The stream queries match the events persisted with any of the aliases, so the history of the event is still
+/// found after the rename:
+///
+/// ```rust
+/// use disintegrate::Event;
+///
+/// #[derive(Event)]
+/// enum DomainEvent {
+///     #[event(alias = "OrderCreated")]
+///     OrderPlaced {
+///         #[id]
+///         order_id: String,
+///     },
+/// }
+///
+/// let info = DomainEvent::SCHEMA.event_info_by_type("OrderCreated").unwrap();
+/// assert_eq!(info.name, "OrderPlaced");
+/// ```
+///
+/// The `#[event(version = N)]` attribute sets the version of a variant, 1 by default. The version is stored
+/// with each appended event, so the version a persisted event was written with is known to its readers:
+///
+/// ```rust
+/// use disintegrate::Event;
+///
+/// #[derive(Event)]
+/// enum DomainEvent {
+///     #[event(version = 2)]
+///     OrderPlaced {
+///         #[id]
+///         order_id: String,
+///     },
+///     OrderShipped {
+///         #[id]
+///         order_id: String,
+///     },
+/// }
+///
+/// let placed = DomainEvent::OrderPlaced { order_id: "o-1".into() };
+/// assert_eq!(placed.version(), 2);
+/// assert_eq!(DomainEvent::SCHEMA.event_info("OrderShipped").unwrap().version, 1);
+/// ```
+#[proc_macro_derive(Event, attributes(stream, id, event))]
 pub fn event(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
     event::event_inner(&ast)
@@ -61,6 +199,63 @@ pub fn event(input: TokenStream) -> TokenStream {
         .into()
 }
 
+/// Defines the events as structs, grouped into streams by module, and an event enum wrapping them.
+///
+/// Each event of a module becomes a struct of that module deriving the `Event` trait, with public fields.
+/// The `#[id]` attribute marks the domain identifiers, and the `#[event(...)]` attribute and the doc comments
+/// describe the events as in the `Event` derive. The event enum has a variant for each event, named after it,
+/// and a stream for each module, named after the module with the `Event` suffix, e.g. `CourseEvent` for the
+/// `course` module.
+///
+/// The `derive` attributes of the enum are also applied to the event structs, while the other attributes are
+/// applied to the enum only. As for the `Event` derive, the events must implement `Clone`, `Debug`, `PartialEq`
+/// and `Eq` to be streamed. The modules import the items of their parent module.
+///
+/// # Example
+///
+/// ```rust
+/// use disintegrate::{events, Event};
+///
+/// events! {
+///     #[derive(Clone, Debug, PartialEq, Eq)]
+///     pub enum DomainEvent {
+///         mod course {
+///             /// A course was created.
+///             CourseCreated {
+///                 #[id]
+///                 course_id: String,
+///                 name: String,
+///             },
+///             CourseClosed {
+///                 #[id]
+///                 course_id: String,
+///             },
+///         }
+///         mod student {
+///             StudentRegistered {
+///                 #[id]
+///                 student_id: String,
+///             },
+///         }
+///     }
+/// }
+///
+/// let event = DomainEvent::CourseCreated(course::CourseCreated {
+///     course_id: "algebra".into(),
+///     name: "Algebra".into(),
+/// });
+/// assert_eq!(event.name(), "CourseCreated");
+/// assert_eq!(CourseEvent::SCHEMA.events, &["CourseCreated", "CourseClosed"]);
+/// assert_eq!(StudentEvent::SCHEMA.events, &["StudentRegistered"]);
+/// ```
+#[proc_macro]
+pub fn events(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as events::EventsInput);
+    events::events_inner(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
 /// Derives the `StateQuery` trait for a struct, enabling its use as a state query in Disintegrate.
 ///
 /// The `state_query` attribute is mandatory and must include the event type associated with the state query.
@@ -71,6 +266,10 @@ pub fn event(input: TokenStream) -> TokenStream {
 /// It is also possible to rename a state using the `rename` argument in the `state_query` attribute. This feature is beneficial
 /// for snapshotting, and the name specified in `rename` is used to identify the snapshot.
 ///
+/// The `exclude` argument removes some events from the generated stream query, e.g.
+/// `#[state_query(DomainEvent, exclude = [UserDeleted])]` or `#[state_query(DomainEvent, exclude(UserDeleted))]`.
+/// The helper `exclude_events` extends the declared exclusions.
+///
 /// # Example
 ///
 /// ```rust
@@ -98,6 +297,42 @@ pub fn event(input: TokenStream) -> TokenStream {
 /// indicating its role as a state query. The `#[state_query]` attribute specifies the associated event type,
 /// and the `#[id]` attribute is used to define the domain identifiers. The `#[state_query]` attribute with `rename`
 /// renames the state to 'user-query-v1' for snapshotting purposes.
+///
+/// As in the `Event` derive, a domain identifier can be renamed with `#[id(rename = "...")]` to query the
+/// identifier of the events with a different field name.
+///
+/// An `Option` field marked with `#[id(optional)]` filters the events only when it is set. The identifiers can
+/// also be split into groups with `#[id(group = "...")]`: the stream query is the union of the queries of the
+/// groups, each filtering by the identifiers of its group and by the identifiers without a group:
+///
+/// ```rust
+/// # use disintegrate::Event;
+/// # #[derive(Event, Clone)]
+/// # enum DomainEvent{
+/// #    CartCreated {
+/// #         #[id]
+/// #         cart_id: String,
+/// #         #[id]
+/// #         customer_id: String,
+/// #     },
+/// #    CouponIssued {
+/// #         #[id]
+/// #         coupon_id: String,
+/// #     },
+/// # }
+/// use disintegrate::StateQuery;
+///
+/// #[derive(StateQuery, Clone)]
+/// #[state_query(DomainEvent)]
+/// struct Checkout {
+///     #[id(group = "cart")]
+///     cart_id: String,
+///     #[id(group = "cart", optional)]
+///     customer_id: Option<String>,
+///     #[id(group = "coupon")]
+///     coupon_id: String,
+/// }
+/// ```
 #[proc_macro_derive(StateQuery, attributes(state_query, id))]
 pub fn state_query(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
@@ -2,18 +2,28 @@ use proc_macro2::{Ident, TokenStream};
 use quote::quote;
 use syn::parse::{Parse, ParseStream};
 use syn::token::Comma;
-use syn::{Data, DeriveInput, Error};
+use syn::{bracketed, parenthesized, Data, DeriveInput, Error};
 use syn::{DataStruct, LitStr};
 
-use crate::symbol::{ID, RENAME, STATE_QUERY};
+use crate::identifier::{identifier_fields, IdentifierField};
+use crate::symbol::{EXCLUDE, RENAME, STATE_QUERY};
 
 enum StateQueryOptionalArgs {
     Rename(LitStr),
+    Exclude(Vec<Ident>),
 }
 
 impl Parse for StateQueryOptionalArgs {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let name = input.parse::<Ident>()?;
+
+        if name == EXCLUDE && input.peek(syn::token::Paren) {
+            let content;
+            parenthesized!(content in input);
+            let events = content.parse_terminated(Ident::parse, Comma)?;
+            return Ok(Self::Exclude(events.into_iter().collect()));
+        }
+
         input.parse::<syn::token::Eq>()?;
 
         if name == RENAME {
@@ -21,6 +31,13 @@ impl Parse for StateQueryOptionalArgs {
             return Ok(Self::Rename(value));
         }
 
+        if name == EXCLUDE {
+            let content;
+            bracketed!(content in input);
+            let events = content.parse_terminated(Ident::parse, Comma)?;
+            return Ok(Self::Exclude(events.into_iter().collect()));
+        }
+
         Err(Error::new(name.span(), "invalid argument"))
     }
 }
@@ -82,21 +99,30 @@ fn impl_struct(ast: &DeriveInput, data: &DataStruct) -> syn::Result<TokenStream>
     let state_query_name = state_query_attrs
         .optional_args
         .iter()
-        .map(|attrs| {
-            let StateQueryOptionalArgs::Rename(rename) = attrs;
-            rename.value()
+        .filter_map(|attrs| match attrs {
+            StateQueryOptionalArgs::Rename(rename) => Some(rename.value()),
+            _ => None,
         })
-        .last()
+        .next_back()
         .unwrap_or_else(|| state_query_ident.to_string());
-
-    let identifiers_fields: Vec<_> = data
-        .fields
+    let excluded_events: Vec<_> = state_query_attrs
+        .optional_args
         .iter()
-        .filter(|f| f.attrs.iter().any(|attr| attr.path() == ID))
-        .flat_map(|f| f.ident.as_ref())
+        .filter_map(|attrs| match attrs {
+            StateQueryOptionalArgs::Exclude(events) => Some(events),
+            _ => None,
+        })
+        .flatten()
         .collect();
 
-    let state_query = impl_state_query(event_type.clone(), &identifiers_fields);
+    let identifiers_fields = identifier_fields(&data.fields)?;
+
+    let mut state_query = impl_state_query(event_type.clone(), &identifiers_fields);
+    if !excluded_events.is_empty() {
+        state_query = quote! {
+            #state_query.exclude_events(disintegrate::event_types!(#event_type, [#(#excluded_events),*]))
+        };
+    }
 
     Ok(quote! {
         #[automatically_derived]
@@ -122,38 +148,96 @@ fn impl_struct(ast: &DeriveInput, data: &DataStruct) -> syn::Result<TokenStream>
 
         impl #state_query_ident {
             pub fn exclude_events<ID: disintegrate::EventId>(&self, events: &'static [&'static str]) -> disintegrate::StreamQuery<ID, <Self as disintegrate::StateQuery>::Event> {
-                self.query().exclude_events(events)
+                self.query().extend_excluded_events(events)
             }
         }
 
     })
 }
 
-fn impl_state_query(event_type: Ident, identifiers_fields: &[&Ident]) -> TokenStream {
-    if identifiers_fields.is_empty() {
-        quote! {
-            disintegrate::query!(#event_type)
+/// Builds the stream query of the state, as the union of the queries of its groups of identifiers.
+///
+/// The identifiers without a group are part of every group.
+fn impl_state_query(event_type: Ident, identifiers_fields: &[IdentifierField]) -> TokenStream {
+    let mut groups: Vec<String> = vec![];
+    for group in identifiers_fields.iter().filter_map(|id| id.group.as_ref()) {
+        if !groups.contains(&group.value()) {
+            groups.push(group.value());
         }
-    } else {
-        let filters = impl_state_filters(identifiers_fields);
-        quote! {
-            disintegrate::query!(#event_type; #filters)
+    }
+    if groups.is_empty() {
+        return impl_group_query(&event_type, identifiers_fields.iter());
+    }
+
+    let mut queries = groups.iter().map(|group| {
+        impl_group_query(
+            &event_type,
+            identifiers_fields.iter().filter(|id| {
+                id.group
+                    .as_ref()
+                    .is_none_or(|id_group| &id_group.value() == group)
+            }),
+        )
+    });
+    let first = queries.next();
+    quote! {
+        {
+            let query = #first;
+            #(let query: disintegrate::StreamQuery<ID, #event_type> = query.union(&#queries);)*
+            query
         }
     }
 }
 
-fn impl_state_filters(identifiers_fields: &[&Ident]) -> Option<TokenStream> {
-    if identifiers_fields.is_empty() {
-        return None;
+/// Builds the stream query of a group of identifiers.
+///
+/// The optional identifiers filter the events only when they are set.
+fn impl_group_query<'a>(
+    event_type: &Ident,
+    identifiers_fields: impl Iterator<Item = &'a IdentifierField<'a>>,
+) -> TokenStream {
+    let (optional_fields, required_fields): (Vec<_>, Vec<_>) =
+        identifiers_fields.partition(|id| id.optional);
+    if optional_fields.is_empty() {
+        return if required_fields.is_empty() {
+            quote! {
+                disintegrate::query!(#event_type)
+            }
+        } else {
+            let filters = impl_state_filters(&required_fields);
+            quote! {
+                disintegrate::query!(#event_type; #filters)
+            }
+        };
+    }
+
+    let filters = impl_state_filters(&required_fields);
+    let optional_members = optional_fields.iter().map(|id| id.member);
+    let optional_names = optional_fields.iter().map(|id| &id.name);
+    quote! {
+        {
+            #[allow(unused_mut)]
+            let mut filter = disintegrate::filter!(#event_type; #filters);
+            #(
+                disintegrate::filter!(@check #event_type; #optional_names);
+                if let Some(value) = &self.#optional_members {
+                    filter = filter.with_identifier(disintegrate::ident!(##optional_names), value.clone());
+                }
+            )*
+            disintegrate::query::<_, #event_type, _>(Some(filter))
+        }
     }
+}
 
-    if identifiers_fields.len() == 1 {
-        Some(quote! {#(#identifiers_fields == self.#identifiers_fields)*})
+fn impl_state_filters(identifiers_fields: &[&IdentifierField]) -> Option<TokenStream> {
+    let (first, rest) = identifiers_fields.split_first()?;
+    let IdentifierField { member, name, .. } = first;
+    if rest.is_empty() {
+        Some(quote! {#name == self.#member})
     } else {
-        let first = identifiers_fields[0];
-        let rest = impl_state_filters(&identifiers_fields[1..]);
+        let rest = impl_state_filters(rest);
         Some(quote! {
-             #first == self.#first, #rest
+             #name == self.#member, #rest
         })
     }
 }
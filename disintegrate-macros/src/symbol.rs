@@ -5,8 +5,17 @@ use syn::{Ident, Path};
 pub struct Symbol(&'static str);
 
 pub const RENAME: Symbol = Symbol("rename");
+pub const EXCLUDE: Symbol = Symbol("exclude");
 pub const STATE_QUERY: Symbol = Symbol("state_query");
 pub const ID: Symbol = Symbol("id");
+pub const EVENT: Symbol = Symbol("event");
+pub const CONSTRUCTORS: Symbol = Symbol("constructors");
+pub const OWNER: Symbol = Symbol("owner");
+pub const ALIAS: Symbol = Symbol("alias");
+pub const STREAM: Symbol = Symbol("stream");
+pub const VERSION: Symbol = Symbol("version");
+pub const OPTIONAL: Symbol = Symbol("optional");
+pub const GROUP: Symbol = Symbol("group");
 
 impl PartialEq<Symbol> for Ident {
     fn eq(&self, word: &Symbol) -> bool {
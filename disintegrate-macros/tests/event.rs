@@ -1,4 +1,6 @@
-use disintegrate::{ident, DomainIdentifierInfo, Event, IdentifierType, IntoIdentifierValue};
+use disintegrate::{
+    domain_identifiers, ident, DomainIdentifierInfo, Event, IdentifierType, IntoIdentifierValue,
+};
 
 #[derive(Event, Clone, Debug, PartialEq, Eq)]
 struct UserUpdatedData {
@@ -181,3 +183,250 @@ fn it_generates_domain_identifiers_schema_set() {
         ]
     );
 }
+
+#[derive(Event, Debug, PartialEq, Eq)]
+#[event(constructors)]
+enum AccountEvent {
+    TransferSent {
+        #[id]
+        account_id: String,
+        to: String,
+        amount: u32,
+    },
+    UserUpdated(UserUpdatedData),
+    AccountClosed,
+}
+
+#[test]
+fn it_generates_event_constructors() {
+    assert_eq!(
+        AccountEvent::transfer_sent("alice".to_string(), "bob".to_string(), 10),
+        AccountEvent::TransferSent {
+            account_id: "alice".to_string(),
+            to: "bob".to_string(),
+            amount: 10
+        }
+    );
+    let payload = UserUpdatedData {
+        user_id: "user123".to_string(),
+        email: "john@example.com".to_string(),
+    };
+    assert_eq!(
+        AccountEvent::user_updated(payload.clone()),
+        AccountEvent::UserUpdated(payload)
+    );
+    assert_eq!(AccountEvent::account_closed(), AccountEvent::AccountClosed);
+}
+
+/// Profile of a user changed.
+#[derive(Event, Clone, Debug, PartialEq, Eq)]
+#[event(owner = "identity")]
+struct ProfileChangedData {
+    #[id]
+    user_id: String,
+}
+
+#[allow(dead_code)]
+#[derive(Event, Debug, PartialEq, Eq)]
+#[event(owner = "payments")]
+enum PaymentEvent {
+    /// A payment was received.
+    ///
+    /// The amount is in cents.
+    PaymentReceived {
+        #[id]
+        payment_id: String,
+    },
+    #[event(owner = "risk")]
+    PaymentFlagged,
+    ProfileChanged(ProfileChangedData),
+}
+
+#[test]
+fn it_captures_the_documentation_metadata_of_the_events() {
+    let received = PaymentEvent::SCHEMA.event_info("PaymentReceived").unwrap();
+    assert_eq!(
+        received.description,
+        Some("A payment was received.\n\nThe amount is in cents.")
+    );
+    assert_eq!(received.owner, Some("payments"));
+
+    let flagged = PaymentEvent::SCHEMA.event_info("PaymentFlagged").unwrap();
+    assert_eq!(flagged.description, None);
+    assert_eq!(flagged.owner, Some("risk"));
+
+    let profile_changed = PaymentEvent::SCHEMA.event_info("ProfileChanged").unwrap();
+    assert_eq!(
+        profile_changed.description,
+        Some("Profile of a user changed.")
+    );
+    assert_eq!(profile_changed.owner, Some("identity"));
+}
+
+#[allow(dead_code)]
+#[derive(Event, Debug, PartialEq, Eq)]
+enum InvoiceEvent {
+    #[event(alias = "InvoiceCreated", alias = "InvoiceSent")]
+    InvoiceIssued {
+        #[id]
+        invoice_id: String,
+    },
+    InvoicePaid {
+        #[id]
+        invoice_id: String,
+    },
+}
+
+#[test]
+fn it_declares_the_aliases_of_the_renamed_events() {
+    let issued = InvoiceEvent::SCHEMA.event_info("InvoiceIssued").unwrap();
+    assert_eq!(issued.aliases, &["InvoiceCreated", "InvoiceSent"]);
+    assert_eq!(
+        issued.event_types().collect::<Vec<_>>(),
+        vec!["InvoiceIssued", "InvoiceCreated", "InvoiceSent"]
+    );
+    assert_eq!(
+        InvoiceEvent::SCHEMA.event_info_by_type("InvoiceSent"),
+        Some(issued)
+    );
+    assert!(InvoiceEvent::SCHEMA
+        .event_info("InvoicePaid")
+        .unwrap()
+        .aliases
+        .is_empty());
+}
+
+#[allow(dead_code)]
+#[derive(Event, Debug, PartialEq, Eq)]
+#[event(version = 3)]
+struct TicketOpened {
+    #[id]
+    ticket_id: String,
+}
+
+#[allow(dead_code, clippy::enum_variant_names)]
+#[derive(Event, Debug, PartialEq, Eq)]
+enum TicketEvent {
+    TicketOpened(TicketOpened),
+    #[event(version = 2)]
+    TicketAssigned {
+        #[id]
+        ticket_id: String,
+        assignee: String,
+    },
+    TicketClosed {
+        #[id]
+        ticket_id: String,
+    },
+}
+
+#[test]
+fn it_declares_the_versions_of_the_events() {
+    let version = |name| TicketEvent::SCHEMA.event_info(name).unwrap().version;
+    assert_eq!(version("TicketOpened"), 3);
+    assert_eq!(version("TicketAssigned"), 2);
+    assert_eq!(version("TicketClosed"), 1);
+    assert_eq!(
+        TicketEvent::TicketAssigned {
+            ticket_id: "t-1".to_string(),
+            assignee: "alice".to_string(),
+        }
+        .version(),
+        2
+    );
+}
+
+#[derive(Event, Debug, PartialEq, Eq)]
+struct WalletOpened {
+    #[id(rename = "account_id")]
+    acc: String,
+}
+
+#[allow(dead_code)]
+#[derive(Event, Debug, PartialEq, Eq)]
+enum WalletEvent {
+    WalletOpened(WalletOpened),
+    WalletClosed {
+        #[id(rename = "account_id")]
+        acc_no: String,
+        reason: String,
+    },
+}
+
+#[test]
+fn it_renames_the_domain_identifiers() {
+    let opened = WalletOpened {
+        acc: "a-1".to_string(),
+    };
+    assert_eq!(
+        opened.domain_identifiers(),
+        domain_identifiers! {account_id: "a-1"}
+    );
+
+    let closed = WalletEvent::WalletClosed {
+        acc_no: "a-2".to_string(),
+        reason: "moved".to_string(),
+    };
+    assert_eq!(
+        closed.domain_identifiers(),
+        domain_identifiers! {account_id: "a-2"}
+    );
+    assert_eq!(
+        WalletEvent::SCHEMA.domain_identifiers,
+        &[&DomainIdentifierInfo {
+            ident: ident!(#account_id),
+            type_info: IdentifierType::String
+        }]
+    );
+    assert_eq!(
+        WalletEvent::SCHEMA
+            .event_info("WalletClosed")
+            .unwrap()
+            .domain_identifiers,
+        &[&ident!(#account_id)]
+    );
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Event, Debug, PartialEq, Eq)]
+#[stream(CatalogEvent, [ProductListed])]
+enum StoreEvent {
+    #[event(stream = CatalogEvent)]
+    ProductPriced {
+        #[id]
+        product_id: String,
+        price: u32,
+    },
+    #[event(stream = CatalogEvent, stream = WarehouseEvent)]
+    ProductListed {
+        #[id]
+        product_id: String,
+    },
+    #[event(stream = WarehouseEvent)]
+    ProductRestocked {
+        #[id]
+        product_id: String,
+        #[id]
+        warehouse_id: String,
+    },
+}
+
+#[test]
+fn it_maps_the_variants_to_their_streams() {
+    assert_eq!(
+        CatalogEvent::SCHEMA.events,
+        &["ProductPriced", "ProductListed"]
+    );
+    assert_eq!(
+        WarehouseEvent::SCHEMA.events,
+        &["ProductListed", "ProductRestocked"]
+    );
+
+    let restocked = StoreEvent::ProductRestocked {
+        product_id: "p1".to_string(),
+        warehouse_id: "w1".to_string(),
+    };
+    let warehouse_event = WarehouseEvent::try_from(restocked).unwrap();
+    assert_eq!(warehouse_event.name(), "ProductRestocked");
+    assert!(CatalogEvent::try_from(StoreEvent::from(warehouse_event)).is_err());
+}
@@ -0,0 +1,101 @@
+use disintegrate::{domain_identifiers, events, Event};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Seats(u32);
+
+events! {
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum DomainEvent {
+        mod course {
+            /// A course was created.
+            CourseCreated {
+                #[id]
+                course_id: String,
+                name: String,
+                seats: Seats,
+            },
+            #[event(alias = "CourseDeleted")]
+            CourseClosed {
+                #[id]
+                course_id: String,
+            },
+        }
+        mod student {
+            StudentRegistered {
+                #[id]
+                student_id: String,
+            },
+            StudentsImported,
+        }
+    }
+}
+
+fn course_created(course_id: &str) -> course::CourseCreated {
+    course::CourseCreated {
+        course_id: course_id.to_string(),
+        name: "Algebra".to_string(),
+        seats: Seats(10),
+    }
+}
+
+#[test]
+fn it_generates_an_event_struct_for_each_event() {
+    let event = course_created("algebra");
+
+    assert_eq!(event.name(), "CourseCreated");
+    assert_eq!(
+        event.domain_identifiers(),
+        domain_identifiers! {course_id: "algebra"}
+    );
+    assert_eq!(
+        course::CourseCreated::SCHEMA.events_info[0].description,
+        Some("A course was created.")
+    );
+    assert_eq!(student::StudentsImported.name(), "StudentsImported");
+}
+
+#[test]
+fn it_wraps_the_event_structs_in_the_event_enum() {
+    let event = DomainEvent::CourseCreated(course_created("algebra"));
+
+    assert_eq!(
+        DomainEvent::SCHEMA.events,
+        &[
+            "CourseCreated",
+            "CourseClosed",
+            "StudentRegistered",
+            "StudentsImported"
+        ]
+    );
+    assert_eq!(event.name(), "CourseCreated");
+    assert_eq!(
+        event.domain_identifiers(),
+        domain_identifiers! {course_id: "algebra"}
+    );
+    assert_eq!(
+        DomainEvent::SCHEMA
+            .event_info_by_type("CourseDeleted")
+            .unwrap()
+            .name,
+        "CourseClosed"
+    );
+}
+
+#[test]
+fn it_groups_the_events_of_a_module_into_a_stream() {
+    let event = DomainEvent::StudentRegistered(student::StudentRegistered {
+        student_id: "alice".to_string(),
+    });
+
+    assert_eq!(
+        CourseEvent::SCHEMA.events,
+        &["CourseCreated", "CourseClosed"]
+    );
+    assert_eq!(
+        StudentEvent::SCHEMA.events,
+        &["StudentRegistered", "StudentsImported"]
+    );
+    assert!(CourseEvent::try_from(event.clone()).is_err());
+    let student_event = StudentEvent::try_from(event.clone()).unwrap();
+    assert_eq!(DomainEvent::from(student_event), event);
+}
@@ -1,4 +1,4 @@
-use disintegrate::{query, Event, StateQuery};
+use disintegrate::{query, union, Event, StateQuery};
 
 #[allow(dead_code)]
 #[derive(Event, Debug, PartialEq, Eq, Clone)]
@@ -34,6 +34,13 @@ struct UserOrder {
     order_id: String,
 }
 
+#[derive(StateQuery, Debug, PartialEq, Eq, Clone)]
+#[state_query(DomainEvent)]
+struct LegacyUserOrders {
+    #[id(rename = "user_id")]
+    uid: i64,
+}
+
 #[test]
 fn it_sets_the_name_of_a_state_query() {
     assert_eq!(UserOrders::NAME, "UserOrders");
@@ -61,3 +68,104 @@ fn it_builds_the_stream_query() {
         query!(DomainEvent; user_id == 2, order_id == "order1")
     );
 }
+
+#[derive(StateQuery, Debug, PartialEq, Eq, Clone)]
+#[state_query(DomainEvent, exclude = [OrderCreated])]
+struct User {
+    #[id]
+    user_id: i64,
+}
+
+#[test]
+fn it_queries_the_renamed_identifiers() {
+    assert_eq!(
+        LegacyUserOrders { uid: 1 }.query::<i64>(),
+        query!(DomainEvent; user_id == 1)
+    );
+}
+
+#[test]
+fn it_excludes_events_from_the_stream_query() {
+    let user = User { user_id: 1 };
+    assert_eq!(
+        user.query::<i64>(),
+        query!(DomainEvent; user_id == 1).exclude_events(&["OrderCreated"])
+    );
+    assert!(!user.query::<i64>().matches_event("OrderCreated"));
+}
+
+#[test]
+fn it_keeps_the_declared_exclusions_when_excluding_more_events() {
+    let user = User { user_id: 1 };
+    assert_eq!(
+        user.exclude_events::<i64>(&["UserCreated"]),
+        query!(DomainEvent; user_id == 1).exclude_events(&["OrderCreated", "UserCreated"])
+    );
+}
+
+#[derive(StateQuery, Debug, PartialEq, Eq, Clone)]
+#[state_query(DomainEvent, exclude(UserCreated))]
+struct Orders {
+    #[id]
+    user_id: i64,
+}
+
+#[test]
+fn it_excludes_the_events_listed_in_parentheses() {
+    assert_eq!(
+        Orders { user_id: 1 }.query::<i64>(),
+        query!(DomainEvent; user_id == 1).exclude_events(&["UserCreated"])
+    );
+}
+
+#[derive(StateQuery, Debug, PartialEq, Eq, Clone)]
+#[state_query(DomainEvent)]
+struct OptionalOrder {
+    #[id]
+    user_id: i64,
+    #[id(optional)]
+    order_id: Option<String>,
+}
+
+#[test]
+fn it_filters_by_the_optional_identifiers_only_when_set() {
+    let all_orders = OptionalOrder {
+        user_id: 1,
+        order_id: None,
+    };
+    assert_eq!(all_orders.query::<i64>(), query!(DomainEvent; user_id == 1));
+
+    let order = OptionalOrder {
+        user_id: 1,
+        order_id: Some("order1".to_string()),
+    };
+    assert_eq!(
+        order.query::<i64>(),
+        query!(DomainEvent; user_id == 1, order_id == "order1")
+    );
+}
+
+#[derive(StateQuery, Debug, PartialEq, Eq, Clone)]
+#[state_query(DomainEvent, exclude(UserCreated))]
+struct UserOrOrder {
+    #[id(group = "user")]
+    user_id: i64,
+    #[id(group = "order")]
+    order_id: String,
+}
+
+#[test]
+fn it_unions_the_groups_of_identifiers() {
+    let user_or_order = UserOrOrder {
+        user_id: 1,
+        order_id: "order1".to_string(),
+    };
+    let expected: disintegrate::StreamQuery<i64, DomainEvent> = union!(
+        query!(DomainEvent; user_id == 1),
+        query!(DomainEvent; order_id == "order1")
+    );
+    assert_eq!(
+        user_or_order.query::<i64>(),
+        expected.exclude_events(&["UserCreated"])
+    );
+}
@@ -0,0 +1,230 @@
+//! # PostgreSQL Event Archiver
+//!
+//! This module moves the old events of the event store to an archive database, to keep the `event` table and
+//! its indexes small. The archive has the schema of the event store and receives the rows as they are, with
+//! the payloads serialized by the `Serde` of the event store. A `PgEventStore` configured with `with_archive`
+//! keeps streaming the archived events, so the decisions and the listeners still see the full history.
+//!
+//! A batch first records the last archived event ID in the `event_archive_watermark` table, then copies the
+//! events to the archive and finally deletes them from the event store: an event is always readable from one
+//! of the two databases, and an event found in both is streamed once.
+use std::time::Duration;
+
+use disintegrate::Event;
+use disintegrate_serde::Serde;
+use futures::Future;
+use sqlx::PgPool;
+
+use crate::{Error, PgEventId, PgEventStore};
+
+#[cfg(test)]
+mod tests;
+
+/// Moves the events older than a cutoff from the event store to an archive database.
+///
+/// The events not yet handled by all the event listeners, and the events waiting in the outbox, are never
+/// archived. The archivers of several instances can run concurrently, as the events are copied idempotently.
+pub struct PgEventArchiver<E, S>
+where
+    E: Event,
+    S: Serde<E> + Send + Sync,
+{
+    event_store: PgEventStore<E, S>,
+    archive: PgPool,
+    older_than: Duration,
+    poll: Duration,
+    batch_size: i64,
+    covered_by_snapshots: bool,
+}
+
+impl<E, S> std::fmt::Debug for PgEventArchiver<E, S>
+where
+    E: Event,
+    S: Serde<E> + Send + Sync,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PgEventArchiver")
+            .field("event_store", &self.event_store)
+            .field("archive", &self.archive)
+            .field("older_than", &self.older_than)
+            .field("poll", &self.poll)
+            .field("batch_size", &self.batch_size)
+            .field("covered_by_snapshots", &self.covered_by_snapshots)
+            .finish()
+    }
+}
+
+impl<E, S> PgEventArchiver<E, S>
+where
+    E: Event + Clone + Send + Sync,
+    S: Serde<E> + Send + Sync,
+{
+    /// Creates a new `PgEventArchiver`, archiving the events older than 30 days every minute.
+    ///
+    /// The archive database is initialized with the schema of the event store.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_store` - The event store whose events are archived.
+    /// * `archive` - The PostgreSQL connection pool of the archive database.
+    pub async fn new(event_store: PgEventStore<E, S>, archive: PgPool) -> Result<Self, Error> {
+        crate::event_store::setup::<E>(&archive, &Default::default()).await?;
+        Ok(Self {
+            event_store,
+            archive,
+            older_than: Duration::from_secs(30 * 24 * 3600),
+            poll: Duration::from_secs(60),
+            batch_size: 1000,
+            covered_by_snapshots: false,
+        })
+    }
+
+    /// Sets the minimum age of the archived events.
+    ///
+    /// # Arguments
+    ///
+    /// * `older_than` - The age of the events, measured from their append.
+    pub fn with_older_than(mut self, older_than: Duration) -> Self {
+        self.older_than = older_than;
+        self
+    }
+
+    /// Sets the interval at which the archiver looks for old events when the previous batch was not full.
+    ///
+    /// # Arguments
+    ///
+    /// * `poll` - The poll interval.
+    pub fn with_poll(mut self, poll: Duration) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    /// Sets the maximum number of events moved by a single batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch_size` - The maximum number of events of a batch.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.clamp(1, i64::MAX as usize) as i64;
+        self
+    }
+
+    /// Archives only the events already incorporated in every snapshot of the `snapshot` table, so the
+    /// states loaded from a snapshot never read the archive.
+    pub fn covered_by_snapshots(mut self) -> Self {
+        self.covered_by_snapshots = true;
+        self
+    }
+
+    /// Moves a batch of old events, in event ID order, to the archive.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of archived events.
+    pub async fn archive(&self) -> Result<usize, Error> {
+        let pool = &self.event_store.pool;
+        let bound = self.bound().await?;
+        let event_ids: Vec<PgEventId> = sqlx::query_scalar(
+            r#"SELECT event_id FROM event
+               WHERE event_id <= $1 AND inserted_at < now() - make_interval(secs => $2)
+               AND NOT EXISTS (SELECT 1 FROM event_outbox o WHERE o.event_id = event.event_id)
+               ORDER BY event_id LIMIT $3"#,
+        )
+        .bind(bound)
+        .bind(self.older_than.as_secs_f64())
+        .bind(self.batch_size)
+        .fetch_all(pool)
+        .await?;
+        let Some(&last_event_id) = event_ids.last() else {
+            return Ok(0);
+        };
+
+        sqlx::query(
+            r#"INSERT INTO event_archive_watermark (archived_up_to) VALUES ($1)
+               ON CONFLICT (id) DO UPDATE SET archived_up_to = GREATEST(event_archive_watermark.archived_up_to, $1), updated_at = now()"#,
+        )
+        .bind(last_event_id)
+        .execute(pool)
+        .await?;
+        let rows: String = sqlx::query_scalar(
+            "SELECT jsonb_agg(to_jsonb(event))::TEXT FROM event WHERE event_id = ANY($1)",
+        )
+        .bind(&event_ids)
+        .fetch_one(pool)
+        .await?;
+        sqlx::query(
+            "INSERT INTO event SELECT * FROM jsonb_populate_recordset(NULL::event, $1::JSONB) ON CONFLICT (event_id) DO NOTHING",
+        )
+        .bind(rows)
+        .execute(&self.archive)
+        .await?;
+        sqlx::query("DELETE FROM event WHERE event_id = ANY($1)")
+            .bind(&event_ids)
+            .execute(pool)
+            .await?;
+        Ok(event_ids.len())
+    }
+
+    /// Archives the old events until the shutdown signal.
+    ///
+    /// The batches are moved back to back while they are full, then the event store is polled.
+    /// The connection errors are retried at the next poll.
+    ///
+    /// # Arguments
+    ///
+    /// * `shutdown` - A future that represents the shutdown signal.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating the success or failure of the archiver.
+    pub async fn start_with_shutdown<F: Future<Output = ()> + Send>(
+        self,
+        shutdown: F,
+    ) -> Result<(), Error> {
+        tokio::pin!(shutdown);
+        let mut poll = tokio::time::interval(self.poll);
+        poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = poll.tick() => {
+                    loop {
+                        match self.archive().await {
+                            Ok(archived) if archived as i64 == self.batch_size => continue,
+                            Ok(_)
+                            | Err(Error::Database(sqlx::Error::Io(_)))
+                            | Err(Error::Database(sqlx::Error::PoolTimedOut)) => break,
+                            Err(err) => return Err(err),
+                        }
+                    }
+                }
+                _ = &mut shutdown => return Ok(()),
+            }
+        }
+    }
+
+    /// Returns the last event ID that can be archived: the lowest checkpoint of the event listeners and,
+    /// if required, the lowest version of the snapshots.
+    async fn bound(&self) -> Result<PgEventId, Error> {
+        let pool = &self.event_store.pool;
+        let mut bound = PgEventId::MAX;
+        if crate::schema::table_exists(pool, "event_listener").await? {
+            let checkpoint: Option<PgEventId> =
+                sqlx::query_scalar("SELECT MIN(last_processed_event_id) FROM event_listener")
+                    .fetch_one(pool)
+                    .await?;
+            bound = bound.min(checkpoint.unwrap_or(PgEventId::MAX));
+        }
+        if self.covered_by_snapshots {
+            let version: Option<PgEventId> =
+                if crate::schema::table_exists(pool, "snapshot").await? {
+                    sqlx::query_scalar("SELECT MIN(version) FROM snapshot")
+                        .fetch_one(pool)
+                        .await?
+                } else {
+                    None
+                };
+            bound = bound.min(version.unwrap_or(0));
+        }
+        Ok(bound)
+    }
+}
@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use super::*;
+use disintegrate::{query, EventStore, Metadata, CORRELATION_ID};
+use disintegrate_serde::serde::json::Json;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, disintegrate::Event)]
+#[serde(tag = "event_type", rename_all = "snake_case")]
+enum OrderEvent {
+    OrderPlaced {
+        #[id]
+        order_id: String,
+    },
+}
+
+fn order_placed(order_id: &str) -> OrderEvent {
+    OrderEvent::OrderPlaced {
+        order_id: order_id.to_string(),
+    }
+}
+
+/// Connects to the event store and to an archive in the `archive` schema of the same database.
+async fn pools(pool_options: PgPoolOptions, connect_options: PgConnectOptions) -> (PgPool, PgPool) {
+    let pool = pool_options
+        .clone()
+        .connect_with(connect_options.clone())
+        .await
+        .unwrap();
+    sqlx::query("CREATE SCHEMA archive")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let archive_pool = pool_options
+        .connect_with(connect_options.options([("search_path", "archive")]))
+        .await
+        .unwrap();
+    (pool, archive_pool)
+}
+
+async fn event_store(pool: PgPool) -> PgEventStore<OrderEvent, Json<OrderEvent>> {
+    let event_store = PgEventStore::new(pool, Json::<OrderEvent>::default())
+        .await
+        .unwrap();
+    event_store
+        .append(
+            vec![order_placed("o1"), order_placed("o2"), order_placed("o3")],
+            query!(OrderEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    event_store
+}
+
+async fn event_ids(pool: &PgPool) -> Vec<PgEventId> {
+    sqlx::query_scalar("SELECT event_id FROM event ORDER BY event_id")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn it_moves_the_old_events_to_the_archive(
+    pool_options: PgPoolOptions,
+    connect_options: PgConnectOptions,
+) {
+    let (pool, archive_pool) = pools(pool_options, connect_options).await;
+    let event_store = event_store(pool.clone()).await;
+    let archiver = PgEventArchiver::new(event_store.clone(), archive_pool.clone())
+        .await
+        .unwrap()
+        .with_older_than(Duration::ZERO)
+        .with_batch_size(2);
+
+    assert_eq!(archiver.archive().await.unwrap(), 2);
+    assert_eq!(event_ids(&pool).await, vec![3]);
+    assert_eq!(event_ids(&archive_pool).await, vec![1, 2]);
+
+    let event_store = event_store.with_archive(archive_pool);
+    let events: Vec<(PgEventId, OrderEvent)> = event_store
+        .stream(&query!(OrderEvent))
+        .map(|event| {
+            let event = event.unwrap();
+            (event.id(), event.into_inner())
+        })
+        .collect()
+        .await;
+    assert_eq!(
+        events,
+        vec![
+            (1, order_placed("o1")),
+            (2, order_placed("o2")),
+            (3, order_placed("o3")),
+        ]
+    );
+    let event_ids: Vec<PgEventId> = event_store
+        .stream(&query!(OrderEvent).change_origin(1))
+        .map(|event| event.unwrap().id())
+        .collect()
+        .await;
+    assert_eq!(event_ids, vec![2, 3]);
+}
+
+#[sqlx::test]
+async fn it_streams_the_archived_events_by_their_metadata(
+    pool_options: PgPoolOptions,
+    connect_options: PgConnectOptions,
+) {
+    let (pool, archive_pool) = pools(pool_options, connect_options).await;
+    let event_store = PgEventStore::new_uninitialized(pool.clone(), Json::<OrderEvent>::default())
+        .with_metadata_index(CORRELATION_ID)
+        .initialize()
+        .await
+        .unwrap();
+    for (version, order_id) in ["o1", "o2"].into_iter().enumerate() {
+        event_store
+            .append_with_metadata(
+                vec![order_placed(order_id)],
+                Metadata::new().with_correlation_id("request-1"),
+                query!(OrderEvent),
+                version as PgEventId,
+            )
+            .await
+            .unwrap();
+    }
+    let archiver = PgEventArchiver::new(event_store.clone(), archive_pool.clone())
+        .await
+        .unwrap()
+        .with_older_than(Duration::ZERO)
+        .with_batch_size(1);
+    assert_eq!(archiver.archive().await.unwrap(), 1);
+
+    let event_store = event_store.with_archive(archive_pool);
+    let event_ids: Vec<PgEventId> = event_store
+        .stream_by_metadata(&query!(OrderEvent), CORRELATION_ID, "request-1")
+        .map(|event| event.unwrap().id())
+        .collect()
+        .await;
+
+    assert_eq!(event_ids, vec![1, 2]);
+}
+
+#[sqlx::test]
+async fn it_archives_only_the_events_covered_by_the_snapshots(
+    pool_options: PgPoolOptions,
+    connect_options: PgConnectOptions,
+) {
+    let (pool, archive_pool) = pools(pool_options, connect_options).await;
+    let event_store = event_store(pool.clone()).await;
+    crate::PgSnapshotter::new(pool.clone(), 10).await.unwrap();
+    sqlx::query(
+        "INSERT INTO snapshot (id, name, query, version, payload) VALUES (gen_random_uuid(), 'order', 'order_id=o1', 1, '{}')",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let archiver = PgEventArchiver::new(event_store, archive_pool.clone())
+        .await
+        .unwrap()
+        .with_older_than(Duration::ZERO)
+        .covered_by_snapshots();
+
+    assert_eq!(archiver.archive().await.unwrap(), 1);
+    assert_eq!(archiver.archive().await.unwrap(), 0);
+    assert_eq!(event_ids(&pool).await, vec![2, 3]);
+    assert_eq!(event_ids(&archive_pool).await, vec![1]);
+}
+
+#[sqlx::test]
+async fn it_keeps_the_recent_events(
+    pool_options: PgPoolOptions,
+    connect_options: PgConnectOptions,
+) {
+    let (pool, archive_pool) = pools(pool_options, connect_options).await;
+    let event_store = event_store(pool.clone()).await;
+    let archiver = PgEventArchiver::new(event_store, archive_pool.clone())
+        .await
+        .unwrap()
+        .with_older_than(Duration::from_secs(3600));
+
+    assert_eq!(archiver.archive().await.unwrap(), 0);
+    assert_eq!(event_ids(&pool).await, vec![1, 2, 3]);
+}
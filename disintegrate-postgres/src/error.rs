@@ -10,6 +10,16 @@ pub enum Error {
     /// An error occurred while deserializing an event payload.
     #[error(transparent)]
     Deserialization(#[from] disintegrate_serde::Error),
+    /// Some events of the store cannot be deserialized by the current serde.
+    #[error("events not deserializable by the current serde: {}", .0.iter().map(|event| format!("`{}` (event {}: {})", event.event_type, event.event_id, event.reason)).collect::<Vec<_>>().join(", "))]
+    IncompatibleEvents(Vec<crate::IncompatibleEvent>),
+    /// An error occurred while serializing or deserializing a value stored as JSON, e.g. the value of a
+    /// key-value store or a scheduled decision.
+    #[error("unable to serialize or deserialize the value: {0}")]
+    ValueSerialization(#[from] serde_json::Error),
+    /// A stream query built at runtime is not valid for the events, e.g. it refers to an unknown domain identifier.
+    #[error(transparent)]
+    InvalidQuery(#[from] disintegrate::StreamQueryError),
     /// An error occurred while mapping the event store event to the query event
     #[error("unable to map the event store event to the query event: {0}")]
     QueryEventMapping(#[source] Box<dyn StdError + 'static + Send + Sync>),
@@ -19,4 +29,105 @@ pub enum Error {
     /// used to make the current business decision. The event store's state has changed, potentially affecting the decision-making process.
     #[error("concurrent modification error")]
     Concurrency,
+    /// The event type has been added to the deny-list of the event store.
+    #[error("event type `{event_type}` is denied: {}", reason.as_deref().unwrap_or("no reason given"))]
+    EventTypeDenied {
+        event_type: String,
+        reason: Option<String>,
+    },
+    /// An event carries a domain identifier whose columns have not been added to the database yet.
+    #[error("the columns of domain identifier `{0}` have not been added to the event store yet")]
+    SchemaNotReady(String),
+    /// The database has been initialized with a schema version not supported by this version of the library.
+    #[error("incompatible schema version: found {found}, expected {expected}")]
+    IncompatibleSchema { found: i32, expected: i32 },
+    /// The database has been initialized by a newer version of the library, whose schema this version may not
+    /// handle, e.g. after a rollback of the application.
+    #[error("the database has been initialized by the newer library version {found}, this is version {running}")]
+    NewerLibrary {
+        found: String,
+        running: &'static str,
+    },
+    /// A domain identifier column of the event store has a type different from the one of the event schema,
+    /// e.g. because a bounded context changed the type of an identifier shared with the other contexts.
+    #[error("the domain identifier `{identifier}` is a {expected} in the event schema but the event store column is a {found}")]
+    IdentifierTypeMismatch {
+        identifier: String,
+        expected: &'static str,
+        found: String,
+    },
+    /// Another instance is initializing the database and did not complete within the setup lock timeout.
+    #[error("the database is being initialized by another instance")]
+    AlreadyInitializing,
+    /// A restored event listener checkpoint refers to an event not contained in the event store.
+    #[error("the checkpoint of event listener `{listener}` ({last_processed_event_id}) is ahead of the last event in the store ({last_event_id})")]
+    CheckpointAheadOfStore {
+        listener: String,
+        last_processed_event_id: i64,
+        last_event_id: i64,
+    },
+    /// The event listener has never been started against the database.
+    #[error("unknown event listener `{0}`")]
+    UnknownListener(String),
+    /// The heartbeat event of the health probe has not been notified within the timeout.
+    #[error("the heartbeat event has not been notified within {0:?}")]
+    ProbeTimeout(std::time::Duration),
+    /// An event listener has been configured to exclude an event that does not exist in the event schema.
+    #[error("event listener `{listener}` excludes the unknown event `{event}`")]
+    UnknownExcludedEvent {
+        listener: &'static str,
+        event: String,
+    },
+    /// An event listener has been partitioned by a domain identifier that does not exist in the event schema.
+    #[error("event listener `{listener}` is partitioned by the unknown domain identifier `{identifier}`")]
+    UnknownPartitionIdentifier {
+        listener: &'static str,
+        identifier: String,
+    },
+    /// An event listener has been sharded by a domain identifier that does not exist in the event schema.
+    #[error(
+        "event listener `{listener}` is sharded by the unknown domain identifier `{identifier}`"
+    )]
+    UnknownShardIdentifier {
+        listener: &'static str,
+        identifier: String,
+    },
+    /// Events have been appended to a read-only event store.
+    #[error("the event store is read-only")]
+    ReadOnly,
+    /// An event listener has been stopped by its retry policy after failing to handle an event.
+    #[error("event listener `{listener}` aborted by its retry policy")]
+    ListenerAborted {
+        listener: &'static str,
+        event_id: Option<crate::PgEventId>,
+    },
+    /// The projection has not been registered in the projection runner.
+    #[error("unknown projection `{0}`")]
+    UnknownProjection(String),
+    /// The read model of a projection could not be reset before a rebuild.
+    #[error("unable to reset projection `{projection}`: {source}")]
+    ProjectionReset {
+        projection: &'static str,
+        #[source]
+        source: Box<dyn StdError + 'static + Send + Sync>,
+    },
+    /// An event listener failed to handle an event replayed by a `PgReplayer`.
+    #[error(
+        "event listener `{listener}` failed to handle the replayed event {event_id}: {source}"
+    )]
+    ReplayFailed {
+        listener: &'static str,
+        event_id: crate::PgEventId,
+        #[source]
+        source: Box<dyn StdError + 'static + Send + Sync>,
+    },
+    /// The events have been looked up by a key of the metadata that has not been indexed with `with_metadata_index`.
+    #[error("the metadata key `{0}` is not indexed")]
+    UnindexedMetadataKey(String),
+    /// The snapshots built from the redacted events could not be deleted from a snapshot store.
+    #[error("unable to invalidate the snapshots: {0}")]
+    SnapshotInvalidation(#[source] Box<dyn StdError + 'static + Send + Sync>),
+    /// The event does not exist in the event store.
+    #[error("unknown event {0}")]
+    UnknownEvent(crate::PgEventId),
 }
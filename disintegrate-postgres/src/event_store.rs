@@ -2,40 +2,121 @@
 //!
 //! This module provides an implementation of the `Snapshotter` trait using PostgreSQL as the underlying storage.
 //! It allows storing and retrieving snapshots from a PostgreSQL database.
+mod analysis;
+mod decoding;
+mod deny_list;
+mod fan_in;
+mod identifier_columns;
+mod identifier_index;
 mod insert_builder;
+mod metadata_filter;
+mod observers;
 mod query_builder;
+mod redaction;
+mod retraction;
+mod retry;
 #[cfg(test)]
 mod tests;
+mod verification;
 
+pub use analysis::QueryAnalysis;
+use decoding::Decoding;
+use deny_list::DenyList;
+pub use fan_in::PgFanInEventStore;
 use futures::stream::BoxStream;
-use insert_builder::InsertBuilder;
+use identifier_columns::IdentifierColumns;
+use identifier_index::create_identifier_indexes;
+pub use identifier_index::PgIdentifierIndex;
+use insert_builder::{BatchInsertBuilder, InsertBuilder};
+#[cfg(feature = "listener")]
+pub(crate) use observers::AppendObserver;
+use observers::AppendObservers;
 use query_builder::QueryBuilder;
-use sqlx::{PgPool, Row};
+pub use retraction::RETRACT_EVENT_TYPE;
+pub use retry::PgRetryPolicy;
+use sqlx::postgres::{PgArguments, PgRow};
+use sqlx::query::Query;
+use sqlx::{PgConnection, PgPool, Postgres, Row};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::error::Error as StdError;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+pub use verification::IncompatibleEvent;
 
 use std::marker::PhantomData;
 
-use crate::{Error, PgEventId};
-use async_stream::stream;
+use crate::{Error, PgEventId, StoreMetadata};
+use async_stream::{stream, try_stream};
 use async_trait::async_trait;
 use disintegrate::StreamQuery;
-use disintegrate::{DomainIdentifierInfo, EventStore};
+use disintegrate::{
+    Cursor, DomainIdentifierInfo, EventStore, EventTimestampResolver, Identifier, Metadata,
+    MigrationPlan, SnapshotStore, StoreMigrations,
+};
 use disintegrate::{Event, PersistedEvent};
-use disintegrate_serde::Serde;
+use disintegrate_serde::{PayloadInfo, Serde};
 
 use futures::StreamExt;
 
+/// The default maximum number of events inserted by a single statement during an append.
+pub const DEFAULT_APPEND_BATCH_SIZE: usize = 1000;
+
+/// The default number of rows fetched and decoded together by a stream.
+pub const DEFAULT_STREAM_BATCH_SIZE: usize = 1000;
+
+/// The maximum time a change of the deny list made by another instance, or directly with SQL, takes to be
+/// enforced by the appends.
+pub const DENY_LIST_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
 /// PostgreSQL event store implementation.
 #[derive(Clone)]
 pub struct PgEventStore<E, S>
 where
+    E: Event,
     S: Serde<E> + Send + Sync,
 {
     pub(crate) pool: PgPool,
-    serde: S,
+    pub(crate) serde: Arc<S>,
+    append_batch_size: usize,
+    stream_batch_size: usize,
+    server_side_cursor: bool,
+    observers: Arc<AppendObservers<E>>,
+    deny_list: Arc<DenyList>,
+    identifier_columns: Arc<IdentifierColumns>,
+    identifier_indexes: HashMap<Identifier, PgIdentifierIndex>,
+    metadata_indexes: BTreeSet<String>,
+    pub(crate) retry_policy: PgRetryPolicy,
+    outbox_event_types: HashSet<String>,
+    skip_retracted_events: bool,
+    archive: Option<PgPool>,
+    snapshot_stores: Vec<Arc<dyn SnapshotStore<PgEventId>>>,
+    schema: Option<String>,
     event_type: PhantomData<E>,
 }
 
+impl<E, S> std::fmt::Debug for PgEventStore<E, S>
+where
+    E: Event,
+    S: Serde<E> + Send + Sync,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PgEventStore")
+            .field("pool", &self.pool)
+            .field("append_batch_size", &self.append_batch_size)
+            .field("stream_batch_size", &self.stream_batch_size)
+            .field("server_side_cursor", &self.server_side_cursor)
+            .field("identifier_indexes", &self.identifier_indexes)
+            .field("metadata_indexes", &self.metadata_indexes)
+            .field("retry_policy", &self.retry_policy)
+            .field("outbox_event_types", &self.outbox_event_types)
+            .field("skip_retracted_events", &self.skip_retracted_events)
+            .field("archive", &self.archive)
+            .field("snapshot_stores", &self.snapshot_stores.len())
+            .field("schema", &self.schema)
+            .finish_non_exhaustive()
+    }
+}
+
 impl<E, S> PgEventStore<E, S>
 where
     S: Serde<E> + Send + Sync,
@@ -48,8 +129,25 @@ where
     /// * `pool` - The PostgreSQL connection pool.
     /// * `serde` - The serialization implementation for the event payload.
     pub async fn new(pool: PgPool, serde: S) -> Result<Self, Error> {
-        setup::<E>(&pool).await?;
-        Ok(Self::new_uninitialized(pool, serde))
+        Self::new_uninitialized(pool, serde).initialize().await
+    }
+
+    /// Initializes the PostgreSQL DB with the configuration of the event store, e.g. the indexes of the
+    /// domain identifiers.
+    ///
+    /// # Returns
+    ///
+    /// The initialized `PgEventStore`, or an error if the setup fails.
+    pub async fn initialize(self) -> Result<Self, Error> {
+        self.create_schema().await?;
+        setup::<E>(&self.pool, &self.identifier_indexes).await?;
+        self.create_metadata_indexes().await?;
+        Ok(Self {
+            identifier_columns: Arc::new(IdentifierColumns::with_available(
+                E::SCHEMA.domain_identifiers,
+            )),
+            ..self
+        })
     }
     /// Creates a new instance of `PgEventStore`.
     ///
@@ -62,6 +160,10 @@ where
     /// to recreate the default structure. Additionally, all `domain_identifier` columns
     /// and their corresponding indexes must be created manually.
     ///
+    /// The columns can be added while the store is running, e.g. during a rolling deploy: until the
+    /// columns of a domain identifier exist, the queries on it match no events and the appends of
+    /// events carrying it fail with `Error::SchemaNotReady`.
+    ///
     /// # Arguments
     ///
     /// * `pool` - The PostgreSQL connection pool.
@@ -69,10 +171,254 @@ where
     pub fn new_uninitialized(pool: PgPool, serde: S) -> Self {
         Self {
             pool,
-            serde,
+            serde: Arc::new(serde),
+            append_batch_size: DEFAULT_APPEND_BATCH_SIZE,
+            stream_batch_size: DEFAULT_STREAM_BATCH_SIZE,
+            server_side_cursor: false,
+            observers: Arc::new(AppendObservers::default()),
+            deny_list: Arc::new(DenyList::default()),
+            identifier_columns: Arc::new(IdentifierColumns::default()),
+            identifier_indexes: HashMap::new(),
+            metadata_indexes: BTreeSet::new(),
+            retry_policy: PgRetryPolicy::default(),
+            outbox_event_types: HashSet::new(),
+            skip_retracted_events: false,
+            archive: None,
+            snapshot_stores: vec![],
+            schema: None,
             event_type: PhantomData,
         }
     }
+
+    /// Sets the maximum number of events inserted by a single statement during an append.
+    ///
+    /// Larger appends are split into chunks of this size, all inserted in the same transaction.
+    /// The chunks are further limited so that a statement never exceeds the PostgreSQL bind parameters limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `append_batch_size` - The maximum number of events per insert statement.
+    pub fn with_append_batch_size(mut self, append_batch_size: usize) -> Self {
+        self.append_batch_size = append_batch_size.max(1);
+        self
+    }
+
+    /// Sets the number of rows fetched and decoded together by a stream.
+    ///
+    /// The payloads of a batch are deserialized on the blocking thread pool of Tokio while the next batch is
+    /// fetched, unless the batch is small, e.g. the few events of an aggregate.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_batch_size` - The maximum number of rows per batch.
+    pub fn with_stream_batch_size(mut self, stream_batch_size: usize) -> Self {
+        self.stream_batch_size = stream_batch_size.max(1);
+        self
+    }
+
+    /// Streams the events through a server-side cursor, fetching `stream_batch_size` rows at a time.
+    ///
+    /// A batch is fetched only when the previous one has been consumed, so a long stream, e.g. the events
+    /// replayed to load a state without snapshot, holds at most two batches in memory whatever the pace of
+    /// its consumer. The cursor keeps a transaction open until the end of the stream.
+    pub fn with_server_side_cursor(mut self) -> Self {
+        self.server_side_cursor = true;
+        self
+    }
+
+    /// Sets the index of a domain identifier, e.g. a compact index for an identifier with billions of distinct values.
+    ///
+    /// The indexes are created, or replaced if they were created with a different kind, when the database is
+    /// initialized with `initialize` or migrated. The other domain identifiers use `PgIdentifierIndex::Standard`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ident` - The domain identifier.
+    /// * `index` - The index of the domain identifier columns.
+    pub fn with_identifier_index(mut self, ident: Identifier, index: PgIdentifierIndex) -> Self {
+        self.identifier_indexes.insert(ident, index);
+        self
+    }
+
+    /// Indexes a key of the metadata, so the events can be looked up by its value with `stream_by_metadata`,
+    /// e.g. all the events appended by a request with its `correlation_id`.
+    ///
+    /// The index is created when the database is initialized with `initialize` or migrated.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the metadata.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not made of lowercase ASCII letters, digits and underscores.
+    pub fn with_metadata_index(mut self, key: &str) -> Self {
+        metadata_filter::validate_key(key);
+        self.metadata_indexes.insert(key.to_string());
+        self
+    }
+
+    /// Sets the retry policy of the transient database errors, e.g. a connection reset or a serialization failure.
+    ///
+    /// The reads are retried, resuming a stream after the last event it returned. An append is retried only
+    /// until the IDs of its events are reserved: after that point it cannot be told whether a failed statement
+    /// took effect, so the error is returned. By default, no statement is retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `retry_policy` - The retry policy of the transient errors.
+    pub fn with_retry_policy(mut self, retry_policy: PgRetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Marks event types for the publication to other services through the outbox.
+    ///
+    /// The appended events of these types are recorded in the `event_outbox` table in the same transaction,
+    /// and published by a `PgOutboxRelay`.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_types` - The names of the event types to publish.
+    pub fn with_outbox<I, T>(mut self, event_types: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.outbox_event_types
+            .extend(event_types.into_iter().map(Into::into));
+        self
+    }
+
+    /// Skips the retracted events in the streams of the event store.
+    ///
+    /// The retractions do not conflict with the decisions in progress: a decision based on a retracted event
+    /// may still be appended, until the state is loaded again.
+    pub fn skip_retracted_events(mut self) -> Self {
+        self.skip_retracted_events = true;
+        self
+    }
+
+    /// Reads the events moved by a `PgEventArchiver` from the archive database.
+    ///
+    /// The streams whose query starts before the last archived event read the events from both databases,
+    /// merged in event ID order, so the archived events remain visible to the decisions and to the listeners.
+    /// The other streams read only the event store.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The PostgreSQL connection pool of the archive database.
+    pub fn with_archive(mut self, pool: PgPool) -> Self {
+        self.archive = Some(pool);
+        self
+    }
+
+    /// Adds a snapshot store whose snapshots are invalidated when the events are redacted.
+    ///
+    /// The snapshots of the `snapshot` table of the event store are always invalidated; the stores kept
+    /// elsewhere, e.g. a `FileSnapshotStore`, must be added to be invalidated too.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The snapshot store used by the snapshotters of the states.
+    pub fn with_snapshot_store(mut self, store: impl SnapshotStore<PgEventId> + 'static) -> Self {
+        self.snapshot_stores.push(Arc::new(store));
+        self
+    }
+
+    /// Isolates the event store of a tenant in its own PostgreSQL schema.
+    ///
+    /// The connections of the event store are opened with the `search_path` set to the schema, so the events,
+    /// the listener checkpoints and every other table of the tenant live in the schema, created by `initialize`.
+    /// The other components, e.g. the `PgSnapshotter`, share the isolation when created with the `pool` of the
+    /// event store. The pool of the schema is capped to `SCHEMA_POOL_MAX_CONNECTIONS` connections, and keeps
+    /// `public` on its `search_path`. The schema must be set before `initialize`:
+    ///
+    /// ```ignore
+    /// let event_store = PgEventStore::new_uninitialized(pool, serde)
+    ///     .with_schema("tenant_x")
+    ///     .initialize()
+    ///     .await?;
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - The name of the schema of the tenant.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name of the schema is not a lowercase SQL identifier, made of ASCII letters, digits and
+    /// underscores and not starting with a digit.
+    pub fn with_schema(mut self, schema: &str) -> Self {
+        self.pool = crate::schema::connect_schema(&self.pool, schema);
+        self.deny_list = Arc::new(DenyList::default());
+        self.identifier_columns = Arc::new(IdentifierColumns::default());
+        self.schema = Some(schema.to_string());
+        self
+    }
+
+    /// Returns the connection pool of the event store, e.g. to create the other components in the schema
+    /// set with `with_schema`.
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Adds an event type to the deny-list, making `append` reject the events of this type.
+    ///
+    /// The deny-list is stored in the `event_type_deny_list` table and is meant to stop a runaway producer
+    /// during an incident without shutting the whole service down. The deny-list is cached by the event
+    /// stores: the other instances reject the event type within `DENY_LIST_REFRESH_INTERVAL`.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The name of the event type to deny.
+    /// * `reason` - The reason why the event type is denied.
+    pub async fn deny_event_type(&self, event_type: &str, reason: &str) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO event_type_deny_list (event_type, reason) VALUES ($1, $2) ON CONFLICT (event_type) DO UPDATE SET reason = $2",
+        )
+        .bind(event_type)
+        .bind(reason)
+        .execute(&self.pool)
+        .await?;
+        self.deny_list.invalidate();
+        Ok(())
+    }
+
+    /// Removes an event type from the deny-list.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The name of the event type to allow.
+    pub async fn allow_event_type(&self, event_type: &str) -> Result<(), Error> {
+        sqlx::query("DELETE FROM event_type_deny_list WHERE event_type = $1")
+            .bind(event_type)
+            .execute(&self.pool)
+            .await?;
+        self.deny_list.invalidate();
+        Ok(())
+    }
+
+    /// Returns the metadata stamped in the `disintegrate_meta` table, if the database has been initialized.
+    pub async fn metadata(&self) -> Result<Option<StoreMetadata>, Error> {
+        crate::metadata::load(&self.pool).await
+    }
+
+    /// Creates the schema set with `with_schema`, if any.
+    async fn create_schema(&self) -> Result<(), Error> {
+        match &self.schema {
+            Some(schema) => crate::schema::create_schema(&self.pool, schema).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Registers an in-process observer of the appended events.
+    ///
+    /// The observer is called after each successful append of this store or of its clones, until it is dropped.
+    #[cfg(feature = "listener")]
+    pub(crate) fn observe_appends(&self, observer: &Arc<AppendObserver<E>>) {
+        self.observers.observe(observer);
+    }
 }
 
 /// Implementation of the event store using PostgreSQL.
@@ -83,15 +429,15 @@ where
 #[async_trait]
 impl<E, S> EventStore<PgEventId, E> for PgEventStore<E, S>
 where
-    E: Event + Send + Sync,
-    S: Serde<E> + Send + Sync,
+    E: Event + Send + Sync + 'static,
+    S: Serde<E> + Send + Sync + 'static,
 {
     type Error = Error;
 
     /// Streams events based on the provided query.
     ///
     /// This function fetches events from the PostgreSQL event store based on the provided
-    /// `query`. It constructs a SQL query using the `SqlEventsCriteriaBuilder` and executes
+    /// `query`. It constructs a SQL query using the `QueryBuilder` and executes
     /// the query using the `sqlx` crate. The fetched events are then converted into
     /// `PersistedEvent` instances and streamed as a boxed stream.
     ///
@@ -111,23 +457,86 @@ where
         QE: TryFrom<E> + Event + 'static + Clone + Send + Sync,
         <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
     {
-        stream! {
-            let mut sql = QueryBuilder::new(query.clone(), "SELECT event_id, payload FROM event WHERE ")
-            .end_with("ORDER BY event_id ASC");
+        let events = self.stream_with_archive(query, None);
+        #[cfg(feature = "otel")]
+        let events =
+            crate::otel::InstrumentedStream::new(events, crate::otel::stream_span(query)).boxed();
+        events
+    }
 
-            for await row in sql.build()
-            .fetch(&self.pool) {
-                let row = row?;
-                let id = row.get(0);
+    /// Returns a page of the events matching the query, in event ID order.
+    ///
+    /// The page is read by a single SQL query, limited to `limit + 1` events to detect the end of the
+    /// event stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The stream query specifying the criteria for filtering events.
+    /// * `cursor` - The position of the page.
+    /// * `limit` - The maximum number of events of the page.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the events of the page and the cursor of the next page, `Cursor::End` if the
+    /// limit is zero, or an error of type `Self::Error`.
+    async fn stream_page<QE>(
+        &self,
+        query: &StreamQuery<PgEventId, QE>,
+        cursor: Cursor<PgEventId>,
+        limit: usize,
+    ) -> Result<(Vec<PersistedEvent<PgEventId, QE>>, Cursor<PgEventId>), Self::Error>
+    where
+        QE: TryFrom<E> + Event + 'static + Clone + Send + Sync,
+        <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
+    {
+        let after = match cursor {
+            _ if limit == 0 => return Ok((vec![], Cursor::End)),
+            Cursor::Start => 0,
+            Cursor::After(event_id) => event_id,
+            Cursor::End => return Ok((vec![], Cursor::End)),
+        };
+        let missing_identifiers = self
+            .retry_policy
+            .retry(|| {
+                self.identifier_columns
+                    .missing(&self.pool, QE::SCHEMA.domain_identifiers)
+            })
+            .await?;
+        let init = self.stream_select();
+        let rows = self
+            .retry_policy
+            .retry(|| async {
+                let mut sql = QueryBuilder::new(query.clone(), &init)
+                    .push_bind(after)
+                    .push(" AND (")
+                    .with_missing_identifiers(missing_identifiers.clone())
+                    .end_with(") ORDER BY event_id ASC")
+                    .limit(limit.saturating_add(1).min(i64::MAX as usize) as i64);
+                Ok(sql.build().fetch_all(&self.pool).await?)
+            })
+            .await?;
+        let events = Decoding::start(&self.serde, rows).events().await?;
+        Ok(cursor.page(events, limit))
+    }
 
-                let payload = self.serde.deserialize(row.get(1))?;
-                yield Ok(PersistedEvent::new(id, payload.try_into().map_err(|e| Error::QueryEventMapping(Box::new(e)))?));
-            }
-        }
-        .boxed()
+    /// Appends new events to the event store, without metadata.
+    ///
+    /// See `append_with_metadata`.
+    async fn append<QE>(
+        &self,
+        events: Vec<E>,
+        query: StreamQuery<PgEventId, QE>,
+        version: PgEventId,
+    ) -> Result<Vec<PersistedEvent<PgEventId, E>>, Self::Error>
+    where
+        E: Clone + 'async_trait,
+        QE: Event + 'static + Clone + Send + Sync,
+    {
+        self.append_with_metadata(events, Metadata::default(), query, version)
+            .await
     }
 
-    /// Appends new events to the event store.
+    /// Appends new events to the event store, together with their metadata.
     ///
     /// This function inserts the provided `events` into the PostgreSQL event store by performing
     /// two separate inserts. First, it inserts the events into the `event_sequence` table to reclaim
@@ -138,9 +547,19 @@ where
     /// meaning that the events generated are no longer valid due to being generated from an old version
     /// of the event store.
     ///
+    /// The events are inserted into the `event` table in chunks of at most `append_batch_size` events,
+    /// all in the same transaction.
+    ///
+    /// Before appending, the event types are checked against the `event_type_deny_list` table, cached for at
+    /// most `DENY_LIST_REFRESH_INTERVAL`: if an event type is denied, no event is appended and an `EventTypeDenied` error is returned. Likewise, if an event carries
+    /// a domain identifier whose columns do not exist yet, a `SchemaNotReady` error is returned.
+    ///
+    /// The metadata are stored as JSON in the `metadata` column of the `event` table.
+    ///
     /// # Arguments
     ///
     /// * `events` - A vector of events to be appended.
+    /// * `metadata` - The metadata of the appended events.
     /// * `query` - The stream query specifying the criteria for filtering events.
     /// * `version` - The ID of the last consumed event.
     ///
@@ -148,16 +567,247 @@ where
     ///
     /// A `Result` containing a vector of `PersistedEvent` representing the appended events,
     /// or an error of type `Self::Error`.
-    async fn append<QE>(
+    async fn append_with_metadata<QE>(
         &self,
         events: Vec<E>,
+        metadata: Metadata,
         query: StreamQuery<PgEventId, QE>,
         version: PgEventId,
     ) -> Result<Vec<PersistedEvent<PgEventId, E>>, Self::Error>
     where
         E: Clone + 'async_trait,
+        QE: Event + 'static + Clone + Send + Sync,
+    {
+        #[cfg(feature = "otel")]
+        let span = crate::otel::append_span(events.len(), &query);
+        let append = self.append_events(events, metadata, query, version);
+        #[cfg(feature = "otel")]
+        let append = tracing::Instrument::instrument(append, span.clone());
+        let result = append.await;
+        #[cfg(feature = "otel")]
+        crate::otel::record_append(&span, &result);
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_append(&result);
+        result
+    }
+}
+
+impl<E, S> PgEventStore<E, S>
+where
+    E: Event + Send + Sync + 'static,
+    S: Serde<E> + Send + Sync + 'static,
+{
+    /// Returns the SQL selecting the streamed events, up to the lower bound of their IDs, which is followed by
+    /// the bind parameter of the bound and the criteria of the query.
+    fn stream_select(&self) -> String {
+        let retracted_criteria = if self.skip_retracted_events {
+            "NOT EXISTS (SELECT 1 FROM event_retraction r WHERE r.event_id = event.event_id) AND "
+        } else {
+            ""
+        };
+        format!("SELECT event_id, payload, metadata::TEXT, event_version, event_type FROM event WHERE {retracted_criteria}event_id > ")
+    }
+
+    /// Streams the events matching the query from the event store, and from its archive if the query starts
+    /// before the archived events.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The stream query specifying the criteria for filtering events.
+    /// * `metadata` - The key of the metadata and the value the events must have been appended with, if any.
+    pub(crate) fn stream_with_archive<'a, QE>(
+        &'a self,
+        query: &'a StreamQuery<PgEventId, QE>,
+        metadata: Option<(&'a str, &'a str)>,
+    ) -> BoxStream<'a, Result<PersistedEvent<PgEventId, QE>, Error>>
+    where
+        QE: TryFrom<E> + Event + 'static + Clone + Send + Sync,
+        <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
+    {
+        match &self.archive {
+            None => self.stream_events(&self.pool, query, metadata),
+            Some(archive) => stream! {
+                let archived_up_to = self
+                    .retry_policy
+                    .retry(|| async {
+                        Ok(sqlx::query_scalar::<_, PgEventId>(
+                            "SELECT COALESCE(MAX(archived_up_to), 0) FROM event_archive_watermark",
+                        )
+                        .fetch_one(&self.pool)
+                        .await?)
+                    })
+                    .await?;
+                let origin = query
+                    .filters()
+                    .iter()
+                    .map(|filter| filter.origin())
+                    .min()
+                    .unwrap_or_default();
+                let mut events = if origin < archived_up_to {
+                    fan_in::merge(vec![
+                        self.stream_events(archive, query, metadata),
+                        self.stream_events(&self.pool, query, metadata),
+                    ])
+                } else {
+                    self.stream_events(&self.pool, query, metadata)
+                };
+                while let Some(event) = events.next().await {
+                    yield event;
+                }
+            }
+            .boxed(),
+        }
+    }
+
+    /// Streams the events matching the query from a database, the event store or its archive.
+    fn stream_events<'a, QE>(
+        &'a self,
+        pool: &'a PgPool,
+        query: &'a StreamQuery<PgEventId, QE>,
+        metadata: Option<(&'a str, &'a str)>,
+    ) -> BoxStream<'a, Result<PersistedEvent<PgEventId, QE>, Error>>
+    where
+        QE: TryFrom<E> + Event + 'static + Clone + Send + Sync,
+        <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
+    {
+        stream! {
+            let mut last_event_id: PgEventId = 0;
+            let mut attempt = 1;
+            loop {
+                let missing_identifiers = self
+                    .retry_policy
+                    .retry(|| self.identifier_columns.missing(&self.pool, QE::SCHEMA.domain_identifiers))
+                    .await?;
+                let select = self.stream_select();
+                let init = if self.server_side_cursor {
+                    format!("DECLARE {STREAM_CURSOR} NO SCROLL CURSOR FOR {select}")
+                } else {
+                    select
+                };
+                let mut sql = QueryBuilder::new(query.clone(), &init).push_bind(last_event_id);
+                if let Some((key, value)) = metadata {
+                    sql = sql
+                        .push(&format!(" AND metadata->>'{key}' = "))
+                        .push_bind(value);
+                }
+                let mut sql = sql
+                .push(" AND (")
+                .with_missing_identifiers(missing_identifiers)
+                .end_with(") ORDER BY event_id ASC");
+
+                let statement = sql.build();
+                let rows = if self.server_side_cursor {
+                    fetch_with_cursor(pool, statement, self.stream_batch_size)
+                } else {
+                    statement.fetch(pool)
+                };
+                let mut rows = rows.chunks(self.stream_batch_size);
+                let mut backoff = None;
+                // the batch being decoded while the next one is fetched.
+                let mut decoding: Option<Decoding<QE>> = None;
+                loop {
+                    let batch = rows.next().await;
+                    let end = batch.is_none();
+                    let mut fetched = vec![];
+                    let mut error = None;
+                    for row in batch.into_iter().flatten() {
+                        match row {
+                            Ok(row) => fetched.push(row),
+                            Err(err) => {
+                                error = Some(Error::Database(err));
+                                break;
+                            }
+                        }
+                    }
+                    if !fetched.is_empty() {
+                        attempt = 1;
+                    }
+                    let next = (!fetched.is_empty()).then(|| Decoding::start(&self.serde, fetched));
+                    if let Some(decoded) = std::mem::replace(&mut decoding, next) {
+                        for event in decoded.events().await? {
+                            last_event_id = event.id();
+                            yield Ok(event);
+                        }
+                    }
+                    if let Some(err) = error {
+                        backoff = self.retry_policy.backoff(&err, attempt);
+                        if backoff.is_none() {
+                            Err(err)?;
+                        }
+                        break;
+                    }
+                    if end {
+                        break;
+                    }
+                }
+                drop(rows);
+                if let Some(decoded) = decoding {
+                    for event in decoded.events().await? {
+                        last_event_id = event.id();
+                        yield Ok(event);
+                    }
+                }
+                match backoff {
+                    Some(backoff) => {
+                        tokio::time::sleep(backoff).await;
+                        attempt += 1;
+                    }
+                    None => break,
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+impl<E, S> PgEventStore<E, S>
+where
+    E: Event + Clone + Send + Sync,
+    S: Serde<E> + Send + Sync,
+{
+    async fn append_events<QE>(
+        &self,
+        events: Vec<E>,
+        metadata: Metadata,
+        query: StreamQuery<PgEventId, QE>,
+        version: PgEventId,
+    ) -> Result<Vec<PersistedEvent<PgEventId, E>>, Error>
+    where
         QE: Event + Clone + Send + Sync,
     {
+        let event_types: Vec<&str> = events.iter().map(|event| event.name()).collect();
+        if let Some((event_type, reason)) = self
+            .retry_policy
+            .retry(|| self.deny_list.denied(&self.pool, &event_types))
+            .await?
+        {
+            return Err(Error::EventTypeDenied { event_type, reason });
+        }
+
+        let missing_identifiers = self
+            .retry_policy
+            .retry(|| {
+                self.identifier_columns
+                    .missing(&self.pool, E::SCHEMA.domain_identifiers)
+            })
+            .await?;
+        if let Some(identifier) = events.iter().find_map(|event| {
+            event
+                .domain_identifiers()
+                .keys()
+                .find(|ident| missing_identifiers.contains(&ident.to_lowercase()))
+                .copied()
+        }) {
+            return Err(Error::SchemaNotReady(identifier.to_string()));
+        }
+        let query_missing_identifiers = self
+            .retry_policy
+            .retry(|| {
+                self.identifier_columns
+                    .missing(&self.pool, QE::SCHEMA.domain_identifiers)
+            })
+            .await?;
+
         let mut persisted_events = Vec::with_capacity(events.len());
         let mut persisted_events_ids: Vec<PgEventId> = Vec::with_capacity(events.len());
         for event in events {
@@ -165,23 +815,60 @@ where
                 InsertBuilder::new(&event, "event_sequence").returning("event_id");
             let row = sequence_insert.build().fetch_one(&self.pool).await?;
             persisted_events_ids.push(row.get(0));
-            persisted_events.push(PersistedEvent::new(row.get(0), event));
+            persisted_events
+                .push(PersistedEvent::new(row.get(0), event).with_metadata(metadata.clone()));
         }
 
+        let result = self
+            .write_events(
+                persisted_events,
+                &persisted_events_ids,
+                &metadata,
+                query,
+                version,
+                query_missing_identifiers,
+            )
+            .await;
+        if result.is_err() {
+            self.release_event_ids(&persisted_events_ids).await;
+        }
+        result
+    }
+
+    /// Writes the events whose IDs were reserved in the `event_sequence` table, checking the concurrency
+    /// conflicts with the query.
+    async fn write_events<QE>(
+        &self,
+        persisted_events: Vec<PersistedEvent<PgEventId, E>>,
+        persisted_events_ids: &[PgEventId],
+        metadata: &Metadata,
+        query: StreamQuery<PgEventId, QE>,
+        version: PgEventId,
+        query_missing_identifiers: HashSet<String>,
+    ) -> Result<Vec<PersistedEvent<PgEventId, E>>, Error>
+    where
+        QE: Event + Clone + Send + Sync,
+    {
         let last_event_id = persisted_events_ids.last().copied().unwrap_or(version);
-        let persisted_event_ids = persisted_events_ids
-            .iter()
-            .map(|id| id.to_string())
-            .collect::<Vec<_>>()
-            .join(",");
         let mut tx = self.pool.begin().await?;
         let mut consume_sql = QueryBuilder::new(
             query.change_origin(version),
-            format!(r#"UPDATE event_sequence es SET consumed = consumed + 1, committed = (es.event_id = ANY('{{{persisted_event_ids}}}'))
-                       FROM (SELECT event_id FROM event_sequence WHERE event_id IN ({persisted_event_ids}) 
-                       OR ((consumed = 0 OR committed = true) 
-                       AND (event_id <= {last_event_id} AND ("#).as_str(),
+            "UPDATE event_sequence es SET consumed = consumed + 1, committed = (es.event_id = ANY(",
+        )
+        .push_bind(persisted_events_ids)
+        .push(
+            r#"))
+                       FROM (SELECT event_id FROM event_sequence WHERE event_id = ANY("#,
         )
+        .push_bind(persisted_events_ids)
+        .push(
+            r#")
+                       OR ((consumed = 0 OR committed = true)
+                       AND (event_id <= "#,
+        )
+        .push_bind(last_event_id)
+        .push(" AND (")
+        .with_missing_identifiers(query_missing_identifiers)
         .end_with("))) ORDER BY event_id FOR UPDATE) upd WHERE es.event_id = upd.event_id");
 
         consume_sql
@@ -190,50 +877,292 @@ where
             .await
             .map_err(map_update_event_id_err)?;
 
-        for event in &persisted_events {
-            let payload = self.serde.serialize((**event).clone());
-            let mut event_insert = InsertBuilder::new(&**event, "event")
-                .with_id(event.id())
-                .with_payload(&payload);
+        let rows: Vec<_> = persisted_events
+            .iter()
+            .map(|event| {
+                (
+                    event.id(),
+                    &**event,
+                    self.serde.serialize_with_info(
+                        (**event).clone(),
+                        PayloadInfo {
+                            event_type: event.name(),
+                            version: event.version(),
+                            event_id: Some(event.id()),
+                        },
+                    ),
+                )
+            })
+            .collect();
+        let metadata = if metadata.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(metadata)?)
+        };
+        let batch_size = self
+            .append_batch_size
+            .min(BatchInsertBuilder::<E>::max_rows());
+        for chunk in rows.chunks(batch_size) {
+            let mut event_insert = BatchInsertBuilder::new(chunk, "event");
+            if let Some(metadata) = &metadata {
+                event_insert = event_insert.with_metadata(metadata);
+            }
             event_insert.build().execute(&mut *tx).await?;
         }
+        let outbox_event_ids: Vec<PgEventId> = persisted_events
+            .iter()
+            .filter(|event| self.outbox_event_types.contains(event.name()))
+            .map(|event| event.id())
+            .collect();
+        if !outbox_event_ids.is_empty() {
+            sqlx::query("INSERT INTO event_outbox (event_id) SELECT * FROM UNNEST($1::bigint[])")
+                .bind(&outbox_event_ids)
+                .execute(&mut *tx)
+                .await?;
+        }
         tx.commit().await?;
+        self.observers.notify(&persisted_events);
 
         Ok(persisted_events)
     }
+
+    /// Releases the IDs reserved by a failed append, so the event listeners do not wait for them.
+    ///
+    /// The release is best effort: the IDs not released are awaited by the listeners until their
+    /// gap timeout passes.
+    async fn release_event_ids(&self, event_ids: &[PgEventId]) {
+        let _ =
+            sqlx::query("DELETE FROM event_sequence WHERE event_id = ANY($1) AND NOT committed")
+                .bind(event_ids)
+                .execute(&self.pool)
+                .await;
+    }
 }
 
-pub async fn setup<E: Event>(pool: &PgPool) -> Result<(), Error> {
-    const RESERVED_NAMES: &[&str] = &["event_id", "payload", "event_type", "inserted_at"];
+/// Resolves the commit timestamps of the events stored in PostgreSQL.
+///
+/// The timestamps are read from the `inserted_at` column of the `event` table,
+/// using its primary key index to find the closest event committed up to the given ID.
+#[async_trait]
+impl<E, S> EventTimestampResolver<PgEventId> for PgEventStore<E, S>
+where
+    E: Event + Send + Sync,
+    S: Serde<E> + Send + Sync,
+{
+    type Error = Error;
 
+    async fn timestamp(&self, event_id: PgEventId) -> Result<Option<SystemTime>, Self::Error> {
+        let epoch: Option<f64> = sqlx::query_scalar(
+            r#"SELECT EXTRACT(EPOCH FROM inserted_at AT TIME ZONE current_setting('TimeZone'))::float8
+               FROM event WHERE event_id <= $1 ORDER BY event_id DESC LIMIT 1"#,
+        )
+        .bind(event_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(epoch.map(|epoch| SystemTime::UNIX_EPOCH + Duration::from_secs_f64(epoch.max(0.0))))
+    }
+}
+
+/// Migrates the schema of the event store.
+///
+/// The plan lists the migrations not yet recorded in the `disintegrate_meta` table; applying it runs the setup
+/// of the event store and records the applied migrations.
+#[async_trait]
+impl<E, S> StoreMigrations for PgEventStore<E, S>
+where
+    E: Event + Send + Sync,
+    S: Serde<E> + Send + Sync,
+{
+    type Error = Error;
+
+    async fn plan(&self) -> Result<MigrationPlan, Self::Error> {
+        let plan = crate::metadata::plan(&self.pool).await?;
+        check_identifier_types::<E>(&self.pool).await?;
+        Ok(plan)
+    }
+
+    async fn apply(&self, plan: &MigrationPlan) -> Result<(), Self::Error> {
+        self.create_schema().await?;
+        setup::<E>(&self.pool, &self.identifier_indexes).await?;
+        self.create_metadata_indexes().await?;
+        crate::metadata::record_migrations(&self.pool, &plan.migrations).await
+    }
+}
+
+/// Appends a system event, with a reserved event type, to the event store.
+pub(crate) async fn append_system_event(
+    conn: &mut PgConnection,
+    event_type: &str,
+    payload: &[u8],
+) -> Result<PgEventId, sqlx::Error> {
+    let event_id: PgEventId = sqlx::query(
+        "INSERT INTO event_sequence (event_type, consumed, committed) VALUES ($1, 1, true) RETURNING event_id",
+    )
+    .bind(event_type)
+    .fetch_one(&mut *conn)
+    .await?
+    .get(0);
+    sqlx::query("INSERT INTO event (event_id, event_type, payload) VALUES ($1, $2, $3)")
+        .bind(event_id)
+        .bind(event_type)
+        .bind(payload)
+        .execute(&mut *conn)
+        .await?;
+    Ok(event_id)
+}
+
+pub async fn setup<E: Event>(
+    pool: &PgPool,
+    identifier_indexes: &HashMap<Identifier, PgIdentifierIndex>,
+) -> Result<(), Error> {
+    const RESERVED_NAMES: &[&str] = &[
+        "event_id",
+        "payload",
+        "event_type",
+        "inserted_at",
+        "metadata",
+        "event_version",
+    ];
+
+    let mut tx = crate::setup_lock::begin(pool).await?;
+    crate::metadata::setup(&mut tx).await?;
     sqlx::query(include_str!("event_store/sql/table_event.sql"))
-        .execute(pool)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(include_str!("event_store/sql/column_event_metadata.sql"))
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(include_str!("event_store/sql/column_event_version.sql"))
+        .execute(&mut *tx)
         .await?;
     sqlx::query(include_str!("event_store/sql/idx_event_type.sql"))
-        .execute(pool)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(include_str!(
+        "event_store/sql/table_event_type_deny_list.sql"
+    ))
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(include_str!("event_store/sql/table_event_outbox.sql"))
+        .execute(&mut *tx)
         .await?;
+    sqlx::query(include_str!("event_store/sql/table_event_retraction.sql"))
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(include_str!(
+        "event_store/sql/table_event_archive_watermark.sql"
+    ))
+    .execute(&mut *tx)
+    .await?;
     sqlx::query(include_str!("event_store/sql/table_event_sequence.sql"))
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
     sqlx::query(include_str!("event_store/sql/idx_event_sequence_type.sql"))
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
+    sqlx::query(include_str!(
+        "event_store/sql/idx_event_sequence_type_event_id.sql"
+    ))
+    .execute(&mut *tx)
+    .await?;
     sqlx::query(include_str!(
         "event_store/sql/idx_event_sequence_committed.sql"
     ))
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
 
     for domain_identifier in E::SCHEMA.domain_identifiers {
         if RESERVED_NAMES.contains(&domain_identifier.ident) {
             panic!("Domain identifier name {domain_identifier} is reserved. Please use a different name.", domain_identifier = domain_identifier.ident);
         }
-        add_domain_identifier_column(pool, "event", domain_identifier).await?;
-        add_domain_identifier_column(pool, "event_sequence", domain_identifier).await?;
+        let index = identifier_indexes
+            .get(&domain_identifier.ident)
+            .copied()
+            .unwrap_or_default();
+        add_domain_identifier_column(&mut tx, "event", domain_identifier, index).await?;
+        add_domain_identifier_column(&mut tx, "event_sequence", domain_identifier, index).await?;
+    }
+    crate::metadata::stamp(&mut tx).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Checks that the domain identifier columns already added to the `event` table have the types of the event schema.
+///
+/// The columns are never altered by the setup, so a domain identifier whose type changed, e.g. after splitting the
+/// events into bounded contexts, would otherwise be stored in a column of the former type.
+///
+/// # Errors
+///
+/// Returns `Error::IdentifierTypeMismatch` for the first domain identifier whose column has a different type.
+async fn check_identifier_types<E: Event>(pool: &PgPool) -> Result<(), Error> {
+    let columns: HashMap<String, String> = sqlx::query_as(
+        "SELECT column_name::TEXT, data_type::TEXT FROM information_schema.columns WHERE table_schema = current_schema() AND table_name = 'event'",
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+    for domain_identifier in E::SCHEMA.domain_identifiers {
+        let expected = identifier_sql_type(domain_identifier.type_info);
+        match columns.get(domain_identifier.ident.into_inner()) {
+            Some(found) if !found.eq_ignore_ascii_case(expected) => {
+                return Err(Error::IdentifierTypeMismatch {
+                    identifier: domain_identifier.ident.to_string(),
+                    expected,
+                    found: found.to_uppercase(),
+                });
+            }
+            _ => {}
+        }
     }
     Ok(())
 }
 
+/// Returns the SQL type of the columns of a domain identifier.
+fn identifier_sql_type(type_info: disintegrate::IdentifierType) -> &'static str {
+    match type_info {
+        disintegrate::IdentifierType::String => "TEXT",
+        disintegrate::IdentifierType::i64 => "BIGINT",
+        disintegrate::IdentifierType::Uuid => "UUID",
+    }
+}
+
+/// The name of the server-side cursor of a stream.
+const STREAM_CURSOR: &str = "disintegrate_stream";
+
+/// Fetches the rows of a query in batches, through a server-side cursor declared by the given statement.
+fn fetch_with_cursor<'a>(
+    pool: &'a PgPool,
+    declare: Query<'a, Postgres, PgArguments>,
+    batch_size: usize,
+) -> BoxStream<'a, Result<PgRow, sqlx::Error>> {
+    try_stream! {
+        let mut tx = pool.begin().await?;
+        declare.execute(&mut *tx).await?;
+        let fetch = format!("FETCH {batch_size} FROM {STREAM_CURSOR}");
+        loop {
+            let rows = sqlx::query(&fetch).fetch_all(&mut *tx).await?;
+            if rows.is_empty() {
+                break;
+            }
+            for row in rows {
+                yield row;
+            }
+        }
+        tx.commit().await?;
+    }
+    .boxed()
+}
+
+/// Decodes the metadata of an event, stored as JSON in the `metadata` column.
+pub(crate) fn decode_metadata(metadata: Option<String>) -> Result<Metadata, Error> {
+    match metadata {
+        Some(metadata) => Ok(serde_json::from_str(&metadata)?),
+        None => Ok(Metadata::default()),
+    }
+}
+
 /// Maps the `sqlx::Error` to `Error::UpdateEventIdError`.
 fn map_update_event_id_err(err: sqlx::Error) -> Error {
     if let sqlx::Error::Database(ref description) = err {
@@ -245,26 +1174,18 @@ fn map_update_event_id_err(err: sqlx::Error) -> Error {
 }
 
 async fn add_domain_identifier_column(
-    pool: &PgPool,
+    conn: &mut PgConnection,
     table: &str,
     domain_identifier: &DomainIdentifierInfo,
+    index: PgIdentifierIndex,
 ) -> Result<(), Error> {
     let column_name = domain_identifier.ident;
-    let sql_type = match domain_identifier.type_info {
-        disintegrate::IdentifierType::String => "TEXT",
-        disintegrate::IdentifierType::i64 => "BIGINT",
-        disintegrate::IdentifierType::Uuid => "UUID",
-    };
+    let sql_type = identifier_sql_type(domain_identifier.type_info);
     sqlx::query(&format!(
         "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS {column_name} {sql_type}"
     ))
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
-    sqlx::query(&format!(
-        "CREATE INDEX IF NOT EXISTS idx_{table}_{column_name} ON {table} USING HASH ({column_name}) WHERE {column_name} IS NOT NULL"
-    ))
-    .execute(pool)
-    .await?;
-    Ok(())
+    create_identifier_indexes(conn, table, &column_name, sql_type, index).await
 }
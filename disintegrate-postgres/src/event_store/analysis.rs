@@ -0,0 +1,146 @@
+//! Analysis of the stream queries
+//!
+//! A stream query is only as fast as the indexes of the columns of its domain identifiers. The columns created
+//! by the event store are indexed, but the custom ones, or the ones whose index was dropped, are not, and the
+//! loads of the states filtered by them scan the whole `event` table. Analyzing a query explains the statement
+//! streaming its events and reports the sequential scans of the events and the identifiers without an index.
+use std::collections::BTreeSet;
+use std::error::Error as StdError;
+use std::fmt;
+
+use disintegrate::{Event, StreamQuery};
+use disintegrate_serde::Serde;
+use serde_json::Value;
+use sqlx::Row;
+
+use super::QueryBuilder;
+use crate::{Error, PgEventId, PgEventStore};
+
+/// The analysis of the query plan of a stream query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryAnalysis {
+    /// The SQL of the statement streaming the events of the query.
+    pub sql: String,
+    /// The query plan of the statement, in the JSON format of `EXPLAIN`.
+    pub plan: String,
+    /// The estimated total cost of the statement.
+    pub total_cost: f64,
+    /// Whether the plan scans the whole `event` table.
+    pub sequential_scan: bool,
+    /// The columns of the domain identifiers filtered by the query that no index of the `event` table starts with.
+    pub unindexed_identifiers: Vec<String>,
+}
+
+impl QueryAnalysis {
+    /// Returns true if the plan scans the whole `event` table or an identifier of the query is not indexed.
+    pub fn has_issues(&self) -> bool {
+        self.sequential_scan || !self.unindexed_identifiers.is_empty()
+    }
+}
+
+impl fmt::Display for QueryAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cost: {:.2}", self.total_cost)?;
+        if self.sequential_scan {
+            write!(f, ", sequential scan of the events")?;
+        }
+        if !self.unindexed_identifiers.is_empty() {
+            write!(
+                f,
+                ", unindexed identifiers: {}",
+                self.unindexed_identifiers.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<E, S> PgEventStore<E, S>
+where
+    E: Event + Send + Sync + 'static,
+    S: Serde<E> + Send + Sync + 'static,
+{
+    /// Analyzes the query plan of the statement streaming the events of a query, e.g. to diagnose a slow load.
+    ///
+    /// The statement is explained, not executed, with the statistics of the tables: on a small table the
+    /// planner prefers a sequential scan even if the identifiers are indexed, so the plan is representative
+    /// only on a database with production-like data.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The stream query to analyze.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `QueryAnalysis` of the query, or an error if the statement cannot be explained.
+    pub async fn analyze_query<QE>(
+        &self,
+        query: &StreamQuery<PgEventId, QE>,
+    ) -> Result<QueryAnalysis, Error>
+    where
+        QE: TryFrom<E> + Event + 'static + Clone + Send + Sync,
+        <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
+    {
+        let missing_identifiers = self
+            .identifier_columns
+            .missing(&self.pool, QE::SCHEMA.domain_identifiers)
+            .await?;
+        let init = format!("EXPLAIN (FORMAT JSON) {}", self.stream_select());
+        let mut sql = QueryBuilder::new(query.clone(), &init)
+            .push_bind(0 as PgEventId)
+            .push(" AND (")
+            .with_missing_identifiers(missing_identifiers.clone())
+            .end_with(") ORDER BY event_id ASC");
+        let statement = sql.build();
+        let explained = sqlx::Execute::sql(&statement)
+            .trim_start_matches("EXPLAIN (FORMAT JSON) ")
+            .to_string();
+        // the plan is a JSON column, whose binary encoding is its text.
+        let plan: String = statement
+            .fetch_one(&self.pool)
+            .await?
+            .try_get_unchecked(0)?;
+        let explain: Value = serde_json::from_str(&plan)?;
+        let root = &explain[0]["Plan"];
+
+        let identifiers: BTreeSet<String> = query
+            .filters()
+            .iter()
+            .flat_map(|filter| {
+                filter
+                    .identifiers()
+                    .keys()
+                    .copied()
+                    .chain(filter.conditions().iter().map(|c| *c.identifier()))
+                    .collect::<Vec<_>>()
+            })
+            .map(|ident| ident.to_lowercase())
+            .filter(|column| !missing_identifiers.contains(column))
+            .collect();
+        let indexed: BTreeSet<String> = sqlx::query_scalar(
+            r#"SELECT a.attname::text FROM pg_index i
+               JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = i.indkey[0]
+               WHERE i.indrelid = 'event'::regclass"#,
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .collect();
+
+        Ok(QueryAnalysis {
+            sql: explained,
+            plan,
+            total_cost: root["Total Cost"].as_f64().unwrap_or_default(),
+            sequential_scan: scans_events(root),
+            unindexed_identifiers: identifiers.difference(&indexed).cloned().collect(),
+        })
+    }
+}
+
+/// Returns true if a node of the plan is a sequential scan of the `event` table.
+fn scans_events(node: &Value) -> bool {
+    let scan = node["Node Type"] == "Seq Scan" && node["Relation Name"] == "event";
+    scan || node["Plans"]
+        .as_array()
+        .is_some_and(|plans| plans.iter().any(scans_events))
+}
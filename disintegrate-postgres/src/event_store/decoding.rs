@@ -0,0 +1,95 @@
+//! Batched decoding of the streamed events
+//!
+//! The rows of a stream are fetched in batches, and the payloads of a batch are deserialized on the blocking
+//! thread pool of Tokio while the next batch is fetched, so the serde does not hold the async runtime when a
+//! long stream of events is loaded. The small batches, e.g. the few events of an aggregate, are decoded in
+//! place, since handing them to another thread costs more than their deserialization.
+use std::error::Error as StdError;
+use std::sync::Arc;
+
+use disintegrate::{Event, PersistedEvent};
+use disintegrate_serde::{PayloadInfo, Serde};
+use sqlx::postgres::PgRow;
+use sqlx::Row;
+use tokio::task::JoinHandle;
+
+use super::decode_metadata;
+use crate::{Error, PgEventId};
+
+/// The number of rows below which a batch is decoded in place.
+const INLINE_DECODING_LIMIT: usize = 64;
+
+/// The decoding of a batch of rows, the columns of which are the ID, the payload, the metadata, the
+/// version and the type of the events.
+pub(crate) enum Decoding<QE: Event> {
+    Decoded(Result<Vec<PersistedEvent<PgEventId, QE>>, Error>),
+    Blocking(JoinHandle<Result<Vec<PersistedEvent<PgEventId, QE>>, Error>>),
+}
+
+impl<QE> Decoding<QE>
+where
+    QE: Event + Send + 'static,
+{
+    /// Starts decoding a batch of rows, on the blocking thread pool unless the batch is small.
+    pub fn start<E, S>(serde: &Arc<S>, rows: Vec<PgRow>) -> Self
+    where
+        E: Event + Send + 'static,
+        S: Serde<E> + Send + Sync + 'static,
+        QE: TryFrom<E>,
+        <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
+    {
+        if rows.len() < INLINE_DECODING_LIMIT {
+            return Decoding::Decoded(decode_rows(serde.as_ref(), rows));
+        }
+        let serde = serde.clone();
+        Decoding::Blocking(tokio::task::spawn_blocking(move || {
+            decode_rows(serde.as_ref(), rows)
+        }))
+    }
+
+    /// Waits for the decoded events of the batch.
+    ///
+    /// # Panics
+    ///
+    /// Resumes the panic of the serde, if it panicked on the blocking thread pool.
+    pub async fn events(self) -> Result<Vec<PersistedEvent<PgEventId, QE>>, Error> {
+        match self {
+            Decoding::Decoded(events) => events,
+            Decoding::Blocking(handle) => match handle.await {
+                Ok(events) => events,
+                Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+                Err(err) => panic!("the decoding of the events has been cancelled: {err}"),
+            },
+        }
+    }
+}
+
+fn decode_rows<E, QE, S>(
+    serde: &S,
+    rows: Vec<PgRow>,
+) -> Result<Vec<PersistedEvent<PgEventId, QE>>, Error>
+where
+    S: Serde<E>,
+    QE: TryFrom<E> + Event,
+    <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
+{
+    rows.into_iter()
+        .map(|row| {
+            let version = row.get::<i32, _>(3) as u32;
+            let info = PayloadInfo {
+                event_type: row.get(4),
+                version,
+                event_id: Some(row.get(0)),
+            };
+            let payload: E = serde.deserialize_with_info(row.get(1), info)?;
+            Ok(PersistedEvent::<PgEventId, QE>::new(
+                row.get(0),
+                payload
+                    .try_into()
+                    .map_err(|e| Error::QueryEventMapping(Box::new(e)))?,
+            )
+            .with_metadata(decode_metadata(row.get(2))?)
+            .with_version(version))
+        })
+        .collect()
+}
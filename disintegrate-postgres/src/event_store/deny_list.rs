@@ -0,0 +1,67 @@
+//! Event type deny list
+//!
+//! The event types in the `event_type_deny_list` table are rejected by `append`. This module caches the
+//! deny list, so that the appends do not look up the table on every decision.
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Instant;
+
+use sqlx::PgPool;
+
+use super::DENY_LIST_REFRESH_INTERVAL;
+use crate::Error;
+
+/// The denied event types, mapped to the reason why they are denied.
+type Denied = HashMap<String, Option<String>>;
+
+/// The cached deny list, together with the time it was looked up.
+#[derive(Debug, Default)]
+pub(crate) struct DenyList {
+    denied: RwLock<(Denied, Option<Instant>)>,
+}
+
+impl DenyList {
+    /// Returns the first denied event type among the given ones, together with the reason why it is denied.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The PostgreSQL connection pool.
+    /// * `event_types` - The event types of an append.
+    pub async fn denied(
+        &self,
+        pool: &PgPool,
+        event_types: &[&str],
+    ) -> Result<Option<(String, Option<String>)>, Error> {
+        {
+            let denied = self.denied.read().unwrap();
+            let fresh = denied
+                .1
+                .is_some_and(|checked_at| checked_at.elapsed() < DENY_LIST_REFRESH_INTERVAL);
+            if fresh {
+                return Ok(denied_from(&denied.0, event_types));
+            }
+        }
+
+        let rows: Denied = sqlx::query_as("SELECT event_type, reason FROM event_type_deny_list")
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .collect();
+        let denied = denied_from(&rows, event_types);
+        *self.denied.write().unwrap() = (rows, Some(Instant::now()));
+        Ok(denied)
+    }
+
+    /// Forces the next check to look up the deny list again, e.g. after it has been changed.
+    pub fn invalidate(&self) {
+        self.denied.write().unwrap().1 = None;
+    }
+}
+
+fn denied_from(denied: &Denied, event_types: &[&str]) -> Option<(String, Option<String>)> {
+    event_types.iter().find_map(|event_type| {
+        denied
+            .get_key_value(*event_type)
+            .map(|(event_type, reason)| (event_type.clone(), reason.clone()))
+    })
+}
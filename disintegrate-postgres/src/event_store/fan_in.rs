@@ -0,0 +1,162 @@
+//! Fan-in event store
+//!
+//! Old events can be archived to other databases to keep the event store small. This module provides a read-only
+//! event store that streams a query across the event store and its archives, merging the events in event ID order,
+//! so that the reporting components can still read the full history.
+use std::error::Error as StdError;
+
+use async_stream::stream;
+use async_trait::async_trait;
+use disintegrate::{Event, EventStore, PersistedEvent, StreamQuery};
+use disintegrate_serde::Serde;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use sqlx::PgPool;
+
+use super::PgEventStore;
+use crate::{Error, PgEventId};
+
+/// Read-only event store merging the events of a `PgEventStore` and of its archives.
+///
+/// The archives are expected to have the schema of the event store, and are never written to.
+/// An event found in several sources, e.g. while it is being moved to an archive, is streamed once.
+#[derive(Clone)]
+pub struct PgFanInEventStore<E, S>
+where
+    E: Event,
+    S: Serde<E> + Send + Sync,
+{
+    sources: Vec<PgEventStore<E, S>>,
+}
+
+impl<E, S> std::fmt::Debug for PgFanInEventStore<E, S>
+where
+    E: Event,
+    S: Serde<E> + Send + Sync,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PgFanInEventStore")
+            .field("sources", &self.sources)
+            .finish()
+    }
+}
+
+impl<E, S> PgFanInEventStore<E, S>
+where
+    E: Event,
+    S: Serde<E> + Clone + Send + Sync,
+{
+    /// Creates a new `PgFanInEventStore` reading from the given event store.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_store` - The event store holding the recent events.
+    pub fn new(event_store: PgEventStore<E, S>) -> Self {
+        Self {
+            sources: vec![event_store],
+        }
+    }
+
+    /// Adds an archive database to the sources of the events.
+    ///
+    /// The archive is read with the serialization of the event store, and it is not initialized.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The PostgreSQL connection pool of the archive database.
+    pub fn with_archive(mut self, pool: PgPool) -> Self {
+        let serde = S::clone(&self.sources[0].serde);
+        let retry_policy = self.sources[0].retry_policy;
+        self.sources
+            .push(PgEventStore::new_uninitialized(pool, serde).with_retry_policy(retry_policy));
+        self
+    }
+}
+
+/// Implementation of the event store merging several PostgreSQL databases.
+///
+/// Only the streaming is supported: the appends must go through the `PgEventStore`.
+#[async_trait]
+impl<E, S> EventStore<PgEventId, E> for PgFanInEventStore<E, S>
+where
+    E: Event + Send + Sync + 'static,
+    S: Serde<E> + Send + Sync + 'static,
+{
+    type Error = Error;
+
+    /// Streams the events matching the query from all the sources, in event ID order.
+    ///
+    /// The query is executed on every source, and the resulting streams are merged by event ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The stream query specifying the criteria for filtering events.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a boxed stream of `PersistedEvent` that matches the query criteria,
+    /// or an error of type `Self::Error`.
+    fn stream<'a, QE>(
+        &'a self,
+        query: &'a StreamQuery<PgEventId, QE>,
+    ) -> BoxStream<'a, Result<PersistedEvent<PgEventId, QE>, Self::Error>>
+    where
+        QE: TryFrom<E> + Event + 'static + Clone + Send + Sync,
+        <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
+    {
+        merge(
+            self.sources
+                .iter()
+                .map(|source| source.stream(query))
+                .collect(),
+        )
+    }
+
+    /// Always fails with `Error::ReadOnly`, as the archives must not receive new events.
+    async fn append<QE>(
+        &self,
+        _events: Vec<E>,
+        _query: StreamQuery<PgEventId, QE>,
+        _version: PgEventId,
+    ) -> Result<Vec<PersistedEvent<PgEventId, E>>, Self::Error>
+    where
+        E: Clone + 'async_trait,
+        QE: Event + Clone + Send + Sync,
+    {
+        Err(Error::ReadOnly)
+    }
+}
+
+/// Merges streams of events ordered by event ID into a single stream ordered by event ID.
+///
+/// An event found in several streams, e.g. while it is being moved to an archive, is streamed once.
+pub(crate) fn merge<'a, QE: Event + Send + 'a>(
+    mut streams: Vec<BoxStream<'a, Result<PersistedEvent<PgEventId, QE>, Error>>>,
+) -> BoxStream<'a, Result<PersistedEvent<PgEventId, QE>, Error>> {
+    if streams.len() == 1 {
+        return streams.remove(0);
+    }
+    stream! {
+        let mut heads = Vec::with_capacity(streams.len());
+        for events in &mut streams {
+            heads.push(events.next().await.transpose()?);
+        }
+        while let Some(next) = heads
+            .iter()
+            .enumerate()
+            .filter_map(|(i, head)| head.as_ref().map(|event| (i, event.id())))
+            .min_by_key(|(_, event_id)| *event_id)
+            .map(|(i, _)| i)
+        {
+            let event = heads[next].take().expect("the head of the next source is set");
+            for (i, (head, events)) in heads.iter_mut().zip(&mut streams).enumerate() {
+                let duplicate = head.as_ref().is_some_and(|other| other.id() == event.id());
+                if i == next || duplicate {
+                    *head = events.next().await.transpose()?;
+                }
+            }
+            yield Ok(event);
+        }
+    }
+    .boxed()
+}
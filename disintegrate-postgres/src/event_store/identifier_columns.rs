@@ -0,0 +1,89 @@
+//! Domain identifier columns
+//!
+//! During a rolling deploy, the instances running a new version of the events can reference a new `#[id]`
+//! before its columns have been added to the `event` and `event_sequence` tables, e.g. when the schema is
+//! managed outside of the application. This module tracks the identifier columns available in the database,
+//! so that the event store can avoid referencing a column that does not exist yet.
+use std::collections::HashSet;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use disintegrate::DomainIdentifierInfo;
+use sqlx::PgPool;
+
+use crate::Error;
+
+/// The minimum time between two lookups of the database catalog while some identifier columns are missing.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The domain identifier columns available in both the `event` and `event_sequence` tables.
+///
+/// The columns are never dropped by the event store, so the catalog is looked up again only while some
+/// of the requested identifiers are missing.
+#[derive(Debug, Default)]
+pub(crate) struct IdentifierColumns {
+    available: RwLock<(HashSet<String>, Option<Instant>)>,
+}
+
+impl IdentifierColumns {
+    /// Creates a new `IdentifierColumns` knowing that the columns of the given identifiers are available.
+    pub fn with_available(domain_identifiers: &[&DomainIdentifierInfo]) -> Self {
+        let available = domain_identifiers
+            .iter()
+            .map(|domain_identifier| column_name(domain_identifier))
+            .collect();
+        Self {
+            available: RwLock::new((available, Some(Instant::now()))),
+        }
+    }
+
+    /// Returns the column names of the domain identifiers that are not available yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The PostgreSQL connection pool.
+    /// * `domain_identifiers` - The domain identifiers referenced by a query or an append.
+    pub async fn missing(
+        &self,
+        pool: &PgPool,
+        domain_identifiers: &[&DomainIdentifierInfo],
+    ) -> Result<HashSet<String>, Error> {
+        let (missing, checked_at) = {
+            let available = self.available.read().unwrap();
+            (missing_from(&available.0, domain_identifiers), available.1)
+        };
+        let fresh = checked_at.is_some_and(|checked_at| checked_at.elapsed() < REFRESH_INTERVAL);
+        if missing.is_empty() || fresh {
+            return Ok(missing);
+        }
+
+        let columns: HashSet<String> = sqlx::query_scalar(
+            r#"SELECT attname::text FROM pg_attribute WHERE attrelid = 'event'::regclass AND attnum > 0 AND NOT attisdropped
+               INTERSECT
+               SELECT attname::text FROM pg_attribute WHERE attrelid = 'event_sequence'::regclass AND attnum > 0 AND NOT attisdropped"#,
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .collect();
+        let missing = missing_from(&columns, domain_identifiers);
+        *self.available.write().unwrap() = (columns, Some(Instant::now()));
+        Ok(missing)
+    }
+}
+
+/// Returns the name of the column of a domain identifier, as folded by PostgreSQL.
+fn column_name(domain_identifier: &DomainIdentifierInfo) -> String {
+    domain_identifier.ident.to_lowercase()
+}
+
+fn missing_from(
+    available: &HashSet<String>,
+    domain_identifiers: &[&DomainIdentifierInfo],
+) -> HashSet<String> {
+    domain_identifiers
+        .iter()
+        .map(|domain_identifier| column_name(domain_identifier))
+        .filter(|column| !available.contains(column))
+        .collect()
+}
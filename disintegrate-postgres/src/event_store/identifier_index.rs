@@ -0,0 +1,128 @@
+//! Domain identifier indexes
+//!
+//! By default a domain identifier is indexed with a hash index in the `event` and `event_sequence` tables, plus a
+//! B-tree index on the identifier and the event ID in the sequence, used by the conflict check of the appends.
+//! For identifiers with billions of distinct values, e.g. a `session_id`, these indexes can be larger than the data:
+//! this module lets the identifier be declared as high-cardinality and indexed with a more compact index.
+use sqlx::{PgConnection, Row};
+
+use crate::Error;
+
+/// The index of the columns of a domain identifier.
+///
+/// The index only changes how the events are looked up: the stream queries and the conflict check are unchanged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PgIdentifierIndex {
+    /// Hash indexes on the identifier, and a B-tree index on the identifier and the event ID in the event sequence.
+    #[default]
+    Standard,
+    /// Hash indexes on the identifier only. The conflict check of the appends can no longer seek the range of
+    /// event IDs of an identifier, so concurrent appends on the identifier may contend more.
+    Hash,
+    /// BRIN indexes storing the range of the identifier values of each block of the tables. They are tiny, but
+    /// only effective if the values correlate with the insertion order, e.g. time-ordered identifiers.
+    Brin,
+    /// BRIN indexes storing a bloom filter of the identifier values of each block of the tables. They are
+    /// compact and effective on random values, at the cost of scanning the blocks with a false positive.
+    BrinBloom,
+}
+
+impl PgIdentifierIndex {
+    /// Returns the access method and the operator class suffix of the index.
+    fn method(&self) -> (&'static str, bool) {
+        match self {
+            PgIdentifierIndex::Standard | PgIdentifierIndex::Hash => ("hash", false),
+            PgIdentifierIndex::Brin => ("brin", false),
+            PgIdentifierIndex::BrinBloom => ("brin", true),
+        }
+    }
+
+    /// Returns the `USING` clause of the index on a column of the given SQL type.
+    fn using(&self, column_name: &str, sql_type: &str) -> String {
+        match self {
+            PgIdentifierIndex::Standard | PgIdentifierIndex::Hash => {
+                format!("USING HASH ({column_name})")
+            }
+            PgIdentifierIndex::Brin => format!("USING BRIN ({column_name})"),
+            PgIdentifierIndex::BrinBloom => {
+                let opclass = match sql_type {
+                    "BIGINT" => "int8_bloom_ops",
+                    "UUID" => "uuid_bloom_ops",
+                    _ => "text_bloom_ops",
+                };
+                format!("USING BRIN ({column_name} {opclass})")
+            }
+        }
+    }
+}
+
+/// Creates the indexes of a domain identifier column, replacing the ones created with a different index kind.
+///
+/// # Arguments
+///
+/// * `conn` - The connection holding the setup lock.
+/// * `table` - The table of the column, either `event` or `event_sequence`.
+/// * `column_name` - The name of the domain identifier column.
+/// * `sql_type` - The SQL type of the column.
+/// * `index` - The index of the domain identifier.
+pub(crate) async fn create_identifier_indexes(
+    conn: &mut PgConnection,
+    table: &str,
+    column_name: &str,
+    sql_type: &str,
+    index: PgIdentifierIndex,
+) -> Result<(), Error> {
+    let index_name = format!("idx_{table}_{column_name}");
+    let replaced = existing_method(conn, &index_name)
+        .await?
+        .is_some_and(|(method, bloom)| (method.as_str(), bloom) != index.method());
+    if replaced {
+        sqlx::query(&format!("DROP INDEX {index_name}"))
+            .execute(&mut *conn)
+            .await?;
+    }
+    sqlx::query(&format!(
+        "CREATE INDEX IF NOT EXISTS {index_name} ON {table} {} WHERE {column_name} IS NOT NULL",
+        index.using(column_name, sql_type)
+    ))
+    .execute(&mut *conn)
+    .await?;
+
+    // The conflict check of an append locks the rows of a domain identifier within an event ID range:
+    // a composite index buckets the sequence by identifier, so concurrent appends of unrelated
+    // streams scan and lock disjoint index ranges.
+    if table == "event_sequence" {
+        let range_index_name = format!("idx_{table}_{column_name}_event_id");
+        if index == PgIdentifierIndex::Standard {
+            sqlx::query(&format!(
+                "CREATE INDEX IF NOT EXISTS {range_index_name} ON {table} ({column_name}, event_id) WHERE {column_name} IS NOT NULL"
+            ))
+            .execute(&mut *conn)
+            .await?;
+        } else if existing_method(conn, &range_index_name).await?.is_some() {
+            sqlx::query(&format!("DROP INDEX {range_index_name}"))
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the access method of an index of the current schema, and whether it uses a bloom operator class.
+///
+/// The index is looked up in the current schema only, so that the index of the same name of another schema on
+/// the `search_path`, e.g. `public`, is never dropped.
+async fn existing_method(
+    conn: &mut PgConnection,
+    index_name: &str,
+) -> Result<Option<(String, bool)>, Error> {
+    Ok(sqlx::query(
+        r#"SELECT am.amname::text, pg_get_indexdef(c.oid) LIKE '%_bloom_ops%'
+           FROM pg_class c JOIN pg_am am ON am.oid = c.relam
+           WHERE c.oid = to_regclass(format('%I.%I', current_schema(), $1))"#,
+    )
+    .bind(index_name)
+    .fetch_optional(&mut *conn)
+    .await?
+    .map(|row| (row.get(0), row.get(1))))
+}
@@ -1,7 +1,8 @@
-use disintegrate::Event;
+use disintegrate::{DomainIdentifierInfo, Event, IdentifierType, IdentifierValue};
 use sqlx::postgres::PgArguments;
 use sqlx::query::Query;
 use sqlx::Postgres;
+use uuid::Uuid;
 
 use crate::PgEventId;
 
@@ -44,6 +45,7 @@ where
     /// # Arguments
     ///
     /// * `id` - The ID of the event.
+    #[cfg(test)]
     pub fn with_id(mut self, id: PgEventId) -> Self {
         self.id = Some(id);
         self
@@ -54,6 +56,7 @@ where
     /// # Arguments
     ///
     /// * `payload` - The payload of the event.
+    #[cfg(test)]
     pub fn with_payload(mut self, payload: &'a [u8]) -> Self {
         self.payload = Some(payload);
         self
@@ -120,6 +123,121 @@ where
     }
 }
 
+/// The maximum number of bind parameters of a PostgreSQL statement.
+pub const MAX_BIND_PARAMETERS: usize = u16::MAX as usize;
+
+/// SQL Batch Insert Builder
+///
+/// A builder for constructing a multi-row insert SQL query of events with their IDs, payloads, versions and,
+/// optionally, their metadata. The domain identifiers columns not set by an event are inserted as `NULL`.
+pub struct BatchInsertBuilder<'a, E>
+where
+    E: Event + Clone,
+{
+    builder: sqlx::QueryBuilder<'a, Postgres>,
+    rows: &'a [(PgEventId, &'a E, Vec<u8>)],
+    metadata: Option<&'a str>,
+}
+
+impl<'a, E> BatchInsertBuilder<'a, E>
+where
+    E: Event + Clone,
+{
+    /// Creates a new instance of `BatchInsertBuilder`.
+    ///
+    /// # Arguments
+    ///
+    /// * `rows` - The IDs, the events and the payloads to be inserted.
+    /// * `table` - The table name.
+    pub fn new(rows: &'a [(PgEventId, &'a E, Vec<u8>)], table: &str) -> Self {
+        Self {
+            builder: sqlx::QueryBuilder::new(format!("INSERT INTO {table} (")),
+            rows,
+            metadata: None,
+        }
+    }
+
+    /// Sets the metadata shared by the events to be inserted.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - The metadata of the events, encoded as JSON.
+    pub fn with_metadata(mut self, metadata: &'a str) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Returns the maximum number of rows that can be inserted by a single statement.
+    pub fn max_rows() -> usize {
+        MAX_BIND_PARAMETERS / (E::SCHEMA.domain_identifiers.len() + 5)
+    }
+
+    /// Builds the SQL insert query.
+    pub fn build(&'a mut self) -> Query<'a, Postgres, PgArguments> {
+        let domain_identifiers: Vec<_> = self
+            .rows
+            .iter()
+            .map(|(_, event, _)| event.domain_identifiers())
+            .collect();
+        let columns: Vec<&DomainIdentifierInfo> = E::SCHEMA
+            .domain_identifiers
+            .iter()
+            .copied()
+            .filter(|info| {
+                domain_identifiers
+                    .iter()
+                    .any(|identifiers| identifiers.contains_key(&info.ident))
+            })
+            .collect();
+
+        let mut separated_builder = self.builder.separated(",");
+        separated_builder.push("event_type");
+        for info in &columns {
+            separated_builder.push(info.ident);
+        }
+        separated_builder.push("event_id");
+        separated_builder.push("payload");
+        separated_builder.push("event_version");
+        if self.metadata.is_some() {
+            separated_builder.push("metadata");
+        }
+        separated_builder.push_unseparated(") VALUES ");
+
+        for (index, ((id, event, payload), domain_identifiers)) in
+            self.rows.iter().zip(&domain_identifiers).enumerate()
+        {
+            if index > 0 {
+                self.builder.push(",");
+            }
+            let mut separated_builder = self.builder.separated(",");
+            separated_builder.push_unseparated("(");
+            separated_builder.push_bind(event.name());
+            for info in &columns {
+                match (domain_identifiers.get(&info.ident), info.type_info) {
+                    (Some(IdentifierValue::String(value)), _) => {
+                        separated_builder.push_bind(value.clone())
+                    }
+                    (Some(IdentifierValue::i64(value)), _) => separated_builder.push_bind(*value),
+                    (Some(IdentifierValue::Uuid(value)), _) => separated_builder.push_bind(*value),
+                    (None, IdentifierType::String) => separated_builder.push_bind(None::<String>),
+                    (None, IdentifierType::i64) => separated_builder.push_bind(None::<i64>),
+                    (None, IdentifierType::Uuid) => separated_builder.push_bind(None::<Uuid>),
+                };
+            }
+            separated_builder.push_bind(*id);
+            separated_builder.push_bind(payload.as_slice());
+            separated_builder.push_bind(event.version() as i32);
+            if let Some(metadata) = self.metadata {
+                separated_builder.push_bind(metadata);
+                separated_builder.push_unseparated("::JSONB");
+            }
+            separated_builder.push_unseparated(")");
+        }
+
+        self.builder.build()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use disintegrate::{
@@ -153,10 +271,18 @@ mod tests {
                 &EventInfo {
                     name: "ShoppingCartAdded",
                     domain_identifiers: &[&ident!(#product_id), &ident!(#cart_id)],
+                    description: None,
+                    owner: None,
+                    aliases: &[],
+                    version: 1,
                 },
                 &EventInfo {
                     name: "ShoppingCartRemoved",
                     domain_identifiers: &[&ident!(#product_id), &ident!(#cart_id)],
+                    description: None,
+                    owner: None,
+                    aliases: &[],
+                    version: 1,
                 },
             ],
             domain_identifiers: &[
@@ -224,4 +350,42 @@ mod tests {
             "INSERT INTO event (event_type,cart_id,product_id,event_id,payload) VALUES ($1,$2,$3,$4,$5)"
         );
     }
+
+    #[test]
+    fn it_builds_batch_insert() {
+        let added = ShoppingCartEvent::Added {
+            product_id: "product_1".into(),
+            cart_id: "cart_1".into(),
+            quantity: 10,
+        };
+        let removed = ShoppingCartEvent::Removed {
+            product_id: "product_1".into(),
+            cart_id: "cart_1".into(),
+            quantity: 10,
+        };
+        let rows = vec![(1, &added, vec![]), (2, &removed, vec![])];
+        let mut insert_query = BatchInsertBuilder::new(&rows, "event");
+
+        assert_eq!(
+            insert_query.build().sql(),
+            "INSERT INTO event (event_type,cart_id,product_id,event_id,payload,event_version) VALUES ($1,$2,$3,$4,$5,$6),($7,$8,$9,$10,$11,$12)"
+        );
+    }
+
+    #[test]
+    fn it_builds_batch_insert_with_metadata() {
+        let added = ShoppingCartEvent::Added {
+            product_id: "product_1".into(),
+            cart_id: "cart_1".into(),
+            quantity: 10,
+        };
+        let rows = vec![(1, &added, vec![])];
+        let mut insert_query =
+            BatchInsertBuilder::new(&rows, "event").with_metadata(r#"{"request_id":"r1"}"#);
+
+        assert_eq!(
+            insert_query.build().sql(),
+            "INSERT INTO event (event_type,cart_id,product_id,event_id,payload,event_version,metadata) VALUES ($1,$2,$3,$4,$5,$6,$7::JSONB)"
+        );
+    }
 }
@@ -0,0 +1,99 @@
+//! Metadata filters
+//!
+//! Support tooling often needs the events appended in a given context, e.g. all the events produced by a
+//! request, which the domain identifiers do not capture. The keys of the metadata declared with
+//! `with_metadata_index` are indexed in the `event` table, so the events can be looked up by their value.
+use std::error::Error as StdError;
+
+use async_stream::try_stream;
+use disintegrate::{Event, PersistedEvent, StreamQuery};
+use disintegrate_serde::Serde;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+
+use crate::{Error, PgEventId, PgEventStore};
+
+/// Checks that a key of the metadata can be used in the name and the expression of an index.
+///
+/// # Panics
+///
+/// Panics if the key is not made of lowercase ASCII letters, digits and underscores.
+pub(crate) fn validate_key(key: &str) {
+    assert!(
+        !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+        "invalid metadata key `{key}`: expected lowercase ASCII letters, digits and underscores"
+    );
+}
+
+impl<E, S> PgEventStore<E, S>
+where
+    E: Event,
+    S: Serde<E> + Send + Sync,
+{
+    /// Creates the indexes of the keys of the metadata declared with `with_metadata_index`.
+    pub(super) async fn create_metadata_indexes(&self) -> Result<(), Error> {
+        for key in &self.metadata_indexes {
+            sqlx::query(&format!(
+                "CREATE INDEX IF NOT EXISTS idx_event_metadata_{key} ON event ((metadata->>'{key}')) WHERE metadata->>'{key}' IS NOT NULL"
+            ))
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+impl<E, S> PgEventStore<E, S>
+where
+    E: Event + Send + Sync + 'static,
+    S: Serde<E> + Send + Sync + 'static,
+{
+    /// Streams the events matching a query that were appended with the given value of a key of the metadata.
+    ///
+    /// As `stream`, the events are also read from the archive set with `with_archive`, and the transient
+    /// database errors are retried with the retry policy of the event store.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The stream query specifying the criteria for filtering events.
+    /// * `key` - The key of the metadata, declared with `with_metadata_index`.
+    /// * `value` - The value of the key.
+    ///
+    /// # Returns
+    ///
+    /// A stream of the matching events, ordered by ID, or `Error::UnindexedMetadataKey` if the key has not been
+    /// declared with `with_metadata_index`.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let events: Vec<_> = event_store
+    ///     .stream_by_metadata(&query!(DomainEvent), CORRELATION_ID, "request-1")
+    ///     .try_collect()
+    ///     .await?;
+    /// ```
+    pub fn stream_by_metadata<'a, QE>(
+        &'a self,
+        query: &'a StreamQuery<PgEventId, QE>,
+        key: &'a str,
+        value: &'a str,
+    ) -> BoxStream<'a, Result<PersistedEvent<PgEventId, QE>, Error>>
+    where
+        QE: TryFrom<E> + Event + 'static + Clone + Send + Sync,
+        <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
+    {
+        try_stream! {
+            if !self.metadata_indexes.contains(key) {
+                Err(Error::UnindexedMetadataKey(key.to_string()))?;
+            }
+            let mut events = self.stream_with_archive(query, Some((key, value)));
+            while let Some(event) = events.next().await {
+                yield event?;
+            }
+        }
+        .boxed()
+    }
+}
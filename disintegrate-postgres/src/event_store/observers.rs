@@ -0,0 +1,58 @@
+//! In-process append observers
+//!
+//! The observers are notified by the `PgEventStore` after a successful append, so that the event listeners
+//! running in the same process can be woken up without waiting for the PostgreSQL notification round-trip.
+use std::sync::{RwLock, Weak};
+
+use disintegrate::{Event, PersistedEvent};
+
+use crate::PgEventId;
+
+/// A function called with the events appended by a `PgEventStore`.
+pub(crate) type AppendObserver<E> = dyn Fn(&[PersistedEvent<PgEventId, E>]) + Send + Sync;
+
+/// The observers of the appends of a `PgEventStore`, shared by all its clones.
+///
+/// The observers are held weakly: an observer stops being notified as soon as it is dropped.
+pub(crate) struct AppendObservers<E: Event> {
+    observers: RwLock<Vec<Weak<AppendObserver<E>>>>,
+}
+
+impl<E: Event> Default for AppendObservers<E> {
+    fn default() -> Self {
+        Self {
+            observers: RwLock::new(vec![]),
+        }
+    }
+}
+
+impl<E: Event> AppendObservers<E> {
+    /// Registers an observer, notified until it is dropped.
+    #[cfg(feature = "listener")]
+    pub fn observe(&self, observer: &std::sync::Arc<AppendObserver<E>>) {
+        self.observers
+            .write()
+            .unwrap()
+            .push(std::sync::Arc::downgrade(observer));
+    }
+
+    /// Notifies the appended events to the live observers, removing the dropped ones.
+    pub fn notify(&self, events: &[PersistedEvent<PgEventId, E>]) {
+        if events.is_empty() {
+            return;
+        }
+        let mut dropped = false;
+        for observer in self.observers.read().unwrap().iter() {
+            match observer.upgrade() {
+                Some(observer) => observer(events),
+                None => dropped = true,
+            }
+        }
+        if dropped {
+            self.observers
+                .write()
+                .unwrap()
+                .retain(|observer| observer.strong_count() > 0);
+        }
+    }
+}
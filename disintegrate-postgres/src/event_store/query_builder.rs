@@ -1,14 +1,19 @@
-use disintegrate::Event;
+use std::collections::HashSet;
+
 use disintegrate::StreamQuery;
+use disintegrate::{Event, EventInfo, IdentifierCondition, IdentifierValue};
 use sqlx::postgres::PgArguments;
 use sqlx::query::Query;
-use sqlx::Postgres;
+use sqlx::{Encode, Postgres, Type};
 
 use crate::PgEventId;
 
 /// SQL Query Builder
 ///
 /// A builder for constructing SQL query based on the stream query.
+///
+/// The values of the query, i.e. the event types, the origins and the domain identifiers, are bound as
+/// parameters of the statement, so its SQL does not depend on them and it is prepared once per connection.
 pub struct QueryBuilder<'a, QE>
 where
     QE: Event + Clone,
@@ -16,6 +21,8 @@ where
     query: StreamQuery<PgEventId, QE>,
     builder: sqlx::QueryBuilder<'a, Postgres>,
     end: Option<&'a str>,
+    limit: Option<i64>,
+    missing_identifiers: HashSet<String>,
 }
 
 impl<'a, QE> QueryBuilder<'a, QE>
@@ -33,9 +40,46 @@ where
             query,
             builder: sqlx::QueryBuilder::new(init),
             end: None,
+            limit: None,
+            missing_identifiers: HashSet::new(),
         }
     }
 
+    /// Appends an SQL fragment to the initial one.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - The SQL fragment to be appended.
+    pub fn push(mut self, sql: &str) -> Self {
+        self.builder.push(sql);
+        self
+    }
+
+    /// Appends a bind parameter to the initial SQL fragment.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value of the parameter.
+    pub fn push_bind<T>(mut self, value: T) -> Self
+    where
+        T: 'a + Encode<'a, Postgres> + Type<Postgres>,
+    {
+        self.builder.push_bind(value);
+        self
+    }
+
+    /// Sets the domain identifiers whose columns do not exist yet in the database.
+    ///
+    /// A criterion on a missing identifier never matches: no event can have a value for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `missing_identifiers` - The column names of the missing domain identifiers.
+    pub fn with_missing_identifiers(mut self, missing_identifiers: HashSet<String>) -> Self {
+        self.missing_identifiers = missing_identifiers;
+        self
+    }
+
     /// Sets the end SQL fragment of the query.
     ///
     /// # Arguments
@@ -46,6 +90,16 @@ where
         self
     }
 
+    /// Limits the number of rows returned by the query.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of rows.
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
     /// Builds the SQL criteria string.
     pub fn build(&'a mut self) -> Query<'a, Postgres, PgArguments> {
         self.build_criteria(self.query.clone());
@@ -53,6 +107,10 @@ where
         if let Some(end) = self.end {
             self.builder.push(format!(" {end}"));
         }
+        if let Some(limit) = self.limit {
+            self.builder.push(" LIMIT ");
+            self.builder.push_bind(limit);
+        }
         self.builder.build()
     }
 
@@ -73,7 +131,7 @@ where
             self.builder.push("(");
             if filter.origin() > 0 {
                 self.builder.push("event_id > ");
-                self.builder.push(filter.origin());
+                self.builder.push_bind(filter.origin());
                 if has_events {
                     self.builder.push(" AND (");
                 }
@@ -82,28 +140,29 @@ where
             let mut events = events.into_iter().peekable();
             while let Some(event) = events.next() {
                 self.builder.push("(");
-                self.builder.push(format!("event_type = '{event}'"));
                 let event_info = QE::SCHEMA.event_info(event).unwrap();
-                let mut event_identifiers = filter
+                self.push_event_type(event_info);
+                if filter.excludes_event_type(event_info) {
+                    self.builder.push(" AND FALSE)");
+                    events.peek().map(|_| self.builder.push(" OR "));
+                    continue;
+                }
+                for (ident, value) in filter
                     .identifiers()
                     .iter()
                     .filter(|(ident, _)| event_info.has_domain_identifier(ident))
-                    .peekable();
-
-                event_identifiers.peek().map(|_| self.builder.push(" AND "));
-
-                while let Some((ident, value)) = event_identifiers.next() {
+                {
+                    self.builder.push(" AND ");
+                    if self.missing_identifiers.contains(&ident.to_lowercase()) {
+                        self.builder.push("FALSE");
+                        continue;
+                    }
                     self.builder.push(format!("{ident} = "));
-                    match value {
-                        disintegrate::IdentifierValue::String(value) => {
-                            self.builder.push_bind(value.clone())
-                        }
-                        disintegrate::IdentifierValue::i64(value) => self.builder.push_bind(*value),
-                        disintegrate::IdentifierValue::Uuid(value) => {
-                            self.builder.push_bind(*value)
-                        }
-                    };
-                    event_identifiers.peek().map(|_| self.builder.push(" AND "));
+                    self.push_identifier_value(value);
+                }
+                for condition in filter.declared_conditions(event_info) {
+                    self.builder.push(" AND ");
+                    self.push_condition(condition);
                 }
                 self.builder.push(")");
                 events.peek().map(|_| self.builder.push(" OR "));
@@ -115,6 +174,62 @@ where
             filters.peek().map(|_| self.builder.push(" OR "));
         }
     }
+
+    /// Pushes the criteria of a comparison of a domain identifier other than the equality.
+    ///
+    /// The column of a missing identifier is null for all the events.
+    fn push_condition(&mut self, condition: &IdentifierCondition) {
+        let ident = condition.identifier();
+        let missing = self.missing_identifiers.contains(&ident.to_lowercase());
+        match condition {
+            IdentifierCondition::IsNull(_) if missing => self.builder.push("TRUE"),
+            IdentifierCondition::IsNull(_) => self.builder.push(format!("{ident} IS NULL")),
+            IdentifierCondition::NotEq(..) | IdentifierCondition::In(..) if missing => {
+                self.builder.push("FALSE")
+            }
+            IdentifierCondition::NotEq(_, value) => {
+                self.builder.push(format!("{ident} <> "));
+                self.push_identifier_value(value)
+            }
+            IdentifierCondition::In(_, values) if values.is_empty() => self.builder.push("FALSE"),
+            IdentifierCondition::In(_, values) => {
+                self.builder.push(format!("{ident} IN ("));
+                let mut values = values.iter().peekable();
+                while let Some(value) = values.next() {
+                    self.push_identifier_value(value);
+                    values.peek().map(|_| self.builder.push(", "));
+                }
+                self.builder.push(")")
+            }
+        };
+    }
+
+    /// Pushes the criteria matching the event type of an event, including the aliases of its former names.
+    fn push_event_type(&mut self, event_info: &EventInfo) {
+        if event_info.aliases.is_empty() {
+            self.builder.push("event_type = ");
+            self.builder.push_bind(event_info.name);
+        } else {
+            self.builder.push("event_type IN (");
+            let mut event_types = event_info.event_types().peekable();
+            while let Some(event_type) = event_types.next() {
+                self.builder.push_bind(event_type);
+                event_types.peek().map(|_| self.builder.push(", "));
+            }
+            self.builder.push(")");
+        }
+    }
+
+    fn push_identifier_value(
+        &mut self,
+        value: &IdentifierValue,
+    ) -> &mut sqlx::QueryBuilder<'a, Postgres> {
+        match value {
+            IdentifierValue::String(value) => self.builder.push_bind(value.clone()),
+            IdentifierValue::i64(value) => self.builder.push_bind(*value),
+            IdentifierValue::Uuid(value) => self.builder.push_bind(*value),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -122,7 +237,7 @@ mod tests {
     use super::*;
     use disintegrate::{
         domain_identifiers, event_types, ident, query, DomainIdentifierInfo, DomainIdentifierSet,
-        Event, EventInfo, EventSchema, IdentifierType,
+        Event, EventInfo, EventSchema, IdentifierType, MissingIdentifier,
     };
     use sqlx::Execute;
 
@@ -140,10 +255,18 @@ mod tests {
                 &EventInfo {
                     name: "Bar",
                     domain_identifiers: &[&ident!(#bar_id)],
+                    description: None,
+                    owner: None,
+                    aliases: &[],
+                    version: 1,
                 },
                 &EventInfo {
                     name: "Foo",
                     domain_identifiers: &[&ident!(#foo_id)],
+                    description: None,
+                    owner: None,
+                    aliases: &[],
+                    version: 1,
                 },
             ],
             domain_identifiers: &[
@@ -172,7 +295,7 @@ mod tests {
 
         assert_eq!(
             sql_builder.build().sql(),
-            "SELECT * FROM event WHERE ((event_type = 'Bar') OR (event_type = 'Foo'))"
+            "SELECT * FROM event WHERE ((event_type = $1) OR (event_type = $2))"
         );
     }
 
@@ -183,7 +306,7 @@ mod tests {
 
         assert_eq!(
             sql_builder.build().sql(),
-            "SELECT * FROM event WHERE ((event_type = 'Bar') OR (event_type = 'Foo' AND foo_id = $1))"
+            "SELECT * FROM event WHERE ((event_type = $1) OR (event_type = $2 AND foo_id = $3))"
         );
     }
 
@@ -194,7 +317,26 @@ mod tests {
 
         assert_eq!(
             sql_builder.build().sql(),
-            "SELECT * FROM event WHERE ((event_type = 'Bar' AND bar_id = $1) OR (event_type = 'Foo' AND foo_id = $2))"
+            "SELECT * FROM event WHERE ((event_type = $1 AND bar_id = $2) OR (event_type = $3 AND foo_id = $4))"
+        );
+    }
+
+    #[test]
+    fn it_builds_query_with_the_identifier_conditions() {
+        let query = query!(TestEvent; foo_id in ["value1", "value2"], bar_id != "value3");
+        let mut sql_builder = QueryBuilder::new(query, "SELECT * FROM event WHERE ");
+
+        assert_eq!(
+            sql_builder.build().sql(),
+            "SELECT * FROM event WHERE ((event_type = $1 AND bar_id <> $2) OR (event_type = $3 AND foo_id IN ($4, $5)))"
+        );
+
+        let query = query!(TestEvent; foo_id is_null);
+        let mut sql_builder = QueryBuilder::new(query, "SELECT * FROM event WHERE ");
+
+        assert_eq!(
+            sql_builder.build().sql(),
+            "SELECT * FROM event WHERE ((event_type = $1) OR (event_type = $2 AND foo_id IS NULL))"
         );
     }
 
@@ -205,7 +347,22 @@ mod tests {
 
         assert_eq!(
             sql_builder.build().sql(),
-            "SELECT * FROM event WHERE (event_id > 10 AND ((event_type = 'Bar') OR (event_type = 'Foo' AND foo_id = $1)))"
+            "SELECT * FROM event WHERE (event_id > $1 AND ((event_type = $2) OR (event_type = $3 AND foo_id = $4)))"
+        );
+    }
+
+    #[test]
+    fn it_builds_query_with_bound_prefix_and_limit() {
+        let query = query!(TestEvent; foo_id == "value");
+        let mut sql_builder = QueryBuilder::new(query, "SELECT * FROM event WHERE event_id > ")
+            .push_bind(10)
+            .push(" AND (")
+            .end_with(") ORDER BY event_id ASC")
+            .limit(100);
+
+        assert_eq!(
+            sql_builder.build().sql(),
+            "SELECT * FROM event WHERE event_id > $1 AND (((event_type = $2) OR (event_type = $3 AND foo_id = $4)) ) ORDER BY event_id ASC LIMIT $5"
         );
     }
 
@@ -217,7 +374,7 @@ mod tests {
 
         assert_eq!(
             sql_builder.build().sql(),
-            "SELECT * FROM event WHERE ((event_type = 'Bar' AND bar_id = $1) OR (event_type = 'Foo')) OR ((event_type = 'Bar') OR (event_type = 'Foo' AND foo_id = $2))"
+            "SELECT * FROM event WHERE ((event_type = $1 AND bar_id = $2) OR (event_type = $3)) OR ((event_type = $4) OR (event_type = $5 AND foo_id = $6))"
         );
     }
 
@@ -229,7 +386,70 @@ mod tests {
 
         assert_eq!(
             sql_builder.build().sql(),
-            r#"SELECT * FROM event WHERE ((event_type = 'Foo'))"#
+            r#"SELECT * FROM event WHERE ((event_type = $1))"#
+        );
+    }
+
+    #[test]
+    fn it_builds_query_with_a_missing_identifier() {
+        let query = query!(TestEvent; foo_id == "value", bar_id == "value2");
+        let mut sql_builder = QueryBuilder::new(query, "SELECT * FROM event WHERE ")
+            .with_missing_identifiers(["foo_id".to_string()].into());
+
+        assert_eq!(
+            sql_builder.build().sql(),
+            "SELECT * FROM event WHERE ((event_type = $1 AND bar_id = $2) OR (event_type = $3 AND FALSE))"
+        );
+    }
+
+    #[test]
+    fn it_builds_query_excluding_the_events_without_the_identifier() {
+        let query = query!(TestEvent; foo_id == "value")
+            .with_missing_identifier(MissingIdentifier::NoMatch);
+        let mut sql_builder = QueryBuilder::new(query, "SELECT * FROM event WHERE ");
+
+        assert_eq!(
+            sql_builder.build().sql(),
+            "SELECT * FROM event WHERE ((event_type = $1 AND FALSE) OR (event_type = $2 AND foo_id = $3))"
+        );
+    }
+
+    #[derive(Clone)]
+    struct RenamedEvent;
+
+    impl Event for RenamedEvent {
+        const SCHEMA: EventSchema = EventSchema {
+            events: &["Foo"],
+            events_info: &[&EventInfo {
+                name: "Foo",
+                domain_identifiers: &[&ident!(#foo_id)],
+                description: None,
+                owner: None,
+                aliases: &["OldFoo", "OlderFoo"],
+                version: 1,
+            }],
+            domain_identifiers: &[&DomainIdentifierInfo {
+                ident: ident!(#foo_id),
+                type_info: IdentifierType::String,
+            }],
+        };
+
+        fn name(&self) -> &'static str {
+            "Foo"
+        }
+        fn domain_identifiers(&self) -> DomainIdentifierSet {
+            domain_identifiers! {}
+        }
+    }
+
+    #[test]
+    fn it_builds_query_with_the_aliases_of_the_renamed_events() {
+        let query = query!(RenamedEvent; foo_id == "value");
+        let mut sql_builder = QueryBuilder::new(query, "SELECT * FROM event WHERE ");
+
+        assert_eq!(
+            sql_builder.build().sql(),
+            "SELECT * FROM event WHERE ((event_type IN ($1, $2, $3) AND foo_id = $4))"
         );
     }
 }
@@ -0,0 +1,112 @@
+//! Event redaction
+//!
+//! The events are immutable, but the personal data they carry must be erased on request, e.g. to honor
+//! the right to be forgotten. Redacting the events of a domain identifier value rewrites their payloads
+//! in place with a redacted copy, keeping their IDs, types and domain identifiers, so the streams, the
+//! checkpoints of the listeners and the projections rebuilt afterwards stay consistent.
+use disintegrate::{Event, Identifier, IntoIdentifierValue, SnapshotStore, StreamQueryBuilder};
+use disintegrate_serde::{PayloadInfo, Serde};
+use sqlx::Row;
+
+use super::QueryBuilder;
+use crate::{Error, PgEventId, PgEventStore, PgSnapshotStore};
+
+impl<E, S> PgEventStore<E, S>
+where
+    E: Event + Clone + Send + Sync,
+    S: Serde<E> + Send + Sync,
+{
+    /// Redacts the events of a domain identifier value, e.g. to forget the personal data of a user.
+    ///
+    /// Each event carrying the value is deserialized, passed to `redact`, and its payload is replaced by the
+    /// serialized result, with the current version of the event, in the event store and in its archive. The
+    /// snapshots that may contain the data of the redacted events are deleted from the `snapshot` table and
+    /// from the stores added with `with_snapshot_store`. The metadata of the events are left untouched, and the read models
+    /// must be cleaned up, or rebuilt, separately.
+    ///
+    /// # Arguments
+    ///
+    /// * `ident` - The domain identifier, e.g. `ident!(#user_id)`.
+    /// * `value` - The value of the domain identifier whose events are redacted.
+    /// * `redact` - The function erasing the personal data of an event, e.g. by blanking its fields.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of redacted events, or `Error::InvalidQuery` if the domain identifier
+    /// is not declared by the events or the value does not have its type.
+    pub async fn redact(
+        &self,
+        ident: Identifier,
+        value: impl IntoIdentifierValue,
+        redact: impl Fn(E) -> E,
+    ) -> Result<usize, Error> {
+        let query = StreamQueryBuilder::<PgEventId, E>::events()
+            .ident(ident.into_inner(), value)
+            .build()?;
+        let missing_identifiers = self
+            .identifier_columns
+            .missing(&self.pool, E::SCHEMA.domain_identifiers)
+            .await?;
+        let mut redacted = 0;
+        let mut first_event_id: Option<PgEventId> = None;
+        for pool in std::iter::once(&self.pool).chain(&self.archive) {
+            let rows = QueryBuilder::new(
+                query.clone(),
+                "SELECT event_id, payload, event_type, event_version FROM event WHERE (",
+            )
+            .with_missing_identifiers(missing_identifiers.clone())
+            .end_with(") ORDER BY event_id ASC")
+            .build()
+            .fetch_all(pool)
+            .await?;
+            let mut tx = pool.begin().await?;
+            for row in rows {
+                let event_id: PgEventId = row.get(0);
+                let info = PayloadInfo {
+                    event_type: row.get(2),
+                    version: row.get::<i32, _>(3) as u32,
+                    event_id: Some(event_id),
+                };
+                let event = redact(self.serde.deserialize_with_info(row.get(1), info)?);
+                let info = PayloadInfo {
+                    event_type: event.name(),
+                    version: event.version(),
+                    event_id: Some(event_id),
+                };
+                let version = event.version() as i32;
+                sqlx::query(
+                    "UPDATE event SET payload = $2, event_version = $3 WHERE event_id = $1",
+                )
+                .bind(event_id)
+                .bind(self.serde.serialize_with_info(event, info))
+                .bind(version)
+                .execute(&mut *tx)
+                .await?;
+                first_event_id = Some(first_event_id.map_or(event_id, |first| first.min(event_id)));
+                redacted += 1;
+            }
+            tx.commit().await?;
+        }
+        if let Some(first_event_id) = first_event_id {
+            self.invalidate_snapshots_from(first_event_id).await?;
+        }
+        Ok(redacted)
+    }
+
+    /// Deletes the snapshots, and the quarantined snapshots, that applied an event or the events following it.
+    async fn invalidate_snapshots_from(&self, event_id: PgEventId) -> Result<(), Error> {
+        if crate::schema::table_exists(&self.pool, "snapshot").await? {
+            PgSnapshotStore::new_uninitialized(self.pool.clone())
+                .invalidate_from(event_id)
+                .await
+                .map_err(Error::SnapshotInvalidation)?;
+        }
+        for store in &self.snapshot_stores {
+            store
+                .invalidate_from(event_id)
+                .await
+                .map_err(Error::SnapshotInvalidation)?;
+        }
+        Ok(())
+    }
+}
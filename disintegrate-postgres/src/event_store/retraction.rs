@@ -0,0 +1,107 @@
+//! Event retractions
+//!
+//! A retraction corrects bad data without rewriting the event history. Retracting an event appends a
+//! system event, with the reserved `$Retract` event type, and records the retracted event in the
+//! `event_retraction` table: the event store can skip the retracted events in the subsequent streams,
+//! and the event listeners that opt in receive the retractions.
+use disintegrate::{Event, Retraction};
+use disintegrate_serde::Serde;
+use serde::Serialize;
+use sqlx::Row;
+
+use super::append_system_event;
+use crate::{Error, PgEventId, PgEventStore};
+
+/// The reserved event type of the retraction events.
+pub const RETRACT_EVENT_TYPE: &str = "$Retract";
+
+#[derive(Serialize)]
+struct RetractPayload<'a> {
+    event_id: PgEventId,
+    reason: &'a str,
+}
+
+impl<E, S> PgEventStore<E, S>
+where
+    E: Event + Send + Sync,
+    S: Serde<E> + Send + Sync,
+{
+    /// Retracts an event.
+    ///
+    /// Retracting an event already retracted has no effect.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - The ID of the event to retract.
+    /// * `reason` - The reason of the retraction.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the ID of the `$Retract` event, or `Error::UnknownEvent` if the event does not exist.
+    pub async fn retract(&self, event_id: PgEventId, reason: &str) -> Result<PgEventId, Error> {
+        let mut tx = self.pool.begin().await?;
+        let event_type: String = sqlx::query("SELECT event_type FROM event WHERE event_id = $1")
+            .bind(event_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(Error::UnknownEvent(event_id))?
+            .get(0);
+        if let Some(retracted_by) =
+            sqlx::query("SELECT retracted_by FROM event_retraction WHERE event_id = $1")
+                .bind(event_id)
+                .fetch_optional(&mut *tx)
+                .await?
+        {
+            return Ok(retracted_by.get(0));
+        }
+        let payload = serde_json::to_vec(&RetractPayload { event_id, reason })
+            .expect("json serialization should not fail");
+        let retracted_by = append_system_event(&mut tx, RETRACT_EVENT_TYPE, &payload).await?;
+        sqlx::query(
+            "INSERT INTO event_retraction (event_id, event_type, retracted_by, reason) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(event_id)
+        .bind(event_type)
+        .bind(retracted_by)
+        .bind(reason)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(retracted_by)
+    }
+
+    /// Returns the retractions recorded after the `origin`, of the events of the given types.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - The ID of the event after which the retractions are returned.
+    /// * `event_types` - The types of the retracted events.
+    /// * `limit` - The maximum number of retractions returned.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the retractions, in the order they have been recorded.
+    pub async fn retractions(
+        &self,
+        origin: PgEventId,
+        event_types: &[&str],
+        limit: i64,
+    ) -> Result<Vec<Retraction<PgEventId>>, Error> {
+        Ok(sqlx::query(
+            "SELECT retracted_by, event_id, event_type, reason FROM event_retraction WHERE retracted_by > $1 AND event_type = ANY($2) ORDER BY retracted_by LIMIT $3",
+        )
+        .bind(origin)
+        .bind(event_types)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| Retraction {
+            id: row.get(0),
+            event_id: row.get(1),
+            event_type: row.get(2),
+            reason: row.get::<Option<String>, _>(3).unwrap_or_default(),
+        })
+        .collect())
+    }
+}
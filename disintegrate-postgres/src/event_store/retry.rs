@@ -0,0 +1,169 @@
+//! Retry of the transient database errors
+//!
+//! A connection reset or a serialization failure usually succeeds when the statement is run again.
+//! This module defines the policy used by the event store to retry the statements that are safe to
+//! run again: the reads, and the statements of an append that run before any event ID is reserved in
+//! the `event_sequence` table.
+use std::future::Future;
+use std::time::Duration;
+
+use crate::Error;
+
+/// The retry policy of the transient database errors of the event store.
+///
+/// The delay between two attempts starts from `initial_backoff` and doubles at each attempt, up to `max_backoff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgRetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for PgRetryPolicy {
+    /// Returns a policy that never retries.
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl PgRetryPolicy {
+    /// Creates a new `PgRetryPolicy`, with a backoff from 50ms up to 1s.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_attempts` - The maximum number of attempts of a statement, including the first one.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets the delays between two attempts.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial_backoff` - The delay after the first failed attempt.
+    /// * `max_backoff` - The maximum delay between two attempts.
+    pub fn with_backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff.max(initial_backoff);
+        self
+    }
+
+    /// Returns the maximum number of attempts of a statement.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns the delay after the given failed attempt, or `None` if the error must not be retried.
+    pub(crate) fn backoff(&self, error: &Error, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_attempts || !is_transient(error) {
+            return None;
+        }
+        let factor = 2u32.saturating_pow(attempt - 1);
+        Some(
+            self.initial_backoff
+                .saturating_mul(factor)
+                .min(self.max_backoff),
+        )
+    }
+
+    /// Runs the given operation, retrying it while it fails with a transient error.
+    ///
+    /// The operation must be safe to run again after a failure.
+    pub(crate) async fn retry<F, Fut, T>(&self, mut operation: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Err(error) => match self.backoff(&error, attempt) {
+                    Some(backoff) => {
+                        tokio::time::sleep(backoff).await;
+                        attempt += 1;
+                    }
+                    None => return Err(error),
+                },
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Checks if the error is likely to go away by running the statement again.
+fn is_transient(error: &Error) -> bool {
+    let Error::Database(error) = error else {
+        return false;
+    };
+    match error {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(description) => description.code().is_some_and(|code| {
+            // serialization_failure, deadlock_detected, admin_shutdown and the connection exceptions
+            matches!(code.as_ref(), "40001" | "40P01" | "57P01") || code.starts_with("08")
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection_reset() -> Error {
+        Error::Database(sqlx::Error::Io(std::io::Error::from(
+            std::io::ErrorKind::ConnectionReset,
+        )))
+    }
+
+    #[test]
+    fn it_backs_off_the_transient_errors_up_to_the_max_attempts() {
+        let policy = PgRetryPolicy::new(4)
+            .with_backoff(Duration::from_millis(100), Duration::from_millis(300));
+
+        assert_eq!(
+            policy.backoff(&connection_reset(), 1),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            policy.backoff(&connection_reset(), 2),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            policy.backoff(&connection_reset(), 3),
+            Some(Duration::from_millis(300))
+        );
+        assert_eq!(policy.backoff(&connection_reset(), 4), None);
+        assert_eq!(policy.backoff(&Error::Concurrency, 1), None);
+        assert_eq!(
+            PgRetryPolicy::default().backoff(&connection_reset(), 1),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn it_retries_an_operation_until_it_succeeds() {
+        let policy =
+            PgRetryPolicy::new(3).with_backoff(Duration::from_millis(1), Duration::from_millis(1));
+        let mut attempts = 0;
+
+        let result = policy
+            .retry(|| {
+                attempts += 1;
+                let attempt = attempts;
+                async move {
+                    if attempt < 3 {
+                        Err(connection_reset())
+                    } else {
+                        Ok(attempt)
+                    }
+                }
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), 3);
+    }
+}
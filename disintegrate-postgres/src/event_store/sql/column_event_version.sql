@@ -0,0 +1 @@
+ALTER TABLE event ADD COLUMN IF NOT EXISTS event_version INTEGER NOT NULL DEFAULT 1;
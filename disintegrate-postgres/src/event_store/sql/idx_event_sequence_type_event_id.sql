@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS idx_event_sequence_type_event_id ON event_sequence (event_type, event_id);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS event_archive_watermark (
+    id BOOLEAN PRIMARY KEY DEFAULT true CHECK (id),
+    archived_up_to bigint NOT NULL,
+    updated_at TIMESTAMP DEFAULT now()
+);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS event_outbox (
+    event_id BIGINT PRIMARY KEY,
+    attempts INT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at TIMESTAMP DEFAULT now()
+);
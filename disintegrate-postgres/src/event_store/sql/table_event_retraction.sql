@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS event_retraction (
+    event_id BIGINT PRIMARY KEY,
+    event_type varchar(255) NOT NULL,
+    retracted_by BIGINT NOT NULL UNIQUE,
+    reason TEXT,
+    retracted_at TIMESTAMP DEFAULT now()
+);
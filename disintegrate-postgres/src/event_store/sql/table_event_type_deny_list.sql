@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS event_type_deny_list (
+    event_type varchar(255) PRIMARY KEY,
+    reason TEXT,
+    inserted_at TIMESTAMP DEFAULT now()
+);
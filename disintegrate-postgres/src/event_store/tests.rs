@@ -1,14 +1,17 @@
 use super::insert_builder::InsertBuilder;
-use crate::{Error, PgEventId, PgEventStore};
+use crate::{Error, PgEventId, PgEventStore, PgFanInEventStore, PgIdentifierIndex, PgRetryPolicy};
 use disintegrate::{
-    domain_identifiers, ident, query, DomainIdentifierInfo, DomainIdentifierSet, Event, EventInfo,
-    EventSchema, EventStore, IdentifierType,
+    domain_identifiers, ident, query, Cursor, DomainIdentifierInfo, DomainIdentifierSet, Event,
+    EventInfo, EventSchema, EventStore, EventTimestampResolver, FileSnapshotStore, IdentifierType,
+    Metadata, Snapshot, SnapshotStore, StoreMigrations, CORRELATION_ID, USER_ID,
 };
-use disintegrate_serde::serde::json::Json;
+use disintegrate_serde::serde::json::{json_upcaster, Json};
+use disintegrate_serde::serde::upcast::Upcasted;
 use disintegrate_serde::{Deserializer, Serializer};
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
-use sqlx::postgres::PgRow;
+use serde_json::Value;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgRow};
 use sqlx::{PgPool, Row};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -37,10 +40,18 @@ impl Event for ShoppingCartEvent {
             &EventInfo {
                 name: "ShoppingCartAdded",
                 domain_identifiers: &[&ident!(#product_id), &ident!(#cart_id)],
+                description: None,
+                owner: None,
+                aliases: &[],
+                version: 1,
             },
             &EventInfo {
                 name: "ShoppingCartRemoved",
                 domain_identifiers: &[&ident!(#product_id), &ident!(#cart_id)],
+                description: None,
+                owner: None,
+                aliases: &[],
+                version: 2,
             },
         ],
         domain_identifiers: &[
@@ -100,6 +111,273 @@ async fn it_queries_events(pool: PgPool) {
     assert_eq!(result.len(), 2);
 }
 
+#[sqlx::test]
+async fn it_queries_events_with_the_identifier_conditions(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+
+    let events = vec![
+        added_event("product_1", "cart_1"),
+        added_event("product_2", "cart_2"),
+        added_event("product_1", "cart_3"),
+        removed_event("product_1", "cart_2"),
+    ];
+    insert_events(&pool, &events).await;
+
+    let cart_ids = ["cart_1", "cart_2"];
+    let query = query!(ShoppingCartEvent; cart_id in cart_ids, product_id != "product_2");
+    let result = event_store.stream(&query).collect::<Vec<_>>().await;
+
+    assert_eq!(result.len(), 2);
+    let query = query!(ShoppingCartEvent; cart_id is_null);
+    assert_eq!(
+        event_store.stream(&query).collect::<Vec<_>>().await.len(),
+        0
+    );
+}
+
+#[sqlx::test]
+async fn it_streams_the_events_with_their_metadata(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let metadata = Metadata::new()
+        .with_correlation_id("request-1")
+        .with_user_id("alice");
+
+    event_store
+        .append_with_metadata(
+            vec![added_event("product_1", "cart_1")],
+            metadata.clone(),
+            query!(ShoppingCartEvent; cart_id == "cart_1"),
+            0,
+        )
+        .await
+        .unwrap();
+    event_store
+        .append(
+            vec![added_event("product_2", "cart_1")],
+            query!(ShoppingCartEvent; cart_id == "cart_1"),
+            1,
+        )
+        .await
+        .unwrap();
+
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+    let result: Vec<_> = event_store
+        .stream(&query)
+        .map(|event| event.unwrap().metadata().clone())
+        .collect()
+        .await;
+    assert_eq!(result, vec![metadata, Metadata::default()]);
+}
+
+#[sqlx::test]
+async fn it_streams_the_events_by_an_indexed_key_of_their_metadata(pool: PgPool) {
+    let event_store =
+        PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new_uninitialized(
+            pool.clone(),
+            Json::default(),
+        )
+        .with_metadata_index(CORRELATION_ID)
+        .initialize()
+        .await
+        .unwrap();
+
+    for (version, (product_id, correlation_id)) in [
+        ("product_1", "request-1"),
+        ("product_2", "request-2"),
+        ("product_3", "request-1"),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        event_store
+            .append_with_metadata(
+                vec![added_event(product_id, "cart_1")],
+                Metadata::new().with_correlation_id(correlation_id),
+                query!(ShoppingCartEvent; cart_id == "cart_1"),
+                version as PgEventId,
+            )
+            .await
+            .unwrap();
+    }
+
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+    let result: Vec<_> = event_store
+        .stream_by_metadata(&query, CORRELATION_ID, "request-1")
+        .map(|event| event.unwrap().into_inner())
+        .collect()
+        .await;
+    assert_eq!(
+        result,
+        vec![
+            added_event("product_1", "cart_1"),
+            added_event("product_3", "cart_1")
+        ]
+    );
+
+    let unindexed: Vec<_> = event_store
+        .stream_by_metadata(&query, USER_ID, "alice")
+        .collect()
+        .await;
+    assert!(matches!(
+        unindexed.as_slice(),
+        [Err(Error::UnindexedMetadataKey(key))] if key == USER_ID
+    ));
+
+    let index: Option<String> = sqlx::query_scalar(
+        "SELECT indexname::TEXT FROM pg_indexes WHERE indexname = 'idx_event_metadata_correlation_id'",
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap();
+    assert!(index.is_some());
+}
+
+#[sqlx::test]
+async fn it_streams_the_events_with_the_version_they_were_appended_with(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    event_store
+        .append(
+            vec![
+                added_event("product_1", "cart_1"),
+                removed_event("product_1", "cart_1"),
+                removed_event("product_2", "cart_1"),
+            ],
+            query!(ShoppingCartEvent; cart_id == "cart_1"),
+            0,
+        )
+        .await
+        .unwrap();
+    let versions: Vec<i32> =
+        sqlx::query_scalar("SELECT event_version FROM event ORDER BY event_id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(versions, vec![1, 2, 2]);
+
+    sqlx::query("UPDATE event SET event_version = 1 WHERE event_id = 2")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+    let result: Vec<_> = event_store
+        .stream(&query)
+        .map(|event| {
+            let event = event.unwrap();
+            (event.version(), (*event).version())
+        })
+        .collect()
+        .await;
+    assert_eq!(result, vec![(1, 1), (1, 2), (2, 2)]);
+}
+
+#[sqlx::test]
+async fn it_upcasts_the_payloads_from_the_version_they_were_appended_with(pool: PgPool) {
+    let serde = Upcasted::new(Json::<ShoppingCartEvent>::default()).with_event_upcaster(
+        "ShoppingCartRemoved",
+        1,
+        json_upcaster(|value| {
+            if let Value::Object(fields) = value {
+                if let Some(product) = fields.remove("product") {
+                    fields.insert("product_id".to_string(), product);
+                }
+            }
+            Ok(())
+        }),
+    );
+    let event_store = PgEventStore::new(pool.clone(), serde).await.unwrap();
+    event_store
+        .append(
+            vec![
+                removed_event("product_1", "cart_1"),
+                removed_event("product_2", "cart_1"),
+            ],
+            query!(ShoppingCartEvent; cart_id == "cart_1"),
+            0,
+        )
+        .await
+        .unwrap();
+    sqlx::query("UPDATE event SET event_version = 1, payload = $1 WHERE event_id = 1")
+        .bind(br#"{"event_type": "removed", "product": "product_1", "cart_id": "cart_1"}"#.to_vec())
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+    let result: Vec<_> = event_store
+        .stream(&query)
+        .map(|event| {
+            let event = event.unwrap();
+            (event.version(), event.into_inner())
+        })
+        .collect()
+        .await;
+    assert_eq!(
+        result,
+        vec![
+            (1, removed_event("product_1", "cart_1")),
+            (2, removed_event("product_2", "cart_1"))
+        ]
+    );
+}
+
+#[sqlx::test]
+async fn it_pages_through_the_events(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let events = vec![
+        added_event("product_1", "cart_1"),
+        added_event("product_1", "cart_2"),
+        removed_event("product_1", "cart_1"),
+        added_event("product_2", "cart_1"),
+    ];
+    insert_events(&pool, &events).await;
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+
+    let (page, cursor) = event_store
+        .stream_page(&query, Cursor::Start, 2)
+        .await
+        .unwrap();
+    assert_eq!(
+        page.into_iter().map(|e| e.id()).collect::<Vec<_>>(),
+        vec![1, 3]
+    );
+    assert_eq!(cursor, Cursor::After(3));
+
+    let (page, cursor) = event_store.stream_page(&query, cursor, 2).await.unwrap();
+    assert_eq!(
+        page.into_iter().map(|e| e.id()).collect::<Vec<_>>(),
+        vec![4]
+    );
+    assert!(cursor.is_end());
+
+    let (page, cursor) = event_store
+        .stream_page(&query, Cursor::Start, 0)
+        .await
+        .unwrap();
+    assert!(page.is_empty());
+    assert!(cursor.is_end());
+}
+
 #[sqlx::test]
 async fn it_appends_events(pool: PgPool) {
     let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
@@ -136,6 +414,217 @@ async fn it_appends_events(pool: PgPool) {
     );
 }
 
+#[sqlx::test]
+async fn it_appends_events_in_chunks(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap()
+    .with_append_batch_size(2);
+    let events: Vec<ShoppingCartEvent> = (0..5)
+        .map(|i| added_event(&format!("product_{i}"), "cart_1"))
+        .collect();
+
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+    let persisted_events = event_store.append(events, query, 0).await.unwrap();
+
+    let stored_events =
+        sqlx::query("SELECT event_id, event_type, payload FROM event ORDER BY event_id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(stored_events.len(), 5);
+    for (row, event) in stored_events.iter().zip(persisted_events) {
+        assert_event_row(row, event.id(), "ShoppingCartAdded", event.into_inner());
+    }
+}
+
+#[sqlx::test]
+async fn it_streams_the_events_in_batches(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap()
+    .with_stream_batch_size(100);
+    // enough events for the batches decoded on the blocking thread pool, and a small last batch.
+    let events: Vec<ShoppingCartEvent> = (0..250)
+        .map(|i| added_event(&format!("product_{i}"), "cart_1"))
+        .collect();
+    event_store
+        .append(events.clone(), query!(ShoppingCartEvent), 0)
+        .await
+        .unwrap();
+
+    let streamed: Vec<(PgEventId, ShoppingCartEvent)> = event_store
+        .stream(&query!(ShoppingCartEvent; cart_id == "cart_1"))
+        .map(|event| {
+            let event = event.unwrap();
+            (event.id(), event.into_inner())
+        })
+        .collect()
+        .await;
+
+    assert_eq!(
+        streamed,
+        (1..)
+            .zip(events)
+            .collect::<Vec<(PgEventId, ShoppingCartEvent)>>()
+    );
+}
+
+#[sqlx::test]
+async fn it_streams_the_events_through_a_server_side_cursor(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap()
+    .with_stream_batch_size(2)
+    .with_server_side_cursor();
+    event_store
+        .append(
+            vec![
+                added_event("p1", "c1"),
+                added_event("p2", "c2"),
+                added_event("p3", "c1"),
+                removed_event("p1", "c1"),
+                added_event("p4", "c1"),
+            ],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+
+    let event_ids: Vec<PgEventId> = event_store
+        .stream(&query!(ShoppingCartEvent; cart_id == "c1"))
+        .map(|event| event.unwrap().id())
+        .collect()
+        .await;
+
+    assert_eq!(event_ids, vec![1, 3, 4, 5]);
+}
+
+#[sqlx::test]
+async fn it_rejects_the_events_of_a_denied_event_type(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+    event_store
+        .deny_event_type("ShoppingCartRemoved", "looping process manager")
+        .await
+        .unwrap();
+
+    let result = event_store
+        .append(
+            vec![
+                added_event("product_1", "cart_1"),
+                removed_event("product_1", "cart_1"),
+            ],
+            query.clone(),
+            0,
+        )
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(Error::EventTypeDenied { event_type, reason: Some(reason) })
+            if event_type == "ShoppingCartRemoved" && reason == "looping process manager"
+    ));
+    let stored_events: i64 = sqlx::query_scalar("SELECT count(*) FROM event")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stored_events, 0);
+
+    event_store
+        .allow_event_type("ShoppingCartRemoved")
+        .await
+        .unwrap();
+    assert!(event_store
+        .append(vec![removed_event("product_1", "cart_1")], query, 0)
+        .await
+        .is_ok());
+}
+
+#[sqlx::test]
+async fn it_caches_the_deny_list_until_it_is_changed_by_the_event_store(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+    event_store
+        .append(vec![added_event("product_1", "cart_1")], query.clone(), 0)
+        .await
+        .unwrap();
+
+    sqlx::query("INSERT INTO event_type_deny_list (event_type) VALUES ('ShoppingCartRemoved')")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let cached = event_store
+        .append(vec![removed_event("product_1", "cart_1")], query.clone(), 1)
+        .await;
+    event_store
+        .deny_event_type("ShoppingCartAdded", "looping process manager")
+        .await
+        .unwrap();
+    let refreshed = event_store
+        .append(vec![removed_event("product_2", "cart_1")], query, 2)
+        .await;
+
+    assert!(cached.is_ok());
+    assert!(matches!(
+        refreshed,
+        Err(Error::EventTypeDenied { event_type, reason: None }) if event_type == "ShoppingCartRemoved"
+    ));
+}
+
+#[sqlx::test]
+async fn it_resolves_the_timestamps_of_the_events(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+    event_store
+        .append(vec![added_event("product_1", "cart_1")], query.clone(), 0)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE event SET inserted_at = inserted_at - interval '7 minutes'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    event_store
+        .append(vec![removed_event("product_1", "cart_1")], query, 1)
+        .await
+        .unwrap();
+
+    assert!(event_store.timestamp(0).await.unwrap().is_none());
+    assert!(event_store.timestamp(1).await.unwrap().is_some());
+    let lag = event_store.time_lag(1, 2).await.unwrap().unwrap();
+    assert!(lag >= std::time::Duration::from_secs(7 * 60));
+    assert!(lag < std::time::Duration::from_secs(8 * 60));
+    assert_eq!(
+        event_store.time_lag(2, 10).await.unwrap(),
+        Some(Default::default())
+    );
+}
+
 fn assert_event_row(
     row: &PgRow,
     event_id: PgEventId,
@@ -177,22 +666,49 @@ async fn it_returns_a_concurrency_error_when_it_appends_events_of_a_query_which_
 }
 
 #[sqlx::test]
-async fn it_returns_a_concurrency_error_when_it_appends_events_of_a_query_which_its_events_have_been_changed(
-    pool: PgPool,
-) {
+async fn it_releases_the_event_ids_reserved_by_a_conflicting_append(pool: PgPool) {
     let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
         pool.clone(),
         Json::default(),
     )
     .await
     .unwrap();
-    let events = vec![
-        added_event("product_1", "cart_1"),
-        removed_event("product_1", "cart_1"),
-    ];
-    insert_events(&pool, &events).await;
 
-    let query_1 = query!(ShoppingCartEvent; cart_id == "cart_1");
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+    event_store
+        .append(vec![added_event("product_1", "cart_1")], query.clone(), 0)
+        .await
+        .unwrap();
+    let result = event_store
+        .append(vec![removed_event("product_1", "cart_1")], query, 0)
+        .await;
+
+    assert!(matches!(result, Err(Error::Concurrency)));
+    let reserved: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM event_sequence WHERE NOT committed")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(reserved, 0);
+}
+
+#[sqlx::test]
+async fn it_returns_a_concurrency_error_when_it_appends_events_of_a_query_which_its_events_have_been_changed(
+    pool: PgPool,
+) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let events = vec![
+        added_event("product_1", "cart_1"),
+        removed_event("product_1", "cart_1"),
+    ];
+    insert_events(&pool, &events).await;
+
+    let query_1 = query!(ShoppingCartEvent; cart_id == "cart_1");
     let query_1_result = event_store
         .stream(&query_1)
         .collect::<Vec<_>>()
@@ -243,3 +759,549 @@ pub async fn insert_events<E: Event + Clone + Serialize>(pool: &PgPool, events:
         event_insert.build().execute(pool).await.unwrap();
     }
 }
+
+#[sqlx::test]
+async fn it_refuses_to_plan_the_migrations_when_an_identifier_changed_type(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    assert!(event_store.plan().await.is_ok());
+    sqlx::query("ALTER TABLE event DROP COLUMN cart_id")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("ALTER TABLE event ADD COLUMN cart_id BIGINT")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let result = event_store.plan().await;
+
+    assert!(matches!(
+        result,
+        Err(Error::IdentifierTypeMismatch { identifier, expected: "TEXT", found }) if identifier == "cart_id" && found == "BIGINT"
+    ));
+}
+
+#[sqlx::test]
+async fn it_migrates_an_uninitialized_event_store(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new_uninitialized(
+        pool.clone(),
+        Json::default(),
+    );
+
+    let dry_run = event_store.migrate(true).await.unwrap();
+    assert_eq!(dry_run.migrations, vec!["v1_initial_schema"]);
+    assert!(!event_store.plan().await.unwrap().is_up_to_date());
+
+    event_store.migrate(false).await.unwrap();
+
+    assert!(event_store.plan().await.unwrap().is_up_to_date());
+    let metadata = event_store.metadata().await.unwrap().unwrap();
+    assert_eq!(metadata.applied_migrations, vec!["v1_initial_schema"]);
+    event_store
+        .append(vec![added_event("p1", "c1")], query!(ShoppingCartEvent), 0)
+        .await
+        .unwrap();
+}
+
+#[sqlx::test]
+async fn it_degrades_gracefully_until_the_identifier_columns_are_added(pool: PgPool) {
+    crate::event_store::setup::<ShoppingCartEvent>(&pool, &Default::default())
+        .await
+        .unwrap();
+    sqlx::query("ALTER TABLE event DROP COLUMN product_id")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new_uninitialized(
+        pool.clone(),
+        Json::default(),
+    );
+
+    let events: Vec<_> = event_store
+        .stream(&query!(ShoppingCartEvent; product_id == "p1"))
+        .collect()
+        .await;
+    assert!(events.is_empty());
+    let result = event_store
+        .append(
+            vec![added_event("p1", "c1")],
+            query!(ShoppingCartEvent; cart_id == "c1"),
+            0,
+        )
+        .await;
+    assert!(matches!(result, Err(Error::SchemaNotReady(identifier)) if identifier == "product_id"));
+
+    crate::event_store::setup::<ShoppingCartEvent>(&pool, &Default::default())
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+
+    event_store
+        .append(
+            vec![added_event("p1", "c1")],
+            query!(ShoppingCartEvent; product_id == "p1"),
+            0,
+        )
+        .await
+        .unwrap();
+    let events: Vec<_> = event_store
+        .stream(&query!(ShoppingCartEvent; product_id == "p1"))
+        .collect()
+        .await;
+    assert_eq!(events.len(), 1);
+}
+
+#[sqlx::test]
+async fn it_merges_the_events_of_the_archives(
+    pool_options: PgPoolOptions,
+    connect_options: PgConnectOptions,
+) {
+    let pool = pool_options
+        .clone()
+        .connect_with(connect_options.clone())
+        .await
+        .unwrap();
+    sqlx::query("CREATE SCHEMA archive")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let archive_pool = pool_options
+        .connect_with(connect_options.options([("search_path", "archive")]))
+        .await
+        .unwrap();
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    crate::event_store::setup::<ShoppingCartEvent>(&archive_pool, &Default::default())
+        .await
+        .unwrap();
+    event_store
+        .append(
+            vec![
+                added_event("p1", "c1"),
+                added_event("p2", "c2"),
+                removed_event("p1", "c1"),
+                added_event("p3", "c1"),
+            ],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    // the event 2 is being moved: it is both in the archive and in the event store.
+    sqlx::query("INSERT INTO archive.event SELECT * FROM public.event WHERE event_id <= 2")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM public.event WHERE event_id = 1")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let fan_in = PgFanInEventStore::new(event_store).with_archive(archive_pool);
+
+    let event_ids: Vec<PgEventId> = fan_in
+        .stream(&query!(ShoppingCartEvent))
+        .map(|event| event.unwrap().id())
+        .collect()
+        .await;
+    assert_eq!(event_ids, vec![1, 2, 3, 4]);
+    let event_ids: Vec<PgEventId> = fan_in
+        .stream(&query!(ShoppingCartEvent; cart_id == "c1"))
+        .map(|event| event.unwrap().id())
+        .collect()
+        .await;
+    assert_eq!(event_ids, vec![1, 3, 4]);
+    let result = fan_in
+        .append(
+            vec![added_event("p4", "c1")],
+            query!(ShoppingCartEvent; cart_id == "c1"),
+            4,
+        )
+        .await;
+    assert!(matches!(result, Err(Error::ReadOnly)));
+}
+
+async fn index_methods(pool: &PgPool, column_name: &str) -> Vec<(String, String)> {
+    sqlx::query_as(
+        r#"SELECT c.relname::text, am.amname::text
+           FROM pg_index i JOIN pg_class c ON c.oid = i.indexrelid JOIN pg_am am ON am.oid = c.relam
+           JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = i.indkey[0]
+           WHERE a.attname = $1 ORDER BY c.relname"#,
+    )
+    .bind(column_name)
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
+
+#[sqlx::test]
+async fn it_indexes_the_high_cardinality_identifiers(pool: PgPool) {
+    let event_store =
+        PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new_uninitialized(
+            pool.clone(),
+            Json::default(),
+        )
+        .with_identifier_index(ident!(#product_id), PgIdentifierIndex::BrinBloom)
+        .initialize()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        index_methods(&pool, "product_id").await,
+        vec![
+            ("idx_event_product_id".to_string(), "brin".to_string()),
+            (
+                "idx_event_sequence_product_id".to_string(),
+                "brin".to_string()
+            ),
+        ]
+    );
+    event_store
+        .append(
+            vec![added_event("p1", "c1"), added_event("p2", "c1")],
+            query!(ShoppingCartEvent; product_id == "p1"),
+            0,
+        )
+        .await
+        .unwrap();
+    let events: Vec<_> = event_store
+        .stream(&query!(ShoppingCartEvent; product_id == "p1"))
+        .collect()
+        .await;
+    assert_eq!(events.len(), 1);
+
+    PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(pool.clone(), Json::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        index_methods(&pool, "product_id").await,
+        vec![
+            ("idx_event_product_id".to_string(), "hash".to_string()),
+            (
+                "idx_event_sequence_product_id".to_string(),
+                "hash".to_string()
+            ),
+            (
+                "idx_event_sequence_product_id_event_id".to_string(),
+                "btree".to_string()
+            ),
+        ]
+    );
+}
+
+#[sqlx::test]
+async fn it_resumes_a_stream_after_a_transient_error(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap()
+    .with_retry_policy(PgRetryPolicy::new(3));
+    let events_count = 20_000;
+    sqlx::query(
+        r#"INSERT INTO event (event_id, event_type, cart_id, product_id, payload)
+           SELECT n, 'ShoppingCartAdded', 'cart_1', 'product_' || n,
+               convert_to(json_build_object('event_type', 'added', 'product_id', 'product_' || n, 'cart_id', 'cart_1', 'padding', repeat('x', 1000))::text, 'UTF8')
+           FROM generate_series(1, $1) AS n"#,
+    )
+    .bind(events_count)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+    let mut stream = event_store.stream(&query);
+    let first = stream.next().await.unwrap().unwrap();
+    sqlx::query(
+        "SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE datname = current_database() AND pid <> pg_backend_pid()",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let mut event_ids = vec![first.id()];
+    while let Some(event) = stream.next().await {
+        event_ids.push(event.unwrap().id());
+    }
+
+    assert_eq!(
+        event_ids,
+        (1..=events_count as PgEventId).collect::<Vec<_>>()
+    );
+}
+
+#[sqlx::test]
+async fn it_skips_the_retracted_events(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+    let persisted_events = event_store
+        .append(
+            vec![
+                added_event("product_1", "cart_1"),
+                added_event("product_2", "cart_1"),
+            ],
+            query.clone(),
+            0,
+        )
+        .await
+        .unwrap();
+    let retracted_id = persisted_events[0].id();
+
+    let retraction_id = event_store
+        .retract(retracted_id, "wrong product")
+        .await
+        .unwrap();
+    assert_eq!(
+        event_store
+            .retract(retracted_id, "wrong product")
+            .await
+            .unwrap(),
+        retraction_id
+    );
+    assert!(matches!(
+        event_store.retract(1000, "unknown").await,
+        Err(Error::UnknownEvent(1000))
+    ));
+
+    let stream_ids = |event_store: PgEventStore<ShoppingCartEvent, Json<ShoppingCartEvent>>| {
+        let query = query.clone();
+        async move {
+            event_store
+                .stream(&query)
+                .map(|event| event.unwrap().id())
+                .collect::<Vec<_>>()
+                .await
+        }
+    };
+    assert_eq!(
+        stream_ids(event_store.clone()).await,
+        vec![retracted_id, persisted_events[1].id()]
+    );
+    assert_eq!(
+        stream_ids(event_store.skip_retracted_events()).await,
+        vec![persisted_events[1].id()]
+    );
+}
+
+#[sqlx::test]
+async fn it_redacts_the_events_of_an_identifier_value(pool: PgPool) {
+    let database: String = sqlx::query_scalar("SELECT current_database()")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let snapshots_dir = std::env::temp_dir().join(format!("disintegrate-redaction-{database}"));
+    let snapshot_store = FileSnapshotStore::new(&snapshots_dir).await.unwrap();
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap()
+    .with_snapshot_store(snapshot_store.clone());
+    let events = vec![
+        added_event("product_1", "cart_1"),
+        added_event("product_2", "cart_2"),
+        removed_event("product_1", "cart_1"),
+    ];
+    insert_events(&pool, &events).await;
+    // the removal was appended before its version was increased.
+    sqlx::query("UPDATE event SET event_version = 1 WHERE event_id = 3")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let snapshot = Snapshot {
+        name: "Cart".to_string(),
+        query: "cart_1".to_string(),
+        version: 3,
+        payload: "{}".to_string(),
+    };
+    snapshot_store.store(snapshot).await.unwrap();
+    let stream = |cart_id: &'static str| {
+        let event_store = event_store.clone();
+        async move {
+            event_store
+                .stream(&query!(ShoppingCartEvent; cart_id == cart_id))
+                .map(|event| {
+                    let event = event.unwrap();
+                    (event.id(), event.into_inner())
+                })
+                .collect::<Vec<_>>()
+                .await
+        }
+    };
+    let cart_2 = stream("cart_2").await;
+
+    let redacted = event_store
+        .redact(ident!(#cart_id), "cart_1", |event| match event {
+            ShoppingCartEvent::Added { cart_id, .. } => ShoppingCartEvent::Added {
+                product_id: "redacted".to_string(),
+                cart_id,
+            },
+            ShoppingCartEvent::Removed { cart_id, .. } => ShoppingCartEvent::Removed {
+                product_id: "redacted".to_string(),
+                cart_id,
+            },
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(redacted, 2);
+    assert_eq!(
+        stream("cart_1").await,
+        vec![
+            (1, added_event("redacted", "cart_1")),
+            (3, removed_event("redacted", "cart_1")),
+        ]
+    );
+    assert_eq!(stream("cart_2").await, cart_2);
+    let versions: Vec<i32> =
+        sqlx::query_scalar("SELECT event_version FROM event ORDER BY event_id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(versions, vec![1, 1, 2]);
+    let snapshot: Option<Snapshot<PgEventId>> =
+        snapshot_store.load("Cart", "cart_1").await.unwrap();
+    assert_eq!(snapshot, None);
+    assert!(matches!(
+        event_store
+            .redact(ident!(#customer_id), "customer_1", |event| event)
+            .await,
+        Err(Error::InvalidQuery(_))
+    ));
+    tokio::fs::remove_dir_all(snapshots_dir).await.unwrap();
+}
+
+#[sqlx::test]
+async fn it_reports_the_event_types_not_deserializable_by_the_serde(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let events = vec![
+        added_event("product_1", "cart_1"),
+        removed_event("product_1", "cart_1"),
+        removed_event("product_2", "cart_1"),
+    ];
+    insert_events(&pool, &events).await;
+    event_store.verify_serde(10).await.unwrap();
+
+    sqlx::query("UPDATE event SET payload = $2 WHERE event_id = $1")
+        .bind(2)
+        .bind(br#"{"event_type": "removed", "product": "product_1"}"#.to_vec())
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let Err(Error::IncompatibleEvents(incompatible)) = event_store.verify_serde(10).await else {
+        panic!("the serde should be incompatible");
+    };
+    assert_eq!(incompatible.len(), 1);
+    assert_eq!(incompatible[0].event_type, "ShoppingCartRemoved");
+    assert_eq!(incompatible[0].event_id, 2);
+    assert!(event_store.verify_serde(1).await.is_ok());
+}
+
+#[sqlx::test]
+async fn it_reports_the_unindexed_identifiers_of_a_query(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let query = query!(ShoppingCartEvent; cart_id == "c1", product_id == "p1");
+
+    let analysis = event_store.analyze_query(&query).await.unwrap();
+    assert!(analysis.unindexed_identifiers.is_empty());
+    assert!(analysis.sql.starts_with("SELECT event_id, payload"));
+    assert!(analysis.total_cost > 0.0);
+
+    sqlx::query("DROP INDEX idx_event_cart_id")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let analysis = event_store.analyze_query(&query).await.unwrap();
+    assert_eq!(analysis.unindexed_identifiers, vec!["cart_id".to_string()]);
+    assert!(analysis.has_issues());
+    assert!(analysis
+        .to_string()
+        .contains("unindexed identifiers: cart_id"));
+}
+
+#[sqlx::test]
+async fn it_isolates_the_events_of_the_tenants_in_their_schemas(pool: PgPool) {
+    let tenant_store = |schema: &'static str| {
+        PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new_uninitialized(
+            pool.clone(),
+            Json::default(),
+        )
+        .with_schema(schema)
+        .initialize()
+    };
+    let tenant_a = tenant_store("tenant_a").await.unwrap();
+    let tenant_b = tenant_store("tenant_b").await.unwrap();
+
+    tenant_a
+        .append(
+            vec![added_event("p1", "c1"), added_event("p2", "c1")],
+            query!(ShoppingCartEvent; cart_id == "c1"),
+            0,
+        )
+        .await
+        .unwrap();
+    // the same cart and version of another tenant do not conflict.
+    tenant_b
+        .append(
+            vec![added_event("p3", "c1")],
+            query!(ShoppingCartEvent; cart_id == "c1"),
+            0,
+        )
+        .await
+        .unwrap();
+
+    let events: Vec<ShoppingCartEvent> = tenant_b
+        .stream(&query!(ShoppingCartEvent; cart_id == "c1"))
+        .map(|event| event.unwrap().into_inner())
+        .collect()
+        .await;
+    assert_eq!(events, vec![added_event("p3", "c1")]);
+    let tenant_a_events: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM tenant_a.event")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(tenant_a_events, 2);
+    let no_public_event_table: bool =
+        sqlx::query_scalar("SELECT to_regclass('public.event') IS NULL")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert!(no_public_event_table);
+}
+
+#[tokio::test]
+#[should_panic(expected = "invalid schema name")]
+async fn it_rejects_an_invalid_schema_name() {
+    let pool = PgPool::connect_lazy("postgres://localhost/disintegrate").unwrap();
+    let _ = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new_uninitialized(
+        pool,
+        Json::default(),
+    )
+    .with_schema("tenant\"; DROP TABLE event; --");
+}
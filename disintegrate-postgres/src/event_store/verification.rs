@@ -0,0 +1,84 @@
+//! Verification of the serde
+//!
+//! An incompatible refactor of the events, e.g. a renamed field without an upcaster, is otherwise discovered
+//! only when a state or an event listener reads an old payload. Verifying the serde at startup deserializes
+//! the most recent payloads of each event type with the current serde and reports the event types that fail.
+use disintegrate::Event;
+use disintegrate_serde::{PayloadInfo, Serde};
+use sqlx::Row;
+
+use crate::{Error, PgEventId, PgEventStore};
+
+/// An event that cannot be deserialized by the current serde.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncompatibleEvent {
+    /// The type the event is stored with.
+    pub event_type: String,
+    /// The ID of the most recent incompatible event of the type.
+    pub event_id: PgEventId,
+    /// The reason of the failure.
+    pub reason: String,
+}
+
+impl<E, S> PgEventStore<E, S>
+where
+    E: Event + Send + Sync,
+    S: Serde<E> + Send + Sync,
+{
+    /// Verifies that the serde can deserialize the events of the store, e.g. at startup.
+    ///
+    /// The serde is validated first, then the most recent payloads of each event type, including the aliases
+    /// of the renamed events, are deserialized: a payload fails if it cannot be deserialized or if it is
+    /// deserialized into an event of another type.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_size` - The number of the most recent payloads verified for each event type.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success, or `Error::IncompatibleEvents` with the most recent incompatible event of
+    /// each failing event type.
+    pub async fn verify_serde(&self, sample_size: usize) -> Result<(), Error> {
+        self.serde.validate_schema()?;
+        let sample_size = sample_size.clamp(1, i64::MAX as usize) as i64;
+        let mut incompatible = vec![];
+        for event_info in E::SCHEMA.events_info {
+            for event_type in event_info.event_types() {
+                let rows = sqlx::query(
+                    r#"SELECT event_id, payload, event_version FROM event WHERE event_id IN (
+                        SELECT event_id FROM event_sequence WHERE event_type = $1
+                        ORDER BY event_id DESC LIMIT $2
+                    ) ORDER BY event_id DESC"#,
+                )
+                .bind(event_type)
+                .bind(sample_size)
+                .fetch_all(&self.pool)
+                .await?;
+                let failure = rows.into_iter().find_map(|row| {
+                    let info = PayloadInfo {
+                        event_type,
+                        version: row.get::<i32, _>(2) as u32,
+                        event_id: Some(row.get(0)),
+                    };
+                    let reason = match self.serde.deserialize_with_info(row.get(1), info) {
+                        Ok(event) if event.name() == event_info.name => return None,
+                        Ok(event) => format!("deserialized as `{}`", event.name()),
+                        Err(err) => err.to_string(),
+                    };
+                    Some(IncompatibleEvent {
+                        event_type: event_type.to_string(),
+                        event_id: row.get(0),
+                        reason,
+                    })
+                });
+                incompatible.extend(failure);
+            }
+        }
+        if incompatible.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::IncompatibleEvents(incompatible))
+        }
+    }
+}
@@ -0,0 +1,306 @@
+//! # PostgreSQL Key-Value Projections
+//!
+//! This module stores simple read models, mapping a key (e.g. a domain identifier) to a serialized value,
+//! without designing a relational schema. The values of all the key-value stores are kept in the
+//! `projection_key_value` table, separated by the name of the store.
+//!
+//! A `KeyValueProjection` describes how the events update the value of a key. `PgKeyValueProjection` turns
+//! it into a `Projection`, which can be registered in the `PgProjectionRunner` and rebuilt like any other
+//! projection.
+use std::fmt::Display;
+use std::marker::PhantomData;
+
+use async_trait::async_trait;
+use disintegrate::{Event, IdempotencyKey, PersistedEvent, Projection, StreamQuery};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::{Error, PgEventId};
+
+#[cfg(test)]
+mod tests;
+
+/// A typed key-value store, backed by the `projection_key_value` table.
+#[derive(Debug)]
+pub struct PgKeyValueStore<K, V> {
+    pool: PgPool,
+    name: &'static str,
+    entry: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> Clone for PgKeyValueStore<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            name: self.name,
+            entry: PhantomData,
+        }
+    }
+}
+
+impl<K, V> PgKeyValueStore<K, V>
+where
+    K: Display + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Creates and initializes a new instance of `PgKeyValueStore`.
+    ///
+    /// # Arguments
+    ///
+    /// - `pool`: A PostgreSQL connection pool (`PgPool`) representing the database connection.
+    /// - `name`: The name of the store, separating its keys from the keys of the other stores.
+    ///
+    /// # Returns
+    ///
+    /// A new `PgKeyValueStore` instance.
+    pub async fn new(pool: PgPool, name: &'static str) -> Result<Self, Error> {
+        setup(&pool).await?;
+        Ok(Self::new_uninitialized(pool, name))
+    }
+
+    /// Creates a new instance of `PgKeyValueStore`.
+    ///
+    /// This constructor does not initialize the database. If you need to initialize the database,
+    /// use `PgKeyValueStore::new` instead.
+    ///
+    /// If you use this constructor, ensure that the database is already initialized.
+    /// Refer to the SQL files in the `key_value/sql` folder for the necessary schema.
+    ///
+    /// # Arguments
+    ///
+    /// - `pool`: A PostgreSQL connection pool (`PgPool`) representing the database connection.
+    /// - `name`: The name of the store, separating its keys from the keys of the other stores.
+    ///
+    /// # Returns
+    ///
+    /// A new `PgKeyValueStore` instance.
+    pub fn new_uninitialized(pool: PgPool, name: &'static str) -> Self {
+        Self {
+            pool,
+            name,
+            entry: PhantomData,
+        }
+    }
+
+    /// Returns the name of the store.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the value of a key, or `None` if the key is not in the store.
+    ///
+    /// # Arguments
+    ///
+    /// - `key`: The key of the value.
+    pub async fn get(&self, key: &K) -> Result<Option<V>, Error> {
+        Ok(self.entry(key).await?.map(|(value, _)| value))
+    }
+
+    /// Sets the value of a key, replacing the current one.
+    ///
+    /// # Arguments
+    ///
+    /// - `key`: The key of the value.
+    /// - `value`: The new value of the key.
+    pub async fn put(&self, key: &K, value: &V) -> Result<(), Error> {
+        self.store(key, value, None).await
+    }
+
+    /// Removes a key from the store.
+    ///
+    /// # Arguments
+    ///
+    /// - `key`: The key to remove.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `true` if the key was in the store.
+    pub async fn delete(&self, key: &K) -> Result<bool, Error> {
+        let result =
+            sqlx::query("DELETE FROM projection_key_value WHERE projection = $1 AND key = $2")
+                .bind(self.name)
+                .bind(key.to_string())
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Removes all the keys of the store.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of removed keys.
+    pub async fn clear(&self) -> Result<u64, Error> {
+        let result = sqlx::query("DELETE FROM projection_key_value WHERE projection = $1")
+            .bind(self.name)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Returns the value of a key together with the ID of the last event applied to it.
+    async fn entry(&self, key: &K) -> Result<Option<(V, Option<PgEventId>)>, Error> {
+        let row: Option<(String, Option<PgEventId>)> = sqlx::query_as(
+            "SELECT value::text, event_id FROM projection_key_value WHERE projection = $1 AND key = $2",
+        )
+        .bind(self.name)
+        .bind(key.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|(value, event_id)| Ok((serde_json::from_str(&value)?, event_id)))
+            .transpose()
+    }
+
+    async fn store(&self, key: &K, value: &V, event_id: Option<PgEventId>) -> Result<(), Error> {
+        sqlx::query(
+            r#"INSERT INTO projection_key_value (projection, key, value, event_id) VALUES ($1, $2, $3::jsonb, $4)
+               ON CONFLICT (projection, key) DO UPDATE SET value = $3::jsonb, event_id = $4, updated_at = now()"#,
+        )
+        .bind(self.name)
+        .bind(key.to_string())
+        .bind(serde_json::to_string(value)?)
+        .bind(event_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Describes a read model mapping a key to a value, updated by the events.
+pub trait KeyValueProjection<E: Event + Clone>: Send + Sync {
+    /// The type of the keys, usually a domain identifier.
+    type Key: Display + Send + Sync;
+    /// The type of the values.
+    type Value: Serialize + DeserializeOwned + Send + Sync;
+
+    /// Returns the unique name of the projection.
+    ///
+    /// The name is also the name of the key-value store and the ID of the event listener running the projection.
+    fn name(&self) -> &'static str;
+
+    /// Returns the stream query of the events applied to the read model.
+    fn query(&self) -> &StreamQuery<PgEventId, E>;
+
+    /// Returns the key updated by an event.
+    fn key(&self, event: &E) -> Self::Key;
+
+    /// Returns the new value of the key, or `None` to remove the key.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The current value of the key, or `None` if the key is not in the store.
+    /// * `event` - The event to apply.
+    fn update(&self, value: Option<Self::Value>, event: E) -> Option<Self::Value>;
+}
+
+/// A `Projection` storing the read model of a `KeyValueProjection` in a `PgKeyValueStore`.
+///
+/// The ID of the last event applied to each key is stored together with the value, so an event
+/// delivered more than once is applied only once.
+#[derive(Debug)]
+pub struct PgKeyValueProjection<P, E>
+where
+    E: Event + Clone,
+    P: KeyValueProjection<E>,
+{
+    projection: P,
+    store: PgKeyValueStore<P::Key, P::Value>,
+    event: PhantomData<fn() -> E>,
+}
+
+impl<P, E> PgKeyValueProjection<P, E>
+where
+    E: Event + Clone,
+    P: KeyValueProjection<E>,
+{
+    /// Creates and initializes a new instance of `PgKeyValueProjection`.
+    ///
+    /// # Arguments
+    ///
+    /// - `pool`: A PostgreSQL connection pool (`PgPool`) representing the database connection.
+    /// - `projection`: The projection whose read model is stored.
+    ///
+    /// # Returns
+    ///
+    /// A new `PgKeyValueProjection` instance.
+    pub async fn new(pool: PgPool, projection: P) -> Result<Self, Error> {
+        setup(&pool).await?;
+        Ok(Self::new_uninitialized(pool, projection))
+    }
+
+    /// Creates a new instance of `PgKeyValueProjection`, without initializing the database.
+    ///
+    /// See `PgKeyValueStore::new_uninitialized`.
+    ///
+    /// # Arguments
+    ///
+    /// - `pool`: A PostgreSQL connection pool (`PgPool`) representing the database connection.
+    /// - `projection`: The projection whose read model is stored.
+    ///
+    /// # Returns
+    ///
+    /// A new `PgKeyValueProjection` instance.
+    pub fn new_uninitialized(pool: PgPool, projection: P) -> Self {
+        let store = PgKeyValueStore::new_uninitialized(pool, projection.name());
+        Self {
+            projection,
+            store,
+            event: PhantomData,
+        }
+    }
+
+    /// Returns the store of the read model, to look up the values.
+    pub fn store(&self) -> PgKeyValueStore<P::Key, P::Value> {
+        self.store.clone()
+    }
+}
+
+#[async_trait]
+impl<P, E> Projection<PgEventId, E> for PgKeyValueProjection<P, E>
+where
+    E: Event + Clone + Send + Sync + 'static,
+    P: KeyValueProjection<E>,
+{
+    type Error = Error;
+
+    fn name(&self) -> &'static str {
+        self.projection.name()
+    }
+
+    fn query(&self) -> &StreamQuery<PgEventId, E> {
+        self.projection.query()
+    }
+
+    async fn apply(
+        &self,
+        event: PersistedEvent<PgEventId, E>,
+        _key: IdempotencyKey<PgEventId>,
+    ) -> Result<(), Self::Error> {
+        let event_id = event.id();
+        let key = self.projection.key(&event);
+        let value = match self.store.entry(&key).await? {
+            Some((_, Some(applied))) if applied >= event_id => return Ok(()),
+            Some((value, _)) => Some(value),
+            None => None,
+        };
+        match self.projection.update(value, event.into_inner()) {
+            Some(value) => self.store.store(&key, &value, Some(event_id)).await,
+            None => self.store.delete(&key).await.map(|_| ()),
+        }
+    }
+
+    async fn reset(&self) -> Result<(), Self::Error> {
+        self.store.clear().await.map(|_| ())
+    }
+}
+
+/// Initializes the table of the key-value stores.
+pub async fn setup(pool: &PgPool) -> Result<(), Error> {
+    let mut tx = crate::setup_lock::begin(pool).await?;
+    sqlx::query(include_str!("key_value/sql/table_projection_key_value.sql"))
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS projection_key_value (
+    projection text NOT NULL,
+    key text NOT NULL,
+    value jsonb NOT NULL,
+    event_id bigint,
+    updated_at TIMESTAMP DEFAULT now(),
+    PRIMARY KEY (projection, key)
+);
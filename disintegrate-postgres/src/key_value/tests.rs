@@ -0,0 +1,146 @@
+use super::*;
+use disintegrate::query;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, disintegrate::Event)]
+#[serde(tag = "event_type", rename_all = "snake_case")]
+enum OrderEvent {
+    OrderPlaced {
+        #[id]
+        order_id: String,
+    },
+    NoteAdded {
+        #[id]
+        order_id: String,
+        note: String,
+    },
+    OrderCancelled {
+        #[id]
+        order_id: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct OrderSummary {
+    notes: Vec<String>,
+}
+
+struct OrderSummaries {
+    query: StreamQuery<PgEventId, OrderEvent>,
+}
+
+impl KeyValueProjection<OrderEvent> for OrderSummaries {
+    type Key = String;
+    type Value = OrderSummary;
+
+    fn name(&self) -> &'static str {
+        "order_summaries"
+    }
+
+    fn query(&self) -> &StreamQuery<PgEventId, OrderEvent> {
+        &self.query
+    }
+
+    fn key(&self, event: &OrderEvent) -> String {
+        match event {
+            OrderEvent::OrderPlaced { order_id }
+            | OrderEvent::NoteAdded { order_id, .. }
+            | OrderEvent::OrderCancelled { order_id } => order_id.clone(),
+        }
+    }
+
+    fn update(&self, value: Option<OrderSummary>, event: OrderEvent) -> Option<OrderSummary> {
+        match event {
+            OrderEvent::OrderPlaced { .. } => Some(OrderSummary { notes: vec![] }),
+            OrderEvent::NoteAdded { note, .. } => value.map(|mut summary| {
+                summary.notes.push(note);
+                summary
+            }),
+            OrderEvent::OrderCancelled { .. } => None,
+        }
+    }
+}
+
+fn note_added(order_id: &str, note: &str) -> OrderEvent {
+    OrderEvent::NoteAdded {
+        order_id: order_id.to_string(),
+        note: note.to_string(),
+    }
+}
+
+#[sqlx::test]
+async fn it_puts_gets_and_deletes_the_values(pool: PgPool) {
+    let store = PgKeyValueStore::<String, OrderSummary>::new(pool.clone(), "summaries")
+        .await
+        .unwrap();
+    let other_store = PgKeyValueStore::<String, OrderSummary>::new_uninitialized(pool, "other");
+    let summary = OrderSummary {
+        notes: vec!["ring the bell".to_string()],
+    };
+
+    store.put(&"o1".to_string(), &summary).await.unwrap();
+
+    assert_eq!(store.get(&"o1".to_string()).await.unwrap(), Some(summary));
+    assert_eq!(other_store.get(&"o1".to_string()).await.unwrap(), None);
+    assert!(store.delete(&"o1".to_string()).await.unwrap());
+    assert!(!store.delete(&"o1".to_string()).await.unwrap());
+    assert_eq!(store.get(&"o1".to_string()).await.unwrap(), None);
+}
+
+#[sqlx::test]
+async fn it_applies_the_events_to_the_values_once(pool: PgPool) {
+    let projection = PgKeyValueProjection::new(
+        pool,
+        OrderSummaries {
+            query: query!(OrderEvent),
+        },
+    )
+    .await
+    .unwrap();
+    let key = IdempotencyKey::new("order_summaries", 0);
+    let order_placed = OrderEvent::OrderPlaced {
+        order_id: "o1".to_string(),
+    };
+
+    for (id, event) in [
+        (1, order_placed.clone()),
+        (2, note_added("o1", "ring the bell")),
+        (2, note_added("o1", "ring the bell")),
+        (3, note_added("o2", "leave at the door")),
+    ] {
+        projection
+            .apply(PersistedEvent::new(id, event), key)
+            .await
+            .unwrap();
+    }
+
+    let store = projection.store();
+    assert_eq!(
+        store.get(&"o1".to_string()).await.unwrap(),
+        Some(OrderSummary {
+            notes: vec!["ring the bell".to_string()]
+        })
+    );
+    assert_eq!(store.get(&"o2".to_string()).await.unwrap(), None);
+
+    projection
+        .apply(
+            PersistedEvent::new(
+                4,
+                OrderEvent::OrderCancelled {
+                    order_id: "o1".to_string(),
+                },
+            ),
+            key,
+        )
+        .await
+        .unwrap();
+    assert_eq!(store.get(&"o1".to_string()).await.unwrap(), None);
+
+    projection
+        .apply(PersistedEvent::new(5, order_placed), key)
+        .await
+        .unwrap();
+    projection.reset().await.unwrap();
+    assert_eq!(store.get(&"o1".to_string()).await.unwrap(), None);
+}
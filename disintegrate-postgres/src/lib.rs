@@ -1,14 +1,48 @@
 //! # PostgreSQL Disintegrate Backend Library
+mod archiver;
 mod error;
 mod event_store;
+mod key_value;
 #[cfg(feature = "listener")]
 mod listener;
+mod metadata;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod namespace;
+#[cfg(feature = "otel")]
+mod otel;
+mod outbox;
+mod scheduler;
+mod schema;
+mod setup_lock;
 mod snapshotter;
+#[cfg(feature = "pg-test")]
+mod testing;
 
-pub use crate::event_store::PgEventStore;
+pub use crate::archiver::PgEventArchiver;
+pub use crate::event_store::{
+    IncompatibleEvent, PgEventStore, PgFanInEventStore, PgIdentifierIndex, PgRetryPolicy,
+    QueryAnalysis, DEFAULT_APPEND_BATCH_SIZE, DEFAULT_STREAM_BATCH_SIZE,
+    DENY_LIST_REFRESH_INTERVAL, RETRACT_EVENT_TYPE,
+};
+pub use crate::key_value::{KeyValueProjection, PgKeyValueProjection, PgKeyValueStore};
 #[cfg(feature = "listener")]
-pub use crate::listener::{PgEventListener, PgEventListenerConfig};
-pub use crate::snapshotter::PgSnapshotter;
+pub use crate::listener::{
+    AlwaysRetry, EventFeed, EventNotification, ListenerCheckpoint, ListenerCheckpoints,
+    ListenerDescription, ListenerProgressEvent, MaxAttempts, PgEventBroadcaster, PgEventListener,
+    PgEventListenerConfig, PgHealthProbe, PgListenerAssignment, PgProjectionRebuilder,
+    PgProjectionRunner, PgReplayer, ProbeMetrics, ProbeReport, RedeliveryWindow, ReplayProgress,
+    Retry, RetryContext, RetryDecision, RetryMetrics, SkipAfter, HEARTBEAT_EVENT_TYPE,
+};
+pub use crate::metadata::{StoreMetadata, SCHEMA_VERSION};
+pub use crate::namespace::PgEventStoreNamespace;
+pub use crate::outbox::{PgOutboxRelay, Publisher};
+pub use crate::scheduler::{PgRecurringRunStore, PgScheduler};
+pub use crate::schema::{schema_pool, SCHEMA_POOL_MAX_CONNECTIONS};
+pub use crate::setup_lock::SETUP_LOCK_TIMEOUT;
+pub use crate::snapshotter::{PgSnapshotStore, PgSnapshotter, QuarantinedSnapshot};
+#[cfg(feature = "pg-test")]
+pub use crate::testing::PgTestDatabase;
 use disintegrate::{DecisionMaker, Event, EventSourcedStateStore, SnapshotConfig, WithSnapshot};
 use disintegrate_serde::Serde;
 pub use error::Error;
@@ -33,8 +67,8 @@ pub type WithPgSnapshot = WithSnapshot<PgEventId, PgSnapshotter>;
 ///
 /// A `PgDecisionMaker` with snapshotting configured according to the provided `snapshot_config`.
 pub fn decision_maker<
-    E: Event + Send + Sync + Clone,
-    S: Serde<E> + Clone + Sync + Send,
+    E: Event + Send + Sync + Clone + 'static,
+    S: Serde<E> + Clone + Sync + Send + 'static,
     SN: SnapshotConfig + Clone,
 >(
     event_store: PgEventStore<E, S>,
@@ -4,25 +4,63 @@
 //! It allows listening events when they are persisted in the event store.
 //! It assures that the events are delivered at least once, so the implementation
 //! of the `EventListener` trait should handle duplicated events delivery in case of failures.
+mod assignment;
+mod broadcast;
+mod catch_up;
+mod checkpoint;
+mod description;
+mod notification;
+mod probe;
+mod progress;
+mod projection;
+mod replay;
+mod retry;
 #[cfg(test)]
 mod tests;
 
+pub use assignment::PgListenerAssignment;
+pub use broadcast::{EventFeed, PgEventBroadcaster};
+pub use checkpoint::{ListenerCheckpoint, ListenerCheckpoints, RedeliveryWindow};
+pub use description::ListenerDescription;
+pub(crate) use notification::notification_channel;
+pub use notification::EventNotification;
+pub use probe::{PgHealthProbe, ProbeMetrics, ProbeReport, HEARTBEAT_EVENT_TYPE};
+pub use progress::ListenerProgressEvent;
+pub use projection::{PgProjectionRebuilder, PgProjectionRunner};
+pub use replay::{PgReplayer, ReplayProgress};
+pub use retry::{
+    AlwaysRetry, MaxAttempts, Retry, RetryContext, RetryDecision, RetryMetrics, SkipAfter,
+};
+
 use crate::{Error, PgEventId};
+use assignment::{AssignedListeners, ListenerShard};
 use async_trait::async_trait;
-use disintegrate::{Event, EventListener, EventStore, StreamQuery};
+use catch_up::{CatchUpGate, CatchUpTicket, Throttle};
+use disintegrate::{
+    BoxDynError, DecisionContext, ErasedEventListener, Event, EventListener, EventStore,
+    Identifier, PersistedEvent, Retraction, StreamQuery,
+};
 use disintegrate_serde::Serde;
 use futures::future::join_all;
+use futures::stream::FuturesUnordered;
 use futures::{try_join, Future, StreamExt};
 use sqlx::{PgPool, Postgres, Row, Transaction};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::error::Error as StdError;
+use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::watch;
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 
-use crate::event_store::PgEventStore;
+use crate::event_store::{AppendObserver, PgEventStore, PgFanInEventStore};
+
+/// The default maximum age of a reserved event ID awaited by the event listeners.
+const DEFAULT_GAP_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// PostgreSQL event listener implementation.
 pub struct PgEventListener<E, S>
@@ -34,6 +72,29 @@ where
     event_store: PgEventStore<E, S>,
     intialize: bool,
     shutdown_token: CancellationToken,
+    catch_up_gate: Arc<CatchUpGate>,
+    assignment: Option<PgListenerAssignment>,
+    assigned_listeners: Arc<AssignedListeners>,
+    notify_callbacks: Vec<NotifyCallback>,
+}
+
+/// A callback receiving the notifications of the inserted events.
+type NotifyCallback = Box<dyn Fn(&EventNotification) + Send + Sync>;
+
+impl<E, S> std::fmt::Debug for PgEventListener<E, S>
+where
+    E: Event + Clone,
+    S: Serde<E> + Send + Sync,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PgEventListener")
+            .field("listeners", &self.executors.len())
+            .field("event_store", &self.event_store)
+            .field("intialize", &self.intialize)
+            .field("assignment", &self.assignment)
+            .field("notify_callbacks", &self.notify_callbacks.len())
+            .finish_non_exhaustive()
+    }
 }
 
 impl<E, S> PgEventListener<E, S>
@@ -56,6 +117,10 @@ where
             executors: vec![],
             shutdown_token: CancellationToken::new(),
             intialize: true,
+            catch_up_gate: Arc::new(CatchUpGate::default()),
+            assignment: None,
+            assigned_listeners: Arc::new(AssignedListeners::default()),
+            notify_callbacks: vec![],
         }
     }
 
@@ -75,6 +140,23 @@ where
         self
     }
 
+    /// Runs only the event listeners assigned to this instance of the fleet.
+    ///
+    /// The event listeners are assigned to the live instances using consistent hashing, and are
+    /// rebalanced automatically when an instance joins or leaves the fleet.
+    ///
+    /// # Parameters
+    ///
+    /// * `assignment`: A `PgListenerAssignment` instance identifying this instance in the fleet.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListener` instance with the assignment set.
+    pub fn with_assignment(mut self, assignment: PgListenerAssignment) -> Self {
+        self.assignment = Some(assignment);
+        self
+    }
+
     /// Registers an event listener to the `PgEventListener`.
     ///
     /// # Parameters
@@ -87,22 +169,110 @@ where
     /// The updated `PgEventListener` instance with the registered event handler.
     pub fn register_listener<QE>(
         mut self,
-        event_listener: impl EventListener<PgEventId, QE> + 'static,
+        event_listener: impl EventListener<PgEventId, QE, Error: StdError + Send + Sync + 'static>
+            + 'static,
         config: PgEventListenerConfig,
     ) -> Self
     where
         QE: TryFrom<E> + Into<E> + Event + Send + Sync + Clone + 'static,
         <QE as TryFrom<E>>::Error: StdError + Send + Sync,
     {
-        self.executors.push(Box::new(PgEventListerExecutor::new(
-            self.event_store.clone(),
-            event_listener,
-            self.shutdown_token.clone(),
-            config,
-        )));
+        self.executors.push(Box::new(
+            PgEventListerExecutor::new(
+                self.event_store.clone(),
+                event_listener,
+                self.shutdown_token.clone(),
+                config,
+            )
+            .with_catch_up_gate(self.catch_up_gate.clone())
+            .with_assigned_listeners(self.assigned_listeners.clone()),
+        ));
         self
     }
 
+    /// Registers an event listener handling the events of the event store.
+    ///
+    /// This is a shorthand of `register_listener` for the common case of a listener of the same
+    /// event type of the event store, which does not need any type annotation.
+    ///
+    /// # Parameters
+    ///
+    /// * `event_listener`: An implementation of the `EventListener` trait for the event type `E`.
+    /// * `config`: A `PgEventListenerConfig` instance representing the configuration for the event listener.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListener` instance with the registered event handler.
+    pub fn register(
+        self,
+        event_listener: impl EventListener<PgEventId, E, Error: StdError + Send + Sync + 'static>
+            + 'static,
+        config: PgEventListenerConfig,
+    ) -> Self {
+        self.register_listener::<E>(event_listener, config)
+    }
+
+    /// Registers a callback receiving the notifications of the inserted events matching a query.
+    ///
+    /// Unlike an event listener, the callback has no checkpoint: it is called only for the events notified
+    /// while the `PgEventListener` is running, at most once, and without their payload. It suits the cheap
+    /// triggers, e.g. the invalidation of a cache, and must not block.
+    ///
+    /// # Parameters
+    ///
+    /// * `query`: The stream query of the notified events.
+    /// * `callback`: The function called with the notification of each matching event.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListener` instance with the registered callback.
+    pub fn on_notify<QE>(
+        mut self,
+        query: StreamQuery<PgEventId, QE>,
+        callback: impl Fn(&EventNotification) + Send + Sync + 'static,
+    ) -> Self
+    where
+        QE: Event + Clone + Send + Sync + 'static,
+    {
+        self.notify_callbacks
+            .push(Box::new(move |notification: &EventNotification| {
+                if notification.matches(&query) {
+                    callback(notification);
+                }
+            }));
+        self
+    }
+
+    /// Returns the descriptions of the registered event listeners, in registration order.
+    ///
+    /// The descriptions expose the queries and the configurations of the event listeners, e.g. to log
+    /// them at startup or to serve them from a debugging endpoint.
+    pub fn listeners(&self) -> Vec<ListenerDescription> {
+        self.executors
+            .iter()
+            .map(|executor| executor.describe())
+            .collect()
+    }
+
+    /// Returns the counters of the retry decisions of a registered event listener.
+    ///
+    /// Each registered event listener has its own counters, even if it shares its configuration with
+    /// other listeners. They can be read while the listener runs.
+    ///
+    /// # Parameters
+    ///
+    /// * `listener_id`: The ID of the event listener.
+    ///
+    /// # Returns
+    ///
+    /// The counters of the event listener, or `None` if no event listener with this ID is registered.
+    pub fn retry_metrics(&self, listener_id: &str) -> Option<Arc<RetryMetrics>> {
+        self.executors
+            .iter()
+            .find(|executor| executor.describe().id == listener_id)
+            .map(|executor| executor.retry_metrics())
+    }
+
     /// Starts the listener process for all registered event listeners.
     ///
     /// # Returns
@@ -114,6 +284,26 @@ where
         }
         let mut handles = vec![];
         let mut wakers = vec![];
+        if let Some(assignment) = self.assignment {
+            let pool = self.event_store.pool.clone();
+            let assigned_listeners = self.assigned_listeners.clone();
+            let shutdown = self.shutdown_token.clone();
+            assignment.refresh(&pool, &assigned_listeners).await?;
+            handles.push(tokio::spawn(async move {
+                let mut heartbeat = tokio::time::interval(assignment.heartbeat_interval());
+                heartbeat.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+                loop {
+                    tokio::select! {
+                        _ = heartbeat.tick() => {
+                            if let Err(Error::Database(err @ sqlx::Error::PoolClosed)) = assignment.refresh(&pool, &assigned_listeners).await {
+                                return Err(Error::Database(err));
+                            }
+                        }
+                        _ = shutdown.cancelled() => return assignment.leave(&pool).await,
+                    }
+                }
+            }));
+        }
         for executor in self.executors {
             executor.init().await?;
             let (waker, task) = executor.run();
@@ -122,13 +312,15 @@ where
             }
             handles.push(task);
         }
-        if !wakers.is_empty() {
+        if !wakers.is_empty() || !self.notify_callbacks.is_empty() {
+            let notify_callbacks = self.notify_callbacks;
             let pool = self.event_store.pool.clone();
+            let channel = notification_channel(&pool).await?;
             let shutdown = self.shutdown_token.clone();
             let watch_new_events = tokio::spawn(async move {
                 loop {
                     let mut listener = sqlx::postgres::PgListener::connect_with(&pool).await?;
-                    listener.listen("new_events").await?;
+                    listener.listen(&channel).await?;
                     loop {
                         tokio::select! {
                             msg = listener.try_recv() => {
@@ -137,6 +329,11 @@ where
                                         for waker in &wakers {
                                             waker.wake(notification.payload());
                                         }
+                                        if let Some(notification) = EventNotification::parse::<E>(notification.payload()) {
+                                            for callback in &notify_callbacks {
+                                                callback(&notification);
+                                            }
+                                        }
                                     },
                                     Ok(None) => {},
                                     Err(err @ sqlx::Error::PoolClosed) => return Err(Error::Database(err)),
@@ -177,9 +374,20 @@ where
     }
 }
 
+/// The outcome of a successful run of an event listener.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandledEvents {
+    last_processed_event_id: PgEventId,
+    caught_up: bool,
+}
+
 #[derive(Debug)]
 pub struct PgEventListenerError {
     last_processed_event_id: PgEventId,
+    failed_event_id: Option<PgEventId>,
+    source: BoxDynError,
+    /// The decision of the retry policy, when it has already been applied to the failed event.
+    decision: Option<RetryDecision>,
 }
 
 /// PostgreSQL listener Configuration
@@ -190,11 +398,59 @@ pub struct PgEventListenerError {
 ///   listener should poll for new events from the event store. This determines how frequently the
 ///   event handler will handles new events.
 /// * `notifier_enabled`: The `notifier_enabled` indicates if the listener is configured to handle events in "real time".
+/// * `excluded_events`: The names of the events excluded from the listener query at runtime.
+/// * `progress_enabled`: The `progress_enabled` indicates if the listener publishes its progress events in the event store.
+/// * `retractions_enabled`: The `retractions_enabled` indicates if the listener receives the retractions of the events.
+/// * `max_events_per_second`: The maximum number of events handled per second, outside the off-peak hours.
+/// * `off_peak_hours`: The UTC hours during which the events are handled without rate limit.
+/// * `priority`: The priority of the listener: the listeners with a lower priority wait for the ones with a higher
+///   priority to catch up.
+/// * `retry`: The policy deciding whether the listener keeps retrying after a failure.
+/// * `concurrent_events`: The names of the events that can be handled concurrently.
+/// * `max_concurrency`: The maximum number of events handled concurrently.
+/// * `partition`: The domain identifier partitioning the events handled concurrently, and the number of partitions.
+/// * `shards`: The domain identifier sharding the events across the processes, and the number of shards.
+/// * `gap_timeout`: How long the listener waits for the events whose IDs are reserved but not yet committed.
 #[derive(Clone)]
 pub struct PgEventListenerConfig {
     poll: Duration,
     fetch_size: usize,
     notifier_enabled: bool,
+    excluded_events: Vec<String>,
+    progress_enabled: bool,
+    retractions_enabled: bool,
+    max_events_per_second: Option<u32>,
+    off_peak_hours: Option<(u8, u8)>,
+    priority: u8,
+    retry: Arc<dyn Retry>,
+    concurrent_events: Vec<String>,
+    max_concurrency: usize,
+    partition: Option<(Identifier, usize)>,
+    shards: Option<(Identifier, u32)>,
+    gap_timeout: Option<Duration>,
+    archives: Vec<PgPool>,
+}
+
+impl std::fmt::Debug for PgEventListenerConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PgEventListenerConfig")
+            .field("poll", &self.poll)
+            .field("fetch_size", &self.fetch_size)
+            .field("notifier_enabled", &self.notifier_enabled)
+            .field("excluded_events", &self.excluded_events)
+            .field("progress_enabled", &self.progress_enabled)
+            .field("retractions_enabled", &self.retractions_enabled)
+            .field("max_events_per_second", &self.max_events_per_second)
+            .field("off_peak_hours", &self.off_peak_hours)
+            .field("priority", &self.priority)
+            .field("concurrent_events", &self.concurrent_events)
+            .field("max_concurrency", &self.max_concurrency)
+            .field("partition", &self.partition)
+            .field("shards", &self.shards)
+            .field("gap_timeout", &self.gap_timeout)
+            .field("archives", &self.archives)
+            .finish_non_exhaustive()
+    }
 }
 
 impl PgEventListenerConfig {
@@ -212,6 +468,19 @@ impl PgEventListenerConfig {
             poll,
             fetch_size: usize::MAX,
             notifier_enabled: false,
+            excluded_events: vec![],
+            progress_enabled: false,
+            retractions_enabled: false,
+            max_events_per_second: None,
+            off_peak_hours: None,
+            priority: 0,
+            retry: Arc::new(AlwaysRetry),
+            concurrent_events: vec![],
+            max_concurrency: 1,
+            partition: None,
+            shards: None,
+            gap_timeout: Some(DEFAULT_GAP_TIMEOUT),
+            archives: vec![],
         }
     }
 
@@ -240,56 +509,390 @@ impl PgEventListenerConfig {
         self.notifier_enabled = true;
         self
     }
+
+    /// Excludes the specified events from the event listener query.
+    ///
+    /// The excluded events augment the ones already excluded by the listener query.
+    /// The event names are validated against the event schema when the listener starts.
+    ///
+    /// # Parameters
+    ///
+    /// * `excluded_events`: The names of the events to exclude.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListenerConfig` instance with the excluded events set.
+    pub fn exclude_events<I, T>(mut self, excluded_events: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.excluded_events
+            .extend(excluded_events.into_iter().map(Into::into));
+        self
+    }
+
+    /// Enables the publication of the listener progress events.
+    ///
+    /// When enabled, the event listener appends a `ListenerProgressEvent` to the event store when it reaches a milestone,
+    /// e.g. `ProjectionRebuilt` when it has processed all the events starting from the beginning of the store.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListenerConfig` instance with the progress events enabled.
+    pub fn with_progress_events(mut self) -> Self {
+        self.progress_enabled = true;
+        self
+    }
+
+    /// Delivers the retractions of the events of the listener query to `EventListener::handle_retraction`.
+    ///
+    /// The retractions are delivered in order with the events, and move the checkpoint of the listener
+    /// like the events do.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListenerConfig` instance with the retractions enabled.
+    pub fn with_retractions(mut self) -> Self {
+        self.retractions_enabled = true;
+        self
+    }
+
+    /// Limits the number of events handled per second, so that the catch-up after a long downtime
+    /// does not saturate the database.
+    ///
+    /// # Parameters
+    ///
+    /// * `max_events_per_second`: The maximum number of events handled per second.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListenerConfig` instance with the rate limit set.
+    pub fn max_events_per_second(mut self, max_events_per_second: u32) -> Self {
+        self.max_events_per_second = Some(max_events_per_second);
+        self
+    }
+
+    /// Sets the off-peak hours, in UTC, during which the events are handled without rate limit.
+    ///
+    /// The off-peak hours may wrap around midnight, e.g. from `22` to `6`.
+    ///
+    /// # Parameters
+    ///
+    /// * `start`: The hour the off-peak period starts at (inclusive).
+    /// * `end`: The hour the off-peak period ends at (exclusive).
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListenerConfig` instance with the off-peak hours set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start` or `end` is not an hour of the day, from `0` to `23`.
+    pub fn off_peak_hours(mut self, start: u8, end: u8) -> Self {
+        assert!(
+            start < 24 && end < 24,
+            "the off-peak hours must be between 0 and 23"
+        );
+        self.off_peak_hours = Some((start, end));
+        self
+    }
+
+    /// Sets the priority of the event listener.
+    ///
+    /// The event listeners registered in the same `PgEventListener` with a lower priority do not handle
+    /// events until the ones with a higher priority have caught up. The default priority is `0`.
+    ///
+    /// # Parameters
+    ///
+    /// * `priority`: The priority of the event listener.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListenerConfig` instance with the priority set.
+    pub fn priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Sets the retry policy of the event listener.
+    ///
+    /// The policy is asked whether to retry every time the listener fails to handle an event.
+    /// When it aborts, the listener stops with an `Error::ListenerAborted` error. By default,
+    /// the listener retries forever.
+    ///
+    /// # Parameters
+    ///
+    /// * `retry`: The retry policy, e.g. `MaxAttempts(5)` or a closure taking a `RetryContext`.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListenerConfig` instance with the retry policy set.
+    pub fn retry(mut self, retry: impl Retry + 'static) -> Self {
+        self.retry = Arc::new(retry);
+        self
+    }
+
+    /// Allows the event listener to handle the specified events concurrently.
+    ///
+    /// The consecutive events of these types are handled concurrently, up to `max_concurrency` at a time: the
+    /// next event starts as soon as any of the events in flight has been handled. The other events are handled
+    /// one at a time, after all the previous events have been handled. The checkpoint advances only over the
+    /// events whose predecessors have all been handled, and a failure is checkpointed once the other events in
+    /// flight have been handled, which are then delivered again.
+    ///
+    /// # Parameters
+    ///
+    /// * `concurrent_events`: The names of the events that do not depend on the order of delivery.
+    /// * `max_concurrency`: The maximum number of events handled concurrently.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListenerConfig` instance with the concurrent events set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_concurrency` is zero.
+    pub fn handle_concurrently<I, T>(mut self, concurrent_events: I, max_concurrency: usize) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        assert!(
+            max_concurrency > 0,
+            "the maximum concurrency must be greater than zero"
+        );
+        self.concurrent_events
+            .extend(concurrent_events.into_iter().map(Into::into));
+        self.max_concurrency = max_concurrency;
+        self
+    }
+
+    /// Handles the events concurrently, partitioned by the value of a domain identifier.
+    ///
+    /// The events are distributed across `partitions` partitions by the hash of the identifier value: the
+    /// events of a partition are handled one at a time, in order, while the events of different partitions
+    /// are handled concurrently. The order of the events is then preserved for each value of the identifier.
+    /// The events without the identifier are handled after all the previous events have been handled.
+    /// The identifier is validated against the event schema when the listener starts.
+    ///
+    /// # Parameters
+    ///
+    /// * `ident`: The domain identifier partitioning the events, e.g. `ident!(#account_id)`.
+    /// * `partitions`: The number of partitions, that is the maximum number of events handled concurrently.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListenerConfig` instance with the partitioning set.
+    pub fn partitioned_by(mut self, ident: Identifier, partitions: usize) -> Self {
+        self.partition = Some((ident, partitions.max(1)));
+        self
+    }
+
+    /// Shards the event listener by the value of a domain identifier, so that several processes share the work.
+    ///
+    /// The hash space of the identifier values is split into `shards` ranges, each one with its own checkpoint
+    /// in the `event_listener` table, identified by `<listener id>#<index>/<shards>` and recording its hash range.
+    /// Every process runs all the shards, and each shard is locked by a single process at a time: the processes
+    /// compete for the shards, or split them with a `PgListenerAssignment`. The order of the events is preserved
+    /// for each value of the identifier. The events without the identifier and the retractions are handled
+    /// by the first shard.
+    ///
+    /// When the shards are changed, the new checkpoints start from the lowest checkpoint of the event listener,
+    /// so some events may be delivered again. The identifier is validated against the event schema when
+    /// the listener starts.
+    ///
+    /// # Parameters
+    ///
+    /// * `ident`: The domain identifier sharding the events, e.g. `ident!(#account_id)`.
+    /// * `shards`: The number of shards, that is the maximum number of processes sharing the work.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListenerConfig` instance with the sharding set.
+    pub fn sharded_by(mut self, ident: Identifier, shards: u32) -> Self {
+        self.shards = Some((ident, shards.max(1)));
+        self
+    }
+
+    /// Sets how long the event listener waits for the events whose IDs are reserved but not yet committed.
+    ///
+    /// The event IDs are reserved before the append transaction starts, so a transaction may commit its events
+    /// after a concurrent transaction committed events with greater IDs. The listener stops before the first
+    /// event following a reserved ID, and handles it once the gap is closed: when the reserving transaction
+    /// commits, fails, or the reservation is older than the timeout, e.g. because its process crashed.
+    /// The default timeout is 10 seconds.
+    ///
+    /// # Parameters
+    ///
+    /// * `gap_timeout`: The maximum age of a reserved event ID awaited by the listener.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListenerConfig` instance with the gap timeout set.
+    pub fn gap_timeout(mut self, gap_timeout: Duration) -> Self {
+        self.gap_timeout = Some(gap_timeout);
+        self
+    }
+
+    /// Disables the gap detection: the event listener does not wait for the reserved event IDs,
+    /// and may miss the events committed after events with greater IDs.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListenerConfig` instance without the gap detection.
+    pub fn without_gap_detection(mut self) -> Self {
+        self.gap_timeout = None;
+        self
+    }
+
+    /// Reads the events also from an archive database, e.g. for a reporting listener that needs
+    /// the full history after the old events have been archived.
+    ///
+    /// The events of the event store and of the archives are merged in event ID order, as done by
+    /// `PgFanInEventStore`. The checkpoint of the listener is still stored in the event store database.
+    ///
+    /// # Parameters
+    ///
+    /// * `pool`: The PostgreSQL connection pool of the archive database.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgEventListenerConfig` instance with the archive added.
+    pub fn with_archive(mut self, pool: PgPool) -> Self {
+        self.archives.push(pool);
+        self
+    }
 }
 
 #[async_trait]
 trait EventListenerExecutor<E: Event + Clone> {
     async fn init(&self) -> Result<(), Error>;
+    fn describe(&self) -> ListenerDescription;
+    fn retry_metrics(&self) -> Arc<RetryMetrics>;
     fn run(&self) -> (Option<ExecutorWaker<E>>, JoinHandle<Result<(), Error>>);
 }
 
-struct PgEventListerExecutor<L, QE, E, S>
+struct PgEventListerExecutor<QE, E, S>
 where
     QE: TryFrom<E> + Event + Send + Sync + Clone,
     <QE as TryFrom<E>>::Error: Send + Sync,
     E: Event + Clone + Sync + Send,
     S: Serde<E> + Clone + Send + Sync,
-    L: EventListener<PgEventId, QE>,
 {
     event_store: PgEventStore<E, S>,
-    event_handler: Arc<L>,
+    sources: PgFanInEventStore<E, S>,
+    event_handler: Arc<dyn EventListener<PgEventId, QE, Error = BoxDynError>>,
+    query: StreamQuery<PgEventId, QE>,
+    unknown_excluded_events: Vec<String>,
     config: PgEventListenerConfig,
+    retry_metrics: Arc<RetryMetrics>,
+    shard: Option<ListenerShard>,
+    checkpoint_id: String,
     wake_channel: (watch::Sender<bool>, watch::Receiver<bool>),
+    rebuilding: Arc<AtomicBool>,
+    catch_up: CatchUpTicket,
+    throttle: Throttle,
+    assigned_listeners: Arc<AssignedListeners>,
     shutdown_token: CancellationToken,
     _event_store_events: PhantomData<E>,
     _event_listener_events: PhantomData<QE>,
 }
 
-impl<L, QE, E, S> PgEventListerExecutor<L, QE, E, S>
+impl<QE, E, S> PgEventListerExecutor<QE, E, S>
 where
     E: Event + Clone + Sync + Send + 'static,
     S: Serde<E> + Clone + Send + Sync + 'static,
     QE: TryFrom<E> + Event + 'static + Send + Sync + Clone,
     <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
-    L: EventListener<PgEventId, QE> + 'static,
 {
     pub fn new(
         event_store: PgEventStore<E, S>,
-        event_handler: L,
+        event_handler: impl EventListener<PgEventId, QE, Error: StdError + Send + Sync + 'static>
+            + 'static,
         shutdown_token: CancellationToken,
         config: PgEventListenerConfig,
     ) -> Self {
+        let mut excluded_events = vec![];
+        let mut unknown_excluded_events = vec![];
+        for name in &config.excluded_events {
+            match QE::SCHEMA.events.iter().find(|event| *event == name) {
+                Some(event) => excluded_events.push(*event),
+                None => unknown_excluded_events.push(name.clone()),
+            }
+        }
+        let query = event_handler
+            .query()
+            .clone()
+            .extend_excluded_events(&excluded_events);
+        let catch_up = CatchUpTicket::new(Arc::new(CatchUpGate::default()), config.priority);
+        let throttle = Throttle {
+            max_events_per_second: config.max_events_per_second,
+            off_peak_hours: config.off_peak_hours,
+        };
+        let sources = config.archives.iter().cloned().fold(
+            PgFanInEventStore::new(event_store.clone()),
+            |sources, pool| sources.with_archive(pool),
+        );
         Self {
             event_store,
-            event_handler: Arc::new(event_handler),
+            sources,
+            checkpoint_id: event_handler.id().to_string(),
+            event_handler: Arc::new(ErasedEventListener::new(event_handler)),
+            query,
+            unknown_excluded_events,
             config,
+            retry_metrics: Arc::new(RetryMetrics::default()),
+            shard: None,
             wake_channel: watch::channel(true),
+            rebuilding: Arc::new(AtomicBool::new(false)),
+            catch_up,
+            throttle,
+            assigned_listeners: Arc::new(AssignedListeners::default()),
             shutdown_token,
             _event_store_events: PhantomData,
             _event_listener_events: PhantomData,
         }
     }
 
+    fn with_catch_up_gate(mut self, catch_up_gate: Arc<CatchUpGate>) -> Self {
+        self.catch_up = CatchUpTicket::new(catch_up_gate, self.config.priority);
+        self
+    }
+
+    fn with_assigned_listeners(mut self, assigned_listeners: Arc<AssignedListeners>) -> Self {
+        self.assigned_listeners = assigned_listeners;
+        self
+    }
+
+    /// Returns the shards of the event listener, or `None` if it is not sharded.
+    fn shards(&self) -> Option<Vec<ListenerShard>> {
+        let (ident, count) = self.config.shards?;
+        Some(
+            (0..count)
+                .map(|index| ListenerShard {
+                    ident,
+                    index,
+                    count,
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the executor of a shard, with its own checkpoint.
+    fn for_shard(&self, shard: ListenerShard) -> Self {
+        let mut executor = self.clone();
+        executor.checkpoint_id = shard.checkpoint_id(self.event_handler.id());
+        executor.shard = Some(shard);
+        executor
+    }
+
+    /// Checks if the executor handles the event, i.e. the event belongs to its shard.
+    fn owns(&self, event: &PersistedEvent<PgEventId, QE>) -> bool {
+        self.shard
+            .is_none_or(|shard| shard.owns(event.domain_identifiers().get(&shard.ident)))
+    }
+
     async fn lock_event_listener(
         &self,
         tx: &mut Transaction<'_, Postgres>,
@@ -302,7 +905,7 @@ where
                 FOR UPDATE SKIP LOCKED 
                 "#,
         )
-        .bind(self.event_handler.id())
+        .bind(&self.checkpoint_id)
         .fetch_optional(&mut **tx)
         .await?
         .map(|r| r.get(0)))
@@ -317,13 +920,14 @@ where
             Ok(last_processed_event_id) => last_processed_event_id,
             Err(PgEventListenerError {
                 last_processed_event_id,
+                ..
             }) => last_processed_event_id,
         };
         sqlx::query(
             "UPDATE event_listener SET last_processed_event_id = $1, updated_at = now() WHERE id = $2",
         )
         .bind(last_processed_event_id)
-        .bind(self.event_handler.id())
+        .bind(&self.checkpoint_id)
         .execute(&mut *tx)
         .await?;
         tx.commit().await
@@ -332,59 +936,480 @@ where
     pub async fn handle_events_from(
         &self,
         mut last_processed_event_id: PgEventId,
-    ) -> Result<PgEventId, PgEventListenerError> {
-        let query = self
-            .event_handler
-            .query()
-            .clone()
-            .change_origin(last_processed_event_id);
-        let mut events_stream = self.event_store.stream(&query).take(self.config.fetch_size);
+    ) -> Result<HandledEvents, PgEventListenerError> {
+        let mut retractions = self.fetch_retractions(last_processed_event_id).await?;
+        // The events after the last fetched retraction wait for the next batch, when more retractions may follow.
+        let retractions_bound = (retractions.len() >= self.config.fetch_size)
+            .then(|| retractions.back().map(|retraction| retraction.id))
+            .flatten();
+        // The events after the first reserved ID not yet committed wait for the next batch.
+        let gap = self.fetch_gap(last_processed_event_id).await?;
+        let query = self.query.clone().change_origin(last_processed_event_id);
+        let mut events_stream = self.sources.stream(&query).take(self.config.fetch_size);
+        let mut fetched = 0;
+        let started_at = Instant::now();
+        let mut in_flight = InFlight::new();
+        // The last event in flight of each partition.
+        let mut partitions: HashMap<u64, PgEventId> = HashMap::new();
 
         while let Some(event) = events_stream.next().await {
-            let event = event.map_err(|_err| PgEventListenerError {
-                last_processed_event_id,
-            })?;
-            let event_id = event.id();
-            match self.event_handler.handle(event).await {
-                Ok(_) => last_processed_event_id = event_id,
-                Err(_) => {
+            self.throttle.wait(started_at, fetched).await;
+            fetched += 1;
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    self.complete(&mut in_flight, &mut last_processed_event_id)
+                        .await?;
                     return Err(PgEventListenerError {
                         last_processed_event_id,
-                    })
+                        failed_event_id: None,
+                        source: Box::new(err),
+                        decision: None,
+                    });
+                }
+            };
+            let event_id = event.id();
+            if retractions_bound.is_some_and(|bound| event_id > bound)
+                || gap.is_some_and(|gap| event_id > gap)
+            {
+                self.complete(&mut in_flight, &mut last_processed_event_id)
+                    .await?;
+                return Ok(HandledEvents {
+                    last_processed_event_id,
+                    caught_up: false,
+                });
+            }
+            if retractions
+                .front()
+                .is_some_and(|retraction| retraction.id < event_id)
+            {
+                self.complete(&mut in_flight, &mut last_processed_event_id)
+                    .await?;
+                self.handle_retractions(&mut retractions, event_id, &mut last_processed_event_id)
+                    .await?;
+            }
+            if !self.owns(&event) {
+                // The event of another shard moves the checkpoint once the previous events are handled.
+                if in_flight.is_empty() {
+                    last_processed_event_id = event_id;
+                }
+                continue;
+            }
+            let concurrent = self
+                .config
+                .concurrent_events
+                .iter()
+                .any(|name| name == event.name());
+            let partition = self.partition(&event);
+            if let Some(partition) = partition {
+                partitions.retain(|_, in_flight_id| *in_flight_id > last_processed_event_id);
+                if let Some(in_flight_id) = partitions.insert(partition, event_id) {
+                    self.complete_through(
+                        &mut in_flight,
+                        &mut last_processed_event_id,
+                        in_flight_id,
+                    )
+                    .await?;
+                }
+            } else if !concurrent {
+                self.complete(&mut in_flight, &mut last_processed_event_id)
+                    .await?;
+            }
+            let event_handler = &self.event_handler;
+            let context = DecisionContext::caused_by(&event);
+            #[cfg(feature = "otel")]
+            let span = crate::otel::event_span(event_handler.id(), event_id, event.name());
+            let handle = async move {
+                #[cfg(feature = "metrics")]
+                let started_at = Instant::now();
+                let result = context.scope(event_handler.handle(event)).await;
+                #[cfg(feature = "metrics")]
+                crate::metrics::record_handle(event_handler.id(), started_at.elapsed());
+                (event_id, result)
+            };
+            #[cfg(feature = "otel")]
+            let handle = tracing::Instrument::instrument(handle, span);
+            in_flight.push(event_id, handle);
+            // The partitions bound the events in flight, one per partition.
+            if partition.is_none() {
+                if concurrent {
+                    self.wait_for_slot(&mut in_flight, &mut last_processed_event_id)
+                        .await?;
+                } else {
+                    self.complete(&mut in_flight, &mut last_processed_event_id)
+                        .await?;
                 }
             }
             if self.shutdown_token.is_cancelled() {
+                self.complete(&mut in_flight, &mut last_processed_event_id)
+                    .await?;
+                return Ok(HandledEvents {
+                    last_processed_event_id,
+                    caught_up: false,
+                });
+            }
+        }
+        self.complete(&mut in_flight, &mut last_processed_event_id)
+            .await?;
+        let caught_up = (fetched as usize) < self.config.fetch_size;
+        if caught_up {
+            self.handle_retractions(
+                &mut retractions,
+                PgEventId::MAX,
+                &mut last_processed_event_id,
+            )
+            .await?;
+        }
+
+        Ok(HandledEvents {
+            last_processed_event_id,
+            caught_up,
+        })
+    }
+
+    /// Fetches the first event ID following the last processed event that is reserved by a transaction
+    /// not yet committed, if the listener detects the gaps.
+    ///
+    /// The events following it wait for the next batch, so that the events of the reserving transaction
+    /// are not skipped when it commits.
+    async fn fetch_gap(
+        &self,
+        last_processed_event_id: PgEventId,
+    ) -> Result<Option<PgEventId>, PgEventListenerError> {
+        let Some(gap_timeout) = self.config.gap_timeout else {
+            return Ok(None);
+        };
+        sqlx::query_scalar(
+            r#"SELECT MIN(event_id) FROM event_sequence
+               WHERE event_id > $1 AND NOT committed AND inserted_at > now() - make_interval(secs => $2)"#,
+        )
+        .bind(last_processed_event_id)
+        .bind(gap_timeout.as_secs_f64())
+        .fetch_one(&self.event_store.pool)
+        .await
+        .map_err(|err| PgEventListenerError {
+            last_processed_event_id,
+            failed_event_id: None,
+            source: Box::new(err),
+            decision: None,
+        })
+    }
+
+    /// Fetches the retractions following the last processed event, if the listener receives them.
+    async fn fetch_retractions(
+        &self,
+        last_processed_event_id: PgEventId,
+    ) -> Result<VecDeque<Retraction<PgEventId>>, PgEventListenerError> {
+        if !self.config.retractions_enabled || self.shard.is_some_and(|shard| shard.index > 0) {
+            return Ok(VecDeque::new());
+        }
+        let event_types: Vec<&str> = QE::SCHEMA
+            .events_info
+            .iter()
+            .flat_map(|info| info.event_types())
+            .collect();
+        let limit = i64::try_from(self.config.fetch_size).unwrap_or(i64::MAX);
+        self.event_store
+            .retractions(last_processed_event_id, &event_types, limit)
+            .await
+            .map(VecDeque::from)
+            .map_err(|err| PgEventListenerError {
+                last_processed_event_id,
+                failed_event_id: None,
+                source: Box::new(err),
+                decision: None,
+            })
+    }
+
+    /// Handles the retractions recorded before the given event, advancing the last processed event over them.
+    async fn handle_retractions(
+        &self,
+        retractions: &mut VecDeque<Retraction<PgEventId>>,
+        before_event_id: PgEventId,
+        last_processed_event_id: &mut PgEventId,
+    ) -> Result<(), PgEventListenerError> {
+        while retractions
+            .front()
+            .is_some_and(|retraction| retraction.id < before_event_id)
+        {
+            let Some(retraction) = retractions.pop_front() else {
                 break;
+            };
+            let retraction_id = retraction.id;
+            if let Err(err) = self.event_handler.handle_retraction(retraction).await {
+                self.event_failed(retraction_id, err, *last_processed_event_id)
+                    .await?;
+            }
+            *last_processed_event_id = retraction_id;
+        }
+        Ok(())
+    }
+
+    /// Waits for the events in flight, advancing the last processed event over the handled and the
+    /// skipped ones until the first failure that is not skipped.
+    async fn complete<F>(
+        &self,
+        in_flight: &mut InFlight<F>,
+        last_processed_event_id: &mut PgEventId,
+    ) -> Result<(), PgEventListenerError>
+    where
+        F: Future<Output = (PgEventId, Result<(), BoxDynError>)>,
+    {
+        self.complete_through(in_flight, last_processed_event_id, PgEventId::MAX)
+            .await
+    }
+
+    /// Waits until fewer than `max_concurrency` events are being handled, advancing the last processed event
+    /// like `complete` over the handled ones.
+    async fn wait_for_slot<F>(
+        &self,
+        in_flight: &mut InFlight<F>,
+        last_processed_event_id: &mut PgEventId,
+    ) -> Result<(), PgEventListenerError>
+    where
+        F: Future<Output = (PgEventId, Result<(), BoxDynError>)>,
+    {
+        while in_flight.handling() >= self.config.max_concurrency {
+            in_flight.next_handled().await;
+            self.complete_through(in_flight, last_processed_event_id, PgEventId::MIN)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the events in flight up to the given event, advancing the last processed event like `complete`.
+    ///
+    /// On a failure that is not skipped, the other events in flight are handled before the failure is returned,
+    /// so that no handler is interrupted halfway: they are delivered again from the checkpoint.
+    async fn complete_through<F>(
+        &self,
+        in_flight: &mut InFlight<F>,
+        last_processed_event_id: &mut PgEventId,
+        through_event_id: PgEventId,
+    ) -> Result<(), PgEventListenerError>
+    where
+        F: Future<Output = (PgEventId, Result<(), BoxDynError>)>,
+    {
+        loop {
+            while let Some((event_id, result)) = in_flight.pop_handled() {
+                if let Err(err) = result {
+                    if let Err(err) = self
+                        .event_failed(event_id, err, *last_processed_event_id)
+                        .await
+                    {
+                        while in_flight.next_handled().await {}
+                        return Err(err);
+                    }
+                }
+                *last_processed_event_id = event_id;
+            }
+            if *last_processed_event_id >= through_event_id || !in_flight.next_handled().await {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Returns the partition of an event, if the events are partitioned and the event has the identifier.
+    fn partition(&self, event: &PersistedEvent<PgEventId, QE>) -> Option<u64> {
+        let (ident, partitions) = self.config.partition?;
+        let mut hasher = DefaultHasher::new();
+        event.domain_identifiers().get(&ident)?.hash(&mut hasher);
+        Some(hasher.finish() % partitions as u64)
+    }
+
+    /// Applies the retry policy to a failed event.
+    ///
+    /// A skipped event is recorded in the dead letter table and the listener moves past it, while the other
+    /// decisions stop the run at the event. If the event cannot be recorded, it is retried.
+    async fn event_failed(
+        &self,
+        event_id: PgEventId,
+        source: BoxDynError,
+        last_processed_event_id: PgEventId,
+    ) -> Result<(), PgEventListenerError> {
+        let mut err = PgEventListenerError {
+            last_processed_event_id,
+            failed_event_id: Some(event_id),
+            source,
+            decision: None,
+        };
+        let (decision, attempts) = self.retry_decision(&err);
+        if decision == RetryDecision::Skip {
+            match self.record_dead_letter(event_id, attempts, &err).await {
+                Ok(()) => return Ok(()),
+                Err(dead_letter_err) => {
+                    err.source = Box::new(dead_letter_err);
+                    err.decision = Some(RetryDecision::Retry);
+                    return Err(err);
+                }
             }
         }
+        err.decision = Some(decision);
+        Err(err)
+    }
 
-        Ok(last_processed_event_id)
+    async fn record_dead_letter(
+        &self,
+        event_id: PgEventId,
+        attempts: u32,
+        err: &PgEventListenerError,
+    ) -> Result<(), sqlx::Error> {
+        let mut error = err.source.to_string();
+        let mut source = err.source.source();
+        while let Some(cause) = source {
+            error.push_str(": ");
+            error.push_str(&cause.to_string());
+            source = cause.source();
+        }
+        sqlx::query(
+            r#"INSERT INTO event_listener_dead_letter (listener_id, event_id, attempts, error)
+               VALUES ($1, $2, $3, $4)
+               ON CONFLICT (listener_id, event_id)
+               DO UPDATE SET attempts = EXCLUDED.attempts, error = EXCLUDED.error, failed_at = now()"#,
+        )
+        .bind(self.event_handler.id())
+        .bind(event_id)
+        .bind(attempts as i32)
+        .bind(error)
+        .execute(&self.event_store.pool)
+        .await?;
+        Ok(())
     }
 
-    pub async fn try_execute(&self) -> Result<(), sqlx::Error> {
+    pub async fn try_execute(&self) -> Result<(), Error> {
+        if !self.assigned_listeners.owns(&self.checkpoint_id) {
+            self.catch_up.caught_up();
+            return Ok(());
+        }
+        if !self.catch_up.may_run() {
+            return Ok(());
+        }
         let mut tx = self.event_store.pool.begin().await?;
         let Some(last_processed_id) = self.lock_event_listener(&mut tx).await? else {
             return Ok(());
         };
+        if last_processed_id == 0 {
+            self.rebuilding.store(true, Ordering::Relaxed);
+        }
+        #[cfg(not(feature = "otel"))]
         let result = self.handle_events_from(last_processed_id).await;
-        self.release_event_listener(result, tx).await
+        #[cfg(feature = "otel")]
+        let result = {
+            use tracing::Instrument;
+            let span = crate::otel::listener_span(self.event_handler.id(), last_processed_id);
+            let result = self
+                .handle_events_from(last_processed_id)
+                .instrument(span.clone())
+                .await;
+            match &result {
+                Ok(handled) => {
+                    span.record(
+                        "disintegrate.listener.last_processed_event_id",
+                        handled.last_processed_event_id,
+                    );
+                    span.record("disintegrate.listener.caught_up", handled.caught_up);
+                    span.record("disintegrate.outcome", "ok");
+                }
+                Err(err) => {
+                    span.record(
+                        "disintegrate.listener.last_processed_event_id",
+                        err.last_processed_event_id,
+                    );
+                    span.record("disintegrate.outcome", "error");
+                }
+            }
+            result
+        };
+        if let Ok(handled) = &result {
+            if handled.caught_up {
+                self.catch_up.caught_up();
+            }
+            #[cfg(feature = "metrics")]
+            crate::metrics::record_lag(
+                &self.event_store.pool,
+                &self.checkpoint_id,
+                handled.last_processed_event_id,
+            )
+            .await;
+            self.publish_progress(handled, &mut tx).await?;
+        }
+        let decision = match &result {
+            Ok(_) => {
+                self.retry_metrics.record_success();
+                RetryDecision::Retry
+            }
+            Err(err) => err.decision.unwrap_or_else(|| self.retry_decision(err).0),
+        };
+        let failed_event_id = result.as_ref().err().and_then(|err| err.failed_event_id);
+        self.release_event_listener(result.map(|handled| handled.last_processed_event_id), tx)
+            .await?;
+        match decision {
+            RetryDecision::Retry | RetryDecision::Skip => Ok(()),
+            RetryDecision::Abort => Err(Error::ListenerAborted {
+                listener: self.event_handler.id(),
+                event_id: failed_event_id,
+            }),
+        }
+    }
+
+    /// Asks the retry policy what to do with a failure, returning the decision and the number of attempts.
+    fn retry_decision(&self, err: &PgEventListenerError) -> (RetryDecision, u32) {
+        let (attempts, elapsed) = self.retry_metrics.record_failure(err.failed_event_id);
+        let decision = match self.config.retry.retry(&RetryContext {
+            listener_id: self.event_handler.id(),
+            event_id: err.failed_event_id,
+            attempts,
+            elapsed,
+            error: err.source.as_ref(),
+        }) {
+            RetryDecision::Skip if err.failed_event_id.is_none() => RetryDecision::Retry,
+            decision => decision,
+        };
+        self.retry_metrics.record_decision(decision);
+        (decision, attempts)
+    }
+
+    async fn publish_progress(
+        &self,
+        handled: &HandledEvents,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), sqlx::Error> {
+        if !self.config.progress_enabled
+            || !handled.caught_up
+            || handled.last_processed_event_id == 0
+            || !self.rebuilding.swap(false, Ordering::Relaxed)
+        {
+            return Ok(());
+        }
+        progress::publish(
+            tx,
+            &ListenerProgressEvent::ProjectionRebuilt {
+                listener_id: self.event_handler.id().to_string(),
+                at_event_id: handled.last_processed_event_id,
+            },
+        )
+        .await?;
+        Ok(())
     }
 
     async fn execute(&self) -> Result<(), Error> {
         let result = self.try_execute().await;
         match result {
-            Err(sqlx::Error::Io(_)) | Err(sqlx::Error::PoolTimedOut) => Ok(()),
-            Err(err) => Err(Error::Database(err)),
+            Err(Error::Database(sqlx::Error::Io(_)))
+            | Err(Error::Database(sqlx::Error::PoolTimedOut)) => Ok(()),
+            Err(err) => Err(err),
             _ => Ok(()),
         }
     }
 
-    pub fn spawn_task(self) -> JoinHandle<Result<(), Error>> {
+    pub fn spawn_task(self, local_waker: Arc<AppendObserver<E>>) -> JoinHandle<Result<(), Error>> {
         let shutdown = self.shutdown_token.clone();
         let mut poll = tokio::time::interval(self.config.poll);
         poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
         let mut wake_tx = self.wake_channel.1.clone();
         tokio::spawn(async move {
+            // the local waker is unregistered from the event store when the task ends.
+            let _local_waker = local_waker;
             loop {
                 tokio::select! {
                     Ok(()) =  wake_tx.changed() => self.execute().await?,
@@ -397,51 +1422,144 @@ where
 }
 
 #[async_trait]
-impl<L, QE, E, S> EventListenerExecutor<E> for PgEventListerExecutor<L, QE, E, S>
+impl<QE, E, S> EventListenerExecutor<E> for PgEventListerExecutor<QE, E, S>
 where
     E: Event + Clone + Sync + Send + 'static,
     S: Serde<E> + Clone + Send + Sync + 'static,
     QE: TryFrom<E> + Into<E> + Event + 'static + Send + Sync + Clone,
     <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
-    L: EventListener<PgEventId, QE> + 'static,
 {
     async fn init(&self) -> Result<(), Error> {
+        if let Some(event) = self.unknown_excluded_events.first() {
+            return Err(Error::UnknownExcludedEvent {
+                listener: self.event_handler.id(),
+                event: event.clone(),
+            });
+        }
+        if let Some((ident, _)) = self.config.partition {
+            if !QE::SCHEMA
+                .domain_identifiers
+                .iter()
+                .any(|info| info.ident == ident)
+            {
+                return Err(Error::UnknownPartitionIdentifier {
+                    listener: self.event_handler.id(),
+                    identifier: ident.to_string(),
+                });
+            }
+        }
+        if let Some((ident, _)) = self.config.shards {
+            if !QE::SCHEMA
+                .domain_identifiers
+                .iter()
+                .any(|info| info.ident == ident)
+            {
+                return Err(Error::UnknownShardIdentifier {
+                    listener: self.event_handler.id(),
+                    identifier: ident.to_string(),
+                });
+            }
+        }
+        // A new checkpoint, e.g. of a shard added to the listener, starts from the lowest checkpoint of the listener.
         let mut tx = self.event_store.pool.begin().await?;
-        sqlx::query("INSERT INTO event_listener (id, last_processed_event_id) VALUES ($1, 0) ON CONFLICT (id) DO NOTHING")
-                .bind(self.event_handler.id())
-                .execute(&mut *tx)
-                .await?;
+        let checkpoints = match self.shards() {
+            Some(shards) => shards
+                .into_iter()
+                .map(|shard| {
+                    (
+                        shard.checkpoint_id(self.event_handler.id()),
+                        Some(shard.hash_range()),
+                    )
+                })
+                .collect(),
+            None => vec![(self.checkpoint_id.clone(), None)],
+        };
+        for (checkpoint_id, hash_range) in checkpoints {
+            sqlx::query(
+                r#"INSERT INTO event_listener (id, last_processed_event_id, listener_id, hash_from, hash_to)
+                   VALUES ($1, (SELECT COALESCE(MIN(last_processed_event_id), 0) FROM event_listener WHERE id = $2 OR listener_id = $2), $2, $3, $4)
+                   ON CONFLICT (id) DO NOTHING"#,
+            )
+            .bind(checkpoint_id)
+            .bind(self.event_handler.id())
+            .bind(hash_range.map(|(from, _)| from))
+            .bind(hash_range.map(|(_, to)| to))
+            .execute(&mut *tx)
+            .await?;
+        }
         tx.commit().await?;
         Ok(())
     }
 
+    fn describe(&self) -> ListenerDescription {
+        ListenerDescription::new(self.event_handler.id(), &self.query, &self.config)
+    }
+
+    fn retry_metrics(&self) -> Arc<RetryMetrics> {
+        self.retry_metrics.clone()
+    }
+
     fn run(&self) -> (Option<ExecutorWaker<E>>, JoinHandle<Result<(), Error>>) {
         let waker = if self.config.notifier_enabled {
             Some(ExecutorWaker {
                 wake_tx: self.wake_channel.0.clone(),
-                query: self.event_handler.query().cast().clone(),
+                query: self.query.cast(),
             })
         } else {
             None
         };
-        (waker, self.clone().spawn_task())
+        // wakes the executor right after an append of the same process, without waiting for the notification.
+        let wake_tx = self.wake_channel.0.clone();
+        let query: StreamQuery<PgEventId, E> = self.query.cast();
+        let local_waker: Arc<AppendObserver<E>> =
+            Arc::new(move |events: &[PersistedEvent<PgEventId, E>]| {
+                if events.iter().any(|event| query.matches(event)) {
+                    wake_tx.send_replace(true);
+                }
+            });
+        self.event_store.observe_appends(&local_waker);
+        let task = match self.shards() {
+            Some(shards) => {
+                let tasks: Vec<_> = shards
+                    .into_iter()
+                    .map(|shard| self.for_shard(shard).spawn_task(local_waker.clone()))
+                    .collect();
+                tokio::spawn(async move {
+                    for result in join_all(tasks).await.into_iter().flatten() {
+                        result?;
+                    }
+                    Ok(())
+                })
+            }
+            None => self.clone().spawn_task(local_waker),
+        };
+        (waker, task)
     }
 }
 
-impl<L, QE, E, S> Clone for PgEventListerExecutor<L, QE, E, S>
+impl<QE, E, S> Clone for PgEventListerExecutor<QE, E, S>
 where
     QE: TryFrom<E> + Event + Send + Sync + Clone,
     <QE as TryFrom<E>>::Error: Send + Sync,
     E: Event + Clone + Sync + Send,
     S: Serde<E> + Clone + Send + Sync,
-    L: EventListener<PgEventId, QE>,
 {
     fn clone(&self) -> Self {
         Self {
             event_store: self.event_store.clone(),
+            sources: self.sources.clone(),
             event_handler: Arc::clone(&self.event_handler),
+            query: self.query.clone(),
+            unknown_excluded_events: self.unknown_excluded_events.clone(),
             config: self.config.clone(),
+            retry_metrics: Arc::clone(&self.retry_metrics),
+            shard: self.shard,
+            checkpoint_id: self.checkpoint_id.clone(),
             wake_channel: self.wake_channel.clone(),
+            rebuilding: Arc::clone(&self.rebuilding),
+            catch_up: self.catch_up.clone(),
+            throttle: self.throttle.clone(),
+            assigned_listeners: Arc::clone(&self.assigned_listeners),
             shutdown_token: self.shutdown_token.clone(),
             _event_store_events: PhantomData,
             _event_listener_events: PhantomData,
@@ -455,24 +1573,105 @@ struct ExecutorWaker<E: Event + Clone> {
 }
 
 impl<E: Event + Clone> ExecutorWaker<E> {
-    fn wake(&self, event: &str) {
-        if self.query.matches_event(event) {
+    fn wake(&self, payload: &str) {
+        let matches = match EventNotification::parse::<E>(payload) {
+            Some(notification) => notification.matches(&self.query),
+            // notifications sent by a trigger created by an older version contain only the event type.
+            None => self.query.matches_event(payload),
+        };
+        if matches {
             self.wake_tx.send_replace(true);
         }
     }
 }
 
+/// The events in flight of an executor, handled concurrently and checkpointed in the order of their IDs.
+struct InFlight<F> {
+    handling: FuturesUnordered<F>,
+    /// The events not yet checkpointed, with their result once handled.
+    results: BTreeMap<PgEventId, Option<Result<(), BoxDynError>>>,
+}
+
+impl<F> InFlight<F>
+where
+    F: Future<Output = (PgEventId, Result<(), BoxDynError>)>,
+{
+    fn new() -> Self {
+        Self {
+            handling: FuturesUnordered::new(),
+            results: BTreeMap::new(),
+        }
+    }
+
+    fn push(&mut self, event_id: PgEventId, handle: F) {
+        self.results.insert(event_id, None);
+        self.handling.push(handle);
+    }
+
+    /// Returns true if all the events have been checkpointed.
+    fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// Returns the number of the events being handled.
+    fn handling(&self) -> usize {
+        self.handling.len()
+    }
+
+    /// Waits for the next event to be handled, returning false if no event is being handled.
+    async fn next_handled(&mut self) -> bool {
+        let Some((event_id, result)) = self.handling.next().await else {
+            return false;
+        };
+        self.results.insert(event_id, Some(result));
+        true
+    }
+
+    /// Removes the first event not yet checkpointed, if it has been handled.
+    fn pop_handled(&mut self) -> Option<(PgEventId, Result<(), BoxDynError>)> {
+        let first = self.results.first_entry()?;
+        if first.get().is_none() {
+            return None;
+        }
+        let (event_id, result) = first.remove_entry();
+        result.map(|result| (event_id, result))
+    }
+}
+
 async fn setup(pool: &PgPool) -> Result<(), Error> {
+    let mut tx = crate::setup_lock::begin(pool).await?;
     sqlx::query(include_str!("listener/sql/table_event_listener.sql"))
-        .execute(pool)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(include_str!("listener/sql/column_event_listener_shard.sql"))
+        .execute(&mut *tx)
         .await?;
+    sqlx::query(include_str!("listener/sql/view_event_listener_lag.sql"))
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(include_str!(
+        "listener/sql/table_event_listener_redelivery.sql"
+    ))
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(include_str!(
+        "listener/sql/table_event_listener_dead_letter.sql"
+    ))
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(include_str!(
+        "listener/sql/table_event_listener_instance.sql"
+    ))
+    .execute(&mut *tx)
+    .await?;
     sqlx::query(include_str!("listener/sql/fn_notify_event_listener.sql"))
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
     sqlx::query(include_str!(
         "listener/sql/trigger_notify_event_listener.sql"
     ))
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
+    tx.commit().await?;
     Ok(())
 }
@@ -0,0 +1,270 @@
+//! Listener assignment
+//!
+//! When the event listeners run across a fleet of processes, this module assigns each event listener
+//! to a single instance using consistent hashing. The instances publish a heartbeat in the
+//! `event_listener_instance` table; when an instance joins, leaves or stops sending heartbeats, the
+//! listeners are rebalanced automatically, moving only the listeners of the changed instance.
+use std::sync::RwLock;
+use std::time::Duration;
+
+use disintegrate::{Identifier, IdentifierValue};
+use sqlx::{PgPool, Row};
+
+use crate::Error;
+
+const VIRTUAL_NODES: usize = 64;
+
+/// The assignment of the event listeners to the instances of a fleet.
+///
+/// Every instance running a `PgEventListener` configured with a `PgListenerAssignment` publishes a
+/// heartbeat at a regular interval, and runs only the event listeners that are assigned to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgListenerAssignment {
+    instance_id: String,
+    heartbeat: Duration,
+    expire_after: Duration,
+}
+
+impl PgListenerAssignment {
+    /// Creates a new `PgListenerAssignment` for the given instance, with a heartbeat every 5 seconds.
+    ///
+    /// # Parameters
+    ///
+    /// * `instance_id`: The unique ID of the instance in the fleet, e.g. the hostname.
+    pub fn new(instance_id: impl Into<String>) -> Self {
+        Self {
+            instance_id: instance_id.into(),
+            heartbeat: Duration::from_secs(5),
+            expire_after: Duration::from_secs(30),
+        }
+    }
+
+    /// Sets the interval between two heartbeats of the instance.
+    pub fn heartbeat(mut self, heartbeat: Duration) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// Sets the time after which an instance without heartbeats is removed from the fleet.
+    pub fn expire_after(mut self, expire_after: Duration) -> Self {
+        self.expire_after = expire_after;
+        self
+    }
+
+    /// Returns the ID of the instance.
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// Returns the interval between two heartbeats of the instance.
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.heartbeat
+    }
+
+    /// Assigns an event listener to one of the given instances.
+    ///
+    /// The assignment is stable: adding or removing an instance only moves the listeners
+    /// assigned to that instance.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the instance the listener is assigned to, or `None` if there are no instances.
+    pub fn assign<'a>(instances: &'a [String], listener_id: &str) -> Option<&'a str> {
+        HashRing::new(instances)
+            .owner(listener_id)
+            .map(|position| instances[position].as_str())
+    }
+
+    /// Publishes the heartbeat of the instance and refreshes the assigned listeners from the live instances.
+    pub(crate) async fn refresh(
+        &self,
+        pool: &PgPool,
+        assigned: &AssignedListeners,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO event_listener_instance (id) VALUES ($1) ON CONFLICT (id) DO UPDATE SET heartbeat_at = now()",
+        )
+        .bind(&self.instance_id)
+        .execute(pool)
+        .await?;
+        let instances: Vec<String> = sqlx::query(
+            "SELECT id FROM event_listener_instance WHERE heartbeat_at > now() - make_interval(secs => $1) ORDER BY id",
+        )
+        .bind(self.expire_after.as_secs_f64())
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| row.get(0))
+        .collect();
+        assigned.update(&self.instance_id, instances);
+        Ok(())
+    }
+
+    /// Removes the instance from the fleet, so its listeners are immediately reassigned.
+    pub(crate) async fn leave(&self, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query("DELETE FROM event_listener_instance WHERE id = $1")
+            .bind(&self.instance_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// The event listeners assigned to the current instance.
+///
+/// Without an assignment, all the event listeners are run by the current instance.
+#[derive(Debug, Default)]
+pub(crate) struct AssignedListeners {
+    fleet: RwLock<Option<(String, Vec<String>)>>,
+}
+
+impl AssignedListeners {
+    fn update(&self, instance_id: &str, instances: Vec<String>) {
+        *self.fleet.write().unwrap() = Some((instance_id.to_string(), instances));
+    }
+
+    /// Checks if the event listener is assigned to the current instance.
+    pub fn owns(&self, listener_id: &str) -> bool {
+        match &*self.fleet.read().unwrap() {
+            Some((instance_id, instances)) => {
+                PgListenerAssignment::assign(instances, listener_id) == Some(instance_id.as_str())
+            }
+            None => true,
+        }
+    }
+}
+
+/// A shard of an event listener, owning the events whose identifier value hashes into its range.
+///
+/// The 32-bit hash space is split into `count` contiguous ranges, one per shard. The events without the
+/// identifier are owned by the first shard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ListenerShard {
+    pub ident: Identifier,
+    pub index: u32,
+    pub count: u32,
+}
+
+impl ListenerShard {
+    const HASH_SPACE: u64 = 1 << 32;
+
+    /// Returns the ID of the checkpoint of the shard in the `event_listener` table.
+    pub fn checkpoint_id(&self, listener_id: &str) -> String {
+        format!("{listener_id}#{}/{}", self.index, self.count)
+    }
+
+    /// Returns the range of the hashes owned by the shard, start inclusive and end exclusive.
+    pub fn hash_range(&self) -> (i64, i64) {
+        let start = u64::from(self.index) * Self::HASH_SPACE / u64::from(self.count);
+        let end = (u64::from(self.index) + 1) * Self::HASH_SPACE / u64::from(self.count);
+        (start as i64, end as i64)
+    }
+
+    /// Checks if the shard owns the events with the given value of the identifier.
+    pub fn owns(&self, value: Option<&IdentifierValue>) -> bool {
+        match value {
+            Some(value) => {
+                let hash = (hash(&value.to_string()) >> 32) as i64;
+                let (start, end) = self.hash_range();
+                start <= hash && hash < end
+            }
+            None => self.index == 0,
+        }
+    }
+}
+
+struct HashRing {
+    points: Vec<(u64, usize)>,
+}
+
+impl HashRing {
+    fn new(instances: &[String]) -> Self {
+        let mut points: Vec<(u64, usize)> = instances
+            .iter()
+            .enumerate()
+            .flat_map(|(position, instance)| {
+                (0..VIRTUAL_NODES).map(move |node| (hash(&format!("{instance}#{node}")), position))
+            })
+            .collect();
+        points.sort_unstable();
+        Self { points }
+    }
+
+    fn owner(&self, key: &str) -> Option<usize> {
+        let key = hash(key);
+        let index = self.points.partition_point(|(point, _)| *point < key);
+        self.points
+            .get(index)
+            .or_else(|| self.points.first())
+            .map(|(_, position)| *position)
+    }
+}
+
+/// FNV-1a followed by the MurmurHash3 finalizer, so that all the instances of the fleet compute the same
+/// assignment regardless of their build, and similar keys are spread evenly over the ring.
+fn hash(key: &str) -> u64 {
+    let mut hash = key.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_splits_the_identifiers_across_the_shards() {
+        let shards: Vec<ListenerShard> = (0..3)
+            .map(|index| ListenerShard {
+                ident: disintegrate::ident!(#account_id),
+                index,
+                count: 3,
+            })
+            .collect();
+
+        for account in 0..100 {
+            let value = IdentifierValue::String(format!("account_{account}"));
+            let owners = shards
+                .iter()
+                .filter(|shard| shard.owns(Some(&value)))
+                .count();
+            assert_eq!(owners, 1);
+        }
+        assert_eq!(shards[0].hash_range().0, 0);
+        assert_eq!(shards[2].hash_range().1, 1 << 32);
+        assert!(shards[0].owns(None));
+        assert!(!shards[1].owns(None));
+    }
+
+    #[test]
+    fn it_moves_only_the_listeners_of_the_removed_instance() {
+        let instances: Vec<String> = ["a", "b", "c"].map(String::from).to_vec();
+        let remaining: Vec<String> = ["a", "c"].map(String::from).to_vec();
+        let listeners: Vec<String> = (0..100).map(|i| format!("listener_{i}")).collect();
+
+        for listener in &listeners {
+            let before = PgListenerAssignment::assign(&instances, listener).unwrap();
+            let after = PgListenerAssignment::assign(&remaining, listener).unwrap();
+            if before != "b" {
+                assert_eq!(before, after);
+            }
+        }
+        let assigned_to_b = listeners
+            .iter()
+            .filter(|listener| PgListenerAssignment::assign(&instances, listener) == Some("b"))
+            .count();
+        assert!(assigned_to_b > 0);
+    }
+
+    #[test]
+    fn it_runs_all_the_listeners_without_an_assignment() {
+        let assigned = AssignedListeners::default();
+
+        assert!(assigned.owns("cart_projection"));
+    }
+}
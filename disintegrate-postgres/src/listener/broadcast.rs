@@ -0,0 +1,270 @@
+//! Live event feed
+//!
+//! This module fans the new events of the event store out to in-process subscribers, e.g. to push them to
+//! the clients of SSE or WebSocket endpoints. The broadcaster is woken by the PostgreSQL notifications of the
+//! inserted events and by the appends of the same process, streams the events appended since the last
+//! broadcast and sends them to a `tokio::sync::broadcast` channel. Each subscriber receives the events
+//! matching its own stream query.
+//!
+//! The feed is live only: it starts from the events appended after the broadcaster has started, and the
+//! events committed out of order with a lower ID than an event already broadcast are not sent. The
+//! components that must not miss an event should be event listeners, which track their checkpoint.
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use disintegrate::{Event, EventStore, PersistedEvent, StreamQuery};
+use disintegrate_serde::Serde;
+use futures::{Stream, StreamExt};
+use sqlx::postgres::PgListener;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch};
+
+use crate::event_store::{AppendObserver, PgEventStore};
+use crate::{Error, PgEventId};
+
+/// Broadcasts the new events of the event store to the in-process subscribers.
+///
+/// # Examples
+///
+/// ```ignore
+/// let broadcaster = PgEventBroadcaster::new(event_store);
+/// tokio::spawn(broadcaster.clone().start_with_shutdown(shutdown));
+///
+/// let feed = broadcaster.subscribe(query!(CourseEvent; course_id == course_id));
+/// ```
+pub struct PgEventBroadcaster<E, S>
+where
+    E: Event + Clone,
+    S: Serde<E> + Send + Sync,
+{
+    event_store: PgEventStore<E, S>,
+    sender: broadcast::Sender<PersistedEvent<PgEventId, E>>,
+    poll: Duration,
+}
+
+impl<E, S> Clone for PgEventBroadcaster<E, S>
+where
+    E: Event + Clone,
+    S: Serde<E> + Clone + Send + Sync,
+{
+    fn clone(&self) -> Self {
+        Self {
+            event_store: self.event_store.clone(),
+            sender: self.sender.clone(),
+            poll: self.poll,
+        }
+    }
+}
+
+impl<E, S> std::fmt::Debug for PgEventBroadcaster<E, S>
+where
+    E: Event + Clone,
+    S: Serde<E> + Send + Sync,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PgEventBroadcaster")
+            .field("subscribers", &self.sender.receiver_count())
+            .field("poll", &self.poll)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E, S> PgEventBroadcaster<E, S>
+where
+    E: Event + Clone + Send + Sync + 'static,
+    S: Serde<E> + Clone + Send + Sync + 'static,
+{
+    /// Creates a new `PgEventBroadcaster`, buffering up to 1024 events for the slowest subscriber.
+    ///
+    /// Besides the notifications, the event store is polled every 5 seconds, in case a notification is lost
+    /// while the connection receiving them is reestablished.
+    ///
+    /// # Parameters
+    ///
+    /// * `event_store`: The event store of the broadcast events.
+    pub fn new(event_store: PgEventStore<E, S>) -> Self {
+        Self {
+            event_store,
+            sender: broadcast::channel(1024).0,
+            poll: Duration::from_secs(5),
+        }
+    }
+
+    /// Sets the number of events buffered for the slowest subscriber.
+    ///
+    /// A subscriber lagging behind by more events receives `RecvError::Lagged` and skips the oldest events.
+    /// It must be set before the first subscription.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.sender = broadcast::channel(capacity.max(1)).0;
+        self
+    }
+
+    /// Sets the interval between two polls of the event store.
+    pub fn with_poll_interval(mut self, poll: Duration) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    /// Subscribes to the new events matching a query.
+    ///
+    /// # Parameters
+    ///
+    /// * `query`: The stream query of the events received by the subscriber.
+    ///
+    /// # Returns
+    ///
+    /// The `EventFeed` receiving the events broadcast after the subscription.
+    pub fn subscribe<QE>(&self, query: StreamQuery<PgEventId, QE>) -> EventFeed<E, QE>
+    where
+        QE: TryFrom<E> + Event + Clone,
+    {
+        EventFeed {
+            receiver: self.sender.subscribe(),
+            query,
+        }
+    }
+
+    /// Broadcasts the new events until the shutdown signal.
+    ///
+    /// # Parameters
+    ///
+    /// * `shutdown`: A future that represents the shutdown signal.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating the success or failure of the broadcaster.
+    pub async fn start_with_shutdown<F: Future<Output = ()> + Send + 'static>(
+        self,
+        shutdown: F,
+    ) -> Result<(), Error> {
+        let pool = self.event_store.pool.clone();
+        super::setup(&pool).await?;
+        let (wake_tx, mut wake_rx) = watch::channel(false);
+        let local_waker: Arc<AppendObserver<E>> = Arc::new(move |_| {
+            wake_tx.send_replace(true);
+        });
+        self.event_store.observe_appends(&local_waker);
+        let mut last_event_id = last_event_id(&pool).await?;
+        let mut poll = tokio::time::interval(self.poll);
+        poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        tokio::pin!(shutdown);
+        let channel = super::notification_channel(&pool).await?;
+        let mut listener = PgListener::connect_with(&pool).await?;
+        listener.listen(&channel).await?;
+        loop {
+            tokio::select! {
+                notification = listener.try_recv() => match notification {
+                    Ok(_) => {}
+                    Err(err @ sqlx::Error::PoolClosed) => return Err(Error::Database(err)),
+                    Err(_) => {
+                        listener = PgListener::connect_with(&pool).await?;
+                        listener.listen(&channel).await?;
+                    }
+                },
+                _ = wake_rx.changed() => {}
+                _ = poll.tick() => {}
+                _ = &mut shutdown => return Ok(()),
+            }
+            last_event_id = self.broadcast_after(last_event_id).await?;
+        }
+    }
+
+    /// Sends the events appended after the given event ID to the subscribers, returning the ID of the last one.
+    async fn broadcast_after(&self, last_event_id: PgEventId) -> Result<PgEventId, Error> {
+        if self.sender.receiver_count() == 0 {
+            return self::last_event_id(&self.event_store.pool)
+                .await
+                .map(|event_id| event_id.max(last_event_id));
+        }
+        let query = disintegrate::query::<PgEventId, E, E>(None).change_origin(last_event_id);
+        let mut events = self.event_store.stream(&query);
+        let mut last_event_id = last_event_id;
+        while let Some(event) = events.next().await {
+            let event = event?;
+            last_event_id = event.id();
+            // the subscribers may have been dropped in the meantime.
+            let _ = self.sender.send(event);
+        }
+        Ok(last_event_id)
+    }
+}
+
+async fn last_event_id(pool: &sqlx::PgPool) -> Result<PgEventId, Error> {
+    Ok(
+        sqlx::query_scalar::<_, PgEventId>("SELECT COALESCE(MAX(event_id), 0) FROM event")
+            .fetch_one(pool)
+            .await?,
+    )
+}
+
+/// The new events matching the query of a subscriber of a `PgEventBroadcaster`.
+pub struct EventFeed<E, QE>
+where
+    E: Event,
+    QE: Event + Clone,
+{
+    receiver: broadcast::Receiver<PersistedEvent<PgEventId, E>>,
+    query: StreamQuery<PgEventId, QE>,
+}
+
+impl<E, QE> std::fmt::Debug for EventFeed<E, QE>
+where
+    E: Event,
+    QE: Event + Clone + std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventFeed")
+            .field("query", &self.query)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E, QE> EventFeed<E, QE>
+where
+    E: Event + Clone,
+    QE: TryFrom<E> + Event + Clone,
+{
+    /// Receives the next event matching the query.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the event, or `RecvError::Lagged` with the number of skipped events if the
+    /// subscriber has fallen behind, or `RecvError::Closed` if the broadcaster has been dropped.
+    pub async fn recv(&mut self) -> Result<PersistedEvent<PgEventId, QE>, RecvError> {
+        loop {
+            let event = self.receiver.recv().await?;
+            if let Some(event) = self.filter(event) {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Turns the feed into a stream, ending when the broadcaster is dropped.
+    ///
+    /// The stream yields `RecvError::Lagged` when the subscriber has fallen behind, e.g. to tell an SSE
+    /// client to reload its view.
+    pub fn into_stream(
+        self,
+    ) -> impl Stream<Item = Result<PersistedEvent<PgEventId, QE>, RecvError>> {
+        futures::stream::unfold(self, |mut feed| async move {
+            match feed.recv().await {
+                Err(RecvError::Closed) => None,
+                result => Some((result, feed)),
+            }
+        })
+    }
+
+    fn filter(&self, event: PersistedEvent<PgEventId, E>) -> Option<PersistedEvent<PgEventId, QE>> {
+        if !self.query.matches_event(event.name()) {
+            return None;
+        }
+        let event_id = event.id();
+        let version = event.version();
+        let metadata = event.metadata().clone();
+        let event = PersistedEvent::new(event_id, QE::try_from(event.into_inner()).ok()?)
+            .with_version(version)
+            .with_metadata(metadata);
+        self.query.matches(&event).then_some(event)
+    }
+}
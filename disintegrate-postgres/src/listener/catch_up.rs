@@ -0,0 +1,126 @@
+//! Listener catch-up throttling
+//!
+//! After a long downtime, the event listeners have to process a large backlog of events. This module
+//! limits the rate at which the events are processed outside the off-peak hours, and lets the listeners
+//! with a higher priority catch up before the others.
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Tracks which event listeners have caught up, so that the listeners with a lower priority
+/// wait for the ones with a higher priority to catch up.
+#[derive(Debug, Default)]
+pub(crate) struct CatchUpGate {
+    listeners: Mutex<Vec<(u8, bool)>>,
+}
+
+impl CatchUpGate {
+    /// Registers an event listener with the given priority, returning its position in the gate.
+    pub fn register(&self, priority: u8) -> usize {
+        let mut listeners = self.listeners.lock().unwrap();
+        listeners.push((priority, false));
+        listeners.len() - 1
+    }
+
+    /// Checks if the listener may process events, i.e. all the listeners with a higher priority have caught up.
+    pub fn may_run(&self, position: usize) -> bool {
+        let listeners = self.listeners.lock().unwrap();
+        let (priority, _) = listeners[position];
+        listeners
+            .iter()
+            .all(|(other_priority, caught_up)| *other_priority <= priority || *caught_up)
+    }
+
+    /// Marks the listener as caught up.
+    pub fn caught_up(&self, position: usize) {
+        self.listeners.lock().unwrap()[position].1 = true;
+    }
+}
+
+/// The position of an event listener in a `CatchUpGate`.
+#[derive(Debug, Clone)]
+pub(crate) struct CatchUpTicket {
+    gate: Arc<CatchUpGate>,
+    position: usize,
+}
+
+impl CatchUpTicket {
+    pub fn new(gate: Arc<CatchUpGate>, priority: u8) -> Self {
+        let position = gate.register(priority);
+        Self { gate, position }
+    }
+
+    pub fn may_run(&self) -> bool {
+        self.gate.may_run(self.position)
+    }
+
+    pub fn caught_up(&self) {
+        self.gate.caught_up(self.position)
+    }
+}
+
+/// Limits the rate at which the events are processed, except during the off-peak hours.
+#[derive(Debug, Clone)]
+pub(crate) struct Throttle {
+    pub max_events_per_second: Option<u32>,
+    pub off_peak_hours: Option<(u8, u8)>,
+}
+
+impl Throttle {
+    /// Waits until the next event can be processed, given the number of events processed since `started_at`.
+    pub async fn wait(&self, started_at: Instant, processed: u32) {
+        let Some(max_events_per_second) = self.max_events_per_second else {
+            return;
+        };
+        if self.is_off_peak(SystemTime::now()) {
+            return;
+        }
+        let expected = Duration::from_secs(1) * processed / max_events_per_second.max(1);
+        if let Some(delay) = expected.checked_sub(started_at.elapsed()) {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    fn is_off_peak(&self, now: SystemTime) -> bool {
+        let Some((start, end)) = self.off_peak_hours else {
+            return false;
+        };
+        let hour = (now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / 3600 % 24) as u8;
+        if start <= end {
+            (start..end).contains(&hour)
+        } else {
+            hour >= start || hour < end
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_lets_lower_priority_listeners_wait_for_higher_priority_ones() {
+        let gate = Arc::new(CatchUpGate::default());
+        let projection = CatchUpTicket::new(gate.clone(), 10);
+        let report = CatchUpTicket::new(gate.clone(), 0);
+
+        assert!(projection.may_run());
+        assert!(!report.may_run());
+
+        projection.caught_up();
+
+        assert!(report.may_run());
+    }
+
+    #[test]
+    fn it_detects_the_off_peak_hours_across_midnight() {
+        let throttle = Throttle {
+            max_events_per_second: Some(10),
+            off_peak_hours: Some((22, 6)),
+        };
+        let at = |hour: u64| UNIX_EPOCH + Duration::from_secs(hour * 3600);
+
+        assert!(throttle.is_off_peak(at(23)));
+        assert!(throttle.is_off_peak(at(2)));
+        assert!(!throttle.is_off_peak(at(12)));
+    }
+}
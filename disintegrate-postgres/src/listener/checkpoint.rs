@@ -0,0 +1,204 @@
+//! Listener checkpoints export
+//!
+//! This module provides the export and the restore of the `event_listener` checkpoint table.
+//! The export can be taken together with a backup of the event store and restored after a point-in-time
+//! recovery, so the read models are restored consistently with the event store.
+//!
+//! It also allows requesting the redelivery of a range of events to an event listener.
+use std::ops::RangeInclusive;
+
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+
+use crate::metadata::{self, SCHEMA_VERSION};
+use crate::{Error, PgEventId, PgEventStore};
+use disintegrate::Event;
+use disintegrate_serde::Serde;
+
+/// The last event processed by an event listener.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListenerCheckpoint {
+    /// The ID of the event listener.
+    pub listener_id: String,
+    /// The ID of the last event processed by the event listener.
+    pub last_processed_event_id: PgEventId,
+}
+
+/// A consistent export of the event listener checkpoints.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListenerCheckpoints {
+    /// The version of the database schema at the time of the export.
+    pub schema_version: i32,
+    /// The version of the library that last initialized the database.
+    pub library_version: String,
+    /// The ID of the last event stored at the time of the export.
+    pub last_event_id: PgEventId,
+    /// The checkpoints of the event listeners.
+    pub checkpoints: Vec<ListenerCheckpoint>,
+}
+
+impl<E, S> PgEventStore<E, S>
+where
+    E: Event,
+    S: Serde<E> + Send + Sync,
+{
+    /// Exports the checkpoints of the event listeners.
+    ///
+    /// The checkpoints and the ID of the last event are read from the same database snapshot.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `ListenerCheckpoints`, or an error if the database has not been initialized.
+    pub async fn export_listener_checkpoints(&self) -> Result<ListenerCheckpoints, Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .execute(&mut *tx)
+            .await?;
+        let metadata = metadata::load(&mut *tx)
+            .await?
+            .ok_or(Error::Database(sqlx::Error::RowNotFound))?;
+        let last_event_id = last_event_id(&mut tx).await?;
+        let checkpoints =
+            sqlx::query("SELECT id, last_processed_event_id FROM event_listener ORDER BY id")
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .map(|row| ListenerCheckpoint {
+                    listener_id: row.get(0),
+                    last_processed_event_id: row.get(1),
+                })
+                .collect();
+        tx.commit().await?;
+        Ok(ListenerCheckpoints {
+            schema_version: metadata.schema_version,
+            library_version: metadata.library_version,
+            last_event_id,
+            checkpoints,
+        })
+    }
+
+    /// Restores the checkpoints of the event listeners.
+    ///
+    /// The checkpoints of the listeners not included in the export are left untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `checkpoints` - The checkpoints previously exported with `export_listener_checkpoints`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::IncompatibleSchema` if the checkpoints have been exported from a different schema version,
+    /// and `Error::CheckpointAheadOfStore` if a checkpoint refers to an event not contained in the event store,
+    /// e.g. because the event store has been recovered to an earlier point in time than the export.
+    pub async fn restore_listener_checkpoints(
+        &self,
+        checkpoints: &ListenerCheckpoints,
+    ) -> Result<(), Error> {
+        if checkpoints.schema_version != SCHEMA_VERSION {
+            return Err(Error::IncompatibleSchema {
+                found: checkpoints.schema_version,
+                expected: SCHEMA_VERSION,
+            });
+        }
+        let mut tx = self.pool.begin().await?;
+        let last_event_id = last_event_id(&mut tx).await?;
+        for checkpoint in &checkpoints.checkpoints {
+            if checkpoint.last_processed_event_id > last_event_id {
+                return Err(Error::CheckpointAheadOfStore {
+                    listener: checkpoint.listener_id.clone(),
+                    last_processed_event_id: checkpoint.last_processed_event_id,
+                    last_event_id,
+                });
+            }
+            sqlx::query(
+                "INSERT INTO event_listener (id, last_processed_event_id) VALUES ($1, $2) ON CONFLICT (id) DO UPDATE SET last_processed_event_id = $2, updated_at = now()",
+            )
+            .bind(&checkpoint.listener_id)
+            .bind(checkpoint.last_processed_event_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// A range of events requested for redelivery to an event listener.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedeliveryWindow {
+    /// The ID of the event listener.
+    pub listener_id: String,
+    /// The ID of the first event to redeliver.
+    pub from_event_id: PgEventId,
+    /// The ID of the last event to redeliver.
+    pub to_event_id: PgEventId,
+    /// The checkpoint of the event listener before the request.
+    pub previous_last_processed_event_id: PgEventId,
+}
+
+impl<E, S> PgEventStore<E, S>
+where
+    E: Event,
+    S: Serde<E> + Send + Sync,
+{
+    /// Requests the redelivery of a range of events to an event listener, e.g. after a bug fix in a projection.
+    ///
+    /// The checkpoint of the event listener is lowered to the event preceding the range, waiting for the
+    /// listener to complete the batch in progress. If the listener has not processed the range yet, the
+    /// checkpoint is left untouched. Since the checkpoint is a single position, the events following the range
+    /// are redelivered as well. The window is recorded in the `event_listener_redelivery` table.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener_id` - The ID of the event listener.
+    /// * `events` - The range of the event IDs to redeliver.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the recorded `RedeliveryWindow`, or `Error::UnknownListener` if the listener
+    /// has never been started.
+    pub async fn request_redelivery(
+        &self,
+        listener_id: &str,
+        events: RangeInclusive<PgEventId>,
+    ) -> Result<RedeliveryWindow, Error> {
+        let (from_event_id, to_event_id) = events.into_inner();
+        let mut tx = self.pool.begin().await?;
+        let previous_last_processed_event_id: PgEventId = sqlx::query_scalar(
+            "SELECT last_processed_event_id FROM event_listener WHERE id = $1 FOR UPDATE",
+        )
+        .bind(listener_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| Error::UnknownListener(listener_id.to_string()))?;
+        sqlx::query(
+            "UPDATE event_listener SET last_processed_event_id = $2, updated_at = now() WHERE id = $1 AND last_processed_event_id > $2",
+        )
+        .bind(listener_id)
+        .bind(from_event_id.saturating_sub(1).max(0))
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO event_listener_redelivery (listener_id, from_event_id, to_event_id, previous_last_processed_event_id) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(listener_id)
+        .bind(from_event_id)
+        .bind(to_event_id)
+        .bind(previous_last_processed_event_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(RedeliveryWindow {
+            listener_id: listener_id.to_string(),
+            from_event_id,
+            to_event_id,
+            previous_last_processed_event_id,
+        })
+    }
+}
+
+async fn last_event_id(conn: &mut sqlx::PgConnection) -> Result<PgEventId, sqlx::Error> {
+    sqlx::query_scalar("SELECT COALESCE(MAX(event_id), 0) FROM event")
+        .fetch_one(conn)
+        .await
+}
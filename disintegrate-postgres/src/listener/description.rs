@@ -0,0 +1,122 @@
+//! Description of the registered event listeners
+//!
+//! The event listeners are registered with their own event type and error type, so their queries and
+//! configurations are not visible once they have been registered. A `ListenerDescription` exposes them
+//! at runtime, e.g. to log them at startup or to serve them from a debugging endpoint.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use disintegrate::{Event, StreamQuery};
+use serde::Serialize;
+
+use super::PgEventListenerConfig;
+use crate::PgEventId;
+
+/// The description of an event listener registered in a `PgEventListener`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ListenerDescription {
+    /// The ID of the event listener.
+    pub id: &'static str,
+    /// The types of the events handled by the event listener.
+    pub event_types: Vec<&'static str>,
+    /// The types of the events excluded from the query, by the query itself or by the configuration.
+    pub excluded_events: Vec<&'static str>,
+    /// The values of the domain identifiers filtered by the query, by identifier.
+    pub identifiers: BTreeMap<String, Vec<String>>,
+    /// The interval at which the event listener polls the event store.
+    pub poll: Duration,
+    /// The maximum number of events fetched from the event store at a time.
+    pub fetch_size: usize,
+    /// Whether the event listener is woken up by the notifications of the database.
+    pub notifier_enabled: bool,
+    /// Whether the event listener publishes its progress events.
+    pub progress_enabled: bool,
+    /// Whether the event listener receives the retractions of the events.
+    pub retractions_enabled: bool,
+    /// The maximum number of events handled per second, outside the off-peak hours.
+    pub max_events_per_second: Option<u32>,
+    /// The UTC hours during which the events are handled without rate limit.
+    pub off_peak_hours: Option<(u8, u8)>,
+    /// The priority of the event listener.
+    pub priority: u8,
+    /// The types of the events handled concurrently.
+    pub concurrent_events: Vec<String>,
+    /// The maximum number of events handled concurrently.
+    pub max_concurrency: usize,
+    /// The number of shards sharing the event listener across the processes, if it is sharded.
+    pub shards: Option<u32>,
+    /// The number of archive databases the events are also read from.
+    pub archives: usize,
+}
+
+impl ListenerDescription {
+    pub(crate) fn new<QE: Event + Clone>(
+        id: &'static str,
+        query: &StreamQuery<PgEventId, QE>,
+        config: &PgEventListenerConfig,
+    ) -> Self {
+        let mut event_types = vec![];
+        let mut excluded_events = vec![];
+        let mut identifiers = BTreeMap::<String, Vec<String>>::new();
+        for filter in query.filters() {
+            let excluded = filter.excluded_events().cloned().unwrap_or_default();
+            for event in filter.events() {
+                if excluded.contains(event) {
+                    push_unique(&mut excluded_events, *event);
+                } else {
+                    push_unique(&mut event_types, *event);
+                }
+            }
+            for (ident, value) in filter.identifiers().iter() {
+                push_unique(
+                    identifiers.entry(ident.to_string()).or_default(),
+                    value.to_string(),
+                );
+            }
+        }
+        Self {
+            id,
+            event_types,
+            excluded_events,
+            identifiers,
+            poll: config.poll,
+            fetch_size: config.fetch_size,
+            notifier_enabled: config.notifier_enabled,
+            progress_enabled: config.progress_enabled,
+            retractions_enabled: config.retractions_enabled,
+            max_events_per_second: config.max_events_per_second,
+            off_peak_hours: config.off_peak_hours,
+            priority: config.priority,
+            concurrent_events: config.concurrent_events.clone(),
+            max_concurrency: config.max_concurrency,
+            shards: config.shards.map(|(_, shards)| shards),
+            archives: config.archives.len(),
+        }
+    }
+}
+
+impl fmt::Display for ListenerDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: events [{}]", self.id, self.event_types.join(", "))?;
+        if !self.identifiers.is_empty() {
+            let identifiers: Vec<String> = self
+                .identifiers
+                .iter()
+                .map(|(ident, values)| format!("{ident} in [{}]", values.join(", ")))
+                .collect();
+            write!(f, ", identifiers {}", identifiers.join(", "))?;
+        }
+        write!(f, ", poll {:?}, priority {}", self.poll, self.priority)?;
+        if self.notifier_enabled {
+            write!(f, ", notifier")?;
+        }
+        Ok(())
+    }
+}
+
+fn push_unique<T: PartialEq>(values: &mut Vec<T>, value: T) {
+    if !values.contains(&value) {
+        values.push(value);
+    }
+}
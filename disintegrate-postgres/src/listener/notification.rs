@@ -0,0 +1,123 @@
+//! Event notifications
+//!
+//! This module provides the payload of the PostgreSQL notifications sent when an event is inserted in the
+//! event store, on the `new_events` channel, or on the `new_events_<schema>` channel for an event store
+//! in a schema other than `public`. The notifications carry the ID, the type and the domain identifiers
+//! of the event, but not its payload: they are cheap triggers, e.g. to invalidate a cache, rather than a way
+//! to read the events.
+use disintegrate::{
+    DomainIdentifier, DomainIdentifierSet, Event, IdentifierType, IdentifierValue, StreamQuery,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{Error, PgEventId};
+
+/// The notification of an event inserted in the event store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventNotification {
+    /// The ID of the event.
+    pub event_id: PgEventId,
+    /// The type of the event.
+    pub event_type: String,
+    /// The domain identifiers of the event.
+    pub identifiers: DomainIdentifierSet,
+}
+
+#[derive(Deserialize)]
+struct NotificationPayload {
+    event_id: PgEventId,
+    event_type: String,
+    #[serde(flatten)]
+    columns: serde_json::Map<String, serde_json::Value>,
+}
+
+impl EventNotification {
+    /// Parses the payload of a notification of the event store.
+    ///
+    /// The domain identifiers are those of the event schema: the other columns of the event are ignored.
+    ///
+    /// # Parameters
+    ///
+    /// * `payload`: The payload of the notification.
+    ///
+    /// # Returns
+    ///
+    /// The notification, or `None` if the payload is not a JSON object with the ID and the type of the event,
+    /// e.g. the payload containing only the event type sent by a trigger created by an older version.
+    pub fn parse<E: Event>(payload: &str) -> Option<Self> {
+        let NotificationPayload {
+            event_id,
+            event_type,
+            columns,
+        } = serde_json::from_str(payload).ok()?;
+        let mut identifiers = DomainIdentifierSet::default();
+        for info in E::SCHEMA.domain_identifiers {
+            let Some(column) = columns.get(info.ident.into_inner()) else {
+                continue;
+            };
+            let value = match info.type_info {
+                IdentifierType::String => column
+                    .as_str()
+                    .map(|value| IdentifierValue::String(value.to_string())),
+                IdentifierType::i64 => column.as_i64().map(IdentifierValue::i64),
+                IdentifierType::Uuid => column
+                    .as_str()
+                    .and_then(|value| Uuid::parse_str(value).ok())
+                    .map(IdentifierValue::Uuid),
+            };
+            if let Some(value) = value {
+                identifiers.insert(DomainIdentifier {
+                    key: info.ident,
+                    value,
+                });
+            }
+        }
+        Some(Self {
+            event_id,
+            event_type,
+            identifiers,
+        })
+    }
+
+    /// Checks if the notified event could match the given query.
+    ///
+    /// The domain identifiers are compared only for the events that declare them, following the
+    /// `MissingIdentifier` semantics of the filters, as the SQL criteria used to stream the events.
+    ///
+    /// # Parameters
+    ///
+    /// * `query`: The stream query.
+    pub fn matches<E: Event + Clone>(&self, query: &StreamQuery<PgEventId, E>) -> bool {
+        let event_type = self.event_type.as_str();
+        let Some(event_info) = E::SCHEMA.event_info(event_type) else {
+            return false;
+        };
+        query.filters().iter().any(|filter| {
+            if filter
+                .excluded_events()
+                .is_some_and(|excluded_events| excluded_events.contains(&event_type))
+            {
+                return false;
+            }
+            if !filter.events().contains(&event_type) {
+                return false;
+            }
+            filter.matches_identifiers(event_info, |ident, value| {
+                self.identifiers.get(ident) == Some(value)
+            })
+        })
+    }
+}
+
+/// Returns the channel of the notifications of the event store in the current schema of the pool.
+///
+/// The channel must be the one chosen by the `notify_event_listener` trigger function.
+pub(crate) async fn notification_channel(pool: &PgPool) -> Result<String, Error> {
+    Ok(sqlx::query_scalar(
+        "SELECT CASE WHEN current_schema() = 'public' THEN 'new_events' ELSE 'new_events_' || current_schema() END",
+    )
+    .fetch_one(pool)
+    .await?)
+}
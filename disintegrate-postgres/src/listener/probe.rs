@@ -0,0 +1,172 @@
+//! Store health probe
+//!
+//! This module provides a background probe that periodically appends a synthetic heartbeat event
+//! to the event store and measures the time it takes to be appended, streamed back and notified
+//! to a listener. The measures give an end-to-end health signal of the store, rather than a simple
+//! connection check.
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+
+use crate::event_store::append_system_event;
+use crate::{Error, PgEventId};
+
+/// The reserved event type of the heartbeat events appended by the probe.
+pub const HEARTBEAT_EVENT_TYPE: &str = "$Heartbeat";
+
+/// The latencies measured by a run of the health probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeReport {
+    /// The ID of the heartbeat event.
+    pub event_id: PgEventId,
+    /// The time taken to append the heartbeat event.
+    pub append_latency: Duration,
+    /// The time taken to stream the heartbeat event back from the store.
+    pub stream_latency: Duration,
+    /// The time elapsed between the append of the heartbeat event and its notification to the listener.
+    pub end_to_end_latency: Duration,
+}
+
+/// A hook receiving the outcome of the health probe runs, e.g. to export them as metrics.
+pub trait ProbeMetrics: Send + Sync {
+    /// Records the latencies measured by a successful run.
+    fn record(&self, report: &ProbeReport);
+
+    /// Records a failed run.
+    fn record_failure(&self, _error: &Error) {}
+}
+
+/// A probe periodically checking the health of the store end-to-end.
+///
+/// At every run, the probe appends a heartbeat event with the reserved `$Heartbeat` event type,
+/// streams it back and waits for its notification, reporting the latencies to the `ProbeMetrics` hook.
+/// The heartbeat events of the previous runs are removed, so the probe does not grow the event store.
+pub struct PgHealthProbe<M> {
+    pool: PgPool,
+    metrics: M,
+    every: Duration,
+    timeout: Duration,
+}
+
+impl<M: ProbeMetrics> PgHealthProbe<M> {
+    /// Creates a new `PgHealthProbe` running every 30 seconds.
+    ///
+    /// # Parameters
+    ///
+    /// * `pool`: The PostgreSQL connection pool of the event store.
+    /// * `metrics`: The hook receiving the outcome of the runs.
+    pub fn new(pool: PgPool, metrics: M) -> Self {
+        Self {
+            pool,
+            metrics,
+            every: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Sets the interval between two runs of the probe.
+    pub fn every(mut self, every: Duration) -> Self {
+        self.every = every;
+        self
+    }
+
+    /// Sets the maximum time to wait for the notification of the heartbeat event.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Runs the probe once.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the measured latencies, or `Error::ProbeTimeout` if the heartbeat event has not
+    /// been notified within the timeout.
+    pub async fn probe(&self) -> Result<ProbeReport, Error> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener
+            .listen(&super::notification_channel(&self.pool).await?)
+            .await?;
+
+        let started_at = Instant::now();
+        let mut tx = self.pool.begin().await?;
+        let event_id = append_system_event(&mut tx, HEARTBEAT_EVENT_TYPE, b"{}").await?;
+        tx.commit().await?;
+        let append_latency = started_at.elapsed();
+
+        let stream_started_at = Instant::now();
+        sqlx::query("SELECT event_id FROM event WHERE event_type = $1 AND event_id = $2")
+            .bind(HEARTBEAT_EVENT_TYPE)
+            .bind(event_id)
+            .fetch_one(&self.pool)
+            .await?;
+        let stream_latency = stream_started_at.elapsed();
+
+        tokio::time::timeout(self.timeout, wait_heartbeat(&mut listener, event_id))
+            .await
+            .map_err(|_| Error::ProbeTimeout(self.timeout))??;
+        let end_to_end_latency = started_at.elapsed();
+
+        for table in ["event", "event_sequence"] {
+            sqlx::query(&format!(
+                "DELETE FROM {table} WHERE event_type = $1 AND event_id < $2"
+            ))
+            .bind(HEARTBEAT_EVENT_TYPE)
+            .bind(event_id)
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(ProbeReport {
+            event_id,
+            append_latency,
+            stream_latency,
+            end_to_end_latency,
+        })
+    }
+
+    /// Runs the probe periodically until the shutdown signal.
+    ///
+    /// # Parameters
+    ///
+    /// * `shutdown`: A future that represents the shutdown signal.
+    pub async fn start_with_shutdown<F: Future<Output = ()> + Send + 'static>(
+        self,
+        shutdown: F,
+    ) -> Result<(), Error> {
+        super::setup(&self.pool).await?;
+        let mut poll = tokio::time::interval(self.every);
+        poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                _ = poll.tick() => match self.probe().await {
+                    Ok(report) => self.metrics.record(&report),
+                    Err(err) => self.metrics.record_failure(&err),
+                },
+                _ = &mut shutdown => return Ok(()),
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct HeartbeatNotification {
+    event_id: PgEventId,
+    event_type: String,
+}
+
+async fn wait_heartbeat(listener: &mut PgListener, event_id: PgEventId) -> Result<(), Error> {
+    loop {
+        let notification = listener.recv().await?;
+        if let Ok(heartbeat) = serde_json::from_str::<HeartbeatNotification>(notification.payload())
+        {
+            if heartbeat.event_type == HEARTBEAT_EVENT_TYPE && heartbeat.event_id == event_id {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Listener progress events
+//!
+//! This module provides the system events published by the event listeners when they reach a milestone.
+//! The system events are stored in the `event` table with a reserved event type, prefixed by `$`,
+//! so they are never returned by the queries of the application events.
+use async_stream::stream;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Row, Transaction};
+
+use crate::event_store::append_system_event;
+use crate::{Error, PgEventId, PgEventStore};
+use disintegrate::{DomainIdentifierSet, Event, EventInfo, EventSchema, PersistedEvent};
+use disintegrate_serde::Serde;
+
+/// A system event published by an event listener when it reaches a milestone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ListenerProgressEvent {
+    /// The event listener has processed all the events of the store, starting from the beginning.
+    ProjectionRebuilt {
+        listener_id: String,
+        at_event_id: PgEventId,
+    },
+}
+
+impl Event for ListenerProgressEvent {
+    const SCHEMA: EventSchema = EventSchema {
+        events: &["$ProjectionRebuilt"],
+        events_info: &[&EventInfo {
+            name: "$ProjectionRebuilt",
+            domain_identifiers: &[],
+            description: None,
+            owner: None,
+            aliases: &[],
+            version: 1,
+        }],
+        domain_identifiers: &[],
+    };
+
+    fn name(&self) -> &'static str {
+        match self {
+            ListenerProgressEvent::ProjectionRebuilt { .. } => "$ProjectionRebuilt",
+        }
+    }
+
+    fn domain_identifiers(&self) -> DomainIdentifierSet {
+        DomainIdentifierSet::default()
+    }
+}
+
+/// Appends a progress event to the event store within the given transaction.
+pub(crate) async fn publish(
+    tx: &mut Transaction<'_, Postgres>,
+    event: &ListenerProgressEvent,
+) -> Result<PgEventId, sqlx::Error> {
+    let payload = serde_json::to_vec(event).expect("json serialization should not fail");
+    append_system_event(tx, event.name(), &payload).await
+}
+
+impl<E, S> PgEventStore<E, S>
+where
+    E: Event + Send + Sync,
+    S: Serde<E> + Send + Sync,
+{
+    /// Streams the progress events published by the event listeners.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - The ID of the event after which the progress events are streamed.
+    ///
+    /// # Returns
+    ///
+    /// A boxed stream of the `ListenerProgressEvent`s published after the `origin`.
+    pub fn listener_progress(
+        &self,
+        origin: PgEventId,
+    ) -> BoxStream<'_, Result<PersistedEvent<PgEventId, ListenerProgressEvent>, Error>> {
+        stream! {
+            let sql = sqlx::query(
+                "SELECT event_id, payload FROM event WHERE event_type = ANY($1) AND event_id > $2 ORDER BY event_id ASC",
+            )
+            .bind(ListenerProgressEvent::SCHEMA.events)
+            .bind(origin);
+            for await row in sql.fetch(&self.pool) {
+                let row = row?;
+                let payload: Vec<u8> = row.get(1);
+                let event = serde_json::from_slice(&payload)
+                    .map_err(|e| disintegrate_serde::Error::Deserialization(Box::new(e)))?;
+                yield Ok(PersistedEvent::new(row.get(0), event));
+            }
+        }
+        .boxed()
+    }
+}
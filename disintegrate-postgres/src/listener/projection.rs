@@ -0,0 +1,254 @@
+//! Projection runner
+//!
+//! This module runs the `Projection`s as event listeners, and allows rebuilding a projection on demand:
+//! the read model is reset and the checkpoint of the projection is moved back to the beginning of the
+//! event stream, so the listener applies all the events again.
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use disintegrate::{BoxDynError, Event, Projection, ProjectionListener};
+use disintegrate_serde::Serde;
+use futures::Future;
+
+use super::{ListenerDescription, PgEventListener, PgEventListenerConfig, PgListenerAssignment};
+use crate::{Error, PgEventId, PgEventStore};
+
+/// Runs the registered projections, and rebuilds them on demand through a `PgProjectionRebuilder`.
+pub struct PgProjectionRunner<E, S>
+where
+    E: Event + Clone,
+    S: Serde<E> + Send + Sync,
+{
+    listener: PgEventListener<E, S>,
+    projections: HashMap<&'static str, Arc<dyn ResetProjection>>,
+}
+
+impl<E, S> std::fmt::Debug for PgProjectionRunner<E, S>
+where
+    E: Event + Clone,
+    S: Serde<E> + Send + Sync,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PgProjectionRunner")
+            .field("listener", &self.listener)
+            .field("projections", &self.projections.keys())
+            .finish()
+    }
+}
+
+impl<E, S> PgProjectionRunner<E, S>
+where
+    E: Event + Clone + Send + Sync + 'static,
+    S: Serde<E> + Clone + Send + Sync + 'static,
+{
+    /// Creates a new `PgProjectionRunner` that applies the events coming from the provided `PgEventStore`.
+    ///
+    /// # Parameters
+    ///
+    /// * `event_store`: An instance of `PgEventStore` representing the event store of the projections.
+    ///
+    /// # Returns
+    ///
+    /// A new `PgProjectionRunner` instance.
+    pub fn builder(event_store: PgEventStore<E, S>) -> Self {
+        Self {
+            listener: PgEventListener::builder(event_store),
+            projections: HashMap::new(),
+        }
+    }
+
+    /// Marks the runner as uninitialized, indicating that the database setup is already done.
+    ///
+    /// See `PgEventListener::uninitialized`.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgProjectionRunner` instance with the `uninitialized` flag set.
+    pub fn uninitialized(mut self) -> Self {
+        self.listener = self.listener.uninitialized();
+        self
+    }
+
+    /// Runs only the projections assigned to this instance of the fleet.
+    ///
+    /// See `PgEventListener::with_assignment`.
+    ///
+    /// # Parameters
+    ///
+    /// * `assignment`: A `PgListenerAssignment` instance identifying this instance in the fleet.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgProjectionRunner` instance with the assignment set.
+    pub fn with_assignment(mut self, assignment: PgListenerAssignment) -> Self {
+        self.listener = self.listener.with_assignment(assignment);
+        self
+    }
+
+    /// Registers a projection to the `PgProjectionRunner`.
+    ///
+    /// The projection is run by an event listener with the name of the projection as ID.
+    ///
+    /// # Parameters
+    ///
+    /// * `projection`: An implementation of the `Projection` trait for the specified event type `QE`.
+    /// * `config`: A `PgEventListenerConfig` instance representing the configuration of the event listener.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgProjectionRunner` instance with the registered projection.
+    pub fn register<QE, P>(mut self, projection: P, config: PgEventListenerConfig) -> Self
+    where
+        QE: TryFrom<E> + Into<E> + Event + Send + Sync + Clone + 'static,
+        <QE as TryFrom<E>>::Error: StdError + Send + Sync,
+        P: Projection<PgEventId, QE, Error: StdError + Send + Sync + 'static> + 'static,
+    {
+        let projection = Arc::new(projection);
+        self.projections.insert(
+            projection.name(),
+            Arc::new(ErasedProjection(projection.clone(), PhantomData)),
+        );
+        self.listener = self
+            .listener
+            .register_listener::<QE>(ProjectionListener::new(projection), config);
+        self
+    }
+
+    /// Returns the descriptions of the event listeners running the registered projections.
+    ///
+    /// See `PgEventListener::listeners`.
+    pub fn listeners(&self) -> Vec<ListenerDescription> {
+        self.listener.listeners()
+    }
+
+    /// Returns a `PgProjectionRebuilder` of the projections registered so far.
+    ///
+    /// The rebuilder can be used while the runner is running, e.g. from an administration endpoint.
+    pub fn rebuilder(&self) -> PgProjectionRebuilder {
+        PgProjectionRebuilder {
+            pool: self.listener.event_store.pool.clone(),
+            projections: self.projections.clone(),
+        }
+    }
+
+    /// Starts the event listeners of all the registered projections.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating the success or failure of the listener process.
+    pub async fn start(self) -> Result<(), Error> {
+        self.listener.start().await
+    }
+
+    /// Starts the event listeners of all the registered projections with a shutdown signal.
+    ///
+    /// # Parameters
+    ///
+    /// * `shutdown`: A future that represents the shutdown signal.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating the success or failure of the listener process.
+    pub async fn start_with_shutdown<F: Future<Output = ()> + Send + 'static>(
+        self,
+        shutdown: F,
+    ) -> Result<(), Error> {
+        self.listener.start_with_shutdown(shutdown).await
+    }
+}
+
+/// Rebuilds the projections of a `PgProjectionRunner` on demand.
+#[derive(Clone)]
+pub struct PgProjectionRebuilder {
+    pool: sqlx::PgPool,
+    projections: HashMap<&'static str, Arc<dyn ResetProjection>>,
+}
+
+impl std::fmt::Debug for PgProjectionRebuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PgProjectionRebuilder")
+            .field("pool", &self.pool)
+            .field("projections", &self.projections.keys())
+            .finish()
+    }
+}
+
+impl PgProjectionRebuilder {
+    /// Returns the names of the projections that can be rebuilt.
+    pub fn projections(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.projections.keys().copied().collect();
+        names.sort();
+        names
+    }
+
+    /// Rebuilds a projection from the beginning of the event stream.
+    ///
+    /// The read model is reset while holding the checkpoint of the projection, waiting for the listener to
+    /// complete the batch in progress, then the checkpoint is moved back to the beginning of the event stream.
+    /// The listener applies all the events again at its next run, and publishes a `ProjectionRebuilt` progress
+    /// event when it catches up, if the progress events are enabled.
+    ///
+    /// # Parameters
+    ///
+    /// * `name`: The name of the projection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating the success of the rebuild request, `Error::UnknownProjection` if the projection
+    /// has not been registered, or `Error::ProjectionReset` if the read model cannot be reset.
+    pub async fn rebuild(&self, name: &str) -> Result<(), Error> {
+        let (&name, projection) = self
+            .projections
+            .get_key_value(name)
+            .ok_or_else(|| Error::UnknownProjection(name.to_string()))?;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SELECT 1 FROM event_listener WHERE id = $1 OR listener_id = $1 FOR UPDATE")
+            .bind(name)
+            .fetch_all(&mut *tx)
+            .await?;
+        projection
+            .reset()
+            .await
+            .map_err(|source| Error::ProjectionReset {
+                projection: name,
+                source,
+            })?;
+        sqlx::query(
+            "INSERT INTO event_listener (id, last_processed_event_id) VALUES ($1, 0) ON CONFLICT (id) DO UPDATE SET last_processed_event_id = 0, updated_at = now()",
+        )
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+        // the shards of a sharded projection restart as well.
+        sqlx::query(
+            "UPDATE event_listener SET last_processed_event_id = 0, updated_at = now() WHERE listener_id = $1",
+        )
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// Erases the event type and the error type of a projection, so the rebuilder can reset any of them.
+#[async_trait]
+trait ResetProjection: Send + Sync {
+    async fn reset(&self) -> Result<(), BoxDynError>;
+}
+
+struct ErasedProjection<P, QE>(Arc<P>, PhantomData<fn() -> QE>);
+
+#[async_trait]
+impl<P, QE> ResetProjection for ErasedProjection<P, QE>
+where
+    QE: Event + Clone + Send + Sync + 'static,
+    P: Projection<PgEventId, QE, Error: StdError + Send + Sync + 'static> + 'static,
+{
+    async fn reset(&self) -> Result<(), BoxDynError> {
+        self.0.reset().await.map_err(Into::into)
+    }
+}
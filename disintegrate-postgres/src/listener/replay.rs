@@ -0,0 +1,299 @@
+//! Replay of an event listener
+//!
+//! A replay moves the checkpoint of an event listener back to an origin, optionally resets its read model,
+//! and handles again the events up to the last event of the store, reporting its progress. The checkpoint
+//! is locked during the replay, so the running instances of the listener wait for it, then continue from
+//! the last replayed event.
+use std::error::Error as StdError;
+use std::time::{Duration, Instant};
+
+use disintegrate::{BoxDynError, Event, EventListener, EventStore};
+use disintegrate_serde::Serde;
+use futures::future::BoxFuture;
+use futures::{Future, FutureExt, StreamExt};
+use sqlx::{Postgres, Transaction};
+
+use super::catch_up::Throttle;
+use crate::{Error, PgEventId, PgEventStore};
+
+type Reset = Box<dyn FnOnce() -> BoxFuture<'static, Result<(), BoxDynError>> + Send>;
+type ProgressObserver = Box<dyn Fn(&ReplayProgress) + Send + Sync>;
+
+/// The progress of a replay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayProgress {
+    /// The number of events handled so far.
+    pub replayed: u64,
+    /// The ID of the last event covered by the replay, or its origin if no event has been covered yet.
+    pub last_event_id: PgEventId,
+    /// The ID of the last event of the store when the replay started, where the replay ends.
+    pub target_event_id: PgEventId,
+    /// The time elapsed since the replay started.
+    pub elapsed: Duration,
+}
+
+/// Replays the events of an event listener from an origin, e.g. to rebuild a read model from a tool run
+/// by an operator instead of manual SQL.
+///
+/// # Examples
+///
+/// ```ignore
+/// let progress = PgReplayer::new(event_store, ProjectionListener::new(projection))
+///     .from(0)
+///     .reset_with(move || async move { sqlx::query("TRUNCATE course").execute(&pool).await })
+///     .max_events_per_second(500)
+///     .on_progress(1000, |progress| println!("{}/{}", progress.last_event_id, progress.target_event_id))
+///     .run()
+///     .await?;
+/// ```
+pub struct PgReplayer<E, S, L>
+where
+    E: Event + Clone,
+    S: Serde<E> + Send + Sync,
+{
+    event_store: PgEventStore<E, S>,
+    listener: L,
+    origin: PgEventId,
+    reset: Option<Reset>,
+    throttle: Throttle,
+    progress_every: u64,
+    on_progress: Option<ProgressObserver>,
+}
+
+impl<E, S, L> std::fmt::Debug for PgReplayer<E, S, L>
+where
+    E: Event + Clone,
+    S: Serde<E> + Send + Sync,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PgReplayer")
+            .field("origin", &self.origin)
+            .field("reset", &self.reset.is_some())
+            .field("throttle", &self.throttle)
+            .field("progress_every", &self.progress_every)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E, S, L> PgReplayer<E, S, L>
+where
+    E: Event + Clone + Send + Sync + 'static,
+    S: Serde<E> + Send + Sync + 'static,
+{
+    /// Creates a new `PgReplayer` of an event listener, replaying all the events of the store.
+    ///
+    /// # Parameters
+    ///
+    /// * `event_store`: The event store of the event listener.
+    /// * `listener`: The event listener handling the replayed events, identified by its ID.
+    ///
+    /// # Returns
+    ///
+    /// A new `PgReplayer` instance.
+    pub fn new(event_store: PgEventStore<E, S>, listener: L) -> Self {
+        Self {
+            event_store,
+            listener,
+            origin: 0,
+            reset: None,
+            throttle: Throttle {
+                max_events_per_second: None,
+                off_peak_hours: None,
+            },
+            progress_every: 1000,
+            on_progress: None,
+        }
+    }
+
+    /// Sets the origin of the replay: the events following it are handled again.
+    ///
+    /// # Parameters
+    ///
+    /// * `origin`: The ID of the last event not replayed, `0` to replay all the events.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgReplayer` instance with the origin set.
+    pub fn from(mut self, origin: PgEventId) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Sets the callback resetting the read model before the replay, e.g. truncating its tables.
+    ///
+    /// The callback runs while the checkpoint of the listener is locked, so the running instances of the
+    /// listener do not update the read model in the meantime.
+    ///
+    /// # Parameters
+    ///
+    /// * `reset`: The callback resetting the read model.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgReplayer` instance with the reset callback set.
+    pub fn reset_with<F, Fut, Err>(mut self, reset: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), Err>> + Send + 'static,
+        Err: Into<BoxDynError> + 'static,
+    {
+        self.reset = Some(Box::new(move || {
+            reset().map(|result| result.map_err(Into::into)).boxed()
+        }));
+        self
+    }
+
+    /// Sets the maximum number of events replayed per second, so the replay does not overload the
+    /// database or the read model.
+    ///
+    /// # Parameters
+    ///
+    /// * `max_events_per_second`: The maximum number of events replayed per second.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgReplayer` instance with the rate limit set.
+    pub fn max_events_per_second(mut self, max_events_per_second: u32) -> Self {
+        self.throttle.max_events_per_second = Some(max_events_per_second);
+        self
+    }
+
+    /// Sets the observer of the progress of the replay.
+    ///
+    /// The observer is called every `every` replayed events, and once more when the replay completes.
+    ///
+    /// # Parameters
+    ///
+    /// * `every`: The number of replayed events between two progress reports.
+    /// * `observer`: The observer receiving the progress reports.
+    ///
+    /// # Returns
+    ///
+    /// The updated `PgReplayer` instance with the progress observer set.
+    pub fn on_progress(
+        mut self,
+        every: u64,
+        observer: impl Fn(&ReplayProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress_every = every.max(1);
+        self.on_progress = Some(Box::new(observer));
+        self
+    }
+
+    /// Runs the replay.
+    ///
+    /// The checkpoint of the listener is locked for the whole replay, and moved to the last replayed event
+    /// when the replay ends, even if it fails: the running instances of the listener continue from there.
+    ///
+    /// # Returns
+    ///
+    /// The final progress of the replay, `Error::ProjectionReset` if the read model cannot be reset, or
+    /// `Error::ReplayFailed` if the listener fails to handle an event.
+    pub async fn run<QE>(self) -> Result<ReplayProgress, Error>
+    where
+        L: EventListener<PgEventId, QE>,
+        L::Error: StdError + Send + Sync + 'static,
+        QE: TryFrom<E> + Event + Clone + Send + Sync + 'static,
+        <QE as TryFrom<E>>::Error: StdError + Send + Sync + 'static,
+    {
+        let listener_id = self.listener.id();
+        super::setup(&self.event_store.pool).await?;
+        let mut tx = self.event_store.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO event_listener (id, last_processed_event_id) VALUES ($1, $2) ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(listener_id)
+        .bind(self.origin)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("SELECT 1 FROM event_listener WHERE id = $1 OR listener_id = $1 FOR UPDATE")
+            .bind(listener_id)
+            .fetch_all(&mut *tx)
+            .await?;
+        if let Some(reset) = self.reset {
+            reset().await.map_err(|source| Error::ProjectionReset {
+                projection: listener_id,
+                source,
+            })?;
+        }
+        let target_event_id: PgEventId =
+            sqlx::query_scalar("SELECT COALESCE(MAX(event_id), 0) FROM event")
+                .fetch_one(&self.event_store.pool)
+                .await?;
+
+        let started_at = Instant::now();
+        let mut progress = ReplayProgress {
+            replayed: 0,
+            last_event_id: self.origin,
+            target_event_id,
+            elapsed: Duration::ZERO,
+        };
+        let query = self.listener.query().clone().change_origin(self.origin);
+        let mut events = self.event_store.stream(&query);
+        let mut failure = None;
+        while let Some(event) = events.next().await {
+            let event = match event {
+                Ok(event) if event.id() <= target_event_id => event,
+                Ok(_) => break,
+                Err(err) => {
+                    failure = Some(err);
+                    break;
+                }
+            };
+            self.throttle
+                .wait(
+                    started_at,
+                    u32::try_from(progress.replayed).unwrap_or(u32::MAX),
+                )
+                .await;
+            let event_id = event.id();
+            if let Err(source) = self.listener.handle(event).await {
+                failure = Some(Error::ReplayFailed {
+                    listener: listener_id,
+                    event_id,
+                    source: Box::new(source),
+                });
+                break;
+            }
+            progress.replayed += 1;
+            progress.last_event_id = event_id;
+            if progress.replayed.is_multiple_of(self.progress_every) {
+                progress.elapsed = started_at.elapsed();
+                if let Some(observer) = &self.on_progress {
+                    observer(&progress);
+                }
+            }
+        }
+        drop(events);
+        if failure.is_none() {
+            // the events up to the target not selected by the query are covered as well.
+            progress.last_event_id = progress.last_event_id.max(target_event_id);
+        }
+        save_checkpoint(&mut tx, listener_id, progress.last_event_id).await?;
+        tx.commit().await?;
+        if let Some(failure) = failure {
+            return Err(failure);
+        }
+        progress.elapsed = started_at.elapsed();
+        if let Some(observer) = &self.on_progress {
+            observer(&progress);
+        }
+        Ok(progress)
+    }
+}
+
+/// Moves the checkpoint of the listener, and of its shards, to the last replayed event.
+async fn save_checkpoint(
+    tx: &mut Transaction<'_, Postgres>,
+    listener_id: &str,
+    last_event_id: PgEventId,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE event_listener SET last_processed_event_id = $2, updated_at = now() WHERE id = $1 OR listener_id = $1",
+    )
+    .bind(listener_id)
+    .bind(last_event_id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
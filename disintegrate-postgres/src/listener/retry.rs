@@ -0,0 +1,211 @@
+//! Listener retry policy
+//!
+//! When an event listener fails to handle an event, the event is retried at the next run of the listener.
+//! This module lets a policy decide, for each failed event, whether the listener keeps retrying it, skips it
+//! or aborts, given the context of the failure, and counts the decisions so that a listener stuck in a retry
+//! loop can be detected.
+use std::error::Error as StdError;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::PgEventId;
+
+/// The context of a failed attempt to handle the events of a listener.
+#[derive(Debug)]
+pub struct RetryContext<'a> {
+    /// The ID of the event listener.
+    pub listener_id: &'static str,
+    /// The ID of the event that failed, or `None` if the events could not be read from the store.
+    pub event_id: Option<PgEventId>,
+    /// The number of consecutive failed attempts to handle the event, including the current one.
+    pub attempts: u32,
+    /// The time elapsed since the first of the consecutive failures.
+    pub elapsed: Duration,
+    /// The error of the current attempt.
+    pub error: &'a (dyn StdError + Send + Sync + 'static),
+}
+
+impl RetryContext<'_> {
+    /// Returns the messages of the error and of its sources, from the outermost to the innermost.
+    pub fn error_chain(&self) -> Vec<String> {
+        let mut chain = vec![self.error.to_string()];
+        let mut source = self.error.source();
+        while let Some(error) = source {
+            chain.push(error.to_string());
+            source = error.source();
+        }
+        chain
+    }
+}
+
+/// The decision of a retry policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryDecision {
+    /// Retries the failed event at the next run of the listener.
+    Retry,
+    /// Skips the failed event, recording it in the `event_listener_dead_letter` table, and handles the
+    /// following events. The failures that are not bound to an event are retried.
+    Skip,
+    /// Stops the listener with an `Error::ListenerAborted` error.
+    Abort,
+}
+
+/// A policy deciding whether a failing event listener keeps retrying.
+pub trait Retry: Send + Sync {
+    /// Decides whether the listener retries, given the context of the failure.
+    fn retry(&self, context: &RetryContext<'_>) -> RetryDecision;
+}
+
+impl<F> Retry for F
+where
+    F: Fn(&RetryContext<'_>) -> RetryDecision + Send + Sync,
+{
+    fn retry(&self, context: &RetryContext<'_>) -> RetryDecision {
+        self(context)
+    }
+}
+
+/// Retries forever. This is the default policy of the event listeners.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AlwaysRetry;
+
+impl Retry for AlwaysRetry {
+    fn retry(&self, _context: &RetryContext<'_>) -> RetryDecision {
+        RetryDecision::Retry
+    }
+}
+
+/// Aborts after the given number of consecutive failed attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxAttempts(pub u32);
+
+impl Retry for MaxAttempts {
+    fn retry(&self, context: &RetryContext<'_>) -> RetryDecision {
+        if context.attempts < self.0 {
+            RetryDecision::Retry
+        } else {
+            RetryDecision::Abort
+        }
+    }
+}
+
+/// Skips the failed event after the given number of consecutive failed attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SkipAfter(pub u32);
+
+impl Retry for SkipAfter {
+    fn retry(&self, context: &RetryContext<'_>) -> RetryDecision {
+        if context.attempts < self.0 {
+            RetryDecision::Retry
+        } else {
+            RetryDecision::Skip
+        }
+    }
+}
+
+/// The counters of the retry decisions of an event listener.
+#[derive(Debug, Default)]
+pub struct RetryMetrics {
+    retries: AtomicU64,
+    skips: AtomicU64,
+    aborts: AtomicU64,
+    consecutive_failures: AtomicU32,
+    first_failure: Mutex<Option<(Option<PgEventId>, Instant)>>,
+}
+
+impl RetryMetrics {
+    /// Returns the number of retry decisions.
+    pub fn retries(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of skip decisions.
+    pub fn skips(&self) -> u64 {
+        self.skips.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of abort decisions.
+    pub fn aborts(&self) -> u64 {
+        self.aborts.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of consecutive failed attempts, reset when the listener succeeds or fails on
+    /// another event.
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures.load(Ordering::Relaxed)
+    }
+
+    /// Returns the time elapsed since the first of the consecutive failures, if the listener is failing.
+    pub fn failing_for(&self) -> Option<Duration> {
+        self.first_failure
+            .lock()
+            .unwrap()
+            .map(|(_, first_failure_at)| first_failure_at.elapsed())
+    }
+
+    pub(crate) fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        *self.first_failure.lock().unwrap() = None;
+    }
+
+    /// Records a failure, returning the number of consecutive failures of the event and the time elapsed
+    /// since the first one.
+    pub(crate) fn record_failure(&self, event_id: Option<PgEventId>) -> (u32, Duration) {
+        let mut first_failure = self.first_failure.lock().unwrap();
+        if first_failure.is_some_and(|(failed_event_id, _)| failed_event_id != event_id) {
+            self.consecutive_failures.store(0, Ordering::Relaxed);
+            *first_failure = None;
+        }
+        let attempts = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        let (_, first_failure_at) = first_failure.get_or_insert_with(|| (event_id, Instant::now()));
+        (attempts, first_failure_at.elapsed())
+    }
+
+    pub(crate) fn record_decision(&self, decision: RetryDecision) {
+        match decision {
+            RetryDecision::Retry => self.retries.fetch_add(1, Ordering::Relaxed),
+            RetryDecision::Skip => self.skips.fetch_add(1, Ordering::Relaxed),
+            RetryDecision::Abort => self.aborts.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("unable to update the read model")]
+    struct ProjectionError(#[source] std::io::Error);
+
+    #[test]
+    fn it_collects_the_error_chain() {
+        let error = ProjectionError(std::io::Error::other("connection reset"));
+        let context = RetryContext {
+            listener_id: "cart_projection",
+            event_id: Some(42),
+            attempts: 3,
+            elapsed: Duration::from_secs(10),
+            error: &error,
+        };
+
+        assert_eq!(
+            context.error_chain(),
+            vec!["unable to update the read model", "connection reset"]
+        );
+        assert_eq!(MaxAttempts(3).retry(&context), RetryDecision::Abort);
+        assert_eq!(MaxAttempts(4).retry(&context), RetryDecision::Retry);
+        assert_eq!(SkipAfter(3).retry(&context), RetryDecision::Skip);
+    }
+
+    #[test]
+    fn it_counts_the_attempts_of_each_event() {
+        let metrics = RetryMetrics::default();
+
+        assert_eq!(metrics.record_failure(Some(1)).0, 1);
+        assert_eq!(metrics.record_failure(Some(1)).0, 2);
+        assert_eq!(metrics.record_failure(Some(2)).0, 1);
+        assert_eq!(metrics.consecutive_failures(), 1);
+    }
+}
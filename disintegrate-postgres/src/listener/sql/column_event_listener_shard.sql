@@ -0,0 +1,4 @@
+ALTER TABLE event_listener
+    ADD COLUMN IF NOT EXISTS listener_id TEXT,
+    ADD COLUMN IF NOT EXISTS hash_from BIGINT,
+    ADD COLUMN IF NOT EXISTS hash_to BIGINT;
@@ -1,8 +1,10 @@
 CREATE OR REPLACE FUNCTION notify_event_listener()
       RETURNS TRIGGER AS $$
  BEGIN
-    PERFORM pg_notify('new_events', NEW.event_type);
+    PERFORM pg_notify(
+        CASE WHEN TG_TABLE_SCHEMA = 'public' THEN 'new_events' ELSE 'new_events_' || TG_TABLE_SCHEMA END,
+        (to_jsonb(NEW) - 'payload' - 'inserted_at' - 'metadata' - 'event_version')::text
+    );
     RETURN new;
  END;
 $$ LANGUAGE plpgsql;
-
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS event_listener_dead_letter (
+    listener_id TEXT NOT NULL,
+    event_id BIGINT NOT NULL,
+    attempts INTEGER NOT NULL,
+    error TEXT NOT NULL,
+    failed_at TIMESTAMP DEFAULT now(),
+    PRIMARY KEY (listener_id, event_id)
+);
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS event_listener_instance (
+    id TEXT PRIMARY KEY,
+    heartbeat_at TIMESTAMP DEFAULT now()
+);
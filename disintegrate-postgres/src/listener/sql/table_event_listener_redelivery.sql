@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS event_listener_redelivery (
+    id BIGINT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    listener_id TEXT NOT NULL,
+    from_event_id BIGINT NOT NULL,
+    to_event_id BIGINT NOT NULL,
+    previous_last_processed_event_id BIGINT NOT NULL,
+    requested_at TIMESTAMP DEFAULT now()
+);
@@ -0,0 +1,18 @@
+CREATE OR REPLACE VIEW event_listener_lag AS
+SELECT
+    l.id AS listener_id,
+    COALESCE(l.last_processed_event_id, 0) AS last_processed_event_id,
+    l.updated_at AS last_processed_at,
+    h.last_event_id,
+    GREATEST(h.last_event_id - COALESCE(l.last_processed_event_id, 0), 0) AS lag_events,
+    p.inserted_at AS oldest_pending_event_at,
+    COALESCE(EXTRACT(EPOCH FROM LOCALTIMESTAMP - p.inserted_at), 0)::DOUBLE PRECISION AS lag_seconds
+FROM event_listener l
+CROSS JOIN (SELECT COALESCE(MAX(event_id), 0) AS last_event_id FROM event) h
+LEFT JOIN LATERAL (
+    SELECT e.inserted_at
+    FROM event e
+    WHERE e.event_id > COALESCE(l.last_processed_event_id, 0)
+    ORDER BY e.event_id
+    LIMIT 1
+) p ON true;
@@ -1,15 +1,20 @@
 use super::*;
 
+use std::collections::BTreeMap;
+
 use async_trait::async_trait;
 use disintegrate::{
     domain_identifiers, ident, query, DomainIdentifierInfo, DomainIdentifierSet, EventInfo,
-    EventSchema, EventStore, IdentifierType, PersistedEvent, StreamQuery,
+    EventSchema, EventStore, IdempotencyKey, IdentifierType, Metadata, PersistedEvent, Projection,
+    Retraction, StreamQuery,
 };
 use disintegrate_serde::serde::json::Json;
 
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, PgPool};
 
+use crate::PgEventStoreNamespace;
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "event_type", rename_all = "snake_case")]
 enum ShoppingCartEvent {
@@ -24,10 +29,18 @@ impl Event for ShoppingCartEvent {
             &EventInfo {
                 name: "ShoppingCartAdded",
                 domain_identifiers: &[&ident!(#product_id), &ident!(#cart_id)],
+                description: None,
+                owner: None,
+                aliases: &[],
+                version: 1,
             },
             &EventInfo {
                 name: "ShoppingCartRemoved",
                 domain_identifiers: &[&ident!(#product_id), &ident!(#cart_id)],
+                description: None,
+                owner: None,
+                aliases: &[],
+                version: 1,
             },
         ],
         domain_identifiers: &[
@@ -175,6 +188,41 @@ async fn it_handles_events(pool: PgPool) {
     assert_eq!(1, first_row.quantity);
 }
 
+#[sqlx::test]
+async fn it_limits_the_number_of_events_handled_per_second(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let event_handler_executor = PgEventListerExecutor::new(
+        event_store.clone(),
+        CartEventHandler::new(pool.clone()).await.unwrap(),
+        CancellationToken::new(),
+        PgEventListenerConfig::poller(Duration::from_secs(1)).max_events_per_second(10),
+    );
+    let events = (0..3)
+        .map(|i| {
+            ShoppingCartEvent::Added(CartEventPayload {
+                cart_id: "cart_1".to_string(),
+                product_id: format!("product_{i}"),
+                quantity: 1,
+            })
+        })
+        .collect();
+    event_store
+        .append(events, query!(ShoppingCartEvent; cart_id == "cart_1"), 0)
+        .await
+        .unwrap();
+
+    let started_at = Instant::now();
+    event_handler_executor.handle_events_from(0).await.unwrap();
+
+    assert!(started_at.elapsed() >= Duration::from_millis(200));
+    assert_eq!(Cart::carts(&pool).await.unwrap().len(), 3);
+}
+
 #[sqlx::test]
 async fn it_runs_event_listeners(pool: PgPool) {
     let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
@@ -219,6 +267,41 @@ async fn it_runs_event_listeners(pool: PgPool) {
     assert_eq!(1, first_row.quantity);
 }
 
+#[sqlx::test]
+async fn it_registers_a_listener_of_the_event_store_events(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    event_store
+        .append(
+            vec![ShoppingCartEvent::Added(CartEventPayload {
+                cart_id: "cart_1".to_string(),
+                product_id: "product_1".to_string(),
+                quantity: 1,
+            })],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+
+    PgEventListener::builder(event_store)
+        .register(
+            CartEventHandler::new(pool.clone()).await.unwrap(),
+            PgEventListenerConfig::poller(Duration::from_millis(10)),
+        )
+        .start_with_shutdown(async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(Cart::carts(&pool).await.unwrap().len(), 1);
+}
+
 #[sqlx::test]
 async fn it_runs_event_listener_with_db_listener(pool: PgPool) {
     let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
@@ -262,3 +345,1719 @@ async fn it_runs_event_listener_with_db_listener(pool: PgPool) {
     assert_eq!("product_1", &first_row.product_id);
     assert_eq!(1, first_row.quantity);
 }
+
+#[sqlx::test]
+async fn it_wakes_the_listeners_after_an_append_in_the_same_process(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let appending_event_store = event_store.clone();
+
+    PgEventListener::builder(event_store)
+        .register_listener(
+            CartEventHandler::new(pool.clone()).await.unwrap(),
+            PgEventListenerConfig::poller(Duration::from_millis(5000)),
+        )
+        .start_with_shutdown(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            appending_event_store
+                .append(
+                    vec![ShoppingCartEvent::Added(CartEventPayload {
+                        cart_id: "cart_1".to_string(),
+                        product_id: "product_1".to_string(),
+                        quantity: 1,
+                    })],
+                    query!(ShoppingCartEvent),
+                    0,
+                )
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(150)).await;
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(Cart::carts(&pool).await.unwrap().len(), 1);
+}
+
+#[sqlx::test]
+async fn it_publishes_the_projection_rebuilt_progress_event(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+
+    let cart_id = "cart_1".to_string();
+    let product_id = "product_1".to_string();
+    let query = query!(ShoppingCartEvent; cart_id == cart_id, product_id == product_id);
+    let persisted_events = event_store
+        .append(
+            vec![ShoppingCartEvent::Added(CartEventPayload {
+                cart_id,
+                product_id,
+                quantity: 1,
+            })],
+            query,
+            0,
+        )
+        .await
+        .unwrap();
+
+    PgEventListener::builder(event_store.clone())
+        .register_listener(
+            CartEventHandler::new(pool.clone()).await.unwrap(),
+            PgEventListenerConfig::poller(Duration::from_millis(10)).with_progress_events(),
+        )
+        .start_with_shutdown(async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        })
+        .await
+        .unwrap();
+
+    let progress_events = event_store
+        .listener_progress(0)
+        .map(|event| event.unwrap().into_inner())
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(
+        progress_events,
+        vec![ListenerProgressEvent::ProjectionRebuilt {
+            listener_id: "carts".to_string(),
+            at_event_id: persisted_events[0].id(),
+        }]
+    );
+}
+
+#[sqlx::test]
+async fn it_exports_and_restores_the_listener_checkpoints(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    setup(&pool).await.unwrap();
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+    event_store
+        .append(
+            vec![ShoppingCartEvent::Added(CartEventPayload {
+                cart_id: "cart_1".to_string(),
+                product_id: "product_1".to_string(),
+                quantity: 1,
+            })],
+            query,
+            0,
+        )
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO event_listener (id, last_processed_event_id) VALUES ('carts', 1)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let checkpoints = event_store.export_listener_checkpoints().await.unwrap();
+    assert_eq!(checkpoints.last_event_id, 1);
+    assert_eq!(
+        checkpoints.checkpoints,
+        vec![ListenerCheckpoint {
+            listener_id: "carts".to_string(),
+            last_processed_event_id: 1,
+        }]
+    );
+
+    sqlx::query("UPDATE event_listener SET last_processed_event_id = 0")
+        .execute(&pool)
+        .await
+        .unwrap();
+    event_store
+        .restore_listener_checkpoints(&checkpoints)
+        .await
+        .unwrap();
+    assert_eq!(
+        event_store.export_listener_checkpoints().await.unwrap(),
+        checkpoints
+    );
+}
+
+#[sqlx::test]
+async fn it_exposes_the_lag_of_the_listeners_in_a_view(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    setup(&pool).await.unwrap();
+    let events: Vec<_> = (1..=3)
+        .map(|quantity| {
+            ShoppingCartEvent::Added(CartEventPayload {
+                cart_id: "cart_1".to_string(),
+                product_id: "product_1".to_string(),
+                quantity,
+            })
+        })
+        .collect();
+    event_store
+        .append(events, query!(ShoppingCartEvent; cart_id == "cart_1"), 0)
+        .await
+        .unwrap();
+    sqlx::query(
+        "INSERT INTO event_listener (id, last_processed_event_id) VALUES ('carts', 1), ('orders', 3)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let lag: Vec<(String, i64, i64, i64, bool, f64)> = sqlx::query_as(
+        "SELECT listener_id, last_processed_event_id, last_event_id, lag_events, oldest_pending_event_at IS NULL, lag_seconds FROM event_listener_lag ORDER BY listener_id",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+
+    assert_eq!(lag.len(), 2);
+    assert_eq!(lag[0].0, "carts");
+    assert_eq!((lag[0].1, lag[0].2, lag[0].3, lag[0].4), (1, 3, 2, false));
+    assert!(lag[0].5 >= 0.0);
+    assert_eq!(lag[1], ("orders".to_string(), 3, 3, 0, true, 0.0));
+}
+
+#[sqlx::test]
+async fn it_refuses_to_restore_a_checkpoint_ahead_of_the_store(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    setup(&pool).await.unwrap();
+    let mut checkpoints = event_store.export_listener_checkpoints().await.unwrap();
+    checkpoints.checkpoints.push(ListenerCheckpoint {
+        listener_id: "carts".to_string(),
+        last_processed_event_id: 10,
+    });
+
+    let result = event_store.restore_listener_checkpoints(&checkpoints).await;
+
+    assert!(matches!(
+        result,
+        Err(Error::CheckpointAheadOfStore { listener, last_processed_event_id: 10, last_event_id: 0 }) if listener == "carts"
+    ));
+}
+
+#[sqlx::test]
+async fn it_lowers_the_checkpoint_to_redeliver_a_range_of_events(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    setup(&pool).await.unwrap();
+    sqlx::query("INSERT INTO event_listener (id, last_processed_event_id) VALUES ('carts', 10)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let window = event_store
+        .request_redelivery("carts", 4..=6)
+        .await
+        .unwrap();
+
+    assert_eq!(window.previous_last_processed_event_id, 10);
+    let last_processed_event_id: PgEventId =
+        sqlx::query_scalar("SELECT last_processed_event_id FROM event_listener WHERE id = 'carts'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(last_processed_event_id, 3);
+    let recorded_windows: i64 = sqlx::query_scalar(
+        "SELECT count(*) FROM event_listener_redelivery WHERE listener_id = 'carts' AND from_event_id = 4 AND to_event_id = 6",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(recorded_windows, 1);
+    assert!(matches!(
+        event_store.request_redelivery("unknown", 4..=6).await,
+        Err(Error::UnknownListener(listener)) if listener == "unknown"
+    ));
+}
+
+struct NoMetrics;
+
+impl ProbeMetrics for NoMetrics {
+    fn record(&self, _report: &ProbeReport) {}
+}
+
+#[sqlx::test]
+async fn it_probes_the_store_end_to_end(pool: PgPool) {
+    PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(pool.clone(), Json::default())
+        .await
+        .unwrap();
+    setup(&pool).await.unwrap();
+    let probe = PgHealthProbe::new(pool.clone(), NoMetrics).timeout(Duration::from_secs(5));
+
+    let first_report = probe.probe().await.unwrap();
+    let second_report = probe.probe().await.unwrap();
+
+    assert!(second_report.end_to_end_latency >= second_report.append_latency);
+    let heartbeats: Vec<PgEventId> =
+        sqlx::query_scalar("SELECT event_id FROM event WHERE event_type = $1")
+            .bind(HEARTBEAT_EVENT_TYPE)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert!(first_report.event_id < second_report.event_id);
+    assert_eq!(heartbeats, vec![second_report.event_id]);
+}
+
+#[sqlx::test]
+async fn it_assigns_each_listener_to_a_single_instance(pool: PgPool) {
+    PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(pool.clone(), Json::default())
+        .await
+        .unwrap();
+    setup(&pool).await.unwrap();
+    let first = PgListenerAssignment::new("first");
+    let second = PgListenerAssignment::new("second");
+    let first_listeners = AssignedListeners::default();
+    let second_listeners = AssignedListeners::default();
+
+    first.refresh(&pool, &first_listeners).await.unwrap();
+    second.refresh(&pool, &second_listeners).await.unwrap();
+    first.refresh(&pool, &first_listeners).await.unwrap();
+
+    let listeners: Vec<String> = (0..20).map(|i| format!("listener_{i}")).collect();
+    for listener in &listeners {
+        assert_ne!(
+            first_listeners.owns(listener),
+            second_listeners.owns(listener)
+        );
+    }
+
+    second.leave(&pool).await.unwrap();
+    first.refresh(&pool, &first_listeners).await.unwrap();
+
+    assert!(listeners
+        .iter()
+        .all(|listener| first_listeners.owns(listener)));
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("the read model is unavailable")]
+struct ReadModelUnavailable;
+
+struct FailingEventHandler {
+    query: StreamQuery<PgEventId, ShoppingCartEvent>,
+}
+
+#[async_trait]
+impl EventListener<PgEventId, ShoppingCartEvent> for FailingEventHandler {
+    type Error = ReadModelUnavailable;
+    fn id(&self) -> &'static str {
+        "failing_carts"
+    }
+
+    fn query(&self) -> &StreamQuery<PgEventId, ShoppingCartEvent> {
+        &self.query
+    }
+
+    async fn handle(
+        &self,
+        _persisted_event: PersistedEvent<PgEventId, ShoppingCartEvent>,
+    ) -> Result<(), Self::Error> {
+        Err(ReadModelUnavailable)
+    }
+}
+
+#[sqlx::test]
+async fn it_aborts_a_failing_listener_according_to_its_retry_policy(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    event_store
+        .append(
+            vec![ShoppingCartEvent::Added(CartEventPayload {
+                cart_id: "cart_1".to_string(),
+                product_id: "product_1".to_string(),
+                quantity: 1,
+            })],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    let contexts = Arc::new(std::sync::Mutex::new(vec![]));
+    let recorded_contexts = contexts.clone();
+    let config = PgEventListenerConfig::poller(Duration::from_millis(10)).retry(
+        move |context: &RetryContext<'_>| {
+            recorded_contexts.lock().unwrap().push((
+                context.listener_id,
+                context.event_id,
+                context.error_chain(),
+            ));
+            MaxAttempts(2).retry(context)
+        },
+    );
+    let listener = PgEventListener::builder(event_store)
+        .register(
+            FailingEventHandler {
+                query: query!(ShoppingCartEvent),
+            },
+            config.clone(),
+        )
+        .register(
+            ConcurrentEventHandler {
+                query: query!(ShoppingCartEvent),
+                probe: Arc::new(ConcurrencyProbe::default()),
+            },
+            config,
+        );
+    let metrics = listener.retry_metrics("failing_carts").unwrap();
+    let other_metrics = listener.retry_metrics("concurrent_carts").unwrap();
+    assert!(listener.retry_metrics("unknown").is_none());
+
+    listener
+        .start_with_shutdown(async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(metrics.retries(), 1);
+    assert_eq!(metrics.aborts(), 1);
+    assert_eq!(metrics.consecutive_failures(), 2);
+    assert_eq!(other_metrics.retries(), 0);
+    assert_eq!(other_metrics.consecutive_failures(), 0);
+    assert_eq!(
+        *contexts.lock().unwrap(),
+        vec![
+            (
+                "failing_carts",
+                Some(1),
+                vec!["the read model is unavailable".to_string()]
+            );
+            2
+        ]
+    );
+}
+
+#[derive(Default)]
+struct ConcurrencyProbe {
+    in_flight: std::sync::atomic::AtomicUsize,
+    max_in_flight: std::sync::atomic::AtomicUsize,
+    in_flight_at_removal: std::sync::Mutex<Vec<usize>>,
+    handled: std::sync::Mutex<Vec<String>>,
+}
+
+struct ConcurrentEventHandler {
+    query: StreamQuery<PgEventId, ShoppingCartEvent>,
+    probe: Arc<ConcurrencyProbe>,
+}
+
+#[async_trait]
+impl EventListener<PgEventId, ShoppingCartEvent> for ConcurrentEventHandler {
+    type Error = ReadModelUnavailable;
+    fn id(&self) -> &'static str {
+        "concurrent_carts"
+    }
+
+    fn query(&self) -> &StreamQuery<PgEventId, ShoppingCartEvent> {
+        &self.query
+    }
+
+    async fn handle(
+        &self,
+        persisted_event: PersistedEvent<PgEventId, ShoppingCartEvent>,
+    ) -> Result<(), Self::Error> {
+        match persisted_event.into_inner() {
+            ShoppingCartEvent::Added(payload) => {
+                let in_flight = self.probe.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.probe
+                    .max_in_flight
+                    .fetch_max(in_flight, Ordering::SeqCst);
+                let duration = if payload.product_id == "slow" {
+                    100
+                } else {
+                    20
+                };
+                tokio::time::sleep(Duration::from_millis(duration)).await;
+                self.probe.in_flight.fetch_sub(1, Ordering::SeqCst);
+                self.probe
+                    .handled
+                    .lock()
+                    .unwrap()
+                    .push(payload.product_id.clone());
+                if payload.product_id == "broken" {
+                    return Err(ReadModelUnavailable);
+                }
+            }
+            ShoppingCartEvent::Removed(_) => self
+                .probe
+                .in_flight_at_removal
+                .lock()
+                .unwrap()
+                .push(self.probe.in_flight.load(Ordering::SeqCst)),
+        }
+        Ok(())
+    }
+}
+
+fn cart_payload(product_id: &str) -> CartEventPayload {
+    CartEventPayload {
+        cart_id: "cart_1".to_string(),
+        product_id: product_id.to_string(),
+        quantity: 1,
+    }
+}
+
+async fn last_processed_event_id(pool: &PgPool, listener_id: &str) -> PgEventId {
+    sqlx::query_scalar("SELECT last_processed_event_id FROM event_listener WHERE id = $1")
+        .bind(listener_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn it_handles_the_concurrent_events_concurrently_and_the_others_in_order(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    event_store
+        .append(
+            vec![
+                ShoppingCartEvent::Added(cart_payload("product_1")),
+                ShoppingCartEvent::Added(cart_payload("product_2")),
+                ShoppingCartEvent::Added(cart_payload("product_3")),
+                ShoppingCartEvent::Removed(cart_payload("product_1")),
+                ShoppingCartEvent::Added(cart_payload("product_4")),
+            ],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    let probe = Arc::new(ConcurrencyProbe::default());
+
+    PgEventListener::builder(event_store)
+        .register(
+            ConcurrentEventHandler {
+                query: query!(ShoppingCartEvent),
+                probe: probe.clone(),
+            },
+            PgEventListenerConfig::poller(Duration::from_millis(10))
+                .handle_concurrently(["ShoppingCartAdded"], 3),
+        )
+        .start_with_shutdown(async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(probe.max_in_flight.load(Ordering::SeqCst), 3);
+    assert_eq!(*probe.in_flight_at_removal.lock().unwrap(), vec![0]);
+    assert_eq!(last_processed_event_id(&pool, "concurrent_carts").await, 5);
+}
+
+#[sqlx::test]
+async fn it_advances_the_checkpoint_only_over_the_handled_prefix(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    event_store
+        .append(
+            vec![
+                ShoppingCartEvent::Added(cart_payload("product_1")),
+                ShoppingCartEvent::Added(cart_payload("broken")),
+                ShoppingCartEvent::Added(cart_payload("product_3")),
+            ],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+
+    PgEventListener::builder(event_store)
+        .register(
+            ConcurrentEventHandler {
+                query: query!(ShoppingCartEvent),
+                probe: Arc::new(ConcurrencyProbe::default()),
+            },
+            PgEventListenerConfig::poller(Duration::from_millis(10))
+                .handle_concurrently(["ShoppingCartAdded"], 3)
+                .retry(MaxAttempts(1)),
+        )
+        .start_with_shutdown(async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(last_processed_event_id(&pool, "concurrent_carts").await, 1);
+}
+
+#[sqlx::test]
+async fn it_starts_a_concurrent_event_as_soon_as_an_event_in_flight_is_handled(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    event_store
+        .append(
+            vec![
+                ShoppingCartEvent::Added(cart_payload("slow")),
+                ShoppingCartEvent::Added(cart_payload("product_2")),
+                ShoppingCartEvent::Added(cart_payload("product_3")),
+                ShoppingCartEvent::Added(cart_payload("product_4")),
+            ],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    let probe = Arc::new(ConcurrencyProbe::default());
+
+    PgEventListener::builder(event_store)
+        .register(
+            ConcurrentEventHandler {
+                query: query!(ShoppingCartEvent),
+                probe: probe.clone(),
+            },
+            PgEventListenerConfig::poller(Duration::from_millis(10))
+                .handle_concurrently(["ShoppingCartAdded"], 2),
+        )
+        .start_with_shutdown(async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(probe.max_in_flight.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        *probe.handled.lock().unwrap(),
+        vec!["product_2", "product_3", "product_4", "slow"]
+    );
+    assert_eq!(last_processed_event_id(&pool, "concurrent_carts").await, 4);
+}
+
+#[sqlx::test]
+async fn it_lets_the_events_in_flight_finish_before_stopping_at_a_failure(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    event_store
+        .append(
+            vec![
+                ShoppingCartEvent::Added(cart_payload("broken")),
+                ShoppingCartEvent::Added(cart_payload("slow")),
+            ],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    let probe = Arc::new(ConcurrencyProbe::default());
+
+    PgEventListener::builder(event_store)
+        .register(
+            ConcurrentEventHandler {
+                query: query!(ShoppingCartEvent),
+                probe: probe.clone(),
+            },
+            PgEventListenerConfig::poller(Duration::from_millis(10))
+                .handle_concurrently(["ShoppingCartAdded"], 3)
+                .retry(MaxAttempts(1)),
+        )
+        .start_with_shutdown(async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(*probe.handled.lock().unwrap(), vec!["broken", "slow"]);
+    assert_eq!(last_processed_event_id(&pool, "concurrent_carts").await, 0);
+}
+
+#[test]
+#[should_panic(expected = "the maximum concurrency must be greater than zero")]
+fn it_rejects_a_zero_maximum_concurrency() {
+    let _ = PgEventListenerConfig::poller(Duration::from_millis(10))
+        .handle_concurrently(["ShoppingCartAdded"], 0);
+}
+
+#[test]
+#[should_panic(expected = "the off-peak hours must be between 0 and 23")]
+fn it_rejects_the_off_peak_hours_out_of_the_day() {
+    let _ = PgEventListenerConfig::poller(Duration::from_millis(10)).off_peak_hours(22, 30);
+}
+
+#[sqlx::test]
+async fn it_skips_a_failing_event_into_the_dead_letter_table(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    event_store
+        .append(
+            vec![
+                ShoppingCartEvent::Added(cart_payload("product_1")),
+                ShoppingCartEvent::Added(cart_payload("broken")),
+                ShoppingCartEvent::Added(cart_payload("product_3")),
+            ],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    let config = PgEventListenerConfig::poller(Duration::from_millis(10)).retry(SkipAfter(2));
+    let listener = PgEventListener::builder(event_store).register(
+        ConcurrentEventHandler {
+            query: query!(ShoppingCartEvent),
+            probe: Arc::new(ConcurrencyProbe::default()),
+        },
+        config,
+    );
+    let metrics = listener.retry_metrics("concurrent_carts").unwrap();
+
+    listener
+        .start_with_shutdown(async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        })
+        .await
+        .unwrap();
+
+    let dead_letters: Vec<(String, i64, i32, String)> = sqlx::query_as(
+        "SELECT listener_id, event_id, attempts, error FROM event_listener_dead_letter",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        dead_letters,
+        vec![(
+            "concurrent_carts".to_string(),
+            2,
+            2,
+            "the read model is unavailable".to_string()
+        )]
+    );
+    assert_eq!(last_processed_event_id(&pool, "concurrent_carts").await, 3);
+    assert_eq!(metrics.retries(), 1);
+    assert_eq!(metrics.skips(), 1);
+    assert_eq!(metrics.consecutive_failures(), 0);
+}
+
+#[sqlx::test]
+async fn it_waits_for_the_events_of_a_transaction_not_yet_committed(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    event_store
+        .append(
+            vec![ShoppingCartEvent::Added(cart_payload("product_1"))],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    // reserves the event ID 2, as a concurrent append does before committing its events.
+    sqlx::query("INSERT INTO event_sequence (event_type) VALUES ('ShoppingCartAdded')")
+        .execute(&pool)
+        .await
+        .unwrap();
+    event_store
+        .append(
+            vec![ShoppingCartEvent::Added(cart_payload("product_3"))],
+            query!(ShoppingCartEvent),
+            2,
+        )
+        .await
+        .unwrap();
+    let run_listener = |config: PgEventListenerConfig| {
+        PgEventListener::builder(event_store.clone())
+            .register(
+                ConcurrentEventHandler {
+                    query: query!(ShoppingCartEvent),
+                    probe: Arc::new(ConcurrencyProbe::default()),
+                },
+                config,
+            )
+            .start_with_shutdown(async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            })
+    };
+
+    run_listener(PgEventListenerConfig::poller(Duration::from_millis(10)))
+        .await
+        .unwrap();
+    assert_eq!(last_processed_event_id(&pool, "concurrent_carts").await, 1);
+
+    run_listener(
+        PgEventListenerConfig::poller(Duration::from_millis(10)).gap_timeout(Duration::ZERO),
+    )
+    .await
+    .unwrap();
+    assert_eq!(last_processed_event_id(&pool, "concurrent_carts").await, 3);
+}
+
+#[sqlx::test]
+async fn it_fails_to_start_when_an_excluded_event_is_unknown(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+
+    let result = PgEventListener::builder(event_store)
+        .register_listener(
+            CartEventHandler::new(pool.clone()).await.unwrap(),
+            PgEventListenerConfig::poller(Duration::from_millis(10))
+                .exclude_events(["ShoppingCartRemoved", "ShoppingCartUpdated"]),
+        )
+        .start_with_shutdown(async {})
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(Error::UnknownExcludedEvent { listener: "carts", event }) if event == "ShoppingCartUpdated"
+    ));
+}
+
+#[derive(Default)]
+struct PartitionProbe {
+    probe: ConcurrencyProbe,
+    handled: std::sync::Mutex<Vec<(String, String)>>,
+}
+
+struct PartitionedEventHandler {
+    query: StreamQuery<PgEventId, ShoppingCartEvent>,
+    probe: Arc<PartitionProbe>,
+}
+
+#[async_trait]
+impl EventListener<PgEventId, ShoppingCartEvent> for PartitionedEventHandler {
+    type Error = ReadModelUnavailable;
+    fn id(&self) -> &'static str {
+        "partitioned_carts"
+    }
+
+    fn query(&self) -> &StreamQuery<PgEventId, ShoppingCartEvent> {
+        &self.query
+    }
+
+    async fn handle(
+        &self,
+        persisted_event: PersistedEvent<PgEventId, ShoppingCartEvent>,
+    ) -> Result<(), Self::Error> {
+        let (ShoppingCartEvent::Added(payload) | ShoppingCartEvent::Removed(payload)) =
+            persisted_event.into_inner();
+        let in_flight = self.probe.probe.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.probe
+            .probe
+            .max_in_flight
+            .fetch_max(in_flight, Ordering::SeqCst);
+        // The first events of a cart take longer, so that an unordered handling would be visible.
+        let delay = if payload.product_id == "product_1" {
+            40
+        } else {
+            5
+        };
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+        self.probe.probe.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.probe
+            .handled
+            .lock()
+            .unwrap()
+            .push((payload.cart_id, payload.product_id));
+        Ok(())
+    }
+}
+
+#[sqlx::test]
+async fn it_handles_the_partitions_concurrently_preserving_the_order_of_each_one(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let carts = ["cart_1", "cart_2", "cart_3"];
+    let events = ["product_1", "product_2", "product_3"]
+        .iter()
+        .flat_map(|product_id| {
+            carts.iter().map(|cart_id| {
+                ShoppingCartEvent::Added(CartEventPayload {
+                    cart_id: cart_id.to_string(),
+                    product_id: product_id.to_string(),
+                    quantity: 1,
+                })
+            })
+        })
+        .collect();
+    event_store
+        .append(events, query!(ShoppingCartEvent), 0)
+        .await
+        .unwrap();
+    let probe = Arc::new(PartitionProbe::default());
+
+    PgEventListener::builder(event_store)
+        .register(
+            PartitionedEventHandler {
+                query: query!(ShoppingCartEvent),
+                probe: probe.clone(),
+            },
+            PgEventListenerConfig::poller(Duration::from_millis(10))
+                .partitioned_by(ident!(#cart_id), 16),
+        )
+        .start_with_shutdown(async {
+            tokio::time::sleep(Duration::from_millis(400)).await;
+        })
+        .await
+        .unwrap();
+
+    let handled = probe.handled.lock().unwrap().clone();
+    for cart_id in carts {
+        let products: Vec<&str> = handled
+            .iter()
+            .filter(|(cart, _)| cart == cart_id)
+            .map(|(_, product)| product.as_str())
+            .collect();
+        assert_eq!(products, vec!["product_1", "product_2", "product_3"]);
+    }
+    assert!(probe.probe.max_in_flight.load(Ordering::SeqCst) > 1);
+    assert_eq!(last_processed_event_id(&pool, "partitioned_carts").await, 9);
+}
+
+#[sqlx::test]
+async fn it_fails_to_start_when_the_partition_identifier_is_unknown(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+
+    let result = PgEventListener::builder(event_store)
+        .register_listener(
+            CartEventHandler::new(pool.clone()).await.unwrap(),
+            PgEventListenerConfig::poller(Duration::from_millis(10))
+                .partitioned_by(ident!(#account_id), 4),
+        )
+        .start_with_shutdown(async {})
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(Error::UnknownPartitionIdentifier { listener: "carts", identifier }) if identifier == "account_id"
+    ));
+}
+
+struct ShardedEventHandler {
+    query: StreamQuery<PgEventId, ShoppingCartEvent>,
+    handled: Arc<std::sync::Mutex<Vec<(String, String)>>>,
+}
+
+#[async_trait]
+impl EventListener<PgEventId, ShoppingCartEvent> for ShardedEventHandler {
+    type Error = ReadModelUnavailable;
+    fn id(&self) -> &'static str {
+        "sharded_carts"
+    }
+
+    fn query(&self) -> &StreamQuery<PgEventId, ShoppingCartEvent> {
+        &self.query
+    }
+
+    async fn handle(
+        &self,
+        persisted_event: PersistedEvent<PgEventId, ShoppingCartEvent>,
+    ) -> Result<(), Self::Error> {
+        let (ShoppingCartEvent::Added(payload) | ShoppingCartEvent::Removed(payload)) =
+            persisted_event.into_inner();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        self.handled
+            .lock()
+            .unwrap()
+            .push((payload.cart_id, payload.product_id));
+        Ok(())
+    }
+}
+
+#[sqlx::test]
+async fn it_shares_a_sharded_listener_across_processes(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let carts: Vec<String> = (1..=6).map(|cart| format!("cart_{cart}")).collect();
+    let events = ["product_1", "product_2"]
+        .iter()
+        .flat_map(|product_id| {
+            carts.iter().map(|cart_id| {
+                ShoppingCartEvent::Added(CartEventPayload {
+                    cart_id: cart_id.clone(),
+                    product_id: product_id.to_string(),
+                    quantity: 1,
+                })
+            })
+        })
+        .collect();
+    event_store
+        .append(events, query!(ShoppingCartEvent), 0)
+        .await
+        .unwrap();
+    let handled = Arc::new(std::sync::Mutex::new(vec![]));
+    let process = |event_store: PgEventStore<ShoppingCartEvent, Json<ShoppingCartEvent>>| {
+        PgEventListener::builder(event_store)
+            .register(
+                ShardedEventHandler {
+                    query: query!(ShoppingCartEvent),
+                    handled: handled.clone(),
+                },
+                PgEventListenerConfig::poller(Duration::from_millis(10))
+                    .sharded_by(ident!(#cart_id), 4),
+            )
+            .start_with_shutdown(async {
+                tokio::time::sleep(Duration::from_millis(400)).await;
+            })
+    };
+
+    let (first, second) = tokio::join!(process(event_store.clone()), process(event_store));
+    first.unwrap();
+    second.unwrap();
+
+    let handled = handled.lock().unwrap().clone();
+    assert_eq!(handled.len(), 12);
+    for cart_id in &carts {
+        let products: Vec<&str> = handled
+            .iter()
+            .filter(|(cart, _)| cart == cart_id)
+            .map(|(_, product)| product.as_str())
+            .collect();
+        assert_eq!(products, vec!["product_1", "product_2"]);
+    }
+    let shards: Vec<(String, i64, i64, i64)> = sqlx::query_as(
+        "SELECT id, last_processed_event_id, hash_from, hash_to FROM event_listener WHERE listener_id = 'sharded_carts' ORDER BY hash_from",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        shards
+            .iter()
+            .map(|(id, last_processed_event_id, _, _)| (id.as_str(), *last_processed_event_id))
+            .collect::<Vec<_>>(),
+        vec![
+            ("sharded_carts#0/4", 12),
+            ("sharded_carts#1/4", 12),
+            ("sharded_carts#2/4", 12),
+            ("sharded_carts#3/4", 12)
+        ]
+    );
+    assert_eq!(shards[0].2, 0);
+    assert_eq!(shards[3].3, 1 << 32);
+}
+
+#[sqlx::test]
+async fn it_starts_the_shards_from_the_checkpoint_of_the_listener(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    event_store
+        .append(
+            vec![
+                ShoppingCartEvent::Added(cart_payload("product_1")),
+                ShoppingCartEvent::Added(cart_payload("product_2")),
+            ],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    setup(&pool).await.unwrap();
+    sqlx::query(
+        "INSERT INTO event_listener (id, last_processed_event_id) VALUES ('sharded_carts', 1)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let handled = Arc::new(std::sync::Mutex::new(vec![]));
+
+    PgEventListener::builder(event_store)
+        .register(
+            ShardedEventHandler {
+                query: query!(ShoppingCartEvent),
+                handled: handled.clone(),
+            },
+            PgEventListenerConfig::poller(Duration::from_millis(10))
+                .sharded_by(ident!(#cart_id), 2),
+        )
+        .start_with_shutdown(async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(
+        *handled.lock().unwrap(),
+        vec![("cart_1".to_string(), "product_2".to_string())]
+    );
+}
+
+#[sqlx::test]
+async fn it_describes_the_registered_listeners(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+
+    let listener = PgEventListener::builder(event_store).register_listener(
+        CartEventHandler {
+            query: query!(ShoppingCartEvent; cart_id == "c1"),
+            pool,
+        },
+        PgEventListenerConfig::poller(Duration::from_secs(5))
+            .with_notifier()
+            .priority(2)
+            .exclude_events(["ShoppingCartRemoved"]),
+    );
+
+    let listeners = listener.listeners();
+    assert_eq!(listeners.len(), 1);
+    let description = &listeners[0];
+    assert_eq!(description.id, "carts");
+    assert_eq!(description.event_types, vec!["ShoppingCartAdded"]);
+    assert_eq!(description.excluded_events, vec!["ShoppingCartRemoved"]);
+    assert_eq!(
+        description.identifiers,
+        BTreeMap::from([("cart_id".to_string(), vec!["c1".to_string()])])
+    );
+    assert_eq!(description.poll, Duration::from_secs(5));
+    assert!(description.notifier_enabled);
+    assert_eq!(description.priority, 2);
+    assert_eq!(
+        description.to_string(),
+        "carts: events [ShoppingCartAdded], identifiers cart_id in [c1], poll 5s, priority 2, notifier"
+    );
+}
+
+#[test]
+fn it_wakes_only_for_events_matching_the_query_identifiers() {
+    let (wake_tx, wake_rx) = watch::channel(true);
+    let waker = ExecutorWaker {
+        wake_tx,
+        query: query!(ShoppingCartEvent; cart_id == "cart_1"),
+    };
+
+    waker.wake(r#"{"event_id": 1, "event_type": "ShoppingCartAdded", "cart_id": "cart_2", "product_id": "product_1"}"#);
+    assert!(!wake_rx.has_changed().unwrap());
+
+    waker.wake(r#"{"event_id": 2, "event_type": "ShoppingCartAdded", "cart_id": "cart_1", "product_id": "product_1"}"#);
+    assert!(wake_rx.has_changed().unwrap());
+}
+
+#[test]
+fn it_wakes_for_notifications_containing_only_the_event_type() {
+    let (wake_tx, wake_rx) = watch::channel(true);
+    let waker = ExecutorWaker {
+        wake_tx,
+        query: query!(ShoppingCartEvent; cart_id == "cart_1"),
+    };
+
+    waker.wake("ShoppingCartAdded");
+    assert!(wake_rx.has_changed().unwrap());
+}
+
+#[test]
+fn it_parses_the_notifications_of_the_inserted_events() {
+    let notification = EventNotification::parse::<ShoppingCartEvent>(
+        r#"{"event_id": 7, "event_type": "ShoppingCartAdded", "cart_id": "cart_1", "product_id": null, "event_version": 1, "metadata": {}}"#,
+    )
+    .unwrap();
+
+    assert_eq!(notification.event_id, 7);
+    assert_eq!(notification.event_type, "ShoppingCartAdded");
+    assert_eq!(
+        notification.identifiers,
+        domain_identifiers! {cart_id: "cart_1"}
+    );
+    assert!(notification.matches(&query!(ShoppingCartEvent; cart_id == "cart_1")));
+    assert!(!notification.matches(&query!(ShoppingCartEvent; cart_id == "cart_2")));
+    assert_eq!(
+        EventNotification::parse::<ShoppingCartEvent>("ShoppingCartAdded"),
+        None
+    );
+}
+
+#[sqlx::test]
+async fn it_calls_the_notify_callbacks_of_the_matching_events(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let appending_event_store = event_store.clone();
+    let notified = Arc::new(std::sync::Mutex::new(vec![]));
+    let recorded = notified.clone();
+
+    PgEventListener::builder(event_store)
+        .on_notify(
+            query!(ShoppingCartEvent; cart_id == "cart_1"),
+            move |notification| recorded.lock().unwrap().push(notification.event_id),
+        )
+        .start_with_shutdown(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            appending_event_store
+                .append(
+                    vec![
+                        ShoppingCartEvent::Added(cart_payload("product_1")),
+                        ShoppingCartEvent::Added(CartEventPayload {
+                            cart_id: "cart_2".to_string(),
+                            product_id: "product_1".to_string(),
+                            quantity: 1,
+                        }),
+                    ],
+                    query!(ShoppingCartEvent),
+                    0,
+                )
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(*notified.lock().unwrap(), vec![1]);
+}
+
+#[sqlx::test]
+async fn it_notifies_the_events_with_metadata_larger_than_a_notification(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let notified = Arc::new(std::sync::Mutex::new(vec![]));
+    let recorded = notified.clone();
+    // a notification is limited to 8000 bytes, so the metadata must not be part of it.
+    let metadata = serde_json::json!({ "user_id": "a".repeat(10_000) }).to_string();
+    let inserting_pool = pool.clone();
+
+    PgEventListener::builder(event_store)
+        .on_notify(query!(ShoppingCartEvent), move |notification| {
+            recorded.lock().unwrap().push(notification.event_id)
+        })
+        .start_with_shutdown(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            sqlx::query(
+                "INSERT INTO event (event_id, event_type, payload, cart_id, product_id, metadata) VALUES (1, 'ShoppingCartAdded', '{}', 'cart_1', 'product_1', $1::JSONB)",
+            )
+            .bind(metadata)
+            .execute(&inserting_pool)
+            .await
+            .unwrap();
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(*notified.lock().unwrap(), vec![1]);
+}
+
+#[sqlx::test]
+async fn it_notifies_the_events_of_a_namespace_on_its_own_channel(pool: PgPool) {
+    let orders = PgEventStoreNamespace::new(&pool, "orders").await.unwrap();
+    let payments = PgEventStoreNamespace::new(&pool, "payments").await.unwrap();
+    let event_store = orders
+        .event_store::<ShoppingCartEvent, _>(Json::default())
+        .await
+        .unwrap();
+    let payments_event_store = payments
+        .event_store::<ShoppingCartEvent, _>(Json::default())
+        .await
+        .unwrap();
+    setup(payments.pool()).await.unwrap();
+    let appending_event_store = event_store.clone();
+    let notified = Arc::new(std::sync::Mutex::new(vec![]));
+    let recorded = notified.clone();
+
+    PgEventListener::builder(event_store)
+        .on_notify(query!(ShoppingCartEvent), move |notification| {
+            recorded.lock().unwrap().push(notification.event_id)
+        })
+        .start_with_shutdown(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            payments_event_store
+                .append(
+                    vec![
+                        ShoppingCartEvent::Added(cart_payload("product_1")),
+                        ShoppingCartEvent::Added(cart_payload("product_2")),
+                    ],
+                    query!(ShoppingCartEvent),
+                    0,
+                )
+                .await
+                .unwrap();
+            appending_event_store
+                .append(
+                    vec![ShoppingCartEvent::Added(cart_payload("product_3"))],
+                    query!(ShoppingCartEvent),
+                    0,
+                )
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(orders.notification_channel(), "new_events_orders");
+    assert_eq!(*notified.lock().unwrap(), vec![1]);
+}
+
+struct CartProjection(CartEventHandler);
+
+#[async_trait]
+impl Projection<PgEventId, ShoppingCartEvent> for CartProjection {
+    type Error = sqlx::Error;
+
+    fn name(&self) -> &'static str {
+        "carts_projection"
+    }
+
+    fn query(&self) -> &StreamQuery<PgEventId, ShoppingCartEvent> {
+        &self.0.query
+    }
+
+    async fn apply(
+        &self,
+        event: PersistedEvent<PgEventId, ShoppingCartEvent>,
+        _key: IdempotencyKey<PgEventId>,
+    ) -> Result<(), Self::Error> {
+        self.0.handle(event).await
+    }
+
+    async fn reset(&self) -> Result<(), Self::Error> {
+        sqlx::query("DELETE FROM carts")
+            .execute(&self.0.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[sqlx::test]
+async fn it_rebuilds_a_projection_on_demand(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let persisted_events = event_store
+        .append(
+            vec![ShoppingCartEvent::Added(cart_payload("product_1"))],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    let run_projections = || async {
+        let runner = PgProjectionRunner::builder(event_store.clone()).register(
+            CartProjection(CartEventHandler::new(pool.clone()).await.unwrap()),
+            PgEventListenerConfig::poller(Duration::from_millis(10)),
+        );
+        let rebuilder = runner.rebuilder();
+        runner
+            .start_with_shutdown(async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            })
+            .await
+            .unwrap();
+        rebuilder
+    };
+
+    let rebuilder = run_projections().await;
+    assert_eq!(Cart::carts(&pool).await.unwrap().len(), 1);
+    assert_eq!(rebuilder.projections(), vec!["carts_projection"]);
+
+    rebuilder.rebuild("carts_projection").await.unwrap();
+    assert!(Cart::carts(&pool).await.unwrap().is_empty());
+    assert_eq!(last_processed_event_id(&pool, "carts_projection").await, 0);
+
+    run_projections().await;
+    assert_eq!(Cart::carts(&pool).await.unwrap().len(), 1);
+    assert_eq!(
+        last_processed_event_id(&pool, "carts_projection").await,
+        persisted_events[0].id()
+    );
+    assert!(matches!(
+        rebuilder.rebuild("unknown").await,
+        Err(Error::UnknownProjection(name)) if name == "unknown"
+    ));
+}
+
+struct RetractionRecorder {
+    query: StreamQuery<PgEventId, ShoppingCartEvent>,
+    handled: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl EventListener<PgEventId, ShoppingCartEvent> for RetractionRecorder {
+    type Error = ReadModelUnavailable;
+    fn id(&self) -> &'static str {
+        "retractions"
+    }
+
+    fn query(&self) -> &StreamQuery<PgEventId, ShoppingCartEvent> {
+        &self.query
+    }
+
+    async fn handle(
+        &self,
+        persisted_event: PersistedEvent<PgEventId, ShoppingCartEvent>,
+    ) -> Result<(), Self::Error> {
+        self.handled
+            .lock()
+            .unwrap()
+            .push(format!("handled {}", persisted_event.id()));
+        Ok(())
+    }
+
+    async fn handle_retraction(
+        &self,
+        retraction: Retraction<PgEventId>,
+    ) -> Result<(), Self::Error> {
+        self.handled
+            .lock()
+            .unwrap()
+            .push(format!("retracted {}", retraction.event_id));
+        Ok(())
+    }
+}
+
+#[sqlx::test]
+async fn it_replays_the_events_of_a_listener_from_an_origin(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    event_store
+        .append(
+            vec![
+                ShoppingCartEvent::Added(cart_payload("product_1")),
+                ShoppingCartEvent::Added(cart_payload("product_2")),
+                ShoppingCartEvent::Added(cart_payload("product_3")),
+            ],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    let handled = Arc::new(std::sync::Mutex::new(vec![]));
+    let reports = Arc::new(std::sync::Mutex::new(vec![]));
+    let reset = Arc::new(AtomicBool::new(false));
+
+    let progress = PgReplayer::new(
+        event_store,
+        ShardedEventHandler {
+            query: query!(ShoppingCartEvent),
+            handled: handled.clone(),
+        },
+    )
+    .from(1)
+    .reset_with({
+        let reset = reset.clone();
+        move || async move {
+            reset.store(true, Ordering::Relaxed);
+            Ok::<(), sqlx::Error>(())
+        }
+    })
+    .max_events_per_second(100)
+    .on_progress(1, {
+        let reports = reports.clone();
+        move |progress: &ReplayProgress| reports.lock().unwrap().push(progress.last_event_id)
+    })
+    .run()
+    .await
+    .unwrap();
+
+    assert!(reset.load(Ordering::Relaxed));
+    let products: Vec<String> = handled
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(_, product_id)| product_id.clone())
+        .collect();
+    assert_eq!(products, vec!["product_2", "product_3"]);
+    assert_eq!(progress.replayed, 2);
+    assert_eq!(progress.target_event_id, 3);
+    assert_eq!(*reports.lock().unwrap(), vec![2, 3, 3]);
+    assert_eq!(last_processed_event_id(&pool, "sharded_carts").await, 3);
+}
+
+#[sqlx::test]
+async fn it_keeps_the_checkpoint_of_a_failed_replay(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    event_store
+        .append(
+            vec![
+                ShoppingCartEvent::Added(cart_payload("product_1")),
+                ShoppingCartEvent::Added(cart_payload("broken")),
+                ShoppingCartEvent::Added(cart_payload("product_3")),
+            ],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+
+    let result = PgReplayer::new(
+        event_store,
+        ConcurrentEventHandler {
+            query: query!(ShoppingCartEvent),
+            probe: Arc::new(ConcurrencyProbe::default()),
+        },
+    )
+    .run()
+    .await;
+
+    assert!(matches!(
+        result,
+        Err(Error::ReplayFailed {
+            listener: "concurrent_carts",
+            event_id: 2,
+            ..
+        })
+    ));
+    assert_eq!(last_processed_event_id(&pool, "concurrent_carts").await, 1);
+}
+
+#[sqlx::test]
+async fn it_delivers_the_retractions_in_order_with_the_events(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let first = event_store
+        .append(
+            vec![
+                ShoppingCartEvent::Added(cart_payload("product_1")),
+                ShoppingCartEvent::Added(cart_payload("product_2")),
+            ],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    let retraction_id = event_store
+        .retract(first[0].id(), "wrong product")
+        .await
+        .unwrap();
+    let second = event_store
+        .append(
+            vec![ShoppingCartEvent::Removed(cart_payload("product_2"))],
+            query!(ShoppingCartEvent),
+            retraction_id,
+        )
+        .await
+        .unwrap();
+    let recorded = Arc::new(std::sync::Mutex::new(vec![]));
+    let executor = PgEventListerExecutor::new(
+        event_store.clone(),
+        RetractionRecorder {
+            query: query!(ShoppingCartEvent),
+            handled: recorded.clone(),
+        },
+        CancellationToken::new(),
+        PgEventListenerConfig::poller(Duration::from_secs(1)).with_retractions(),
+    );
+
+    let handled = executor.handle_events_from(0).await.unwrap();
+
+    assert_eq!(handled.last_processed_event_id, second[0].id());
+    assert_eq!(
+        *recorded.lock().unwrap(),
+        vec![
+            format!("handled {}", first[0].id()),
+            format!("handled {}", first[1].id()),
+            format!("retracted {}", first[0].id()),
+            format!("handled {}", second[0].id()),
+        ]
+    );
+}
+
+struct ContextRecordingHandler {
+    query: StreamQuery<PgEventId, ShoppingCartEvent>,
+    contexts: Arc<std::sync::Mutex<Vec<Option<DecisionContext>>>>,
+}
+
+#[async_trait]
+impl EventListener<PgEventId, ShoppingCartEvent> for ContextRecordingHandler {
+    type Error = sqlx::Error;
+    fn id(&self) -> &'static str {
+        "context_recording"
+    }
+
+    fn query(&self) -> &StreamQuery<PgEventId, ShoppingCartEvent> {
+        &self.query
+    }
+
+    async fn handle(
+        &self,
+        _persisted_event: PersistedEvent<PgEventId, ShoppingCartEvent>,
+    ) -> Result<(), Self::Error> {
+        self.contexts
+            .lock()
+            .unwrap()
+            .push(DecisionContext::current());
+        Ok(())
+    }
+}
+
+#[sqlx::test]
+async fn it_handles_each_event_in_the_context_of_the_event(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    event_store
+        .append_with_metadata(
+            vec![ShoppingCartEvent::Added(cart_payload("product_1"))],
+            Metadata::new().with_correlation_id("request-1"),
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    let contexts = Arc::new(std::sync::Mutex::new(vec![]));
+    let event_handler_executor = PgEventListerExecutor::new(
+        event_store,
+        ContextRecordingHandler {
+            query: query!(ShoppingCartEvent),
+            contexts: contexts.clone(),
+        },
+        CancellationToken::new(),
+        PgEventListenerConfig::poller(Duration::from_secs(1)),
+    );
+
+    event_handler_executor.handle_events_from(0).await.unwrap();
+
+    let context = contexts.lock().unwrap()[0].clone().unwrap();
+    assert_eq!(context.metadata().correlation_id(), Some("request-1"));
+    assert_eq!(context.metadata().causation_id(), Some("1"));
+}
+
+#[sqlx::test]
+async fn it_broadcasts_the_new_events_matching_the_queries_of_the_subscribers(pool: PgPool) {
+    let event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    // a store without the append observers of the broadcaster, as if it were in another process.
+    let remote_event_store = PgEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    let added = |cart_id: &str, product_id: &str| {
+        ShoppingCartEvent::Added(CartEventPayload {
+            cart_id: cart_id.to_string(),
+            product_id: product_id.to_string(),
+            quantity: 1,
+        })
+    };
+    event_store
+        .append(
+            vec![added("cart_1", "product_0")],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    let broadcaster = PgEventBroadcaster::new(event_store.clone());
+    let mut feed = broadcaster.subscribe(query!(ShoppingCartEvent; cart_id == "cart_1"));
+    let shutdown = CancellationToken::new();
+    let running = tokio::spawn(broadcaster.start_with_shutdown(shutdown.clone().cancelled_owned()));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    remote_event_store
+        .append(
+            vec![added("cart_2", "product_1"), added("cart_1", "product_2")],
+            query!(ShoppingCartEvent),
+            1,
+        )
+        .await
+        .unwrap();
+    let event = tokio::time::timeout(Duration::from_secs(2), feed.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(event.id(), 3);
+    assert_eq!(event.into_inner(), added("cart_1", "product_2"));
+
+    event_store
+        .append(
+            vec![added("cart_1", "product_3")],
+            query!(ShoppingCartEvent),
+            3,
+        )
+        .await
+        .unwrap();
+    let event = tokio::time::timeout(Duration::from_secs(2), feed.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(event.into_inner(), added("cart_1", "product_3"));
+
+    shutdown.cancel();
+    running.await.unwrap().unwrap();
+}
@@ -0,0 +1,156 @@
+//! # PostgreSQL Store Metadata
+//!
+//! This module keeps track of the library version, the schema version and the migrations applied to
+//! the database in the `disintegrate_meta` table. The metadata are used to refuse running against a
+//! schema that is not compatible with this version of the library.
+use disintegrate::MigrationPlan;
+use sqlx::{PgConnection, PgExecutor, PgPool, Row};
+
+use crate::Error;
+
+#[cfg(test)]
+mod tests;
+
+/// The version of the database schema required by this version of the library.
+pub const SCHEMA_VERSION: i32 = 1;
+
+/// The version of the library stamped in the metadata table.
+pub const LIBRARY_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The migrations of the schema: the migration at index `i` brings the schema to version `i + 1`.
+const MIGRATIONS: &[&str] = &["v1_initial_schema"];
+
+/// The metadata stored in the `disintegrate_meta` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreMetadata {
+    /// The version of the library that last initialized the database.
+    pub library_version: String,
+    /// The version of the database schema.
+    pub schema_version: i32,
+    /// The names of the migrations applied to the database.
+    pub applied_migrations: Vec<String>,
+}
+
+/// Loads the metadata of the store, if the database has been stamped.
+pub async fn load<'c>(executor: impl PgExecutor<'c>) -> Result<Option<StoreMetadata>, Error> {
+    let row = sqlx::query(
+        "SELECT library_version, schema_version, applied_migrations FROM disintegrate_meta WHERE id = 1",
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(row.map(|row| StoreMetadata {
+        library_version: row.get(0),
+        schema_version: row.get(1),
+        applied_migrations: row.get(2),
+    }))
+}
+
+/// Checks that the database has not been initialized by a newer version of the library.
+///
+/// # Errors
+///
+/// Returns `Error::NewerLibrary` if the stamped library version is newer than `LIBRARY_VERSION`.
+fn check_library_version(metadata: &StoreMetadata) -> Result<(), Error> {
+    if version_parts(&metadata.library_version) > version_parts(LIBRARY_VERSION) {
+        return Err(Error::NewerLibrary {
+            found: metadata.library_version.clone(),
+            running: LIBRARY_VERSION,
+        });
+    }
+    Ok(())
+}
+
+/// Returns the numeric parts of a version, e.g. `[1, 2, 3]` for `1.2.3-beta`, ignoring the pre-release.
+fn version_parts(version: &str) -> Vec<u64> {
+    version
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|part| part.parse().unwrap_or_default())
+        .collect()
+}
+
+/// Computes the migrations required to bring the database to `SCHEMA_VERSION`.
+///
+/// # Errors
+///
+/// Returns `Error::IncompatibleSchema` if the database has a schema version newer than the supported one, or
+/// `Error::NewerLibrary` if it has been initialized by a newer version of the library.
+pub async fn plan(pool: &PgPool) -> Result<MigrationPlan, Error> {
+    let metadata = if crate::schema::table_exists(pool, "disintegrate_meta").await? {
+        load(pool).await?
+    } else {
+        None
+    };
+    if let Some(metadata) = &metadata {
+        check_library_version(metadata)?;
+    }
+    let current_version = metadata.map(|metadata| metadata.schema_version);
+    if let Some(found) = current_version.filter(|version| *version > SCHEMA_VERSION) {
+        return Err(Error::IncompatibleSchema {
+            found,
+            expected: SCHEMA_VERSION,
+        });
+    }
+    let applied = current_version.unwrap_or(0) as usize;
+    Ok(MigrationPlan {
+        current_version,
+        target_version: SCHEMA_VERSION,
+        migrations: MIGRATIONS[applied..]
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+    })
+}
+
+/// Records the names of the applied migrations in the metadata table.
+pub async fn record_migrations(pool: &PgPool, migrations: &[String]) -> Result<(), Error> {
+    sqlx::query(
+        "UPDATE disintegrate_meta SET applied_migrations = applied_migrations || $1, updated_at = now() WHERE id = 1",
+    )
+    .bind(migrations)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Creates the metadata table and checks that this version of the library supports the database.
+///
+/// The caller is expected to hold the setup lock, and to `stamp` the database once the migrations are applied.
+///
+/// # Errors
+///
+/// Returns `Error::IncompatibleSchema` if the database has been initialized with a schema version
+/// that this version of the library does not support, or `Error::NewerLibrary` if it has been initialized
+/// by a newer version of the library.
+pub async fn setup(conn: &mut PgConnection) -> Result<(), Error> {
+    sqlx::query(include_str!("metadata/sql/table_disintegrate_meta.sql"))
+        .execute(&mut *conn)
+        .await?;
+
+    if let Some(metadata) = load(&mut *conn).await? {
+        if metadata.schema_version != SCHEMA_VERSION {
+            return Err(Error::IncompatibleSchema {
+                found: metadata.schema_version,
+                expected: SCHEMA_VERSION,
+            });
+        }
+        check_library_version(&metadata)?;
+    }
+    Ok(())
+}
+
+/// Stamps the current library and schema versions, once the migrations have been applied.
+///
+/// The caller is expected to hold the setup lock, in the transaction applying the migrations.
+pub async fn stamp(conn: &mut PgConnection) -> Result<(), Error> {
+    sqlx::query(
+        "INSERT INTO disintegrate_meta (id, library_version, schema_version) VALUES (1, $1, $2) ON CONFLICT (id) DO UPDATE SET library_version = $1, updated_at = now()",
+    )
+    .bind(LIBRARY_VERSION)
+    .bind(SCHEMA_VERSION)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS disintegrate_meta (
+    id smallint PRIMARY KEY DEFAULT 1 CHECK (id = 1),
+    library_version TEXT NOT NULL,
+    schema_version INT NOT NULL,
+    applied_migrations TEXT[] NOT NULL DEFAULT '{}',
+    updated_at TIMESTAMP DEFAULT now()
+);
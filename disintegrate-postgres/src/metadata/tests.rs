@@ -0,0 +1,86 @@
+use sqlx::PgPool;
+
+use super::*;
+
+async fn setup_and_stamp(pool: &PgPool) {
+    let mut conn = pool.acquire().await.unwrap();
+    setup(&mut conn).await.unwrap();
+    stamp(&mut conn).await.unwrap();
+}
+
+#[sqlx::test]
+async fn it_stamps_the_library_and_schema_versions(pool: PgPool) {
+    setup(&mut pool.acquire().await.unwrap()).await.unwrap();
+    assert!(load(&pool).await.unwrap().is_none());
+
+    stamp(&mut pool.acquire().await.unwrap()).await.unwrap();
+
+    let metadata = load(&pool).await.unwrap().unwrap();
+    assert_eq!(metadata.library_version, LIBRARY_VERSION);
+    assert_eq!(metadata.schema_version, SCHEMA_VERSION);
+    assert!(metadata.applied_migrations.is_empty());
+}
+
+#[sqlx::test]
+async fn it_refuses_an_incompatible_schema(pool: PgPool) {
+    setup_and_stamp(&pool).await;
+    sqlx::query("UPDATE disintegrate_meta SET schema_version = $1")
+        .bind(SCHEMA_VERSION + 1)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let result = setup(&mut pool.acquire().await.unwrap()).await;
+
+    assert!(matches!(
+        result,
+        Err(Error::IncompatibleSchema { found, expected }) if found == SCHEMA_VERSION + 1 && expected == SCHEMA_VERSION
+    ));
+}
+
+#[sqlx::test]
+async fn it_plans_the_initial_schema_of_an_empty_database(pool: PgPool) {
+    let plan = plan(&pool).await.unwrap();
+
+    assert_eq!(plan.current_version, None);
+    assert_eq!(plan.target_version, SCHEMA_VERSION);
+    assert_eq!(plan.migrations, vec!["v1_initial_schema"]);
+}
+
+#[sqlx::test]
+async fn it_plans_no_migrations_for_an_up_to_date_database(pool: PgPool) {
+    setup_and_stamp(&pool).await;
+
+    let plan = plan(&pool).await.unwrap();
+
+    assert_eq!(plan.current_version, Some(SCHEMA_VERSION));
+    assert!(plan.is_up_to_date());
+}
+
+#[sqlx::test]
+async fn it_refuses_a_database_initialized_by_a_newer_library(pool: PgPool) {
+    setup_and_stamp(&pool).await;
+    sqlx::query("UPDATE disintegrate_meta SET library_version = '999.0.0'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let result = setup(&mut pool.acquire().await.unwrap()).await;
+
+    assert!(matches!(
+        result,
+        Err(Error::NewerLibrary { found, running }) if found == "999.0.0" && running == LIBRARY_VERSION
+    ));
+    assert!(matches!(plan(&pool).await, Err(Error::NewerLibrary { .. })));
+    assert_eq!(
+        load(&pool).await.unwrap().unwrap().library_version,
+        "999.0.0"
+    );
+}
+
+#[test]
+fn it_compares_the_library_versions_numerically() {
+    assert!(version_parts("1.10.0") > version_parts("1.9.3"));
+    assert!(version_parts("2.0.0-beta.1") == version_parts("2.0.0"));
+    assert!(version_parts("1.0.0") < version_parts("1.0.1"));
+}
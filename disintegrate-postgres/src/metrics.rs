@@ -0,0 +1,56 @@
+//! Metrics of the event store and of the event listeners.
+//!
+//! The metrics are recorded through the [metrics](https://crates.io/crates/metrics) facade, so they are
+//! exported by the recorder installed by the application, e.g. a Prometheus exporter.
+#[cfg(feature = "listener")]
+use std::time::Duration;
+
+use disintegrate::{Event, PersistedEvent};
+#[cfg(feature = "listener")]
+use sqlx::PgPool;
+
+use crate::{Error, PgEventId};
+
+/// Counts the appended events by event type, and the appends failed because of a concurrency conflict.
+pub(crate) fn record_append<E: Event>(result: &Result<Vec<PersistedEvent<PgEventId, E>>, Error>) {
+    match result {
+        Ok(events) => {
+            for event in events {
+                ::metrics::counter!("disintegrate_events_appended_total", "event_type" => event.name())
+                    .increment(1);
+            }
+        }
+        Err(Error::Concurrency) => {
+            ::metrics::counter!("disintegrate_concurrency_conflicts_total").increment(1);
+        }
+        Err(_) => {}
+    }
+}
+
+/// Records the time taken by an event listener to handle an event.
+#[cfg(feature = "listener")]
+pub(crate) fn record_handle(listener_id: &'static str, elapsed: Duration) {
+    ::metrics::histogram!("disintegrate_listener_handle_duration_seconds", "listener" => listener_id)
+        .record(elapsed);
+}
+
+/// Records the number of events appended after the last event processed by an event listener, or by a
+/// shard of an event listener.
+///
+/// The lag is not recorded if the last event ID cannot be read: the metrics never fail the listener.
+#[cfg(feature = "listener")]
+pub(crate) async fn record_lag(
+    pool: &PgPool,
+    listener_id: &str,
+    last_processed_event_id: PgEventId,
+) {
+    let last_event_id =
+        sqlx::query_scalar::<_, PgEventId>("SELECT COALESCE(MAX(event_id), 0) FROM event")
+            .fetch_one(pool)
+            .await;
+    if let Ok(last_event_id) = last_event_id {
+        let lag = last_event_id.saturating_sub(last_processed_event_id).max(0);
+        ::metrics::gauge!("disintegrate_listener_lag_events", "listener" => listener_id.to_string())
+            .set(lag as f64);
+    }
+}
@@ -0,0 +1,102 @@
+//! # PostgreSQL Event Store Namespace
+//!
+//! This module groups the components of an event store living in the same database as other event stores,
+//! e.g. one per bounded context, each with its own `Event` type. A namespace is a PostgreSQL schema holding
+//! every table of the event store: the events and their sequence, the listener checkpoints, the snapshots and
+//! the trigger function notifying the new events, on a channel of its own.
+use disintegrate::Event;
+use disintegrate_serde::Serde;
+use sqlx::PgPool;
+
+use crate::{Error, PgEventStore, PgSnapshotter};
+
+#[cfg(test)]
+mod tests;
+
+/// A namespace of the database holding an independent event store.
+///
+/// # Examples
+///
+/// ```ignore
+/// let orders = PgEventStoreNamespace::new(&pool, "orders").await?;
+/// let event_store = orders.event_store(Json::<OrderEvent>::default()).await?;
+/// let snapshotter = orders.snapshotter(10).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct PgEventStoreNamespace {
+    name: String,
+    pool: PgPool,
+}
+
+impl PgEventStoreNamespace {
+    /// Creates the namespace, if it does not exist yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The PostgreSQL connection pool of the database.
+    /// * `name` - The name of the namespace, used as the name of its schema.
+    ///
+    /// # Returns
+    ///
+    /// The `PgEventStoreNamespace`, or an error if its schema cannot be created.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is not a lowercase SQL identifier, made of ASCII letters, digits and underscores
+    /// and not starting with a digit.
+    pub async fn new(pool: &PgPool, name: &str) -> Result<Self, Error> {
+        Ok(Self {
+            name: name.to_string(),
+            pool: crate::schema::schema_pool(pool, name).await?,
+        })
+    }
+
+    /// Returns the name of the namespace.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the connection pool scoped to the namespace, e.g. to create a `PgKeyValueStore` in it.
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Returns the PostgreSQL channel of the notifications of the new events of the namespace.
+    pub fn notification_channel(&self) -> String {
+        if self.name == "public" {
+            "new_events".to_string()
+        } else {
+            format!("new_events_{}", self.name)
+        }
+    }
+
+    /// Initializes the event store of the namespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `serde` - The serialization implementation for the event payload.
+    ///
+    /// # Returns
+    ///
+    /// The initialized `PgEventStore`, or an error if the setup fails.
+    pub async fn event_store<E, S>(&self, serde: S) -> Result<PgEventStore<E, S>, Error>
+    where
+        E: Event,
+        S: Serde<E> + Send + Sync,
+    {
+        PgEventStore::new(self.pool.clone(), serde).await
+    }
+
+    /// Initializes the snapshotter of the namespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `every` - The number of events between consecutive snapshots.
+    ///
+    /// # Returns
+    ///
+    /// The initialized `PgSnapshotter`, or an error if the setup fails.
+    pub async fn snapshotter(&self, every: u64) -> Result<PgSnapshotter, Error> {
+        PgSnapshotter::new(self.pool.clone(), every).await
+    }
+}
@@ -0,0 +1,74 @@
+use disintegrate::{query, EventStore};
+use disintegrate_serde::serde::json::Json;
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, disintegrate::Event)]
+#[serde(tag = "event_type", rename_all = "snake_case")]
+enum OrderEvent {
+    OrderPlaced {
+        #[id]
+        order_id: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, disintegrate::Event)]
+#[serde(tag = "event_type", rename_all = "snake_case")]
+enum PaymentEvent {
+    PaymentReceived {
+        #[id]
+        payment_id: i64,
+    },
+}
+
+#[sqlx::test]
+async fn it_isolates_the_event_sequences_of_the_namespaces(pool: PgPool) {
+    let orders = PgEventStoreNamespace::new(&pool, "orders").await.unwrap();
+    let payments = PgEventStoreNamespace::new(&pool, "payments").await.unwrap();
+    let order_store = orders
+        .event_store(Json::<OrderEvent>::default())
+        .await
+        .unwrap();
+    let payment_store = payments
+        .event_store(Json::<PaymentEvent>::default())
+        .await
+        .unwrap();
+    payments.snapshotter(10).await.unwrap();
+
+    let placed = order_store
+        .append(
+            vec![
+                OrderEvent::OrderPlaced {
+                    order_id: "o1".to_string(),
+                },
+                OrderEvent::OrderPlaced {
+                    order_id: "o2".to_string(),
+                },
+            ],
+            query!(OrderEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    let received = payment_store
+        .append(
+            vec![PaymentEvent::PaymentReceived { payment_id: 1 }],
+            query!(PaymentEvent),
+            0,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(placed.last().unwrap().id(), 2);
+    assert_eq!(received.last().unwrap().id(), 1);
+    let snapshot_tables: Vec<String> = sqlx::query_scalar(
+        "SELECT table_schema::text FROM information_schema.tables WHERE table_name = 'snapshot'",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(snapshot_tables, vec!["payments".to_string()]);
+    assert_eq!(payments.name(), "payments");
+    assert_eq!(payments.notification_channel(), "new_events_payments");
+}
@@ -0,0 +1,146 @@
+//! Tracing instrumentation of the event store and of the event listeners.
+//!
+//! The spans carry stable attributes, suitable to be exported to OpenTelemetry: the query is described by
+//! its event types and by the names of its domain identifiers, never by the identifier values, to keep the
+//! cardinality of the attributes low.
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use disintegrate::{Event, StreamQuery};
+use futures::{Stream, StreamExt};
+use tracing::field::Empty;
+use tracing::Span;
+
+use crate::{Error, PgEventId};
+
+/// Creates the span of a stream of events.
+pub(crate) fn stream_span<QE: Event + Clone>(query: &StreamQuery<PgEventId, QE>) -> Span {
+    tracing::info_span!(
+        "disintegrate.event_store.stream",
+        db.system = "postgresql",
+        disintegrate.query.events = query_events(query),
+        disintegrate.query.identifiers = query_identifiers(query),
+        disintegrate.events = Empty,
+    )
+}
+
+/// Creates the span of an append of events.
+pub(crate) fn append_span<QE: Event + Clone>(
+    events: usize,
+    query: &StreamQuery<PgEventId, QE>,
+) -> Span {
+    tracing::info_span!(
+        "disintegrate.event_store.append",
+        db.system = "postgresql",
+        disintegrate.query.events = query_events(query),
+        disintegrate.query.identifiers = query_identifiers(query),
+        disintegrate.events = events,
+        disintegrate.outcome = Empty,
+    )
+}
+
+/// Records the outcome of an append: `ok`, `conflict` or `error`.
+pub(crate) fn record_append<T>(span: &Span, result: &Result<T, Error>) {
+    let outcome = match result {
+        Ok(_) => "ok",
+        Err(Error::Concurrency) => "conflict",
+        Err(_) => "error",
+    };
+    span.record("disintegrate.outcome", outcome);
+}
+
+/// Creates the span of a batch of events handled by an event listener.
+///
+/// The listener records the ID of the last handled event, whether it caught up with the event store,
+/// and the outcome: `ok` or `error`.
+#[cfg(feature = "listener")]
+pub(crate) fn listener_span(listener_id: &str, last_processed_event_id: PgEventId) -> Span {
+    tracing::info_span!(
+        "disintegrate.event_listener.handle_events",
+        disintegrate.listener.id = listener_id,
+        disintegrate.listener.from_event_id = last_processed_event_id,
+        disintegrate.listener.last_processed_event_id = Empty,
+        disintegrate.listener.caught_up = Empty,
+        disintegrate.outcome = Empty,
+    )
+}
+
+/// Creates the span of an event handled by an event listener.
+#[cfg(feature = "listener")]
+pub(crate) fn event_span(listener_id: &str, event_id: PgEventId, event_type: &str) -> Span {
+    tracing::info_span!(
+        "disintegrate.event_listener.handle",
+        disintegrate.listener.id = listener_id,
+        disintegrate.event.id = event_id,
+        "disintegrate.event.type" = event_type,
+    )
+}
+
+/// A stream entering its span while it is polled, and recording the number of returned events.
+pub(crate) struct InstrumentedStream<S> {
+    inner: S,
+    span: Span,
+    events: u64,
+}
+
+impl<S> InstrumentedStream<S> {
+    pub(crate) fn new(inner: S, span: Span) -> Self {
+        Self {
+            inner,
+            span,
+            events: 0,
+        }
+    }
+}
+
+impl<S: Stream + Unpin> Stream for InstrumentedStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let _entered = this.span.enter();
+        let poll = this.inner.poll_next_unpin(cx);
+        match &poll {
+            Poll::Ready(Some(_)) => this.events += 1,
+            Poll::Ready(None) => {
+                this.span.record("disintegrate.events", this.events);
+            }
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
+
+fn query_events<QE: Event + Clone>(query: &StreamQuery<PgEventId, QE>) -> String {
+    let mut events: Vec<&str> = query
+        .filters()
+        .iter()
+        .flat_map(|filter| filter.events().iter().copied())
+        .collect();
+    events.sort_unstable();
+    events.dedup();
+    events.join(",")
+}
+
+fn query_identifiers<QE: Event + Clone>(query: &StreamQuery<PgEventId, QE>) -> String {
+    let mut identifiers: Vec<String> = query
+        .filters()
+        .iter()
+        .flat_map(|filter| {
+            filter
+                .identifiers()
+                .keys()
+                .map(ToString::to_string)
+                .chain(
+                    filter
+                        .conditions()
+                        .iter()
+                        .map(|condition| condition.identifier().to_string()),
+                )
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    identifiers.sort_unstable();
+    identifiers.dedup();
+    identifiers.join(",")
+}
@@ -0,0 +1,195 @@
+//! # PostgreSQL Outbox
+//!
+//! This module publishes the events of the event store to other services with at-least-once delivery.
+//! The event types marked with `PgEventStore::with_outbox` are recorded in the `event_outbox` table in the
+//! same transaction that appends them, and the `PgOutboxRelay` publishes the recorded events, in event ID order,
+//! through a user-supplied `Publisher`. An event is removed from the outbox only after it has been published,
+//! so it may be published more than once if the relay stops in between.
+use std::error::Error as StdError;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use disintegrate::{Event, PersistedEvent};
+use disintegrate_serde::{PayloadInfo, Serde};
+use futures::Future;
+use sqlx::Row;
+
+use crate::event_store::decode_metadata;
+use crate::{Error, PgEventId, PgEventStore};
+
+#[cfg(test)]
+mod tests;
+
+/// Publishes the events of the outbox to an external system, e.g. a Kafka topic or a NATS subject.
+#[async_trait]
+pub trait Publisher<E: Event>: Send + Sync {
+    /// The type of error that may occur while publishing an event.
+    type Error: StdError + Send + Sync + 'static;
+
+    /// Publishes an event.
+    ///
+    /// The same event may be published more than once, so the consumers should use the event ID to
+    /// discard the duplicates.
+    async fn publish(&self, event: &PersistedEvent<PgEventId, E>) -> Result<(), Self::Error>;
+}
+
+/// Relays the events recorded in the outbox to a `Publisher`.
+///
+/// The relays of several instances can run concurrently: each pending event is locked by a single relay.
+pub struct PgOutboxRelay<E, S, P>
+where
+    E: Event,
+    S: Serde<E> + Send + Sync,
+{
+    event_store: PgEventStore<E, S>,
+    publisher: P,
+    poll: Duration,
+    batch_size: i64,
+}
+
+impl<E, S, P> std::fmt::Debug for PgOutboxRelay<E, S, P>
+where
+    E: Event,
+    S: Serde<E> + Send + Sync,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PgOutboxRelay")
+            .field("event_store", &self.event_store)
+            .field("poll", &self.poll)
+            .field("batch_size", &self.batch_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E, S, P> PgOutboxRelay<E, S, P>
+where
+    E: Event + Clone + Send + Sync,
+    S: Serde<E> + Send + Sync,
+    P: Publisher<E>,
+{
+    /// Creates a new `PgOutboxRelay`, polling the outbox every second.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_store` - The event store whose outbox is relayed.
+    /// * `publisher` - The publisher of the events.
+    pub fn new(event_store: PgEventStore<E, S>, publisher: P) -> Self {
+        Self {
+            event_store,
+            publisher,
+            poll: Duration::from_secs(1),
+            batch_size: 100,
+        }
+    }
+
+    /// Sets the interval at which the outbox is polled when it is empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `poll` - The poll interval.
+    pub fn with_poll(mut self, poll: Duration) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    /// Sets the maximum number of events published in a single transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch_size` - The maximum number of events of a batch.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.clamp(1, i64::MAX as usize) as i64;
+        self
+    }
+
+    /// Publishes a batch of pending events, in event ID order.
+    ///
+    /// The batch stops at the first event that cannot be published: the error is recorded in the outbox,
+    /// together with the number of attempts, and the event is published again by the next batch.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of published events, or an error if the outbox cannot be read.
+    pub async fn relay(&self) -> Result<usize, Error> {
+        let mut tx = self.event_store.pool.begin().await?;
+        let rows = sqlx::query(
+            r#"SELECT o.event_id, e.payload, e.metadata::TEXT, e.event_version, e.event_type FROM event_outbox o JOIN event e ON e.event_id = o.event_id
+               ORDER BY o.event_id LIMIT $1 FOR UPDATE OF o SKIP LOCKED"#,
+        )
+        .bind(self.batch_size)
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut published = 0;
+        for row in rows {
+            let event_id: PgEventId = row.get(0);
+            let version = row.get::<i32, _>(3) as u32;
+            let info = PayloadInfo {
+                event_type: row.get(4),
+                version,
+                event_id: Some(event_id),
+            };
+            let event = PersistedEvent::new(
+                event_id,
+                self.event_store
+                    .serde
+                    .deserialize_with_info(row.get(1), info)?,
+            )
+            .with_metadata(decode_metadata(row.get(2))?)
+            .with_version(version);
+            if let Err(err) = self.publisher.publish(&event).await {
+                sqlx::query(
+                    "UPDATE event_outbox SET attempts = attempts + 1, last_error = $2 WHERE event_id = $1",
+                )
+                .bind(event_id)
+                .bind(err.to_string())
+                .execute(&mut *tx)
+                .await?;
+                break;
+            }
+            sqlx::query("DELETE FROM event_outbox WHERE event_id = $1")
+                .bind(event_id)
+                .execute(&mut *tx)
+                .await?;
+            published += 1;
+        }
+        tx.commit().await?;
+        Ok(published)
+    }
+
+    /// Relays the outbox until the shutdown signal.
+    ///
+    /// The batches are published back to back while the outbox is full, then the outbox is polled.
+    /// The connection errors are retried at the next poll.
+    ///
+    /// # Arguments
+    ///
+    /// * `shutdown` - A future that represents the shutdown signal.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating the success or failure of the relay.
+    pub async fn start_with_shutdown<F: Future<Output = ()> + Send>(
+        self,
+        shutdown: F,
+    ) -> Result<(), Error> {
+        tokio::pin!(shutdown);
+        let mut poll = tokio::time::interval(self.poll);
+        poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = poll.tick() => {
+                    loop {
+                        match self.relay().await {
+                            Ok(published) if published as i64 == self.batch_size => continue,
+                            Ok(_)
+                            | Err(Error::Database(sqlx::Error::Io(_)))
+                            | Err(Error::Database(sqlx::Error::PoolTimedOut)) => break,
+                            Err(err) => return Err(err),
+                        }
+                    }
+                }
+                _ = &mut shutdown => return Ok(()),
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::sync::Mutex;
+
+use super::*;
+use disintegrate::{query, EventStore};
+use disintegrate_serde::serde::json::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, disintegrate::Event)]
+#[serde(tag = "event_type", rename_all = "snake_case")]
+enum OrderEvent {
+    OrderPlaced {
+        #[id]
+        order_id: String,
+    },
+    OrderNoteAdded {
+        #[id]
+        order_id: String,
+        note: String,
+    },
+}
+
+fn order_placed(order_id: &str) -> OrderEvent {
+    OrderEvent::OrderPlaced {
+        order_id: order_id.to_string(),
+    }
+}
+
+fn order_note_added(order_id: &str) -> OrderEvent {
+    OrderEvent::OrderNoteAdded {
+        order_id: order_id.to_string(),
+        note: "ring the bell".to_string(),
+    }
+}
+
+#[derive(Default)]
+struct RecordingPublisher {
+    published: Mutex<Vec<(PgEventId, OrderEvent)>>,
+}
+
+#[async_trait]
+impl Publisher<OrderEvent> for RecordingPublisher {
+    type Error = std::convert::Infallible;
+
+    async fn publish(
+        &self,
+        event: &PersistedEvent<PgEventId, OrderEvent>,
+    ) -> Result<(), Self::Error> {
+        self.published
+            .lock()
+            .unwrap()
+            .push((event.id(), event.clone().into_inner()));
+        Ok(())
+    }
+}
+
+struct FailingPublisher;
+
+#[async_trait]
+impl Publisher<OrderEvent> for FailingPublisher {
+    type Error = std::io::Error;
+
+    async fn publish(
+        &self,
+        _event: &PersistedEvent<PgEventId, OrderEvent>,
+    ) -> Result<(), Self::Error> {
+        Err(std::io::Error::other("broker unavailable"))
+    }
+}
+
+async fn event_store(pool: PgPool) -> PgEventStore<OrderEvent, Json<OrderEvent>> {
+    PgEventStore::new(pool, Json::<OrderEvent>::default())
+        .await
+        .unwrap()
+        .with_outbox(["OrderPlaced"])
+}
+
+#[sqlx::test]
+async fn it_publishes_the_events_marked_for_the_outbox(pool: PgPool) {
+    let event_store = event_store(pool.clone()).await;
+    let appended = event_store
+        .append(
+            vec![order_placed("o1"), order_note_added("o1")],
+            query!(OrderEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    let relay = PgOutboxRelay::new(event_store, RecordingPublisher::default());
+
+    assert_eq!(relay.relay().await.unwrap(), 1);
+    assert_eq!(relay.relay().await.unwrap(), 0);
+
+    assert_eq!(
+        *relay.publisher.published.lock().unwrap(),
+        vec![(appended[0].id(), order_placed("o1"))]
+    );
+    let pending: i64 = sqlx::query_scalar("SELECT count(*) FROM event_outbox")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(pending, 0);
+}
+
+#[sqlx::test]
+async fn it_keeps_the_events_that_cannot_be_published(pool: PgPool) {
+    let event_store = event_store(pool.clone()).await;
+    let appended = event_store
+        .append(vec![order_placed("o1")], query!(OrderEvent), 0)
+        .await
+        .unwrap();
+    let relay = PgOutboxRelay::new(event_store, FailingPublisher);
+
+    assert_eq!(relay.relay().await.unwrap(), 0);
+    assert_eq!(relay.relay().await.unwrap(), 0);
+
+    let (event_id, attempts, last_error): (PgEventId, i32, Option<String>) =
+        sqlx::query_as("SELECT event_id, attempts, last_error FROM event_outbox")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(event_id, appended[0].id());
+    assert_eq!(attempts, 2);
+    assert_eq!(last_error.as_deref(), Some("broker unavailable"));
+}
@@ -0,0 +1,332 @@
+//! # PostgreSQL Scheduler
+//!
+//! This module makes decisions at a later time, e.g. closing a course 30 days after its creation, without
+//! an external cron. The `PgScheduler` records the serialized decisions in the `scheduled_decision` table,
+//! together with the time they are due, and makes the due decisions through a `MakeDecision` implementation.
+//!
+//! A decision is removed from the table only after it has been made, so it may be made more than once
+//! if the scheduler stops in between: the decisions should be idempotent, e.g. closing a course that is
+//! already closed should be a no-op.
+use std::marker::PhantomData;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use disintegrate::{Decision, DecisionError, MakeDecision};
+use futures::Future;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::{PgPool, Row};
+
+use crate::{Error, PgEventId};
+
+mod recurring;
+#[cfg(test)]
+mod tests;
+
+pub use recurring::PgRecurringRunStore;
+
+/// Schedules decisions to be made at a later time.
+///
+/// Each scheduled decision is identified by a key, e.g. `close-course-<course_id>`, which can be used to
+/// cancel or reschedule it. The schedulers of several instances can run concurrently: each due decision
+/// is locked by a single scheduler.
+#[derive(Debug)]
+pub struct PgScheduler<D> {
+    pool: PgPool,
+    name: &'static str,
+    poll: Duration,
+    retry_delay: Duration,
+    batch_size: i64,
+    decision: PhantomData<fn() -> D>,
+}
+
+impl<D> Clone for PgScheduler<D> {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            name: self.name,
+            poll: self.poll,
+            retry_delay: self.retry_delay,
+            batch_size: self.batch_size,
+            decision: PhantomData,
+        }
+    }
+}
+
+impl<D> PgScheduler<D>
+where
+    D: Decision + Serialize + DeserializeOwned,
+{
+    /// Creates and initializes a new instance of `PgScheduler`, polling the due decisions every second.
+    ///
+    /// # Arguments
+    ///
+    /// - `pool`: A PostgreSQL connection pool (`PgPool`) representing the database connection.
+    /// - `name`: The name of the scheduler, separating its decisions from the decisions of the other schedulers.
+    ///
+    /// # Returns
+    ///
+    /// A new `PgScheduler` instance.
+    pub async fn new(pool: PgPool, name: &'static str) -> Result<Self, Error> {
+        setup(&pool).await?;
+        Ok(Self::new_uninitialized(pool, name))
+    }
+
+    /// Creates a new instance of `PgScheduler`.
+    ///
+    /// This constructor does not initialize the database. If you need to initialize the database,
+    /// use `PgScheduler::new` instead.
+    ///
+    /// If you use this constructor, ensure that the database is already initialized.
+    /// Refer to the SQL files in the `scheduler/sql` folder for the necessary schema.
+    ///
+    /// # Arguments
+    ///
+    /// - `pool`: A PostgreSQL connection pool (`PgPool`) representing the database connection.
+    /// - `name`: The name of the scheduler, separating its decisions from the decisions of the other schedulers.
+    ///
+    /// # Returns
+    ///
+    /// A new `PgScheduler` instance.
+    pub fn new_uninitialized(pool: PgPool, name: &'static str) -> Self {
+        Self {
+            pool,
+            name,
+            poll: Duration::from_secs(1),
+            retry_delay: Duration::from_secs(1),
+            batch_size: 100,
+            decision: PhantomData,
+        }
+    }
+
+    /// Sets the interval at which the due decisions are polled.
+    ///
+    /// # Arguments
+    ///
+    /// - `poll`: The poll interval.
+    pub fn with_poll(mut self, poll: Duration) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    /// Sets the delay before a decision failed because of the state store is made again.
+    ///
+    /// # Arguments
+    ///
+    /// - `retry_delay`: The delay before the next attempt.
+    pub fn with_retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Sets the maximum number of decisions made in a single transaction.
+    ///
+    /// # Arguments
+    ///
+    /// - `batch_size`: The maximum number of decisions of a batch.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.clamp(1, i64::MAX as usize) as i64;
+        self
+    }
+
+    /// Schedules a decision to be made after a delay.
+    ///
+    /// A decision already scheduled with the same key is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// - `key`: The key of the scheduled decision.
+    /// - `decision`: The decision to make.
+    /// - `delay`: The delay after which the decision is due.
+    pub async fn schedule(&self, key: &str, decision: &D, delay: Duration) -> Result<(), Error> {
+        self.schedule_at(key, decision, SystemTime::now() + delay)
+            .await
+    }
+
+    /// Schedules a decision to be made at the given time.
+    ///
+    /// A decision already scheduled with the same key is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// - `key`: The key of the scheduled decision.
+    /// - `decision`: The decision to make.
+    /// - `due_at`: The time at which the decision is due.
+    pub async fn schedule_at(
+        &self,
+        key: &str,
+        decision: &D,
+        due_at: SystemTime,
+    ) -> Result<(), Error> {
+        let due_at = due_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        sqlx::query(
+            r#"INSERT INTO scheduled_decision (scheduler, key, decision, due_at) VALUES ($1, $2, $3, to_timestamp($4))
+               ON CONFLICT (scheduler, key) DO UPDATE SET decision = $3, due_at = to_timestamp($4), attempts = 0, last_error = NULL, scheduled_at = now()"#,
+        )
+        .bind(self.name)
+        .bind(key)
+        .bind(serde_json::to_string(decision)?)
+        .bind(due_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Cancels a scheduled decision.
+    ///
+    /// A decision being made while it is cancelled is not interrupted: the cancellation waits for it
+    /// to complete, and returns `false` if the decision has been made.
+    ///
+    /// # Arguments
+    ///
+    /// - `key`: The key of the scheduled decision.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `true` if the decision was scheduled.
+    pub async fn cancel(&self, key: &str) -> Result<bool, Error> {
+        let result =
+            sqlx::query("DELETE FROM scheduled_decision WHERE scheduler = $1 AND key = $2")
+                .bind(self.name)
+                .bind(key)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Makes a batch of due decisions, in due time order.
+    ///
+    /// A decision is removed once it has been made, even if it has been rejected: a decision rejected
+    /// by the business rules, or by the validation, would be rejected again. A decision failed because
+    /// of the state store, e.g. because of a concurrency conflict, or that cannot be deserialized, is kept:
+    /// the error is recorded, together with the number of attempts, and the decision is made again after the
+    /// retry delay.
+    ///
+    /// # Arguments
+    ///
+    /// - `decision_maker`: The decision maker of the scheduled decisions.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of decisions made, or an error if the scheduled decisions cannot be read.
+    pub async fn run_due<M>(&self, decision_maker: &M) -> Result<usize, Error>
+    where
+        M: MakeDecision<PgEventId, D> + Sync,
+    {
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query(
+            r#"SELECT key, decision FROM scheduled_decision WHERE scheduler = $1 AND due_at <= now()
+               ORDER BY due_at LIMIT $2 FOR UPDATE SKIP LOCKED"#,
+        )
+        .bind(self.name)
+        .bind(self.batch_size)
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut made = 0;
+        for row in rows {
+            let key: String = row.get(0);
+            let outcome = match serde_json::from_str::<D>(row.get(1)) {
+                Ok(decision) => match decision_maker.make_decision(decision).await {
+                    Ok(_)
+                    | Err(DecisionError::Domain(_))
+                    | Err(DecisionError::Invalid(_))
+                    | Err(DecisionError::Rejected(_)) => Ok(()),
+                    Err(DecisionError::EventStore(err)) | Err(DecisionError::StateStore(err)) => {
+                        Err(err.to_string())
+                    }
+                    Err(_) => Err("the decision failed".to_string()),
+                },
+                Err(err) => Err(err.to_string()),
+            };
+            match outcome {
+                Ok(()) => {
+                    sqlx::query("DELETE FROM scheduled_decision WHERE scheduler = $1 AND key = $2")
+                        .bind(self.name)
+                        .bind(&key)
+                        .execute(&mut *tx)
+                        .await?;
+                    made += 1;
+                }
+                Err(err) => {
+                    sqlx::query(
+                        r#"UPDATE scheduled_decision SET attempts = attempts + 1, last_error = $3,
+                           due_at = now() + make_interval(secs => $4) WHERE scheduler = $1 AND key = $2"#,
+                    )
+                    .bind(self.name)
+                    .bind(&key)
+                    .bind(err)
+                    .bind(self.retry_delay.as_secs_f64())
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+        }
+        tx.commit().await?;
+        Ok(made)
+    }
+
+    /// Makes the due decisions until the shutdown signal.
+    ///
+    /// The batches are made back to back while there are due decisions, then the scheduled decisions are polled.
+    /// The connection errors are retried at the next poll.
+    ///
+    /// # Arguments
+    ///
+    /// - `decision_maker`: The decision maker of the scheduled decisions.
+    /// - `shutdown`: A future that represents the shutdown signal.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating the success or failure of the scheduler.
+    pub async fn start_with_shutdown<M, F>(
+        &self,
+        decision_maker: &M,
+        shutdown: F,
+    ) -> Result<(), Error>
+    where
+        M: MakeDecision<PgEventId, D> + Sync,
+        F: Future<Output = ()> + Send,
+    {
+        tokio::pin!(shutdown);
+        let mut poll = tokio::time::interval(self.poll);
+        poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = poll.tick() => {
+                    loop {
+                        match self.run_due(decision_maker).await {
+                            Ok(made) if made as i64 == self.batch_size => continue,
+                            Ok(_)
+                            | Err(Error::Database(sqlx::Error::Io(_)))
+                            | Err(Error::Database(sqlx::Error::PoolTimedOut)) => break,
+                            Err(err) => return Err(err),
+                        }
+                    }
+                }
+                _ = &mut shutdown => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Initializes the table of the scheduled decisions.
+pub async fn setup(pool: &PgPool) -> Result<(), Error> {
+    let mut tx = crate::setup_lock::begin(pool).await?;
+    sqlx::query(include_str!("scheduler/sql/table_scheduled_decision.sql"))
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(include_str!(
+        "scheduler/sql/idx_scheduled_decision_due_at.sql"
+    ))
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(include_str!(
+        "scheduler/sql/table_recurring_decision_run.sql"
+    ))
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
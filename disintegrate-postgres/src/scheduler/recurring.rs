@@ -0,0 +1,95 @@
+//! Runs of the recurring decisions.
+//!
+//! `PgRecurringRunStore` records the time of the last run of each recurring decision in the
+//! `recurring_decision_run` table, in milliseconds since the epoch. A run is claimed with a compare-and-set
+//! of the last run, so the replicas of an application sharing the database make each run once.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use disintegrate::{BoxDynError, RecurringRunStore};
+use sqlx::PgPool;
+
+use super::setup;
+use crate::Error;
+
+/// PostgreSQL implementation of the `RecurringRunStore` trait.
+#[derive(Debug, Clone)]
+pub struct PgRecurringRunStore {
+    pool: PgPool,
+}
+
+impl PgRecurringRunStore {
+    /// Creates and initializes a new instance of `PgRecurringRunStore`.
+    ///
+    /// # Arguments
+    ///
+    /// - `pool`: A PostgreSQL connection pool (`PgPool`) representing the database connection.
+    ///
+    /// # Returns
+    ///
+    /// A new `PgRecurringRunStore` instance.
+    pub async fn new(pool: PgPool) -> Result<Self, Error> {
+        setup(&pool).await?;
+        Ok(Self::new_uninitialized(pool))
+    }
+
+    /// Creates a new instance of `PgRecurringRunStore`.
+    ///
+    /// This constructor does not initialize the database. If you need to initialize the database,
+    /// use `PgRecurringRunStore::new` instead.
+    ///
+    /// # Arguments
+    ///
+    /// - `pool`: A PostgreSQL connection pool (`PgPool`) representing the database connection.
+    ///
+    /// # Returns
+    ///
+    /// A new `PgRecurringRunStore` instance.
+    pub fn new_uninitialized(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl RecurringRunStore for PgRecurringRunStore {
+    async fn last_run(&self, name: &str) -> Result<Option<SystemTime>, BoxDynError> {
+        let last_run_at: Option<i64> =
+            sqlx::query_scalar("SELECT last_run_at FROM recurring_decision_run WHERE name = $1")
+                .bind(name)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(last_run_at.map(|millis| UNIX_EPOCH + Duration::from_millis(millis as u64)))
+    }
+
+    async fn claim_run(
+        &self,
+        name: &str,
+        previous: Option<SystemTime>,
+        at: SystemTime,
+    ) -> Result<bool, BoxDynError> {
+        let claimed = match previous {
+            None => sqlx::query(
+                "INSERT INTO recurring_decision_run (name, last_run_at) VALUES ($1, $2) ON CONFLICT (name) DO NOTHING",
+            )
+            .bind(name)
+            .bind(millis(at))
+            .execute(&self.pool)
+            .await?,
+            Some(previous) => sqlx::query(
+                "UPDATE recurring_decision_run SET last_run_at = $2 WHERE name = $1 AND last_run_at = $3",
+            )
+            .bind(name)
+            .bind(millis(at))
+            .bind(millis(previous))
+            .execute(&self.pool)
+            .await?,
+        };
+        Ok(claimed.rows_affected() == 1)
+    }
+}
+
+fn millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
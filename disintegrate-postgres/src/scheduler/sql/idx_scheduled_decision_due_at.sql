@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS idx_scheduled_decision_due_at ON scheduled_decision(scheduler, due_at);
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS recurring_decision_run (
+    name text PRIMARY KEY,
+    last_run_at bigint NOT NULL
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS scheduled_decision (
+    scheduler text NOT NULL,
+    key text NOT NULL,
+    decision text NOT NULL,
+    due_at TIMESTAMP NOT NULL,
+    attempts integer NOT NULL DEFAULT 0,
+    last_error text,
+    scheduled_at TIMESTAMP DEFAULT now(),
+    PRIMARY KEY (scheduler, key)
+);
@@ -0,0 +1,167 @@
+use std::sync::Mutex;
+
+use super::*;
+use async_trait::async_trait;
+use disintegrate::PersistedEvent;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, disintegrate::Event)]
+#[serde(tag = "event_type", rename_all = "snake_case")]
+enum CourseEvent {
+    CourseClosed {
+        #[id]
+        course_id: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CloseCourse {
+    course_id: String,
+}
+
+fn close_course(course_id: &str) -> CloseCourse {
+    CloseCourse {
+        course_id: course_id.to_string(),
+    }
+}
+
+impl Decision for CloseCourse {
+    type Event = CourseEvent;
+    type StateQuery = ();
+    type Error = std::convert::Infallible;
+
+    fn state_query(&self) -> Self::StateQuery {}
+
+    fn process(&self, _state: &Self::StateQuery) -> Result<Vec<Self::Event>, Self::Error> {
+        Ok(vec![CourseEvent::CourseClosed {
+            course_id: self.course_id.clone(),
+        }])
+    }
+}
+
+#[derive(Default)]
+struct RecordingDecisionMaker {
+    made: Mutex<Vec<CloseCourse>>,
+    unavailable: bool,
+}
+
+#[async_trait]
+impl MakeDecision<PgEventId, CloseCourse> for RecordingDecisionMaker {
+    async fn make_decision(
+        &self,
+        decision: CloseCourse,
+    ) -> Result<Vec<PersistedEvent<PgEventId, CourseEvent>>, DecisionError<std::convert::Infallible>>
+    {
+        if self.unavailable {
+            return Err(DecisionError::StateStore("event store unavailable".into()));
+        }
+        let events = decision.process(&()).unwrap();
+        self.made.lock().unwrap().push(decision);
+        Ok(events
+            .into_iter()
+            .map(|event| PersistedEvent::new(1, event))
+            .collect())
+    }
+}
+
+#[sqlx::test]
+async fn it_makes_the_due_decisions(pool: PgPool) {
+    let scheduler = PgScheduler::new(pool, "courses").await.unwrap();
+    let decision_maker = RecordingDecisionMaker::default();
+    scheduler
+        .schedule("close-c1", &close_course("c1"), Duration::ZERO)
+        .await
+        .unwrap();
+    scheduler
+        .schedule("close-c2", &close_course("c2"), Duration::from_secs(3600))
+        .await
+        .unwrap();
+
+    assert_eq!(scheduler.run_due(&decision_maker).await.unwrap(), 1);
+    assert_eq!(scheduler.run_due(&decision_maker).await.unwrap(), 0);
+    assert_eq!(
+        *decision_maker.made.lock().unwrap(),
+        vec![close_course("c1")]
+    );
+    assert!(scheduler.cancel("close-c2").await.unwrap());
+    assert!(!scheduler.cancel("close-c1").await.unwrap());
+}
+
+#[sqlx::test]
+async fn it_replaces_the_decisions_scheduled_with_the_same_key(pool: PgPool) {
+    let scheduler = PgScheduler::new(pool, "courses").await.unwrap();
+    let decision_maker = RecordingDecisionMaker::default();
+    scheduler
+        .schedule("close", &close_course("c1"), Duration::from_secs(3600))
+        .await
+        .unwrap();
+    scheduler
+        .schedule_at("close", &close_course("c2"), UNIX_EPOCH)
+        .await
+        .unwrap();
+
+    assert_eq!(scheduler.run_due(&decision_maker).await.unwrap(), 1);
+    assert_eq!(
+        *decision_maker.made.lock().unwrap(),
+        vec![close_course("c2")]
+    );
+}
+
+#[sqlx::test]
+async fn it_retries_the_decisions_failed_because_of_the_state_store(pool: PgPool) {
+    let scheduler = PgScheduler::new(pool.clone(), "courses")
+        .await
+        .unwrap()
+        .with_retry_delay(Duration::from_secs(3600));
+    let decision_maker = RecordingDecisionMaker {
+        unavailable: true,
+        ..Default::default()
+    };
+    scheduler
+        .schedule("close-c1", &close_course("c1"), Duration::ZERO)
+        .await
+        .unwrap();
+
+    assert_eq!(scheduler.run_due(&decision_maker).await.unwrap(), 0);
+
+    let (attempts, last_error, retried_later): (i32, String, bool) = sqlx::query_as(
+        "SELECT attempts, last_error, due_at > now() FROM scheduled_decision WHERE key = 'close-c1'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(attempts, 1);
+    assert_eq!(last_error, "event store unavailable");
+    assert!(retried_later);
+}
+
+#[sqlx::test]
+async fn it_claims_each_run_of_a_recurring_decision_once(pool: PgPool) {
+    use disintegrate::RecurringRunStore;
+
+    let run_store = PgRecurringRunStore::new(pool.clone()).await.unwrap();
+    let first_run = UNIX_EPOCH + Duration::from_millis(1_000);
+    let second_run = UNIX_EPOCH + Duration::from_millis(2_000);
+
+    assert_eq!(run_store.last_run("accrual").await.unwrap(), None);
+    assert!(run_store
+        .claim_run("accrual", None, first_run)
+        .await
+        .unwrap());
+    assert!(!run_store
+        .claim_run("accrual", None, first_run)
+        .await
+        .unwrap());
+    assert!(run_store
+        .claim_run("accrual", Some(first_run), second_run)
+        .await
+        .unwrap());
+    assert!(!run_store
+        .claim_run("accrual", Some(first_run), second_run)
+        .await
+        .unwrap());
+    assert_eq!(
+        run_store.last_run("accrual").await.unwrap(),
+        Some(second_run)
+    );
+}
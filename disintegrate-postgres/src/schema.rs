@@ -0,0 +1,101 @@
+//! # PostgreSQL Schemas
+//!
+//! This module scopes the tables of Disintegrate to a PostgreSQL schema, e.g. to keep the event stores of two
+//! bounded contexts in the same database. The table names are not configurable, since they are shared by the
+//! SQL statements, the triggers and the migrations of every component: the schema is the unit of isolation
+//! instead. The connections of a schema are opened with the `search_path` set to it, so the unqualified names
+//! used by the event store, the listeners and the snapshotter resolve to the tables of the schema. The `public`
+//! schema follows it on the `search_path`, so the extensions and the functions installed there stay available.
+use sqlx::{PgExecutor, PgPool};
+
+use crate::Error;
+
+#[cfg(test)]
+mod tests;
+
+/// The maximum number of connections of the pool of a schema.
+///
+/// Each schema has its own connection pool, so the connections of a database grow with the number of schemas,
+/// e.g. of tenants: the pool of a schema is capped to this number, or to the maximum of the pool it is derived
+/// from if lower, and it keeps no idle connection open.
+pub const SCHEMA_POOL_MAX_CONNECTIONS: u32 = 5;
+
+/// Creates a schema, if it does not exist yet, and returns a connection pool scoped to it.
+///
+/// The pool has the same options as the given one, capped to `SCHEMA_POOL_MAX_CONNECTIONS` connections, and the
+/// components created with it, e.g. a `PgSnapshotter` or a `PgKeyValueStore`, create and use their tables in the
+/// schema.
+///
+/// # Arguments
+///
+/// * `pool` - The PostgreSQL connection pool of the database.
+/// * `schema` - The name of the schema.
+///
+/// # Returns
+///
+/// The connection pool scoped to the schema, or an error if the schema cannot be created.
+///
+/// # Panics
+///
+/// Panics if the name of the schema is not a lowercase SQL identifier, made of ASCII letters, digits and
+/// underscores and not starting with a digit.
+pub async fn schema_pool(pool: &PgPool, schema: &str) -> Result<PgPool, Error> {
+    let schema_pool = connect_schema(pool, schema);
+    create_schema(&schema_pool, schema).await?;
+    Ok(schema_pool)
+}
+
+/// Returns a connection pool scoped to a schema, without connecting to the database.
+///
+/// The pool is capped to `SCHEMA_POOL_MAX_CONNECTIONS` connections, and its `search_path` is the schema followed
+/// by `public`.
+///
+/// # Panics
+///
+/// Panics if the name of the schema is not a lowercase SQL identifier.
+pub(crate) fn connect_schema(pool: &PgPool, schema: &str) -> PgPool {
+    assert!(
+        schema.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+            && schema
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+        "invalid schema name `{schema}`: expected a lowercase SQL identifier"
+    );
+    let options = pool.options();
+    options
+        .clone()
+        .max_connections(
+            options
+                .get_max_connections()
+                .min(SCHEMA_POOL_MAX_CONNECTIONS),
+        )
+        .min_connections(0)
+        .connect_lazy_with(
+            pool.connect_options()
+                .as_ref()
+                .clone()
+                .options([("search_path", format!("{schema},public"))]),
+        )
+}
+
+/// Creates a schema, if it does not exist yet.
+pub(crate) async fn create_schema(pool: &PgPool, schema: &str) -> Result<(), Error> {
+    sqlx::query(&format!("CREATE SCHEMA IF NOT EXISTS {schema}"))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Returns true if a table exists in the current schema.
+///
+/// The table is looked up in the current schema only: the `search_path` of a schema pool also contains
+/// `public`, where the table of the same name of another store may exist.
+pub(crate) async fn table_exists<'e>(
+    executor: impl PgExecutor<'e>,
+    table: &str,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT to_regclass(format('%I.%I', current_schema(), $1)) IS NOT NULL")
+        .bind(table)
+        .fetch_one(executor)
+        .await
+}
@@ -0,0 +1,118 @@
+use disintegrate::{query, EventStore};
+use disintegrate_serde::serde::json::Json;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use super::*;
+use crate::{PgEventStore, PgSnapshotter};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, disintegrate::Event)]
+#[serde(tag = "event_type", rename_all = "snake_case")]
+enum OrderEvent {
+    OrderPlaced {
+        #[id]
+        order_id: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, disintegrate::Event)]
+#[serde(tag = "event_type", rename_all = "snake_case")]
+enum PaymentEvent {
+    PaymentReceived {
+        #[id]
+        payment_id: i64,
+        #[id]
+        order_id: String,
+    },
+}
+
+async fn table_exists(pool: &PgPool, table: &str) -> bool {
+    sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+        .bind(table)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn it_keeps_the_stores_of_the_bounded_contexts_in_their_schemas(pool: PgPool) {
+    let orders = PgEventStore::new(
+        schema_pool(&pool, "orders").await.unwrap(),
+        Json::<OrderEvent>::default(),
+    )
+    .await
+    .unwrap();
+    let payments = PgEventStore::new(
+        schema_pool(&pool, "payments").await.unwrap(),
+        Json::<PaymentEvent>::default(),
+    )
+    .await
+    .unwrap();
+    PgSnapshotter::new(payments.pool().clone(), 10)
+        .await
+        .unwrap();
+
+    orders
+        .append(
+            vec![OrderEvent::OrderPlaced {
+                order_id: "o1".to_string(),
+            }],
+            query!(OrderEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    payments
+        .append(
+            vec![PaymentEvent::PaymentReceived {
+                payment_id: 1,
+                order_id: "o1".to_string(),
+            }],
+            query!(PaymentEvent),
+            0,
+        )
+        .await
+        .unwrap();
+
+    let payment_ids: Vec<_> = payments
+        .stream(&query!(PaymentEvent; order_id == "o1"))
+        .map(|event| event.unwrap().id())
+        .collect()
+        .await;
+    // the event IDs are not shared by the stores.
+    assert_eq!(payment_ids, vec![1]);
+    assert!(table_exists(&pool, "orders.event").await);
+    assert!(!table_exists(&pool, "orders.snapshot").await);
+    assert!(table_exists(&pool, "payments.snapshot").await);
+    assert!(!table_exists(&pool, "public.event").await);
+}
+
+#[sqlx::test]
+async fn it_caps_the_pool_of_a_schema_and_keeps_public_on_the_search_path(pool: PgPool) {
+    sqlx::query("CREATE FUNCTION public.tenant_greeting() RETURNS TEXT AS $$ SELECT 'hello' $$ LANGUAGE SQL")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let orders = schema_pool(&pool, "orders").await.unwrap();
+
+    assert!(orders.options().get_max_connections() <= SCHEMA_POOL_MAX_CONNECTIONS);
+    let search_path: String = sqlx::query_scalar("SHOW search_path")
+        .fetch_one(&orders)
+        .await
+        .unwrap();
+    assert_eq!(search_path, "orders,public");
+    let greeting: String = sqlx::query_scalar("SELECT tenant_greeting()")
+        .fetch_one(&orders)
+        .await
+        .unwrap();
+    assert_eq!(greeting, "hello");
+}
+
+#[sqlx::test]
+async fn it_looks_up_the_tables_in_the_schema_only(pool: PgPool) {
+    PgSnapshotter::new(pool.clone(), 10).await.unwrap();
+    let orders = schema_pool(&pool, "orders").await.unwrap();
+
+    assert!(super::table_exists(&pool, "snapshot").await.unwrap());
+    assert!(!super::table_exists(&orders, "snapshot").await.unwrap());
+}
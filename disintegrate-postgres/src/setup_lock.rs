@@ -0,0 +1,75 @@
+//! # PostgreSQL Setup Lock
+//!
+//! This module serializes the initialization of the database across multiple application instances.
+//! When several replicas start simultaneously, their DDL statements would race and may deadlock.
+//! The setup is executed within a transaction holding a PostgreSQL advisory lock, so only one instance
+//! at a time initializes the database, while the others wait for it to complete.
+use std::time::Duration;
+
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::Error;
+
+#[cfg(test)]
+mod tests;
+
+/// The maximum time an instance waits for another instance to complete the setup.
+pub const SETUP_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The key of the advisory lock held during the setup.
+const SETUP_LOCK_KEY: i64 = 0x6469_7369_6e74_6567;
+
+/// Begins a transaction holding the setup lock, waiting at most `SETUP_LOCK_TIMEOUT` to acquire it.
+///
+/// The lock is released when the transaction is committed or rolled back.
+///
+/// # Errors
+///
+/// Returns `Error::AlreadyInitializing` if another instance holds the lock for longer than the timeout.
+pub(crate) async fn begin(pool: &PgPool) -> Result<Transaction<'static, Postgres>, Error> {
+    begin_with_timeout(pool, SETUP_LOCK_TIMEOUT).await
+}
+
+/// Begins a transaction holding the setup lock, waiting at most `timeout` to acquire it.
+///
+/// The lock timeout only applies to the acquisition of the lock: the setup statements run afterwards with
+/// the lock timeout of the connection.
+pub(crate) async fn begin_with_timeout(
+    pool: &PgPool,
+    timeout: Duration,
+) -> Result<Transaction<'static, Postgres>, Error> {
+    let mut tx = pool.begin().await?;
+    let lock_timeout: String = sqlx::query_scalar("SELECT current_setting('lock_timeout')")
+        .fetch_one(&mut *tx)
+        .await?;
+    set_lock_timeout(&mut tx, &format!("{}ms", timeout.as_millis())).await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(SETUP_LOCK_KEY)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_lock_err)?;
+    set_lock_timeout(&mut tx, &lock_timeout).await?;
+    Ok(tx)
+}
+
+/// Sets the lock timeout until the end of the transaction.
+async fn set_lock_timeout(
+    tx: &mut Transaction<'static, Postgres>,
+    value: &str,
+) -> Result<(), Error> {
+    sqlx::query("SELECT set_config('lock_timeout', $1, true)")
+        .bind(value)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Maps the `sqlx::Error` to `Error::AlreadyInitializing` when the lock timeout expires.
+fn map_lock_err(err: sqlx::Error) -> Error {
+    if let sqlx::Error::Database(ref description) = err {
+        if description.code().as_deref() == Some("55P03") {
+            return Error::AlreadyInitializing;
+        }
+    }
+    Error::Database(err)
+}
@@ -0,0 +1,31 @@
+use sqlx::PgPool;
+
+use super::*;
+
+#[sqlx::test]
+async fn it_returns_already_initializing_when_the_lock_is_held(pool: PgPool) {
+    let mut holder = begin(&pool).await.unwrap();
+
+    let result = begin_with_timeout(&pool, Duration::from_millis(100)).await;
+
+    assert!(matches!(result, Err(Error::AlreadyInitializing)));
+    sqlx::query("SELECT 1").execute(&mut *holder).await.unwrap();
+    holder.rollback().await.unwrap();
+    assert!(begin_with_timeout(&pool, Duration::from_millis(100))
+        .await
+        .is_ok());
+}
+
+#[sqlx::test]
+async fn it_applies_the_lock_timeout_to_the_lock_acquisition_only(pool: PgPool) {
+    let mut tx = begin_with_timeout(&pool, Duration::from_millis(100))
+        .await
+        .unwrap();
+
+    let lock_timeout: String = sqlx::query_scalar("SHOW lock_timeout")
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
+
+    assert_eq!(lock_timeout, "0");
+}
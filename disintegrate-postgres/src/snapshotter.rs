@@ -1,10 +1,16 @@
 //! # PostgreSQL Snapshotter
 //!
 //! This module provides an implementation of the `Snapshotter` trait using PostgreSQL as the underlying storage.
-//! It allows storing and retrieving snapshots from a PostgreSQL database.
+//! It allows storing and retrieving snapshots from a PostgreSQL database. The storage itself is available
+//! as `PgSnapshotStore`, an implementation of the `SnapshotStore` trait.
+//!
+//! A snapshot that cannot be deserialized is moved to the `snapshot_quarantine` table, together with
+//! the deserialization error, so that a systematic corruption (e.g. after a bad deploy) can be detected.
 use async_trait::async_trait;
-use disintegrate::{BoxDynError, Event, IntoState, StateSnapshotter, StreamQuery};
-use disintegrate::{StatePart, StateQuery};
+use disintegrate::{
+    BoxDynError, Snapshot, SnapshotPolicy, SnapshotStore, Snapshotter, StatePart, StateQuery,
+    StateSnapshotter,
+};
 use md5::{Digest, Md5};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -21,10 +27,9 @@ mod tests;
 ///
 /// The `PgSnapshotter` struct implements the `Snapshotter` trait for PostgreSQL databases.
 /// It allows for stroring and retrieving snapshots of `StateQuery` from PostgreSQL database.
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct PgSnapshotter {
-    pool: PgPool,
-    every: u64,
+    snapshotter: Snapshotter<PgSnapshotStore>,
 }
 
 impl PgSnapshotter {
@@ -60,7 +65,32 @@ impl PgSnapshotter {
     ///
     /// A new `PgSnapshotter` instance.
     pub fn new_uninitialized(pool: PgPool, every: u64) -> Self {
-        Self { pool, every }
+        Self {
+            snapshotter: Snapshotter::new(PgSnapshotStore::new_uninitialized(pool), every),
+        }
+    }
+
+    /// Sets the policy deciding when the snapshots of the states are stored, replacing the frequency.
+    ///
+    /// # Arguments
+    ///
+    /// - `policy`: The snapshot policy, evaluated for each part of a state, e.g. a `PerStatePolicy`.
+    ///
+    /// # Returns
+    ///
+    /// The `PgSnapshotter` with the snapshot policy.
+    pub fn with_policy(mut self, policy: impl SnapshotPolicy + 'static) -> Self {
+        self.snapshotter = self.snapshotter.with_policy(policy);
+        self
+    }
+
+    /// Returns the snapshots quarantined because they could not be deserialized, from the most recent.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the quarantined snapshots, or an error if the query fails.
+    pub async fn quarantined_snapshots(&self) -> Result<Vec<QuarantinedSnapshot>, Error> {
+        self.snapshotter.store().quarantined_snapshots().await
     }
 }
 
@@ -70,46 +100,166 @@ impl StateSnapshotter<PgEventId> for PgSnapshotter {
     where
         S: Send + Sync + DeserializeOwned + StateQuery + 'static,
     {
-        let query = query_key(&default.query());
-        let stored_snapshot =
-            sqlx::query("SELECT name, query, payload, version FROM snapshot where id = $1")
-                .bind(snapshot_id(S::NAME, &query))
-                .fetch_one(&self.pool)
-                .await;
-        if let Ok(row) = stored_snapshot {
-            let snapshot_name: String = row.get(0);
-            let snapshot_query: String = row.get(1);
-            if S::NAME == snapshot_name && query == snapshot_query {
-                let payload = serde_json::from_str(row.get(2)).unwrap_or(default.into_state());
-                return StatePart::new(row.get(3), payload);
-            }
-        }
-
-        default
+        self.snapshotter.load_snapshot(default).await
     }
 
     async fn store_snapshot<S>(&self, state: &StatePart<PgEventId, S>) -> Result<(), BoxDynError>
     where
         S: Send + Sync + Serialize + StateQuery + 'static,
     {
-        if state.applied_events() <= self.every {
-            return Ok(());
-        }
-        let query = query_key(&state.query());
-        let id = snapshot_id(S::NAME, &query);
-        let version = state.version();
-        let payload = serde_json::to_string(&state.clone().into_state())?;
+        self.snapshotter.store_snapshot(state).await
+    }
+}
+
+/// PostgreSQL implementation for the `SnapshotStore` trait.
+///
+/// The snapshots are stored in the `snapshot` table. A snapshot that cannot be deserialized is moved
+/// to the `snapshot_quarantine` table.
+#[derive(Debug, Clone)]
+pub struct PgSnapshotStore {
+    pool: PgPool,
+}
+
+impl PgSnapshotStore {
+    /// Creates and initializes a new instance of `PgSnapshotStore` with the specified PostgreSQL connection pool.
+    ///
+    /// # Arguments
+    ///
+    /// - `pool`: A PostgreSQL connection pool (`PgPool`) representing the database connection.
+    ///
+    /// # Returns
+    ///
+    /// A new `PgSnapshotStore` instance.
+    pub async fn new(pool: PgPool) -> Result<Self, Error> {
+        setup(&pool).await?;
+        Ok(Self::new_uninitialized(pool))
+    }
+
+    /// Creates a new instance of `PgSnapshotStore` with the specified PostgreSQL connection pool.
+    ///
+    /// This constructor does not initialize the database. If you need to initialize the database,
+    /// use `PgSnapshotStore::new` instead.
+    ///
+    /// # Arguments
+    ///
+    /// - `pool`: A PostgreSQL connection pool (`PgPool`) representing the database connection.
+    ///
+    /// # Returns
+    ///
+    /// A new `PgSnapshotStore` instance.
+    pub fn new_uninitialized(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Returns the snapshots quarantined because they could not be deserialized, from the most recent.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the quarantined snapshots, or an error if the query fails.
+    pub async fn quarantined_snapshots(&self) -> Result<Vec<QuarantinedSnapshot>, Error> {
+        Ok(sqlx::query(
+            "SELECT name, query, version, payload, error FROM snapshot_quarantine ORDER BY quarantined_at DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| QuarantinedSnapshot {
+            name: row.get(0),
+            query: row.get(1),
+            version: row.get(2),
+            payload: row.get(3),
+            error: row.get(4),
+        })
+        .collect())
+    }
+
+    /// Moves a corrupt snapshot to the `snapshot_quarantine` table, recording the deserialization error.
+    async fn quarantine(&self, id: Uuid, error: &str) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT INTO snapshot_quarantine (id, name, query, version, payload, error) SELECT id, name, query, version, payload, $2 FROM snapshot WHERE id = $1")
+            .bind(id)
+            .bind(error)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM snapshot WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SnapshotStore<PgEventId> for PgSnapshotStore {
+    async fn load(
+        &self,
+        name: &str,
+        query: &str,
+    ) -> Result<Option<Snapshot<PgEventId>>, BoxDynError> {
+        Ok(
+            sqlx::query("SELECT name, query, payload, version FROM snapshot where id = $1")
+                .bind(snapshot_id(name, query))
+                .fetch_optional(&self.pool)
+                .await?
+                .map(|row| Snapshot {
+                    name: row.get(0),
+                    query: row.get(1),
+                    payload: row.get(2),
+                    version: row.get(3),
+                }),
+        )
+    }
+
+    async fn store(&self, snapshot: Snapshot<PgEventId>) -> Result<(), BoxDynError> {
         sqlx::query("INSERT INTO snapshot (id, name, query, payload, version) VALUES ($1,$2,$3,$4,$5) ON CONFLICT(id) DO UPDATE SET name = $2, query = $3, payload = $4, version = $5 WHERE snapshot.version < $5")
-        .bind(id)
-        .bind(S::NAME)
-        .bind(query)
-        .bind(payload)
-        .bind(version)
+        .bind(snapshot_id(&snapshot.name, &snapshot.query))
+        .bind(snapshot.name)
+        .bind(snapshot.query)
+        .bind(snapshot.payload)
+        .bind(snapshot.version)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
+
+    async fn discard(
+        &self,
+        snapshot: Snapshot<PgEventId>,
+        error: String,
+    ) -> Result<(), BoxDynError> {
+        Ok(self
+            .quarantine(snapshot_id(&snapshot.name, &snapshot.query), &error)
+            .await?)
+    }
+
+    async fn invalidate_from(&self, version: PgEventId) -> Result<(), BoxDynError> {
+        let mut tx = self.pool.begin().await?;
+        for table in ["snapshot", "snapshot_quarantine"] {
+            sqlx::query(&format!("DELETE FROM {table} WHERE version >= $1"))
+                .bind(version)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// A snapshot moved to the quarantine because it could not be deserialized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedSnapshot {
+    /// The name of the state query.
+    pub name: String,
+    /// The key of the stream query of the state.
+    pub query: String,
+    /// The ID of the last event applied to the snapshot.
+    pub version: PgEventId,
+    /// The serialized state that could not be deserialized.
+    pub payload: String,
+    /// The deserialization error.
+    pub error: String,
 }
 
 fn snapshot_id(state_name: &str, query: &str) -> Uuid {
@@ -122,32 +272,16 @@ fn snapshot_id(state_name: &str, query: &str) -> Uuid {
     )
 }
 
-fn query_key<E: Event + Clone>(query: &StreamQuery<PgEventId, E>) -> String {
-    let mut result = String::new();
-    for f in query.filters() {
-        let excluded_events = if let Some(exclued_events) = f.excluded_events() {
-            format!("-{}", exclued_events.join(","))
-        } else {
-            "".to_string()
-        };
-        result += &format!(
-            "({}|{}{}|{})",
-            f.origin(),
-            f.events().join(","),
-            excluded_events,
-            f.identifiers()
-                .iter()
-                .map(|(k, v)| format!("{k}={v}"))
-                .collect::<Vec<_>>()
-                .join(",")
-        );
-    }
-    result
-}
-
 pub async fn setup(pool: &PgPool) -> Result<(), Error> {
+    let mut tx = crate::setup_lock::begin(pool).await?;
     sqlx::query(include_str!("snapshotter/sql/table_snapshot.sql"))
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
+    sqlx::query(include_str!(
+        "snapshotter/sql/table_snapshot_quarantine.sql"
+    ))
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
     Ok(())
 }
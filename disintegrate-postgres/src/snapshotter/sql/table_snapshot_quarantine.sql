@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS snapshot_quarantine (
+    id uuid,
+    name text,
+    query text,
+    version bigint,
+    payload text,
+    error text,
+    quarantined_at TIMESTAMP DEFAULT now()
+);
@@ -1,6 +1,7 @@
 use disintegrate::{
-    domain_identifiers, ident, query, DomainIdentifierInfo, DomainIdentifierSet, Event, EventId,
-    EventInfo, EventSchema, IdentifierType, IntoState, IntoStatePart, PersistedEvent, StateMutate,
+    domain_identifiers, ident, query, query_key, DomainIdentifierInfo, DomainIdentifierSet, Event,
+    EventId, EventInfo, EventSchema, EveryEventsPolicy, IdentifierType, IntoState, IntoStatePart,
+    NeverPolicy, PerStatePolicy, PersistedEvent, SizeThresholdPolicy, StateMutate,
 };
 use disintegrate_serde::{serde::json::Json, Deserializer};
 use serde::Deserialize;
@@ -20,6 +21,10 @@ impl Event for CartEvent {
         events_info: &[&EventInfo {
             name: "CartProductAdded",
             domain_identifiers: &[&ident!(#cart_id), &ident!(#product_id)],
+            description: None,
+            owner: None,
+            aliases: &[],
+            version: 1,
         }],
         domain_identifiers: &[
             &DomainIdentifierInfo {
@@ -107,7 +112,7 @@ async fn it_stores_snapshots(pool: PgPool) {
         .await
         .unwrap();
 
-    let query_key = query_key(&state.query());
+    let query_key = query_key(&state.query::<PgEventId>());
     let snapshot_id = snapshot_id(CartState::NAME, &query_key);
     assert_eq!(stored_snapshot.id, snapshot_id);
     assert_eq!(stored_snapshot.name, CartState::NAME);
@@ -121,12 +126,45 @@ async fn it_stores_snapshots(pool: PgPool) {
     assert_eq!(stored_snapshot.version, 1);
 }
 
+#[sqlx::test]
+async fn it_stores_the_snapshots_allowed_by_the_policy_of_the_state(pool: PgPool) {
+    let snapshotter = PgSnapshotter::new(pool.clone(), 0)
+        .await
+        .unwrap()
+        .with_policy(
+            PerStatePolicy::new(EveryEventsPolicy(0)).with_state(CartState::NAME, NeverPolicy),
+        );
+    let mut state = CartState::new("c1", []).into_state_part();
+    state.mutate_part(PersistedEvent::new(
+        1,
+        CartEvent::ItemAdded {
+            cart_id: "c1".to_string(),
+            item_id: "p1".to_string(),
+        },
+    ));
+
+    snapshotter.store_snapshot(&state).await.unwrap();
+    let stored_snapshots: i64 = sqlx::query_scalar("SELECT count(*) FROM snapshot")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stored_snapshots, 0);
+
+    let snapshotter = snapshotter.with_policy(SizeThresholdPolicy(1));
+    snapshotter.store_snapshot(&state).await.unwrap();
+    let stored_snapshots: i64 = sqlx::query_scalar("SELECT count(*) FROM snapshot")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stored_snapshots, 1);
+}
+
 #[sqlx::test]
 async fn it_loads_snapshots(pool: PgPool) {
     let snapshotter = PgSnapshotter::new(pool.clone(), 2).await.unwrap();
     let default_state = CartState::new("c1", []);
     let expected_state = CartState::new("c1", ["p1", "p2"]);
-    let query_key = query_key(&default_state.query());
+    let query_key = query_key(&default_state.query::<PgEventId>());
     let snapshot_id = snapshot_id(CartState::NAME, &query_key);
     sqlx::query("INSERT INTO snapshot (id, name, query, payload, version) VALUES ($1,$2,$3,$4,$5) ON CONFLICT(id) DO UPDATE SET name = $2, query = $3, payload = $4, version = $5 WHERE snapshot.version < $5")
         .bind(snapshot_id)
@@ -144,3 +182,66 @@ async fn it_loads_snapshots(pool: PgPool) {
     assert_eq!(loaded_state.version(), 3);
     assert_eq!(loaded_state.into_state(), expected_state);
 }
+
+#[sqlx::test]
+async fn it_quarantines_the_snapshots_that_cannot_be_deserialized(pool: PgPool) {
+    let snapshotter = PgSnapshotter::new(pool.clone(), 2).await.unwrap();
+    let default_state = CartState::new("c1", []);
+    let query_key = query_key(&default_state.query::<PgEventId>());
+    sqlx::query("INSERT INTO snapshot (id, name, query, payload, version) VALUES ($1,$2,$3,$4,$5)")
+        .bind(snapshot_id(CartState::NAME, &query_key))
+        .bind(CartState::NAME)
+        .bind(&query_key)
+        .bind(r#"{"cart_id": "c1"}"#)
+        .bind(3)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let loaded_state = snapshotter
+        .load_snapshot(default_state.clone().into_state_part())
+        .await;
+
+    assert_eq!(loaded_state.version(), 0);
+    assert_eq!(loaded_state.into_state(), default_state);
+    let stored_snapshots: i64 = sqlx::query_scalar("SELECT count(*) FROM snapshot")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stored_snapshots, 0);
+    let quarantined = snapshotter.quarantined_snapshots().await.unwrap();
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(quarantined[0].name, CartState::NAME);
+    assert_eq!(quarantined[0].query, query_key);
+    assert_eq!(quarantined[0].version, 3);
+    assert_eq!(quarantined[0].payload, r#"{"cart_id": "c1"}"#);
+    assert!(quarantined[0].error.contains("missing field `items`"));
+}
+
+#[sqlx::test]
+async fn it_invalidates_the_snapshots_from_an_event(pool: PgPool) {
+    setup(&pool).await.unwrap();
+    let store = PgSnapshotStore::new_uninitialized(pool.clone());
+    let snapshot = |query: &str, version: PgEventId| Snapshot {
+        name: CartState::NAME.to_string(),
+        query: query.to_string(),
+        version,
+        payload: "{}".to_string(),
+    };
+    store.store(snapshot("c1", 3)).await.unwrap();
+    store.store(snapshot("c2", 5)).await.unwrap();
+    store.store(snapshot("c3", 8)).await.unwrap();
+    store
+        .discard(snapshot("c3", 8), "corrupt".to_string())
+        .await
+        .unwrap();
+
+    store.invalidate_from(5).await.unwrap();
+
+    let mut stored = vec![];
+    for query in ["c1", "c2", "c3"] {
+        stored.push(store.load(CartState::NAME, query).await.unwrap());
+    }
+    assert_eq!(stored, vec![Some(snapshot("c1", 3)), None, None]);
+    assert!(store.quarantined_snapshots().await.unwrap().is_empty());
+}
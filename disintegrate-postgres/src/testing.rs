@@ -0,0 +1,91 @@
+//! # PostgreSQL Test Utilities
+//!
+//! This module provides a disposable PostgreSQL database for integration tests.
+//! The database runs in a container started through `testcontainers` and is removed
+//! as soon as the `PgTestDatabase` is dropped.
+use disintegrate::{BoxDynError, Event};
+use disintegrate_serde::Serde;
+use sqlx::PgPool;
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::testcontainers::runners::AsyncRunner;
+use testcontainers_modules::testcontainers::ContainerAsync;
+
+use crate::{PgEventStore, PgSnapshotter};
+
+/// A disposable PostgreSQL database for integration tests.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let database = PgTestDatabase::start().await?;
+/// let event_store = database.event_store(Json::<DomainEvent>::default()).await?;
+/// ```
+pub struct PgTestDatabase {
+    pool: PgPool,
+    _container: ContainerAsync<Postgres>,
+}
+
+impl PgTestDatabase {
+    /// Starts a new PostgreSQL container and connects to it.
+    ///
+    /// # Returns
+    ///
+    /// A `PgTestDatabase` connected to the started container, or an error if the
+    /// container cannot be started.
+    pub async fn start() -> Result<Self, BoxDynError> {
+        let container = Postgres::default().start().await?;
+        let host = container.get_host().await?;
+        let port = container.get_host_port_ipv4(5432).await?;
+        let pool = PgPool::connect(&format!(
+            "postgres://postgres:postgres@{host}:{port}/postgres"
+        ))
+        .await?;
+        Ok(Self {
+            pool,
+            _container: container,
+        })
+    }
+
+    /// Returns the connection pool of the test database.
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Initializes the event store tables and returns a ready `PgEventStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `serde` - The serialization implementation for the event payload.
+    pub async fn event_store<E, S>(&self, serde: S) -> Result<PgEventStore<E, S>, BoxDynError>
+    where
+        E: Event,
+        S: Serde<E> + Send + Sync,
+    {
+        Ok(PgEventStore::new(self.pool.clone(), serde).await?)
+    }
+
+    /// Initializes the snapshot table and returns a ready `PgSnapshotter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `every` - The number of events between consecutive snapshots.
+    pub async fn snapshotter(&self, every: u64) -> Result<PgSnapshotter, BoxDynError> {
+        Ok(PgSnapshotter::new(self.pool.clone(), every).await?)
+    }
+
+    /// Returns a `PgEventListener` builder for the given event store.
+    ///
+    /// The listener tables are created when the listener starts.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_store` - The event store the listener reads the events from.
+    #[cfg(feature = "listener")]
+    pub fn listener<E, S>(&self, event_store: PgEventStore<E, S>) -> crate::PgEventListener<E, S>
+    where
+        E: Event + Clone + Send + Sync + 'static,
+        S: Serde<E> + Clone + Send + Sync + 'static,
+    {
+        crate::PgEventListener::builder(event_store)
+    }
+}
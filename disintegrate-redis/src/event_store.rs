@@ -0,0 +1,317 @@
+//! Redis Event Store
+//!
+//! This module provides an implementation of the `EventStore` trait using Redis Streams as the underlying storage.
+//! All the events are appended to a single stream, with the event type and the domain identifiers stored as fields
+//! of the entries, and the appends are validated by a Lua script that runs atomically on the server.
+mod criteria;
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use async_stream::stream;
+use async_trait::async_trait;
+use criteria::{clauses, Clause, IDENTIFIER_FIELD_PREFIX};
+use disintegrate::{
+    Event, EventStore, PersistedEvent, StreamQuery, UncheckedAppend, UncheckedAppendMetrics,
+};
+use disintegrate_serde::Serde;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use redis::aio::MultiplexedConnection;
+use redis::streams::StreamRangeReply;
+use redis::{Client, Script, Value};
+
+use crate::{Error, RedisEventId};
+
+/// The default prefix of the Redis keys of the event store.
+pub const DEFAULT_KEY_PREFIX: &str = "disintegrate";
+
+/// The maximum number of stream entries read by a single command while streaming the events.
+const READ_BATCH_SIZE: usize = 1000;
+
+/// Redis event store implementation.
+#[derive(Clone)]
+pub struct RedisEventStore<E, S>
+where
+    E: Event,
+    S: Serde<E> + Send + Sync,
+{
+    pub(crate) connection: MultiplexedConnection,
+    serde: S,
+    events_key: String,
+    event_id_key: String,
+    pub(crate) event_listener_key: String,
+    append_script: Script,
+    unchecked_append_metrics: Arc<UncheckedAppendMetrics>,
+    event_type: PhantomData<E>,
+}
+
+impl<E, S> std::fmt::Debug for RedisEventStore<E, S>
+where
+    E: Event,
+    S: Serde<E> + Send + Sync,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisEventStore")
+            .field("events_key", &self.events_key)
+            .field("event_id_key", &self.event_id_key)
+            .field("event_listener_key", &self.event_listener_key)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E, S> RedisEventStore<E, S>
+where
+    E: Event + Clone,
+    S: Serde<E> + Send + Sync,
+{
+    /// Connects to Redis and returns a new instance of `RedisEventStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The Redis client.
+    /// * `serde` - The serialization implementation for the event payload.
+    pub async fn new(client: Client, serde: S) -> Result<Self, Error> {
+        let connection = client.get_multiplexed_async_connection().await?;
+        Ok(Self {
+            connection,
+            serde,
+            events_key: String::new(),
+            event_id_key: String::new(),
+            event_listener_key: String::new(),
+            append_script: Script::new(include_str!("event_store/lua/append.lua")),
+            unchecked_append_metrics: Arc::new(UncheckedAppendMetrics::default()),
+            event_type: PhantomData,
+        }
+        .with_key_prefix(DEFAULT_KEY_PREFIX))
+    }
+
+    /// Sets the prefix of the Redis keys of the event store, so that several event stores can share a database.
+    ///
+    /// The keys are wrapped in a hash tag, so that they are assigned to the same slot of a Redis cluster.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix of the keys.
+    pub fn with_key_prefix(mut self, prefix: &str) -> Self {
+        self.events_key = format!("{{{prefix}}}:events");
+        self.event_id_key = format!("{{{prefix}}}:event_id");
+        self.event_listener_key = format!("{{{prefix}}}:event_listener");
+        self
+    }
+
+    /// Appends new events to the event store without validating the appended events against a query.
+    ///
+    /// The append is recorded in the `UncheckedAppendMetrics` of the event store.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - A vector of events to be appended.
+    /// * `unchecked` - The capability to append without validation, stating why it is safe.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a vector of `PersistedEvent` representing the appended events,
+    /// or an error of type `Error`.
+    pub async fn append_without_validation(
+        &self,
+        events: Vec<E>,
+        unchecked: UncheckedAppend,
+    ) -> Result<Vec<PersistedEvent<RedisEventId, E>>, Error> {
+        let persisted = self.invoke_append(events, 0, None).await?;
+        self.unchecked_append_metrics
+            .record(&unchecked, persisted.len());
+        Ok(persisted)
+    }
+
+    /// Returns the counters of the appends made without validation.
+    ///
+    /// The counters are shared by the clones of the event store.
+    pub fn unchecked_append_metrics(&self) -> Arc<UncheckedAppendMetrics> {
+        Arc::clone(&self.unchecked_append_metrics)
+    }
+
+    async fn invoke_append(
+        &self,
+        events: Vec<E>,
+        version: RedisEventId,
+        clauses: Option<Vec<Clause>>,
+    ) -> Result<Vec<PersistedEvent<RedisEventId, E>>, Error> {
+        let clauses = match clauses {
+            Some(clauses) => serde_json::to_string(&clauses)
+                .expect("the clauses of a stream query can always be serialized"),
+            None => String::new(),
+        };
+        let mut invocation = self.append_script.prepare_invoke();
+        invocation
+            .key(&self.events_key)
+            .key(&self.event_id_key)
+            .arg(version)
+            .arg(clauses)
+            .arg(events.len());
+        for event in &events {
+            let mut fields: Vec<(String, Vec<u8>)> = vec![
+                ("event_type".to_string(), event.name().as_bytes().to_vec()),
+                ("payload".to_string(), self.serde.serialize(event.clone())),
+            ];
+            fields.extend(event.domain_identifiers().iter().map(|(ident, value)| {
+                (
+                    format!("{IDENTIFIER_FIELD_PREFIX}{ident}"),
+                    value.to_string().into_bytes(),
+                )
+            }));
+            invocation.arg(fields.len() * 2);
+            for (field, value) in fields {
+                invocation.arg(field).arg(value);
+            }
+        }
+
+        let event_ids: Vec<RedisEventId> = invocation
+            .invoke_async(&mut self.connection.clone())
+            .await
+            .map_err(|err| {
+                if err.code() == Some("CONFLICT") {
+                    Error::Concurrency
+                } else {
+                    Error::Redis(err)
+                }
+            })?;
+        Ok(event_ids
+            .into_iter()
+            .zip(events)
+            .map(|(id, event)| PersistedEvent::new(id, event))
+            .collect())
+    }
+}
+
+/// Implementation of the event store using Redis.
+///
+/// This module provides the implementation of the `EventStore` trait for `RedisEventStore`,
+/// allowing interaction with a Redis event store. It enables streaming events based on
+/// a query and appending new events to the event store.
+#[async_trait]
+impl<E, S> EventStore<RedisEventId, E> for RedisEventStore<E, S>
+where
+    E: Event + Clone + Send + Sync,
+    S: Serde<E> + Send + Sync,
+{
+    type Error = Error;
+
+    /// Streams events based on the provided query.
+    ///
+    /// The entries of the events stream are read in batches from the smallest origin of the query, and
+    /// only the payloads of the entries matching the query are deserialized.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The stream query specifying the criteria for filtering events.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a boxed stream of `PersistedEvent` that matches the query criteria,
+    /// or an error of type `Self::Error`.
+    fn stream<'a, QE>(
+        &'a self,
+        query: &'a StreamQuery<RedisEventId, QE>,
+    ) -> BoxStream<'a, Result<PersistedEvent<RedisEventId, QE>, Self::Error>>
+    where
+        QE: TryFrom<E> + Event + 'static + Clone + Send + Sync,
+        <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
+    {
+        stream! {
+            let clauses = clauses(query);
+            let mut connection = self.connection.clone();
+            let mut last_event_id = clauses.iter().map(|clause| clause.origin).min().unwrap_or_default();
+            let mut exhausted = clauses.is_empty();
+            while !exhausted {
+                let reply: StreamRangeReply = redis::cmd("XRANGE")
+                    .arg(&self.events_key)
+                    .arg(format!("{}-0", last_event_id + 1))
+                    .arg("+")
+                    .arg("COUNT")
+                    .arg(READ_BATCH_SIZE)
+                    .query_async(&mut connection)
+                    .await?;
+                let read = reply.ids.len();
+                for entry in reply.ids {
+                    let event_id = parse_event_id(&entry.id)?;
+                    last_event_id = event_id;
+                    let mut fields = entry.map;
+                    let event_type = string_field(&entry.id, &fields, "event_type")?;
+                    let identifiers: HashMap<String, String> = fields
+                        .iter()
+                        .filter(|(field, _)| field.starts_with(IDENTIFIER_FIELD_PREFIX))
+                        .map(|(field, _)| Ok((field.clone(), string_field(&entry.id, &fields, field)?)))
+                        .collect::<Result<_, Error>>()?;
+                    if !clauses.iter().any(|clause| clause.matches(event_id, &event_type, &identifiers)) {
+                        continue;
+                    }
+                    let payload: Vec<u8> = fields
+                        .remove("payload")
+                        .map(|payload| redis::from_redis_value(&payload))
+                        .transpose()?
+                        .ok_or_else(|| Error::MalformedEntry(entry.id.clone()))?;
+                    let payload = self.serde.deserialize(payload)?;
+                    yield Ok(PersistedEvent::new(event_id, payload.try_into().map_err(|e| Error::QueryEventMapping(Box::new(e)))?));
+                }
+                exhausted = read < READ_BATCH_SIZE;
+            }
+        }
+        .boxed()
+    }
+
+    /// Appends new events to the event store.
+    ///
+    /// The events are appended by a Lua script that first checks that no event matching the `query` has been
+    /// appended after `version`, and then adds the events to the stream. Since the script runs atomically,
+    /// no other append can interleave between the check and the write. If a matching event is found,
+    /// a `Concurrency` error is returned and no event is appended.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - A vector of events to be appended.
+    /// * `query` - The stream query specifying the criteria for filtering events.
+    /// * `version` - The ID of the last consumed event.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a vector of `PersistedEvent` representing the appended events,
+    /// or an error of type `Self::Error`.
+    async fn append<QE>(
+        &self,
+        events: Vec<E>,
+        query: StreamQuery<RedisEventId, QE>,
+        version: RedisEventId,
+    ) -> Result<Vec<PersistedEvent<RedisEventId, E>>, Self::Error>
+    where
+        E: Clone + 'async_trait,
+        QE: Event + Clone + Send + Sync,
+    {
+        let clauses = clauses(&query.change_origin(version));
+        self.invoke_append(events, version, Some(clauses)).await
+    }
+}
+
+/// Parses the event ID from the ID of a stream entry, e.g. `42-0`.
+fn parse_event_id(entry_id: &str) -> Result<RedisEventId, Error> {
+    entry_id
+        .split_once('-')
+        .and_then(|(event_id, _)| event_id.parse().ok())
+        .ok_or_else(|| Error::MalformedEntry(entry_id.to_string()))
+}
+
+fn string_field(
+    entry_id: &str,
+    fields: &HashMap<String, Value>,
+    field: &str,
+) -> Result<String, Error> {
+    fields
+        .get(field)
+        .map(redis::from_redis_value)
+        .transpose()?
+        .ok_or_else(|| Error::MalformedEntry(entry_id.to_string()))
+}
@@ -0,0 +1,238 @@
+//! Stream query criteria
+//!
+//! Redis has no query language over the stream entries: the stream queries are flattened into a list of
+//! clauses, one for each event type, that are evaluated both by the store while streaming the events and
+//! by the append script while checking for conflicts, so that the two code paths share the same semantics.
+use std::collections::{BTreeMap, HashMap};
+
+use disintegrate::{Event, IdentifierCondition, StreamQuery};
+use serde::Serialize;
+
+use crate::RedisEventId;
+
+/// The prefix of the stream entry fields holding the domain identifiers.
+pub const IDENTIFIER_FIELD_PREFIX: &str = "id:";
+
+/// A clause matching the events of a type, after an origin, with the given domain identifiers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Clause {
+    pub event_type: &'static str,
+    pub origin: RedisEventId,
+    pub identifiers: BTreeMap<String, String>,
+    pub conditions: Vec<ClauseCondition>,
+}
+
+/// A comparison of a domain identifier with a list of values.
+///
+/// The identifier must be one of the values, or, if the condition is negated, none of them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ClauseCondition {
+    pub identifier: String,
+    pub values: Vec<String>,
+    pub negated: bool,
+}
+
+impl ClauseCondition {
+    fn matches(&self, fields: &HashMap<String, String>) -> bool {
+        let value = fields.get(&format!("{IDENTIFIER_FIELD_PREFIX}{}", self.identifier));
+        value.is_some_and(|value| self.values.contains(value)) != self.negated
+    }
+}
+
+impl Clause {
+    /// Checks if the clause matches a stream entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - The ID of the stream entry.
+    /// * `event_type` - The event type of the stream entry.
+    /// * `fields` - The domain identifier fields of the stream entry.
+    pub fn matches(
+        &self,
+        event_id: RedisEventId,
+        event_type: &str,
+        fields: &HashMap<String, String>,
+    ) -> bool {
+        self.event_type == event_type
+            && event_id > self.origin
+            && self.identifiers.iter().all(|(ident, value)| {
+                fields.get(&format!("{IDENTIFIER_FIELD_PREFIX}{ident}")) == Some(value)
+            })
+            && self
+                .conditions
+                .iter()
+                .all(|condition| condition.matches(fields))
+    }
+}
+
+/// Flattens a stream query into the clauses matching its events.
+///
+/// The domain identifiers of a filter, compared by equality or by a condition, are included only in the clauses
+/// of the event types that declare them, following the `MissingIdentifier` semantics of the filter.
+pub fn clauses<QE: Event + Clone>(query: &StreamQuery<RedisEventId, QE>) -> Vec<Clause> {
+    let mut clauses = vec![];
+    for filter in query.filters() {
+        'event_types: for event_type in filter.events() {
+            if filter
+                .excluded_events()
+                .is_some_and(|excluded_events| excluded_events.contains(event_type))
+            {
+                continue;
+            }
+            let Some(event_info) = QE::SCHEMA.event_info(event_type) else {
+                continue;
+            };
+            if filter.excludes_event_type(event_info) {
+                continue;
+            }
+            let identifiers: BTreeMap<_, _> = filter
+                .identifiers()
+                .iter()
+                .filter(|(ident, _)| event_info.has_domain_identifier(ident))
+                .map(|(ident, value)| (ident.to_string(), value.to_string()))
+                .collect();
+            let mut conditions = vec![];
+            for condition in filter.declared_conditions(event_info) {
+                let (values, negated) = match condition {
+                    IdentifierCondition::NotEq(_, value) => (vec![value.to_string()], true),
+                    IdentifierCondition::In(_, values) => {
+                        (values.iter().map(ToString::to_string).collect(), false)
+                    }
+                    // the identifiers declared by an event are never null.
+                    IdentifierCondition::IsNull(_) => continue 'event_types,
+                };
+                conditions.push(ClauseCondition {
+                    identifier: condition.identifier().to_string(),
+                    values,
+                    negated,
+                });
+            }
+            // the events persisted before a rename are stored with the aliases of the event.
+            clauses.extend(event_info.event_types().map(|event_type| Clause {
+                event_type,
+                origin: filter.origin(),
+                identifiers: identifiers.clone(),
+                conditions: conditions.clone(),
+            }));
+        }
+    }
+    clauses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use disintegrate::{
+        domain_identifiers, ident, query, DomainIdentifierInfo, DomainIdentifierSet, EventInfo,
+        EventSchema, IdentifierType, MissingIdentifier,
+    };
+
+    #[allow(dead_code)]
+    #[derive(Clone)]
+    enum TestEvent {
+        Bar { bar_id: String },
+        Foo { foo_id: String },
+    }
+
+    impl Event for TestEvent {
+        const SCHEMA: EventSchema = EventSchema {
+            events: &["Bar", "Foo"],
+            events_info: &[
+                &EventInfo {
+                    name: "Bar",
+                    domain_identifiers: &[&ident!(#bar_id)],
+                    description: None,
+                    owner: None,
+                    aliases: &[],
+                    version: 1,
+                },
+                &EventInfo {
+                    name: "Foo",
+                    domain_identifiers: &[&ident!(#foo_id)],
+                    description: None,
+                    owner: None,
+                    aliases: &[],
+                    version: 1,
+                },
+            ],
+            domain_identifiers: &[
+                &DomainIdentifierInfo {
+                    ident: ident!(#bar_id),
+                    type_info: IdentifierType::String,
+                },
+                &DomainIdentifierInfo {
+                    ident: ident!(#foo_id),
+                    type_info: IdentifierType::String,
+                },
+            ],
+        };
+
+        fn name(&self) -> &'static str {
+            match self {
+                TestEvent::Bar { .. } => "Bar",
+                TestEvent::Foo { .. } => "Foo",
+            }
+        }
+
+        fn domain_identifiers(&self) -> DomainIdentifierSet {
+            match self {
+                TestEvent::Bar { bar_id } => domain_identifiers! {bar_id: bar_id},
+                TestEvent::Foo { foo_id } => domain_identifiers! {foo_id: foo_id},
+            }
+        }
+    }
+
+    #[test]
+    fn it_flattens_a_query_into_clauses() {
+        let query = query!(10 => TestEvent; foo_id == "value");
+
+        assert_eq!(
+            clauses(&query),
+            vec![
+                Clause {
+                    event_type: "Bar",
+                    origin: 10,
+                    identifiers: BTreeMap::new(),
+                    conditions: vec![],
+                },
+                Clause {
+                    event_type: "Foo",
+                    origin: 10,
+                    identifiers: [("foo_id".to_string(), "value".to_string())].into(),
+                    conditions: vec![],
+                },
+            ]
+        );
+        assert!(
+            clauses(&query.with_missing_identifier(MissingIdentifier::NoMatch))
+                .iter()
+                .all(|clause| clause.event_type == "Foo")
+        );
+    }
+
+    #[test]
+    fn it_matches_the_stream_entries() {
+        let clause = &clauses(&query!(TestEvent; foo_id == "value"))[1];
+        let fields: HashMap<String, String> =
+            [("id:foo_id".to_string(), "value".to_string())].into();
+
+        assert!(clause.matches(1, "Foo", &fields));
+        assert!(!clause.matches(1, "Bar", &fields));
+        assert!(!clause.matches(1, "Foo", &HashMap::new()));
+    }
+
+    #[test]
+    fn it_matches_the_identifier_conditions() {
+        let query_clauses = clauses(&query!(TestEvent; foo_id in ["v1", "v2"], bar_id != "v3"));
+        let foo = |value: &str| [("id:foo_id".to_string(), value.to_string())].into();
+        let bar = |value: &str| [("id:bar_id".to_string(), value.to_string())].into();
+
+        assert!(query_clauses[1].matches(1, "Foo", &foo("v2")));
+        assert!(!query_clauses[1].matches(1, "Foo", &foo("v3")));
+        assert!(query_clauses[0].matches(1, "Bar", &bar("v1")));
+        assert!(!query_clauses[0].matches(1, "Bar", &bar("v3")));
+        assert!(clauses(&query!(TestEvent; foo_id is_null))
+            .iter()
+            .all(|clause| clause.event_type == "Bar"));
+    }
+}
@@ -0,0 +1,58 @@
+-- Appends events to the stream, failing if an event matching the clauses has been appended after the version.
+--
+-- KEYS[1]: the events stream
+-- KEYS[2]: the event ID counter
+-- ARGV[1]: the version, i.e. the ID of the last event used to make the decision
+-- ARGV[2]: the JSON encoded clauses of the validation query, or an empty string to skip the validation
+-- ARGV[3]: the number of events to append, followed, for each event, by the number of its fields and the fields
+local version = tonumber(ARGV[1])
+
+if ARGV[2] ~= '' then
+    local clauses = cjson.decode(ARGV[2])
+    local entries = redis.call('XRANGE', KEYS[1], (version + 1) .. '-0', '+')
+    for _, entry in ipairs(entries) do
+        local event_id = tonumber(string.match(entry[1], '^(%d+)'))
+        local fields = {}
+        for i = 1, #entry[2], 2 do
+            fields[entry[2][i]] = entry[2][i + 1]
+        end
+        for _, clause in ipairs(clauses) do
+            if fields['event_type'] == clause['event_type'] and event_id > clause['origin'] then
+                local matches = true
+                for ident, value in pairs(clause['identifiers']) do
+                    if fields['id:' .. ident] ~= value then
+                        matches = false
+                        break
+                    end
+                end
+                for _, condition in ipairs(clause['conditions']) do
+                    local found = false
+                    for _, value in ipairs(condition['values']) do
+                        if fields['id:' .. condition['identifier']] == value then
+                            found = true
+                            break
+                        end
+                    end
+                    if found == condition['negated'] then
+                        matches = false
+                        break
+                    end
+                end
+                if matches then
+                    return redis.error_reply('CONFLICT event ' .. event_id .. ' matches the validation query')
+                end
+            end
+        end
+    end
+end
+
+local event_ids = {}
+local position = 4
+for _ = 1, tonumber(ARGV[3]) do
+    local fields_count = tonumber(ARGV[position])
+    local event_id = redis.call('INCR', KEYS[2])
+    redis.call('XADD', KEYS[1], event_id .. '-0', unpack(ARGV, position + 1, position + fields_count))
+    table.insert(event_ids, event_id)
+    position = position + fields_count + 1
+end
+return event_ids
@@ -0,0 +1,126 @@
+use disintegrate::{query, Event, EventStore};
+use disintegrate_serde::serde::json::Json;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+
+use super::*;
+use crate::testing::{client, key_prefix};
+
+#[derive(Debug, Clone, PartialEq, Event, Serialize, Deserialize)]
+enum ShoppingCartEvent {
+    Added {
+        #[id]
+        product_id: String,
+        #[id]
+        cart_id: String,
+    },
+    Removed {
+        #[id]
+        product_id: String,
+        #[id]
+        cart_id: String,
+    },
+}
+
+fn added_event(product_id: &str, cart_id: &str) -> ShoppingCartEvent {
+    ShoppingCartEvent::Added {
+        product_id: product_id.to_string(),
+        cart_id: cart_id.to_string(),
+    }
+}
+
+fn removed_event(product_id: &str, cart_id: &str) -> ShoppingCartEvent {
+    ShoppingCartEvent::Removed {
+        product_id: product_id.to_string(),
+        cart_id: cart_id.to_string(),
+    }
+}
+
+/// Connects to the Redis server of the tests, using keys not shared with the other tests.
+async fn event_store() -> RedisEventStore<ShoppingCartEvent, Json<ShoppingCartEvent>> {
+    RedisEventStore::new(client(), Json::default())
+        .await
+        .unwrap()
+        .with_key_prefix(&key_prefix())
+}
+
+#[test]
+fn it_parses_the_event_id_of_a_stream_entry() {
+    assert_eq!(parse_event_id("42-0").unwrap(), 42);
+    assert!(matches!(
+        parse_event_id("not-an-id"),
+        Err(Error::MalformedEntry(_))
+    ));
+}
+
+#[tokio::test]
+#[ignore = "requires a Redis server referenced by REDIS_URL"]
+async fn it_appends_and_streams_the_events_of_a_query() {
+    let event_store = event_store().await;
+
+    let persisted_events = event_store
+        .append(
+            vec![
+                added_event("product_1", "cart_1"),
+                added_event("product_1", "cart_2"),
+                removed_event("product_1", "cart_1"),
+            ],
+            query!(ShoppingCartEvent; cart_id == "cart_1"),
+            0,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        persisted_events
+            .iter()
+            .map(|event| event.id())
+            .collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+
+    let events: Vec<_> = event_store
+        .stream(&query!(ShoppingCartEvent; cart_id == "cart_1"))
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(
+        events,
+        vec![
+            PersistedEvent::new(1, added_event("product_1", "cart_1")),
+            PersistedEvent::new(3, removed_event("product_1", "cart_1")),
+        ]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a Redis server referenced by REDIS_URL"]
+async fn it_returns_a_concurrency_error_when_an_event_of_the_query_has_been_appended() {
+    let event_store = event_store().await;
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+    event_store
+        .append(vec![added_event("product_1", "cart_1")], query.clone(), 0)
+        .await
+        .unwrap();
+    event_store
+        .append_without_validation(
+            vec![added_event("product_2", "cart_1")],
+            UncheckedAppend::because("simulating a concurrent append"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(event_store.unchecked_append_metrics().appends(), 1);
+
+    let result = event_store
+        .append(vec![removed_event("product_1", "cart_1")], query, 1)
+        .await;
+
+    assert!(matches!(result, Err(Error::Concurrency)));
+    event_store
+        .append(
+            vec![added_event("product_1", "cart_2")],
+            query!(ShoppingCartEvent; cart_id == "cart_2"),
+            1,
+        )
+        .await
+        .unwrap();
+}
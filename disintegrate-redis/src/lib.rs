@@ -0,0 +1,35 @@
+//! # Redis Disintegrate Backend Library
+mod error;
+mod event_store;
+#[cfg(feature = "listener")]
+mod listener;
+#[cfg(test)]
+mod testing;
+
+pub use crate::event_store::RedisEventStore;
+#[cfg(feature = "listener")]
+pub use crate::listener::{RedisEventListener, RedisEventListenerConfig};
+use disintegrate::{DecisionMaker, Event, EventSourcedStateStore, NoSnapshot};
+use disintegrate_serde::Serde;
+pub use error::Error;
+
+pub type RedisEventId = i64;
+
+/// An alias for [`DecisionMaker`], specialized for Redis.
+pub type RedisDecisionMaker<E, S> =
+    DecisionMaker<EventSourcedStateStore<RedisEventId, E, RedisEventStore<E, S>, NoSnapshot>>;
+
+/// Creates a decision maker specialized for Redis.
+///
+/// # Arguments
+///
+/// - `event_store`: An instance of `RedisEventStore`.
+///
+/// # Returns
+///
+/// A `RedisDecisionMaker` with no snapshotting.
+pub fn decision_maker<E: Event + Send + Sync + Clone, S: Serde<E> + Clone + Sync + Send>(
+    event_store: RedisEventStore<E, S>,
+) -> RedisDecisionMaker<E, S> {
+    DecisionMaker::new(EventSourcedStateStore::new(event_store, NoSnapshot))
+}
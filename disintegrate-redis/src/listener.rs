@@ -0,0 +1,311 @@
+//! # Redis Event Listener
+//!
+//! This module provides an implementation of the event listeners using Redis to store their checkpoints.
+//! The events are handled in order, and the ID of the last processed event of each listener is stored in the
+//! `event_listener` hash of the event store, so that a listener resumes from where it left off after a restart.
+//!
+//! The checkpoints are not locked: a listener must be run by a single process.
+#[cfg(test)]
+mod tests;
+
+use std::error::Error as StdError;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use disintegrate::{
+    BoxDynError, DecisionContext, ErasedEventListener, Event, EventListener, EventStore,
+};
+use disintegrate_serde::Serde;
+use futures::future::join_all;
+use futures::{try_join, StreamExt};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::{Error, RedisEventId, RedisEventStore};
+
+/// Redis event listener implementation.
+pub struct RedisEventListener<E, S>
+where
+    E: Event + Clone,
+    S: Serde<E> + Send + Sync,
+{
+    executors: Vec<Box<dyn EventListenerExecutor>>,
+    event_store: RedisEventStore<E, S>,
+    shutdown_token: CancellationToken,
+}
+
+impl<E, S> std::fmt::Debug for RedisEventListener<E, S>
+where
+    E: Event + Clone,
+    S: Serde<E> + Send + Sync,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisEventListener")
+            .field("listeners", &self.executors.len())
+            .field("event_store", &self.event_store)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E, S> RedisEventListener<E, S>
+where
+    E: Event + Clone + Send + Sync + 'static,
+    S: Serde<E> + Clone + Send + Sync + 'static,
+{
+    /// Creates a new `RedisEventListener` that listens to the events coming from the provided `RedisEventStore`
+    ///
+    /// # Parameters
+    ///
+    /// * `event_store`: An instance of `RedisEventStore` representing the event store for the listener.
+    ///
+    /// # Returns
+    ///
+    /// A new `RedisEventListener` instance.
+    pub fn builder(event_store: RedisEventStore<E, S>) -> Self {
+        Self {
+            event_store,
+            executors: vec![],
+            shutdown_token: CancellationToken::new(),
+        }
+    }
+
+    /// Registers an event listener to the `RedisEventListener`.
+    ///
+    /// # Parameters
+    ///
+    /// * `event_listener`: An implementation of the `EventListener` trait for the specified event type `QE`.
+    /// * `config`: A `RedisEventListenerConfig` instance representing the configuration for the event listener.
+    ///
+    /// # Returns
+    ///
+    /// The updated `RedisEventListener` instance with the registered event handler.
+    pub fn register_listener<QE>(
+        mut self,
+        event_listener: impl EventListener<RedisEventId, QE, Error: StdError + Send + Sync + 'static>
+            + 'static,
+        config: RedisEventListenerConfig,
+    ) -> Self
+    where
+        QE: TryFrom<E> + Event + Send + Sync + Clone + 'static,
+        <QE as TryFrom<E>>::Error: StdError + Send + Sync,
+    {
+        self.executors.push(Box::new(RedisEventListenerExecutor {
+            event_store: self.event_store.clone(),
+            event_handler: Arc::new(ErasedEventListener::new(event_listener)),
+            config,
+            shutdown_token: self.shutdown_token.clone(),
+            _event_listener_events: PhantomData,
+        }));
+        self
+    }
+
+    /// Registers an event listener handling the events of the event store.
+    ///
+    /// This is a shorthand of `register_listener` for the common case of a listener of the same
+    /// event type of the event store, which does not need any type annotation.
+    ///
+    /// # Parameters
+    ///
+    /// * `event_listener`: An implementation of the `EventListener` trait for the event type `E`.
+    /// * `config`: A `RedisEventListenerConfig` instance representing the configuration for the event listener.
+    ///
+    /// # Returns
+    ///
+    /// The updated `RedisEventListener` instance with the registered event handler.
+    pub fn register(
+        self,
+        event_listener: impl EventListener<RedisEventId, E, Error: StdError + Send + Sync + 'static>
+            + 'static,
+        config: RedisEventListenerConfig,
+    ) -> Self {
+        self.register_listener::<E>(event_listener, config)
+    }
+
+    /// Starts the listener process for all registered event listeners.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating the success or failure of the listener process.
+    pub async fn start(self) -> Result<(), Error> {
+        let mut handles = vec![];
+        for executor in self.executors {
+            executor.init().await?;
+            handles.push(executor.run());
+        }
+        join_all(handles).await;
+        Ok(())
+    }
+
+    /// Starts the listener process for all the registered event listeners with a shutdown signal.
+    ///
+    /// # Parameters
+    ///
+    /// * `shutdown`: A future that represents the shutdown signal.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating the success or failure of the listener process.
+    pub async fn start_with_shutdown<F: Future<Output = ()> + Send + 'static>(
+        self,
+        shutdown: F,
+    ) -> Result<(), Error> {
+        let shutdown_token = self.shutdown_token.clone();
+        let shutdown_handle = async move {
+            shutdown.await;
+            shutdown_token.cancel();
+            Ok::<(), Error>(())
+        };
+        try_join!(self.start(), shutdown_handle).map(|_| ())
+    }
+}
+
+/// Redis listener Configuration
+///
+/// # Properties:
+///
+/// * `poll`: The interval at which the listener polls for new events from the event store.
+/// * `fetch_size`: The maximum number of events handled by a single poll.
+#[derive(Debug, Clone)]
+pub struct RedisEventListenerConfig {
+    poll: Duration,
+    fetch_size: usize,
+}
+
+impl RedisEventListenerConfig {
+    /// Creates a new `RedisEventListenerConfig` with the specified poll interval.
+    ///
+    /// # Parameters
+    ///
+    /// * `poll`: The poll interval.
+    ///
+    /// # Returns
+    ///
+    /// A new `RedisEventListenerConfig` instance.
+    pub fn poller(poll: Duration) -> Self {
+        Self {
+            poll,
+            fetch_size: usize::MAX,
+        }
+    }
+
+    /// Sets the fetch size for the event listener.
+    /// The fetch size determines the number of events to fetch from the event store at a time.
+    ///
+    /// # Parameters
+    ///
+    /// * `fetch_size`: The number of events to fetch from the event store at a time.
+    ///
+    /// # Returns
+    ///
+    /// A new `RedisEventListenerConfig` instance.
+    pub fn fetch_size(mut self, fetch_size: usize) -> Self {
+        self.fetch_size = fetch_size;
+        self
+    }
+}
+
+#[async_trait]
+trait EventListenerExecutor {
+    async fn init(&self) -> Result<(), Error>;
+    fn run(self: Box<Self>) -> JoinHandle<Result<(), Error>>;
+}
+
+struct RedisEventListenerExecutor<QE, E, S>
+where
+    QE: TryFrom<E> + Event + Send + Sync + Clone,
+    E: Event + Clone + Sync + Send,
+    S: Serde<E> + Clone + Send + Sync,
+{
+    event_store: RedisEventStore<E, S>,
+    event_handler: Arc<dyn EventListener<RedisEventId, QE, Error = BoxDynError>>,
+    config: RedisEventListenerConfig,
+    shutdown_token: CancellationToken,
+    _event_listener_events: PhantomData<QE>,
+}
+
+impl<QE, E, S> RedisEventListenerExecutor<QE, E, S>
+where
+    E: Event + Clone + Sync + Send + 'static,
+    S: Serde<E> + Clone + Send + Sync + 'static,
+    QE: TryFrom<E> + Event + 'static + Send + Sync + Clone,
+    <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
+{
+    /// Handles the events following the last processed one, stopping at the first failure.
+    ///
+    /// The failed event is retried at the next poll.
+    async fn execute(&self) -> Result<(), Error> {
+        let mut connection = self.event_store.connection.clone();
+        let last_processed_event_id: RedisEventId = redis::cmd("HGET")
+            .arg(&self.event_store.event_listener_key)
+            .arg(self.event_handler.id())
+            .query_async(&mut connection)
+            .await?;
+        let query = self
+            .event_handler
+            .query()
+            .clone()
+            .change_origin(last_processed_event_id);
+        let mut handled_event_id = last_processed_event_id;
+        let mut events_stream = self.event_store.stream(&query).take(self.config.fetch_size);
+        while let Some(Ok(event)) = events_stream.next().await {
+            let event_id = event.id();
+            let context = DecisionContext::caused_by(&event);
+            if context
+                .scope(self.event_handler.handle(event))
+                .await
+                .is_err()
+            {
+                break;
+            }
+            handled_event_id = event_id;
+            if self.shutdown_token.is_cancelled() {
+                break;
+            }
+        }
+        if handled_event_id > last_processed_event_id {
+            redis::cmd("HSET")
+                .arg(&self.event_store.event_listener_key)
+                .arg(self.event_handler.id())
+                .arg(handled_event_id)
+                .exec_async(&mut connection)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<QE, E, S> EventListenerExecutor for RedisEventListenerExecutor<QE, E, S>
+where
+    E: Event + Clone + Sync + Send + 'static,
+    S: Serde<E> + Clone + Send + Sync + 'static,
+    QE: TryFrom<E> + Event + 'static + Send + Sync + Clone,
+    <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
+{
+    async fn init(&self) -> Result<(), Error> {
+        redis::cmd("HSETNX")
+            .arg(&self.event_store.event_listener_key)
+            .arg(self.event_handler.id())
+            .arg(0)
+            .exec_async(&mut self.event_store.connection.clone())
+            .await?;
+        Ok(())
+    }
+
+    fn run(self: Box<Self>) -> JoinHandle<Result<(), Error>> {
+        let shutdown = self.shutdown_token.clone();
+        let mut poll = tokio::time::interval(self.config.poll);
+        poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = poll.tick() => self.execute().await?,
+                    _ = shutdown.cancelled() => return Ok::<(), Error>(()),
+                };
+            }
+        })
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::Mutex;
+
+use disintegrate::{query, PersistedEvent, StreamQuery};
+use disintegrate_serde::serde::json::Json;
+use serde::{Deserialize, Serialize};
+
+use super::*;
+use crate::testing::{client, key_prefix};
+
+#[derive(Debug, Clone, PartialEq, Event, Serialize, Deserialize)]
+enum ShoppingCartEvent {
+    Added {
+        #[id]
+        product_id: String,
+        #[id]
+        cart_id: String,
+    },
+}
+
+fn added_event(product_id: &str, cart_id: &str) -> ShoppingCartEvent {
+    ShoppingCartEvent::Added {
+        product_id: product_id.to_string(),
+        cart_id: cart_id.to_string(),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("the read model is not available")]
+struct ReadModelError;
+
+/// Records the handled events, failing the first attempt to handle the event `fail_once`.
+struct RecordingListener {
+    query: StreamQuery<RedisEventId, ShoppingCartEvent>,
+    handled: Arc<Mutex<Vec<RedisEventId>>>,
+    fail_once: Mutex<Option<RedisEventId>>,
+}
+
+#[async_trait]
+impl EventListener<RedisEventId, ShoppingCartEvent> for RecordingListener {
+    type Error = ReadModelError;
+
+    fn id(&self) -> &'static str {
+        "carts"
+    }
+
+    fn query(&self) -> &StreamQuery<RedisEventId, ShoppingCartEvent> {
+        &self.query
+    }
+
+    async fn handle(
+        &self,
+        event: PersistedEvent<RedisEventId, ShoppingCartEvent>,
+    ) -> Result<(), Self::Error> {
+        let mut fail_once = self.fail_once.lock().unwrap();
+        if *fail_once == Some(event.id()) {
+            *fail_once = None;
+            return Err(ReadModelError);
+        }
+        self.handled.lock().unwrap().push(event.id());
+        Ok(())
+    }
+}
+
+#[tokio::test]
+#[ignore = "requires a Redis server referenced by REDIS_URL"]
+async fn it_handles_the_events_retrying_the_failed_ones() {
+    let event_store = RedisEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        client(),
+        Json::default(),
+    )
+    .await
+    .unwrap()
+    .with_key_prefix(&key_prefix());
+    event_store
+        .append(
+            vec![
+                added_event("p1", "c1"),
+                added_event("p2", "c2"),
+                added_event("p3", "c1"),
+            ],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    let handled = Arc::new(Mutex::new(vec![]));
+    let listener = RecordingListener {
+        query: query!(ShoppingCartEvent; cart_id == "c1"),
+        handled: handled.clone(),
+        fail_once: Mutex::new(Some(3)),
+    };
+
+    RedisEventListener::builder(event_store.clone())
+        .register(
+            listener,
+            RedisEventListenerConfig::poller(Duration::from_millis(10)),
+        )
+        .start_with_shutdown(tokio::time::sleep(Duration::from_millis(200)))
+        .await
+        .unwrap();
+
+    assert_eq!(*handled.lock().unwrap(), vec![1, 3]);
+    let last_processed_event_id: RedisEventId = redis::cmd("HGET")
+        .arg(&event_store.event_listener_key)
+        .arg("carts")
+        .query_async(&mut event_store.connection.clone())
+        .await
+        .unwrap();
+    assert_eq!(last_processed_event_id, 3);
+}
@@ -0,0 +1,27 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use redis::Client;
+
+/// Connects to the Redis server referenced by the `REDIS_URL` environment variable.
+///
+/// The tests using Redis are ignored by default: run them with `cargo test -- --ignored` and a server.
+pub(crate) fn client() -> Client {
+    let url = std::env::var("REDIS_URL")
+        .expect("REDIS_URL must reference the Redis server of the ignored tests");
+    Client::open(url).unwrap()
+}
+
+/// Returns a key prefix not shared with the other tests, so they do not need a database of their own.
+pub(crate) fn key_prefix() -> String {
+    static NEXT_STORE: AtomicU32 = AtomicU32::new(0);
+
+    let run = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!(
+        "disintegrate_test_{run}_{}",
+        NEXT_STORE.fetch_add(1, Ordering::Relaxed)
+    )
+}
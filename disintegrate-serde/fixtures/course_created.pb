@@ -0,0 +1,2 @@
+
+c-42Café Rust 🦀 (2rust2beginner@
\ No newline at end of file
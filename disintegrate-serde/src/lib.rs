@@ -1,6 +1,14 @@
 //! # Event Store Serialization Deserializaion Library
 //!
 //! This library provides traits and implementations for serializing and deserializing events for the Disintegrate Event Store.
-//! It includes implementations for common formats such as Avro, JSON, Protocol Buffers (Prost).
+//! It includes implementations for common formats such as Avro, JSON, Protocol Buffers (Prost),
+//! and the compression and the encryption of the payloads at rest.
+#[cfg(feature = "schema")]
+pub mod schema;
 pub mod serde;
-pub use crate::serde::{Deserializer, Error, Serde, Serializer};
+#[cfg(any(feature = "zstd", feature = "lz4", feature = "gzip"))]
+pub use crate::serde::compressed::{Compressed, Compression};
+#[cfg(feature = "encryption")]
+pub use crate::serde::encrypted::{Encrypted, EncryptionKey, KeyProvider, Keyring};
+pub use crate::serde::upcast::{Upcasted, Upcaster};
+pub use crate::serde::{Deserializer, Error, PayloadInfo, Serde, Serializer};
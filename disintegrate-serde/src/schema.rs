@@ -0,0 +1,218 @@
+//! # Event schema generation
+//!
+//! This module generates the JSON Schema and the TypeScript definitions of the events, so that
+//! the consumers of the event feed written in other languages can be type-safe.
+//! The events must implement [`JsonSchema`], usually derived with `#[derive(schemars::JsonSchema)]`.
+//!
+//! The generation is meant to be run as a codegen step, e.g. from a `build.rs`:
+//!
+//! ```rust,ignore
+//! disintegrate_serde::schema::write_schema::<DomainEvent>(std::env::var("OUT_DIR")?)?;
+//! ```
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+
+pub use schemars::JsonSchema;
+use serde_json::{Map, Value};
+
+/// Generates the JSON Schema of the type `T`.
+pub fn json_schema<T: JsonSchema>() -> Value {
+    schemars::schema_for!(T).to_value()
+}
+
+/// Generates the TypeScript definitions of the type `T`.
+///
+/// A type alias is emitted for `T` and for each of the types it references.
+pub fn typescript<T: JsonSchema>() -> String {
+    let schema = json_schema::<T>();
+    let mut definitions = String::new();
+    write_type_alias(&mut definitions, &T::schema_name(), &schema);
+    if let Some(Value::Object(defs)) = schema.get("$defs") {
+        for (name, schema) in defs {
+            write_type_alias(&mut definitions, name, schema);
+        }
+    }
+    definitions
+}
+
+/// Writes the JSON Schema and the TypeScript definitions of the type `T` in the given directory.
+///
+/// The files are named after the schema name of `T`: `<name>.schema.json` and `<name>.ts`.
+///
+/// # Arguments
+///
+/// * `out_dir` - The directory where the files are written.
+pub fn write_schema<T: JsonSchema>(out_dir: impl AsRef<Path>) -> io::Result<()> {
+    let out_dir = out_dir.as_ref();
+    let name = T::schema_name();
+    let schema = serde_json::to_string_pretty(&json_schema::<T>())
+        .expect("json serialization should not fail");
+    std::fs::write(out_dir.join(format!("{name}.schema.json")), schema)?;
+    std::fs::write(out_dir.join(format!("{name}.ts")), typescript::<T>())
+}
+
+fn write_type_alias(definitions: &mut String, name: &str, schema: &Value) {
+    if let Some(description) = schema.get("description").and_then(Value::as_str) {
+        let _ = writeln!(definitions, "/** {description} */");
+    }
+    let _ = writeln!(definitions, "export type {name} = {};", ts_type(schema));
+}
+
+fn ts_type(schema: &Value) -> String {
+    let Value::Object(schema) = schema else {
+        return "unknown".to_string();
+    };
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        return reference
+            .rsplit('/')
+            .next()
+            .unwrap_or(reference)
+            .to_string();
+    }
+    if let Some(value) = schema.get("const") {
+        return value.to_string();
+    }
+    if let Some(Value::Array(values)) = schema.get("enum") {
+        return union(values.iter().map(Value::to_string));
+    }
+    if let Some(Value::Array(schemas)) = schema.get("oneOf").or_else(|| schema.get("anyOf")) {
+        return union(schemas.iter().map(ts_type));
+    }
+    match schema.get("type") {
+        Some(Value::String(ty)) => ts_primitive(ty, schema),
+        Some(Value::Array(types)) => union(
+            types
+                .iter()
+                .filter_map(Value::as_str)
+                .map(|ty| ts_primitive(ty, schema)),
+        ),
+        _ => "unknown".to_string(),
+    }
+}
+
+fn ts_primitive(ty: &str, schema: &Map<String, Value>) -> String {
+    match ty {
+        "string" => "string".to_string(),
+        "integer" | "number" => "number".to_string(),
+        "boolean" => "boolean".to_string(),
+        "null" => "null".to_string(),
+        "array" => match schema.get("prefixItems") {
+            Some(Value::Array(items)) => {
+                format!(
+                    "[{}]",
+                    items.iter().map(ts_type).collect::<Vec<_>>().join(", ")
+                )
+            }
+            _ => format!(
+                "Array<{}>",
+                schema.get("items").map_or("unknown".to_string(), ts_type)
+            ),
+        },
+        "object" => ts_object(schema),
+        _ => "unknown".to_string(),
+    }
+}
+
+fn ts_object(schema: &Map<String, Value>) -> String {
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let mut members: Vec<String> = schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .map(|properties| {
+            properties
+                .iter()
+                .map(|(name, property)| {
+                    let optional = if required.contains(&name.as_str()) {
+                        ""
+                    } else {
+                        "?"
+                    };
+                    format!(
+                        "{}{optional}: {}",
+                        ts_property_name(name),
+                        ts_type(property)
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    match schema.get("additionalProperties") {
+        Some(Value::Bool(false)) => {}
+        Some(additional @ Value::Object(_)) => {
+            members.push(format!("[key: string]: {}", ts_type(additional)))
+        }
+        _ if members.is_empty() => return "Record<string, unknown>".to_string(),
+        _ => {}
+    }
+    format!("{{ {} }}", members.join("; "))
+}
+
+fn ts_property_name(name: &str) -> String {
+    let is_identifier = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if is_identifier {
+        name.to_string()
+    } else {
+        Value::String(name.to_string()).to_string()
+    }
+}
+
+fn union(types: impl Iterator<Item = String>) -> String {
+    let types: Vec<String> = types.collect();
+    if types.is_empty() {
+        "never".to_string()
+    } else {
+        types.join(" | ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, JsonSchema)]
+    enum AccountEvent {
+        AccountOpened { account_id: String },
+        AmountDeposited(Deposit),
+        AccountClosed,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, JsonSchema)]
+    struct Deposit {
+        account_id: String,
+        amount: u32,
+        note: Option<String>,
+    }
+
+    #[test]
+    fn it_generates_the_json_schema_of_the_events() {
+        let schema = json_schema::<AccountEvent>();
+
+        assert_eq!(schema["title"], "AccountEvent");
+        assert!(schema["$defs"]["Deposit"].is_object());
+    }
+
+    #[test]
+    fn it_generates_the_typescript_definitions_of_the_events() {
+        let definitions = typescript::<AccountEvent>();
+
+        assert_eq!(
+            definitions,
+            "export type AccountEvent = \"AccountClosed\" | { AccountOpened: { account_id: string } } | { AmountDeposited: Deposit };\n\
+             export type Deposit = { account_id: string; amount: number; note?: string | null };\n"
+        );
+    }
+}
@@ -1,11 +1,18 @@
 #[cfg(feature = "avro")]
 pub mod avro;
+#[cfg(any(feature = "zstd", feature = "lz4", feature = "gzip"))]
+pub mod compressed;
+#[cfg(feature = "encryption")]
+pub mod encrypted;
 #[cfg(feature = "json")]
 pub mod json;
 #[cfg(feature = "prost")]
 pub mod prost;
 #[cfg(feature = "protobuf")]
 pub mod protobuf;
+pub mod upcast;
+
+use upcast::{Upcasted, Upcaster};
 
 /// Serialization and deserialization error.
 #[derive(Debug, thiserror::Error)]
@@ -16,6 +23,24 @@ pub enum Error {
     /// an error occurred while converting the persisted data to the application data
     #[error("conversion error")]
     Conversion,
+    /// the payload has a version newer than the ones known by the upcasters
+    #[error("unsupported payload version: {0}")]
+    UnsupportedVersion(u32),
+    /// the schema registry failed to register or to return a schema
+    #[error("schema registry error: {0}")]
+    SchemaRegistry(#[source] Box<dyn std::error::Error + Sync + Send>),
+    /// the payload is compressed with an algorithm not enabled by the features
+    #[error("unsupported compression algorithm: {0}")]
+    UnsupportedCompression(u8),
+    /// the payload is encrypted with a key unknown to the key provider
+    #[error("unknown encryption key: {0}")]
+    UnknownKey(u32),
+    /// the payload cannot be decrypted with its key, e.g. because it has been tampered with or moved to another event
+    #[error("decryption error with key {0}")]
+    Decryption(u32),
+    /// the payload is not encrypted, while the serde only accepts encrypted payloads
+    #[error("the payload is not encrypted")]
+    Unencrypted,
 }
 
 /// Defines the behavior for serializing values of type `T`.
@@ -30,6 +55,35 @@ pub trait Serializer<T> {
     ///
     /// A byte vector containing the serialized representation of the value.
     fn serialize(&self, value: T) -> Vec<u8>;
+
+    /// Serializes a value to be appended to the event store, together with the information stored with it.
+    ///
+    /// The default implementation ignores the information; the encrypting serde binds the payload to the ID of
+    /// its event, and the wrapping serdes forward it to the serde they wrap.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to be serialized.
+    /// * `info` - The type, the version and the ID of the event of the payload.
+    ///
+    /// # Returns
+    ///
+    /// A byte vector containing the serialized representation of the value.
+    fn serialize_with_info(&self, value: T, info: PayloadInfo<'_>) -> Vec<u8> {
+        let _ = info;
+        self.serialize(value)
+    }
+}
+
+/// The information stored by the event store with a payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadInfo<'a> {
+    /// The type of the event, as stored in the event store.
+    pub event_type: &'a str,
+    /// The version of the event the payload was appended with, declared by `#[event(version = N)]`.
+    pub version: u32,
+    /// The ID of the event, if the event store knows it.
+    pub event_id: Option<i64>,
 }
 
 /// Defines the behavior for deserializing values of type `T`.
@@ -44,9 +98,85 @@ pub trait Deserializer<T> {
     ///
     /// A `Result` containing the deserialized value on success, or an error on failure.
     fn deserialize(&self, data: Vec<u8>) -> Result<T, Error>;
+
+    /// Deserializes a payload read from the event store, together with the information stored with it.
+    ///
+    /// The default implementation ignores the information; the upcasting serde uses the version of the event
+    /// to choose the upcasters of the payload, and the wrapping serdes forward it to the serde they wrap.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The byte vector to be deserialized.
+    /// * `info` - The type, the version and the ID of the event the payload was appended with.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the deserialized value on success, or an error on failure.
+    fn deserialize_with_info(&self, data: Vec<u8>, info: PayloadInfo<'_>) -> Result<T, Error> {
+        let _ = info;
+        self.deserialize(data)
+    }
+
+    /// Validates the configuration of the deserializer, e.g. at startup, before any payload is read.
+    ///
+    /// The default implementation accepts any configuration; the wrapping serdes validate the serde they wrap.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating whether the payloads can be deserialized, or the error preventing it.
+    fn validate_schema(&self) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 /// Combines the `Serializer` and `Deserializer` traits for convenience.
-pub trait Serde<T>: Serializer<T> + Deserializer<T> {}
+pub trait Serde<T>: Serializer<T> + Deserializer<T> {
+    /// Wraps the serde to upcast the payloads of the older versions, registering the upcaster of version 0.
+    ///
+    /// See [`Upcasted`] for the details of the versioning of the payloads.
+    ///
+    /// # Arguments
+    ///
+    /// * `upcaster` - The upcaster transforming the payloads of version 0 into version 1.
+    fn with_upcaster(self, upcaster: impl Upcaster + 'static) -> Upcasted<Self>
+    where
+        Self: Sized,
+    {
+        Upcasted::new(self).with_upcaster(upcaster)
+    }
+
+    /// Wraps the serde to compress the payloads with an algorithm.
+    ///
+    /// See [`compressed::Compressed`] for the details of the compression of the payloads.
+    ///
+    /// # Arguments
+    ///
+    /// * `compression` - The algorithm compressing the new payloads.
+    #[cfg(any(feature = "zstd", feature = "lz4", feature = "gzip"))]
+    fn with_compression(self, compression: compressed::Compression) -> compressed::Compressed<Self>
+    where
+        Self: Sized,
+    {
+        compressed::Compressed::new(self, compression)
+    }
+
+    /// Wraps the serde to encrypt the payloads with the keys of a key provider.
+    ///
+    /// See [`encrypted::Encrypted`] for the details of the encryption of the payloads.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The provider of the encryption keys.
+    #[cfg(feature = "encryption")]
+    fn with_encryption(
+        self,
+        keys: impl encrypted::KeyProvider + 'static,
+    ) -> encrypted::Encrypted<Self>
+    where
+        Self: Sized,
+    {
+        encrypted::Encrypted::new(self, keys)
+    }
+}
 
 impl<K, T> Serde<T> for K where K: Serializer<T> + Deserializer<T> {}
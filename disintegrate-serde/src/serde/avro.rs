@@ -1,20 +1,82 @@
 /// A module for serializing and deserializing data using Avro schema.
+use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::sync::{Arc, RwLock};
 
 use super::Error;
-use apache_avro::{from_value, Codec, Reader, Schema, Writer};
+use apache_avro::{
+    from_avro_datum, from_value, to_avro_datum, to_value, types::Value, Codec, Reader, Schema,
+    Writer,
+};
 use serde::{Deserialize, Serialize};
 
 use crate::serde::{Deserializer, Serializer};
 
+pub mod registry;
+
+use registry::SchemaRegistry;
+
+/// The first byte of the payloads prefixed with the ID of their schema, as in the Confluent wire format.
+const MAGIC_BYTE: u8 = 0;
+
+/// The length of the header of a payload prefixed with the ID of its schema: the magic byte followed by
+/// the big-endian schema ID.
+const HEADER_LEN: usize = 5;
+
 /// An Avro serialization and deserialization module.
 #[derive(Debug, Clone)]
 pub struct Avro<I, O> {
     schema: Schema,
+    registration: Option<Registration>,
     input: PhantomData<I>,
     output: PhantomData<O>,
 }
 
+/// The registration of the writer schema in a schema registry, with the writer schemas already resolved.
+#[derive(Clone)]
+struct Registration {
+    registry: Arc<dyn SchemaRegistry>,
+    schema_id: u32,
+    writer_schemas: Arc<RwLock<HashMap<u32, Schema>>>,
+}
+
+impl std::fmt::Debug for Registration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Registration")
+            .field("schema_id", &self.schema_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Registration {
+    /// Decodes a datum written with the schema of an ID, resolving it into the reader schema.
+    fn decode(&self, schema_id: u32, datum: &[u8], reader_schema: &Schema) -> Result<Value, Error> {
+        let known = self
+            .writer_schemas
+            .read()
+            .expect("the lock is not poisoned")
+            .contains_key(&schema_id);
+        if !known {
+            let writer_schema = Schema::parse_str(&self.registry.schema(schema_id)?)
+                .map_err(|e| Error::SchemaRegistry(Box::new(e)))?;
+            self.writer_schemas
+                .write()
+                .expect("the lock is not poisoned")
+                .insert(schema_id, writer_schema);
+        }
+        let writer_schemas = self
+            .writer_schemas
+            .read()
+            .expect("the lock is not poisoned");
+        from_avro_datum(
+            &writer_schemas[&schema_id],
+            &mut &datum[..],
+            Some(reader_schema),
+        )
+        .map_err(|e| Error::Deserialization(Box::new(e)))
+    }
+}
+
 impl<I, O> Avro<I, O> {
     /// Create a new instance of `Avro` with the specified Avro schema.
     ///
@@ -29,10 +91,43 @@ impl<I, O> Avro<I, O> {
         let schema = Schema::parse_str(schema).unwrap();
         Self {
             schema,
+            registration: None,
             input: PhantomData,
             output: PhantomData,
         }
     }
+
+    /// Registers the schema in a schema registry, to prefix the payloads with the ID of their schema.
+    ///
+    /// The payloads are then written in the Confluent wire format: a zero byte, the big-endian ID of the
+    /// schema and the Avro datum. The payloads are read with the schema of this serde, resolved from the
+    /// writer schema of their ID, so the payloads of the older versions of the events remain readable
+    /// without managing their schemas. The payloads written before the registration remain readable too.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The schema registry.
+    /// * `subject` - The subject of the schema, e.g. the name of the event record.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Avro` instance on success, or an error if the schema cannot be registered.
+    pub fn with_registry(
+        mut self,
+        registry: impl SchemaRegistry + 'static,
+        subject: &str,
+    ) -> Result<Self, Error> {
+        let schema_id = registry.register(subject, &self.schema.canonical_form())?;
+        self.registration = Some(Registration {
+            registry: Arc::new(registry),
+            schema_id,
+            writer_schemas: Arc::new(RwLock::new(HashMap::from([(
+                schema_id,
+                self.schema.clone(),
+            )]))),
+        });
+        Ok(self)
+    }
 }
 
 impl<I, O> Serializer<I> for Avro<I, O>
@@ -50,6 +145,16 @@ where
     /// Serialized bytes representing the value in Avro format.
     fn serialize(&self, value: I) -> Vec<u8> {
         let target = O::from(value);
+        if let Some(registration) = &self.registration {
+            let value = to_value(target).expect("avro serialization should not fail");
+            let datum =
+                to_avro_datum(&self.schema, value).expect("avro serialization should not fail");
+            let mut data = Vec::with_capacity(HEADER_LEN + datum.len());
+            data.push(MAGIC_BYTE);
+            data.extend_from_slice(&registration.schema_id.to_be_bytes());
+            data.extend(datum);
+            return data;
+        }
         let mut writer = Writer::with_codec(&self.schema, Vec::new(), Codec::Deflate);
         writer
             .append_ser(target)
@@ -73,11 +178,24 @@ where
     ///
     /// A `Result` containing the deserialized value on success, or an error on failure.
     fn deserialize(&self, data: Vec<u8>) -> Result<I, Error> {
-        let mut reader = Reader::new(&data[..]).map_err(|e| Error::Deserialization(Box::new(e)))?;
-        let value = reader
-            .next()
-            .expect("at least one value should be present")
-            .map_err(|e| Error::Deserialization(Box::new(e)))?;
+        let value = match &self.registration {
+            Some(registration) if data.len() >= HEADER_LEN && data[0] == MAGIC_BYTE => {
+                let schema_id = u32::from_be_bytes(
+                    data[1..HEADER_LEN]
+                        .try_into()
+                        .expect("the schema ID is 4 bytes long"),
+                );
+                registration.decode(schema_id, &data[HEADER_LEN..], &self.schema)?
+            }
+            _ => {
+                let mut reader =
+                    Reader::new(&data[..]).map_err(|e| Error::Deserialization(Box::new(e)))?;
+                reader
+                    .next()
+                    .expect("at least one value should be present")
+                    .map_err(|e| Error::Deserialization(Box::new(e)))?
+            }
+        };
         let target: O = from_value(&value).map_err(|e| Error::Deserialization(Box::new(e)))?;
         I::try_from(target).map_err(|_| Error::Conversion)
     }
@@ -86,6 +204,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use registry::InMemorySchemaRegistry;
     use std::convert::TryFrom;
 
     #[derive(Debug, PartialEq, Clone)]
@@ -149,4 +268,96 @@ mod tests {
         // Ensure the deserialized data matches the original input
         assert_eq!(deserialized, input);
     }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    struct CourseCreated {
+        course_id: String,
+        name: String,
+        seats: i32,
+        starts_at: i64,
+        published: bool,
+        tags: Vec<String>,
+        description: Option<String>,
+        capacity_delta: i32,
+    }
+
+    #[test]
+    fn it_deserializes_the_golden_payload() {
+        let avro = Avro::<CourseCreated, CourseCreated>::new(include_str!(
+            "../../fixtures/course_created.avsc"
+        ));
+
+        let deserialized: CourseCreated = avro
+            .deserialize(include_bytes!("../../fixtures/course_created.avro").to_vec())
+            .unwrap();
+
+        assert_eq!(
+            deserialized,
+            CourseCreated {
+                course_id: "c-42".to_string(),
+                name: "Café Rust 🦀".to_string(),
+                seats: 30,
+                starts_at: 9_007_199_254_740_993,
+                published: true,
+                tags: vec!["rust".to_string(), "beginner".to_string()],
+                description: None,
+                capacity_delta: -2,
+            }
+        );
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    struct LabeledData {
+        value: String,
+        label: String,
+    }
+
+    const LABELED_SCHEMA: &str = r#"
+        {
+            "type": "record",
+            "name": "TestRecord",
+            "fields": [
+                { "name": "value", "type": "string" },
+                { "name": "label", "type": "string", "default": "none" }
+            ]
+        }
+    "#;
+
+    #[test]
+    fn it_resolves_the_writer_schema_of_the_registered_payloads() {
+        let registry = Arc::new(InMemorySchemaRegistry::new());
+        let writer = Avro::<SerializedData, SerializedData>::new(TEST_SCHEMA)
+            .with_registry(registry.clone(), "TestRecord")
+            .unwrap();
+        let reader = Avro::<LabeledData, LabeledData>::new(LABELED_SCHEMA)
+            .with_registry(registry.clone(), "TestRecord")
+            .unwrap();
+        let data = writer.serialize(SerializedData {
+            value: "42".to_string(),
+        });
+
+        assert_eq!(data[..HEADER_LEN], [MAGIC_BYTE, 0, 0, 0, 1]);
+        assert_eq!(
+            reader.deserialize(data).unwrap(),
+            LabeledData {
+                value: "42".to_string(),
+                label: "none".to_string(),
+            }
+        );
+
+        let unregistered =
+            Avro::<SerializedData, SerializedData>::new(TEST_SCHEMA).serialize(SerializedData {
+                value: "7".to_string(),
+            });
+        assert_eq!(
+            writer.deserialize(unregistered).unwrap(),
+            SerializedData {
+                value: "7".to_string(),
+            }
+        );
+        assert!(matches!(
+            reader.deserialize(vec![MAGIC_BYTE, 0, 0, 0, 9, 2, 0]),
+            Err(Error::SchemaRegistry(_))
+        ));
+    }
 }
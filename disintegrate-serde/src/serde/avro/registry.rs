@@ -0,0 +1,255 @@
+//! Schema registries of the Avro serde.
+//!
+//! A schema registry assigns an ID to each schema registered under a subject. The Avro serde registers its
+//! writer schema, prefixes the payloads with the ID of the schema, and resolves the writer schema of a
+//! payload from its ID when it is deserialized, so the schemas of the older versions of the events do not
+//! have to be managed by hand.
+#[cfg(feature = "avro-registry")]
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use super::Error;
+
+/// A registry of the Avro schemas, e.g. a Confluent-compatible schema registry.
+pub trait SchemaRegistry: Send + Sync {
+    /// Registers a schema under a subject, or returns its ID if it is already registered.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject` - The subject of the schema, e.g. the name of the event record.
+    /// * `schema` - The JSON definition of the schema.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the ID of the schema on success, or an error on failure.
+    fn register(&self, subject: &str, schema: &str) -> Result<u32, Error>;
+
+    /// Returns the schema of an ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the schema.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the JSON definition of the schema on success, or an error on failure.
+    fn schema(&self, id: u32) -> Result<String, Error>;
+}
+
+impl<R: SchemaRegistry + ?Sized> SchemaRegistry for Arc<R> {
+    fn register(&self, subject: &str, schema: &str) -> Result<u32, Error> {
+        (**self).register(subject, schema)
+    }
+
+    fn schema(&self, id: u32) -> Result<String, Error> {
+        (**self).schema(id)
+    }
+}
+
+/// A schema registry held in memory, e.g. for the tests.
+///
+/// The IDs are assigned from 1, and a schema registered under several subjects keeps the same ID.
+#[derive(Debug, Default)]
+pub struct InMemorySchemaRegistry {
+    schemas: RwLock<Vec<String>>,
+}
+
+impl InMemorySchemaRegistry {
+    /// Creates an empty `InMemorySchemaRegistry`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SchemaRegistry for InMemorySchemaRegistry {
+    fn register(&self, _subject: &str, schema: &str) -> Result<u32, Error> {
+        let mut schemas = self.schemas.write().expect("the lock is not poisoned");
+        let index = match schemas.iter().position(|registered| registered == schema) {
+            Some(index) => index,
+            None => {
+                schemas.push(schema.to_string());
+                schemas.len() - 1
+            }
+        };
+        Ok(index as u32 + 1)
+    }
+
+    fn schema(&self, id: u32) -> Result<String, Error> {
+        let schemas = self.schemas.read().expect("the lock is not poisoned");
+        id.checked_sub(1)
+            .and_then(|index| schemas.get(index as usize))
+            .cloned()
+            .ok_or_else(|| Error::SchemaRegistry(format!("unknown schema: {id}").into()))
+    }
+}
+
+/// A client of a Confluent-compatible schema registry, caching the fetched schemas.
+///
+/// The requests are blocking: the writer schema is registered when the serde is created, and the schema
+/// of an ID is fetched only the first time a payload written with it is deserialized.
+#[cfg(feature = "avro-registry")]
+pub struct ConfluentSchemaRegistry {
+    url: String,
+    authorization: Option<String>,
+    agent: ureq::Agent,
+    schemas: RwLock<HashMap<u32, String>>,
+}
+
+#[cfg(feature = "avro-registry")]
+impl std::fmt::Debug for ConfluentSchemaRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConfluentSchemaRegistry")
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "avro-registry")]
+impl ConfluentSchemaRegistry {
+    /// Creates a new `ConfluentSchemaRegistry`.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The base URL of the schema registry, e.g. `http://localhost:8081`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            authorization: None,
+            agent: ureq::Agent::new(),
+            schemas: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the `Authorization` header of the requests, e.g. `Basic <credentials>`.
+    ///
+    /// # Arguments
+    ///
+    /// * `authorization` - The value of the header.
+    pub fn with_authorization(mut self, authorization: impl Into<String>) -> Self {
+        self.authorization = Some(authorization.into());
+        self
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let request = self
+            .agent
+            .request(method, &format!("{}{path}", self.url))
+            .set("Accept", "application/vnd.schemaregistry.v1+json");
+        match &self.authorization {
+            Some(authorization) => request.set("Authorization", authorization),
+            None => request,
+        }
+    }
+}
+
+#[cfg(feature = "avro-registry")]
+impl SchemaRegistry for ConfluentSchemaRegistry {
+    fn register(&self, subject: &str, schema: &str) -> Result<u32, Error> {
+        let response: serde_json::Value = self
+            .request("POST", &format!("/subjects/{subject}/versions"))
+            .set("Content-Type", "application/vnd.schemaregistry.v1+json")
+            .send_json(serde_json::json!({ "schema": schema }))
+            .map_err(|e| Error::SchemaRegistry(Box::new(e)))?
+            .into_json()
+            .map_err(|e| Error::SchemaRegistry(Box::new(e)))?;
+        let id = response["id"]
+            .as_u64()
+            .and_then(|id| u32::try_from(id).ok())
+            .ok_or_else(|| Error::SchemaRegistry(format!("invalid response: {response}").into()))?;
+        self.schemas
+            .write()
+            .expect("the lock is not poisoned")
+            .insert(id, schema.to_string());
+        Ok(id)
+    }
+
+    fn schema(&self, id: u32) -> Result<String, Error> {
+        if let Some(schema) = self
+            .schemas
+            .read()
+            .expect("the lock is not poisoned")
+            .get(&id)
+        {
+            return Ok(schema.clone());
+        }
+        let response: serde_json::Value = self
+            .request("GET", &format!("/schemas/ids/{id}"))
+            .call()
+            .map_err(|e| Error::SchemaRegistry(Box::new(e)))?
+            .into_json()
+            .map_err(|e| Error::SchemaRegistry(Box::new(e)))?;
+        let schema = response["schema"]
+            .as_str()
+            .ok_or_else(|| Error::SchemaRegistry(format!("invalid response: {response}").into()))?
+            .to_string();
+        self.schemas
+            .write()
+            .expect("the lock is not poisoned")
+            .insert(id, schema.clone());
+        Ok(schema)
+    }
+}
+
+#[cfg(all(test, feature = "avro-registry"))]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    use super::*;
+
+    /// Serves the given responses, one per connection, and returns the received requests.
+    fn serve(responses: Vec<&'static str>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|body| {
+                    let (stream, _) = listener.accept().unwrap();
+                    let mut reader = BufReader::new(stream);
+                    let mut request = String::new();
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                            content_length = length.trim().parse().unwrap();
+                        }
+                        if line == "\r\n" {
+                            break;
+                        }
+                        request.push_str(&line);
+                    }
+                    let mut content = vec![0; content_length];
+                    reader.read_exact(&mut content).unwrap();
+                    request.push_str(&String::from_utf8(content).unwrap());
+                    write!(
+                        reader.get_mut(),
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    )
+                    .unwrap();
+                    request
+                })
+                .collect()
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn it_registers_and_fetches_the_schemas() {
+        let (url, server) = serve(vec![r#"{"id": 7}"#, r#"{"schema": "\"long\""}"#]);
+        let registry = ConfluentSchemaRegistry::new(url).with_authorization("Basic dXNlcjpwYXNz");
+
+        assert_eq!(registry.register("course-value", r#""string""#).unwrap(), 7);
+        assert_eq!(registry.schema(7).unwrap(), r#""string""#);
+        assert_eq!(registry.schema(8).unwrap(), r#""long""#);
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("POST /subjects/course-value/versions "));
+        assert!(requests[0].contains("Authorization: Basic dXNlcjpwYXNz"));
+        assert!(requests[0].ends_with(r#"{"schema":"\"string\""}"#));
+        assert!(requests[1].starts_with("GET /schemas/ids/8 "));
+    }
+}
@@ -0,0 +1,267 @@
+//! Compression of the persisted payloads.
+//!
+//! `Compressed` wraps a serde and compresses the payloads it serializes, to reduce the storage dominated
+//! by large payloads. The compressed payloads start with a header naming their algorithm, so the algorithm
+//! can be changed over time, and the payloads persisted before the compression was enabled, which have no
+//! header, are deserialized as they are. A payload that would not shrink is persisted uncompressed.
+//!
+//! The algorithms are enabled by the `zstd`, `lz4` and `gzip` features.
+#[cfg(feature = "gzip")]
+use std::io::{Read, Write};
+
+use super::Error;
+use crate::serde::{Deserializer, PayloadInfo, Serializer};
+
+/// The marker preceding the algorithm of a compressed payload.
+const COMPRESSION_MARKER: [u8; 3] = [0xD1, 0x5E, 0xC0];
+
+/// The length of the header of a compressed payload: the marker followed by the algorithm.
+const HEADER_LEN: usize = COMPRESSION_MARKER.len() + 1;
+
+#[cfg(feature = "zstd")]
+const ZSTD: u8 = 1;
+#[cfg(feature = "lz4")]
+const LZ4: u8 = 2;
+#[cfg(feature = "gzip")]
+const GZIP: u8 = 3;
+
+/// The compression algorithm of the payloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Zstandard, with the given compression level.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+    /// LZ4, favoring the speed over the ratio.
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// Gzip, with the given compression level from 0 to 9.
+    #[cfg(feature = "gzip")]
+    Gzip(u32),
+}
+
+impl Compression {
+    fn id(&self) -> u8 {
+        match self {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(_) => ZSTD,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => LZ4,
+            #[cfg(feature = "gzip")]
+            Compression::Gzip(_) => GZIP,
+        }
+    }
+
+    fn compress(&self, payload: &[u8]) -> Vec<u8> {
+        match self {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => zstd::encode_all(payload, *level)
+                .expect("zstd compression in memory should not fail"),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => lz4_flex::compress_prepend_size(payload),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip(level) => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(*level));
+                encoder
+                    .write_all(payload)
+                    .expect("gzip compression in memory should not fail");
+                encoder
+                    .finish()
+                    .expect("gzip compression in memory should not fail")
+            }
+        }
+    }
+}
+
+/// Decompresses a payload compressed with the algorithm of the given ID.
+fn decompress(algorithm: u8, payload: &[u8]) -> Result<Vec<u8>, Error> {
+    match algorithm {
+        #[cfg(feature = "zstd")]
+        ZSTD => zstd::decode_all(payload).map_err(|e| Error::Deserialization(Box::new(e))),
+        #[cfg(feature = "lz4")]
+        LZ4 => lz4_flex::decompress_size_prepended(payload)
+            .map_err(|e| Error::Deserialization(Box::new(e))),
+        #[cfg(feature = "gzip")]
+        GZIP => {
+            let mut data = Vec::new();
+            flate2::read::GzDecoder::new(payload)
+                .read_to_end(&mut data)
+                .map_err(|e| Error::Deserialization(Box::new(e)))?;
+            Ok(data)
+        }
+        algorithm => Err(Error::UnsupportedCompression(algorithm)),
+    }
+}
+
+/// A serde compressing the payloads of the wrapped serde.
+#[derive(Debug, Clone, Copy)]
+pub struct Compressed<S> {
+    serde: S,
+    compression: Compression,
+}
+
+impl<S> Compressed<S> {
+    /// Creates a new `Compressed` serde.
+    ///
+    /// # Arguments
+    ///
+    /// * `serde` - The serde of the events.
+    /// * `compression` - The algorithm compressing the new payloads.
+    pub fn new(serde: S, compression: Compression) -> Self {
+        Self { serde, compression }
+    }
+
+    /// Compresses a payload, if it shrinks.
+    fn compressed(&self, payload: Vec<u8>) -> Vec<u8> {
+        let compressed = self.compression.compress(&payload);
+        if HEADER_LEN + compressed.len() >= payload.len() {
+            return payload;
+        }
+        let mut data = Vec::with_capacity(HEADER_LEN + compressed.len());
+        data.extend_from_slice(&COMPRESSION_MARKER);
+        data.push(self.compression.id());
+        data.extend(compressed);
+        data
+    }
+}
+
+impl<S, T> Serializer<T> for Compressed<S>
+where
+    S: Serializer<T>,
+{
+    /// Serializes the given value with the wrapped serde, compressing the payload if it shrinks.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to be serialized.
+    ///
+    /// # Returns
+    ///
+    /// The compressed payload of the value, or the payload of the wrapped serde if it does not shrink.
+    fn serialize(&self, value: T) -> Vec<u8> {
+        self.compressed(self.serde.serialize(value))
+    }
+
+    fn serialize_with_info(&self, value: T, info: PayloadInfo<'_>) -> Vec<u8> {
+        self.compressed(self.serde.serialize_with_info(value, info))
+    }
+}
+
+impl<S, T> Deserializer<T> for Compressed<S>
+where
+    S: Deserializer<T>,
+{
+    /// Decompresses the given payload with the algorithm of its header and deserializes it with the wrapped serde.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The payload to be deserialized, compressed or not.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the deserialized value on success, or an error on failure.
+    fn deserialize(&self, data: Vec<u8>) -> Result<T, Error> {
+        self.serde.deserialize(decompressed(data)?)
+    }
+
+    fn deserialize_with_info(&self, data: Vec<u8>, info: PayloadInfo<'_>) -> Result<T, Error> {
+        self.serde.deserialize_with_info(decompressed(data)?, info)
+    }
+
+    fn validate_schema(&self) -> Result<(), Error> {
+        self.serde.validate_schema()
+    }
+}
+
+/// Decompresses a payload with the algorithm of its header, returning the payloads without a header unchanged.
+fn decompressed(data: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if data.len() < HEADER_LEN || data[..COMPRESSION_MARKER.len()] != COMPRESSION_MARKER {
+        return Ok(data);
+    }
+    decompress(data[COMPRESSION_MARKER.len()], &data[HEADER_LEN..])
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::serde::json::Json;
+    use crate::Serde;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    #[serde(tag = "event_type")]
+    enum DocumentEvent {
+        DocumentUploaded { content: String },
+    }
+
+    fn uploaded(content: &str) -> DocumentEvent {
+        DocumentEvent::DocumentUploaded {
+            content: content.to_string(),
+        }
+    }
+
+    fn compressions() -> Vec<Compression> {
+        vec![
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(3),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4,
+            #[cfg(feature = "gzip")]
+            Compression::Gzip(6),
+        ]
+    }
+
+    #[test]
+    fn it_compresses_the_large_payloads() {
+        let event = uploaded(&"lorem ipsum ".repeat(500));
+        let uncompressed = Json::<DocumentEvent>::default().serialize(event.clone());
+        for compression in compressions() {
+            let serde = Json::<DocumentEvent>::default().with_compression(compression);
+
+            let data = serde.serialize(event.clone());
+
+            assert!(data.len() < uncompressed.len() / 10);
+            assert_eq!(data[COMPRESSION_MARKER.len()], compression.id());
+            assert_eq!(serde.deserialize(data).unwrap(), event);
+        }
+    }
+
+    #[test]
+    fn it_keeps_the_payloads_that_do_not_shrink() {
+        for compression in compressions() {
+            let serde = Json::<DocumentEvent>::default().with_compression(compression);
+
+            let data = serde.serialize(uploaded("short"));
+
+            assert_eq!(
+                data,
+                br#"{"event_type":"DocumentUploaded","content":"short"}"#
+            );
+            assert_eq!(serde.deserialize(data).unwrap(), uploaded("short"));
+        }
+    }
+
+    #[test]
+    fn it_deserializes_the_payloads_of_another_algorithm() {
+        let event = uploaded(&"lorem ipsum ".repeat(500));
+        for written_with in compressions() {
+            let data = Json::<DocumentEvent>::default()
+                .with_compression(written_with)
+                .serialize(event.clone());
+            for compression in compressions() {
+                let serde = Json::<DocumentEvent>::default().with_compression(compression);
+                assert_eq!(serde.deserialize(data.clone()).unwrap(), event);
+            }
+        }
+
+        let mut unknown = COMPRESSION_MARKER.to_vec();
+        unknown.push(99);
+        assert!(matches!(
+            Json::<DocumentEvent>::default()
+                .with_compression(compressions()[0])
+                .deserialize(unknown),
+            Err(Error::UnsupportedCompression(99))
+        ));
+    }
+}
@@ -0,0 +1,448 @@
+//! Encryption of the persisted payloads.
+//!
+//! `Encrypted` wraps a serde and encrypts the payloads it serializes with AES-256-GCM, so the personal data
+//! of the events are encrypted at rest independently from the encryption of the disks. The keys are supplied
+//! by a [`KeyProvider`]: the new payloads are encrypted with its current key, and the key used for a payload
+//! is recorded in its header, so the keys can be rotated while the payloads encrypted with the previous keys
+//! remain readable.
+//!
+//! The payloads serialized for an event of the event store are bound to the ID of the event, used as the
+//! associated data of the encryption: a payload copied into another event cannot be decrypted.
+//!
+//! The payloads without a header are rejected, so that a plaintext payload cannot be substituted for an
+//! encrypted one. The payloads persisted before the encryption was enabled are deserialized as they are only
+//! once the serde accepts them, with [`Encrypted::accept_unencrypted`], until they have been encrypted again.
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+
+use super::Error;
+use crate::serde::{Deserializer, PayloadInfo, Serializer};
+
+/// The marker preceding the key ID of an encrypted payload not bound to its event.
+const ENCRYPTION_MARKER: [u8; 4] = [0xD1, 0x5E, 0xE4, 0x00];
+
+/// The marker preceding the key ID of an encrypted payload bound to the ID of its event.
+const BOUND_ENCRYPTION_MARKER: [u8; 4] = [0xD1, 0x5E, 0xE4, 0x01];
+
+/// The length of the nonce of an encrypted payload.
+const NONCE_LEN: usize = 12;
+
+/// The length of the header of an encrypted payload: the marker, the big-endian key ID and the nonce.
+const HEADER_LEN: usize = ENCRYPTION_MARKER.len() + 4 + NONCE_LEN;
+
+/// A 256-bit encryption key.
+pub type EncryptionKey = [u8; 32];
+
+/// Supplies the keys encrypting and decrypting the payloads, e.g. from a key management service.
+pub trait KeyProvider: Send + Sync {
+    /// Returns the ID and the value of the key encrypting the new payloads.
+    fn current_key(&self) -> (u32, EncryptionKey);
+
+    /// Returns the key with the given ID, to decrypt the payloads encrypted with it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_id` - The ID of the key, recorded in the header of the payload.
+    ///
+    /// # Returns
+    ///
+    /// The key, or `None` if the key is unknown.
+    fn key(&self, key_id: u32) -> Option<EncryptionKey>;
+}
+
+/// A set of keys held in memory: a current key and the retired keys still decrypting the older payloads.
+#[derive(Clone)]
+pub struct Keyring {
+    current: u32,
+    keys: BTreeMap<u32, EncryptionKey>,
+}
+
+impl std::fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keyring")
+            .field("current", &self.current)
+            .field("keys", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Keyring {
+    /// Creates a new `Keyring` with its current key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_id` - The ID of the current key.
+    /// * `key` - The current key.
+    pub fn new(key_id: u32, key: EncryptionKey) -> Self {
+        Self {
+            current: key_id,
+            keys: BTreeMap::from([(key_id, key)]),
+        }
+    }
+
+    /// Adds a retired key, only decrypting the payloads encrypted with it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_id` - The ID of the retired key.
+    /// * `key` - The retired key.
+    pub fn with_retired_key(mut self, key_id: u32, key: EncryptionKey) -> Self {
+        if key_id != self.current {
+            self.keys.insert(key_id, key);
+        }
+        self
+    }
+}
+
+impl KeyProvider for Keyring {
+    fn current_key(&self) -> (u32, EncryptionKey) {
+        (self.current, self.keys[&self.current])
+    }
+
+    fn key(&self, key_id: u32) -> Option<EncryptionKey> {
+        self.keys.get(&key_id).copied()
+    }
+}
+
+/// A serde encrypting the payloads of the wrapped serde.
+#[derive(Clone)]
+pub struct Encrypted<S> {
+    serde: S,
+    keys: Arc<dyn KeyProvider>,
+    accept_unencrypted: bool,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for Encrypted<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encrypted")
+            .field("serde", &self.serde)
+            .field("key_id", &self.keys.current_key().0)
+            .field("accept_unencrypted", &self.accept_unencrypted)
+            .finish()
+    }
+}
+
+impl<S> Encrypted<S> {
+    /// Creates a new `Encrypted` serde.
+    ///
+    /// # Arguments
+    ///
+    /// * `serde` - The serde of the events.
+    /// * `keys` - The provider of the encryption keys.
+    pub fn new(serde: S, keys: impl KeyProvider + 'static) -> Self {
+        Self {
+            serde,
+            keys: Arc::new(keys),
+            accept_unencrypted: false,
+        }
+    }
+
+    /// Accepts the payloads without an encryption header, deserializing them as they are.
+    ///
+    /// This is meant for the migration of an event store whose payloads were persisted before the encryption
+    /// was enabled: once they have been encrypted again with `reencrypt`, the flag should be removed, so that a
+    /// plaintext payload cannot be substituted for an encrypted one.
+    pub fn accept_unencrypted(mut self) -> Self {
+        self.accept_unencrypted = true;
+        self
+    }
+
+    /// Encrypts a payload again with the current key, e.g. to retire a key after a rotation.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The payload, encrypted with any known key, or not encrypted if the serde accepts it.
+    /// * `event_id` - The ID of the event of the payload, binding the encrypted payload to it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the payload encrypted with the current key on success, or an error if the
+    /// payload cannot be decrypted.
+    pub fn reencrypt(&self, data: Vec<u8>, event_id: Option<i64>) -> Result<Vec<u8>, Error> {
+        match split_header(&data) {
+            Some((key_id, bound))
+                if key_id == self.keys.current_key().0 && bound == event_id.is_some() =>
+            {
+                Ok(data)
+            }
+            _ => Ok(self.encrypt(self.decrypt(data, event_id)?, event_id)),
+        }
+    }
+
+    fn encrypt(&self, payload: Vec<u8>, event_id: Option<i64>) -> Vec<u8> {
+        let (key_id, key) = self.keys.current_key();
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = event_id.map(i64::to_be_bytes).unwrap_or_default();
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: payload.as_slice(),
+                    aad: if event_id.is_some() { &aad } else { &[] },
+                },
+            )
+            .expect("the payload is not too long to be encrypted");
+        let mut data = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+        data.extend_from_slice(if event_id.is_some() {
+            &BOUND_ENCRYPTION_MARKER
+        } else {
+            &ENCRYPTION_MARKER
+        });
+        data.extend_from_slice(&key_id.to_be_bytes());
+        data.extend_from_slice(&nonce);
+        data.extend(ciphertext);
+        data
+    }
+
+    /// Decrypts a payload, checking that a payload bound to an event is the payload of the given event.
+    fn decrypt(&self, data: Vec<u8>, event_id: Option<i64>) -> Result<Vec<u8>, Error> {
+        let Some((key_id, bound)) = split_header(&data) else {
+            return if self.accept_unencrypted {
+                Ok(data)
+            } else {
+                Err(Error::Unencrypted)
+            };
+        };
+        let key = self.keys.key(key_id).ok_or(Error::UnknownKey(key_id))?;
+        let aad = match (bound, event_id) {
+            (true, Some(event_id)) => event_id.to_be_bytes(),
+            (true, None) => return Err(Error::Decryption(key_id)),
+            (false, _) => Default::default(),
+        };
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let nonce = Nonce::from_slice(&data[HEADER_LEN - NONCE_LEN..HEADER_LEN]);
+        cipher
+            .decrypt(
+                nonce,
+                Payload {
+                    msg: &data[HEADER_LEN..],
+                    aad: if bound { &aad } else { &[] },
+                },
+            )
+            .map_err(|_| Error::Decryption(key_id))
+    }
+}
+
+impl<S, T> Serializer<T> for Encrypted<S>
+where
+    S: Serializer<T>,
+{
+    /// Serializes the given value with the wrapped serde, encrypting the payload with the current key.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to be serialized.
+    ///
+    /// # Returns
+    ///
+    /// The encrypted payload of the value.
+    fn serialize(&self, value: T) -> Vec<u8> {
+        self.encrypt(self.serde.serialize(value), None)
+    }
+
+    /// Serializes the given value with the wrapped serde, encrypting the payload with the current key and
+    /// binding it to the ID of its event, if known.
+    fn serialize_with_info(&self, value: T, info: PayloadInfo<'_>) -> Vec<u8> {
+        self.encrypt(self.serde.serialize_with_info(value, info), info.event_id)
+    }
+}
+
+impl<S, T> Deserializer<T> for Encrypted<S>
+where
+    S: Deserializer<T>,
+{
+    /// Decrypts the given payload with the key recorded in its header and deserializes it with the wrapped serde.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The encrypted payload to be deserialized.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the deserialized value on success, or an error on failure.
+    fn deserialize(&self, data: Vec<u8>) -> Result<T, Error> {
+        self.serde.deserialize(self.decrypt(data, None)?)
+    }
+
+    /// Decrypts the given payload, checking that it is bound to the ID of its event, and deserializes it with
+    /// the wrapped serde.
+    fn deserialize_with_info(&self, data: Vec<u8>, info: PayloadInfo<'_>) -> Result<T, Error> {
+        self.serde
+            .deserialize_with_info(self.decrypt(data, info.event_id)?, info)
+    }
+
+    /// Validates the wrapped serde, and checks that a payload encrypted with the current key can be decrypted.
+    fn validate_schema(&self) -> Result<(), Error> {
+        self.decrypt(self.encrypt(vec![], Some(0)), Some(0))?;
+        self.serde.validate_schema()
+    }
+}
+
+/// Returns the ID of the key of an encrypted payload and whether it is bound to its event, or `None` if the
+/// payload is not encrypted.
+fn split_header(data: &[u8]) -> Option<(u32, bool)> {
+    if data.len() < HEADER_LEN {
+        return None;
+    }
+    let bound = match &data[..ENCRYPTION_MARKER.len()] {
+        marker if marker == ENCRYPTION_MARKER => false,
+        marker if marker == BOUND_ENCRYPTION_MARKER => true,
+        _ => return None,
+    };
+    let key_id = u32::from_be_bytes(
+        data[ENCRYPTION_MARKER.len()..ENCRYPTION_MARKER.len() + 4]
+            .try_into()
+            .expect("the key ID is 4 bytes long"),
+    );
+    Some((key_id, bound))
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::serde::json::Json;
+    use crate::Serde;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    #[serde(tag = "event_type")]
+    enum PersonEvent {
+        PersonRegistered { email: String },
+    }
+
+    fn registered() -> PersonEvent {
+        PersonEvent::PersonRegistered {
+            email: "someone@example.com".to_string(),
+        }
+    }
+
+    #[test]
+    fn it_encrypts_the_payloads() {
+        let serde = Json::<PersonEvent>::default().with_encryption(Keyring::new(1, [7; 32]));
+
+        let data = serde.serialize(registered());
+
+        assert_eq!(split_header(&data), Some((1, false)));
+        assert!(!data
+            .windows(b"someone@example.com".len())
+            .any(|window| window == b"someone@example.com"));
+        assert_eq!(serde.deserialize(data).unwrap(), registered());
+        assert_eq!(
+            serde
+                .accept_unencrypted()
+                .deserialize(
+                    br#"{"event_type": "PersonRegistered", "email": "other@example.com"}"#.to_vec()
+                )
+                .unwrap(),
+            PersonEvent::PersonRegistered {
+                email: "other@example.com".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn it_decrypts_the_payloads_of_the_retired_keys() {
+        let old = Json::<PersonEvent>::default().with_encryption(Keyring::new(1, [7; 32]));
+        let rotated = Json::<PersonEvent>::default()
+            .with_encryption(Keyring::new(2, [8; 32]).with_retired_key(1, [7; 32]));
+        let data = old.serialize(registered());
+
+        assert_eq!(rotated.deserialize(data.clone()).unwrap(), registered());
+        let reencrypted = rotated.reencrypt(data.clone(), None).unwrap();
+        assert_eq!(split_header(&reencrypted), Some((2, false)));
+        assert_eq!(rotated.deserialize(reencrypted).unwrap(), registered());
+
+        let forgotten = Json::<PersonEvent>::default().with_encryption(Keyring::new(2, [8; 32]));
+        assert!(matches!(
+            forgotten.deserialize(data),
+            Err(Error::UnknownKey(1))
+        ));
+    }
+
+    #[test]
+    fn it_rejects_the_unencrypted_payloads_unless_migrating() {
+        let serde = Json::<PersonEvent>::default().with_encryption(Keyring::new(1, [7; 32]));
+        let plaintext = Json::<PersonEvent>::default().serialize(registered());
+
+        assert!(matches!(
+            serde.deserialize(plaintext.clone()),
+            Err(Error::Unencrypted)
+        ));
+        assert!(matches!(
+            serde.reencrypt(plaintext.clone(), Some(1)),
+            Err(Error::Unencrypted)
+        ));
+
+        let migrating = serde.accept_unencrypted();
+        let reencrypted = migrating.reencrypt(plaintext, Some(1)).unwrap();
+        assert_eq!(split_header(&reencrypted), Some((1, true)));
+    }
+
+    #[test]
+    fn it_binds_the_payloads_to_their_event() {
+        let serde = Json::<PersonEvent>::default().with_encryption(Keyring::new(1, [7; 32]));
+        let info = |event_id| PayloadInfo {
+            event_type: "PersonRegistered",
+            version: 1,
+            event_id,
+        };
+        let data = serde.serialize_with_info(registered(), info(Some(42)));
+
+        assert_eq!(split_header(&data), Some((1, true)));
+        assert_eq!(
+            serde
+                .deserialize_with_info(data.clone(), info(Some(42)))
+                .unwrap(),
+            registered()
+        );
+        assert!(matches!(
+            serde.deserialize_with_info(data.clone(), info(Some(43))),
+            Err(Error::Decryption(1))
+        ));
+        assert!(matches!(
+            serde.deserialize_with_info(data.clone(), info(None)),
+            Err(Error::Decryption(1))
+        ));
+        assert!(matches!(serde.deserialize(data), Err(Error::Decryption(1))));
+    }
+
+    #[test]
+    fn it_rejects_the_tampered_payloads() {
+        let serde = Json::<PersonEvent>::default().with_encryption(Keyring::new(1, [7; 32]));
+        let mut data = serde.serialize(registered());
+        *data.last_mut().unwrap() ^= 1;
+
+        assert!(matches!(serde.deserialize(data), Err(Error::Decryption(1))));
+    }
+
+    #[test]
+    fn it_validates_that_the_current_key_decrypts_the_payloads() {
+        struct ForgetfulKeys;
+
+        impl KeyProvider for ForgetfulKeys {
+            fn current_key(&self) -> (u32, EncryptionKey) {
+                (1, [7; 32])
+            }
+
+            fn key(&self, _key_id: u32) -> Option<EncryptionKey> {
+                None
+            }
+        }
+
+        assert!(Json::<PersonEvent>::default()
+            .with_encryption(Keyring::new(1, [7; 32]))
+            .validate_schema()
+            .is_ok());
+        assert!(matches!(
+            Deserializer::<PersonEvent>::validate_schema(
+                &Json::<PersonEvent>::default().with_encryption(ForgetfulKeys)
+            ),
+            Err(Error::UnknownKey(1))
+        ));
+    }
+}
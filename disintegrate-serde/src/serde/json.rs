@@ -1,10 +1,13 @@
 //! A JSON serialization and deserialization module.
+use std::collections::HashMap;
 use std::marker::PhantomData;
 
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 use super::Error;
 use crate::serde::{Deserializer, Serializer};
+use crate::Upcaster;
 
 /// A struct to serialize and deserialize JSON payloads.
 #[derive(Debug, Clone, Copy)]
@@ -52,6 +55,188 @@ where
     }
 }
 
+/// A struct to serialize and deserialize JSON payloads, filling the missing fields with registered defaults.
+///
+/// When new fields are added to an event, the payloads persisted before the change do not contain them.
+/// `JsonWithDefaults` fills the missing fields from the defaults registered for the event type before
+/// deserializing the payload, so that the new fields do not need to be annotated with `#[serde(default)]`.
+///
+/// The event type is read from the `tag` field for internally tagged enums, otherwise the payload is
+/// expected to be externally tagged, i.e. an object with the event type as its only key.
+///
+/// The payloads of a renamed event are tagged with its former name: the aliases registered with
+/// `with_alias` are replaced with the current name of the event before deserializing the payload.
+#[derive(Debug, Clone)]
+pub struct JsonWithDefaults<T> {
+    tag: Option<String>,
+    defaults: HashMap<String, Map<String, Value>>,
+    aliases: HashMap<String, String>,
+    event_type: PhantomData<T>,
+}
+
+impl<T> Default for JsonWithDefaults<T> {
+    fn default() -> Self {
+        Self {
+            tag: None,
+            defaults: HashMap::new(),
+            aliases: HashMap::new(),
+            event_type: PhantomData,
+        }
+    }
+}
+
+impl<T> JsonWithDefaults<T> {
+    /// Sets the field containing the event type, for internally tagged enums.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - The name of the field containing the event type.
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    /// Registers the default values of the fields of an event type.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The event type the defaults apply to.
+    /// * `defaults` - A JSON object containing the default value of each field.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `defaults` is not a JSON object.
+    pub fn with_defaults(mut self, event_type: impl Into<String>, defaults: Value) -> Self {
+        let Value::Object(defaults) = defaults else {
+            panic!("the defaults must be a JSON object");
+        };
+        self.defaults
+            .entry(event_type.into())
+            .or_default()
+            .extend(defaults);
+        self
+    }
+
+    /// Registers a former name of an event type, e.g. one declared with `#[event(alias = "...")]`.
+    ///
+    /// # Arguments
+    ///
+    /// * `alias` - The former name of the event type, found in the persisted payloads.
+    /// * `event_type` - The current name of the event type.
+    pub fn with_alias(mut self, alias: impl Into<String>, event_type: impl Into<String>) -> Self {
+        self.aliases.insert(alias.into(), event_type.into());
+        self
+    }
+
+    fn fill_defaults(&self, value: &mut Value) {
+        let Value::Object(object) = value else {
+            return;
+        };
+        self.resolve_alias(object);
+        let (event_type, fields) = match &self.tag {
+            Some(tag) => match object.get(tag).and_then(Value::as_str) {
+                Some(event_type) => (event_type.to_string(), object),
+                None => return,
+            },
+            None if object.len() == 1 => {
+                let (event_type, fields) = object.iter_mut().next().unwrap();
+                match fields {
+                    Value::Object(fields) => (event_type.clone(), fields),
+                    _ => return,
+                }
+            }
+            None => return,
+        };
+        if let Some(defaults) = self.defaults.get(&event_type) {
+            for (field, default) in defaults {
+                fields
+                    .entry(field.clone())
+                    .or_insert_with(|| default.clone());
+            }
+        }
+    }
+
+    /// Replaces the former name of the event type with its current name.
+    fn resolve_alias(&self, object: &mut Map<String, Value>) {
+        match &self.tag {
+            Some(tag) => {
+                if let Some(event_type) = object
+                    .get(tag)
+                    .and_then(Value::as_str)
+                    .and_then(|alias| self.aliases.get(alias))
+                {
+                    object.insert(tag.clone(), Value::String(event_type.clone()));
+                }
+            }
+            None if object.len() == 1 => {
+                let alias = object.keys().next().unwrap();
+                if let Some(event_type) = self.aliases.get(alias) {
+                    let alias = alias.clone();
+                    let fields = object.remove(&alias).unwrap();
+                    object.insert(event_type.clone(), fields);
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+impl<T> Serializer<T> for JsonWithDefaults<T>
+where
+    T: Serialize,
+{
+    /// Serializes the given value to JSON format and returns the serialized bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to be serialized.
+    ///
+    /// # Returns
+    ///
+    /// Serialized bytes representing the value in JSON format.
+    fn serialize(&self, value: T) -> Vec<u8> {
+        serde_json::to_vec(&value).expect("json serialization should not fail")
+    }
+}
+
+impl<T> Deserializer<T> for JsonWithDefaults<T>
+where
+    for<'d> T: Deserialize<'d>,
+{
+    /// Deserializes the given JSON bytes to produce a value of type `T`, filling the missing fields with the registered defaults.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The JSON bytes to be deserialized.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the deserialized value on success, or an error on failure.
+    fn deserialize(&self, data: Vec<u8>) -> Result<T, Error> {
+        let mut value: Value =
+            serde_json::from_slice(&data).map_err(|e| Error::Deserialization(Box::new(e)))?;
+        self.fill_defaults(&mut value);
+        serde_json::from_value(value).map_err(|e| Error::Deserialization(Box::new(e)))
+    }
+}
+
+/// Creates an upcaster transforming the JSON payloads in place, e.g. to rename a field.
+///
+/// # Arguments
+///
+/// * `upcast` - The function transforming the JSON value of a payload into the next version.
+pub fn json_upcaster<F>(upcast: F) -> impl Upcaster
+where
+    F: Fn(&mut Value) -> Result<(), Error> + Send + Sync,
+{
+    move |payload: Vec<u8>| {
+        let mut value: Value =
+            serde_json::from_slice(&payload).map_err(|e| Error::Deserialization(Box::new(e)))?;
+        upcast(&mut value)?;
+        Ok(serde_json::to_vec(&value).expect("json serialization should not fail"))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -75,4 +260,136 @@ mod tests {
 
         assert_eq!(person, deserialized_person);
     }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    enum PersonEvent {
+        PersonRegistered { name: String, age: u32 },
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    #[serde(tag = "event_type")]
+    enum TaggedPersonEvent {
+        PersonRegistered { name: String, age: u32 },
+    }
+
+    #[test]
+    fn it_fills_the_missing_fields_with_defaults() {
+        let json_serializer = JsonWithDefaults::<PersonEvent>::default()
+            .with_defaults("PersonRegistered", serde_json::json!({"age": 18}));
+
+        let deserialized_event = json_serializer
+            .deserialize(br#"{"PersonRegistered": {"name": "Some Name"}}"#.to_vec())
+            .unwrap();
+
+        assert_eq!(
+            deserialized_event,
+            PersonEvent::PersonRegistered {
+                name: String::from("Some Name"),
+                age: 18
+            }
+        );
+    }
+
+    #[test]
+    fn it_fills_the_missing_fields_of_internally_tagged_events() {
+        let json_serializer = JsonWithDefaults::<TaggedPersonEvent>::default()
+            .tag("event_type")
+            .with_defaults("PersonRegistered", serde_json::json!({"age": 18}));
+
+        let deserialized_event = json_serializer
+            .deserialize(br#"{"event_type": "PersonRegistered", "name": "Some Name"}"#.to_vec())
+            .unwrap();
+        let deserialized_complete_event = json_serializer
+            .deserialize(
+                br#"{"event_type": "PersonRegistered", "name": "Some Name", "age": 30}"#.to_vec(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            deserialized_event,
+            TaggedPersonEvent::PersonRegistered {
+                name: String::from("Some Name"),
+                age: 18
+            }
+        );
+        assert_eq!(
+            deserialized_complete_event,
+            TaggedPersonEvent::PersonRegistered {
+                name: String::from("Some Name"),
+                age: 30
+            }
+        );
+    }
+
+    #[test]
+    fn it_deserializes_the_events_persisted_with_an_alias() {
+        let json_serializer = JsonWithDefaults::<PersonEvent>::default()
+            .with_alias("PersonCreated", "PersonRegistered")
+            .with_defaults("PersonRegistered", serde_json::json!({"age": 18}));
+        let tagged_json_serializer = JsonWithDefaults::<TaggedPersonEvent>::default()
+            .tag("event_type")
+            .with_alias("PersonCreated", "PersonRegistered");
+
+        let deserialized_event = json_serializer
+            .deserialize(br#"{"PersonCreated": {"name": "Some Name"}}"#.to_vec())
+            .unwrap();
+        let deserialized_tagged_event = tagged_json_serializer
+            .deserialize(
+                br#"{"event_type": "PersonCreated", "name": "Some Name", "age": 30}"#.to_vec(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            deserialized_event,
+            PersonEvent::PersonRegistered {
+                name: String::from("Some Name"),
+                age: 18
+            }
+        );
+        assert_eq!(
+            deserialized_tagged_event,
+            TaggedPersonEvent::PersonRegistered {
+                name: String::from("Some Name"),
+                age: 30
+            }
+        );
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    #[serde(tag = "event_type", rename_all = "snake_case")]
+    enum CourseEvent {
+        CourseCreated {
+            course_id: String,
+            name: String,
+            seats: i32,
+            starts_at: i64,
+            published: bool,
+            tags: Vec<String>,
+            description: Option<String>,
+            capacity_delta: i32,
+        },
+    }
+
+    #[test]
+    fn it_deserializes_the_golden_payload() {
+        let json_serializer = Json::<CourseEvent>::default();
+
+        let deserialized_event = json_serializer
+            .deserialize(include_bytes!("../../fixtures/course_created.json").to_vec())
+            .unwrap();
+
+        assert_eq!(
+            deserialized_event,
+            CourseEvent::CourseCreated {
+                course_id: "c-42".to_string(),
+                name: "Café Rust 🦀".to_string(),
+                seats: 30,
+                starts_at: 9_007_199_254_740_993,
+                published: true,
+                tags: vec!["rust".to_string(), "beginner".to_string()],
+                description: None,
+                capacity_delta: -2,
+            }
+        );
+    }
 }
@@ -8,6 +8,8 @@ use prost::{bytes::Bytes, Message};
 use super::Error;
 use crate::serde::{Deserializer, Serializer};
 
+pub mod envelope;
+
 /// A struct to serialize and deserialize Protobuf payloads.
 #[derive(Debug, Clone, Copy)]
 pub struct Prost<I, O>(PhantomData<I>, PhantomData<O>)
@@ -105,4 +107,47 @@ mod tests {
         // Verify that the deserialized person matches the original person
         assert_eq!(person, deserialized_person);
     }
+
+    #[derive(PartialEq, Message, Clone)]
+    struct CourseCreated {
+        #[prost(string, tag = "1")]
+        course_id: String,
+        #[prost(string, tag = "2")]
+        name: String,
+        #[prost(int32, tag = "3")]
+        seats: i32,
+        #[prost(int64, tag = "4")]
+        starts_at: i64,
+        #[prost(bool, tag = "5")]
+        published: bool,
+        #[prost(string, repeated, tag = "6")]
+        tags: Vec<String>,
+        #[prost(string, optional, tag = "7")]
+        description: Option<String>,
+        #[prost(int32, tag = "8")]
+        capacity_delta: i32,
+    }
+
+    #[test]
+    fn it_deserializes_the_golden_payload() {
+        let serde_module = Prost::<CourseCreated, CourseCreated>::new();
+        let golden_payload = include_bytes!("../../fixtures/course_created.pb").to_vec();
+
+        let deserialized = serde_module.deserialize(golden_payload.clone()).unwrap();
+
+        assert_eq!(
+            deserialized,
+            CourseCreated {
+                course_id: "c-42".to_string(),
+                name: "Café Rust 🦀".to_string(),
+                seats: 30,
+                starts_at: 9_007_199_254_740_993,
+                published: true,
+                tags: vec!["rust".to_string(), "beginner".to_string()],
+                description: None,
+                capacity_delta: -2,
+            }
+        );
+        assert_eq!(serde_module.serialize(deserialized), golden_payload);
+    }
 }
@@ -0,0 +1,360 @@
+//! An envelope of the Protobuf payloads, dispatching the events by type.
+//!
+//! Instead of a single message with a `oneof` of all the events, each event type has its own message,
+//! wrapped in an `EventEnvelope` carrying the event type, the version of the message and the message as
+//! a `google.protobuf.Any`:
+//!
+//! ```proto
+//! message EventEnvelope {
+//!   string event_type = 1;
+//!   uint32 version = 2;
+//!   google.protobuf.Any payload = 3;
+//! }
+//! ```
+//!
+//! The consumers in other languages can decode the `event` table with this definition, and unpack the
+//! payload by its type URL.
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use prost::{Message, Name};
+use prost_types::Any;
+
+use super::Error;
+use crate::serde::{Deserializer, Serializer};
+
+/// The envelope of a Protobuf payload.
+#[derive(Clone, PartialEq, Message)]
+pub struct EventEnvelope {
+    /// The type of the event.
+    #[prost(string, tag = "1")]
+    pub event_type: String,
+    /// The version of the message of the event.
+    #[prost(uint32, tag = "2")]
+    pub version: u32,
+    /// The message of the event.
+    #[prost(message, optional, tag = "3")]
+    pub payload: Option<Any>,
+}
+
+/// Encodes and decodes the events of a type with their message.
+trait MessageCodec<E>: Send + Sync {
+    fn encode(&self, event: E) -> Any;
+    fn decode(&self, payload: Any) -> Result<E, Error>;
+}
+
+struct Codec<M>(PhantomData<fn() -> M>);
+
+impl<E, M> MessageCodec<E> for Codec<M>
+where
+    M: Message + Name + Default + TryFrom<E>,
+    E: From<M>,
+{
+    fn encode(&self, event: E) -> Any {
+        let message = M::try_from(event)
+            .unwrap_or_else(|_| panic!("the event should be converted into {}", M::full_name()));
+        Any {
+            type_url: M::type_url(),
+            value: message.encode_to_vec(),
+        }
+    }
+
+    fn decode(&self, payload: Any) -> Result<E, Error> {
+        if payload.type_url != M::type_url() {
+            return Err(Error::Deserialization(
+                format!(
+                    "unexpected payload type: {}, expected: {}",
+                    payload.type_url,
+                    M::type_url()
+                )
+                .into(),
+            ));
+        }
+        let message =
+            M::decode(payload.value.as_slice()).map_err(|e| Error::Deserialization(Box::new(e)))?;
+        Ok(E::from(message))
+    }
+}
+
+/// A serde wrapping the Protobuf message of each event type in an [`EventEnvelope`].
+///
+/// # Examples
+///
+/// ```ignore
+/// let serde = ProstEnvelope::new(DomainEvent::name)
+///     .with_message::<proto::CourseCreated>("CourseCreated", 1)
+///     .with_message::<proto::CourseClosed>("CourseClosed", 1);
+/// ```
+pub struct ProstEnvelope<E> {
+    event_type: fn(&E) -> &'static str,
+    codecs: HashMap<(String, u32), Arc<dyn MessageCodec<E>>>,
+    versions: HashMap<String, u32>,
+}
+
+impl<E> Clone for ProstEnvelope<E> {
+    fn clone(&self) -> Self {
+        Self {
+            event_type: self.event_type,
+            codecs: self.codecs.clone(),
+            versions: self.versions.clone(),
+        }
+    }
+}
+
+impl<E> std::fmt::Debug for ProstEnvelope<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProstEnvelope")
+            .field("versions", &self.versions)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E> ProstEnvelope<E> {
+    /// Creates a new `ProstEnvelope`, without messages.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The function returning the type of an event, e.g. `DomainEvent::name`.
+    pub fn new(event_type: fn(&E) -> &'static str) -> Self {
+        Self {
+            event_type,
+            codecs: HashMap::new(),
+            versions: HashMap::new(),
+        }
+    }
+
+    /// Registers the message of a version of an event type.
+    ///
+    /// The events are serialized with the message of the latest version of their type, and the payloads
+    /// are deserialized with the message of their version: the messages of the older versions can be kept
+    /// registered to read the payloads persisted with them.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the event.
+    /// * `version` - The version of the message.
+    pub fn with_message<M>(mut self, event_type: &str, version: u32) -> Self
+    where
+        M: Message + Name + Default + TryFrom<E> + 'static,
+        E: From<M> + 'static,
+    {
+        self.codecs.insert(
+            (event_type.to_string(), version),
+            Arc::new(Codec::<M>(PhantomData)),
+        );
+        let latest = self
+            .versions
+            .entry(event_type.to_string())
+            .or_insert(version);
+        *latest = (*latest).max(version);
+        self
+    }
+}
+
+impl<E> Serializer<E> for ProstEnvelope<E> {
+    /// Serializes the given event with the message of the latest version of its type, in an envelope.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The event to be serialized.
+    ///
+    /// # Returns
+    ///
+    /// The Protobuf-encoded envelope of the event.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no message is registered for the type of the event.
+    fn serialize(&self, value: E) -> Vec<u8> {
+        let event_type = (self.event_type)(&value);
+        let version = *self
+            .versions
+            .get(event_type)
+            .unwrap_or_else(|| panic!("no message is registered for {event_type}"));
+        let codec = &self.codecs[&(event_type.to_string(), version)];
+        EventEnvelope {
+            event_type: event_type.to_string(),
+            version,
+            payload: Some(codec.encode(value)),
+        }
+        .encode_to_vec()
+    }
+}
+
+impl<E> Deserializer<E> for ProstEnvelope<E> {
+    /// Deserializes the given envelope with the message of its event type and version.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The Protobuf-encoded envelope to be deserialized.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the deserialized event on success, or an error on failure.
+    fn deserialize(&self, data: Vec<u8>) -> Result<E, Error> {
+        let envelope = EventEnvelope::decode(data.as_slice())
+            .map_err(|e| Error::Deserialization(Box::new(e)))?;
+        let codec = self
+            .codecs
+            .get(&(envelope.event_type.clone(), envelope.version))
+            .ok_or_else(|| {
+                Error::Deserialization(
+                    format!(
+                        "no message is registered for {} version {}",
+                        envelope.event_type, envelope.version
+                    )
+                    .into(),
+                )
+            })?;
+        codec.decode(envelope.payload.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Clone)]
+    enum DomainEvent {
+        CourseCreated { course_id: String, seats: u32 },
+        CourseClosed { course_id: String },
+    }
+
+    impl DomainEvent {
+        fn name(&self) -> &'static str {
+            match self {
+                DomainEvent::CourseCreated { .. } => "CourseCreated",
+                DomainEvent::CourseClosed { .. } => "CourseClosed",
+            }
+        }
+    }
+
+    #[derive(PartialEq, Message, Clone)]
+    struct CourseCreatedV1 {
+        #[prost(string, tag = "1")]
+        course_id: String,
+    }
+
+    #[derive(PartialEq, Message, Clone)]
+    struct CourseCreated {
+        #[prost(string, tag = "1")]
+        course_id: String,
+        #[prost(uint32, tag = "2")]
+        seats: u32,
+    }
+
+    #[derive(PartialEq, Message, Clone)]
+    struct CourseClosed {
+        #[prost(string, tag = "1")]
+        course_id: String,
+    }
+
+    macro_rules! impl_message {
+        ($message:ident, $name:literal, $variant:ident { $($field:ident),* } $(, $default:ident: $value:expr)?) => {
+            impl Name for $message {
+                const NAME: &'static str = $name;
+                const PACKAGE: &'static str = "course";
+
+                fn type_url() -> String {
+                    format!("type.googleapis.com/{}", Self::full_name())
+                }
+            }
+
+            impl TryFrom<DomainEvent> for $message {
+                type Error = ();
+
+                fn try_from(event: DomainEvent) -> Result<Self, Self::Error> {
+                    match event {
+                        #[allow(unused_variables)]
+                        DomainEvent::$variant { $($field,)* .. } => Ok(Self { $($field),* }),
+                        _ => Err(()),
+                    }
+                }
+            }
+
+            impl From<$message> for DomainEvent {
+                fn from(message: $message) -> Self {
+                    DomainEvent::$variant {
+                        $($field: message.$field,)*
+                        $($default: $value)?
+                    }
+                }
+            }
+        };
+    }
+
+    impl_message!(CourseCreatedV1, "CourseCreatedV1", CourseCreated { course_id }, seats: 0);
+    impl_message!(
+        CourseCreated,
+        "CourseCreated",
+        CourseCreated { course_id, seats }
+    );
+    impl_message!(CourseClosed, "CourseClosed", CourseClosed { course_id });
+
+    fn serde() -> ProstEnvelope<DomainEvent> {
+        ProstEnvelope::new(DomainEvent::name)
+            .with_message::<CourseCreated>("CourseCreated", 2)
+            .with_message::<CourseCreatedV1>("CourseCreated", 1)
+            .with_message::<CourseClosed>("CourseClosed", 1)
+    }
+
+    #[test]
+    fn it_wraps_the_events_in_an_envelope() {
+        let serde = serde();
+        let event = DomainEvent::CourseCreated {
+            course_id: "c-42".to_string(),
+            seats: 30,
+        };
+
+        let data = serde.serialize(event.clone());
+
+        let envelope = EventEnvelope::decode(data.as_slice()).unwrap();
+        assert_eq!(envelope.event_type, "CourseCreated");
+        assert_eq!(envelope.version, 2);
+        assert_eq!(
+            envelope.payload.unwrap().type_url,
+            "type.googleapis.com/course.CourseCreated"
+        );
+        assert_eq!(serde.deserialize(data).unwrap(), event);
+    }
+
+    #[test]
+    fn it_deserializes_the_payloads_of_an_older_version() {
+        let data = EventEnvelope {
+            event_type: "CourseCreated".to_string(),
+            version: 1,
+            payload: Some(Any {
+                type_url: CourseCreatedV1::type_url(),
+                value: CourseCreatedV1 {
+                    course_id: "c-42".to_string(),
+                }
+                .encode_to_vec(),
+            }),
+        }
+        .encode_to_vec();
+
+        assert_eq!(
+            serde().deserialize(data).unwrap(),
+            DomainEvent::CourseCreated {
+                course_id: "c-42".to_string(),
+                seats: 0
+            }
+        );
+    }
+
+    #[test]
+    fn it_rejects_the_unknown_event_types() {
+        let data = EventEnvelope {
+            event_type: "CourseRenamed".to_string(),
+            version: 1,
+            payload: None,
+        }
+        .encode_to_vec();
+
+        assert!(matches!(
+            serde().deserialize(data),
+            Err(Error::Deserialization(_))
+        ));
+    }
+}
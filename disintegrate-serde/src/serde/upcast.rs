@@ -0,0 +1,362 @@
+//! Upcasting of the payloads persisted by older versions of the events.
+//!
+//! Renaming a field or changing the shape of an event breaks the deserialization of the payloads
+//! persisted before the change. `Upcasted` wraps a serde and stamps the payloads it serializes with a
+//! version; when a payload of an older version is deserialized, it is transformed by the chain of the
+//! upcasters registered after that version, so that the wrapped serde only deals with the current shape
+//! of the events.
+//!
+//! The upcaster registered first transforms the payloads of version 0, i.e. the payloads persisted before
+//! the upcasting was enabled, into payloads of version 1, the second one transforms version 1 into
+//! version 2, and so on. Upcasters must never be removed or reordered once payloads of their target version
+//! have been persisted.
+//!
+//! The upcasters of an event type can instead follow the version declared with `#[event(version = N)]`,
+//! which the event store stores with each event: the upcaster registered with `with_event_upcaster` for a
+//! version transforms the payloads of that version of the event into the next one.
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use super::Error;
+use crate::serde::{Deserializer, PayloadInfo, Serializer};
+
+/// The marker preceding the version of a versioned payload.
+const VERSION_MARKER: [u8; 4] = [0xD1, 0x5E, 0xC7, 0x00];
+
+/// The length of the header of a versioned payload: the marker followed by the big-endian version.
+const HEADER_LEN: usize = VERSION_MARKER.len() + 4;
+
+/// Transforms a serialized payload of a version into a payload of the next version.
+pub trait Upcaster: Send + Sync {
+    /// Upcasts the given payload.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The serialized payload of the version handled by the upcaster.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the payload of the next version on success, or an error on failure.
+    fn upcast(&self, payload: Vec<u8>) -> Result<Vec<u8>, Error>;
+}
+
+impl<F> Upcaster for F
+where
+    F: Fn(Vec<u8>) -> Result<Vec<u8>, Error> + Send + Sync,
+{
+    fn upcast(&self, payload: Vec<u8>) -> Result<Vec<u8>, Error> {
+        self(payload)
+    }
+}
+
+/// A serde upcasting the payloads of the older versions before deserializing them.
+#[derive(Clone)]
+pub struct Upcasted<S> {
+    serde: S,
+    upcasters: Vec<Arc<dyn Upcaster>>,
+    event_upcasters: BTreeMap<(String, u32), Arc<dyn Upcaster>>,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for Upcasted<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Upcasted")
+            .field("serde", &self.serde)
+            .field("version", &self.version())
+            .field("event_upcasters", &self.event_upcasters.keys())
+            .finish()
+    }
+}
+
+impl<S> Upcasted<S> {
+    /// Creates a new `Upcasted` serde, without upcasters.
+    ///
+    /// # Arguments
+    ///
+    /// * `serde` - The serde of the current version of the events.
+    pub fn new(serde: S) -> Self {
+        Self {
+            serde,
+            upcasters: vec![],
+            event_upcasters: BTreeMap::new(),
+        }
+    }
+
+    /// Registers the upcaster of the next version.
+    ///
+    /// # Arguments
+    ///
+    /// * `upcaster` - The upcaster transforming the payloads of the current version into the next one.
+    pub fn with_upcaster(mut self, upcaster: impl Upcaster + 'static) -> Self {
+        self.upcasters.push(Arc::new(upcaster));
+        self
+    }
+
+    /// Registers the upcaster of a version of an event type, declared with `#[event(version = N)]`.
+    ///
+    /// The upcaster is applied to the payloads read by the event store with their version, after the upcasters
+    /// registered with `with_upcaster`.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the event, as stored in the event store.
+    /// * `version` - The version of the payloads transformed by the upcaster into the next version.
+    /// * `upcaster` - The upcaster.
+    pub fn with_event_upcaster(
+        mut self,
+        event_type: &str,
+        version: u32,
+        upcaster: impl Upcaster + 'static,
+    ) -> Self {
+        self.event_upcasters
+            .insert((event_type.to_string(), version), Arc::new(upcaster));
+        self
+    }
+
+    /// Returns the version of the payloads serialized by this serde, i.e. the number of registered upcasters.
+    pub fn version(&self) -> u32 {
+        self.upcasters.len() as u32
+    }
+
+    fn upcast(&self, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let (version, mut payload) = split_version(data);
+        let upcasters = self
+            .upcasters
+            .get(version as usize..)
+            .ok_or(Error::UnsupportedVersion(version))?;
+        for upcaster in upcasters {
+            payload = upcaster.upcast(payload)?;
+        }
+        Ok(payload)
+    }
+
+    fn upcast_event(&self, mut payload: Vec<u8>, info: PayloadInfo<'_>) -> Result<Vec<u8>, Error> {
+        let mut version = info.version;
+        while let Some(upcaster) = self
+            .event_upcasters
+            .get(&(info.event_type.to_string(), version))
+        {
+            payload = upcaster.upcast(payload)?;
+            version += 1;
+        }
+        Ok(payload)
+    }
+
+    /// Stamps a payload with the current version, if the serde has upcasters.
+    fn versioned(&self, payload: Vec<u8>) -> Vec<u8> {
+        if self.upcasters.is_empty() {
+            return payload;
+        }
+        let mut data = Vec::with_capacity(HEADER_LEN + payload.len());
+        data.extend_from_slice(&VERSION_MARKER);
+        data.extend_from_slice(&self.version().to_be_bytes());
+        data.extend(payload);
+        data
+    }
+}
+
+impl<S, T> Serializer<T> for Upcasted<S>
+where
+    S: Serializer<T>,
+{
+    /// Serializes the given value with the wrapped serde, stamping the payload with the current version.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to be serialized.
+    ///
+    /// # Returns
+    ///
+    /// The versioned payload of the value.
+    fn serialize(&self, value: T) -> Vec<u8> {
+        self.versioned(self.serde.serialize(value))
+    }
+
+    fn serialize_with_info(&self, value: T, info: PayloadInfo<'_>) -> Vec<u8> {
+        self.versioned(self.serde.serialize_with_info(value, info))
+    }
+}
+
+impl<S, T> Deserializer<T> for Upcasted<S>
+where
+    S: Deserializer<T>,
+{
+    /// Upcasts the given payload to the current version and deserializes it with the wrapped serde.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The versioned payload to be deserialized.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the deserialized value on success, or an error on failure.
+    fn deserialize(&self, data: Vec<u8>) -> Result<T, Error> {
+        self.serde.deserialize(self.upcast(data)?)
+    }
+
+    /// Upcasts the given payload with the upcasters of the serde, then with the upcasters of its event
+    /// from the version it was appended with, and deserializes it with the wrapped serde.
+    fn deserialize_with_info(&self, data: Vec<u8>, info: PayloadInfo<'_>) -> Result<T, Error> {
+        let payload = self.upcast_event(self.upcast(data)?, info)?;
+        self.serde.deserialize_with_info(payload, info)
+    }
+
+    fn validate_schema(&self) -> Result<(), Error> {
+        self.serde.validate_schema()
+    }
+}
+
+/// Splits a payload into its version and the payload serialized by the wrapped serde.
+///
+/// The payloads without a header are of version 0.
+fn split_version(mut data: Vec<u8>) -> (u32, Vec<u8>) {
+    if data.len() < HEADER_LEN || data[..VERSION_MARKER.len()] != VERSION_MARKER {
+        return (0, data);
+    }
+    let version = u32::from_be_bytes(
+        data[VERSION_MARKER.len()..HEADER_LEN]
+            .try_into()
+            .expect("the version is 4 bytes long"),
+    );
+    (version, data.split_off(HEADER_LEN))
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::serde::json::{json_upcaster, Json};
+    use crate::Serde;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    #[serde(tag = "event_type")]
+    enum PersonEvent {
+        PersonRegistered { full_name: String, age: u32 },
+    }
+
+    fn serde() -> Upcasted<Json<PersonEvent>> {
+        Json::<PersonEvent>::default()
+            .with_upcaster(json_upcaster(|value| {
+                if let Value::Object(fields) = value {
+                    if let Some(name) = fields.remove("name") {
+                        fields.insert("full_name".to_string(), name);
+                    }
+                }
+                Ok(())
+            }))
+            .with_upcaster(json_upcaster(|value| {
+                if let Value::Object(fields) = value {
+                    fields.entry("age").or_insert(Value::from(18));
+                }
+                Ok(())
+            }))
+    }
+
+    #[test]
+    fn it_upcasts_the_payloads_of_the_older_versions() {
+        let serde = serde();
+
+        let version_0 = br#"{"event_type": "PersonRegistered", "name": "Some Name"}"#.to_vec();
+        let mut version_1 = VERSION_MARKER.to_vec();
+        version_1.extend(1u32.to_be_bytes());
+        version_1.extend(br#"{"event_type": "PersonRegistered", "full_name": "Other Name"}"#);
+
+        assert_eq!(
+            serde.deserialize(version_0).unwrap(),
+            PersonEvent::PersonRegistered {
+                full_name: "Some Name".to_string(),
+                age: 18
+            }
+        );
+        assert_eq!(
+            serde.deserialize(version_1).unwrap(),
+            PersonEvent::PersonRegistered {
+                full_name: "Other Name".to_string(),
+                age: 18
+            }
+        );
+    }
+
+    #[test]
+    fn it_stamps_the_serialized_payloads_with_the_current_version() {
+        let serde = serde();
+        let event = PersonEvent::PersonRegistered {
+            full_name: "Some Name".to_string(),
+            age: 30,
+        };
+
+        let data = serde.serialize(event.clone());
+
+        assert_eq!(split_version(data.clone()).0, 2);
+        assert_eq!(serde.deserialize(data).unwrap(), event);
+
+        let mut unknown = VERSION_MARKER.to_vec();
+        unknown.extend(3u32.to_be_bytes());
+        unknown.extend(br#"{}"#);
+        assert!(matches!(
+            serde.deserialize(unknown),
+            Err(Error::UnsupportedVersion(3))
+        ));
+    }
+
+    #[test]
+    fn it_upcasts_the_payloads_from_the_declared_version_of_their_event() {
+        let serde = Upcasted::new(Json::<PersonEvent>::default())
+            .with_event_upcaster(
+                "PersonRegistered",
+                1,
+                json_upcaster(|value| {
+                    if let Value::Object(fields) = value {
+                        if let Some(name) = fields.remove("name") {
+                            fields.insert("full_name".to_string(), name);
+                        }
+                    }
+                    Ok(())
+                }),
+            )
+            .with_event_upcaster(
+                "PersonRegistered",
+                2,
+                json_upcaster(|value| {
+                    if let Value::Object(fields) = value {
+                        fields.entry("age").or_insert(Value::from(18));
+                    }
+                    Ok(())
+                }),
+            );
+        let info = |version| PayloadInfo {
+            event_type: "PersonRegistered",
+            version,
+            event_id: None,
+        };
+
+        let version_1 = br#"{"event_type": "PersonRegistered", "name": "Some Name"}"#.to_vec();
+        let version_2 =
+            br#"{"event_type": "PersonRegistered", "full_name": "Other Name"}"#.to_vec();
+        let version_3 =
+            br#"{"event_type": "PersonRegistered", "full_name": "Third Name", "age": 30}"#.to_vec();
+
+        assert_eq!(
+            serde.deserialize_with_info(version_1, info(1)).unwrap(),
+            PersonEvent::PersonRegistered {
+                full_name: "Some Name".to_string(),
+                age: 18
+            }
+        );
+        assert_eq!(
+            serde.deserialize_with_info(version_2, info(2)).unwrap(),
+            PersonEvent::PersonRegistered {
+                full_name: "Other Name".to_string(),
+                age: 18
+            }
+        );
+        assert_eq!(
+            serde.deserialize_with_info(version_3, info(3)).unwrap(),
+            PersonEvent::PersonRegistered {
+                full_name: "Third Name".to_string(),
+                age: 30
+            }
+        );
+    }
+}
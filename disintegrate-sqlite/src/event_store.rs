@@ -0,0 +1,311 @@
+//! SQLite Event Store
+//!
+//! This module provides an implementation of the `EventStore` trait using SQLite as the underlying storage.
+//! The events are stored in the `event` table, with a column for each domain identifier, as in the PostgreSQL
+//! event store. Since SQLite serializes the writers, the conflicting events are checked by the same statement
+//! that inserts the new events.
+mod criteria;
+#[cfg(test)]
+mod tests;
+
+use std::error::Error as StdError;
+use std::marker::PhantomData;
+
+use async_stream::stream;
+use async_trait::async_trait;
+pub(crate) use criteria::push_criteria;
+use criteria::push_identifier_value;
+use disintegrate::{DomainIdentifierInfo, Event, EventStore, PersistedEvent, StreamQuery};
+use disintegrate_serde::Serde;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use sqlx::{QueryBuilder, Row, Sqlite, SqliteConnection, SqlitePool};
+
+use crate::{Error, SqliteEventId};
+
+/// The default maximum number of events inserted by a single statement during an append.
+pub const DEFAULT_APPEND_BATCH_SIZE: usize = 1000;
+
+/// The maximum number of bind parameters of a SQLite statement.
+const MAX_BIND_PARAMETERS: usize = 32766;
+
+/// SQLite event store implementation.
+#[derive(Clone)]
+pub struct SqliteEventStore<E, S>
+where
+    E: Event,
+    S: Serde<E> + Send + Sync,
+{
+    pub(crate) pool: SqlitePool,
+    serde: S,
+    append_batch_size: usize,
+    event_type: PhantomData<E>,
+}
+
+impl<E, S> std::fmt::Debug for SqliteEventStore<E, S>
+where
+    E: Event,
+    S: Serde<E> + Send + Sync,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqliteEventStore")
+            .field("pool", &self.pool)
+            .field("append_batch_size", &self.append_batch_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E, S> SqliteEventStore<E, S>
+where
+    S: Serde<E> + Send + Sync,
+    E: Event,
+{
+    /// Initializes the SQLite DB and returns a new instance of `SqliteEventStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The SQLite connection pool.
+    /// * `serde` - The serialization implementation for the event payload.
+    pub async fn new(pool: SqlitePool, serde: S) -> Result<Self, Error> {
+        setup::<E>(&pool).await?;
+        Ok(Self::new_uninitialized(pool, serde))
+    }
+
+    /// Creates a new instance of `SqliteEventStore`.
+    ///
+    /// This constructor does not initialize the database or add the `domain_identifier` columns.
+    /// If you need to initialize the database, use `SqliteEventStore::new` instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The SQLite connection pool.
+    /// * `serde` - The serialization implementation for the event payload.
+    pub fn new_uninitialized(pool: SqlitePool, serde: S) -> Self {
+        Self {
+            pool,
+            serde,
+            append_batch_size: DEFAULT_APPEND_BATCH_SIZE,
+            event_type: PhantomData,
+        }
+    }
+
+    /// Sets the maximum number of events inserted by a single statement during an append.
+    ///
+    /// Larger appends are split into chunks of this size, all inserted in the same transaction.
+    /// The chunks are further limited so that a statement never exceeds the SQLite bind parameters limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `append_batch_size` - The maximum number of events per insert statement.
+    pub fn with_append_batch_size(mut self, append_batch_size: usize) -> Self {
+        self.append_batch_size = append_batch_size.max(1);
+        self
+    }
+
+    /// Returns the maximum number of events inserted by a single statement.
+    ///
+    /// Half of the bind parameters are left to the criteria of the conflict check.
+    fn batch_size(&self) -> usize {
+        let parameters_per_event = 2 + E::SCHEMA.domain_identifiers.len();
+        self.append_batch_size
+            .min(MAX_BIND_PARAMETERS / 2 / parameters_per_event)
+            .max(1)
+    }
+
+    /// Pushes the selection of the rows of the events to insert into the `event` table.
+    fn push_rows<'a>(&self, builder: &mut QueryBuilder<'a, Sqlite>, events: &'a [E])
+    where
+        E: Clone,
+    {
+        let domain_identifiers = E::SCHEMA.domain_identifiers;
+        builder.push("INSERT INTO event (event_type, payload");
+        for domain_identifier in domain_identifiers {
+            builder.push(format!(", {}", domain_identifier.ident));
+        }
+        builder.push(") SELECT * FROM (VALUES ");
+        let mut rows = events.iter().peekable();
+        while let Some(event) = rows.next() {
+            let identifiers = event.domain_identifiers();
+            builder.push("(");
+            builder.push_bind(event.name());
+            builder.push(", ");
+            builder.push_bind(self.serde.serialize(event.clone()));
+            for domain_identifier in domain_identifiers {
+                builder.push(", ");
+                match identifiers.get(&domain_identifier.ident) {
+                    Some(value) => push_identifier_value(builder, value),
+                    None => {
+                        builder.push("NULL");
+                    }
+                }
+            }
+            builder.push(")");
+            rows.peek().map(|_| builder.push(", "));
+        }
+        builder.push(")");
+    }
+}
+
+/// Implementation of the event store using SQLite.
+///
+/// This module provides the implementation of the `EventStore` trait for `SqliteEventStore`,
+/// allowing interaction with a SQLite event store. It enables streaming events based on
+/// a query and appending new events to the event store.
+#[async_trait]
+impl<E, S> EventStore<SqliteEventId, E> for SqliteEventStore<E, S>
+where
+    E: Event + Send + Sync,
+    S: Serde<E> + Send + Sync,
+{
+    type Error = Error;
+
+    /// Streams events based on the provided query.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The stream query specifying the criteria for filtering events.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a boxed stream of `PersistedEvent` that matches the query criteria,
+    /// or an error of type `Self::Error`.
+    fn stream<'a, QE>(
+        &'a self,
+        query: &'a StreamQuery<SqliteEventId, QE>,
+    ) -> BoxStream<'a, Result<PersistedEvent<SqliteEventId, QE>, Self::Error>>
+    where
+        QE: TryFrom<E> + Event + 'static + Clone + Send + Sync,
+        <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
+    {
+        stream! {
+            let mut sql = QueryBuilder::new("SELECT event_id, payload FROM event WHERE ");
+            push_criteria(&mut sql, query);
+            sql.push(" ORDER BY event_id ASC");
+
+            for await row in sql.build().fetch(&self.pool) {
+                let row = row?;
+                let id = row.get(0);
+
+                let payload = self.serde.deserialize(row.get(1))?;
+                yield Ok(PersistedEvent::new(id, payload.try_into().map_err(|e| Error::QueryEventMapping(Box::new(e)))?));
+            }
+        }
+        .boxed()
+    }
+
+    /// Appends new events to the event store.
+    ///
+    /// The first chunk of events is inserted only if no event matching the `query` has been appended after
+    /// `version`. Since the check and the insert are executed by the same statement, and SQLite acquires the
+    /// write lock before executing a write statement, no other append can interleave between them. The remaining
+    /// chunks are inserted in the same transaction. If a conflicting event is found, a `Concurrency` error
+    /// is returned and no event is appended.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - A vector of events to be appended.
+    /// * `query` - The stream query specifying the criteria for filtering events.
+    /// * `version` - The ID of the last consumed event.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a vector of `PersistedEvent` representing the appended events,
+    /// or an error of type `Self::Error`.
+    async fn append<QE>(
+        &self,
+        events: Vec<E>,
+        query: StreamQuery<SqliteEventId, QE>,
+        version: SqliteEventId,
+    ) -> Result<Vec<PersistedEvent<SqliteEventId, E>>, Self::Error>
+    where
+        E: Clone + 'async_trait,
+        QE: Event + Clone + Send + Sync,
+    {
+        let query = query.change_origin(version);
+        let mut event_ids: Vec<SqliteEventId> = Vec::with_capacity(events.len());
+        let mut tx = self.pool.begin().await?;
+        for (i, chunk) in events.chunks(self.batch_size()).enumerate() {
+            let mut insert = QueryBuilder::new("");
+            self.push_rows(&mut insert, chunk);
+            if i == 0 {
+                insert.push(" WHERE NOT EXISTS (SELECT 1 FROM event WHERE ");
+                push_criteria(&mut insert, &query);
+                insert.push(")");
+            }
+            insert.push(" RETURNING event_id");
+            let mut chunk_ids: Vec<SqliteEventId> = insert
+                .build()
+                .fetch_all(&mut *tx)
+                .await?
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+            if chunk_ids.len() < chunk.len() {
+                return Err(Error::Concurrency);
+            }
+            chunk_ids.sort_unstable();
+            event_ids.extend(chunk_ids);
+        }
+        tx.commit().await?;
+
+        Ok(event_ids
+            .into_iter()
+            .zip(events)
+            .map(|(id, event)| PersistedEvent::new(id, event))
+            .collect())
+    }
+}
+
+pub async fn setup<E: Event>(pool: &SqlitePool) -> Result<(), Error> {
+    const RESERVED_NAMES: &[&str] = &["event_id", "payload", "event_type", "inserted_at"];
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(include_str!("event_store/sql/table_event.sql"))
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(include_str!("event_store/sql/idx_event_type.sql"))
+        .execute(&mut *tx)
+        .await?;
+
+    let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info('event')")
+        .fetch_all(&mut *tx)
+        .await?;
+    for domain_identifier in E::SCHEMA.domain_identifiers {
+        if RESERVED_NAMES.contains(&domain_identifier.ident) {
+            panic!("Domain identifier name {domain_identifier} is reserved. Please use a different name.", domain_identifier = domain_identifier.ident);
+        }
+        let exists = columns
+            .iter()
+            .any(|column| column.eq_ignore_ascii_case(&domain_identifier.ident));
+        add_domain_identifier_column(&mut tx, domain_identifier, exists).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn add_domain_identifier_column(
+    conn: &mut SqliteConnection,
+    domain_identifier: &DomainIdentifierInfo,
+    exists: bool,
+) -> Result<(), Error> {
+    let column_name = domain_identifier.ident;
+    if !exists {
+        let sql_type = match domain_identifier.type_info {
+            disintegrate::IdentifierType::String => "TEXT",
+            disintegrate::IdentifierType::i64 => "INTEGER",
+            disintegrate::IdentifierType::Uuid => "TEXT",
+        };
+        sqlx::query(&format!(
+            "ALTER TABLE event ADD COLUMN {column_name} {sql_type}"
+        ))
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    sqlx::query(&format!(
+        "CREATE INDEX IF NOT EXISTS idx_event_{column_name} ON event ({column_name}, event_id) WHERE {column_name} IS NOT NULL"
+    ))
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,244 @@
+use disintegrate::{Event, EventInfo, IdentifierCondition, IdentifierValue, StreamQuery};
+use sqlx::{QueryBuilder, Sqlite};
+
+use crate::SqliteEventId;
+
+/// Pushes the SQL criteria selecting the events of a stream query.
+///
+/// The criteria are a boolean expression on the columns of the `event` table, so they can be used both
+/// to stream the events and to check for conflicting events while appending. The event types, the origins
+/// and the domain identifiers are bound as parameters, so the SQL of the statement does not depend on them.
+///
+/// # Arguments
+///
+/// * `builder` - The SQL query builder the criteria are pushed to.
+/// * `query` - The stream query specifying the filtering options.
+pub(crate) fn push_criteria<QE>(
+    builder: &mut QueryBuilder<'_, Sqlite>,
+    query: &StreamQuery<SqliteEventId, QE>,
+) where
+    QE: Event + Clone,
+{
+    let mut filters = query.filters().iter().peekable();
+    while let Some(filter) = filters.next() {
+        let events: Vec<&str> = filter
+            .events()
+            .iter()
+            .filter(|e| {
+                filter
+                    .excluded_events()
+                    .is_none_or(|excluded_events| !excluded_events.contains(e))
+            })
+            .cloned()
+            .collect();
+        let has_events = !events.is_empty();
+        builder.push("(");
+        if filter.origin() > 0 {
+            builder.push("event_id > ");
+            builder.push_bind(filter.origin());
+            builder.push(if has_events { " AND (" } else { " AND " });
+        }
+        if !has_events {
+            builder.push("FALSE");
+        }
+
+        let mut events = events.into_iter().peekable();
+        while let Some(event) = events.next() {
+            let event_info = QE::SCHEMA.event_info(event).unwrap();
+            push_event_type(builder, event_info);
+            if filter.excludes_event_type(event_info) {
+                builder.push(" AND FALSE");
+            }
+            for (ident, value) in filter
+                .identifiers()
+                .iter()
+                .filter(|(ident, _)| event_info.has_domain_identifier(ident))
+            {
+                builder.push(format!(" AND {ident} = "));
+                push_identifier_value(builder, value);
+            }
+            for condition in filter.declared_conditions(event_info) {
+                builder.push(" AND ");
+                push_condition(builder, condition);
+            }
+            builder.push(")");
+            events.peek().map(|_| builder.push(" OR "));
+        }
+        if filter.origin() > 0 && has_events {
+            builder.push(")");
+        }
+        builder.push(")");
+        filters.peek().map(|_| builder.push(" OR "));
+    }
+}
+
+/// Pushes the criteria matching the event type of an event, including the aliases of its former names.
+fn push_event_type(builder: &mut QueryBuilder<'_, Sqlite>, event_info: &EventInfo) {
+    if event_info.aliases.is_empty() {
+        builder.push("(event_type = ");
+        builder.push_bind(event_info.name);
+    } else {
+        builder.push("(event_type IN (");
+        let mut event_types = event_info.event_types().peekable();
+        while let Some(event_type) = event_types.next() {
+            builder.push_bind(event_type);
+            event_types.peek().map(|_| builder.push(", "));
+        }
+        builder.push(")");
+    }
+}
+
+/// Pushes the criteria of a comparison of a domain identifier other than the equality.
+fn push_condition(builder: &mut QueryBuilder<'_, Sqlite>, condition: &IdentifierCondition) {
+    let ident = condition.identifier();
+    match condition {
+        IdentifierCondition::NotEq(_, value) => {
+            builder.push(format!("{ident} <> "));
+            push_identifier_value(builder, value);
+        }
+        IdentifierCondition::In(_, values) if values.is_empty() => {
+            builder.push("FALSE");
+        }
+        IdentifierCondition::In(_, values) => {
+            builder.push(format!("{ident} IN ("));
+            let mut values = values.iter().peekable();
+            while let Some(value) = values.next() {
+                push_identifier_value(builder, value);
+                values.peek().map(|_| builder.push(", "));
+            }
+            builder.push(")");
+        }
+        IdentifierCondition::IsNull(_) => {
+            builder.push(format!("{ident} IS NULL"));
+        }
+    }
+}
+
+/// Pushes the bind parameter of a domain identifier value.
+///
+/// UUIDs are stored in their hyphenated text representation, so they remain readable in the database.
+pub(crate) fn push_identifier_value(
+    builder: &mut QueryBuilder<'_, Sqlite>,
+    value: &IdentifierValue,
+) {
+    match value {
+        IdentifierValue::String(value) => builder.push_bind(value.clone()),
+        IdentifierValue::i64(value) => builder.push_bind(*value),
+        IdentifierValue::Uuid(value) => builder.push_bind(value.to_string()),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use disintegrate::{
+        domain_identifiers, event_types, ident, query, DomainIdentifierInfo, DomainIdentifierSet,
+        EventInfo, EventSchema, IdentifierType, MissingIdentifier,
+    };
+
+    #[allow(dead_code)]
+    #[derive(Clone)]
+    enum TestEvent {
+        Bar { bar_id: String },
+        Foo { foo_id: String },
+    }
+
+    impl Event for TestEvent {
+        const SCHEMA: EventSchema = EventSchema {
+            events: &["Bar", "Foo"],
+            events_info: &[
+                &EventInfo {
+                    name: "Bar",
+                    domain_identifiers: &[&ident!(#bar_id)],
+                    description: None,
+                    owner: None,
+                    aliases: &[],
+                    version: 1,
+                },
+                &EventInfo {
+                    name: "Foo",
+                    domain_identifiers: &[&ident!(#foo_id)],
+                    description: None,
+                    owner: None,
+                    aliases: &[],
+                    version: 1,
+                },
+            ],
+            domain_identifiers: &[
+                &DomainIdentifierInfo {
+                    ident: ident!(#foo_id),
+                    type_info: IdentifierType::String,
+                },
+                &DomainIdentifierInfo {
+                    ident: ident!(#bar_id),
+                    type_info: IdentifierType::String,
+                },
+            ],
+        };
+
+        fn name(&self) -> &'static str {
+            ""
+        }
+        fn domain_identifiers(&self) -> DomainIdentifierSet {
+            domain_identifiers! {}
+        }
+    }
+
+    fn criteria(query: StreamQuery<SqliteEventId, TestEvent>) -> String {
+        let mut builder = QueryBuilder::new("");
+        push_criteria(&mut builder, &query);
+        builder.sql().to_string()
+    }
+
+    #[test]
+    fn it_builds_the_criteria_of_a_query() {
+        assert_eq!(
+            criteria(query!(TestEvent)),
+            "((event_type = ?) OR (event_type = ?))"
+        );
+        assert_eq!(
+            criteria(query!(10 => TestEvent; foo_id == "value")),
+            "(event_id > ? AND ((event_type = ?) OR (event_type = ? AND foo_id = ?)))"
+        );
+        assert_eq!(
+            criteria(
+                query!(TestEvent; bar_id == "value1").union(&query!(TestEvent; foo_id == "value2"))
+            ),
+            "((event_type = ? AND bar_id = ?) OR (event_type = ?)) OR ((event_type = ?) OR (event_type = ? AND foo_id = ?))"
+        );
+    }
+
+    #[test]
+    fn it_builds_the_criteria_of_the_identifier_conditions() {
+        assert_eq!(
+            criteria(query!(TestEvent; foo_id in ["value1", "value2"], bar_id != "value3")),
+            "((event_type = ? AND bar_id <> ?) OR (event_type = ? AND foo_id IN (?, ?)))"
+        );
+        assert_eq!(
+            criteria(query!(TestEvent; foo_id is_null)),
+            "((event_type = ?) OR (event_type = ? AND foo_id IS NULL))"
+        );
+    }
+
+    #[test]
+    fn it_builds_the_criteria_of_the_excluded_events() {
+        assert_eq!(
+            criteria(
+                query!(TestEvent; bar_id == "value1")
+                    .exclude_events(event_types!(TestEvent, [Bar]))
+            ),
+            "((event_type = ?))"
+        );
+        assert_eq!(
+            criteria(query!(TestEvent).exclude_events(event_types!(TestEvent, [Bar, Foo]))),
+            "(FALSE)"
+        );
+        assert_eq!(
+            criteria(
+                query!(TestEvent; foo_id == "value")
+                    .with_missing_identifier(MissingIdentifier::NoMatch)
+            ),
+            "((event_type = ? AND FALSE) OR (event_type = ? AND foo_id = ?))"
+        );
+    }
+}
@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS idx_event_type ON event (event_type);
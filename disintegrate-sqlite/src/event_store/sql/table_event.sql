@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS event (
+    event_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_type TEXT NOT NULL,
+    payload BLOB NOT NULL,
+    inserted_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,187 @@
+use disintegrate::{query, Event, EventStore, PersistedEvent};
+use disintegrate_serde::serde::json::Json;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+
+use super::*;
+use crate::testing::memory_pool;
+
+#[derive(Debug, Clone, PartialEq, Event, Serialize, Deserialize)]
+enum ShoppingCartEvent {
+    Added {
+        #[id]
+        product_id: String,
+        #[id]
+        cart_id: String,
+    },
+    Removed {
+        #[id]
+        product_id: String,
+        #[id]
+        cart_id: String,
+    },
+}
+
+fn added_event(product_id: &str, cart_id: &str) -> ShoppingCartEvent {
+    ShoppingCartEvent::Added {
+        product_id: product_id.to_string(),
+        cart_id: cart_id.to_string(),
+    }
+}
+
+fn removed_event(product_id: &str, cart_id: &str) -> ShoppingCartEvent {
+    ShoppingCartEvent::Removed {
+        product_id: product_id.to_string(),
+        cart_id: cart_id.to_string(),
+    }
+}
+
+async fn event_store() -> SqliteEventStore<ShoppingCartEvent, Json<ShoppingCartEvent>> {
+    SqliteEventStore::new(memory_pool().await, Json::default())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn it_appends_and_streams_the_events_of_a_query() {
+    let event_store = event_store().await;
+
+    let persisted_events = event_store
+        .append(
+            vec![
+                added_event("product_1", "cart_1"),
+                added_event("product_1", "cart_2"),
+                removed_event("product_1", "cart_1"),
+            ],
+            query!(ShoppingCartEvent; cart_id == "cart_1"),
+            0,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        persisted_events
+            .iter()
+            .map(|event| event.id())
+            .collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+
+    let events: Vec<_> = event_store
+        .stream(&query!(ShoppingCartEvent; cart_id == "cart_1"))
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(
+        events,
+        vec![
+            PersistedEvent::new(1, added_event("product_1", "cart_1")),
+            PersistedEvent::new(3, removed_event("product_1", "cart_1")),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn it_returns_a_concurrency_error_when_an_event_of_the_query_has_been_appended() {
+    let event_store = event_store().await;
+    let query = query!(ShoppingCartEvent; cart_id == "cart_1");
+    event_store
+        .append(vec![added_event("product_1", "cart_1")], query.clone(), 0)
+        .await
+        .unwrap();
+    event_store
+        .append(vec![added_event("product_2", "cart_1")], query.clone(), 1)
+        .await
+        .unwrap();
+
+    let result = event_store
+        .append(vec![removed_event("product_1", "cart_1")], query, 1)
+        .await;
+
+    assert!(matches!(result, Err(Error::Concurrency)));
+    event_store
+        .append(
+            vec![added_event("product_1", "cart_2")],
+            query!(ShoppingCartEvent; cart_id == "cart_2"),
+            1,
+        )
+        .await
+        .unwrap();
+    let events: Vec<_> = event_store
+        .stream(&query!(ShoppingCartEvent))
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 3);
+}
+
+#[tokio::test]
+async fn it_appends_events_in_chunks() {
+    let event_store = event_store().await.with_append_batch_size(2);
+    let events: Vec<_> = (0..5)
+        .map(|i| added_event(&format!("product_{i}"), "cart_1"))
+        .collect();
+
+    let persisted_events = event_store
+        .append(events.clone(), query!(ShoppingCartEvent), 0)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        persisted_events
+            .iter()
+            .map(|event| event.id())
+            .collect::<Vec<_>>(),
+        vec![1, 2, 3, 4, 5]
+    );
+    let stored: Vec<_> = event_store
+        .stream(&query!(ShoppingCartEvent))
+        .map_ok(|event| event.into_inner())
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(stored, events);
+}
+
+#[tokio::test]
+async fn it_lets_only_one_of_the_concurrent_appends_succeed() {
+    let path = std::env::temp_dir().join(format!(
+        "disintegrate_sqlite_concurrent_appends_{}.db",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(8)
+        .connect_with(
+            sqlx::sqlite::SqliteConnectOptions::new()
+                .filename(&path)
+                .create_if_missing(true),
+        )
+        .await
+        .unwrap();
+    let event_store =
+        SqliteEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(pool, Json::default())
+            .await
+            .unwrap();
+
+    let results = futures::future::join_all((0..8).map(|i| {
+        let event_store = event_store.clone();
+        async move {
+            event_store
+                .append(
+                    vec![added_event(&format!("product_{i}"), "cart_1")],
+                    query!(ShoppingCartEvent; cart_id == "cart_1"),
+                    0,
+                )
+                .await
+        }
+    }))
+    .await;
+
+    let appended = results.iter().filter(|result| result.is_ok()).count();
+    let conflicts = results
+        .iter()
+        .filter(|result| matches!(result, Err(Error::Concurrency)))
+        .count();
+    assert_eq!((appended, conflicts), (1, 7));
+    let _ = std::fs::remove_file(&path);
+}
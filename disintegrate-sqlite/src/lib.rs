@@ -0,0 +1,48 @@
+//! # SQLite Disintegrate Backend Library
+//!
+//! An embedded event store with the semantics of the PostgreSQL backend, for local development and tests.
+mod error;
+mod event_store;
+#[cfg(feature = "listener")]
+mod listener;
+mod snapshotter;
+#[cfg(test)]
+mod testing;
+
+pub use crate::event_store::{SqliteEventStore, DEFAULT_APPEND_BATCH_SIZE};
+#[cfg(feature = "listener")]
+pub use crate::listener::{SqliteEventListener, SqliteEventListenerConfig};
+pub use crate::snapshotter::SqliteSnapshotter;
+use disintegrate::{DecisionMaker, Event, EventSourcedStateStore, SnapshotConfig, WithSnapshot};
+use disintegrate_serde::Serde;
+pub use error::Error;
+
+pub type SqliteEventId = i64;
+
+/// An alias for [`DecisionMaker`], specialized for SQLite.
+pub type SqliteDecisionMaker<E, S, SN> =
+    DecisionMaker<EventSourcedStateStore<SqliteEventId, E, SqliteEventStore<E, S>, SN>>;
+
+/// An alias for [`WithSnapshot`], specialized for SQLite.
+pub type WithSqliteSnapshot = WithSnapshot<SqliteEventId, SqliteSnapshotter>;
+
+/// Creates a decision maker specialized for SQLite.
+///
+/// # Arguments
+///
+/// - `event_store`: An instance of `SqliteEventStore`.
+/// - `snapshot_config`: The `SnapshotConfig` to be used for the snapshotting.
+///
+/// # Returns
+///
+/// A `SqliteDecisionMaker` with snapshotting configured according to the provided `snapshot_config`.
+pub fn decision_maker<
+    E: Event + Send + Sync + Clone,
+    S: Serde<E> + Clone + Sync + Send,
+    SN: SnapshotConfig + Clone,
+>(
+    event_store: SqliteEventStore<E, S>,
+    snapshot_config: SN,
+) -> SqliteDecisionMaker<E, S, SN> {
+    DecisionMaker::new(EventSourcedStateStore::new(event_store, snapshot_config))
+}
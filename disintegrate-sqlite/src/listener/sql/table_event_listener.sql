@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS event_listener (
+    id TEXT PRIMARY KEY,
+    last_processed_event_id INTEGER NOT NULL,
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,108 @@
+use std::sync::Mutex;
+
+use disintegrate::{query, PersistedEvent, StreamQuery};
+use disintegrate_serde::serde::json::Json;
+use serde::{Deserialize, Serialize};
+
+use super::*;
+use crate::testing::memory_pool;
+
+#[derive(Debug, Clone, PartialEq, Event, Serialize, Deserialize)]
+enum ShoppingCartEvent {
+    Added {
+        #[id]
+        product_id: String,
+        #[id]
+        cart_id: String,
+    },
+}
+
+fn added_event(product_id: &str, cart_id: &str) -> ShoppingCartEvent {
+    ShoppingCartEvent::Added {
+        product_id: product_id.to_string(),
+        cart_id: cart_id.to_string(),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("the read model is not available")]
+struct ReadModelError;
+
+/// Records the handled events, failing the first attempt to handle the event `fail_once`.
+struct RecordingListener {
+    query: StreamQuery<SqliteEventId, ShoppingCartEvent>,
+    handled: Arc<Mutex<Vec<SqliteEventId>>>,
+    fail_once: Mutex<Option<SqliteEventId>>,
+}
+
+#[async_trait]
+impl EventListener<SqliteEventId, ShoppingCartEvent> for RecordingListener {
+    type Error = ReadModelError;
+
+    fn id(&self) -> &'static str {
+        "carts"
+    }
+
+    fn query(&self) -> &StreamQuery<SqliteEventId, ShoppingCartEvent> {
+        &self.query
+    }
+
+    async fn handle(
+        &self,
+        event: PersistedEvent<SqliteEventId, ShoppingCartEvent>,
+    ) -> Result<(), Self::Error> {
+        let mut fail_once = self.fail_once.lock().unwrap();
+        if *fail_once == Some(event.id()) {
+            *fail_once = None;
+            return Err(ReadModelError);
+        }
+        self.handled.lock().unwrap().push(event.id());
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn it_handles_the_events_retrying_the_failed_ones() {
+    let pool = memory_pool().await;
+    let event_store = SqliteEventStore::<ShoppingCartEvent, Json<ShoppingCartEvent>>::new(
+        pool.clone(),
+        Json::default(),
+    )
+    .await
+    .unwrap();
+    event_store
+        .append(
+            vec![
+                added_event("p1", "c1"),
+                added_event("p2", "c2"),
+                added_event("p3", "c1"),
+            ],
+            query!(ShoppingCartEvent),
+            0,
+        )
+        .await
+        .unwrap();
+    let handled = Arc::new(Mutex::new(vec![]));
+    let listener = RecordingListener {
+        query: query!(ShoppingCartEvent; cart_id == "c1"),
+        handled: handled.clone(),
+        fail_once: Mutex::new(Some(3)),
+    };
+
+    SqliteEventListener::builder(event_store)
+        .register(
+            listener,
+            SqliteEventListenerConfig::poller(Duration::from_millis(10)),
+        )
+        .start_with_shutdown(tokio::time::sleep(Duration::from_millis(200)))
+        .await
+        .unwrap();
+
+    assert_eq!(*handled.lock().unwrap(), vec![1, 3]);
+    let last_processed_event_id: SqliteEventId =
+        sqlx::query_scalar("SELECT last_processed_event_id FROM event_listener WHERE id = 'carts'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(last_processed_event_id, 3);
+}
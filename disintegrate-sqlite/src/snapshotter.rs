@@ -0,0 +1,149 @@
+//! # SQLite Snapshotter
+//!
+//! This module provides an implementation of the `Snapshotter` trait using SQLite as the underlying storage.
+//! It allows storing and retrieving snapshots from a SQLite database.
+use async_trait::async_trait;
+use disintegrate::{
+    BoxDynError, Event, IntoState, MissingIdentifier, StateSnapshotter, StreamQuery,
+};
+use disintegrate::{StatePart, StateQuery};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::Row;
+use sqlx::SqlitePool;
+
+use crate::{Error, SqliteEventId};
+
+#[cfg(test)]
+mod tests;
+
+/// SQLite implementation for the `Snapshotter` trait.
+///
+/// The `SqliteSnapshotter` struct implements the `Snapshotter` trait for SQLite databases.
+/// It allows for storing and retrieving snapshots of `StateQuery` from a SQLite database.
+#[derive(Debug, Clone)]
+pub struct SqliteSnapshotter {
+    pool: SqlitePool,
+    every: u64,
+}
+
+impl SqliteSnapshotter {
+    /// Creates and initializes a new instance of `SqliteSnapshotter` with the specified SQLite connection pool and snapshot frequency.
+    ///
+    /// # Arguments
+    ///
+    /// - `pool`: A SQLite connection pool (`SqlitePool`) representing the database connection.
+    /// - `every`: The frequency of snapshot creation, specified as the number of events between consecutive snapshots.
+    ///
+    /// # Returns
+    ///
+    /// A new `SqliteSnapshotter` instance.
+    pub async fn new(pool: SqlitePool, every: u64) -> Result<Self, Error> {
+        setup(&pool).await?;
+        Ok(Self::new_uninitialized(pool, every))
+    }
+
+    /// Creates a new instance of `SqliteSnapshotter` with the specified SQLite connection pool and snapshot frequency.
+    ///
+    /// This constructor does not initialize the database. If you need to initialize the database,
+    /// use `SqliteSnapshotter::new` instead.
+    ///
+    /// # Arguments
+    ///
+    /// - `pool`: A SQLite connection pool (`SqlitePool`) representing the database connection.
+    /// - `every`: The frequency of snapshot creation, defined as the number of events between consecutive snapshots.
+    ///
+    /// # Returns
+    ///
+    /// A new `SqliteSnapshotter` instance.
+    pub fn new_uninitialized(pool: SqlitePool, every: u64) -> Self {
+        Self { pool, every }
+    }
+}
+
+#[async_trait]
+impl StateSnapshotter<SqliteEventId> for SqliteSnapshotter {
+    async fn load_snapshot<S>(
+        &self,
+        default: StatePart<SqliteEventId, S>,
+    ) -> StatePart<SqliteEventId, S>
+    where
+        S: Send + Sync + DeserializeOwned + StateQuery + 'static,
+    {
+        let query = query_key(&default.query());
+        let stored_snapshot =
+            sqlx::query("SELECT payload, version FROM snapshot WHERE name = ? AND query = ?")
+                .bind(S::NAME)
+                .bind(&query)
+                .fetch_one(&self.pool)
+                .await;
+        if let Ok(row) = stored_snapshot {
+            // A snapshot that cannot be deserialized is ignored: the state is rebuilt from the events.
+            if let Ok(payload) = serde_json::from_str(row.get(0)) {
+                return StatePart::new(row.get(1), payload);
+            }
+        }
+
+        default
+    }
+
+    async fn store_snapshot<S>(
+        &self,
+        state: &StatePart<SqliteEventId, S>,
+    ) -> Result<(), BoxDynError>
+    where
+        S: Send + Sync + Serialize + StateQuery + 'static,
+    {
+        if state.applied_events() <= self.every {
+            return Ok(());
+        }
+        let query = query_key(&state.query());
+        let version = state.version();
+        let payload = serde_json::to_string(&state.clone().into_state())?;
+        sqlx::query("INSERT INTO snapshot (name, query, payload, version) VALUES (?1, ?2, ?3, ?4) ON CONFLICT(name, query) DO UPDATE SET payload = ?3, version = ?4 WHERE snapshot.version < ?4")
+        .bind(S::NAME)
+        .bind(query)
+        .bind(payload)
+        .bind(version)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+fn query_key<E: Event + Clone>(query: &StreamQuery<SqliteEventId, E>) -> String {
+    let mut result = String::new();
+    for f in query.filters() {
+        let excluded_events = if let Some(excluded_events) = f.excluded_events() {
+            format!("-{}", excluded_events.join(","))
+        } else {
+            "".to_string()
+        };
+        let missing_identifier = match f.missing_identifier() {
+            MissingIdentifier::Match => "",
+            MissingIdentifier::NoMatch => "|no_match",
+        };
+        result += &format!(
+            "({}|{}{}|{}{})",
+            f.origin(),
+            f.events().join(","),
+            excluded_events,
+            f.identifiers()
+                .iter()
+                .map(|(k, v)| format!("{k}={v}"))
+                .chain(f.conditions().iter().map(ToString::to_string))
+                .collect::<Vec<_>>()
+                .join(","),
+            missing_identifier
+        );
+    }
+    result
+}
+
+pub async fn setup(pool: &SqlitePool) -> Result<(), Error> {
+    sqlx::query(include_str!("snapshotter/sql/table_snapshot.sql"))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS snapshot (
+    name TEXT NOT NULL,
+    query TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    payload TEXT NOT NULL,
+    inserted_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (name, query)
+);
@@ -0,0 +1,92 @@
+use disintegrate::{query, Event, EventId, IntoState, IntoStatePart, PersistedEvent, StateMutate};
+use serde::Deserialize;
+
+use super::*;
+use crate::testing::memory_pool;
+
+#[derive(Debug, Clone, PartialEq, Event, Serialize, Deserialize)]
+enum CartEvent {
+    ItemAdded {
+        #[id]
+        cart_id: String,
+        #[id]
+        item_id: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CartState {
+    cart_id: String,
+    items: Vec<String>,
+}
+
+impl CartState {
+    fn new<const N: usize>(cart_id: &str, items: [&str; N]) -> Self {
+        Self {
+            cart_id: cart_id.to_string(),
+            items: items.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+impl StateQuery for CartState {
+    const NAME: &'static str = "cart-state";
+    type Event = CartEvent;
+
+    fn query<ID: EventId>(&self) -> disintegrate::StreamQuery<ID, Self::Event> {
+        query!(CartEvent; cart_id == self.cart_id)
+    }
+}
+
+impl StateMutate for CartState {
+    fn mutate(&mut self, event: Self::Event) {
+        match event {
+            CartEvent::ItemAdded { item_id, .. } => self.items.push(item_id),
+        }
+    }
+}
+
+#[tokio::test]
+async fn it_stores_and_loads_snapshots() {
+    let snapshotter = SqliteSnapshotter::new(memory_pool().await, 0)
+        .await
+        .unwrap();
+    let mut state = CartState::new("c1", []).into_state_part();
+    state.mutate_part(PersistedEvent::new(
+        1,
+        CartEvent::ItemAdded {
+            cart_id: "c1".to_string(),
+            item_id: "p1".to_string(),
+        },
+    ));
+
+    snapshotter.store_snapshot(&state).await.unwrap();
+    let loaded_state = snapshotter
+        .load_snapshot(CartState::new("c1", []).into_state_part())
+        .await;
+
+    assert_eq!(loaded_state.version(), 1);
+    assert_eq!(loaded_state.into_state(), CartState::new("c1", ["p1"]));
+}
+
+#[tokio::test]
+async fn it_ignores_the_snapshots_that_cannot_be_deserialized() {
+    let pool = memory_pool().await;
+    let snapshotter = SqliteSnapshotter::new(pool.clone(), 0).await.unwrap();
+    let default_state = CartState::new("c1", []);
+    sqlx::query("INSERT INTO snapshot (name, query, payload, version) VALUES (?, ?, ?, ?)")
+        .bind(CartState::NAME)
+        .bind(query_key(&default_state.query::<SqliteEventId>()))
+        .bind(r#"{"cart_id": "c1"}"#)
+        .bind(3)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let loaded_state = snapshotter
+        .load_snapshot(default_state.clone().into_state_part())
+        .await;
+
+    assert_eq!(loaded_state.version(), 0);
+    assert_eq!(loaded_state.into_state(), default_state);
+}
@@ -0,0 +1,15 @@
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+
+/// Creates a pool of an in-memory database.
+///
+/// Every connection to `sqlite::memory:` opens a different database, so the pool keeps a single connection open.
+pub(crate) async fn memory_pool() -> SqlitePool {
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap()
+}
@@ -0,0 +1,300 @@
+//! Application service layer
+//!
+//! An application service is the entry point of the use cases of a bounded context: it executes the
+//! decisions through a decision maker and answers the queries from the read models. `Application` wires
+//! the two together and maps their errors into a single `ApplicationError`, so that the transport layer,
+//! e.g. an HTTP or gRPC handler, can translate the failures consistently across the services.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let application = Application::new(decision_maker, read_model);
+//!
+//! application.execute(OpenAccount::new(account_id)).await?;
+//! let account = application
+//!     .query(|read_model| read_model.account_by_id(account_id))
+//!     .await?;
+//! ```
+use std::future::Future;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::decision::Error as DecisionError;
+use crate::{
+    BoxDynError, Decision, DecisionLayer, DecisionMaker, DecisionTracer, EventId, IntoState,
+    IntoStatePart, LoadState, MultiState, PersistDecision, PersistedEvent,
+};
+
+/// Represents the errors of the application services.
+#[derive(Debug, thiserror::Error)]
+pub enum ApplicationError {
+    /// The decision was rejected before the state was loaded, e.g. because of a malformed input.
+    #[error("invalid decision: {0}")]
+    Invalid(#[source] BoxDynError),
+    /// The decision was rejected by the business rules.
+    #[error("domain error: {0}")]
+    Domain(#[source] BoxDynError),
+    /// The decision was rejected by a `DecisionLayer`, e.g. because the user is not authorized.
+    #[error("rejected decision: {0}")]
+    Rejected(#[source] BoxDynError),
+    /// The state could not be loaded or the changes could not be persisted, e.g. because of a
+    /// concurrency conflict or an unavailable event store.
+    #[error("state store error: {0}")]
+    StateStore(#[source] BoxDynError),
+    /// A read model could not answer a query.
+    #[error("read model error: {0}")]
+    ReadModel(#[source] BoxDynError),
+}
+
+impl<DE> From<DecisionError<DE>> for ApplicationError
+where
+    DE: std::error::Error + Send + Sync + 'static,
+{
+    fn from(error: DecisionError<DE>) -> Self {
+        match error {
+            DecisionError::Invalid(err) => ApplicationError::Invalid(err),
+            DecisionError::Domain(err) => ApplicationError::Domain(Box::new(err)),
+            DecisionError::Rejected(err) => ApplicationError::Rejected(err),
+            DecisionError::EventStore(err) | DecisionError::StateStore(err) => {
+                ApplicationError::StateStore(err)
+            }
+        }
+    }
+}
+
+/// Executes the decisions of an application service.
+///
+/// It is implemented by `DecisionMaker` and `ShardedDecisionMaker`, so that an `Application` can be
+/// built on top of either of them.
+#[async_trait]
+pub trait MakeDecision<ID: EventId, D: Decision> {
+    /// Makes the given business decision, persisting the resulting events in the event store.
+    ///
+    /// Refer to [`DecisionMaker::make`] for details.
+    async fn make_decision(
+        &self,
+        decision: D,
+    ) -> Result<Vec<PersistedEvent<ID, D::Event>>, DecisionError<D::Error>>;
+}
+
+#[async_trait]
+impl<SS, T, L, ID, D> MakeDecision<ID, D> for DecisionMaker<SS, T, L>
+where
+    ID: EventId,
+    SS: LoadState<ID, D::StateQuery, D::Event>
+        + PersistDecision<ID, D::StateQuery, D::Event>
+        + Send
+        + Sync,
+    T: DecisionTracer<ID, D::StateQuery, D::Event>,
+    L: DecisionLayer,
+    D: Decision + 'static,
+    D::Event: 'static,
+    D::StateQuery: Serialize + DeserializeOwned + IntoStatePart<ID, D::StateQuery> + 'static,
+    <D::StateQuery as IntoStatePart<ID, D::StateQuery>>::Target: Send
+        + Sync
+        + Serialize
+        + DeserializeOwned
+        + IntoState<D::StateQuery>
+        + MultiState<ID, D::Event>,
+    D::Error: 'static,
+{
+    async fn make_decision(
+        &self,
+        decision: D,
+    ) -> Result<Vec<PersistedEvent<ID, D::Event>>, DecisionError<D::Error>> {
+        self.make(decision).await
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[async_trait]
+impl<SS, T, L, ID, D> MakeDecision<ID, D> for crate::ShardedDecisionMaker<SS, T, L>
+where
+    ID: EventId,
+    SS: LoadState<ID, D::StateQuery, D::Event>
+        + PersistDecision<ID, D::StateQuery, D::Event>
+        + Send
+        + Sync,
+    T: DecisionTracer<ID, D::StateQuery, D::Event>,
+    L: DecisionLayer,
+    D: Decision + 'static,
+    D::Event: 'static,
+    D::StateQuery: Serialize + DeserializeOwned + IntoStatePart<ID, D::StateQuery> + 'static,
+    <D::StateQuery as IntoStatePart<ID, D::StateQuery>>::Target: Send
+        + Sync
+        + Serialize
+        + DeserializeOwned
+        + IntoState<D::StateQuery>
+        + MultiState<ID, D::Event>,
+    D::Error: 'static,
+{
+    async fn make_decision(
+        &self,
+        decision: D,
+    ) -> Result<Vec<PersistedEvent<ID, D::Event>>, DecisionError<D::Error>> {
+        self.make(decision).await
+    }
+}
+
+/// An application service, executing the decisions through a decision maker and answering the queries
+/// from the read models.
+#[derive(Debug, Clone)]
+pub struct Application<DM, R> {
+    decision_maker: DM,
+    read_model: R,
+}
+
+impl<DM, R> Application<DM, R> {
+    /// Creates a new instance of `Application`.
+    ///
+    /// # Parameters
+    ///
+    /// - `decision_maker`: The decision maker executing the decisions, e.g. a `DecisionMaker` or a `ShardedDecisionMaker`.
+    /// - `read_model`: The read models answering the queries, e.g. a repository or a tuple of repositories.
+    pub fn new(decision_maker: DM, read_model: R) -> Self {
+        Self {
+            decision_maker,
+            read_model,
+        }
+    }
+
+    /// Returns the decision maker of the application.
+    pub fn decision_maker(&self) -> &DM {
+        &self.decision_maker
+    }
+
+    /// Returns the read models of the application.
+    pub fn read_model(&self) -> &R {
+        &self.read_model
+    }
+
+    /// Executes the given decision.
+    ///
+    /// # Parameters
+    ///
+    /// - `decision`: The business decision to be executed.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the persisted events if the decision succeeds, or an `ApplicationError`
+    /// describing why the decision failed.
+    pub async fn execute<ID, D>(
+        &self,
+        decision: D,
+    ) -> Result<Vec<PersistedEvent<ID, D::Event>>, ApplicationError>
+    where
+        ID: EventId,
+        D: Decision,
+        D::Error: std::error::Error + 'static,
+        DM: MakeDecision<ID, D>,
+    {
+        Ok(self.decision_maker.make_decision(decision).await?)
+    }
+
+    /// Answers a query from the read models.
+    ///
+    /// # Parameters
+    ///
+    /// - `query`: A function running the query on the read models.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the answer of the query, or an `ApplicationError::ReadModel` if the
+    /// query fails.
+    pub async fn query<'a, F, Fut, T, QE>(&'a self, query: F) -> Result<T, ApplicationError>
+    where
+        F: FnOnce(&'a R) -> Fut,
+        Fut: Future<Output = Result<T, QE>>,
+        QE: Into<BoxDynError>,
+    {
+        query(&self.read_model)
+            .await
+            .map_err(|err| ApplicationError::ReadModel(err.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::*;
+    use crate::{EventSourcedStateStore, NoSnapshot, StreamQuery};
+
+    struct AddItem;
+
+    impl Decision for AddItem {
+        type Event = ShoppingCartEvent;
+        type StateQuery = Cart;
+        type Error = CartError;
+
+        fn state_query(&self) -> Cart {
+            cart("c1", [])
+        }
+
+        fn process(&self, state: &Cart) -> Result<Vec<ShoppingCartEvent>, CartError> {
+            if state.items.contains(&"p1".to_string()) {
+                return Err(CartError("the item is already in the cart".to_string()));
+            }
+            Ok(vec![item_added_event("p1", "c1")])
+        }
+    }
+
+    struct Items(Vec<String>);
+
+    impl Items {
+        async fn count(&self) -> Result<usize, BoxDynError> {
+            Ok(self.0.len())
+        }
+
+        async fn fail(&self) -> Result<usize, BoxDynError> {
+            Err("read model unavailable".into())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_executes_a_decision() {
+        let mut database = MockDatabase::new();
+        database
+            .expect_stream()
+            .once()
+            .return_once(|_| event_stream::<ShoppingCartEvent>([]));
+        database.expect_append().once().return_once(
+            |_, _: StreamQuery<i64, ShoppingCartEvent>, _| {
+                vec![PersistedEvent::new(1, item_added_event("p1", "c1"))]
+            },
+        );
+        let state_store = EventSourcedStateStore::new(MockEventStore::new(database), NoSnapshot);
+        let application = Application::new(DecisionMaker::new(state_store), Items(vec![]));
+
+        let events = application.execute(AddItem).await.unwrap();
+
+        assert_eq!(
+            events,
+            vec![PersistedEvent::new(1, item_added_event("p1", "c1"))]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_maps_the_errors_of_the_decisions_and_the_queries() {
+        let mut database = MockDatabase::new();
+        database
+            .expect_stream()
+            .once()
+            .return_once(|_| event_stream([item_added_event("p1", "c1")]));
+        let state_store = EventSourcedStateStore::new(MockEventStore::new(database), NoSnapshot);
+        let application = Application::new(
+            DecisionMaker::new(state_store),
+            Items(vec!["p1".to_string()]),
+        );
+
+        let result = application.execute(AddItem).await;
+        assert!(matches!(result, Err(ApplicationError::Domain(_))));
+
+        assert_eq!(application.query(Items::count).await.unwrap(), 1);
+        assert!(matches!(
+            application.query(Items::fail).await,
+            Err(ApplicationError::ReadModel(_))
+        ));
+    }
+}
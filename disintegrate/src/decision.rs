@@ -1,4 +1,26 @@
 //! A Decision serves as a building block for developing the business logic of an application.
+mod composite;
+mod context;
+#[cfg(feature = "tokio")]
+mod recurring;
+#[cfg(feature = "tokio")]
+mod retry;
+#[cfg(feature = "tokio")]
+mod sharded;
+
+pub use context::DecisionContext;
+#[cfg(feature = "tokio")]
+pub use recurring::{
+    CronError, CronSchedule, InMemoryRunStore, RecurringDecision, RecurringRunStore, Schedule,
+};
+#[cfg(feature = "load-test")]
+pub(crate) use retry::is_in_memory_conflict;
+#[cfg(feature = "tokio")]
+pub use retry::RetryPolicy;
+#[cfg(feature = "tokio")]
+pub use sharded::ShardedDecisionMaker;
+
+use std::time::{Duration, Instant};
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -7,7 +29,7 @@ use crate::event::EventId;
 use crate::state_store::LoadedState;
 use crate::stream_query::StreamQuery;
 use crate::{event::Event, PersistedEvent};
-use crate::{BoxDynError, IntoState, IntoStatePart, LoadState, MultiState};
+use crate::{BoxDynError, IntoState, IntoStatePart, LoadState, Metadata, MultiState};
 
 /// Represents a business decision taken from a state built upon the occurred events.
 pub trait Decision: Send + Sync {
@@ -36,6 +58,15 @@ pub trait Decision: Send + Sync {
         None
     }
 
+    /// Validates the decision input before the state is loaded.
+    ///
+    /// Malformed decisions, e.g. a command with a negative amount, are rejected with an `Invalid` error
+    /// without paying the cost of loading the state. The errors of the `validator` crate can be returned directly:
+    /// `Ok(validator::Validate::validate(self)?)`.
+    fn validate(&self) -> Result<(), BoxDynError> {
+        Ok(())
+    }
+
     /// Evaluates the decision based on the mutated state, ensuring that all business rules
     /// are verified against the current state. This method generates a series of events
     /// that capture the changes made by the decision, allowing the results to be
@@ -54,7 +85,164 @@ pub trait Decision: Send + Sync {
     fn process(&self, state: &Self::StateQuery) -> Result<Vec<Self::Event>, Self::Error>;
 }
 
+/// The reason why a traced decision failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecisionFailure {
+    /// The decision was rejected by the domain logic.
+    Domain,
+    /// The decision changes could not be persisted, e.g. because of a concurrency conflict.
+    Persist(String),
+}
+
+/// A diagnostic bundle captured when a decision fails.
+///
+/// It contains everything needed to understand why a decision was rejected: the query used to
+/// hydrate the state, the hydrated state, its version, the validation query and the events the
+/// decision attempted to persist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecisionTrace<ID: EventId, S, E: Event + Clone> {
+    /// The stream query used to hydrate the decision state.
+    pub state_query: StreamQuery<ID, E>,
+    /// The hydrated state the decision was processed against.
+    pub state: S,
+    /// The version of the hydrated state.
+    pub version: ID,
+    /// The validation query of the decision, if any.
+    pub validation_query: Option<StreamQuery<ID, E>>,
+    /// The events the decision attempted to persist. Empty when the domain rejected the decision.
+    pub events: Vec<E>,
+    /// The reason of the failure.
+    pub failure: DecisionFailure,
+}
+
+/// A sink receiving the diagnostic bundles of failed decisions.
+///
+/// Implementations can write the traces to a log, a database table or any other storage
+/// suitable for later inspection.
+#[async_trait::async_trait]
+pub trait DecisionTraceSink<ID: EventId, E: Event + Clone>: Send + Sync {
+    /// Returns `true` if the sink is collecting traces.
+    ///
+    /// When the sink is disabled, the `DecisionMaker` does not pay the cost of building the traces.
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    /// Records the trace of a failed decision.
+    async fn record<S>(&self, trace: DecisionTrace<ID, S, E>)
+    where
+        S: Serialize + Send + Sync + 'static;
+}
+
+/// Indicates that the decision tracing is disabled.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoDecisionTrace;
+
+#[async_trait::async_trait]
+impl<ID, E, T> DecisionTraceSink<ID, E> for std::sync::Arc<T>
+where
+    ID: EventId,
+    E: Event + Clone + Send + Sync + 'static,
+    T: DecisionTraceSink<ID, E>,
+{
+    fn enabled(&self) -> bool {
+        (**self).enabled()
+    }
+
+    async fn record<S>(&self, trace: DecisionTrace<ID, S, E>)
+    where
+        S: Serialize + Send + Sync + 'static,
+    {
+        (**self).record(trace).await
+    }
+}
+
+/// Traces the failed decisions of the states of type `S`.
+///
+/// It is implemented by `NoDecisionTrace` for any state, and by `SinkTracer` for the states that can be cloned
+/// and serialized, so only the decision makers with a trace sink require them.
+#[async_trait::async_trait]
+pub trait DecisionTracer<ID: EventId, S, E: Event + Clone>: Send + Sync {
+    /// Returns a copy of the state, kept to trace the decision if its changes cannot be persisted, or `None`
+    /// if the decisions are not traced.
+    fn keep(&self, state: &S) -> Option<S>;
+
+    /// Records the trace of a failed decision.
+    async fn trace(
+        &self,
+        state: &S,
+        version: ID,
+        validation_query: Option<StreamQuery<ID, E>>,
+        events: Vec<E>,
+        failure: DecisionFailure,
+    );
+}
+
+#[async_trait::async_trait]
+impl<ID, S, E> DecisionTracer<ID, S, E> for NoDecisionTrace
+where
+    ID: EventId,
+    S: Sync,
+    E: Event + Clone + Send + Sync + 'static,
+{
+    fn keep(&self, _state: &S) -> Option<S> {
+        None
+    }
+
+    async fn trace(
+        &self,
+        _state: &S,
+        _version: ID,
+        _validation_query: Option<StreamQuery<ID, E>>,
+        _events: Vec<E>,
+        _failure: DecisionFailure,
+    ) {
+    }
+}
+
+/// Traces the failed decisions to a `DecisionTraceSink`, see `DecisionMaker::with_trace_sink`.
+#[derive(Debug, Clone)]
+pub struct SinkTracer<T>(T);
+
+#[async_trait::async_trait]
+impl<ID, S, E, T> DecisionTracer<ID, S, E> for SinkTracer<T>
+where
+    ID: EventId,
+    S: Clone + Send + Sync + Serialize + IntoStatePart<ID, S> + 'static,
+    <S as IntoStatePart<ID, S>>::Target: Send + MultiState<ID, E>,
+    E: Event + Clone + Send + Sync + 'static,
+    T: DecisionTraceSink<ID, E>,
+{
+    fn keep(&self, state: &S) -> Option<S> {
+        self.0.enabled().then(|| state.clone())
+    }
+
+    async fn trace(
+        &self,
+        state: &S,
+        version: ID,
+        validation_query: Option<StreamQuery<ID, E>>,
+        events: Vec<E>,
+        failure: DecisionFailure,
+    ) {
+        if !self.0.enabled() {
+            return;
+        }
+        self.0
+            .record(DecisionTrace {
+                state_query: state.clone().into_state_part().query_all(),
+                state: state.clone(),
+                version,
+                validation_query,
+                events,
+                failure,
+            })
+            .await;
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
 pub enum Error<DE> {
     #[error("event store error: {0}")]
     EventStore(#[source] BoxDynError),
@@ -62,12 +250,222 @@ pub enum Error<DE> {
     StateStore(#[source] BoxDynError),
     #[error("domain error: {0}")]
     Domain(#[source] DE),
+    #[error("invalid decision: {0}")]
+    Invalid(#[source] BoxDynError),
+    #[error("rejected decision: {0}")]
+    Rejected(#[source] BoxDynError),
+}
+
+#[cfg(any(feature = "otel", feature = "metrics"))]
+impl<DE> Error<DE> {
+    /// Returns the outcome of the failed decision recorded in its span and in its metrics.
+    fn outcome(&self) -> &'static str {
+        match self {
+            Error::EventStore(_) => "event_store_error",
+            Error::StateStore(_) => "state_store_error",
+            Error::Domain(_) => "domain_error",
+            Error::Invalid(_) => "invalid",
+            Error::Rejected(_) => "rejected",
+        }
+    }
+}
+
+/// The refusal of a decision by a `DecisionLayer`, e.g. when the user is not authorized.
+///
+/// A hook of a layer returning a `Refusal` rejects the decision with a `Rejected` error, while any other
+/// error is a failure of the layer itself, returned as an `EventStore` error.
+#[derive(thiserror::Error, Debug)]
+#[error("{0}")]
+pub struct Refusal(BoxDynError);
+
+impl Refusal {
+    /// Creates a new `Refusal`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - The reason why the decision is refused.
+    pub fn new(reason: impl Into<BoxDynError>) -> Self {
+        Self(reason.into())
+    }
+}
+
+/// Maps the error of a hook of a layer: a `Refusal` rejects the decision, any other error is a failure of
+/// the layer.
+fn layer_error<DE>(err: BoxDynError) -> Error<DE> {
+    match err.downcast::<Refusal>() {
+        Ok(refusal) => Error::Rejected(refusal.0),
+        Err(err) => Error::EventStore(err),
+    }
+}
+
+/// A layer wrapping the decisions made by a `DecisionMaker`, to add cross-cutting concerns such as
+/// logging, metrics, authorization checks or retries on concurrency conflicts.
+///
+/// All the hooks have a default implementation, so a layer implements only the ones it needs.
+#[async_trait::async_trait]
+pub trait DecisionLayer: Send + Sync {
+    /// Called after the decision has been validated, before its state is loaded.
+    ///
+    /// A `Refusal` rejects the decision with a `Rejected` error, e.g. when the user is not authorized, while any
+    /// other error fails the decision with an `EventStore` error.
+    async fn before_decision<D>(&self, _decision: &D) -> Result<(), BoxDynError>
+    where
+        D: Decision + 'static,
+    {
+        Ok(())
+    }
+
+    /// Called before the state of the decision is loaded, to add the metadata of its events, e.g. the user
+    /// making the decision or the correlation ID of the request.
+    ///
+    /// # Parameters
+    ///
+    /// - `decision`: The decision being made.
+    /// - `metadata`: The metadata of the events, including the ones given to `DecisionMaker::make_with_metadata`.
+    async fn metadata<D>(&self, _decision: &D, _metadata: &mut Metadata)
+    where
+        D: Decision + 'static,
+    {
+    }
+
+    /// Called with the events produced by the decision, before they are persisted.
+    ///
+    /// A `Refusal` rejects the decision with a `Rejected` error, while any other error fails the decision with
+    /// an `EventStore` error. In both cases, the events are not persisted.
+    ///
+    /// # Parameters
+    ///
+    /// - `decision`: The decision being made.
+    /// - `version`: The version of the state the decision was processed against.
+    /// - `events`: The events produced by the decision.
+    async fn before_persist<D, ID>(
+        &self,
+        _decision: &D,
+        _version: ID,
+        _events: &[D::Event],
+    ) -> Result<(), BoxDynError>
+    where
+        D: Decision + 'static,
+        ID: EventId,
+    {
+        Ok(())
+    }
+
+    /// Returns `true` if the decision should be made again, from a freshly loaded state, after a state store
+    /// error, e.g. a concurrency conflict.
+    ///
+    /// # Parameters
+    ///
+    /// - `decision`: The decision being made.
+    /// - `error`: The state store error.
+    /// - `attempt`: The number of attempts made so far, starting from 1.
+    async fn retry<D>(&self, _decision: &D, _error: &BoxDynError, _attempt: u32) -> bool
+    where
+        D: Decision + 'static,
+    {
+        false
+    }
+
+    /// Called with the outcome of the decision, including the decisions rejected by the validation or by a layer.
+    ///
+    /// # Parameters
+    ///
+    /// - `decision`: The decision that was made.
+    /// - `outcome`: The persisted events, or the error of the decision.
+    /// - `elapsed`: The time taken by the decision, including the retries.
+    async fn after_decision<D, ID>(
+        &self,
+        _decision: &D,
+        _outcome: Result<&[PersistedEvent<ID, D::Event>], &Error<D::Error>>,
+        _elapsed: Duration,
+    ) where
+        D: Decision + 'static,
+        ID: EventId,
+    {
+    }
+}
+
+/// Indicates that the decisions are not wrapped by any layer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoDecisionLayer;
+
+#[async_trait::async_trait]
+impl DecisionLayer for NoDecisionLayer {}
+
+/// Two layers applied one inside the other, built by `DecisionMaker::with_layer`.
+///
+/// The `before` hooks of the outer layer run first and its `after_decision` hook runs last.
+/// A decision is retried if any of the layers asks for it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LayerStack<Inner, Outer> {
+    inner: Inner,
+    outer: Outer,
+}
+
+#[async_trait::async_trait]
+impl<Inner, Outer> DecisionLayer for LayerStack<Inner, Outer>
+where
+    Inner: DecisionLayer,
+    Outer: DecisionLayer,
+{
+    async fn before_decision<D>(&self, decision: &D) -> Result<(), BoxDynError>
+    where
+        D: Decision + 'static,
+    {
+        self.outer.before_decision(decision).await?;
+        self.inner.before_decision(decision).await
+    }
+
+    async fn metadata<D>(&self, decision: &D, metadata: &mut Metadata)
+    where
+        D: Decision + 'static,
+    {
+        self.outer.metadata(decision, metadata).await;
+        self.inner.metadata(decision, metadata).await;
+    }
+
+    async fn before_persist<D, ID>(
+        &self,
+        decision: &D,
+        version: ID,
+        events: &[D::Event],
+    ) -> Result<(), BoxDynError>
+    where
+        D: Decision + 'static,
+        ID: EventId,
+    {
+        self.outer.before_persist(decision, version, events).await?;
+        self.inner.before_persist(decision, version, events).await
+    }
+
+    async fn retry<D>(&self, decision: &D, error: &BoxDynError, attempt: u32) -> bool
+    where
+        D: Decision + 'static,
+    {
+        self.inner.retry(decision, error, attempt).await
+            || self.outer.retry(decision, error, attempt).await
+    }
+
+    async fn after_decision<D, ID>(
+        &self,
+        decision: &D,
+        outcome: Result<&[PersistedEvent<ID, D::Event>], &Error<D::Error>>,
+        elapsed: Duration,
+    ) where
+        D: Decision + 'static,
+        ID: EventId,
+    {
+        self.inner.after_decision(decision, outcome, elapsed).await;
+        self.outer.after_decision(decision, outcome, elapsed).await;
+    }
 }
 
 /// The `DecisionMaker` struct is responsible for executing and persisting business decisions.
 #[derive(Clone)]
-pub struct DecisionMaker<SS> {
+pub struct DecisionMaker<SS, T = NoDecisionTrace, L = NoDecisionLayer> {
     state_store: SS,
+    trace_sink: T,
+    layer: L,
 }
 
 impl<SS> DecisionMaker<SS> {
@@ -78,7 +476,59 @@ impl<SS> DecisionMaker<SS> {
     /// - `state_store`: The state store backend used by the `DecisionMaker` to load the current state
     ///   and persist the decision.
     pub fn new(state_store: SS) -> Self {
-        Self { state_store }
+        Self {
+            state_store,
+            trace_sink: NoDecisionTrace,
+            layer: NoDecisionLayer,
+        }
+    }
+}
+
+impl<SS, T, L> DecisionMaker<SS, T, L> {
+    /// Sets the sink receiving the diagnostic traces of failed decisions.
+    ///
+    /// # Parameters
+    ///
+    /// - `trace_sink`: The sink where the traces of the failed decisions are written.
+    pub fn with_trace_sink<U>(self, trace_sink: U) -> DecisionMaker<SS, SinkTracer<U>, L> {
+        DecisionMaker {
+            state_store: self.state_store,
+            trace_sink: SinkTracer(trace_sink),
+            layer: self.layer,
+        }
+    }
+
+    /// Makes the decisions failed because of a concurrency conflict again, from a freshly loaded state.
+    ///
+    /// The policy is added as the outermost layer, see `with_layer`.
+    ///
+    /// # Parameters
+    ///
+    /// - `retry_policy`: The policy defining the attempts of a decision and the backoff between them.
+    #[cfg(feature = "tokio")]
+    pub fn with_retry_policy(
+        self,
+        retry_policy: RetryPolicy,
+    ) -> DecisionMaker<SS, T, LayerStack<L, RetryPolicy>> {
+        self.with_layer(retry_policy)
+    }
+
+    /// Wraps the decisions with a layer.
+    ///
+    /// The layers can be stacked: the last added layer is the outermost one.
+    ///
+    /// # Parameters
+    ///
+    /// - `layer`: The layer wrapping the decisions.
+    pub fn with_layer<U>(self, layer: U) -> DecisionMaker<SS, T, LayerStack<L, U>> {
+        DecisionMaker {
+            state_store: self.state_store,
+            trace_sink: self.trace_sink,
+            layer: LayerStack {
+                inner: self.layer,
+                outer: layer,
+            },
+        }
     }
 
     /// Makes the given business decision, persisting the resulting events in the event store.
@@ -100,7 +550,224 @@ impl<SS> DecisionMaker<SS> {
         ID: EventId,
         E: Event + Clone + Sync + Send + 'static,
         SS: LoadState<ID, S, E> + PersistDecision<ID, S, E>,
-        D: Decision<StateQuery = S, Event = E>,
+        T: DecisionTracer<ID, S, E>,
+        L: DecisionLayer,
+        D: Decision<StateQuery = S, Event = E> + 'static,
+        S: Send + Sync + Serialize + DeserializeOwned + IntoStatePart<ID, S>,
+        <S as IntoStatePart<ID, S>>::Target:
+            Send + Sync + Serialize + DeserializeOwned + IntoState<S> + MultiState<ID, E>,
+        <D as Decision>::Error: 'static,
+    {
+        self.make_in_context(decision, DecisionContext::current().unwrap_or_default())
+            .await
+    }
+
+    /// Makes the given business decision, persisting the resulting events in the event store together with
+    /// their metadata.
+    ///
+    /// The given metadata are added to the ones of the current `DecisionContext`, replacing the values of the
+    /// same keys. The layers can add further metadata, see `DecisionLayer::metadata`.
+    ///
+    /// # Parameters
+    ///
+    /// - `decision`: The business decision to be executed, implementing the `Decision` trait.
+    /// - `metadata`: The metadata of the events, e.g. the correlation ID of the request.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the persisted events, carrying the metadata, or the encountered error.
+    pub async fn make_with_metadata<D, S, ID, E>(
+        &self,
+        decision: D,
+        metadata: Metadata,
+    ) -> Result<Vec<PersistedEvent<ID, E>>, Error<D::Error>>
+    where
+        ID: EventId,
+        E: Event + Clone + Sync + Send + 'static,
+        SS: LoadState<ID, S, E> + PersistDecision<ID, S, E>,
+        T: DecisionTracer<ID, S, E>,
+        L: DecisionLayer,
+        D: Decision<StateQuery = S, Event = E> + 'static,
+        S: Send + Sync + Serialize + DeserializeOwned + IntoStatePart<ID, S>,
+        <S as IntoStatePart<ID, S>>::Target:
+            Send + Sync + Serialize + DeserializeOwned + IntoState<S> + MultiState<ID, E>,
+        <D as Decision>::Error: 'static,
+    {
+        let context = DecisionContext::current().unwrap_or_default();
+        self.make_stamped(decision, Metadata::from(context).merge(metadata))
+            .await
+    }
+
+    /// Makes the given business decision in a context, stamping the resulting events with the correlation and
+    /// causation IDs of the context.
+    ///
+    /// Unlike `make`, which uses the context of the current task set by `DecisionContext::scope`, the context is
+    /// given explicitly.
+    ///
+    /// # Parameters
+    ///
+    /// - `decision`: The business decision to be executed, implementing the `Decision` trait.
+    /// - `context`: The context of the decision, e.g. the command or the event triggering it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the persisted events, carrying the metadata of the context, or the encountered error.
+    pub async fn make_in_context<D, S, ID, E>(
+        &self,
+        decision: D,
+        context: DecisionContext,
+    ) -> Result<Vec<PersistedEvent<ID, E>>, Error<D::Error>>
+    where
+        ID: EventId,
+        E: Event + Clone + Sync + Send + 'static,
+        SS: LoadState<ID, S, E> + PersistDecision<ID, S, E>,
+        T: DecisionTracer<ID, S, E>,
+        L: DecisionLayer,
+        D: Decision<StateQuery = S, Event = E> + 'static,
+        S: Send + Sync + Serialize + DeserializeOwned + IntoStatePart<ID, S>,
+        <S as IntoStatePart<ID, S>>::Target:
+            Send + Sync + Serialize + DeserializeOwned + IntoState<S> + MultiState<ID, E>,
+        <D as Decision>::Error: 'static,
+    {
+        self.make_stamped(decision, context.into()).await
+    }
+
+    async fn make_stamped<D, S, ID, E>(
+        &self,
+        decision: D,
+        metadata: Metadata,
+    ) -> Result<Vec<PersistedEvent<ID, E>>, Error<D::Error>>
+    where
+        ID: EventId,
+        E: Event + Clone + Sync + Send + 'static,
+        SS: LoadState<ID, S, E> + PersistDecision<ID, S, E>,
+        T: DecisionTracer<ID, S, E>,
+        L: DecisionLayer,
+        D: Decision<StateQuery = S, Event = E> + 'static,
+        S: Send + Sync + Serialize + DeserializeOwned + IntoStatePart<ID, S>,
+        <S as IntoStatePart<ID, S>>::Target:
+            Send + Sync + Serialize + DeserializeOwned + IntoState<S> + MultiState<ID, E>,
+        <D as Decision>::Error: 'static,
+    {
+        let started_at = Instant::now();
+        #[cfg(feature = "otel")]
+        let span = tracing::info_span!(
+            "disintegrate.decision.make",
+            disintegrate.decision.name = std::any::type_name::<D>(),
+            disintegrate.events = tracing::field::Empty,
+            disintegrate.outcome = tracing::field::Empty,
+        );
+        let make = self.make_layered(&decision, metadata);
+        #[cfg(feature = "otel")]
+        let make = tracing::Instrument::instrument(make, span.clone());
+        let result = make.await;
+        #[cfg(feature = "otel")]
+        match &result {
+            Ok(events) => {
+                span.record("disintegrate.events", events.len());
+                span.record("disintegrate.outcome", "ok");
+            }
+            Err(err) => {
+                span.record("disintegrate.outcome", err.outcome());
+            }
+        }
+        #[cfg(feature = "metrics")]
+        {
+            let name = std::any::type_name::<D>();
+            let outcome = match &result {
+                Ok(_) => "ok",
+                Err(err) => err.outcome(),
+            };
+            metrics::counter!("disintegrate_decisions_total", "decision" => name, "outcome" => outcome)
+                .increment(1);
+            metrics::histogram!("disintegrate_decision_duration_seconds", "decision" => name)
+                .record(started_at.elapsed());
+        }
+        self.layer
+            .after_decision(&decision, result.as_deref(), started_at.elapsed())
+            .await;
+        result
+    }
+
+    /// Makes several business decisions atomically, persisting the events of all of them in a single append.
+    ///
+    /// The decisions, given as a tuple of up to five decisions, are processed against their respective states,
+    /// loaded together. If any decision is rejected no event is persisted, and the append fails with a concurrency
+    /// error if an event matching the validation query of any decision has been appended in the meantime.
+    ///
+    /// # Parameters
+    ///
+    /// - `decisions`: The tuple of decisions to be executed, sharing the same event and error types.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the events of all the decisions, in the order of the decisions, or the first
+    /// encountered error.
+    pub async fn make_all<D, S, ID, E>(
+        &self,
+        decisions: D,
+    ) -> Result<Vec<PersistedEvent<ID, E>>, Error<D::Error>>
+    where
+        ID: EventId,
+        E: Event + Clone + Sync + Send + 'static,
+        SS: LoadState<ID, S, E> + PersistDecision<ID, S, E>,
+        T: DecisionTracer<ID, S, E>,
+        L: DecisionLayer,
+        D: Decision<StateQuery = S, Event = E> + 'static,
+        S: Send + Sync + Serialize + DeserializeOwned + IntoStatePart<ID, S>,
+        <S as IntoStatePart<ID, S>>::Target:
+            Send + Sync + Serialize + DeserializeOwned + IntoState<S> + MultiState<ID, E>,
+        <D as Decision>::Error: 'static,
+    {
+        self.make(decisions).await
+    }
+
+    async fn make_layered<D, S, ID, E>(
+        &self,
+        decision: &D,
+        mut metadata: Metadata,
+    ) -> Result<Vec<PersistedEvent<ID, E>>, Error<D::Error>>
+    where
+        ID: EventId,
+        E: Event + Clone + Sync + Send + 'static,
+        SS: LoadState<ID, S, E> + PersistDecision<ID, S, E>,
+        T: DecisionTracer<ID, S, E>,
+        L: DecisionLayer,
+        D: Decision<StateQuery = S, Event = E> + 'static,
+        S: Send + Sync + Serialize + DeserializeOwned + IntoStatePart<ID, S>,
+        <S as IntoStatePart<ID, S>>::Target:
+            Send + Sync + Serialize + DeserializeOwned + IntoState<S> + MultiState<ID, E>,
+        <D as Decision>::Error: 'static,
+    {
+        decision.validate().map_err(Error::Invalid)?;
+        self.layer
+            .before_decision(decision)
+            .await
+            .map_err(layer_error)?;
+        self.layer.metadata(decision, &mut metadata).await;
+        let mut attempt = 1;
+        loop {
+            match self.make_once(decision, &metadata).await {
+                Err(Error::StateStore(err)) if self.layer.retry(decision, &err, attempt).await => {
+                    attempt += 1
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn make_once<D, S, ID, E>(
+        &self,
+        decision: &D,
+        metadata: &Metadata,
+    ) -> Result<Vec<PersistedEvent<ID, E>>, Error<D::Error>>
+    where
+        ID: EventId,
+        E: Event + Clone + Sync + Send + 'static,
+        SS: LoadState<ID, S, E> + PersistDecision<ID, S, E>,
+        T: DecisionTracer<ID, S, E>,
+        L: DecisionLayer,
+        D: Decision<StateQuery = S, Event = E> + 'static,
         S: Send + Sync + Serialize + DeserializeOwned + IntoStatePart<ID, S>,
         <S as IntoStatePart<ID, S>>::Target:
             Send + Sync + Serialize + DeserializeOwned + IntoState<S> + MultiState<ID, E>,
@@ -111,18 +778,56 @@ impl<SS> DecisionMaker<SS> {
             .load(decision.state_query())
             .await
             .map_err(Error::StateStore)?;
-        let changes = decision
-            .process(&loaded_state.state)
-            .map_err(Error::Domain)?;
-        let events = self
+        let changes = match decision.process(&loaded_state.state) {
+            Ok(changes) => changes,
+            Err(err) => {
+                self.trace_sink
+                    .trace(
+                        &loaded_state.state,
+                        loaded_state.version,
+                        decision.validation_query(),
+                        vec![],
+                        DecisionFailure::Domain,
+                    )
+                    .await;
+                return Err(Error::Domain(err));
+            }
+        };
+        let version = loaded_state.version;
+        self.layer
+            .before_persist(decision, version, &changes)
+            .await
+            .map_err(layer_error)?;
+        let attempt = self
+            .trace_sink
+            .keep(&loaded_state.state)
+            .map(|state| (state, changes.clone()));
+        let events = match self
             .state_store
             .persist(
                 loaded_state,
-                changes.into_iter().collect(),
+                changes,
+                metadata.clone(),
                 decision.validation_query(),
             )
             .await
-            .map_err(Error::StateStore)?;
+        {
+            Ok(events) => events,
+            Err(err) => {
+                if let Some((state, changes)) = attempt {
+                    self.trace_sink
+                        .trace(
+                            &state,
+                            version,
+                            decision.validation_query(),
+                            changes,
+                            DecisionFailure::Persist(err.to_string()),
+                        )
+                        .await;
+                }
+                return Err(Error::StateStore(err));
+            }
+        };
 
         Ok(events)
     }
@@ -137,6 +842,7 @@ pub trait PersistDecision<ID: EventId, S, E: Event + Clone> {
     ///
     /// - `loaded_state`: The current state loaded from the event store, used to check if the events to be persisted have been produced from a non-stale state.
     /// - `events`: A vector of events representing the changes to be stored.
+    /// - `metadata`: The metadata of the events, persisted by the event stores supporting them.
     /// - `validation_query`: An optional stream query used to validate the state before persisting changes.
     ///
     /// # Returns
@@ -146,6 +852,7 @@ pub trait PersistDecision<ID: EventId, S, E: Event + Clone> {
         &self,
         loaded_state: LoadedState<ID, S>,
         events: Vec<E>,
+        metadata: Metadata,
         validation_query: Option<StreamQuery<ID, E>>,
     ) -> Result<Vec<PersistedEvent<ID, E>>, BoxDynError>;
 }
@@ -196,4 +903,428 @@ mod test {
 
         decision_maker.make(mock_add_item).await.unwrap();
     }
+
+    struct RemoveItems {
+        quantity: u32,
+    }
+
+    impl Decision for RemoveItems {
+        type Event = ShoppingCartEvent;
+        type StateQuery = Cart;
+        type Error = CartError;
+
+        fn state_query(&self) -> Cart {
+            cart("c1", [])
+        }
+
+        fn validate(&self) -> Result<(), BoxDynError> {
+            if self.quantity == 0 {
+                return Err("quantity must be positive".into());
+            }
+            Ok(())
+        }
+
+        fn process(&self, _state: &Cart) -> Result<Vec<ShoppingCartEvent>, CartError> {
+            Ok(vec![item_removed_event("p1", "c1")])
+        }
+    }
+
+    #[tokio::test]
+    async fn it_rejects_an_invalid_decision_without_loading_the_state() {
+        let database = MockDatabase::new();
+        let event_store = MockEventStore::new(database);
+        let state_store = EventSourcedStateStore::new(event_store, NoSnapshot);
+        let decision_maker = DecisionMaker::new(state_store);
+
+        let result = decision_maker.make(RemoveItems { quantity: 0 }).await;
+
+        assert!(
+            matches!(result, Err(super::Error::Invalid(err)) if err.to_string() == "quantity must be positive")
+        );
+    }
+
+    #[derive(Default)]
+    struct RecordingTraceSink {
+        traces: std::sync::Mutex<Vec<(i64, Vec<ShoppingCartEvent>, DecisionFailure)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl DecisionTraceSink<i64, ShoppingCartEvent> for RecordingTraceSink {
+        async fn record<S>(&self, trace: DecisionTrace<i64, S, ShoppingCartEvent>)
+        where
+            S: Serialize + Send + Sync + 'static,
+        {
+            self.traces
+                .lock()
+                .unwrap()
+                .push((trace.version, trace.events, trace.failure));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_traces_a_decision_rejected_by_the_domain() {
+        let mut database = MockDatabase::new();
+
+        database
+            .expect_stream()
+            .once()
+            .return_once(|_| event_stream([item_added_event("p1", "c1")]));
+
+        let mut mock_add_item = MockDecision::new();
+        mock_add_item
+            .expect_state_query()
+            .once()
+            .return_once(|| cart("c1", []));
+        mock_add_item
+            .expect_validation_query()
+            .once()
+            .return_once(|| Option::<StreamQuery<i64, ShoppingCartEvent>>::None);
+        mock_add_item
+            .expect_process()
+            .once()
+            .return_once(|_| Err(CartError("Some error".to_string())));
+
+        let event_store = MockEventStore::new(database);
+        let state_store = EventSourcedStateStore::new(event_store, NoSnapshot);
+        let trace_sink = std::sync::Arc::new(RecordingTraceSink::default());
+        let decision_maker =
+            DecisionMaker::new(state_store).with_trace_sink(std::sync::Arc::clone(&trace_sink));
+
+        assert!(decision_maker.make(mock_add_item).await.is_err());
+        assert_eq!(
+            *trace_sink.traces.lock().unwrap(),
+            vec![(1, vec![], DecisionFailure::Domain)]
+        );
+    }
+
+    /// A state store whose persists fail, as on a concurrency conflict.
+    struct ConflictingStateStore<SS>(SS);
+
+    #[async_trait::async_trait]
+    impl<SS> LoadState<i64, Cart, ShoppingCartEvent> for ConflictingStateStore<SS>
+    where
+        SS: LoadState<i64, Cart, ShoppingCartEvent> + Sync,
+    {
+        async fn load(&self, state_query: Cart) -> Result<LoadedState<i64, Cart>, BoxDynError> {
+            self.0.load(state_query).await
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl<SS: Sync> PersistDecision<i64, Cart, ShoppingCartEvent> for ConflictingStateStore<SS> {
+        async fn persist(
+            &self,
+            _loaded_state: LoadedState<i64, Cart>,
+            _events: Vec<ShoppingCartEvent>,
+            _metadata: Metadata,
+            _validation_query: Option<StreamQuery<i64, ShoppingCartEvent>>,
+        ) -> Result<Vec<PersistedEvent<i64, ShoppingCartEvent>>, BoxDynError> {
+            Err("concurrency conflict".into())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_traces_a_decision_whose_changes_cannot_be_persisted() {
+        let mut database = MockDatabase::new();
+        database
+            .expect_stream()
+            .once()
+            .return_once(|_| event_stream([item_added_event("p1", "c1")]));
+
+        let event_store = MockEventStore::new(database);
+        let state_store =
+            ConflictingStateStore(EventSourcedStateStore::new(event_store, NoSnapshot));
+        let trace_sink = std::sync::Arc::new(RecordingTraceSink::default());
+        let decision_maker =
+            DecisionMaker::new(state_store).with_trace_sink(std::sync::Arc::clone(&trace_sink));
+
+        assert!(decision_maker
+            .make(RemoveItems { quantity: 1 })
+            .await
+            .is_err());
+        assert_eq!(
+            *trace_sink.traces.lock().unwrap(),
+            vec![(
+                1,
+                vec![item_removed_event("p1", "c1")],
+                DecisionFailure::Persist("concurrency conflict".to_string())
+            )]
+        );
+    }
+
+    #[derive(Default)]
+    struct RecordingLayer {
+        name: &'static str,
+        reject: bool,
+        fail: bool,
+        retries: u32,
+        log: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl DecisionLayer for RecordingLayer {
+        async fn before_decision<D>(&self, _decision: &D) -> Result<(), BoxDynError>
+        where
+            D: Decision + 'static,
+        {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} before_decision", self.name));
+            Ok(())
+        }
+
+        async fn before_persist<D, ID>(
+            &self,
+            _decision: &D,
+            version: ID,
+            events: &[D::Event],
+        ) -> Result<(), BoxDynError>
+        where
+            D: Decision + 'static,
+            ID: EventId,
+        {
+            let events: Vec<_> = events.iter().map(|event| event.name()).collect();
+            self.log.lock().unwrap().push(format!(
+                "{} before_persist {version} {}",
+                self.name,
+                events.join(",")
+            ));
+            if self.reject {
+                return Err(Refusal::new("not authorized").into());
+            }
+            if self.fail {
+                return Err("the authorization service is unavailable".into());
+            }
+            Ok(())
+        }
+
+        async fn retry<D>(&self, _decision: &D, _error: &BoxDynError, attempt: u32) -> bool
+        where
+            D: Decision + 'static,
+        {
+            attempt <= self.retries
+        }
+
+        async fn after_decision<D, ID>(
+            &self,
+            _decision: &D,
+            outcome: Result<&[PersistedEvent<ID, D::Event>], &super::Error<D::Error>>,
+            _elapsed: Duration,
+        ) where
+            D: Decision + 'static,
+            ID: EventId,
+        {
+            self.log.lock().unwrap().push(format!(
+                "{} after_decision {}",
+                self.name,
+                if outcome.is_ok() { "ok" } else { "err" }
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_wraps_a_decision_with_the_layers() {
+        let mut database = MockDatabase::new();
+        database
+            .expect_stream()
+            .once()
+            .return_once(|_| event_stream([item_added_event("p1", "c1")]));
+        database.expect_append().once().return_once(
+            |_, _: StreamQuery<i64, ShoppingCartEvent>, _| {
+                vec![PersistedEvent::new(2, item_removed_event("p1", "c1"))]
+            },
+        );
+
+        let event_store = MockEventStore::new(database);
+        let state_store = EventSourcedStateStore::new(event_store, NoSnapshot);
+        let log = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let decision_maker = DecisionMaker::new(state_store)
+            .with_layer(RecordingLayer {
+                name: "inner",
+                log: log.clone(),
+                ..Default::default()
+            })
+            .with_layer(RecordingLayer {
+                name: "outer",
+                log: log.clone(),
+                ..Default::default()
+            });
+
+        decision_maker
+            .make(RemoveItems { quantity: 1 })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                "outer before_decision",
+                "inner before_decision",
+                "outer before_persist 1 ItemRemoved",
+                "inner before_persist 1 ItemRemoved",
+                "inner after_decision ok",
+                "outer after_decision ok",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_does_not_persist_the_events_rejected_by_a_layer() {
+        let mut database = MockDatabase::new();
+        database
+            .expect_stream()
+            .once()
+            .return_once(|_| event_stream([item_added_event("p1", "c1")]));
+        database.expect_append::<ShoppingCartEvent>().never();
+
+        let event_store = MockEventStore::new(database);
+        let state_store = EventSourcedStateStore::new(event_store, NoSnapshot);
+        let log = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let decision_maker = DecisionMaker::new(state_store).with_layer(RecordingLayer {
+            name: "auth",
+            reject: true,
+            log: log.clone(),
+            ..Default::default()
+        });
+
+        let result = decision_maker.make(RemoveItems { quantity: 1 }).await;
+
+        assert!(
+            matches!(result, Err(super::Error::Rejected(err)) if err.to_string() == "not authorized")
+        );
+        assert_eq!(
+            log.lock().unwrap().last().unwrap(),
+            "auth after_decision err"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_tells_the_failures_of_a_layer_apart_from_its_refusals() {
+        let mut database = MockDatabase::new();
+        database
+            .expect_stream()
+            .once()
+            .return_once(|_| event_stream([item_added_event("p1", "c1")]));
+        database.expect_append::<ShoppingCartEvent>().never();
+
+        let event_store = MockEventStore::new(database);
+        let state_store = EventSourcedStateStore::new(event_store, NoSnapshot);
+        let decision_maker = DecisionMaker::new(state_store).with_layer(RecordingLayer {
+            name: "auth",
+            fail: true,
+            ..Default::default()
+        });
+
+        let result = decision_maker.make(RemoveItems { quantity: 1 }).await;
+
+        assert!(
+            matches!(result, Err(super::Error::EventStore(err)) if err.to_string() == "the authorization service is unavailable")
+        );
+    }
+
+    #[tokio::test]
+    async fn it_retries_a_decision_when_a_layer_asks_for_it() {
+        let mut database = MockDatabase::new();
+        let mut loads = 0;
+        database.expect_stream().times(2).returning(move |_| {
+            loads += 1;
+            if loads == 1 {
+                vec![Err(crate::utils::tests::Error)]
+            } else {
+                event_stream([item_added_event("p1", "c1")])
+            }
+        });
+        database.expect_append().once().return_once(
+            |_, _: StreamQuery<i64, ShoppingCartEvent>, _| {
+                vec![PersistedEvent::new(2, item_removed_event("p1", "c1"))]
+            },
+        );
+
+        let event_store = MockEventStore::new(database);
+        let state_store = EventSourcedStateStore::new(event_store, NoSnapshot);
+        let decision_maker = DecisionMaker::new(state_store).with_layer(RecordingLayer {
+            name: "retry",
+            retries: 1,
+            ..Default::default()
+        });
+
+        let events = decision_maker
+            .make(RemoveItems { quantity: 1 })
+            .await
+            .unwrap();
+
+        assert_eq!(events.len(), 1);
+    }
+
+    struct UserLayer;
+
+    #[async_trait::async_trait]
+    impl DecisionLayer for UserLayer {
+        async fn metadata<D>(&self, _decision: &D, metadata: &mut Metadata)
+        where
+            D: Decision + 'static,
+        {
+            metadata.insert(crate::USER_ID, "alice");
+        }
+    }
+
+    #[tokio::test]
+    async fn it_persists_the_events_with_the_metadata_of_the_decision() {
+        let mut database = MockDatabase::new();
+        database
+            .expect_stream()
+            .once()
+            .return_once(|_| event_stream([item_added_event("p1", "c1")]));
+        database.expect_append().once().return_once(
+            |_, _: StreamQuery<i64, ShoppingCartEvent>, _| {
+                vec![PersistedEvent::new(2, item_removed_event("p1", "c1"))]
+            },
+        );
+
+        let event_store = MockEventStore::new(database);
+        let state_store = EventSourcedStateStore::new(event_store, NoSnapshot);
+        let decision_maker = DecisionMaker::new(state_store).with_layer(UserLayer);
+
+        let events = decision_maker
+            .make_with_metadata(
+                RemoveItems { quantity: 1 },
+                Metadata::new().with_correlation_id("request-1"),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            events[0].metadata(),
+            &Metadata::new()
+                .with_correlation_id("request-1")
+                .with_user_id("alice")
+        );
+    }
+
+    #[tokio::test]
+    async fn it_stamps_the_events_with_the_context_of_the_decision() {
+        let mut database = MockDatabase::new();
+        database
+            .expect_stream()
+            .once()
+            .return_once(|_| event_stream([item_added_event("p1", "c1")]));
+        database.expect_append().once().return_once(
+            |_, _: StreamQuery<i64, ShoppingCartEvent>, _| {
+                vec![PersistedEvent::new(2, item_removed_event("p1", "c1"))]
+            },
+        );
+
+        let event_store = MockEventStore::new(database);
+        let state_store = EventSourcedStateStore::new(event_store, NoSnapshot);
+        let decision_maker = DecisionMaker::new(state_store);
+
+        let events = DecisionContext::from_command("request-1")
+            .scope(decision_maker.make(RemoveItems { quantity: 1 }))
+            .await
+            .unwrap();
+
+        assert_eq!(events[0].metadata().correlation_id(), Some("request-1"));
+        assert_eq!(events[0].metadata().causation_id(), Some("request-1"));
+    }
 }
@@ -0,0 +1,186 @@
+//! Decisions made together in a single transaction.
+//!
+//! A tuple of decisions is a decision itself: the states of all the decisions are loaded in a single
+//! multi-state, each decision is processed against its own state, and the events of all the decisions
+//! are appended atomically, validated by the union of the validation queries of the decisions.
+use paste::paste;
+
+use super::Decision;
+use crate::event::{Event, EventId};
+use crate::stream_query::StreamQuery;
+use crate::{union, BoxDynError, StateQuery};
+
+macro_rules! impl_composite_decision {
+    (
+        [$($ty:ident),*], $last:ident
+    ) => {
+        impl<E, DE, $($ty,)* $last> Decision for ($($ty,)* $last)
+        where
+            E: Event + Clone + Send + Sync,
+            DE: Send + Sync,
+            $($ty: Decision<Event = E, Error = DE, StateQuery: StateQuery<Event: Into<E>>>,)*
+            $last: Decision<Event = E, Error = DE, StateQuery: StateQuery<Event: Into<E>>>,
+        {
+            type Event = E;
+            type StateQuery = ($($ty::StateQuery,)* $last::StateQuery);
+            type Error = DE;
+
+            fn state_query(&self) -> Self::StateQuery {
+                paste! {
+                    let ($([<decision_ $ty:lower>],)* [<decision_ $last:lower>]) = self;
+                    ($([<decision_ $ty:lower>].state_query(),)* [<decision_ $last:lower>].state_query())
+                }
+            }
+
+            fn validation_query<ID: EventId>(&self) -> Option<StreamQuery<ID, E>> {
+                paste! {
+                    let ($([<decision_ $ty:lower>],)* [<decision_ $last:lower>]) = self;
+                    Some(union!(
+                        $(validation_query::<ID, _>([<decision_ $ty:lower>]),)*
+                        validation_query::<ID, _>([<decision_ $last:lower>])
+                    ))
+                }
+            }
+
+            fn validate(&self) -> Result<(), BoxDynError> {
+                paste! {
+                    let ($([<decision_ $ty:lower>],)* [<decision_ $last:lower>]) = self;
+                    $([<decision_ $ty:lower>].validate()?;)*
+                    [<decision_ $last:lower>].validate()
+                }
+            }
+
+            fn process(&self, state: &Self::StateQuery) -> Result<Vec<E>, DE> {
+                paste! {
+                    let ($([<decision_ $ty:lower>],)* [<decision_ $last:lower>]) = self;
+                    let ($([<state_ $ty:lower>],)* [<state_ $last:lower>]) = state;
+                    let mut events = vec![];
+                    $(events.extend([<decision_ $ty:lower>].process([<state_ $ty:lower>])?);)*
+                    events.extend([<decision_ $last:lower>].process([<state_ $last:lower>])?);
+                    Ok(events)
+                }
+            }
+        }
+    }
+}
+
+impl_composite_decision!([T1], T2);
+impl_composite_decision!([T1, T2], T3);
+impl_composite_decision!([T1, T2, T3], T4);
+impl_composite_decision!([T1, T2, T3, T4], T5);
+
+/// Returns the validation query of the decision, or the query of its state if it has none.
+fn validation_query<ID, D>(decision: &D) -> StreamQuery<ID, D::Event>
+where
+    ID: EventId,
+    D: Decision<StateQuery: StateQuery<Event: Into<D::Event>>>,
+{
+    decision
+        .validation_query()
+        .unwrap_or_else(|| decision.state_query().query().cast())
+}
+
+#[cfg(test)]
+mod tests {
+    use mockall::predicate::eq;
+
+    use super::*;
+    use crate::utils::tests::*;
+    use crate::{DecisionMaker, EventSourcedStateStore, NoSnapshot, PersistedEvent};
+
+    struct AddItem {
+        cart_id: &'static str,
+        item_id: &'static str,
+    }
+
+    impl Decision for AddItem {
+        type Event = ShoppingCartEvent;
+        type StateQuery = Cart;
+        type Error = CartError;
+
+        fn state_query(&self) -> Cart {
+            cart(self.cart_id, [])
+        }
+
+        fn process(&self, state: &Cart) -> Result<Vec<ShoppingCartEvent>, CartError> {
+            if state.items.iter().any(|item| item == self.item_id) {
+                return Err(CartError(format!("{} already added", self.item_id)));
+            }
+            Ok(vec![item_added_event(self.item_id, self.cart_id)])
+        }
+    }
+
+    #[tokio::test]
+    async fn it_appends_the_events_of_all_the_decisions_at_once() {
+        let mut database = MockDatabase::new();
+        database.expect_stream().once().return_once(|_| {
+            event_stream([item_added_event("p1", "c1"), item_added_event("p2", "c2")])
+        });
+        database
+            .expect_append()
+            .with(
+                eq(vec![
+                    item_added_event("p2", "c1"),
+                    item_added_event("p1", "c2"),
+                ]),
+                eq(union!(&cart("c1", []), &cart("c2", [])).change_origin(0)),
+                eq(2),
+            )
+            .once()
+            .return_once(|events, _: StreamQuery<i64, ShoppingCartEvent>, _| {
+                events
+                    .into_iter()
+                    .zip(3..)
+                    .map(|(event, id)| PersistedEvent::new(id, event))
+                    .collect()
+            });
+
+        let state_store = EventSourcedStateStore::new(MockEventStore::new(database), NoSnapshot);
+        let decision_maker = DecisionMaker::new(state_store);
+
+        let events = decision_maker
+            .make_all((
+                AddItem {
+                    cart_id: "c1",
+                    item_id: "p2",
+                },
+                AddItem {
+                    cart_id: "c2",
+                    item_id: "p1",
+                },
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(events.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn it_appends_nothing_when_a_decision_is_rejected() {
+        let mut database = MockDatabase::new();
+        database.expect_stream().once().return_once(|_| {
+            event_stream([item_added_event("p1", "c1"), item_added_event("p2", "c2")])
+        });
+        database.expect_append::<ShoppingCartEvent>().never();
+
+        let state_store = EventSourcedStateStore::new(MockEventStore::new(database), NoSnapshot);
+        let decision_maker = DecisionMaker::new(state_store);
+
+        let result = decision_maker
+            .make_all((
+                AddItem {
+                    cart_id: "c1",
+                    item_id: "p2",
+                },
+                AddItem {
+                    cart_id: "c2",
+                    item_id: "p2",
+                },
+            ))
+            .await;
+
+        assert!(
+            matches!(result, Err(crate::DecisionError::Domain(err)) if err == CartError("p2 already added".to_string()))
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! The context of the decisions, propagating the correlation and causation IDs.
+//!
+//! A `DecisionContext` describes what triggered a chain of decisions: a command received by the
+//! application or an event handled by an event listener. The decisions made within the context
+//! stamp their events with the metadata derived from it, so the events of a business transaction
+//! can be traced across decisions and listeners.
+use std::cell::RefCell;
+use std::future::{poll_fn, Future};
+use std::pin::pin;
+
+use crate::{event::EventId, Event, Metadata, PersistedEvent};
+
+thread_local! {
+    /// The context of the future being polled by the thread, set by `DecisionContext::scope`.
+    static CURRENT: RefCell<Option<DecisionContext>> = const { RefCell::new(None) };
+}
+
+/// Restores the context of the enclosing scope when the poll of a scoped future ends, even by a panic,
+/// taking back the context of the scope.
+struct ScopeGuard<'a> {
+    scope: &'a mut Option<DecisionContext>,
+    enclosing: Option<DecisionContext>,
+}
+
+impl Drop for ScopeGuard<'_> {
+    fn drop(&mut self) {
+        *self.scope = CURRENT.with(|current| current.replace(self.enclosing.take()));
+    }
+}
+
+/// The context in which the decisions are made.
+///
+/// The context can be given explicitly to `DecisionMaker::make_in_context`, or set for a whole task with
+/// `scope`: the `DecisionMaker` stamps the events of the decisions made in the scope with the metadata
+/// of the context. The event listeners run each handler in the context of the handled event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecisionContext {
+    metadata: Metadata,
+}
+
+impl DecisionContext {
+    /// Creates an empty context.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the context of a command, starting a business transaction.
+    ///
+    /// The command ID is both the correlation ID and the causation ID of the events.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_id` - The ID of the command, e.g. the ID of the request.
+    pub fn from_command(command_id: impl Into<String>) -> Self {
+        let command_id = command_id.into();
+        Self {
+            metadata: Metadata::new()
+                .with_correlation_id(command_id.clone())
+                .with_causation_id(command_id),
+        }
+    }
+
+    /// Creates the context of the decisions triggered by an event.
+    ///
+    /// The event is the cause of the events, which inherit its correlation ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event triggering the decisions.
+    pub fn caused_by<ID: EventId, E: Event>(event: &PersistedEvent<ID, E>) -> Self {
+        Self {
+            metadata: Metadata::caused_by(event),
+        }
+    }
+
+    /// Sets the ID of the user making the decisions.
+    pub fn with_user_id(self, user_id: impl Into<String>) -> Self {
+        Self {
+            metadata: self.metadata.with_user_id(user_id),
+        }
+    }
+
+    /// Sets a custom key of the metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the metadata.
+    /// * `value` - The value of the key.
+    pub fn with(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            metadata: self.metadata.with(key, value),
+        }
+    }
+
+    /// Returns the metadata stamped on the events of the decisions.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Returns the context of the current task, if any.
+    pub fn current() -> Option<Self> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Runs a future within the context.
+    ///
+    /// The context is set while the future is polled, whatever the runtime, so the futures spawned by
+    /// the future run outside of it.
+    ///
+    /// # Arguments
+    ///
+    /// * `future` - The future making the decisions, e.g. a command handler.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut scope = Some(self);
+        poll_fn(|cx| {
+            let enclosing = CURRENT.with(|current| current.replace(scope.take()));
+            let _guard = ScopeGuard {
+                scope: &mut scope,
+                enclosing,
+            };
+            future.as_mut().poll(cx)
+        })
+        .await
+    }
+}
+
+impl From<DecisionContext> for Metadata {
+    fn from(context: DecisionContext) -> Self {
+        context.metadata
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::item_added_event;
+
+    #[tokio::test]
+    async fn it_exposes_the_context_within_its_scope() {
+        let context = DecisionContext::from_command("request-1").with_user_id("alice");
+
+        let current = context
+            .clone()
+            .scope(async { DecisionContext::current() })
+            .await;
+
+        assert_eq!(current, Some(context));
+        assert_eq!(DecisionContext::current(), None);
+    }
+
+    #[tokio::test]
+    async fn it_restores_the_enclosing_context_after_a_nested_scope() {
+        let outer = DecisionContext::from_command("request-1");
+        let inner = DecisionContext::from_command("request-2");
+
+        let (nested, restored) = outer
+            .clone()
+            .scope(async {
+                let nested = inner
+                    .clone()
+                    .scope(async {
+                        tokio::task::yield_now().await;
+                        DecisionContext::current()
+                    })
+                    .await;
+                (nested, DecisionContext::current())
+            })
+            .await;
+
+        assert_eq!(nested, Some(inner));
+        assert_eq!(restored, Some(outer));
+        assert_eq!(DecisionContext::current(), None);
+    }
+
+    #[test]
+    fn it_derives_the_context_from_the_triggering_event() {
+        let event = PersistedEvent::new(7, item_added_event("p1", "c1"))
+            .with_metadata(Metadata::new().with_correlation_id("request-1"));
+
+        let context = DecisionContext::caused_by(&event);
+
+        assert_eq!(context.metadata().correlation_id(), Some("request-1"));
+        assert_eq!(context.metadata().causation_id(), Some("7"));
+    }
+}
@@ -0,0 +1,443 @@
+//! Recurring decisions, dispatched periodically to a set of targets.
+mod schedule;
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use futures::future::{select, Either};
+use futures::{stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{Decision, DecisionLayer, DecisionMaker, DecisionTracer, Error, PersistDecision};
+use crate::event::{Event, EventId};
+use crate::{BoxDynError, IntoState, IntoStatePart, LoadState, MultiState, PersistedEvent};
+
+pub use schedule::{CronError, CronSchedule, Schedule};
+
+/// The delay before the run store is queried again after a failure.
+const RUN_STORE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Records the runs of the recurring decisions.
+///
+/// The run store makes the schedule survive the restarts, and coordinates the replicas of an application:
+/// a run is made only by the replica that records it.
+#[async_trait]
+pub trait RecurringRunStore: Send + Sync {
+    /// Returns the time of the last run of a recurring decision, if it has ever run.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the recurring decision.
+    async fn last_run(&self, name: &str) -> Result<Option<SystemTime>, BoxDynError>;
+
+    /// Records a run of a recurring decision, if its last run is still `previous`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the recurring decision.
+    /// * `previous` - The time of the last run read by `last_run`.
+    /// * `at` - The time of the new run.
+    ///
+    /// # Returns
+    ///
+    /// True if the run has been recorded, false if another run has been recorded after `previous`.
+    async fn claim_run(
+        &self,
+        name: &str,
+        previous: Option<SystemTime>,
+        at: SystemTime,
+    ) -> Result<bool, BoxDynError>;
+}
+
+#[async_trait]
+impl<R: RecurringRunStore + ?Sized> RecurringRunStore for Arc<R> {
+    async fn last_run(&self, name: &str) -> Result<Option<SystemTime>, BoxDynError> {
+        self.as_ref().last_run(name).await
+    }
+
+    async fn claim_run(
+        &self,
+        name: &str,
+        previous: Option<SystemTime>,
+        at: SystemTime,
+    ) -> Result<bool, BoxDynError> {
+        self.as_ref().claim_run(name, previous, at).await
+    }
+}
+
+/// Records the runs of the recurring decisions in memory.
+///
+/// The runs are lost on restart and are not shared with the other processes: each replica of an application
+/// makes its own runs.
+#[derive(Debug, Default)]
+pub struct InMemoryRunStore {
+    runs: Mutex<HashMap<String, SystemTime>>,
+}
+
+#[async_trait]
+impl RecurringRunStore for InMemoryRunStore {
+    async fn last_run(&self, name: &str) -> Result<Option<SystemTime>, BoxDynError> {
+        Ok(self.runs.lock().unwrap().get(name).copied())
+    }
+
+    async fn claim_run(
+        &self,
+        name: &str,
+        previous: Option<SystemTime>,
+        at: SystemTime,
+    ) -> Result<bool, BoxDynError> {
+        let mut runs = self.runs.lock().unwrap();
+        if runs.get(name).copied() != previous {
+            return Ok(false);
+        }
+        runs.insert(name.to_string(), at);
+        Ok(true)
+    }
+}
+
+/// A decision made periodically for each target returned by a user-provided query.
+///
+/// At every run, the `targets` query enumerates the targets of the decision, e.g. the IDs of the accounts
+/// accruing interests, and the `decision` function builds the decision for each target. The decisions are
+/// dispatched to the `DecisionMaker` keeping at most `max_in_flight` decisions in progress, so that a large
+/// number of targets does not flood the event store.
+pub struct RecurringDecision<TF, DF> {
+    schedule: Schedule,
+    targets: TF,
+    decision: DF,
+    max_in_flight: usize,
+    name: String,
+    run_store: Arc<dyn RecurringRunStore>,
+}
+
+impl<TF, DF> RecurringDecision<TF, DF> {
+    /// Creates a new `RecurringDecision` made on the given schedule.
+    ///
+    /// # Parameters
+    ///
+    /// - `schedule`: The times at which the decision is made.
+    /// - `targets`: An async function returning the targets of the decision.
+    /// - `decision`: A function building the decision for a target.
+    pub fn new(schedule: Schedule, targets: TF, decision: DF) -> Self {
+        Self {
+            schedule,
+            targets,
+            decision,
+            max_in_flight: 1,
+            name: String::new(),
+            run_store: Arc::new(InMemoryRunStore::default()),
+        }
+    }
+
+    /// Creates a new `RecurringDecision` made every `period`.
+    ///
+    /// # Parameters
+    ///
+    /// - `period`: The interval between two runs.
+    /// - `targets`: An async function returning the targets of the decision.
+    /// - `decision`: A function building the decision for a target.
+    pub fn every(period: Duration, targets: TF, decision: DF) -> Self {
+        Self::new(Schedule::every(period), targets, decision)
+    }
+
+    /// Creates a new `RecurringDecision` made on a cron schedule, evaluated in UTC.
+    ///
+    /// See [`Schedule::cron`] for the syntax of the expression.
+    ///
+    /// # Parameters
+    ///
+    /// - `expression`: The cron expression, e.g. `0 2 * * *` for every night at 2:00.
+    /// - `targets`: An async function returning the targets of the decision.
+    /// - `decision`: A function building the decision for a target.
+    pub fn cron(expression: &str, targets: TF, decision: DF) -> Result<Self, CronError> {
+        Ok(Self::new(Schedule::cron(expression)?, targets, decision))
+    }
+
+    /// Records the runs in the given store instead of in memory.
+    ///
+    /// A persistent store keeps the schedule across the restarts, and makes each run only once among the
+    /// replicas sharing it.
+    ///
+    /// # Parameters
+    ///
+    /// - `name`: The name of the recurring decision in the store.
+    /// - `run_store`: The store of the runs.
+    pub fn with_run_store(
+        mut self,
+        name: &str,
+        run_store: impl RecurringRunStore + 'static,
+    ) -> Self {
+        self.name = name.to_string();
+        self.run_store = Arc::new(run_store);
+        self
+    }
+
+    /// Sets the maximum number of decisions in progress at the same time.
+    ///
+    /// # Parameters
+    ///
+    /// - `max_in_flight`: The maximum number of decisions in progress.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_in_flight` is zero.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        assert!(
+            max_in_flight > 0,
+            "the maximum number of decisions in flight must be greater than zero"
+        );
+        self.max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Enumerates the targets and makes the decision for each of them.
+    ///
+    /// # Parameters
+    ///
+    /// - `decision_maker`: The `DecisionMaker` used to make the decisions.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the outcome of the decision of each target, or an error if the targets
+    /// cannot be enumerated.
+    #[allow(clippy::type_complexity)]
+    pub async fn run_once<K, D, SS, T, L, S, ID, E, Fut>(
+        &self,
+        decision_maker: &DecisionMaker<SS, T, L>,
+    ) -> Result<Vec<Result<Vec<PersistedEvent<ID, E>>, Error<D::Error>>>, BoxDynError>
+    where
+        TF: Fn() -> Fut,
+        Fut: Future<Output = Result<Vec<K>, BoxDynError>>,
+        DF: Fn(K) -> D,
+        ID: EventId,
+        E: Event + Clone + Sync + Send + 'static,
+        SS: LoadState<ID, S, E> + PersistDecision<ID, S, E>,
+        T: DecisionTracer<ID, S, E>,
+        L: DecisionLayer,
+        D: Decision<StateQuery = S, Event = E> + 'static,
+        S: Send + Sync + Serialize + DeserializeOwned + IntoStatePart<ID, S>,
+        <S as IntoStatePart<ID, S>>::Target:
+            Send + Sync + Serialize + DeserializeOwned + IntoState<S> + MultiState<ID, E>,
+        <D as Decision>::Error: 'static,
+    {
+        let targets = (self.targets)().await?;
+        Ok(stream::iter(targets)
+            .map(|target| decision_maker.make((self.decision)(target)))
+            .buffered(self.max_in_flight)
+            .collect()
+            .await)
+    }
+
+    /// Runs the recurring decision on its schedule until the `shutdown` future completes.
+    ///
+    /// The first run is due one schedule after the last run recorded in the run store or, if the decision
+    /// has never run, after the start. A run missed while no replica was running is made once as soon as
+    /// the decision starts. Before each run, the run is claimed in the run store, so the replicas sharing
+    /// the store do not make the same run twice.
+    ///
+    /// The errors of a run, including the ones returned by the decisions, do not stop the schedule:
+    /// the next run is attempted at the next due time. Use a `DecisionTraceSink` to capture the failed decisions.
+    ///
+    /// # Parameters
+    ///
+    /// - `decision_maker`: The `DecisionMaker` used to make the decisions.
+    /// - `shutdown`: A future that represents the shutdown signal.
+    pub async fn run<K, D, SS, T, L, S, ID, E, Fut>(
+        &self,
+        decision_maker: &DecisionMaker<SS, T, L>,
+        shutdown: impl Future<Output = ()>,
+    ) where
+        TF: Fn() -> Fut,
+        Fut: Future<Output = Result<Vec<K>, BoxDynError>>,
+        DF: Fn(K) -> D,
+        ID: EventId,
+        E: Event + Clone + Sync + Send + 'static,
+        SS: LoadState<ID, S, E> + PersistDecision<ID, S, E>,
+        T: DecisionTracer<ID, S, E>,
+        L: DecisionLayer,
+        D: Decision<StateQuery = S, Event = E> + 'static,
+        S: Send + Sync + Serialize + DeserializeOwned + IntoStatePart<ID, S>,
+        <S as IntoStatePart<ID, S>>::Target:
+            Send + Sync + Serialize + DeserializeOwned + IntoState<S> + MultiState<ID, E>,
+        <D as Decision>::Error: 'static,
+    {
+        let started_at = SystemTime::now();
+        let mut shutdown = pin!(shutdown);
+        loop {
+            let next_run = pin!(async {
+                let Ok(last_run) = self.run_store.last_run(&self.name).await else {
+                    tokio::time::sleep(RUN_STORE_RETRY_DELAY).await;
+                    return;
+                };
+                let due = self
+                    .schedule
+                    .next_after(last_run.unwrap_or(started_at))
+                    .max(SystemTime::now());
+                if let Ok(wait) = due.duration_since(SystemTime::now()) {
+                    tokio::time::sleep(wait).await;
+                }
+                match self.run_store.claim_run(&self.name, last_run, due).await {
+                    Ok(true) => {
+                        let _ = self.run_once(decision_maker).await;
+                    }
+                    // another replica has made the run.
+                    Ok(false) => {}
+                    Err(_) => tokio::time::sleep(RUN_STORE_RETRY_DELAY).await,
+                }
+            });
+            if let Either::Left(_) = select(shutdown.as_mut(), next_run).await {
+                return;
+            }
+        }
+    }
+}
+
+/// Declares a [`RecurringDecision`].
+///
+/// The schedule is either a fixed period, `every`, or a cron expression evaluated in UTC, `cron`. The
+/// `max_in_flight` and `run_store` settings are optional.
+///
+/// # Panics
+///
+/// Panics if the cron expression is invalid.
+///
+/// # Examples
+///
+/// ```ignore
+/// let interest_accrual = recurring! {
+///     cron: "0 2 * * *",
+///     targets: || async { Ok(read_model.open_accounts().await?) },
+///     decision: AccrueInterest::new,
+///     max_in_flight: 8,
+///     run_store: ("interest_accrual", PgRecurringRunStore::new(pool).await?),
+/// };
+/// ```
+#[macro_export]
+macro_rules! recurring {
+    (
+        every: $period:expr,
+        targets: $targets:expr,
+        decision: $decision:expr
+        $(, max_in_flight: $max_in_flight:expr)?
+        $(, run_store: ($name:expr, $run_store:expr))?
+        $(,)?
+    ) => {
+        $crate::RecurringDecision::every($period, $targets, $decision)
+            $(.max_in_flight($max_in_flight))?
+            $(.with_run_store($name, $run_store))?
+    };
+    (
+        cron: $expression:expr,
+        targets: $targets:expr,
+        decision: $decision:expr
+        $(, max_in_flight: $max_in_flight:expr)?
+        $(, run_store: ($name:expr, $run_store:expr))?
+        $(,)?
+    ) => {
+        $crate::RecurringDecision::cron($expression, $targets, $decision)
+            .unwrap_or_else(|err| panic!("{err}"))
+            $(.max_in_flight($max_in_flight))?
+            $(.with_run_store($name, $run_store))?
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{utils::tests::*, EventSourcedStateStore, NoSnapshot, StreamQuery};
+
+    #[tokio::test]
+    async fn it_makes_the_decision_for_each_target() {
+        let mut database = MockDatabase::new();
+        database
+            .expect_stream()
+            .times(2)
+            .returning(|_| event_stream::<ShoppingCartEvent>([]));
+        database.expect_append().times(2).returning(
+            |events: Vec<ShoppingCartEvent>, _: StreamQuery<i64, ShoppingCartEvent>, _| {
+                events
+                    .into_iter()
+                    .map(|event| PersistedEvent::new(1, event))
+                    .collect()
+            },
+        );
+        let state_store = EventSourcedStateStore::new(MockEventStore::new(database), NoSnapshot);
+        let decision_maker = DecisionMaker::new(state_store);
+
+        let recurring_decision = RecurringDecision::every(
+            Duration::from_secs(60),
+            || async { Ok(vec!["c1", "c2"]) },
+            |cart_id: &'static str| {
+                let mut add_item = MockDecision::new();
+                add_item
+                    .expect_state_query()
+                    .once()
+                    .return_once(move || cart(cart_id, []));
+                add_item
+                    .expect_validation_query()
+                    .once()
+                    .return_once(|| Option::<StreamQuery<i64, ShoppingCartEvent>>::None);
+                add_item
+                    .expect_process()
+                    .once()
+                    .return_once(move |_| Ok(vec![item_added_event("p1", cart_id)]));
+                add_item
+            },
+        )
+        .max_in_flight(2);
+
+        let outcomes = recurring_decision.run_once(&decision_maker).await.unwrap();
+
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes.iter().all(Result::is_ok));
+    }
+
+    #[tokio::test]
+    async fn it_runs_on_the_schedule_once_among_the_replicas() {
+        let state_store =
+            EventSourcedStateStore::new(MockEventStore::new(MockDatabase::new()), NoSnapshot);
+        let decision_maker = DecisionMaker::new(state_store);
+        let runs = Arc::new(AtomicUsize::new(0));
+        let run_store = Arc::new(InMemoryRunStore::default());
+        let replica = || {
+            let runs = runs.clone();
+            recurring! {
+                every: Duration::from_millis(100),
+                targets: move || {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    async { Ok(Vec::<&'static str>::new()) }
+                },
+                decision: |_cart_id: &'static str| MockDecision::new(),
+                run_store: ("carts", run_store.clone()),
+            }
+        };
+
+        replica()
+            .run(
+                &decision_maker,
+                tokio::time::sleep(Duration::from_millis(50)),
+            )
+            .await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+
+        let (first, second) = (replica(), replica());
+        futures::join!(
+            first.run(
+                &decision_maker,
+                tokio::time::sleep(Duration::from_millis(250))
+            ),
+            second.run(
+                &decision_maker,
+                tokio::time::sleep(Duration::from_millis(250))
+            ),
+        );
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+}
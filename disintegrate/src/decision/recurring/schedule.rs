@@ -0,0 +1,266 @@
+//! Schedules of the recurring decisions.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The number of days searched for the next occurrence of a cron expression.
+///
+/// The longest gap between two occurrences is the one of the expressions matching only the 29th of February,
+/// which can skip a leap year at the turn of a century.
+const CRON_SEARCH_DAYS: u64 = 8 * 366;
+
+/// The times at which a recurring decision is made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// A fixed period between two runs.
+    Every(Duration),
+    /// A cron expression, evaluated in UTC.
+    Cron(CronSchedule),
+}
+
+impl Schedule {
+    /// Creates a schedule with a fixed period between two runs.
+    ///
+    /// # Arguments
+    ///
+    /// * `period` - The interval between two runs.
+    pub fn every(period: Duration) -> Self {
+        Self::Every(period)
+    }
+
+    /// Creates a schedule from a cron expression, evaluated in UTC.
+    ///
+    /// The expression has the five fields of the standard cron: minute, hour, day of the month, month and
+    /// day of the week, where Sunday is either 0 or 7. A field is `*`, a value, a range such as `1-5`, a step such
+    /// as `*/15` or `0-30/10`, or a comma separated list of them. When both the day of the month and the day of
+    /// the week are restricted, a day matching either of them is selected, as in the standard cron.
+    ///
+    /// # Arguments
+    ///
+    /// * `expression` - The cron expression, e.g. `0 2 * * *` for every night at 2:00.
+    ///
+    /// # Returns
+    ///
+    /// The schedule, or a `CronError` if the expression is invalid or never matches.
+    pub fn cron(expression: &str) -> Result<Self, CronError> {
+        CronSchedule::parse(expression).map(Self::Cron)
+    }
+
+    /// Returns the time of the first run after the given time.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The time after which the next run is searched, usually the time of the last run.
+    pub fn next_after(&self, time: SystemTime) -> SystemTime {
+        match self {
+            Self::Every(period) => time + *period,
+            Self::Cron(cron) => cron.next_after(time),
+        }
+    }
+}
+
+/// An error returned when a cron expression is invalid.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid cron expression `{expression}`: {reason}")]
+pub struct CronError {
+    expression: String,
+    reason: String,
+}
+
+/// A parsed cron expression.
+///
+/// Each field is stored as a bit set of its allowed values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    days_of_month_restricted: bool,
+    days_of_week_restricted: bool,
+}
+
+impl CronSchedule {
+    fn parse(expression: &str) -> Result<Self, CronError> {
+        let error = |reason: String| CronError {
+            expression: expression.to_string(),
+            reason,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+            return Err(error(format!("expected 5 fields, found {}", fields.len())));
+        };
+        let mut days_of_week_set = parse_field(days_of_week, 0, 7).map_err(&error)?;
+        // Sunday is either 0 or 7.
+        if days_of_week_set & (1 << 7) != 0 {
+            days_of_week_set = (days_of_week_set | 1) & !(1 << 7);
+        }
+        let schedule = Self {
+            minutes: parse_field(minutes, 0, 59).map_err(&error)?,
+            hours: parse_field(hours, 0, 23).map_err(&error)?,
+            days_of_month: parse_field(days_of_month, 1, 31).map_err(&error)?,
+            months: parse_field(months, 1, 12).map_err(&error)?,
+            days_of_week: days_of_week_set,
+            days_of_month_restricted: days_of_month != "*",
+            days_of_week_restricted: days_of_week != "*",
+        };
+        schedule
+            .next_minute(0)
+            .ok_or_else(|| error("the expression never matches".to_string()))?;
+        Ok(schedule)
+    }
+
+    fn next_after(&self, time: SystemTime) -> SystemTime {
+        let minute = time
+            .duration_since(UNIX_EPOCH)
+            .expect("the time of a schedule is after the epoch")
+            .as_secs()
+            / 60
+            + 1;
+        let next = self
+            .next_minute(minute)
+            .expect("a valid cron expression matches at least once in eight years");
+        UNIX_EPOCH + Duration::from_secs(next * 60)
+    }
+
+    /// Returns the first minute since the epoch, starting from `from`, matching the expression.
+    fn next_minute(&self, from: u64) -> Option<u64> {
+        let first_day = from / 1440;
+        (first_day..first_day + CRON_SEARCH_DAYS)
+            .filter(|day| self.matches_day(*day))
+            .find_map(|day| {
+                let start = if day == first_day { from % 1440 } else { 0 };
+                (start..1440)
+                    .find(|minute| {
+                        self.hours & (1 << (minute / 60)) != 0
+                            && self.minutes & (1 << (minute % 60)) != 0
+                    })
+                    .map(|minute| day * 1440 + minute)
+            })
+    }
+
+    fn matches_day(&self, day: u64) -> bool {
+        let (_, month, day_of_month) = civil_from_days(day);
+        // the epoch is a Thursday.
+        let day_of_week = (day + 4) % 7;
+        let day_of_month = self.days_of_month & (1 << day_of_month) != 0;
+        let day_of_week = self.days_of_week & (1 << day_of_week) != 0;
+        let day_matches = if self.days_of_month_restricted && self.days_of_week_restricted {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        };
+        self.months & (1 << month) != 0 && day_matches
+    }
+}
+
+/// Parses a field of a cron expression into the bit set of its allowed values.
+fn parse_field(field: &str, min: u64, max: u64) -> Result<u64, String> {
+    let value = |value: &str| {
+        value
+            .parse::<u64>()
+            .ok()
+            .filter(|value| (min..=max).contains(value))
+            .ok_or_else(|| format!("`{value}` is not a value between {min} and {max}"))
+    };
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u64>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| format!("`{step}` is not a valid step"))?,
+            ),
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // a single value with a step, e.g. `5/15`, runs up to the maximum.
+            None if step > 1 => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if start > end {
+            return Err(format!("`{range}` is an empty range"));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+/// Converts the number of days since the epoch into the year, the month and the day of the month.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2026-01-01T00:00:00Z, a Thursday.
+    const NEW_YEAR: u64 = 1_767_225_600;
+
+    fn next(expression: &str, after: u64) -> u64 {
+        Schedule::cron(expression)
+            .unwrap()
+            .next_after(UNIX_EPOCH + Duration::from_secs(after))
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn it_finds_the_next_occurrence_of_a_cron_expression() {
+        assert_eq!(next("* * * * *", NEW_YEAR), NEW_YEAR + 60);
+        assert_eq!(next("0 2 * * *", NEW_YEAR), NEW_YEAR + 2 * 3600);
+        assert_eq!(next("0 2 * * *", NEW_YEAR + 2 * 3600), NEW_YEAR + 26 * 3600);
+        assert_eq!(next("*/15 * * * *", NEW_YEAR + 60), NEW_YEAR + 15 * 60);
+        // the first Monday of the year is the 5th of January.
+        assert_eq!(
+            next("30 9 * * 1", NEW_YEAR),
+            NEW_YEAR + 4 * 86400 + 9 * 3600 + 1800
+        );
+        assert_eq!(next("0 0 1 3 *", NEW_YEAR), NEW_YEAR + 59 * 86400);
+        // either the 10th of the month or a Sunday, the 4th of January.
+        assert_eq!(next("0 0 10 * 7", NEW_YEAR), NEW_YEAR + 3 * 86400);
+        // the next 29th of February is in 2028.
+        assert_eq!(
+            next("0 0 29 2 *", NEW_YEAR),
+            NEW_YEAR + (365 + 365 + 59) * 86400
+        );
+    }
+
+    #[test]
+    fn it_rejects_the_invalid_cron_expressions() {
+        for expression in [
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(Schedule::cron(expression).is_err(), "{expression}");
+        }
+        assert_eq!(
+            Schedule::cron("0 0 30 2 *").unwrap_err().to_string(),
+            "invalid cron expression `0 0 30 2 *`: the expression never matches"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Retry of the decisions failed because of a concurrency conflict.
+//!
+//! When the events of the state query are appended after the state has been loaded, the event store
+//! rejects the changes of the decision. Making the decision again from a freshly loaded state usually
+//! succeeds, so the `RetryPolicy` layer reloads the state and processes the decision again, waiting
+//! for an exponential backoff between two attempts.
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{Decision, DecisionLayer};
+use crate::BoxDynError;
+
+/// The retry policy of the decisions failed because of a concurrency conflict.
+///
+/// The delay between two attempts starts from `initial_backoff` and doubles at each attempt, up to `max_backoff`.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    is_conflict: Arc<dyn Fn(&BoxDynError) -> bool + Send + Sync>,
+}
+
+impl std::fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RetryPolicy {
+    /// Creates a new `RetryPolicy`, with a backoff from 10ms up to 1s.
+    ///
+    /// The concurrency conflicts of the in-memory event store are recognized out of the box. The conflicts
+    /// of the other event stores must be recognized with `retry_when`.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_attempts` - The maximum number of attempts of a decision, including the first one.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+            is_conflict: Arc::new(is_in_memory_conflict),
+        }
+    }
+
+    /// Sets the delays between two attempts.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial_backoff` - The delay after the first failed attempt.
+    /// * `max_backoff` - The maximum delay between two attempts.
+    pub fn with_backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff.max(initial_backoff);
+        self
+    }
+
+    /// Sets how the concurrency conflicts are recognized among the errors of the state store.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_conflict` - Returns true if the error of the state store is a concurrency conflict, e.g.
+    ///   `|err| matches!(err.downcast_ref(), Some(disintegrate_postgres::Error::Concurrency))`.
+    pub fn retry_when(
+        mut self,
+        is_conflict: impl Fn(&BoxDynError) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.is_conflict = Arc::new(is_conflict);
+        self
+    }
+
+    /// Returns the maximum number of attempts of a decision.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns the delay after the given failed attempt, or `None` if the error must not be retried.
+    fn backoff(&self, error: &BoxDynError, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_attempts || !(self.is_conflict)(error) {
+            return None;
+        }
+        let factor = 2u32.saturating_pow(attempt - 1);
+        Some(
+            self.initial_backoff
+                .saturating_mul(factor)
+                .min(self.max_backoff),
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl DecisionLayer for RetryPolicy {
+    async fn retry<D>(&self, _decision: &D, error: &BoxDynError, attempt: u32) -> bool
+    where
+        D: Decision + 'static,
+    {
+        let Some(backoff) = self.backoff(error, attempt) else {
+            return false;
+        };
+        tokio::time::sleep(backoff).await;
+        true
+    }
+}
+
+#[cfg(feature = "in-memory")]
+pub(crate) fn is_in_memory_conflict(err: &BoxDynError) -> bool {
+    matches!(err.downcast_ref(), Some(crate::InMemoryError::Concurrency))
+}
+
+#[cfg(not(feature = "in-memory"))]
+pub(crate) fn is_in_memory_conflict(_err: &BoxDynError) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::*;
+    use crate::{DecisionMaker, EventSourcedStateStore, NoSnapshot, StreamQuery};
+
+    fn conflict() -> BoxDynError {
+        Box::new(Error)
+    }
+
+    #[test]
+    fn it_backs_off_the_conflicts_up_to_the_max_attempts() {
+        let policy = RetryPolicy::new(4)
+            .with_backoff(Duration::from_millis(100), Duration::from_millis(300))
+            .retry_when(|err| err.is::<Error>());
+
+        assert_eq!(
+            policy.backoff(&conflict(), 1),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            policy.backoff(&conflict(), 2),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            policy.backoff(&conflict(), 3),
+            Some(Duration::from_millis(300))
+        );
+        assert_eq!(policy.backoff(&conflict(), 4), None);
+        assert_eq!(policy.backoff(&"unavailable".into(), 1), None);
+    }
+
+    #[tokio::test]
+    async fn it_makes_a_conflicting_decision_again_from_a_fresh_state() {
+        let mut database = MockDatabase::new();
+        database
+            .expect_stream::<ShoppingCartEvent>()
+            .times(3)
+            .returning(|_| vec![Err(Error)]);
+        database.expect_append::<ShoppingCartEvent>().never();
+
+        let mut mock_add_item = MockDecision::new();
+        mock_add_item
+            .expect_state_query()
+            .times(3)
+            .returning(|| cart("c1", []));
+        mock_add_item
+            .expect_validation_query()
+            .returning(|| Option::<StreamQuery<i64, ShoppingCartEvent>>::None);
+
+        let state_store = EventSourcedStateStore::new(MockEventStore::new(database), NoSnapshot);
+        let decision_maker = DecisionMaker::new(state_store).with_retry_policy(
+            RetryPolicy::new(3)
+                .with_backoff(Duration::from_millis(1), Duration::from_millis(1))
+                .retry_when(|err| err.is::<Error>()),
+        );
+
+        let result = decision_maker.make(mock_add_item).await;
+
+        assert!(matches!(result, Err(crate::DecisionError::StateStore(_))));
+    }
+}
@@ -0,0 +1,166 @@
+//! A `DecisionMaker` executor that serializes the decisions targeting the same domain identifiers.
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::{Mutex, Semaphore};
+
+use super::{
+    Decision, DecisionLayer, DecisionMaker, DecisionTracer, Error, NoDecisionLayer, PersistDecision,
+};
+use crate::event::{Event, EventId};
+use crate::stream_query::StreamQuery;
+use crate::{IntoState, IntoStatePart, LoadState, MultiState, PersistedEvent};
+
+/// Executes decisions through a `DecisionMaker`, sharding them into lanes by the hash of their domain identifiers.
+///
+/// Each lane processes one decision at a time, so decisions targeting the same domain identifiers
+/// are serialized within the process instead of conflicting in the event store. The overall number
+/// of decisions processed concurrently is bounded by `max_concurrency`.
+#[derive(Clone)]
+pub struct ShardedDecisionMaker<SS, T, L = NoDecisionLayer> {
+    decision_maker: DecisionMaker<SS, T, L>,
+    lanes: Arc<[Mutex<()>]>,
+    concurrency: Arc<Semaphore>,
+}
+
+impl<SS, T, L> ShardedDecisionMaker<SS, T, L> {
+    /// Creates a new instance of `ShardedDecisionMaker`.
+    ///
+    /// The overall concurrency is initially bounded by the number of lanes.
+    ///
+    /// # Parameters
+    ///
+    /// - `decision_maker`: The `DecisionMaker` used to execute the decisions.
+    /// - `lanes`: The number of lanes the decisions are sharded into.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lanes` is zero.
+    pub fn new(decision_maker: DecisionMaker<SS, T, L>, lanes: usize) -> Self {
+        assert!(lanes > 0, "the number of lanes must be greater than zero");
+        Self {
+            decision_maker,
+            lanes: (0..lanes).map(|_| Mutex::new(())).collect(),
+            concurrency: Arc::new(Semaphore::new(lanes)),
+        }
+    }
+
+    /// Sets the maximum number of decisions processed concurrently across all the lanes.
+    ///
+    /// # Parameters
+    ///
+    /// - `max_concurrency`: The maximum number of concurrent decisions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_concurrency` is zero, as no decision could ever be processed.
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        assert!(
+            max_concurrency > 0,
+            "the maximum concurrency must be greater than zero"
+        );
+        self.concurrency = Arc::new(Semaphore::new(max_concurrency));
+        self
+    }
+
+    /// Makes the given business decision, waiting for its lane to be free.
+    ///
+    /// Refer to [`DecisionMaker::make`] for details.
+    pub async fn make<D, S, ID, E>(
+        &self,
+        decision: D,
+    ) -> Result<Vec<PersistedEvent<ID, E>>, Error<D::Error>>
+    where
+        ID: EventId,
+        E: Event + Clone + Sync + Send + 'static,
+        SS: LoadState<ID, S, E> + PersistDecision<ID, S, E>,
+        T: DecisionTracer<ID, S, E>,
+        L: DecisionLayer,
+        D: Decision<StateQuery = S, Event = E> + 'static,
+        S: Send + Sync + Serialize + DeserializeOwned + IntoStatePart<ID, S>,
+        <S as IntoStatePart<ID, S>>::Target:
+            Send + Sync + Serialize + DeserializeOwned + IntoState<S> + MultiState<ID, E>,
+        <D as Decision>::Error: 'static,
+    {
+        let query = decision.state_query().into_state_part().query_all();
+        let lane = &self.lanes[lane_of(&query, self.lanes.len())];
+        let _lane_guard = lane.lock().await;
+        let _permit = self
+            .concurrency
+            .acquire()
+            .await
+            .expect("the concurrency semaphore is never closed");
+        self.decision_maker.make(decision).await
+    }
+}
+
+/// Returns the lane of the query, computed from the hash of its domain identifiers.
+fn lane_of<ID: EventId, E: Event + Clone>(query: &StreamQuery<ID, E>, lanes: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    for filter in query.filters() {
+        filter.identifiers().hash(&mut hasher);
+    }
+    (hasher.finish() % lanes as u64) as usize
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{utils::tests::*, EventSourcedStateStore, NoSnapshot, StateQuery};
+
+    #[test]
+    fn it_assigns_the_same_lane_to_the_same_identifiers() {
+        let query: StreamQuery<i64, ShoppingCartEvent> = cart("c1", []).query();
+        let same_query: StreamQuery<i64, ShoppingCartEvent> = cart("c1", []).query();
+
+        assert_eq!(lane_of(&query, 16), lane_of(&same_query, 16));
+        assert!(lane_of(&query, 16) < 16);
+    }
+
+    #[tokio::test]
+    async fn it_makes_a_decision_in_its_lane() {
+        let mut database = MockDatabase::new();
+        database
+            .expect_stream()
+            .once()
+            .return_once(|_| event_stream([item_added_event("p1", "c1")]));
+        database.expect_append().once().return_once(
+            |_, _: StreamQuery<i64, ShoppingCartEvent>, _| {
+                vec![PersistedEvent::new(2, item_added_event("p2", "c1"))]
+            },
+        );
+
+        let mut mock_add_item = MockDecision::new();
+        mock_add_item
+            .expect_state_query()
+            .times(2)
+            .returning(|| cart("c1", []));
+        mock_add_item
+            .expect_validation_query()
+            .once()
+            .return_once(|| Option::<StreamQuery<i64, ShoppingCartEvent>>::None);
+        mock_add_item
+            .expect_process()
+            .once()
+            .return_once(|_| Ok(vec![item_added_event("p2", "c1")]));
+
+        let state_store = EventSourcedStateStore::new(MockEventStore::new(database), NoSnapshot);
+        let decision_maker =
+            ShardedDecisionMaker::new(DecisionMaker::new(state_store), 4).max_concurrency(2);
+
+        let events = decision_maker.make(mock_add_item).await.unwrap();
+
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "the maximum concurrency must be greater than zero")]
+    fn it_rejects_a_zero_maximum_concurrency() {
+        let state_store =
+            EventSourcedStateStore::new(MockEventStore::new(MockDatabase::new()), NoSnapshot);
+        ShardedDecisionMaker::new(DecisionMaker::new(state_store), 4).max_concurrency(0);
+    }
+}
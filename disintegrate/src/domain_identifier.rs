@@ -50,7 +50,7 @@ pub struct DomainIdentifier {
 /// A set of domain identifiers, represented as a map of `Identifier` keys and values.
 ///
 /// The `DomainIdentifierSet` struct is used to store a collection of domain identifiers.
-#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
 pub struct DomainIdentifierSet(BTreeMap<Identifier, IdentifierValue>);
 
 impl DomainIdentifierSet {
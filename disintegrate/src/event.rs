@@ -5,17 +5,50 @@
 //!
 //! The PersistedEvent struct wraps an event and contains an ID assigned by the event store. It represents
 //! an event that has been persisted in the event store.
-use crate::{domain_identifier::DomainIdentifierSet, Identifier, IdentifierType};
+use crate::{domain_identifier::DomainIdentifierSet, Identifier, IdentifierType, Metadata};
+use std::fmt::{Debug, Display};
 use std::ops::Deref;
 
+#[cfg(all(test, feature = "in-memory"))]
+mod tests;
+
 /// Represents the ID of an event.
+///
+/// The event IDs are totally ordered: an event appended after another one has a greater ID.
+/// The default value is the origin of the event stream, the ID preceding all the events, so a
+/// query starting from the origin returns all the events.
+///
+/// The trait is implemented for all the types satisfying its bounds, so custom ID types, such as
+/// 128-bit IDs, only need to derive or implement `Default`, `Copy`, `Ord`, `Debug` and `Display`.
+/// The snapshots, which store the version of the state, also require the ID to implement `Serialize`
+/// and `Deserialize`.
 pub trait EventId:
-    Default + Copy + Clone + PartialEq + Eq + Ord + PartialOrd + Send + Sync + 'static
+    Default + Copy + Clone + PartialEq + Eq + Ord + PartialOrd + Debug + Display + Send + Sync + 'static
 {
+    /// Returns the origin of the event stream, preceding all the events.
+    fn zero() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if the ID is the origin of the event stream.
+    fn is_origin(&self) -> bool {
+        *self == Self::zero()
+    }
 }
 
 impl<Id> EventId for Id where
-    Id: Default + Copy + Clone + PartialEq + Eq + Ord + PartialOrd + Send + Sync + 'static
+    Id: Default
+        + Copy
+        + Clone
+        + PartialEq
+        + Eq
+        + Ord
+        + PartialOrd
+        + Debug
+        + Display
+        + Send
+        + Sync
+        + 'static
 {
 }
 
@@ -28,12 +61,25 @@ pub struct EventInfo {
     pub name: &'static str,
     /// The domain identifiers associated with the event.
     pub domain_identifiers: &'static [&'static Identifier],
+    /// The description of the event, taken from its doc comments.
+    pub description: Option<&'static str>,
+    /// The team or the service owning the event.
+    pub owner: Option<&'static str>,
+    /// The former names of the event, still found in the persisted history after a rename.
+    pub aliases: &'static [&'static str],
+    /// The version of the event, set with `#[event(version = N)]` and increased when its payload changes.
+    pub version: u32,
 }
 
 impl EventInfo {
+    /// Returns the name of the event followed by its aliases, i.e. all the event types it is stored with.
+    pub fn event_types(&self) -> impl Iterator<Item = &'static str> {
+        std::iter::once(self.name).chain(self.aliases.iter().copied())
+    }
+
     /// Returns true if the event has the given domain identifier.
     pub fn has_domain_identifier(&self, ident: &Identifier) -> bool {
-        self.domain_identifiers.iter().any(|id| *id == ident)
+        self.domain_identifiers.contains(&ident)
     }
 }
 
@@ -64,6 +110,16 @@ impl EventSchema {
             .find(|info| info.name == name)
             .copied()
     }
+
+    /// Returns the info of the event stored with the given event type, which is either its name or one of its aliases.
+    pub fn event_info_by_type(&self, event_type: &str) -> Option<&EventInfo> {
+        self.event_info(event_type).or_else(|| {
+            self.events_info
+                .iter()
+                .find(|info| info.aliases.contains(&event_type))
+                .copied()
+        })
+    }
 }
 
 /// Represents an event in the event store.
@@ -78,21 +134,57 @@ pub trait Event {
     fn domain_identifiers(&self) -> DomainIdentifierSet;
     /// Retrieves the name of the event.
     fn name(&self) -> &'static str;
+    /// Retrieves the version of the event declared in the schema, 1 if not declared.
+    fn version(&self) -> u32 {
+        Self::SCHEMA
+            .event_info(self.name())
+            .map_or(1, |info| info.version)
+    }
 }
 
 /// Wrapper for a persisted event.
 ///
-/// It contains an ID assigned by the event store and the event itself.
-#[derive(Debug, Clone)]
+/// It contains an ID assigned by the event store, the event itself, the metadata and the version it was appended with.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PersistedEvent<ID: EventId, E: Event> {
     pub(crate) id: ID,
     pub(crate) event: E,
+    pub(crate) metadata: Metadata,
+    pub(crate) version: u32,
 }
 
 impl<ID: EventId, E: Event> PersistedEvent<ID, E> {
-    /// Creates a new `PersistedEvent` instance with the given ID and event.
+    /// Creates a new `PersistedEvent` instance with the given ID and event, without metadata,
+    /// at the current version of the event.
     pub fn new(id: ID, event: E) -> Self {
-        Self { id, event }
+        let version = event.version();
+        Self {
+            id,
+            event,
+            metadata: Metadata::default(),
+            version,
+        }
+    }
+
+    /// Sets the metadata the event was appended with.
+    pub fn with_metadata(self, metadata: Metadata) -> Self {
+        Self { metadata, ..self }
+    }
+
+    /// Returns the metadata the event was appended with.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Sets the version the event was appended with.
+    pub fn with_version(self, version: u32) -> Self {
+        Self { version, ..self }
+    }
+
+    /// Returns the version the event was appended with, which is older than the current version of the
+    /// event if its payload was upcasted.
+    pub fn version(&self) -> u32 {
+        self.version
     }
 
     /// Returns the inner event.
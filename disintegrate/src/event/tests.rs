@@ -0,0 +1,180 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::*;
+use crate::utils::tests::*;
+use crate::{
+    query, BoxDynError, EventListener, EventSourcedStateStore, EventStore, InMemoryEventStore,
+    LoadState, NoSnapshot, StatePart, StateQuery, StateSnapshotter, StreamQuery, WithSnapshot,
+};
+
+/// A 128-bit event ID, e.g. a ULID-like ID ordered by time.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct Id128(u128);
+
+impl fmt::Display for Id128 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+const FIRST_ID: u128 = 1 << 64;
+
+/// Maps the sequence numbers of the in-memory event store, starting from 1, to IDs above the range of `u64`.
+impl From<u64> for Id128 {
+    fn from(sequence: u64) -> Self {
+        Id128(FIRST_ID + u128::from(sequence) - 1)
+    }
+}
+
+async fn event_store_with(
+    events: Vec<ShoppingCartEvent>,
+) -> InMemoryEventStore<Id128, ShoppingCartEvent> {
+    let event_store = InMemoryEventStore::new();
+    event_store
+        .append(events, query!(ShoppingCartEvent), Id128::zero())
+        .await
+        .unwrap();
+    event_store
+}
+
+#[derive(Clone, Default)]
+struct InMemorySnapshotter {
+    stored_versions: Arc<Mutex<Vec<Id128>>>,
+}
+
+#[async_trait]
+impl StateSnapshotter<Id128> for InMemorySnapshotter {
+    async fn load_snapshot<S>(&self, default: StatePart<Id128, S>) -> StatePart<Id128, S>
+    where
+        S: Send + Sync + DeserializeOwned + StateQuery + 'static,
+    {
+        default
+    }
+
+    async fn store_snapshot<S>(&self, state: &StatePart<Id128, S>) -> Result<(), BoxDynError>
+    where
+        S: Send + Sync + Serialize + StateQuery + 'static,
+    {
+        self.stored_versions.lock().unwrap().push(state.version());
+        Ok(())
+    }
+}
+
+struct CartListener {
+    query: StreamQuery<Id128, ShoppingCartEvent>,
+    handled: Mutex<Vec<Id128>>,
+}
+
+#[async_trait]
+impl EventListener<Id128, ShoppingCartEvent> for CartListener {
+    type Error = Error;
+
+    fn id(&self) -> &'static str {
+        "cart_listener"
+    }
+
+    fn query(&self) -> &StreamQuery<Id128, ShoppingCartEvent> {
+        &self.query
+    }
+
+    async fn handle(&self, event: PersistedEvent<Id128, ShoppingCartEvent>) -> Result<(), Error> {
+        self.handled.lock().unwrap().push(event.id());
+        Ok(())
+    }
+}
+
+#[test]
+fn it_uses_the_default_as_origin() {
+    assert_eq!(Id128::zero(), Id128::default());
+    assert!(Id128::zero().is_origin());
+    assert!(!Id128(FIRST_ID).is_origin());
+    assert_eq!(
+        Id128(FIRST_ID).to_string(),
+        "00000000000000010000000000000000"
+    );
+}
+
+#[test]
+fn it_starts_a_stream_query_from_the_origin() {
+    let query: StreamQuery<Id128, ShoppingCartEvent> = query!(ShoppingCartEvent);
+
+    assert!(query
+        .filters()
+        .iter()
+        .all(|filter| filter.origin().is_origin()));
+}
+
+#[tokio::test]
+async fn it_streams_the_events_after_a_custom_origin() {
+    let event_store = event_store_with(vec![
+        item_added_event("p1", "c1"),
+        item_added_event("p2", "c1"),
+        item_removed_event("p1", "c1"),
+    ])
+    .await;
+
+    let query = query!(Id128(FIRST_ID) => ShoppingCartEvent; cart_id == "c1");
+    let ids: Vec<Id128> = event_store
+        .stream(&query)
+        .map(|event| event.unwrap().id())
+        .collect()
+        .await;
+
+    assert_eq!(ids, vec![Id128(FIRST_ID + 1), Id128(FIRST_ID + 2)]);
+}
+
+#[tokio::test]
+async fn it_loads_a_state_with_a_custom_event_id() {
+    let event_store = event_store_with(vec![
+        item_added_event("p1", "c1"),
+        item_added_event("p2", "c1"),
+    ])
+    .await;
+    let state_store = EventSourcedStateStore::new(event_store, NoSnapshot);
+
+    let loaded_state = state_store.load(Cart::new("c1")).await.unwrap();
+
+    assert_eq!(loaded_state.state, cart("c1", ["p1".into(), "p2".into()]));
+    assert_eq!(loaded_state.version, Id128(FIRST_ID + 1));
+}
+
+#[tokio::test]
+async fn it_snapshots_a_state_with_a_custom_event_id() {
+    let event_store = event_store_with(vec![item_added_event("p1", "c1")]).await;
+    let snapshotter = InMemorySnapshotter::default();
+    let state_store =
+        EventSourcedStateStore::new(event_store, WithSnapshot::new(snapshotter.clone()));
+
+    state_store.load(Cart::new("c1")).await.unwrap();
+
+    assert_eq!(
+        *snapshotter.stored_versions.lock().unwrap(),
+        vec![Id128(FIRST_ID)]
+    );
+}
+
+#[tokio::test]
+async fn it_streams_the_events_of_the_query_of_a_listener_with_a_custom_event_id() {
+    let event_store = event_store_with(vec![
+        item_added_event("p1", "c1"),
+        item_added_event("p2", "c2"),
+    ])
+    .await;
+    let listener = CartListener {
+        query: query!(ShoppingCartEvent; cart_id == "c2"),
+        handled: Mutex::new(vec![]),
+    };
+
+    let mut events = event_store.stream(listener.query());
+    while let Some(event) = events.next().await {
+        listener.handle(event.unwrap()).await.unwrap();
+    }
+
+    assert_eq!(*listener.handled.lock().unwrap(), vec![Id128(FIRST_ID + 1)]);
+}
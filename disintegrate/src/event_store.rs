@@ -10,11 +10,17 @@
 use crate::{
     event::{Event, EventId, PersistedEvent},
     stream_query::StreamQuery,
+    Metadata,
 };
 
 use async_trait::async_trait;
 use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use std::collections::BTreeMap;
 use std::error::Error as StdError;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
 /// An event store.
 ///
 /// This trait provides methods for streaming events and appending events to the event store.
@@ -43,6 +49,47 @@ where
         QE: TryFrom<E> + Event + 'static + Clone + Send + Sync,
         <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync;
 
+    /// Returns a page of the events matching the query, in event ID order.
+    ///
+    /// Unlike `stream`, the page does not hold a connection to the storage once it has been returned,
+    /// so a large history can be browsed one page at a time, e.g. by an administration UI. The default
+    /// implementation reads the page from `stream`, starting from the cursor.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The stream query specifying the filtering conditions.
+    /// * `cursor` - The position of the page: `Cursor::Start` for the first page, then the cursor
+    ///   returned with the previous page.
+    /// * `limit` - The maximum number of events of the page.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the events of the page and the cursor of the next page, `Cursor::End` if there
+    /// are no more events or the limit is zero, or an error.
+    async fn stream_page<QE>(
+        &self,
+        query: &StreamQuery<ID, QE>,
+        cursor: Cursor<ID>,
+        limit: usize,
+    ) -> Result<(Vec<PersistedEvent<ID, QE>>, Cursor<ID>), Self::Error>
+    where
+        QE: TryFrom<E> + Event + 'static + Clone + Send + Sync,
+        <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
+    {
+        let query = match cursor {
+            _ if limit == 0 => return Ok((vec![], Cursor::End)),
+            Cursor::Start => query.clone(),
+            Cursor::After(event_id) => query.clone().change_origin(event_id),
+            Cursor::End => return Ok((vec![], Cursor::End)),
+        };
+        let events = self
+            .stream(&query)
+            .take(limit.saturating_add(1))
+            .try_collect()
+            .await?;
+        Ok(cursor.page(events, limit))
+    }
+
     /// Appends a batch of events to the event store.
     ///
     /// # Arguments
@@ -69,4 +116,205 @@ where
     where
         E: Clone + 'async_trait,
         QE: Event + 'static + Clone + Send + Sync;
+
+    /// Appends a batch of events to the event store, together with their metadata.
+    ///
+    /// The metadata are returned with the persisted events by the streams of the event stores that persist them.
+    /// The default implementation appends the events with `append` without persisting the metadata, which are
+    /// only attached to the returned events: the event stores persisting the metadata override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - A vector of events to append to the event store.
+    /// * `metadata` - The metadata of the appended events.
+    /// * `query` - The stream query associated with the appended events.
+    /// * `last_event_id` - The ID of the last event in the event stream that was queried before appending.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a vector of `PersistedEvent` representing the appended events, or an error.
+    async fn append_with_metadata<QE>(
+        &self,
+        events: Vec<E>,
+        metadata: Metadata,
+        query: StreamQuery<ID, QE>,
+        last_event_id: ID,
+    ) -> Result<Vec<PersistedEvent<ID, E>>, Self::Error>
+    where
+        E: Clone + 'async_trait,
+        QE: Event + 'static + Clone + Send + Sync,
+    {
+        let events = self.append(events, query, last_event_id).await?;
+        Ok(events
+            .into_iter()
+            .map(|event| event.with_metadata(metadata.clone()))
+            .collect())
+    }
+}
+
+/// The position of a page of events in the event stream.
+///
+/// See `EventStore::stream_page`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cursor<ID> {
+    /// The beginning of the event stream.
+    Start,
+    /// The events after the given event ID.
+    After(ID),
+    /// The end of the event stream: there are no more events.
+    End,
+}
+
+impl<ID: EventId> Cursor<ID> {
+    /// Returns true if there are no more events.
+    pub fn is_end(&self) -> bool {
+        matches!(self, Cursor::End)
+    }
+
+    /// Truncates the events read from this cursor to a page of `limit` events, returning the page and
+    /// the cursor of the next page.
+    ///
+    /// The events should be read with a limit of `limit + 1`: the next page is the end of the event stream
+    /// if at most `limit` events have been read. A page of zero events is the end of the event stream, so that
+    /// a caller following the cursors does not loop on the same position.
+    pub fn page<QE: Event>(
+        self,
+        mut events: Vec<PersistedEvent<ID, QE>>,
+        limit: usize,
+    ) -> (Vec<PersistedEvent<ID, QE>>, Cursor<ID>) {
+        if limit == 0 {
+            return (vec![], Cursor::End);
+        }
+        if events.len() <= limit {
+            return (events, Cursor::End);
+        }
+        events.truncate(limit);
+        let cursor = events
+            .last()
+            .map_or(self, |event| Cursor::After(event.id()));
+        (events, cursor)
+    }
+}
+
+/// The capability required to append events without validating them against a query.
+///
+/// An unchecked append bypasses the optimistic concurrency control: the appended events may contradict the
+/// decisions made concurrently. The token states why the append is safe, so that the bypasses stand out in
+/// code reviews, and the appends made with it are counted by reason in the `UncheckedAppendMetrics` of the
+/// event store.
+///
+/// # Examples
+///
+/// ```
+/// use disintegrate::UncheckedAppend;
+///
+/// let unchecked = UncheckedAppend::because("importing the history of the legacy system");
+/// assert_eq!(unchecked.reason(), "importing the history of the legacy system");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UncheckedAppend {
+    reason: &'static str,
+}
+
+impl UncheckedAppend {
+    /// Creates the capability to append events without validation.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the append does not need the concurrency control, e.g. a data import.
+    pub fn because(reason: &'static str) -> Self {
+        Self { reason }
+    }
+
+    /// Returns why the append does not need the concurrency control.
+    pub fn reason(&self) -> &'static str {
+        self.reason
+    }
+}
+
+/// The counters of the unchecked appends of an event store.
+///
+/// The counters are shared by the clones of the event store, so they can be read, e.g. exported as metrics,
+/// while the application runs.
+#[derive(Debug, Default)]
+pub struct UncheckedAppendMetrics {
+    appends: AtomicU64,
+    events: AtomicU64,
+    reasons: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl UncheckedAppendMetrics {
+    /// Returns the number of unchecked appends.
+    pub fn appends(&self) -> u64 {
+        self.appends.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of events appended without validation.
+    pub fn events(&self) -> u64 {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of unchecked appends by reason.
+    pub fn reasons(&self) -> BTreeMap<&'static str, u64> {
+        self.reasons.lock().unwrap().clone()
+    }
+
+    /// Records an unchecked append. It is called by the event stores.
+    ///
+    /// # Arguments
+    ///
+    /// * `unchecked` - The capability the events have been appended with.
+    /// * `events` - The number of appended events.
+    pub fn record(&self, unchecked: &UncheckedAppend, events: usize) {
+        self.appends.fetch_add(1, Ordering::Relaxed);
+        self.events.fetch_add(events as u64, Ordering::Relaxed);
+        *self
+            .reasons
+            .lock()
+            .unwrap()
+            .entry(unchecked.reason())
+            .or_default() += 1;
+    }
+}
+
+/// Resolves the commit timestamps of the events.
+///
+/// It translates the distance between two events, e.g. the lag of an event listener,
+/// from a number of events into a time interval.
+#[async_trait]
+pub trait EventTimestampResolver<ID>
+where
+    ID: EventId,
+{
+    type Error: Send + Sync;
+
+    /// Returns the commit timestamp of the given event.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - The ID of the event.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the commit timestamp of the event, or of the closest event committed before it
+    /// if the ID has not been assigned to an event. `None` if no events have been committed up to the given ID.
+    async fn timestamp(&self, event_id: ID) -> Result<Option<SystemTime>, Self::Error>;
+
+    /// Returns the time elapsed between the commits of two events.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The ID of the first event of the range.
+    /// * `to` - The ID of the last event of the range.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the time elapsed between the two events, or `None` if one of the timestamps cannot be resolved.
+    async fn time_lag(&self, from: ID, to: ID) -> Result<Option<Duration>, Self::Error> {
+        let (Some(from), Some(to)) = (self.timestamp(from).await?, self.timestamp(to).await?)
+        else {
+            return Ok(None);
+        };
+        Ok(Some(to.duration_since(from).unwrap_or_default()))
+    }
 }
@@ -173,7 +173,7 @@ impl Display for Identifier {
 }
 
 /// The `IdentifierError` struct represents an error that can occur when working with identifiers.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct IdentifierError(String);
 
 impl IdentifierError {
@@ -233,13 +233,22 @@ macro_rules! impl_identifier_type {
            $($type,)+
         }
 
-        #[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+        #[derive(Debug, Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
         #[allow(non_camel_case_types)]
         /// Represents the value of an identifier, allowing different types.
         pub enum IdentifierValue{
            $($type($type),)+
         }
 
+        impl IdentifierValue{
+            /// Returns the type of the value.
+            pub fn identifier_type(&self) -> IdentifierType {
+                match self{
+                    $(Self::$type(_) => IdentifierType::$type,)+
+                }
+            }
+        }
+
         impl std::fmt::Display for IdentifierValue{
             fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
                 match self{
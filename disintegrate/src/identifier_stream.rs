@@ -0,0 +1,184 @@
+//! Per-identifier streams
+//!
+//! Event stores organized in aggregate streams address the events by stream name, e.g. `account-123`.
+//! This module emulates that abstraction on top of stream queries: an `IdentifierStream` selects, in order,
+//! all the events that carry a domain identifier with a given value, so that the projections and the mental
+//! models built around aggregate streams can be migrated without rethinking them upfront.
+//!
+//! # Examples
+//!
+//! ```
+//! use disintegrate::{ident, IdentifierStream};
+//!
+//! let stream = IdentifierStream::new(ident!(#account_id), 123);
+//!
+//! assert_eq!(stream.to_string(), "account_id-123");
+//! ```
+use std::fmt;
+
+use crate::{
+    event::EventId, DomainIdentifier, DomainIdentifierSet, Event, Identifier, IdentifierValue,
+    IntoIdentifierValue, StreamFilter, StreamQuery,
+};
+
+/// The stream of the events carrying a domain identifier with a given value.
+///
+/// Unlike a stream query filtering on the same identifier, the stream excludes the event types that
+/// do not declare the identifier, as an aggregate stream only contains the events of the aggregate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentifierStream {
+    key: Identifier,
+    value: IdentifierValue,
+}
+
+impl IdentifierStream {
+    /// Creates a new `IdentifierStream`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The domain identifier of the stream, e.g. `account_id`.
+    /// * `value` - The value of the domain identifier, e.g. `123`.
+    pub fn new(key: Identifier, value: impl IntoIdentifierValue) -> Self {
+        Self {
+            key,
+            value: value.into_identifier_value(),
+        }
+    }
+
+    /// Returns the domain identifier of the stream.
+    pub fn key(&self) -> Identifier {
+        self.key
+    }
+
+    /// Returns the value of the domain identifier of the stream.
+    pub fn value(&self) -> &IdentifierValue {
+        &self.value
+    }
+
+    /// Returns the names of the events of `E` that belong to the stream.
+    pub fn events<E: Event>(&self) -> Vec<&'static str> {
+        E::SCHEMA
+            .events_info
+            .iter()
+            .filter(|info| info.has_domain_identifier(&self.key))
+            .map(|info| info.name)
+            .collect()
+    }
+
+    /// Returns the stream query selecting the events of the stream.
+    ///
+    /// The query can be used with any `EventStore`, e.g. `event_store.stream(&stream.query())`, and its
+    /// origin can be changed to resume the stream from a known event.
+    pub fn query<ID: EventId, E: Event + Clone>(&self) -> StreamQuery<ID, E> {
+        let events = self.events::<E>();
+        let excluded_events: Vec<&'static str> = E::SCHEMA
+            .events
+            .iter()
+            .filter(|event| !events.contains(event))
+            .copied()
+            .collect();
+        let mut identifiers = DomainIdentifierSet::default();
+        identifiers.insert(DomainIdentifier {
+            key: self.key,
+            value: self.value.clone(),
+        });
+        crate::query::<ID, E, E>(Some(StreamFilter::new(identifiers)))
+            .extend_excluded_events(&excluded_events)
+    }
+}
+
+/// Formats the stream as `<identifier>-<value>`, the naming convention of the aggregate streams.
+impl fmt::Display for IdentifierStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.key, self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{domain_identifiers, ident, DomainIdentifierInfo, EventInfo, EventSchema};
+    use crate::{IdentifierType, PersistedEvent};
+
+    #[derive(Debug, Clone)]
+    enum BankEvent {
+        AccountOpened { account_id: i64 },
+        AmountDeposited { account_id: i64 },
+        InterestRateChanged,
+    }
+
+    impl Event for BankEvent {
+        const SCHEMA: EventSchema = EventSchema {
+            events: &["AccountOpened", "AmountDeposited", "InterestRateChanged"],
+            events_info: &[
+                &EventInfo {
+                    name: "AccountOpened",
+                    domain_identifiers: &[&ident!(#account_id)],
+                    description: None,
+                    owner: None,
+                    aliases: &[],
+                    version: 1,
+                },
+                &EventInfo {
+                    name: "AmountDeposited",
+                    domain_identifiers: &[&ident!(#account_id)],
+                    description: None,
+                    owner: None,
+                    aliases: &[],
+                    version: 1,
+                },
+                &EventInfo {
+                    name: "InterestRateChanged",
+                    domain_identifiers: &[],
+                    description: None,
+                    owner: None,
+                    aliases: &[],
+                    version: 1,
+                },
+            ],
+            domain_identifiers: &[&DomainIdentifierInfo {
+                ident: ident!(#account_id),
+                type_info: IdentifierType::i64,
+            }],
+        };
+
+        fn name(&self) -> &'static str {
+            match self {
+                BankEvent::AccountOpened { .. } => "AccountOpened",
+                BankEvent::AmountDeposited { .. } => "AmountDeposited",
+                BankEvent::InterestRateChanged => "InterestRateChanged",
+            }
+        }
+
+        fn domain_identifiers(&self) -> DomainIdentifierSet {
+            match self {
+                BankEvent::AccountOpened { account_id }
+                | BankEvent::AmountDeposited { account_id } => {
+                    domain_identifiers! {account_id: account_id}
+                }
+                BankEvent::InterestRateChanged => domain_identifiers! {},
+            }
+        }
+    }
+
+    #[test]
+    fn it_selects_only_the_events_of_the_identifier() {
+        let stream = IdentifierStream::new(ident!(#account_id), 123);
+        let query: StreamQuery<i64, BankEvent> = stream.query();
+
+        assert_eq!(stream.to_string(), "account_id-123");
+        assert_eq!(
+            stream.events::<BankEvent>(),
+            vec!["AccountOpened", "AmountDeposited"]
+        );
+        assert!(query.matches(&PersistedEvent::new(
+            1,
+            BankEvent::AccountOpened { account_id: 123 }
+        )));
+        assert!(!query.matches(&PersistedEvent::new(
+            2,
+            BankEvent::AmountDeposited { account_id: 456 }
+        )));
+        assert!(!query.matches(&PersistedEvent::new(3, BankEvent::InterestRateChanged)));
+    }
+}
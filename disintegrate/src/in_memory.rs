@@ -0,0 +1,452 @@
+//! In-memory Event Store
+//!
+//! This module provides an implementation of the `EventStore` trait that keeps the events in memory.
+//! It is meant for unit tests, examples and prototypes that need an event store with the same semantics
+//! of the database backends, without running a database: the events are assigned increasing IDs starting
+//! from 1, the stream queries are evaluated as the backends do, and an append fails with a concurrency
+//! error when an event matching its query has been appended after the given version.
+//!
+//! The events are lost when the last clone of the event store is dropped.
+use std::error::Error as StdError;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+
+use crate::{
+    BoxDynError, Event, EventId, EventStore, Metadata, PersistedEvent, StreamQuery,
+    UncheckedAppend, UncheckedAppendMetrics,
+};
+
+/// Represents the errors of the in-memory event store.
+#[derive(Debug, thiserror::Error)]
+pub enum InMemoryError {
+    /// An error that occurs when the event type cannot be converted to the type of the query.
+    #[error(transparent)]
+    QueryEventMapping(#[from] BoxDynError),
+    /// An error that occurs when the events matching the query have changed since the given version.
+    #[error("concurrency error")]
+    Concurrency,
+    /// An error that occurs when the next event ID cannot be represented by the event ID type.
+    #[error("event ID overflow")]
+    EventIdOverflow,
+}
+
+/// In-memory event store implementation.
+///
+/// The clones of the event store share the same events.
+#[derive(Debug)]
+pub struct InMemoryEventStore<ID, E>
+where
+    ID: EventId,
+    E: Event + Clone,
+{
+    events: Arc<RwLock<Vec<PersistedEvent<ID, E>>>>,
+    unchecked_append_metrics: Arc<UncheckedAppendMetrics>,
+}
+
+impl<ID, E> Clone for InMemoryEventStore<ID, E>
+where
+    ID: EventId,
+    E: Event + Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            events: Arc::clone(&self.events),
+            unchecked_append_metrics: Arc::clone(&self.unchecked_append_metrics),
+        }
+    }
+}
+
+impl<ID, E> Default for InMemoryEventStore<ID, E>
+where
+    ID: EventId,
+    E: Event + Clone,
+{
+    fn default() -> Self {
+        Self {
+            events: Arc::new(RwLock::new(vec![])),
+            unchecked_append_metrics: Arc::new(UncheckedAppendMetrics::default()),
+        }
+    }
+}
+
+impl<ID, E> InMemoryEventStore<ID, E>
+where
+    ID: EventId + TryFrom<u64>,
+    E: Event + Clone,
+{
+    /// Creates a new, empty `InMemoryEventStore`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns all the events of the event store, in the order they were appended.
+    pub fn events(&self) -> Vec<PersistedEvent<ID, E>> {
+        self.events.read().unwrap().clone()
+    }
+
+    /// Appends new events to the event store without validating the appended events against a query.
+    ///
+    /// The append is recorded in the `UncheckedAppendMetrics` of the event store.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - A vector of events to be appended.
+    /// * `unchecked` - The capability to append without validation, stating why it is safe.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a vector of `PersistedEvent` representing the appended events,
+    /// or an error of type `InMemoryError`.
+    pub fn append_without_validation(
+        &self,
+        events: Vec<E>,
+        unchecked: UncheckedAppend,
+    ) -> Result<Vec<PersistedEvent<ID, E>>, InMemoryError> {
+        let mut stored = self.events.write().unwrap();
+        let persisted = push_events(&mut stored, events, Metadata::default())?;
+        self.unchecked_append_metrics
+            .record(&unchecked, persisted.len());
+        Ok(persisted)
+    }
+
+    /// Returns the counters of the appends made without validation.
+    pub fn unchecked_append_metrics(&self) -> Arc<UncheckedAppendMetrics> {
+        Arc::clone(&self.unchecked_append_metrics)
+    }
+}
+
+#[async_trait]
+impl<ID, E> EventStore<ID, E> for InMemoryEventStore<ID, E>
+where
+    ID: EventId + TryFrom<u64> + Send + Sync,
+    E: Event + Clone + Send + Sync,
+{
+    type Error = InMemoryError;
+
+    /// Streams events based on the provided query.
+    ///
+    /// The stream is a snapshot of the events matching the query when the stream is created.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The stream query specifying the criteria for filtering events.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a boxed stream of `PersistedEvent` that matches the query criteria,
+    /// or an error of type `Self::Error`.
+    fn stream<'a, QE>(
+        &'a self,
+        query: &'a StreamQuery<ID, QE>,
+    ) -> BoxStream<'a, Result<PersistedEvent<ID, QE>, Self::Error>>
+    where
+        QE: TryFrom<E> + Event + 'static + Clone + Send + Sync,
+        <QE as TryFrom<E>>::Error: StdError + 'static + Send + Sync,
+    {
+        let events: Vec<Result<PersistedEvent<ID, QE>, Self::Error>> = self
+            .events
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|event| matches(query, event))
+            .map(|event| {
+                let id = event.id();
+                let metadata = event.metadata().clone();
+                let event = QE::try_from(event.clone().into_inner())
+                    .map_err(|e| InMemoryError::QueryEventMapping(Box::new(e)))?;
+                Ok(PersistedEvent::new(id, event).with_metadata(metadata))
+            })
+            .collect();
+        stream::iter(events).boxed()
+    }
+
+    /// Appends new events to the event store.
+    ///
+    /// The check for the events matching the `query` appended after `version` and the write happen
+    /// while holding the lock of the events, so no other append can interleave between them. If a matching
+    /// event is found, a `Concurrency` error is returned and no event is appended.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - A vector of events to be appended.
+    /// * `query` - The stream query specifying the criteria for filtering events.
+    /// * `version` - The ID of the last consumed event.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a vector of `PersistedEvent` representing the appended events,
+    /// or an error of type `Self::Error`.
+    async fn append<QE>(
+        &self,
+        events: Vec<E>,
+        query: StreamQuery<ID, QE>,
+        version: ID,
+    ) -> Result<Vec<PersistedEvent<ID, E>>, Self::Error>
+    where
+        E: Clone + 'async_trait,
+        QE: Event + Clone + Send + Sync,
+    {
+        self.append_events(events, Metadata::default(), query, version)
+    }
+
+    /// Appends new events to the event store, together with their metadata.
+    ///
+    /// The metadata are kept with the events and returned by the streams of the event store.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - A vector of events to be appended.
+    /// * `metadata` - The metadata of the appended events.
+    /// * `query` - The stream query specifying the criteria for filtering events.
+    /// * `version` - The ID of the last consumed event.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a vector of `PersistedEvent` representing the appended events,
+    /// or an error of type `Self::Error`.
+    async fn append_with_metadata<QE>(
+        &self,
+        events: Vec<E>,
+        metadata: Metadata,
+        query: StreamQuery<ID, QE>,
+        version: ID,
+    ) -> Result<Vec<PersistedEvent<ID, E>>, Self::Error>
+    where
+        E: Clone + 'async_trait,
+        QE: Event + Clone + Send + Sync,
+    {
+        self.append_events(events, metadata, query, version)
+    }
+}
+
+impl<ID, E> InMemoryEventStore<ID, E>
+where
+    ID: EventId + TryFrom<u64>,
+    E: Event + Clone,
+{
+    /// Appends the events if no event matching the query has been appended after the version.
+    fn append_events<QE: Event + Clone>(
+        &self,
+        events: Vec<E>,
+        metadata: Metadata,
+        query: StreamQuery<ID, QE>,
+        version: ID,
+    ) -> Result<Vec<PersistedEvent<ID, E>>, InMemoryError> {
+        let query = query.change_origin(version);
+        let mut stored = self.events.write().unwrap();
+        if stored.iter().any(|event| matches(&query, event)) {
+            return Err(InMemoryError::Concurrency);
+        }
+        push_events(&mut stored, events, metadata)
+    }
+}
+
+/// Checks if a stored event matches a query on a possibly different event type.
+fn matches<ID, QE, E>(query: &StreamQuery<ID, QE>, event: &PersistedEvent<ID, E>) -> bool
+where
+    ID: EventId,
+    QE: Event + Clone,
+    E: Event + Clone,
+{
+    let identifiers = event.domain_identifiers();
+    query.filters().iter().any(|filter| {
+        filter.events().contains(&event.name())
+            && !filter
+                .excluded_events()
+                .is_some_and(|excluded_events| excluded_events.contains(&event.name()))
+            && event.id() > filter.origin()
+            && QE::SCHEMA
+                .event_info(event.name())
+                .is_some_and(|event_info| {
+                    filter.matches_identifiers(event_info, |ident, value| {
+                        identifiers.get(ident) == Some(value)
+                    })
+                })
+    })
+}
+
+fn push_events<ID, E>(
+    stored: &mut Vec<PersistedEvent<ID, E>>,
+    events: Vec<E>,
+    metadata: Metadata,
+) -> Result<Vec<PersistedEvent<ID, E>>, InMemoryError>
+where
+    ID: EventId + TryFrom<u64>,
+    E: Event + Clone,
+{
+    let first_id = stored.len() as u64 + 1;
+    let persisted = events
+        .into_iter()
+        .enumerate()
+        .map(|(offset, event)| {
+            let id = ID::try_from(first_id + offset as u64)
+                .map_err(|_| InMemoryError::EventIdOverflow)?;
+            Ok(PersistedEvent::new(id, event).with_metadata(metadata.clone()))
+        })
+        .collect::<Result<Vec<_>, InMemoryError>>()?;
+    stored.extend(persisted.iter().cloned());
+    Ok(persisted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::{item_added_event, item_removed_event, ShoppingCartEvent};
+    use crate::{query, Cursor};
+    use futures::TryStreamExt;
+
+    fn cart_query(cart_id: &str) -> StreamQuery<i64, ShoppingCartEvent> {
+        query!(ShoppingCartEvent; cart_id == cart_id)
+    }
+
+    #[tokio::test]
+    async fn it_streams_the_events_matching_the_query() {
+        let event_store = InMemoryEventStore::<i64, ShoppingCartEvent>::new();
+        event_store
+            .append_without_validation(
+                vec![
+                    item_added_event("p1", "c1"),
+                    item_added_event("p2", "c2"),
+                    item_removed_event("p1", "c1"),
+                ],
+                UncheckedAppend::because("seeding the test events"),
+            )
+            .unwrap();
+
+        let events: Vec<_> = event_store
+            .stream(&cart_query("c1"))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                PersistedEvent::new(1, item_added_event("p1", "c1")),
+                PersistedEvent::new(3, item_removed_event("p1", "c1")),
+            ]
+        );
+
+        let events: Vec<_> = event_store
+            .stream(&cart_query("c1").change_origin(1))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![PersistedEvent::new(3, item_removed_event("p1", "c1"))]
+        );
+
+        let metrics = event_store.unchecked_append_metrics();
+        assert_eq!(metrics.appends(), 1);
+        assert_eq!(metrics.events(), 3);
+        assert_eq!(
+            metrics.reasons(),
+            std::collections::BTreeMap::from([("seeding the test events", 1)])
+        );
+    }
+
+    #[tokio::test]
+    async fn it_pages_through_the_events_matching_the_query() {
+        let event_store = InMemoryEventStore::<i64, ShoppingCartEvent>::new();
+        event_store
+            .append_without_validation(
+                vec![
+                    item_added_event("p1", "c1"),
+                    item_added_event("p2", "c2"),
+                    item_added_event("p2", "c1"),
+                    item_removed_event("p1", "c1"),
+                ],
+                UncheckedAppend::because("seeding the test events"),
+            )
+            .unwrap();
+
+        let (events, cursor) = event_store
+            .stream_page(&cart_query("c1"), Cursor::Start, 2)
+            .await
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                PersistedEvent::new(1, item_added_event("p1", "c1")),
+                PersistedEvent::new(3, item_added_event("p2", "c1")),
+            ]
+        );
+        assert_eq!(cursor, Cursor::After(3));
+
+        let (events, cursor) = event_store
+            .stream_page(&cart_query("c1"), cursor, 2)
+            .await
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![PersistedEvent::new(4, item_removed_event("p1", "c1"))]
+        );
+        assert!(cursor.is_end());
+
+        let (events, cursor) = event_store
+            .stream_page(&cart_query("c1"), Cursor::Start, 0)
+            .await
+            .unwrap();
+        assert!(events.is_empty());
+        assert!(cursor.is_end());
+    }
+
+    #[tokio::test]
+    async fn it_rejects_an_append_when_the_query_has_changed() {
+        let event_store = InMemoryEventStore::<i64, ShoppingCartEvent>::new();
+        event_store
+            .append(vec![item_added_event("p1", "c1")], cart_query("c1"), 0)
+            .await
+            .unwrap();
+        event_store
+            .append(vec![item_added_event("p2", "c2")], cart_query("c2"), 0)
+            .await
+            .unwrap();
+
+        let conflict = event_store
+            .append(vec![item_added_event("p3", "c1")], cart_query("c1"), 0)
+            .await;
+        assert!(matches!(conflict, Err(InMemoryError::Concurrency)));
+
+        let appended = event_store
+            .clone()
+            .append(vec![item_added_event("p3", "c1")], cart_query("c1"), 1)
+            .await
+            .unwrap();
+        assert_eq!(
+            appended,
+            vec![PersistedEvent::new(3, item_added_event("p3", "c1"))]
+        );
+        assert_eq!(event_store.events().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn it_streams_the_events_with_their_metadata() {
+        let event_store = InMemoryEventStore::<i64, ShoppingCartEvent>::new();
+        let metadata = Metadata::new()
+            .with_correlation_id("request-1")
+            .with_user_id("alice");
+        event_store
+            .append_with_metadata(
+                vec![item_added_event("p1", "c1")],
+                metadata.clone(),
+                cart_query("c1"),
+                0,
+            )
+            .await
+            .unwrap();
+
+        let events: Vec<_> = event_store
+            .stream(&cart_query("c1"))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![PersistedEvent::new(1, item_added_event("p1", "c1")).with_metadata(metadata)]
+        );
+    }
+}
@@ -1,11 +1,23 @@
 #![doc = include_str!("../README.md")]
 
+mod application;
 mod decision;
 mod domain_identifier;
 mod event;
 mod event_store;
 mod identifier;
+mod identifier_stream;
+#[cfg(feature = "in-memory")]
+mod in_memory;
 mod listener;
+#[cfg(feature = "load-test")]
+mod load_test;
+mod metadata;
+mod migrations;
+mod projection;
+#[cfg(feature = "tokio")]
+mod reconciliation;
+mod snapshot_store;
 mod state;
 mod state_store;
 mod stream_query;
@@ -13,7 +25,19 @@ mod testing;
 pub mod utils;
 
 #[doc(inline)]
-pub use crate::decision::{Decision, DecisionMaker, Error as DecisionError, PersistDecision};
+pub use crate::application::{Application, ApplicationError, MakeDecision};
+#[cfg(feature = "tokio")]
+#[doc(inline)]
+pub use crate::decision::{
+    CronError, CronSchedule, InMemoryRunStore, RecurringDecision, RecurringRunStore, RetryPolicy,
+    Schedule, ShardedDecisionMaker,
+};
+#[doc(inline)]
+pub use crate::decision::{
+    Decision, DecisionContext, DecisionFailure, DecisionLayer, DecisionMaker, DecisionTrace,
+    DecisionTraceSink, DecisionTracer, Error as DecisionError, LayerStack, NoDecisionLayer,
+    NoDecisionTrace, PersistDecision, Refusal, SinkTracer,
+};
 #[doc(inline)]
 pub use crate::domain_identifier::{DomainIdentifier, DomainIdentifierSet};
 #[doc(inline)]
@@ -21,34 +45,75 @@ pub use crate::event::{
     DomainIdentifierInfo, Event, EventId, EventInfo, EventSchema, PersistedEvent,
 };
 #[doc(inline)]
-pub use crate::event_store::EventStore;
+pub use crate::event_store::{
+    Cursor, EventStore, EventTimestampResolver, UncheckedAppend, UncheckedAppendMetrics,
+};
 #[doc(inline)]
 pub use crate::identifier::{Identifier, IdentifierType, IdentifierValue, IntoIdentifierValue};
 #[doc(inline)]
-pub use crate::listener::EventListener;
+pub use crate::identifier_stream::IdentifierStream;
+#[cfg(feature = "in-memory")]
+#[doc(inline)]
+pub use crate::in_memory::{InMemoryError, InMemoryEventStore};
+#[doc(inline)]
+pub use crate::listener::{ErasedEventListener, EventListener, Retraction};
+#[cfg(feature = "load-test")]
+#[doc(inline)]
+pub use crate::load_test::{LoadReport, LoadStats, LoadTest};
+#[doc(inline)]
+pub use crate::metadata::{Metadata, CAUSATION_ID, CORRELATION_ID, USER_ID};
+#[doc(inline)]
+pub use crate::migrations::{MigrationPlan, StoreMigrations};
+#[doc(inline)]
+pub use crate::projection::{IdempotencyKey, Projection, ProjectionListener};
+#[cfg(feature = "tokio")]
+#[doc(inline)]
+pub use crate::reconciliation::{
+    Drift, DriftObserver, NoDriftObserver, Reconciliation, ReconciliationReport,
+};
+#[cfg(feature = "tokio")]
+#[doc(inline)]
+pub use crate::snapshot_store::FileSnapshotStore;
+#[doc(inline)]
+pub use crate::snapshot_store::{
+    query_key, EveryEventsPolicy, IntervalPolicy, NeverPolicy, PerStatePolicy, SizeThresholdPolicy,
+    Snapshot, SnapshotCandidate, SnapshotPolicy, SnapshotStore, Snapshotter,
+};
 #[doc(inline)]
 pub use crate::state::{IntoState, IntoStatePart, MultiState, StateMutate, StatePart, StateQuery};
 #[doc(inline)]
 pub use crate::state_store::{
-    EventSourcedStateStore, LoadState, LoadedState, NoSnapshot, SnapshotConfig, StateSnapshotter,
-    WithSnapshot,
+    EventSourcedStateStore, LoadState, LoadedState, NoSnapshot, ReplayLimit, ReplayLimitExceeded,
+    SnapshotConfig, StateSnapshotter, WithSnapshot,
 };
 #[doc(inline)]
-pub use crate::stream_query::{query, StreamFilter, StreamQuery};
+pub use crate::stream_query::{
+    query, IdentifierCondition, MissingIdentifier, StreamFilter, StreamQuery, StreamQueryBuilder,
+    StreamQueryError,
+};
 #[doc(inline)]
-pub use crate::testing::TestHarness;
+pub use crate::testing::{ListenerDelivery, ListenerTestHarness, TestHarness};
 
 pub type BoxDynError = Box<dyn std::error::Error + 'static + Send + Sync>;
 
 #[cfg(feature = "macros")]
-pub use disintegrate_macros::{Event, StateQuery};
+pub use disintegrate_macros::{events, Event, StateQuery};
 
 #[cfg(feature = "serde")]
 pub mod serde {
     //! # Event Store Serialization Deserializaion.
+    #[cfg(feature = "serde-schema")]
+    #[doc(inline)]
+    pub use disintegrate_serde::schema;
     #[cfg(feature = "serde-avro")]
     #[doc(inline)]
     pub use disintegrate_serde::serde::avro;
+    #[cfg(any(feature = "serde-zstd", feature = "serde-lz4", feature = "serde-gzip"))]
+    #[doc(inline)]
+    pub use disintegrate_serde::serde::compressed;
+    #[cfg(feature = "serde-encryption")]
+    #[doc(inline)]
+    pub use disintegrate_serde::serde::encrypted;
     #[cfg(feature = "serde-json")]
     #[doc(inline)]
     pub use disintegrate_serde::serde::json;
@@ -59,7 +124,7 @@ pub mod serde {
     #[doc(inline)]
     pub use disintegrate_serde::serde::protobuf;
     #[doc(inline)]
-    pub use disintegrate_serde::{Deserializer, Serde, Serializer};
+    pub use disintegrate_serde::{Deserializer, Serde, Serializer, Upcasted, Upcaster};
 }
 
 #[doc(hidden)]
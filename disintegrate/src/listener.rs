@@ -1,9 +1,12 @@
 //! Event listener handles events that are emitted.
+use std::error::Error as StdError;
+
 use async_trait::async_trait;
 
 use crate::{
     event::{Event, EventId, PersistedEvent},
     stream_query::StreamQuery,
+    BoxDynError,
 };
 
 /// Represents an event listener, which handles persisted events.
@@ -27,4 +30,74 @@ pub trait EventListener<ID: EventId, E: Event + Clone>: Send + Sync {
     /// This method handle the event coming from the event stream.
     /// The method returns a result indicating success or an error that may occur during the event handler.
     async fn handle(&self, event: PersistedEvent<ID, E>) -> Result<(), Self::Error>;
+
+    /// Handles the retraction of an event.
+    ///
+    /// The retractions are delivered, in order with the events, only to the event listeners that opt in.
+    /// The default implementation ignores them.
+    async fn handle_retraction(&self, _retraction: Retraction<ID>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// The retraction of a persisted event.
+///
+/// A retraction corrects bad data without rewriting the event history: the retracted event stays in
+/// the event store, and the event stores can be configured to skip it in the subsequent streams.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retraction<ID: EventId> {
+    /// The ID of the event recording the retraction.
+    pub id: ID,
+    /// The ID of the retracted event.
+    pub event_id: ID,
+    /// The type of the retracted event.
+    pub event_type: String,
+    /// The reason of the retraction.
+    pub reason: String,
+}
+
+/// Erases the error type of an event listener, boxing its errors.
+///
+/// The event stores run the registered event listeners as `EventListener<ID, E, Error = BoxDynError>` trait
+/// objects, so that their executors do not depend on the type of each listener.
+pub struct ErasedEventListener<L>(L);
+
+impl<L> ErasedEventListener<L> {
+    /// Creates a new `ErasedEventListener`.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - The event listener whose errors are boxed.
+    pub fn new(listener: L) -> Self {
+        Self(listener)
+    }
+}
+
+#[async_trait]
+impl<ID, E, L> EventListener<ID, E> for ErasedEventListener<L>
+where
+    ID: EventId,
+    E: Event + Clone + Send + Sync + 'static,
+    L: EventListener<ID, E, Error: StdError + Send + Sync + 'static>,
+{
+    type Error = BoxDynError;
+
+    fn id(&self) -> &'static str {
+        self.0.id()
+    }
+
+    fn query(&self) -> &StreamQuery<ID, E> {
+        self.0.query()
+    }
+
+    async fn handle(&self, event: PersistedEvent<ID, E>) -> Result<(), Self::Error> {
+        self.0.handle(event).await.map_err(Into::into)
+    }
+
+    async fn handle_retraction(&self, retraction: Retraction<ID>) -> Result<(), Self::Error> {
+        self.0
+            .handle_retraction(retraction)
+            .await
+            .map_err(Into::into)
+    }
 }
@@ -0,0 +1,576 @@
+//! Load testing of decisions
+//!
+//! `LoadTest` runs a mix of decisions against a decision maker, e.g. a `DecisionMaker` backed by the
+//! in-memory or the Postgres event store, so the load goes through the same code paths as the application:
+//! state loading, snapshotting and optimistic locking. The number of concurrent workers follows a ramp profile,
+//! made of stages like the ones of the common load testing tools, and the outcome of each decision is
+//! collected in a `LoadReport`.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let report = LoadTest::new(decision_maker)
+//!     .scenario("add_item", 9, |n| AddItem::new(format!("cart-{}", n % 100), n))
+//!     .scenario("checkout", 1, |n| Checkout::new(format!("cart-{}", n % 100)))
+//!     .ramp(Duration::from_secs(10), 50)
+//!     .hold(Duration::from_secs(60))
+//!     .ramp(Duration::from_secs(5), 0)
+//!     .run()
+//!     .await;
+//! println!("{report}");
+//! ```
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+
+use crate::decision::{is_in_memory_conflict, Error as DecisionError};
+use crate::{BoxDynError, Decision, EventId, MakeDecision};
+
+/// The interval at which the number of workers is adjusted to the ramp profile.
+const TICK: Duration = Duration::from_millis(50);
+
+type RunDecision = Arc<dyn Fn(u64) -> BoxFuture<'static, Outcome> + Send + Sync>;
+
+/// The outcome of a decision, before the failures are classified.
+enum Outcome {
+    Succeeded,
+    Rejected,
+    Failed(BoxDynError),
+}
+
+struct Scenario {
+    name: &'static str,
+    weight: u64,
+    run: RunDecision,
+}
+
+/// A stage of the ramp profile: the number of workers moves linearly to `target` over `duration`.
+#[derive(Debug, Clone, Copy)]
+struct Stage {
+    duration: Duration,
+    target: usize,
+}
+
+/// Runs a weighted mix of decisions with a ramp profile of concurrent workers.
+pub struct LoadTest<DM, ID> {
+    decision_maker: DM,
+    scenarios: Vec<Scenario>,
+    stages: Vec<Stage>,
+    is_conflict: Arc<dyn Fn(&BoxDynError) -> bool + Send + Sync>,
+    _event_id: PhantomData<fn() -> ID>,
+}
+
+impl<DM, ID> fmt::Debug for LoadTest<DM, ID> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoadTest")
+            .field(
+                "scenarios",
+                &self
+                    .scenarios
+                    .iter()
+                    .map(|scenario| (scenario.name, scenario.weight))
+                    .collect::<Vec<_>>(),
+            )
+            .field("stages", &self.stages)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<DM, ID> LoadTest<DM, ID>
+where
+    DM: Clone + Send + Sync + 'static,
+    ID: EventId,
+{
+    /// Creates a new `LoadTest`.
+    ///
+    /// The concurrency conflicts of the in-memory event store are recognized out of the box. The conflicts
+    /// of the other event stores must be recognized with `conflicts_when`.
+    ///
+    /// # Arguments
+    ///
+    /// * `decision_maker` - The decision maker executing the decisions, e.g. a `DecisionMaker` or a `ShardedDecisionMaker`.
+    pub fn new(decision_maker: DM) -> Self {
+        Self {
+            decision_maker,
+            scenarios: vec![],
+            stages: vec![],
+            is_conflict: Arc::new(is_in_memory_conflict),
+            _event_id: PhantomData,
+        }
+    }
+
+    /// Adds a decision to the mix.
+    ///
+    /// The decisions are picked in proportion to their weights, e.g. a scenario with weight 9 runs nine times
+    /// as often as a scenario with weight 1.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the scenario in the report.
+    /// * `weight` - The weight of the scenario in the mix.
+    /// * `decision` - Builds the decision from the sequence number of the run, e.g. to spread the decisions
+    ///   over a given number of aggregates and tune the contention.
+    pub fn scenario<D, F>(mut self, name: &'static str, weight: u32, decision: F) -> Self
+    where
+        D: Decision + 'static,
+        DM: MakeDecision<ID, D>,
+        F: Fn(u64) -> D + Send + Sync + 'static,
+    {
+        let decision_maker = self.decision_maker.clone();
+        let run: RunDecision = Arc::new(move |n| {
+            let decision_maker = decision_maker.clone();
+            let decision = decision(n);
+            Box::pin(async move {
+                match decision_maker.make_decision(decision).await {
+                    Ok(_) => Outcome::Succeeded,
+                    Err(
+                        DecisionError::Domain(_)
+                        | DecisionError::Invalid(_)
+                        | DecisionError::Rejected(_),
+                    ) => Outcome::Rejected,
+                    Err(DecisionError::EventStore(err) | DecisionError::StateStore(err)) => {
+                        Outcome::Failed(err)
+                    }
+                }
+            })
+        });
+        self.scenarios.push(Scenario {
+            name,
+            weight: u64::from(weight),
+            run,
+        });
+        self
+    }
+
+    /// Moves the number of concurrent workers linearly to `target` over `duration`.
+    ///
+    /// The profile starts from zero workers, so a first stage with a zero duration starts the test
+    /// at full concurrency.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - The duration of the stage.
+    /// * `target` - The number of concurrent workers at the end of the stage.
+    pub fn ramp(mut self, duration: Duration, target: usize) -> Self {
+        self.stages.push(Stage { duration, target });
+        self
+    }
+
+    /// Keeps the number of concurrent workers reached by the previous stage for `duration`.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - The duration of the stage.
+    pub fn hold(self, duration: Duration) -> Self {
+        let target = self.stages.last().map_or(0, |stage| stage.target);
+        self.ramp(duration, target)
+    }
+
+    /// Sets how the concurrency conflicts are recognized among the failures of the state store.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_conflict` - Returns true if the error of the state store is a concurrency conflict, e.g.
+    ///   `|err| matches!(err.downcast_ref(), Some(disintegrate_postgres::Error::Concurrency))`.
+    pub fn conflicts_when(
+        mut self,
+        is_conflict: impl Fn(&BoxDynError) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.is_conflict = Arc::new(is_conflict);
+        self
+    }
+
+    /// Runs the load test until the end of the ramp profile.
+    ///
+    /// The decisions in progress at the end of the profile are completed and included in the report.
+    ///
+    /// # Returns
+    ///
+    /// The `LoadReport` of the decisions run.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no scenario has been added or if the total weight of the scenarios is zero.
+    pub async fn run(self) -> LoadReport {
+        let scenarios: Arc<[Scenario]> = self.scenarios.into();
+        let total_weight: u64 = scenarios.iter().map(|scenario| scenario.weight).sum();
+        assert!(
+            total_weight > 0,
+            "the load test requires at least a scenario with a positive weight"
+        );
+        let recorder = Arc::new(Recorder::new(&scenarios, self.is_conflict));
+        let workers = Arc::new(AtomicUsize::new(0));
+        let finished = Arc::new(AtomicBool::new(false));
+        let sequence = Arc::new(AtomicU64::new(0));
+        let total_duration: Duration = self.stages.iter().map(|stage| stage.duration).sum();
+
+        let started_at = Instant::now();
+        let mut handles = vec![];
+        loop {
+            let elapsed = started_at.elapsed();
+            if elapsed >= total_duration {
+                break;
+            }
+            let concurrency = concurrency_at(&self.stages, elapsed);
+            workers.store(concurrency, Ordering::Relaxed);
+            while handles.len() < concurrency {
+                handles.push(tokio::spawn(worker(
+                    handles.len(),
+                    scenarios.clone(),
+                    total_weight,
+                    workers.clone(),
+                    finished.clone(),
+                    sequence.clone(),
+                    recorder.clone(),
+                )));
+            }
+            tokio::time::sleep(TICK.min(total_duration - elapsed)).await;
+        }
+        finished.store(true, Ordering::Relaxed);
+        for handle in handles {
+            // A worker only fails if a decision panics: its decision is not recorded.
+            let _ = handle.await;
+        }
+        let elapsed = started_at.elapsed();
+
+        recorder.report(elapsed)
+    }
+}
+
+/// Returns the number of workers prescribed by the ramp profile at the given time.
+fn concurrency_at(stages: &[Stage], elapsed: Duration) -> usize {
+    let mut start = 0;
+    let mut stage_start = Duration::ZERO;
+    for stage in stages {
+        let stage_end = stage_start + stage.duration;
+        if elapsed < stage_end {
+            let progress = (elapsed - stage_start).as_secs_f64() / stage.duration.as_secs_f64();
+            let concurrency = start as f64 + (stage.target as f64 - start as f64) * progress;
+            return concurrency.round() as usize;
+        }
+        start = stage.target;
+        stage_start = stage_end;
+    }
+    start
+}
+
+async fn worker(
+    index: usize,
+    scenarios: Arc<[Scenario]>,
+    total_weight: u64,
+    workers: Arc<AtomicUsize>,
+    finished: Arc<AtomicBool>,
+    sequence: Arc<AtomicU64>,
+    recorder: Arc<Recorder>,
+) {
+    while !finished.load(Ordering::Relaxed) {
+        if index >= workers.load(Ordering::Relaxed) {
+            tokio::time::sleep(TICK).await;
+            continue;
+        }
+        let n = sequence.fetch_add(1, Ordering::Relaxed);
+        let scenario = pick_scenario(&scenarios, n % total_weight);
+        let started_at = Instant::now();
+        let outcome = (scenarios[scenario].run)(n).await;
+        recorder.record(scenario, started_at.elapsed(), outcome);
+    }
+}
+
+/// Returns the index of the scenario owning the given slot of the total weight.
+fn pick_scenario(scenarios: &[Scenario], mut slot: u64) -> usize {
+    for (index, scenario) in scenarios.iter().enumerate() {
+        if slot < scenario.weight {
+            return index;
+        }
+        slot -= scenario.weight;
+    }
+    unreachable!("the slot is lower than the total weight")
+}
+
+struct Recorder {
+    names: Vec<&'static str>,
+    stats: Mutex<Vec<LoadStats>>,
+    is_conflict: Arc<dyn Fn(&BoxDynError) -> bool + Send + Sync>,
+}
+
+impl Recorder {
+    fn new(
+        scenarios: &[Scenario],
+        is_conflict: Arc<dyn Fn(&BoxDynError) -> bool + Send + Sync>,
+    ) -> Self {
+        Self {
+            names: scenarios.iter().map(|scenario| scenario.name).collect(),
+            stats: Mutex::new(scenarios.iter().map(|_| LoadStats::default()).collect()),
+            is_conflict,
+        }
+    }
+
+    fn record(&self, scenario: usize, latency: Duration, outcome: Outcome) {
+        let conflict = matches!(&outcome, Outcome::Failed(err) if (self.is_conflict)(err));
+        let mut stats = self.stats.lock().unwrap();
+        let stats = &mut stats[scenario];
+        stats.decisions += 1;
+        match outcome {
+            Outcome::Succeeded => stats.succeeded += 1,
+            Outcome::Rejected => stats.rejected += 1,
+            Outcome::Failed(_) if conflict => stats.conflicts += 1,
+            Outcome::Failed(_) => stats.failed += 1,
+        }
+        stats.latencies.push(latency);
+    }
+
+    fn report(&self, elapsed: Duration) -> LoadReport {
+        let mut stats = std::mem::take(&mut *self.stats.lock().unwrap());
+        let mut total = LoadStats::default();
+        for stats in &mut stats {
+            stats.latencies.sort_unstable();
+            total.merge(stats);
+        }
+        total.latencies.sort_unstable();
+        LoadReport {
+            elapsed,
+            total,
+            scenarios: self.names.iter().copied().zip(stats).collect(),
+        }
+    }
+}
+
+/// The outcomes and the latencies of the decisions of a load test.
+#[derive(Debug, Clone, Default)]
+pub struct LoadStats {
+    /// The number of decisions run.
+    pub decisions: u64,
+    /// The number of decisions whose events have been persisted.
+    pub succeeded: u64,
+    /// The number of decisions rejected by the business rules or by their validation.
+    pub rejected: u64,
+    /// The number of decisions failed because of a concurrency conflict.
+    pub conflicts: u64,
+    /// The number of decisions failed because of any other error of the state store.
+    pub failed: u64,
+    latencies: Vec<Duration>,
+}
+
+impl LoadStats {
+    fn merge(&mut self, other: &LoadStats) {
+        self.decisions += other.decisions;
+        self.succeeded += other.succeeded;
+        self.rejected += other.rejected;
+        self.conflicts += other.conflicts;
+        self.failed += other.failed;
+        self.latencies.extend_from_slice(&other.latencies);
+    }
+
+    /// Returns the latency below which the given percentage of the decisions completed.
+    ///
+    /// # Arguments
+    ///
+    /// * `percentile` - The percentile, between 0 and 100.
+    pub fn latency(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * self.latencies.len() as f64).ceil();
+        self.latencies[(rank as usize).clamp(1, self.latencies.len()) - 1]
+    }
+
+    /// Returns the median latency of the decisions.
+    pub fn p50(&self) -> Duration {
+        self.latency(50.0)
+    }
+
+    /// Returns the 99th percentile of the latency of the decisions.
+    pub fn p99(&self) -> Duration {
+        self.latency(99.0)
+    }
+
+    /// Returns the highest latency of the decisions.
+    pub fn max(&self) -> Duration {
+        self.latency(100.0)
+    }
+
+    /// Returns the share of the decisions failed because of a concurrency conflict, between 0 and 1.
+    pub fn conflict_rate(&self) -> f64 {
+        if self.decisions == 0 {
+            return 0.0;
+        }
+        self.conflicts as f64 / self.decisions as f64
+    }
+}
+
+/// The report of a load test.
+#[derive(Debug, Clone)]
+pub struct LoadReport {
+    /// The duration of the load test.
+    pub elapsed: Duration,
+    /// The statistics of all the decisions.
+    pub total: LoadStats,
+    /// The statistics of the decisions of each scenario, in the order the scenarios have been added.
+    pub scenarios: Vec<(&'static str, LoadStats)>,
+}
+
+impl LoadReport {
+    /// Returns the number of decisions run per second.
+    pub fn throughput(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            return 0.0;
+        }
+        self.total.decisions as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} decisions in {:.1?} ({:.1} decisions/s)",
+            self.total.decisions,
+            self.elapsed,
+            self.throughput()
+        )?;
+        writeln!(
+            f,
+            "{:<20} {:>10} {:>10} {:>10} {:>10} {:>10} {:>12} {:>12}",
+            "scenario", "decisions", "succeeded", "rejected", "conflicts", "failed", "p50", "p99"
+        )?;
+        for (name, stats) in self
+            .scenarios
+            .iter()
+            .map(|(name, stats)| (*name, stats))
+            .chain(std::iter::once(("total", &self.total)))
+        {
+            writeln!(
+                f,
+                "{:<20} {:>10} {:>10} {:>10} {:>10} {:>10} {:>12.1?} {:>12.1?}",
+                name,
+                stats.decisions,
+                stats.succeeded,
+                stats.rejected,
+                stats.conflicts,
+                stats.failed,
+                stats.p50(),
+                stats.p99()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::utils::tests::{cart, item_added_event, Cart, CartError, ShoppingCartEvent};
+    use crate::PersistedEvent;
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("conflict")]
+    struct Conflict;
+
+    struct AddItem(u64);
+
+    impl Decision for AddItem {
+        type Event = ShoppingCartEvent;
+        type StateQuery = Cart;
+        type Error = CartError;
+
+        fn state_query(&self) -> Cart {
+            cart("c1", [])
+        }
+
+        fn process(&self, _state: &Cart) -> Result<Vec<ShoppingCartEvent>, CartError> {
+            Ok(vec![item_added_event(&self.0.to_string(), "c1")])
+        }
+    }
+
+    /// Fails every third decision with a conflict and rejects every fifth.
+    #[derive(Clone)]
+    struct FlakyDecisionMaker;
+
+    #[async_trait]
+    impl MakeDecision<i64, AddItem> for FlakyDecisionMaker {
+        async fn make_decision(
+            &self,
+            decision: AddItem,
+        ) -> Result<Vec<PersistedEvent<i64, ShoppingCartEvent>>, DecisionError<CartError>> {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            match decision.0 {
+                n if n % 3 == 0 => Err(DecisionError::StateStore(Box::new(Conflict))),
+                n if n % 5 == 0 => Err(DecisionError::Domain(CartError("full".to_string()))),
+                n => Ok(vec![PersistedEvent::new(
+                    n as i64,
+                    item_added_event(&n.to_string(), "c1"),
+                )]),
+            }
+        }
+    }
+
+    #[test]
+    fn it_ramps_the_concurrency_linearly() {
+        let stages = [
+            Stage {
+                duration: Duration::from_secs(10),
+                target: 10,
+            },
+            Stage {
+                duration: Duration::from_secs(10),
+                target: 10,
+            },
+            Stage {
+                duration: Duration::ZERO,
+                target: 20,
+            },
+            Stage {
+                duration: Duration::from_secs(10),
+                target: 0,
+            },
+        ];
+
+        assert_eq!(concurrency_at(&stages, Duration::ZERO), 0);
+        assert_eq!(concurrency_at(&stages, Duration::from_secs(5)), 5);
+        assert_eq!(concurrency_at(&stages, Duration::from_secs(15)), 10);
+        assert_eq!(concurrency_at(&stages, Duration::from_secs(20)), 20);
+        assert_eq!(concurrency_at(&stages, Duration::from_secs(25)), 10);
+        assert_eq!(concurrency_at(&stages, Duration::from_secs(30)), 0);
+    }
+
+    #[tokio::test]
+    async fn it_reports_the_outcomes_of_the_decisions() {
+        let report = LoadTest::new(FlakyDecisionMaker)
+            .scenario("add_item", 3, AddItem)
+            .scenario("add_other_item", 1, AddItem)
+            .conflicts_when(|err| err.is::<Conflict>())
+            .ramp(Duration::ZERO, 4)
+            .hold(Duration::from_millis(200))
+            .run()
+            .await;
+
+        let total = &report.total;
+        assert!(total.decisions > 0);
+        assert_eq!(
+            total.decisions,
+            total.succeeded + total.rejected + total.conflicts + total.failed
+        );
+        assert!(total.conflicts > 0);
+        assert!(total.rejected > 0);
+        assert_eq!(total.failed, 0);
+        assert!(total.p99() >= Duration::from_millis(1));
+        assert!(total.p50() <= total.p99() && total.p99() <= total.max());
+        assert_eq!(
+            report
+                .scenarios
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>(),
+            vec!["add_item", "add_other_item"]
+        );
+        let add_item = report.scenarios[0].1.decisions as f64;
+        assert!((add_item / total.decisions as f64 - 0.75).abs() < 0.05);
+        assert!(report.throughput() > 0.0);
+    }
+}
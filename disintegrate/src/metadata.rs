@@ -0,0 +1,172 @@
+//! Event metadata
+//!
+//! The metadata describe the context in which the events were appended, such as the request that caused them
+//! or the user who made the decision, without being part of the domain model. They are given to the event
+//! store when the events are appended, and returned with the persisted events to the states and the event
+//! listeners.
+//!
+//! # Examples
+//!
+//! ```
+//! use disintegrate::Metadata;
+//!
+//! let metadata = Metadata::new()
+//!     .with_correlation_id("request-1")
+//!     .with_user_id("alice")
+//!     .with("tenant", "acme");
+//!
+//! assert_eq!(metadata.correlation_id(), Some("request-1"));
+//! assert_eq!(metadata.get("tenant"), Some("acme"));
+//! ```
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{event::EventId, Event, PersistedEvent};
+
+/// The key of the correlation ID, shared by all the events of the same business transaction.
+pub const CORRELATION_ID: &str = "correlation_id";
+/// The key of the causation ID, the ID of the message that caused the events.
+pub const CAUSATION_ID: &str = "causation_id";
+/// The key of the ID of the user who caused the events.
+pub const USER_ID: &str = "user_id";
+
+/// The metadata of the appended events, as a map of string values.
+///
+/// Besides the well-known keys, `correlation_id`, `causation_id` and `user_id`, the metadata can hold
+/// custom keys.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Metadata(BTreeMap<String, String>);
+
+impl Metadata {
+    /// Creates empty metadata.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the metadata of the events caused by a persisted event, e.g. in a process manager.
+    ///
+    /// The causation ID is the ID of the event, and the correlation ID is inherited from the event,
+    /// or is the ID of the event if it has none.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event causing the new events.
+    pub fn caused_by<ID: EventId, E: Event>(event: &PersistedEvent<ID, E>) -> Self {
+        let event_id = event.id().to_string();
+        let correlation_id = event
+            .metadata()
+            .correlation_id()
+            .map(str::to_string)
+            .unwrap_or_else(|| event_id.clone());
+        Self::new()
+            .with_correlation_id(correlation_id)
+            .with_causation_id(event_id)
+    }
+
+    /// Sets the value of a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the metadata.
+    /// * `value` - The value of the key.
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.0.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets the correlation ID.
+    pub fn with_correlation_id(self, correlation_id: impl Into<String>) -> Self {
+        self.with(CORRELATION_ID, correlation_id)
+    }
+
+    /// Sets the causation ID.
+    pub fn with_causation_id(self, causation_id: impl Into<String>) -> Self {
+        self.with(CAUSATION_ID, causation_id)
+    }
+
+    /// Sets the ID of the user.
+    pub fn with_user_id(self, user_id: impl Into<String>) -> Self {
+        self.with(USER_ID, user_id)
+    }
+
+    /// Sets the value of a key, replacing the previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the metadata.
+    /// * `value` - The value of the key.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.0.insert(key.into(), value.into());
+    }
+
+    /// Adds the keys of other metadata, replacing the values of the keys already set.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The metadata to add.
+    pub fn merge(mut self, other: Metadata) -> Self {
+        self.0.extend(other.0);
+        self
+    }
+
+    /// Returns the value of a key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// Returns the correlation ID.
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.get(CORRELATION_ID)
+    }
+
+    /// Returns the causation ID.
+    pub fn causation_id(&self) -> Option<&str> {
+        self.get(CAUSATION_ID)
+    }
+
+    /// Returns the ID of the user.
+    pub fn user_id(&self) -> Option<&str> {
+        self.get(USER_ID)
+    }
+
+    /// Returns `true` if the metadata have no keys.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns an iterator over the keys and the values, ordered by key.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::item_added_event;
+
+    #[test]
+    fn it_correlates_the_events_caused_by_an_event() {
+        let event = PersistedEvent::new(7, item_added_event("p1", "c1"));
+
+        let metadata = Metadata::caused_by(&event);
+
+        assert_eq!(metadata.correlation_id(), Some("7"));
+        assert_eq!(metadata.causation_id(), Some("7"));
+    }
+
+    #[test]
+    fn it_inherits_the_correlation_id_of_the_causing_event() {
+        let event = PersistedEvent::new(7, item_added_event("p1", "c1"))
+            .with_metadata(Metadata::new().with_correlation_id("request-1"));
+
+        let metadata = Metadata::caused_by(&event);
+
+        assert_eq!(metadata.correlation_id(), Some("request-1"));
+        assert_eq!(metadata.causation_id(), Some("7"));
+    }
+}
@@ -0,0 +1,120 @@
+//! Store migrations bring the schema of a storage backend to the version required by the library.
+//!
+//! The `StoreMigrations` trait is implemented by the storage backends, so that the same orchestration
+//! can plan, preview and apply the migrations regardless of the underlying database.
+use async_trait::async_trait;
+
+/// The migrations required to bring a store to the schema version of the library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationPlan {
+    /// The schema version of the store, or `None` if the store has not been initialized.
+    pub current_version: Option<i32>,
+    /// The schema version required by the library.
+    pub target_version: i32,
+    /// The names of the migrations to apply, in order.
+    pub migrations: Vec<String>,
+}
+
+impl MigrationPlan {
+    /// Checks if the store is already at the target schema version.
+    pub fn is_up_to_date(&self) -> bool {
+        self.migrations.is_empty()
+    }
+}
+
+/// The migrations of a storage backend.
+#[async_trait]
+pub trait StoreMigrations {
+    type Error: Send + Sync;
+
+    /// Computes the migrations required to bring the store to the target schema version, without applying them.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the migration plan, or an error if the store schema cannot be migrated.
+    async fn plan(&self) -> Result<MigrationPlan, Self::Error>;
+
+    /// Applies the migrations of the plan and stamps the target schema version in the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - The plan computed by `plan`.
+    async fn apply(&self, plan: &MigrationPlan) -> Result<(), Self::Error>;
+
+    /// Plans and applies the migrations of the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `dry_run` - If `true`, the plan is returned without being applied.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the migration plan, applied unless `dry_run` is set.
+    async fn migrate(&self, dry_run: bool) -> Result<MigrationPlan, Self::Error> {
+        let plan = self.plan().await?;
+        if !dry_run && !plan.is_up_to_date() {
+            self.apply(&plan).await?;
+        }
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    struct InMemoryStore {
+        version: Mutex<Option<i32>>,
+    }
+
+    #[async_trait]
+    impl StoreMigrations for InMemoryStore {
+        type Error = String;
+
+        async fn plan(&self) -> Result<MigrationPlan, Self::Error> {
+            let current_version = *self.version.lock().unwrap();
+            let migrations = match current_version {
+                None => vec!["v1_initial_schema".to_string(), "v2_indexes".to_string()],
+                Some(1) => vec!["v2_indexes".to_string()],
+                Some(_) => vec![],
+            };
+            Ok(MigrationPlan {
+                current_version,
+                target_version: 2,
+                migrations,
+            })
+        }
+
+        async fn apply(&self, plan: &MigrationPlan) -> Result<(), Self::Error> {
+            *self.version.lock().unwrap() = Some(plan.target_version);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_previews_the_migrations_without_applying_them() {
+        let store = InMemoryStore {
+            version: Mutex::new(Some(1)),
+        };
+
+        let plan = store.migrate(true).await.unwrap();
+
+        assert_eq!(plan.migrations, vec!["v2_indexes"]);
+        assert_eq!(*store.version.lock().unwrap(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn it_applies_the_planned_migrations() {
+        let store = InMemoryStore {
+            version: Mutex::new(None),
+        };
+
+        let plan = store.migrate(false).await.unwrap();
+
+        assert_eq!(plan.current_version, None);
+        assert_eq!(plan.migrations.len(), 2);
+        assert!(store.plan().await.unwrap().is_up_to_date());
+    }
+}
@@ -0,0 +1,238 @@
+//! Projections build the read models from the events.
+//!
+//! A `Projection` is an `EventListener` that owns a read model: besides handling the events, it knows
+//! how to reset the read model, so the backends can rebuild it from the beginning of the event stream.
+//! `ProjectionListener` turns a projection into an `EventListener`, deriving an `IdempotencyKey`
+//! for each event.
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::{
+    event::{Event, EventId, PersistedEvent},
+    listener::{EventListener, Retraction},
+    stream_query::StreamQuery,
+};
+
+/// The key identifying the application of an event to a projection.
+///
+/// The events are delivered at least once, so the same event may be applied more than once. The key is the
+/// same for every delivery of the event to the projection: it can be stored together with the read model,
+/// or sent to an external system, to recognize the events already applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IdempotencyKey<ID> {
+    projection: &'static str,
+    event_id: ID,
+}
+
+impl<ID: EventId> IdempotencyKey<ID> {
+    /// Creates a new `IdempotencyKey`.
+    ///
+    /// # Arguments
+    ///
+    /// * `projection` - The name of the projection.
+    /// * `event_id` - The ID of the applied event.
+    pub fn new(projection: &'static str, event_id: ID) -> Self {
+        Self {
+            projection,
+            event_id,
+        }
+    }
+
+    /// Returns the name of the projection.
+    pub fn projection(&self) -> &'static str {
+        self.projection
+    }
+
+    /// Returns the ID of the applied event.
+    pub fn event_id(&self) -> ID {
+        self.event_id
+    }
+}
+
+impl<ID: EventId> fmt::Display for IdempotencyKey<ID> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.projection, self.event_id)
+    }
+}
+
+/// Represents a projection, which builds a read model from the persisted events.
+#[async_trait]
+pub trait Projection<ID: EventId, E: Event + Clone>: Send + Sync {
+    /// The type of error that may occur while applying an event or resetting the read model.
+    type Error;
+
+    /// Returns the unique name of the projection.
+    ///
+    /// The name is also the ID of the event listener running the projection.
+    fn name(&self) -> &'static str;
+
+    /// Returns the stream query of the events applied to the read model.
+    fn query(&self) -> &StreamQuery<ID, E>;
+
+    /// Applies an event to the read model.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to apply.
+    /// * `key` - The idempotency key of the event, the same for every delivery of the event.
+    async fn apply(
+        &self,
+        event: PersistedEvent<ID, E>,
+        key: IdempotencyKey<ID>,
+    ) -> Result<(), Self::Error>;
+
+    /// Deletes the content of the read model, before it is rebuilt from the beginning of the event stream.
+    async fn reset(&self) -> Result<(), Self::Error>;
+
+    /// Removes the effects of a retracted event from the read model.
+    ///
+    /// See `EventListener::handle_retraction`. The default implementation ignores the retractions.
+    async fn retract(&self, _retraction: Retraction<ID>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<ID, E, P> Projection<ID, E> for Arc<P>
+where
+    ID: EventId,
+    E: Event + Clone + Send + Sync + 'static,
+    P: Projection<ID, E> + ?Sized,
+{
+    type Error = P::Error;
+
+    fn name(&self) -> &'static str {
+        self.as_ref().name()
+    }
+
+    fn query(&self) -> &StreamQuery<ID, E> {
+        self.as_ref().query()
+    }
+
+    async fn apply(
+        &self,
+        event: PersistedEvent<ID, E>,
+        key: IdempotencyKey<ID>,
+    ) -> Result<(), Self::Error> {
+        self.as_ref().apply(event, key).await
+    }
+
+    async fn reset(&self) -> Result<(), Self::Error> {
+        self.as_ref().reset().await
+    }
+
+    async fn retract(&self, retraction: Retraction<ID>) -> Result<(), Self::Error> {
+        self.as_ref().retract(retraction).await
+    }
+}
+
+/// An `EventListener` applying the events to a `Projection`.
+#[derive(Debug, Clone)]
+pub struct ProjectionListener<P>(P);
+
+impl<P> ProjectionListener<P> {
+    /// Creates a new `ProjectionListener`.
+    ///
+    /// # Arguments
+    ///
+    /// * `projection` - The projection the events are applied to.
+    pub fn new(projection: P) -> Self {
+        Self(projection)
+    }
+
+    /// Returns the projection the events are applied to.
+    pub fn projection(&self) -> &P {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<ID, E, P> EventListener<ID, E> for ProjectionListener<P>
+where
+    ID: EventId,
+    E: Event + Clone + Send + Sync + 'static,
+    P: Projection<ID, E>,
+{
+    type Error = P::Error;
+
+    fn id(&self) -> &'static str {
+        self.0.name()
+    }
+
+    fn query(&self) -> &StreamQuery<ID, E> {
+        self.0.query()
+    }
+
+    async fn handle(&self, event: PersistedEvent<ID, E>) -> Result<(), Self::Error> {
+        let key = IdempotencyKey::new(self.0.name(), event.id());
+        self.0.apply(event, key).await
+    }
+
+    async fn handle_retraction(&self, retraction: Retraction<ID>) -> Result<(), Self::Error> {
+        self.0.retract(retraction).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::query;
+    use crate::utils::tests::{item_added_event, ShoppingCartEvent};
+
+    struct CartCounter {
+        query: StreamQuery<i64, ShoppingCartEvent>,
+        applied: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Projection<i64, ShoppingCartEvent> for CartCounter {
+        type Error = std::convert::Infallible;
+
+        fn name(&self) -> &'static str {
+            "cart_counter"
+        }
+
+        fn query(&self) -> &StreamQuery<i64, ShoppingCartEvent> {
+            &self.query
+        }
+
+        async fn apply(
+            &self,
+            _event: PersistedEvent<i64, ShoppingCartEvent>,
+            key: IdempotencyKey<i64>,
+        ) -> Result<(), Self::Error> {
+            self.applied.lock().unwrap().push(key.to_string());
+            Ok(())
+        }
+
+        async fn reset(&self) -> Result<(), Self::Error> {
+            self.applied.lock().unwrap().clear();
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_applies_the_events_with_their_idempotency_key() {
+        let listener = ProjectionListener::new(Arc::new(CartCounter {
+            query: query!(ShoppingCartEvent),
+            applied: Mutex::new(vec![]),
+        }));
+
+        listener
+            .handle(PersistedEvent::new(7, item_added_event("p1", "c1")))
+            .await
+            .unwrap();
+
+        assert_eq!(EventListener::id(&listener), "cart_counter");
+        assert_eq!(
+            *listener.projection().applied.lock().unwrap(),
+            vec!["cart_counter:7".to_string()]
+        );
+        listener.projection().reset().await.unwrap();
+        assert!(listener.projection().applied.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,397 @@
+//! Reconciliation of the read models with the states rebuilt from the events.
+//!
+//! A projection bug silently corrupts a read model: the events are right, but the rows derived from them
+//! are not. A `Reconciliation` periodically samples some identifiers, rebuilds their state from the events
+//! and compares it with the read model, reporting the drifts to a `DriftObserver`.
+use std::future::Future;
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::future::{select, Either};
+use tokio::time::MissedTickBehavior;
+
+use crate::event::{Event, EventId};
+use crate::{BoxDynError, LoadState};
+
+/// A read model not matching the state rebuilt from the events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Drift<ID: EventId, K, S> {
+    /// The identifier whose read model drifted.
+    pub identifier: K,
+    /// The version of the state rebuilt from the events.
+    pub version: ID,
+    /// The state rebuilt from the events.
+    pub expected: S,
+    /// The state read from the read model.
+    pub actual: S,
+}
+
+/// The outcome of a reconciliation run.
+#[derive(Debug)]
+pub struct ReconciliationReport<ID: EventId, K, S> {
+    /// The number of identifiers checked.
+    pub checked: usize,
+    /// The drifts found.
+    pub drifts: Vec<Drift<ID, K, S>>,
+    /// The identifiers that could not be checked, together with the error.
+    pub failures: Vec<(K, BoxDynError)>,
+}
+
+/// An observer notified of the drifts found by a `Reconciliation`.
+///
+/// Implementations can log the drifts, export them as metrics or raise an alert.
+#[async_trait]
+pub trait DriftObserver<ID: EventId, K, S>: Send + Sync {
+    /// Called for each drift found.
+    async fn drift(&self, drift: &Drift<ID, K, S>);
+}
+
+/// Indicates that the drifts are only returned in the `ReconciliationReport`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoDriftObserver;
+
+#[async_trait]
+impl<ID, K, S> DriftObserver<ID, K, S> for NoDriftObserver
+where
+    ID: EventId,
+    K: Sync,
+    S: Sync,
+{
+    async fn drift(&self, _drift: &Drift<ID, K, S>) {}
+}
+
+#[async_trait]
+impl<ID, K, S, T> DriftObserver<ID, K, S> for std::sync::Arc<T>
+where
+    ID: EventId,
+    K: Sync,
+    S: Sync,
+    T: DriftObserver<ID, K, S>,
+{
+    async fn drift(&self, drift: &Drift<ID, K, S>) {
+        (**self).drift(drift).await
+    }
+}
+
+/// Compares periodically a read model with the states rebuilt from the events.
+///
+/// At every run, the `identifiers` query enumerates the identifiers of the read model, e.g. the IDs of the
+/// carts, and a sample of them is checked: the `state_query` function builds the state query of an identifier,
+/// which is loaded from the events, and the `read_model` function reads the same state from the read model.
+/// The samples rotate over the identifiers, so that all of them are eventually checked.
+///
+/// The read model is updated asynchronously, so it may lag behind the events. The grace period gives it time to
+/// catch up: the drifted identifiers are checked again after the grace period, and reported only if they still drift.
+pub struct Reconciliation<IF, QF, RF, O = NoDriftObserver> {
+    period: Duration,
+    identifiers: IF,
+    state_query: QF,
+    read_model: RF,
+    observer: O,
+    sample_size: usize,
+    grace_period: Duration,
+    offset: AtomicUsize,
+}
+
+impl<IF, QF, RF> Reconciliation<IF, QF, RF> {
+    /// Creates a new `Reconciliation` run every `period`, checking up to 100 identifiers per run.
+    ///
+    /// # Parameters
+    ///
+    /// - `period`: The interval between two runs.
+    /// - `identifiers`: An async function returning the identifiers of the read model.
+    /// - `state_query`: A function building the state query of an identifier.
+    /// - `read_model`: An async function reading the state of an identifier from the read model.
+    pub fn every(period: Duration, identifiers: IF, state_query: QF, read_model: RF) -> Self {
+        Self {
+            period,
+            identifiers,
+            state_query,
+            read_model,
+            observer: NoDriftObserver,
+            sample_size: 100,
+            grace_period: Duration::ZERO,
+            offset: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<IF, QF, RF, O> Reconciliation<IF, QF, RF, O> {
+    /// Sets the observer notified of the drifts.
+    ///
+    /// # Parameters
+    ///
+    /// - `observer`: The observer of the drifts.
+    pub fn with_observer<U>(self, observer: U) -> Reconciliation<IF, QF, RF, U> {
+        Reconciliation {
+            period: self.period,
+            identifiers: self.identifiers,
+            state_query: self.state_query,
+            read_model: self.read_model,
+            observer,
+            sample_size: self.sample_size,
+            grace_period: self.grace_period,
+            offset: self.offset,
+        }
+    }
+
+    /// Sets the maximum number of identifiers checked per run.
+    ///
+    /// # Parameters
+    ///
+    /// - `sample_size`: The maximum number of identifiers checked per run.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sample_size` is zero.
+    pub fn with_sample_size(mut self, sample_size: usize) -> Self {
+        assert!(sample_size > 0, "the sample size must be greater than zero");
+        self.sample_size = sample_size;
+        self
+    }
+
+    /// Sets the time given to the read model to catch up before a drift is reported.
+    ///
+    /// # Parameters
+    ///
+    /// - `grace_period`: The delay before the drifted identifiers are checked again.
+    pub fn with_grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// Checks a sample of the identifiers.
+    ///
+    /// # Parameters
+    ///
+    /// - `state_store`: The state store rebuilding the states from the events.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the report of the run, or an error if the identifiers cannot be enumerated.
+    pub async fn run_once<ID, K, S, E, SS, IFut, RFut>(
+        &self,
+        state_store: &SS,
+    ) -> Result<ReconciliationReport<ID, K, S>, BoxDynError>
+    where
+        IF: Fn() -> IFut,
+        IFut: Future<Output = Result<Vec<K>, BoxDynError>>,
+        QF: Fn(&K) -> S,
+        RF: Fn(K) -> RFut,
+        RFut: Future<Output = Result<S, BoxDynError>>,
+        ID: EventId,
+        K: Clone,
+        S: PartialEq,
+        E: Event + Clone,
+        SS: LoadState<ID, S, E>,
+        O: DriftObserver<ID, K, S>,
+    {
+        let sample = self.sample((self.identifiers)().await?);
+        let mut report = ReconciliationReport {
+            checked: sample.len(),
+            drifts: vec![],
+            failures: vec![],
+        };
+        let mut drifted = vec![];
+        for identifier in sample {
+            match self.check(state_store, identifier.clone()).await {
+                Ok(Some(drift)) => drifted.push(drift),
+                Ok(None) => (),
+                Err(err) => report.failures.push((identifier, err)),
+            }
+        }
+        if !drifted.is_empty() && !self.grace_period.is_zero() {
+            tokio::time::sleep(self.grace_period).await;
+            for drift in std::mem::take(&mut drifted) {
+                let identifier = drift.identifier;
+                match self.check(state_store, identifier.clone()).await {
+                    Ok(Some(drift)) => drifted.push(drift),
+                    Ok(None) => (),
+                    Err(err) => report.failures.push((identifier, err)),
+                }
+            }
+        }
+        for drift in &drifted {
+            self.observer.drift(drift).await;
+        }
+        report.drifts = drifted;
+        Ok(report)
+    }
+
+    /// Runs the reconciliation every `period` until the `shutdown` future completes.
+    ///
+    /// The errors of a run do not stop the schedule: the next run is attempted at the next tick.
+    /// Use a `DriftObserver` to be notified of the drifts.
+    ///
+    /// # Parameters
+    ///
+    /// - `state_store`: The state store rebuilding the states from the events.
+    /// - `shutdown`: A future that represents the shutdown signal.
+    pub async fn run<ID, K, S, E, SS, IFut, RFut>(
+        &self,
+        state_store: &SS,
+        shutdown: impl Future<Output = ()>,
+    ) where
+        IF: Fn() -> IFut,
+        IFut: Future<Output = Result<Vec<K>, BoxDynError>>,
+        QF: Fn(&K) -> S,
+        RF: Fn(K) -> RFut,
+        RFut: Future<Output = Result<S, BoxDynError>>,
+        ID: EventId,
+        K: Clone,
+        S: PartialEq,
+        E: Event + Clone,
+        SS: LoadState<ID, S, E>,
+        O: DriftObserver<ID, K, S>,
+    {
+        let mut interval = tokio::time::interval(self.period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut shutdown = pin!(shutdown);
+        loop {
+            let next_run = pin!(async {
+                interval.tick().await;
+                let _ = self.run_once(state_store).await;
+            });
+            if let Either::Left(_) = select(shutdown.as_mut(), next_run).await {
+                return;
+            }
+        }
+    }
+
+    /// Selects the identifiers of the run, starting after the last identifier checked by the previous run.
+    fn sample<K>(&self, mut identifiers: Vec<K>) -> Vec<K> {
+        if identifiers.len() <= self.sample_size {
+            return identifiers;
+        }
+        let start = self.offset.fetch_add(self.sample_size, Ordering::Relaxed) % identifiers.len();
+        identifiers.rotate_left(start);
+        identifiers.truncate(self.sample_size);
+        identifiers
+    }
+
+    async fn check<ID, K, S, E, SS, RFut>(
+        &self,
+        state_store: &SS,
+        identifier: K,
+    ) -> Result<Option<Drift<ID, K, S>>, BoxDynError>
+    where
+        QF: Fn(&K) -> S,
+        RF: Fn(K) -> RFut,
+        RFut: Future<Output = Result<S, BoxDynError>>,
+        ID: EventId,
+        K: Clone,
+        S: PartialEq,
+        E: Event + Clone,
+        SS: LoadState<ID, S, E>,
+    {
+        let loaded = state_store.load((self.state_query)(&identifier)).await?;
+        let actual = (self.read_model)(identifier.clone()).await?;
+        if loaded.state == actual {
+            return Ok(None);
+        }
+        Ok(Some(Drift {
+            identifier,
+            version: loaded.version,
+            expected: loaded.state,
+            actual,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::utils::tests::*;
+    use crate::LoadedState;
+
+    struct EventStates(HashMap<String, Vec<String>>);
+
+    #[async_trait]
+    impl LoadState<i64, Cart, ShoppingCartEvent> for EventStates {
+        async fn load(&self, mut cart: Cart) -> Result<LoadedState<i64, Cart>, BoxDynError> {
+            cart.items = self.0.get(&cart.cart_id).cloned().unwrap_or_default();
+            Ok(LoadedState {
+                state: cart,
+                version: 3,
+            })
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingObserver(Mutex<Vec<Drift<i64, String, Cart>>>);
+
+    #[async_trait]
+    impl DriftObserver<i64, String, Cart> for RecordingObserver {
+        async fn drift(&self, drift: &Drift<i64, String, Cart>) {
+            self.0.lock().unwrap().push(drift.clone());
+        }
+    }
+
+    fn event_states() -> EventStates {
+        EventStates(HashMap::from([
+            ("c1".to_string(), vec!["p1".to_string()]),
+            ("c2".to_string(), vec!["p1".to_string(), "p2".to_string()]),
+            ("c3".to_string(), vec![]),
+        ]))
+    }
+
+    async fn read_model(cart_id: String) -> Result<Cart, BoxDynError> {
+        match cart_id.as_str() {
+            "c2" => Ok(cart("c2", ["p1".to_string()])),
+            "c3" => Err("read model unavailable".into()),
+            _ => Ok(cart("c1", ["p1".to_string()])),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_reports_the_read_models_drifted_from_the_events() {
+        let observer = Arc::new(RecordingObserver::default());
+        let reconciliation = Reconciliation::every(
+            Duration::from_secs(60),
+            || async { Ok(vec!["c1".to_string(), "c2".to_string(), "c3".to_string()]) },
+            |cart_id: &String| Cart::new(cart_id),
+            read_model,
+        )
+        .with_observer(observer.clone());
+
+        let report = reconciliation.run_once(&event_states()).await.unwrap();
+
+        let drift = Drift {
+            identifier: "c2".to_string(),
+            version: 3,
+            expected: cart("c2", ["p1".to_string(), "p2".to_string()]),
+            actual: cart("c2", ["p1".to_string()]),
+        };
+        assert_eq!(report.checked, 3);
+        assert_eq!(report.drifts, vec![drift.clone()]);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].0, "c3");
+        assert_eq!(*observer.0.lock().unwrap(), vec![drift]);
+    }
+
+    #[tokio::test]
+    async fn it_rotates_the_sampled_identifiers() {
+        let reconciliation = Reconciliation::every(
+            Duration::from_secs(60),
+            || async { Ok(vec!["c1".to_string(), "c2".to_string(), "c3".to_string()]) },
+            |cart_id: &String| Cart::new(cart_id),
+            read_model,
+        )
+        .with_sample_size(2);
+
+        let first = reconciliation.run_once(&event_states()).await.unwrap();
+        let second = reconciliation.run_once(&event_states()).await.unwrap();
+
+        assert_eq!(first.checked, 2);
+        assert_eq!(first.drifts.len(), 1);
+        assert!(first.failures.is_empty());
+        assert_eq!(second.checked, 2);
+        assert!(second.drifts.is_empty());
+        assert_eq!(second.failures.len(), 1);
+    }
+}
@@ -0,0 +1,224 @@
+//! Snapshot stores
+//!
+//! The snapshots of the states can be large, and do not need to live in the same database as the events:
+//! they are only an optimization, and can be dropped at any time. This module splits the snapshotting logic,
+//! i.e. when a snapshot is taken and how it is serialized, from the storage of the snapshots, so that the
+//! snapshots can be kept in a cheaper storage than the primary database.
+//!
+//! A `SnapshotStore` only stores serialized snapshots, and `Snapshotter` turns any of them into a
+//! `StateSnapshotter`. `FileSnapshotStore` stores the snapshots in a directory of the file system.
+#[cfg(feature = "tokio")]
+mod file;
+mod policy;
+
+use std::sync::{Arc, OnceLock};
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    BoxDynError, Event, EventId, IntoState, MissingIdentifier, StatePart, StateQuery,
+    StateSnapshotter, StreamQuery,
+};
+
+#[cfg(feature = "tokio")]
+pub use file::FileSnapshotStore;
+pub use policy::{
+    EveryEventsPolicy, IntervalPolicy, NeverPolicy, PerStatePolicy, SizeThresholdPolicy,
+    SnapshotCandidate, SnapshotPolicy,
+};
+
+/// A serialized snapshot of a state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot<ID> {
+    /// The name of the state query.
+    pub name: String,
+    /// The key of the stream query of the state, see [`query_key`].
+    pub query: String,
+    /// The ID of the last event applied to the state.
+    pub version: ID,
+    /// The state serialized as JSON.
+    pub payload: String,
+}
+
+/// A storage of serialized snapshots.
+///
+/// The snapshots are identified by the name of the state query and the key of its stream query.
+#[async_trait]
+pub trait SnapshotStore<ID: EventId>: Send + Sync {
+    /// Loads the snapshot of a state, if it has been stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the state query.
+    /// * `query` - The key of the stream query of the state.
+    async fn load(&self, name: &str, query: &str) -> Result<Option<Snapshot<ID>>, BoxDynError>;
+
+    /// Stores the snapshot of a state, unless a snapshot with the same or a more recent version is already stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - The snapshot to store.
+    async fn store(&self, snapshot: Snapshot<ID>) -> Result<(), BoxDynError>;
+
+    /// Discards a snapshot that cannot be deserialized, e.g. after an incompatible change of the state.
+    ///
+    /// The default implementation keeps the snapshot, which is replaced by the next stored snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - The snapshot that cannot be deserialized.
+    /// * `error` - The deserialization error.
+    async fn discard(&self, _snapshot: Snapshot<ID>, _error: String) -> Result<(), BoxDynError> {
+        Ok(())
+    }
+
+    /// Deletes the snapshots that applied an event, e.g. after the event has been rewritten, so the states are
+    /// rebuilt from the events.
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - The ID of the event: the snapshots of the same or a more recent version are deleted.
+    async fn invalidate_from(&self, version: ID) -> Result<(), BoxDynError>;
+}
+
+/// A `StateSnapshotter` keeping the snapshots in a `SnapshotStore`.
+///
+/// The states are serialized as JSON, and a snapshot of a state is stored when its `SnapshotPolicy` allows it,
+/// by default when more than `every` events have been applied to the state since it was loaded.
+#[derive(Debug, Clone)]
+pub struct Snapshotter<T> {
+    store: T,
+    policy: Arc<dyn SnapshotPolicy>,
+}
+
+impl<T> Snapshotter<T> {
+    /// Creates a new instance of `Snapshotter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The storage of the snapshots.
+    /// * `every` - The frequency of snapshot creation, specified as the number of events between consecutive snapshots.
+    pub fn new(store: T, every: u64) -> Self {
+        Self {
+            store,
+            policy: Arc::new(EveryEventsPolicy(every)),
+        }
+    }
+
+    /// Sets the policy deciding when the snapshots of the states are stored, replacing the frequency.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The snapshot policy, evaluated for each part of a state.
+    pub fn with_policy(mut self, policy: impl SnapshotPolicy + 'static) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+
+    /// Returns the storage of the snapshots.
+    pub fn store(&self) -> &T {
+        &self.store
+    }
+}
+
+#[async_trait]
+impl<ID, T> StateSnapshotter<ID> for Snapshotter<T>
+where
+    ID: EventId,
+    T: SnapshotStore<ID>,
+{
+    async fn load_snapshot<S>(&self, default: StatePart<ID, S>) -> StatePart<ID, S>
+    where
+        S: Send + Sync + DeserializeOwned + StateQuery + 'static,
+    {
+        let query = query_key(&default.query::<ID>());
+        let Ok(Some(snapshot)) = self.store.load(S::NAME, &query).await else {
+            return default;
+        };
+        if snapshot.name != S::NAME || snapshot.query != query {
+            return default;
+        }
+        match serde_json::from_str(&snapshot.payload) {
+            Ok(payload) => StatePart::new(snapshot.version, payload),
+            Err(err) => {
+                // The snapshot is only an optimization: the state is rebuilt from the events
+                // even if the snapshot cannot be discarded.
+                let _ = self.store.discard(snapshot, err.to_string()).await;
+                default
+            }
+        }
+    }
+
+    async fn store_snapshot<S>(&self, state: &StatePart<ID, S>) -> Result<(), BoxDynError>
+    where
+        S: Send + Sync + Serialize + StateQuery + 'static,
+    {
+        if state.applied_events() == 0 {
+            return Ok(());
+        }
+        let query = query_key(&state.query::<ID>());
+        let payload = {
+            // the state is serialized once, either by a policy checking its size or for the snapshot.
+            let serialized = OnceLock::new();
+            let serialize =
+                || serialized.get_or_init(|| serde_json::to_string(&state.clone().into_state()));
+            let size = || serialize().as_ref().map_or(0, String::len);
+            let candidate = SnapshotCandidate {
+                name: S::NAME,
+                query: &query,
+                applied_events: state.applied_events(),
+                size: &size,
+            };
+            if !self.policy.should_snapshot(&candidate) {
+                return Ok(());
+            }
+            match serialized.into_inner() {
+                Some(payload) => payload?,
+                None => serde_json::to_string(&state.clone().into_state())?,
+            }
+        };
+        self.store
+            .store(Snapshot {
+                name: S::NAME.to_string(),
+                query,
+                version: state.version(),
+                payload,
+            })
+            .await
+    }
+}
+
+/// Returns the key identifying a stream query in the snapshot stores.
+///
+/// The key contains the origin, the event types, the domain identifiers and the missing identifier
+/// semantics of each filter of the query. The semantics are left out of the key for `MissingIdentifier::Match`,
+/// so the keys of the snapshots stored before it was introduced are unchanged.
+pub fn query_key<ID: EventId, E: Event + Clone>(query: &StreamQuery<ID, E>) -> String {
+    let mut result = String::new();
+    for f in query.filters() {
+        let excluded_events = if let Some(exclued_events) = f.excluded_events() {
+            format!("-{}", exclued_events.join(","))
+        } else {
+            "".to_string()
+        };
+        let missing_identifier = match f.missing_identifier() {
+            MissingIdentifier::Match => "",
+            MissingIdentifier::NoMatch => "|no_match",
+        };
+        result += &format!(
+            "({}|{}{}|{}{})",
+            f.origin(),
+            f.events().join(","),
+            excluded_events,
+            f.identifiers()
+                .iter()
+                .map(|(k, v)| format!("{k}={v}"))
+                .chain(f.conditions().iter().map(ToString::to_string))
+                .collect::<Vec<_>>()
+                .join(","),
+            missing_identifier
+        );
+    }
+    result
+}
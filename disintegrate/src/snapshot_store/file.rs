@@ -0,0 +1,216 @@
+//! File system snapshot store
+//!
+//! Each snapshot is stored as a JSON file, named after a hash of the name and the query key of the
+//! state. The files are replaced atomically, so a reader never sees a partially written snapshot.
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use uuid::Uuid;
+
+use super::{Snapshot, SnapshotStore};
+use crate::{BoxDynError, EventId};
+
+/// A counter making the names of the temporary files unique within the process.
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A snapshot store keeping the snapshots in a directory of the file system, e.g. a volume shared by the
+/// instances of the application or a bucket mounted on the file system.
+#[derive(Debug, Clone)]
+pub struct FileSnapshotStore {
+    dir: PathBuf,
+}
+
+impl FileSnapshotStore {
+    /// Creates a new instance of `FileSnapshotStore`, creating the directory if it does not exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory where the snapshots are stored.
+    pub async fn new(dir: impl Into<PathBuf>) -> Result<Self, std::io::Error> {
+        let dir = dir.into();
+        tokio::fs::create_dir_all(&dir).await?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, name: &str, query: &str) -> PathBuf {
+        let id = Uuid::new_v5(&Uuid::NAMESPACE_OID, format!("{name}\n{query}").as_bytes());
+        self.dir.join(format!("{id}.json"))
+    }
+
+    async fn read<ID: DeserializeOwned>(
+        &self,
+        name: &str,
+        query: &str,
+    ) -> Result<Option<Snapshot<ID>>, BoxDynError> {
+        match tokio::fs::read(self.path(name, query)).await {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl<ID> SnapshotStore<ID> for FileSnapshotStore
+where
+    ID: EventId + Serialize + DeserializeOwned,
+{
+    async fn load(&self, name: &str, query: &str) -> Result<Option<Snapshot<ID>>, BoxDynError> {
+        self.read(name, query).await
+    }
+
+    async fn store(&self, snapshot: Snapshot<ID>) -> Result<(), BoxDynError> {
+        let stored = self
+            .read::<ID>(&snapshot.name, &snapshot.query)
+            .await
+            .ok()
+            .flatten();
+        if stored.is_some_and(|stored| stored.version >= snapshot.version) {
+            return Ok(());
+        }
+        let path = self.path(&snapshot.name, &snapshot.query);
+        let temp_path = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        tokio::fs::write(&temp_path, serde_json::to_vec(&snapshot)?).await?;
+        if let Err(err) = tokio::fs::rename(&temp_path, &path).await {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    async fn discard(&self, snapshot: Snapshot<ID>, _error: String) -> Result<(), BoxDynError> {
+        match tokio::fs::remove_file(self.path(&snapshot.name, &snapshot.query)).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn invalidate_from(&self, version: ID) -> Result<(), BoxDynError> {
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let data = match tokio::fs::read(&path).await {
+                Ok(data) => data,
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+            // the snapshots that cannot be read are deleted as well, as they may have applied the event.
+            let stale = serde_json::from_slice::<Snapshot<ID>>(&data)
+                .map_or(true, |snapshot| snapshot.version >= version);
+            if stale {
+                match tokio::fs::remove_file(&path).await {
+                    Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::{cart, Cart};
+    use crate::{IntoStatePart, Snapshotter, StateSnapshotter};
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("disintegrate-snapshots-{}", Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn it_stores_and_loads_the_snapshots() {
+        let dir = temp_dir();
+        let snapshotter = Snapshotter::new(FileSnapshotStore::new(&dir).await.unwrap(), 0);
+        let mut state = cart("c1", []).into_state_part();
+        state.mutate_part(crate::PersistedEvent::new(
+            3,
+            crate::utils::tests::item_added_event("p1", "c1"),
+        ));
+
+        snapshotter.store_snapshot(&state).await.unwrap();
+        let loaded: crate::StatePart<i64, Cart> = snapshotter
+            .load_snapshot(cart("c1", []).into_state_part())
+            .await;
+
+        assert_eq!(loaded.version(), 3);
+        assert_eq!(*loaded, cart("c1", ["p1".to_string()]));
+        let other: crate::StatePart<i64, Cart> = snapshotter
+            .load_snapshot(cart("c2", []).into_state_part())
+            .await;
+        assert_eq!(other.version(), 0);
+
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_keeps_the_most_recent_snapshot() {
+        let dir = temp_dir();
+        let store = FileSnapshotStore::new(&dir).await.unwrap();
+        let snapshot = |version: i64| Snapshot {
+            name: "Cart".to_string(),
+            query: "(0|ItemAdded|cart_id=c1)".to_string(),
+            version,
+            payload: "{}".to_string(),
+        };
+
+        store.store(snapshot(5)).await.unwrap();
+        store.store(snapshot(3)).await.unwrap();
+        let stored: Option<Snapshot<i64>> = store
+            .load("Cart", "(0|ItemAdded|cart_id=c1)")
+            .await
+            .unwrap();
+        assert_eq!(stored, Some(snapshot(5)));
+
+        store
+            .discard(snapshot(5), "corrupt".to_string())
+            .await
+            .unwrap();
+        let stored: Option<Snapshot<i64>> = store
+            .load("Cart", "(0|ItemAdded|cart_id=c1)")
+            .await
+            .unwrap();
+        assert_eq!(stored, None);
+
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_invalidates_the_snapshots_from_an_event() {
+        let dir = temp_dir();
+        let store = FileSnapshotStore::new(&dir).await.unwrap();
+        let snapshot = |query: &str, version: i64| Snapshot {
+            name: "Cart".to_string(),
+            query: query.to_string(),
+            version,
+            payload: "{}".to_string(),
+        };
+        store.store(snapshot("c1", 3)).await.unwrap();
+        store.store(snapshot("c2", 5)).await.unwrap();
+        store.store(snapshot("c3", 8)).await.unwrap();
+
+        store.invalidate_from(5).await.unwrap();
+
+        let mut stored = vec![];
+        for query in ["c1", "c2", "c3"] {
+            stored.push(
+                SnapshotStore::<i64>::load(&store, "Cart", query)
+                    .await
+                    .unwrap(),
+            );
+        }
+        assert_eq!(stored, vec![Some(snapshot("c1", 3)), None, None]);
+
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+//! Snapshot policies
+//!
+//! A `SnapshotPolicy` decides, for each part of a state stored by a `Snapshotter`, whether a snapshot is
+//! taken. The high-churn states benefit from frequent snapshots, while the states rebuilt from a handful of
+//! events need none: `PerStatePolicy` applies a different policy to each state.
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A part of a state whose snapshot can be stored.
+pub struct SnapshotCandidate<'a> {
+    pub(super) name: &'a str,
+    pub(super) query: &'a str,
+    pub(super) applied_events: u64,
+    pub(super) size: &'a (dyn Fn() -> usize + Sync),
+}
+
+impl SnapshotCandidate<'_> {
+    /// Returns the name of the state query.
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    /// Returns the key of the stream query of the state, see [`query_key`](crate::query_key).
+    pub fn query(&self) -> &str {
+        self.query
+    }
+
+    /// Returns the number of events applied to the state since it was loaded.
+    pub fn applied_events(&self) -> u64 {
+        self.applied_events
+    }
+
+    /// Returns the size in bytes of the serialized state.
+    ///
+    /// The state is serialized on the first call, and the serialization is reused by the snapshot.
+    pub fn size(&self) -> usize {
+        (self.size)()
+    }
+}
+
+/// Decides whether a snapshot of a state is stored.
+///
+/// The policy is only evaluated for the states with events applied since they were loaded.
+pub trait SnapshotPolicy: Debug + Send + Sync {
+    /// Returns true if a snapshot of the state must be stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The part of the state whose snapshot can be stored.
+    fn should_snapshot(&self, state: &SnapshotCandidate<'_>) -> bool;
+}
+
+/// Stores a snapshot when more than the given number of events have been applied to the state since it was loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EveryEventsPolicy(pub u64);
+
+impl SnapshotPolicy for EveryEventsPolicy {
+    fn should_snapshot(&self, state: &SnapshotCandidate<'_>) -> bool {
+        state.applied_events() > self.0
+    }
+}
+
+/// Stores a snapshot of a state at most once in the given interval.
+///
+/// The time of the last snapshot of each state is kept in memory, so each process of the application
+/// takes its own snapshots, and the first snapshot of a state is taken as soon as an event is applied to it.
+#[derive(Debug)]
+pub struct IntervalPolicy {
+    interval: Duration,
+    snapshots: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl IntervalPolicy {
+    /// Creates a new instance of `IntervalPolicy`.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The minimum interval between the snapshots of a state.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            snapshots: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl SnapshotPolicy for IntervalPolicy {
+    fn should_snapshot(&self, state: &SnapshotCandidate<'_>) -> bool {
+        let now = Instant::now();
+        let mut snapshots = self.snapshots.lock().unwrap();
+        // the states snapshotted before the interval can be snapshotted again, so they are forgotten.
+        snapshots.retain(|_, taken_at| now.duration_since(*taken_at) < self.interval);
+        let key = (state.name().to_string(), state.query().to_string());
+        if snapshots.contains_key(&key) {
+            return false;
+        }
+        snapshots.insert(key, now);
+        true
+    }
+}
+
+/// Stores a snapshot of the states whose serialized size reaches the given number of bytes.
+///
+/// The size of a state usually grows with the events applied to it, so the large states are the expensive
+/// ones to rebuild, while the small ones are not worth a snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeThresholdPolicy(pub usize);
+
+impl SnapshotPolicy for SizeThresholdPolicy {
+    fn should_snapshot(&self, state: &SnapshotCandidate<'_>) -> bool {
+        state.size() >= self.0
+    }
+}
+
+/// Never stores a snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NeverPolicy;
+
+impl SnapshotPolicy for NeverPolicy {
+    fn should_snapshot(&self, _state: &SnapshotCandidate<'_>) -> bool {
+        false
+    }
+}
+
+/// Applies a policy to each state, identified by the name of its state query.
+///
+/// # Examples
+///
+/// ```ignore
+/// let policy = PerStatePolicy::new(NeverPolicy)
+///     .with_state(Cart::NAME, EveryEventsPolicy(10))
+///     .with_state(Inventory::NAME, IntervalPolicy::new(Duration::from_secs(60)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct PerStatePolicy {
+    default: Arc<dyn SnapshotPolicy>,
+    states: HashMap<String, Arc<dyn SnapshotPolicy>>,
+}
+
+impl PerStatePolicy {
+    /// Creates a new instance of `PerStatePolicy`.
+    ///
+    /// # Arguments
+    ///
+    /// * `default` - The policy of the states without a policy of their own.
+    pub fn new(default: impl SnapshotPolicy + 'static) -> Self {
+        Self {
+            default: Arc::new(default),
+            states: HashMap::new(),
+        }
+    }
+
+    /// Sets the policy of a state.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the state query.
+    /// * `policy` - The policy of the state.
+    pub fn with_state(mut self, name: &str, policy: impl SnapshotPolicy + 'static) -> Self {
+        self.states.insert(name.to_string(), Arc::new(policy));
+        self
+    }
+}
+
+impl SnapshotPolicy for PerStatePolicy {
+    fn should_snapshot(&self, state: &SnapshotCandidate<'_>) -> bool {
+        self.states
+            .get(state.name())
+            .unwrap_or(&self.default)
+            .should_snapshot(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(name: &str, applied_events: u64, size: usize) -> bool {
+        let size = move || size;
+        let candidate = SnapshotCandidate {
+            name,
+            query: "",
+            applied_events,
+            size: &size,
+        };
+        PerStatePolicy::new(NeverPolicy)
+            .with_state("Cart", EveryEventsPolicy(2))
+            .with_state("Catalog", SizeThresholdPolicy(100))
+            .should_snapshot(&candidate)
+    }
+
+    #[test]
+    fn it_applies_the_policy_of_each_state() {
+        assert!(!candidate("Cart", 2, 1000));
+        assert!(candidate("Cart", 3, 0));
+        assert!(!candidate("Catalog", 10, 99));
+        assert!(candidate("Catalog", 1, 100));
+        assert!(!candidate("Order", 1000, 1000));
+    }
+
+    #[test]
+    fn it_takes_a_snapshot_of_a_state_once_in_the_interval() {
+        let policy = IntervalPolicy::new(Duration::from_secs(60));
+        let size = || 0;
+        let candidate = |query| SnapshotCandidate {
+            name: "Cart",
+            query,
+            applied_events: 1,
+            size: &size,
+        };
+
+        assert!(policy.should_snapshot(&candidate("c1")));
+        assert!(!policy.should_snapshot(&candidate("c1")));
+        assert!(policy.should_snapshot(&candidate("c2")));
+    }
+}
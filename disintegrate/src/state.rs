@@ -215,7 +215,7 @@ where
 /// # Type Parameters
 ///
 /// - `S`: The type implementing the `StateMutate` trait, representing the sub-state.
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StatePart<ID: EventId, S: StateQuery> {
     /// The version of the sub-state.
     version: ID,
@@ -321,7 +321,7 @@ macro_rules! impl_from_state {
             paste::paste! {
                 fn into_state_part(self) -> ($(StatePart<ID, $ty>,)*StatePart<ID, $last>){
                     let ($([<state_ $ty:lower>],)* [<state_ $last:lower>])= self;
-                    ($(StatePart{ inner: [<state_ $ty:lower>], version: Default::default(), applied_events: 0},)* StatePart{inner: [<state_ $last:lower>], version: Default::default(), applied_events: 0})
+                    ($(StatePart{ inner: [<state_ $ty:lower>], version: ID::zero(), applied_events: 0},)* StatePart{inner: [<state_ $last:lower>], version: ID::zero(), applied_events: 0})
                 }
             }
         }
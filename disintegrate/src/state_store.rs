@@ -8,11 +8,12 @@ use crate::event::EventId;
 use crate::BoxDynError;
 use crate::EventStore;
 use crate::StateQuery;
-use crate::{Event, PersistedEvent, StreamQuery};
+use crate::{Event, Metadata, PersistedEvent, StreamQuery};
 use async_trait::async_trait;
 use futures::TryStreamExt;
 use std::error::Error as StdError;
 use std::ops::Deref;
+use std::sync::Arc;
 
 /// Represents the state loaded from the event store, along with its version.
 ///
@@ -22,6 +23,7 @@ use std::ops::Deref;
 /// # Type Parameters
 ///
 /// - `S`: The type of the state.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LoadedState<ID: EventId, S> {
     /// The loaded state.
     pub(crate) state: S,
@@ -92,13 +94,13 @@ pub trait StateSnapshotter<ID: EventId> {
 pub trait SnapshotConfig {}
 
 /// Indicates that the snapshot is disabled.
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct NoSnapshot;
 
 impl SnapshotConfig for NoSnapshot {}
 
 /// Indicates that the snapshot is enabled and handled by the provided backend.
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub struct WithSnapshot<ID: EventId, T: StateSnapshotter<ID> + Clone> {
     backend: T,
     event_id: std::marker::PhantomData<ID>,
@@ -121,6 +123,70 @@ impl<ID: EventId, T: StateSnapshotter<ID> + Clone> Deref for WithSnapshot<ID, T>
     }
 }
 
+/// The guardrail of the number of events replayed to load a state, e.g. because its snapshot is missing.
+///
+/// Without a snapshot, the state is loaded from its first event, which can be millions of events for a
+/// long-lived stream. The limit either reports the loads replaying more events, and lets them complete,
+/// or makes them fail as soon as the limit is exceeded, without reading the rest of the events.
+#[derive(Clone)]
+pub struct ReplayLimit {
+    max_events: u64,
+    warn: Option<ReplayWarning>,
+}
+
+type ReplayWarning = Arc<dyn Fn(&ReplayLimitExceeded) + Send + Sync>;
+
+impl ReplayLimit {
+    /// Creates a limit that fails the loads replaying more than `max_events` events.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_events` - The maximum number of events replayed by a load.
+    pub fn error(max_events: u64) -> Self {
+        Self {
+            max_events,
+            warn: None,
+        }
+    }
+
+    /// Creates a limit that reports the loads replaying more than `max_events` events, e.g. to a log.
+    ///
+    /// The warning is called once per load, when the limit is exceeded, and the load completes.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_events` - The number of events replayed by a load above which the warning is called.
+    /// * `warn` - The function called with the exceeded limit.
+    pub fn warn(
+        max_events: u64,
+        warn: impl Fn(&ReplayLimitExceeded) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            max_events,
+            warn: Some(Arc::new(warn)),
+        }
+    }
+}
+
+impl std::fmt::Debug for ReplayLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReplayLimit")
+            .field("max_events", &self.max_events)
+            .field("warn", &self.warn.is_some())
+            .finish()
+    }
+}
+
+/// The error, or the warning, of a load exceeding its `ReplayLimit`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("loading the state `{state}` replays more than {max_events} events")]
+pub struct ReplayLimitExceeded {
+    /// The type name of the loaded state.
+    pub state: &'static str,
+    /// The maximum number of events of the limit.
+    pub max_events: u64,
+}
+
 /// Represents an event sourced decision state store. It loads and stores decision states from events in a event store.
 #[derive(Clone)]
 pub struct EventSourcedStateStore<ID, E, ES, SN>
@@ -132,6 +198,7 @@ where
 {
     event_store: ES,
     snapshot: SN,
+    replay_limit: Option<ReplayLimit>,
     event_id_type: std::marker::PhantomData<ID>,
     event_type: std::marker::PhantomData<E>,
 }
@@ -147,12 +214,28 @@ where
         EventSourcedStateStore {
             event_store,
             snapshot,
+            replay_limit: None,
             event_id_type: std::marker::PhantomData,
             event_type: std::marker::PhantomData,
         }
     }
 
-    async fn mutate_state<S>(&self, mut state_query: S) -> Result<S, BoxDynError>
+    /// Sets the guardrail of the number of events replayed by a load.
+    ///
+    /// # Arguments
+    ///
+    /// * `replay_limit` - The limit of the events replayed by a load, after the snapshots if any.
+    pub fn with_replay_limit(mut self, replay_limit: ReplayLimit) -> Self {
+        self.replay_limit = Some(replay_limit);
+        self
+    }
+
+    /// Folds the events of the state as they are streamed, so only the state is kept in memory.
+    async fn mutate_state<S>(
+        &self,
+        mut state_query: S,
+        state: &'static str,
+    ) -> Result<S, BoxDynError>
     where
         ES: EventStore<ID, E> + Clone + Sync + Send,
         <ES as EventStore<ID, E>>::Error: StdError + Send + Sync + 'static,
@@ -161,7 +244,23 @@ where
     {
         let query = state_query.query_all();
         let mut event_stream = self.event_store.stream(&query);
+        let mut replayed: u64 = 0;
         while let Some(event) = event_stream.try_next().await? {
+            replayed += 1;
+            if let Some(limit) = self
+                .replay_limit
+                .as_ref()
+                .filter(|limit| replayed == limit.max_events + 1)
+            {
+                let exceeded = ReplayLimitExceeded {
+                    state,
+                    max_events: limit.max_events,
+                };
+                match &limit.warn {
+                    Some(warn) => warn(&exceeded),
+                    None => return Err(Box::new(exceeded)),
+                }
+            }
             state_query.mutate_all(event);
         }
         Ok(state_query)
@@ -180,7 +279,9 @@ where
         Send + Sync + Serialize + DeserializeOwned + IntoState<S> + MultiState<ID, E>,
 {
     async fn load(&self, state_query: S) -> Result<LoadedState<ID, S>, BoxDynError> {
-        let mutated_state = self.mutate_state(state_query.into_state_part()).await?;
+        let mutated_state = self
+            .mutate_state(state_query.into_state_part(), std::any::type_name::<S>())
+            .await?;
         let version = mutated_state.version();
         Ok(LoadedState {
             state: mutated_state.into_state(),
@@ -209,7 +310,9 @@ where
     async fn load(&self, state_query: S) -> Result<LoadedState<ID, S>, BoxDynError> {
         let mut state_query = state_query.into_state_part();
         state_query.load_all(&self.snapshot.backend).await;
-        let state = self.mutate_state(state_query).await?;
+        let state = self
+            .mutate_state(state_query, std::any::type_name::<S>())
+            .await?;
         state.store_all(&self.snapshot.backend).await?;
         let version = state.version();
         Ok(LoadedState {
@@ -235,13 +338,14 @@ where
         &self,
         loaded_state: LoadedState<ID, S>,
         events: Vec<E>,
+        metadata: Metadata,
         validation_query: Option<StreamQuery<ID, E>>,
     ) -> Result<Vec<PersistedEvent<ID, E>>, BoxDynError> {
         let query =
             validation_query.unwrap_or_else(|| loaded_state.state.into_state_part().query_all());
         Ok(self
             .event_store
-            .append(events, query, loaded_state.version)
+            .append_with_metadata(events, metadata, query, loaded_state.version)
             .await?)
     }
 }
@@ -276,6 +380,71 @@ mod test {
         assert_eq!(cart2, cart("c2", ["p3".to_owned()]));
     }
 
+    #[tokio::test]
+    async fn it_loads_a_comparable_state() {
+        let mut mock_store = MockDatabase::new();
+
+        mock_store
+            .expect_stream()
+            .once()
+            .return_once(|_| event_stream([item_added_event("p1", "c1")]));
+
+        let event_store = MockEventStore::new(mock_store);
+        let state_store = EventSourcedStateStore::new(event_store, NoSnapshot);
+        let loaded_state = state_store.load(cart("c1", [])).await.unwrap();
+
+        assert_eq!(
+            loaded_state,
+            LoadedState {
+                state: cart("c1", ["p1".to_owned()]),
+                version: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn it_fails_the_loads_exceeding_the_replay_limit() {
+        let mut mock_store = MockDatabase::new();
+        mock_store.expect_stream().once().return_once(|_| {
+            event_stream([
+                item_added_event("p1", "c1"),
+                item_added_event("p2", "c1"),
+                item_added_event("p3", "c1"),
+            ])
+        });
+
+        let state_store = EventSourcedStateStore::new(MockEventStore::new(mock_store), NoSnapshot)
+            .with_replay_limit(ReplayLimit::error(2));
+        let err = state_store.load(cart("c1", [])).await.unwrap_err();
+
+        let exceeded = err.downcast_ref::<ReplayLimitExceeded>().unwrap();
+        assert_eq!(exceeded.max_events, 2);
+        assert!(exceeded.state.ends_with("Cart"));
+    }
+
+    #[tokio::test]
+    async fn it_warns_once_about_the_loads_exceeding_the_replay_limit() {
+        let mut mock_store = MockDatabase::new();
+        mock_store.expect_stream().once().return_once(|_| {
+            event_stream([
+                item_added_event("p1", "c1"),
+                item_added_event("p2", "c1"),
+                item_added_event("p3", "c1"),
+            ])
+        });
+        let warnings = Arc::new(std::sync::Mutex::new(vec![]));
+        let recorded = warnings.clone();
+
+        let state_store = EventSourcedStateStore::new(MockEventStore::new(mock_store), NoSnapshot)
+            .with_replay_limit(ReplayLimit::warn(1, move |exceeded| {
+                recorded.lock().unwrap().push(exceeded.max_events)
+            }));
+        let loaded_state = state_store.load(cart("c1", [])).await.unwrap();
+
+        assert_eq!(loaded_state.version(), 3);
+        assert_eq!(*warnings.lock().unwrap(), vec![1]);
+    }
+
     #[tokio::test]
     async fn it_persists_decision_changes() {
         let mut mock_store = MockDatabase::new();
@@ -291,7 +460,12 @@ mod test {
         let state = (Cart::new("c1"), Cart::new("c2"));
         let loaded_state = LoadedState { state, version: 1 };
         state_store
-            .persist(loaded_state, vec![item_added_event("p2", "c1")], None)
+            .persist(
+                loaded_state,
+                vec![item_added_event("p2", "c1")],
+                Metadata::default(),
+                None,
+            )
             .await
             .unwrap();
     }
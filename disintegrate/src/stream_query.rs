@@ -8,13 +8,22 @@
 //! such as `eq`, `and`, and `or`. These can be used to construct complex filter expressions.
 //!
 //! The `StreamFilter` enum defines different types of filters that can be applied to event streams,
-//! including equality filters, logical AND filters, and logical OR filters. Filters are evaluated
+//! including equality filters, logical AND filters, and logical OR filters. Besides the equality, the domain
+//! identifiers can be compared with the `IdentifierCondition`s: `!=`, `in` and `is_null`. Filters are evaluated
 //! using the `FilterEvaluator` trait, which provides an `eval` method for evaluating a filter against
 //! an event.
 use core::fmt::Debug;
+use std::fmt;
 use std::marker::PhantomData;
 
-use crate::{domain_identifiers, event::EventId, DomainIdentifierSet, Event, PersistedEvent};
+mod builder;
+
+pub use builder::{StreamQueryBuilder, StreamQueryError};
+
+use crate::{
+    domain_identifiers, event::EventId, DomainIdentifier, DomainIdentifierSet, Event, EventInfo,
+    Identifier, IdentifierValue, IntoIdentifierValue, PersistedEvent,
+};
 
 /// Represents a query for filtering event streams.
 ///
@@ -118,8 +127,62 @@ impl<ID: EventId, E: Event + Clone> StreamQuery<ID, E> {
         }
     }
 
+    /// Adds the specified events to the ones already excluded from the stream query.
+    ///
+    /// Unlike `exclude_events`, the previously excluded events are preserved. This is useful
+    /// when the excluded events are only known at runtime, e.g. from the application configuration.
+    pub fn extend_excluded_events(self, excluded_events: &[&'static str]) -> Self {
+        let filters = self
+            .filters
+            .iter()
+            .map(|f| {
+                let mut filter_excluded_events = f.excluded_events.clone().unwrap_or_default();
+                filter_excluded_events.extend(
+                    excluded_events
+                        .iter()
+                        .filter(|e| f.events.contains(e) && !filter_excluded_events.contains(e))
+                        .collect::<Vec<_>>(),
+                );
+                StreamFilter {
+                    excluded_events: Some(filter_excluded_events),
+                    ..f.clone()
+                }
+            })
+            .collect();
+
+        StreamQuery {
+            filters,
+            event_type: PhantomData,
+            event_id_type: PhantomData,
+        }
+    }
+
+    /// Sets how the filters of the stream query treat the events that do not declare one of their
+    /// domain identifiers.
+    pub fn with_missing_identifier(self, missing_identifier: MissingIdentifier) -> Self {
+        let filters = self
+            .filters
+            .iter()
+            .map(|f| StreamFilter {
+                missing_identifier,
+                ..f.clone()
+            })
+            .collect();
+
+        StreamQuery {
+            filters,
+            event_type: PhantomData,
+            event_id_type: PhantomData,
+        }
+    }
+
     /// Checks if the stream query matches the given event.
+    ///
+    /// The domain identifiers of a filter are compared only for the events that declare them in the
+    /// event schema, following the `MissingIdentifier` semantics of the filter, as the event stores do.
     pub fn matches(&self, event: &PersistedEvent<ID, E>) -> bool {
+        let event_info = E::SCHEMA.event_info(event.name());
+        let event_identifiers = event.domain_identifiers();
         self.filters.iter().any(|filter| {
             if let Some(excluded_events) = &filter.excluded_events {
                 if excluded_events.contains(&event.name()) {
@@ -131,11 +194,11 @@ impl<ID: EventId, E: Event + Clone> StreamQuery<ID, E> {
                 return false;
             }
 
-            if filter
-                .identifiers
-                .iter()
-                .any(|(ident, value)| event.domain_identifiers().get(ident) != Some(value))
-            {
+            if !event_info.is_some_and(|event_info| {
+                filter.matches_identifiers(event_info, |ident, value| {
+                    event_identifiers.get(ident) == Some(value)
+                })
+            }) {
                 return false;
             }
 
@@ -188,6 +251,9 @@ where
 }
 
 /// Creates a stream query with a given event type and filter.
+///
+/// The domain identifiers of the filter are compared with `==`, `!=`, `in` followed by an iterable of values
+/// and `is_null`, e.g. `query!(CartEvent; cart_id in &cart_ids, coupon_id is_null)`.
 #[macro_export]
 macro_rules! query {
     ($event_ty: ty) => {{
@@ -221,37 +287,65 @@ macro_rules! event_types{
 }
 
 /// Creates stream filters for querying event streams.
+///
+/// The domain identifiers are compared with `==`, `!=`, `in` followed by an iterable of values,
+/// e.g. `cart_id in ["c1", "c2"]` or `cart_id in &cart_ids`, and `is_null`.
 #[macro_export]
 #[doc(hidden)]
 macro_rules! filter {
-    ($origin:expr => $event_ty:ty; $($ident:ident == $value:expr),*) =>{
-        $crate::filter!($event_ty; $($ident == $value),*).change_origin($origin)
-    };
-    ($event_ty:ty; $($ident:ident == $value:expr),*) =>{
+    (@check $event_ty:ty; $ident:ident) => {
+        #[allow(dead_code)]
         {
-            #[allow(dead_code)]
-            {
-                use $crate::Event;
-                // Check if the domain identifiers exist
-                const DOMAIN_IDENTIFIERS: &[&$crate::DomainIdentifierInfo] = <$event_ty>::SCHEMA.domain_identifiers;
-                const DOMAIN_IDENTIFIERS_INDENTS: &[&str] = &$crate::const_slice_iter!(DOMAIN_IDENTIFIERS, const fn map(item: &$crate::DomainIdentifierInfo) -> &str {
-                    item.ident.into_inner()
-                });
-
-                $(
-                   const _:&[&str] = {
-                       const FILTER_ARG: &[&str] = &[stringify!($ident)];
-                       if !$crate::utils::include(DOMAIN_IDENTIFIERS_INDENTS, FILTER_ARG) {
-                           panic!(concat!("Invalid domain filter: the domain identifier ", stringify!($ident), " does not exist"));
-                       }
-                       FILTER_ARG
-                   };
-
-                )*
-            }
-            $crate::StreamFilter::<_, $event_ty>::new($crate::domain_identifiers!($($ident: $value.clone()),*))
+            use $crate::Event;
+            // Check if the domain identifier exists
+            const DOMAIN_IDENTIFIERS: &[&$crate::DomainIdentifierInfo] = <$event_ty>::SCHEMA.domain_identifiers;
+            const DOMAIN_IDENTIFIERS_INDENTS: &[&str] = &$crate::const_slice_iter!(DOMAIN_IDENTIFIERS, const fn map(item: &$crate::DomainIdentifierInfo) -> &str {
+                item.ident.into_inner()
+            });
+            const _:&[&str] = {
+                const FILTER_ARG: &[&str] = &[stringify!($ident)];
+                if !$crate::utils::include(DOMAIN_IDENTIFIERS_INDENTS, FILTER_ARG) {
+                    panic!(concat!("Invalid domain filter: the domain identifier ", stringify!($ident), " does not exist"));
+                }
+                FILTER_ARG
+            };
         }
     };
+    (@build $event_ty:ty; $filter:expr;) => {
+        $filter
+    };
+    (@build $event_ty:ty; $filter:expr; $ident:ident == $value:expr $(, $($rest:tt)*)?) => {{
+        $crate::filter!(@check $event_ty; $ident);
+        $crate::filter!(@build $event_ty; $filter.with_identifier($crate::ident!(#$ident), $value.clone()); $($($rest)*)?)
+    }};
+    (@build $event_ty:ty; $filter:expr; $ident:ident != $value:expr $(, $($rest:tt)*)?) => {{
+        $crate::filter!(@check $event_ty; $ident);
+        $crate::filter!(@build $event_ty; $filter.with_condition($crate::IdentifierCondition::NotEq(
+            $crate::ident!(#$ident),
+            $crate::IntoIdentifierValue::into_identifier_value($value.clone()),
+        )); $($($rest)*)?)
+    }};
+    (@build $event_ty:ty; $filter:expr; $ident:ident in $values:expr $(, $($rest:tt)*)?) => {{
+        $crate::filter!(@check $event_ty; $ident);
+        $crate::filter!(@build $event_ty; $filter.with_condition($crate::IdentifierCondition::In(
+            $crate::ident!(#$ident),
+            ::std::iter::IntoIterator::into_iter($values)
+                .map($crate::IntoIdentifierValue::into_identifier_value)
+                .collect(),
+        )); $($($rest)*)?)
+    }};
+    (@build $event_ty:ty; $filter:expr; $ident:ident is_null $(, $($rest:tt)*)?) => {{
+        $crate::filter!(@check $event_ty; $ident);
+        $crate::filter!(@build $event_ty; $filter.with_condition($crate::IdentifierCondition::IsNull(
+            $crate::ident!(#$ident),
+        )); $($($rest)*)?)
+    }};
+    ($origin:expr => $event_ty:ty; $($filter:tt)*) =>{
+        $crate::filter!($event_ty; $($filter)*).change_origin($origin)
+    };
+    ($event_ty:ty; $($filter:tt)*) =>{
+        $crate::filter!(@build $event_ty; $crate::StreamFilter::<_, $event_ty>::new($crate::domain_identifiers!()); $($filter)*)
+    };
 }
 
 /// unions two or more stream queries into a single query.
@@ -272,6 +366,71 @@ macro_rules! union {
     };
 }
 
+/// How a filter treats the events that do not declare one of its domain identifiers.
+///
+/// The identifiers declared by an event are always compared by equality: an event without a value for
+/// a declared identifier never matches. This enum only affects the event types whose schema does not
+/// include the identifier, e.g. a `CouponApplied` event without a `cart_id` in a query filtering by cart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingIdentifier {
+    /// The identifier is ignored, and the event matches on the remaining criteria.
+    #[default]
+    Match,
+    /// The event does not match.
+    NoMatch,
+}
+
+/// A comparison of a domain identifier other than the equality.
+///
+/// An event type that does not declare the identifier has no value for it: it matches `IsNull`, while
+/// `NotEq` and `In` follow the `MissingIdentifier` semantics of the filter, as the equality does.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IdentifierCondition {
+    /// The identifier is different from the value.
+    NotEq(Identifier, IdentifierValue),
+    /// The identifier is one of the values.
+    In(Identifier, Vec<IdentifierValue>),
+    /// The identifier has no value.
+    IsNull(Identifier),
+}
+
+impl IdentifierCondition {
+    /// Returns the domain identifier compared by the condition.
+    pub fn identifier(&self) -> &Identifier {
+        match self {
+            Self::NotEq(ident, _) | Self::In(ident, _) | Self::IsNull(ident) => ident,
+        }
+    }
+
+    /// Checks if the condition holds for the value of an identifier declared by the event.
+    ///
+    /// The declared identifiers always have a value, so they are never null.
+    ///
+    /// # Arguments
+    ///
+    /// * `eq` - Compares the value of the identifier with the given value.
+    fn holds(&self, eq: impl Fn(&IdentifierValue) -> bool) -> bool {
+        match self {
+            Self::NotEq(_, value) => !eq(value),
+            Self::In(_, values) => values.iter().any(eq),
+            Self::IsNull(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for IdentifierCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotEq(ident, value) => write!(f, "{ident}!={value}"),
+            Self::In(ident, values) => {
+                let values: Vec<String> = values.iter().map(ToString::to_string).collect();
+                write!(f, "{ident} in [{}]", values.join(","))
+            }
+            Self::IsNull(ident) => write!(f, "{ident} is null"),
+        }
+    }
+}
+
 /// Represents a filter applied to an event stream.
 ///
 /// A `StreamFilter` is used to define filters and constraints for querying event streams.
@@ -285,6 +444,10 @@ pub struct StreamFilter<ID: EventId, E: Event + Clone> {
     origin: ID,
     /// The names of the events to exclude from the query results.
     excluded_events: Option<Vec<&'static str>>,
+    /// How the events that do not declare one of the domain identifiers are treated.
+    missing_identifier: MissingIdentifier,
+    /// The comparisons of the domain identifiers other than the equality.
+    conditions: Vec<IdentifierCondition>,
     /// A marker indicating the event type associated with the stream filter.
     event_type: PhantomData<E>,
 }
@@ -295,12 +458,29 @@ impl<ID: EventId, E: Event + Clone> StreamFilter<ID, E> {
         Self {
             events: E::SCHEMA.events,
             identifiers,
-            origin: Default::default(),
+            origin: ID::zero(),
             excluded_events: None,
+            missing_identifier: MissingIdentifier::default(),
+            conditions: vec![],
             event_type: PhantomData,
         }
     }
 
+    /// Adds a domain identifier compared by equality to the stream filter.
+    pub fn with_identifier(mut self, ident: Identifier, value: impl IntoIdentifierValue) -> Self {
+        self.identifiers.insert(DomainIdentifier {
+            key: ident,
+            value: value.into_identifier_value(),
+        });
+        self
+    }
+
+    /// Adds a comparison of a domain identifier to the stream filter.
+    pub fn with_condition(mut self, condition: IdentifierCondition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
     /// Changes the origin of the stream filter.
     pub fn change_origin(self, origin: ID) -> Self {
         Self { origin, ..self }
@@ -325,6 +505,8 @@ impl<ID: EventId, E: Event + Clone> StreamFilter<ID, E> {
             identifiers: self.identifiers.clone(),
             origin: self.origin,
             excluded_events: self.excluded_events.clone(),
+            missing_identifier: self.missing_identifier,
+            conditions: self.conditions.clone(),
             event_type: PhantomData,
         }
     }
@@ -348,6 +530,74 @@ impl<ID: EventId, E: Event + Clone> StreamFilter<ID, E> {
     pub fn excluded_events(&self) -> Option<&Vec<&'static str>> {
         self.excluded_events.as_ref()
     }
+
+    /// Returns how the events that do not declare one of the domain identifiers are treated.
+    pub fn missing_identifier(&self) -> MissingIdentifier {
+        self.missing_identifier
+    }
+
+    /// Returns the comparisons of the domain identifiers other than the equality.
+    pub fn conditions(&self) -> &[IdentifierCondition] {
+        &self.conditions
+    }
+
+    /// Checks if the filter excludes an event type whatever the values of its domain identifiers.
+    ///
+    /// An event type is excluded when it does not declare an identifier compared by value and the filter
+    /// follows the `MissingIdentifier::NoMatch` semantics.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_info` - The schema information of the event.
+    pub fn excludes_event_type(&self, event_info: &EventInfo) -> bool {
+        self.missing_identifier == MissingIdentifier::NoMatch
+            && self
+                .identifiers
+                .keys()
+                .chain(
+                    self.conditions
+                        .iter()
+                        .filter(|condition| !matches!(condition, IdentifierCondition::IsNull(_)))
+                        .map(IdentifierCondition::identifier),
+                )
+                .any(|ident| !event_info.has_domain_identifier(ident))
+    }
+
+    /// Returns the comparisons of the domain identifiers declared by an event type, other than the equality.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_info` - The schema information of the event.
+    pub fn declared_conditions<'a>(
+        &'a self,
+        event_info: &'a EventInfo,
+    ) -> impl Iterator<Item = &'a IdentifierCondition> + 'a {
+        self.conditions
+            .iter()
+            .filter(|condition| event_info.has_domain_identifier(condition.identifier()))
+    }
+
+    /// Checks if the domain identifiers of the filter match the identifiers of an event.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_info` - The schema information of the event.
+    /// * `eq` - Compares a domain identifier declared by the event with the given value.
+    pub fn matches_identifiers(
+        &self,
+        event_info: &EventInfo,
+        eq: impl Fn(&Identifier, &IdentifierValue) -> bool,
+    ) -> bool {
+        !self.excludes_event_type(event_info)
+            && self
+                .identifiers
+                .iter()
+                .filter(|(ident, _)| event_info.has_domain_identifier(ident))
+                .all(|(ident, value)| eq(ident, value))
+            && self
+                .declared_conditions(event_info)
+                .all(|condition| condition.holds(|value| eq(condition.identifier(), value)))
+    }
 }
 
 #[cfg(test)]
@@ -355,7 +605,9 @@ mod tests {
     use crate::ident;
     use crate::stream_query::StreamFilter;
     use crate::utils::tests::*;
-    use crate::IdentifierValue;
+    use crate::{
+        IdentifierCondition, IdentifierValue, MissingIdentifier, PersistedEvent, StreamQuery,
+    };
 
     #[test]
     fn test_filter_with_no_origin_and_no_exclude_events() {
@@ -397,4 +649,80 @@ mod tests {
             IdentifierValue::i64(42)
         );
     }
+
+    #[test]
+    fn it_extends_the_excluded_events() {
+        let query: StreamQuery<i64, ShoppingCartEvent> = query!(ShoppingCartEvent; cart_id == "c1")
+            .exclude_events(event_types!(ShoppingCartEvent, [ItemAdded]))
+            .extend_excluded_events(&["ItemRemoved", "ItemAdded"]);
+
+        assert_eq!(
+            query.filters()[0].excluded_events(),
+            Some(&vec!["ItemAdded", "ItemRemoved"])
+        );
+    }
+
+    #[test]
+    fn it_applies_the_missing_identifier_semantics() {
+        let query: StreamQuery<i64, ShoppingCartEvent> =
+            crate::query(Some(StreamFilter::<i64, ShoppingCartEvent>::new(
+                crate::domain_identifiers! {cart_id: "c1", coupon_id: "coupon_1"},
+            )));
+        let item_added = PersistedEvent::new(1, item_added_event("p1", "c1"));
+
+        assert!(query.matches(&item_added));
+        assert!(!query.matches(&PersistedEvent::new(2, item_added_event("p1", "c2"))));
+        assert!(!query
+            .with_missing_identifier(MissingIdentifier::NoMatch)
+            .matches(&item_added));
+    }
+
+    #[test]
+    fn it_compares_the_identifiers_with_the_conditions() {
+        let cart_ids = vec!["c1".to_string(), "c2".to_string()];
+        let query: StreamQuery<i64, ShoppingCartEvent> =
+            query!(ShoppingCartEvent; cart_id in &cart_ids, item_id != "p1");
+
+        assert_eq!(
+            query.filters()[0].conditions(),
+            &[
+                IdentifierCondition::In(
+                    ident!(#cart_id),
+                    vec![
+                        IdentifierValue::String("c1".to_string()),
+                        IdentifierValue::String("c2".to_string())
+                    ]
+                ),
+                IdentifierCondition::NotEq(
+                    ident!(#item_id),
+                    IdentifierValue::String("p1".to_string())
+                ),
+            ]
+        );
+        assert!(query.matches(&PersistedEvent::new(1, item_added_event("p2", "c2"))));
+        assert!(!query.matches(&PersistedEvent::new(2, item_added_event("p1", "c1"))));
+        assert!(!query.matches(&PersistedEvent::new(3, item_added_event("p2", "c3"))));
+    }
+
+    #[test]
+    fn it_matches_the_null_identifiers() {
+        let item_added = PersistedEvent::new(1, item_added_event("p1", "c1"));
+        let declared: StreamQuery<i64, ShoppingCartEvent> =
+            query!(ShoppingCartEvent; cart_id is_null);
+        let undeclared: StreamQuery<i64, ShoppingCartEvent> = crate::query(Some(
+            StreamFilter::<i64, ShoppingCartEvent>::new(crate::domain_identifiers! {cart_id: "c1"})
+                .with_condition(IdentifierCondition::IsNull(ident!(#coupon_id))),
+        ));
+
+        assert!(!declared.matches(&item_added));
+        assert!(undeclared.matches(&item_added));
+        assert_eq!(
+            crate::query_key(&undeclared),
+            "(0|ItemAdded,ItemRemoved|cart_id=c1,coupon_id is null)"
+        );
+        assert_eq!(
+            crate::query_key(&undeclared.with_missing_identifier(MissingIdentifier::NoMatch)),
+            "(0|ItemAdded,ItemRemoved|cart_id=c1,coupon_id is null|no_match)"
+        );
+    }
 }
@@ -0,0 +1,350 @@
+//! A runtime builder of stream queries.
+//!
+//! The `query!` macro checks the domain identifiers at compile time, so it cannot be used when the
+//! identifiers are only known at runtime, e.g. in admin tools where they come from the user input.
+//! The `StreamQueryBuilder` validates them against the schema of the events when the query is built.
+use std::str::FromStr;
+
+use uuid::Uuid;
+
+use super::{IdentifierCondition, MissingIdentifier, StreamFilter, StreamQuery};
+use crate::event::EventId;
+use crate::{
+    domain_identifiers, DomainIdentifier, Event, Identifier, IdentifierType, IdentifierValue,
+    IntoIdentifierValue,
+};
+
+/// An error of the `StreamQueryBuilder`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum StreamQueryError {
+    /// The domain identifier is not declared by the events.
+    #[error("the domain identifier {0} does not exist")]
+    UnknownIdentifier(String),
+    /// The event is not part of the event type.
+    #[error("the event {0} does not exist")]
+    UnknownEvent(String),
+    /// The value does not have the type of the domain identifier.
+    #[error("the value {value} of the domain identifier {ident} is not a {expected:?}")]
+    InvalidValue {
+        /// The domain identifier.
+        ident: String,
+        /// The value.
+        value: String,
+        /// The type of the domain identifier.
+        expected: IdentifierType,
+    },
+}
+
+/// Builds a stream query at runtime, validating it against the schema of the events.
+///
+/// The string values of the identifiers are parsed into the type of the identifier, so that the values
+/// read from the user input can be used as they are. The first error is returned by `build`.
+///
+/// # Examples
+///
+/// ```
+/// # use disintegrate::{DomainIdentifierSet, Event, EventSchema, EventInfo, DomainIdentifierInfo, IdentifierType, ident, domain_identifiers};
+/// # #[derive(Clone, Debug)]
+/// # struct CartEvent;
+/// # impl Event for CartEvent {
+/// #     const SCHEMA: EventSchema = EventSchema {
+/// #         events: &["ItemAdded", "ItemRemoved"],
+/// #         events_info: &[
+/// #             &EventInfo { name: "ItemAdded", domain_identifiers: &[&ident!(#cart_id)], description: None, owner: None, aliases: &[], version: 1 },
+/// #             &EventInfo { name: "ItemRemoved", domain_identifiers: &[&ident!(#cart_id)], description: None, owner: None, aliases: &[], version: 1 },
+/// #         ],
+/// #         domain_identifiers: &[&DomainIdentifierInfo { ident: ident!(#cart_id), type_info: IdentifierType::String }],
+/// #     };
+/// #     fn name(&self) -> &'static str { "ItemAdded" }
+/// #     fn domain_identifiers(&self) -> DomainIdentifierSet { domain_identifiers! {} }
+/// # }
+/// use disintegrate::{StreamQuery, StreamQueryBuilder, StreamQueryError};
+///
+/// let query: StreamQuery<i64, CartEvent> = StreamQueryBuilder::events()
+///     .ident("cart_id", "c1")
+///     .exclude("ItemRemoved")
+///     .build()
+///     .unwrap();
+///
+/// let error = StreamQueryBuilder::<i64, CartEvent>::events()
+///     .ident("basket_id", "c1")
+///     .build()
+///     .unwrap_err();
+/// assert_eq!(error, StreamQueryError::UnknownIdentifier("basket_id".to_string()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct StreamQueryBuilder<ID: EventId, E: Event + Clone> {
+    filter: StreamFilter<ID, E>,
+    excluded_events: Vec<&'static str>,
+    missing_identifier: MissingIdentifier,
+    error: Option<StreamQueryError>,
+}
+
+impl<ID: EventId, E: Event + Clone> StreamQueryBuilder<ID, E> {
+    /// Creates a builder of a query selecting all the events of the event type.
+    pub fn events() -> Self {
+        Self {
+            filter: StreamFilter::new(domain_identifiers! {}),
+            excluded_events: vec![],
+            missing_identifier: MissingIdentifier::default(),
+            error: None,
+        }
+    }
+
+    /// Filters the events by the value of a domain identifier.
+    ///
+    /// # Arguments
+    ///
+    /// * `ident` - The name of the domain identifier.
+    /// * `value` - The value of the domain identifier.
+    pub fn ident(self, ident: &str, value: impl IntoIdentifierValue) -> Self {
+        self.with(ident, |mut this, ident| {
+            let value = this.value(ident, value.into_identifier_value())?;
+            this.filter
+                .identifiers
+                .insert(DomainIdentifier { key: ident, value });
+            Ok(this)
+        })
+    }
+
+    /// Filters the events whose domain identifier differs from the value.
+    ///
+    /// # Arguments
+    ///
+    /// * `ident` - The name of the domain identifier.
+    /// * `value` - The excluded value of the domain identifier.
+    pub fn not_eq(self, ident: &str, value: impl IntoIdentifierValue) -> Self {
+        self.with(ident, |this, ident| {
+            let value = this.value(ident, value.into_identifier_value())?;
+            Ok(this.condition(IdentifierCondition::NotEq(ident, value)))
+        })
+    }
+
+    /// Filters the events whose domain identifier is one of the values.
+    ///
+    /// # Arguments
+    ///
+    /// * `ident` - The name of the domain identifier.
+    /// * `values` - The values of the domain identifier.
+    pub fn is_in<V: IntoIdentifierValue>(
+        self,
+        ident: &str,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        self.with(ident, |this, ident| {
+            let values = values
+                .into_iter()
+                .map(|value| this.value(ident, value.into_identifier_value()))
+                .collect::<Result<_, _>>()?;
+            Ok(this.condition(IdentifierCondition::In(ident, values)))
+        })
+    }
+
+    /// Filters the events without a value for the domain identifier.
+    ///
+    /// # Arguments
+    ///
+    /// * `ident` - The name of the domain identifier.
+    pub fn is_null(self, ident: &str) -> Self {
+        self.with(ident, |this, ident| {
+            Ok(this.condition(IdentifierCondition::IsNull(ident)))
+        })
+    }
+
+    /// Excludes an event from the query.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The name of the event to exclude.
+    pub fn exclude(mut self, event: &str) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        match E::SCHEMA.events.iter().find(|name| **name == event) {
+            Some(name) => self.excluded_events.push(name),
+            None => self.error = Some(StreamQueryError::UnknownEvent(event.to_string())),
+        }
+        self
+    }
+
+    /// Sets the origin of the query: only the events following it are selected.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - The ID of the last event not selected.
+    pub fn origin(self, origin: ID) -> Self {
+        Self {
+            filter: self.filter.change_origin(origin),
+            ..self
+        }
+    }
+
+    /// Sets how the query treats the events that do not declare one of the domain identifiers.
+    ///
+    /// # Arguments
+    ///
+    /// * `missing_identifier` - The semantics of the missing identifiers.
+    pub fn missing_identifier(self, missing_identifier: MissingIdentifier) -> Self {
+        Self {
+            missing_identifier,
+            ..self
+        }
+    }
+
+    /// Builds the stream query.
+    ///
+    /// # Returns
+    ///
+    /// The stream query, or the first error found while building it.
+    pub fn build(self) -> Result<StreamQuery<ID, E>, StreamQueryError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let query = crate::query::<ID, E, E>(Some(self.filter))
+            .with_missing_identifier(self.missing_identifier);
+        if self.excluded_events.is_empty() {
+            Ok(query)
+        } else {
+            Ok(query.extend_excluded_events(&self.excluded_events))
+        }
+    }
+
+    /// Applies a criteria on a domain identifier, if the identifier exists and no error occurred so far.
+    fn with(
+        self,
+        ident: &str,
+        criteria: impl FnOnce(Self, Identifier) -> Result<Self, StreamQueryError>,
+    ) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        let Some(info) = E::SCHEMA
+            .domain_identifiers
+            .iter()
+            .find(|info| info.ident.into_inner() == ident)
+        else {
+            return Self {
+                error: Some(StreamQueryError::UnknownIdentifier(ident.to_string())),
+                ..self
+            };
+        };
+        let fallback = self.clone();
+        criteria(self, info.ident).unwrap_or_else(|error| Self {
+            error: Some(error),
+            ..fallback
+        })
+    }
+
+    fn condition(self, condition: IdentifierCondition) -> Self {
+        Self {
+            filter: self.filter.with_condition(condition),
+            ..self
+        }
+    }
+
+    /// Converts a value into the type of the domain identifier, parsing the strings.
+    fn value(
+        &self,
+        ident: Identifier,
+        value: IdentifierValue,
+    ) -> Result<IdentifierValue, StreamQueryError> {
+        let expected = E::SCHEMA
+            .domain_identifiers
+            .iter()
+            .find(|info| info.ident == ident)
+            .map(|info| info.type_info)
+            .expect("the identifier is declared by the schema");
+        let invalid = |value: &IdentifierValue| StreamQueryError::InvalidValue {
+            ident: ident.to_string(),
+            value: value.to_string(),
+            expected,
+        };
+        match (&value, expected) {
+            (value, expected) if value.identifier_type() == expected => Ok(value.clone()),
+            (IdentifierValue::String(text), IdentifierType::i64) => i64::from_str(text)
+                .map(IdentifierValue::i64)
+                .map_err(|_| invalid(&value)),
+            (IdentifierValue::String(text), IdentifierType::Uuid) => Uuid::from_str(text)
+                .map(IdentifierValue::Uuid)
+                .map_err(|_| invalid(&value)),
+            _ => Err(invalid(&value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query;
+    use crate::utils::tests::*;
+
+    #[test]
+    fn it_builds_the_same_query_of_the_macro() {
+        let query: StreamQuery<i64, ShoppingCartEvent> = StreamQueryBuilder::events()
+            .ident("cart_id", "c1")
+            .not_eq("item_id", "i1")
+            .is_in("item_id", ["i2", "i3"])
+            .exclude("ItemRemoved")
+            .origin(10)
+            .build()
+            .unwrap();
+
+        let item_ids = ["i2", "i3"];
+        let expected: StreamQuery<i64, ShoppingCartEvent> =
+            query!(10 => ShoppingCartEvent; cart_id == "c1", item_id != "i1", item_id in item_ids)
+                .extend_excluded_events(&["ItemRemoved"]);
+        assert_eq!(query, expected);
+    }
+
+    #[test]
+    fn it_builds_a_query_selecting_all_the_events() {
+        let query: StreamQuery<i64, ShoppingCartEvent> =
+            StreamQueryBuilder::events().build().unwrap();
+
+        assert_eq!(query, query!(ShoppingCartEvent));
+    }
+
+    #[test]
+    fn it_fails_when_the_identifier_does_not_exist() {
+        let error = StreamQueryBuilder::<i64, ShoppingCartEvent>::events()
+            .ident("customer_id", "c1")
+            .ident("cart_id", "c1")
+            .build()
+            .unwrap_err();
+
+        assert_eq!(
+            error,
+            StreamQueryError::UnknownIdentifier("customer_id".to_string())
+        );
+    }
+
+    #[test]
+    fn it_fails_when_the_event_does_not_exist() {
+        let error = StreamQueryBuilder::<i64, ShoppingCartEvent>::events()
+            .exclude("CartCleared")
+            .build()
+            .unwrap_err();
+
+        assert_eq!(
+            error,
+            StreamQueryError::UnknownEvent("CartCleared".to_string())
+        );
+    }
+
+    #[test]
+    fn it_fails_when_the_value_has_a_different_type() {
+        let error = StreamQueryBuilder::<i64, ShoppingCartEvent>::events()
+            .is_in("cart_id", [1, 2])
+            .build()
+            .unwrap_err();
+
+        assert_eq!(
+            error,
+            StreamQueryError::InvalidValue {
+                ident: "cart_id".to_string(),
+                value: "1".to_string(),
+                expected: IdentifierType::String,
+            }
+        );
+    }
+}
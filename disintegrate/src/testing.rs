@@ -1,10 +1,28 @@
 //! Utility for testing a Decision implementation
 //!
 //! The test harness allows you to set up a history of events, perform the given decision,
-//! and make assertions about the resulting changes.
+//! and make assertions about the resulting changes. Events appended by a concurrent writer after the state has been
+//! loaded can be injected between the "given" and the "when" steps, to check that the decision is rejected by a
+//! concurrency conflict or that its validation query tolerates the interleaving.
+//!
+//! The state can also start from a snapshot, and the validation query and the version the changes would be
+//! persisted with can be asserted, to cover the `validation_query` implementations without an event store.
+//!
+//! A chain of decisions can be performed in the "when" step, each one deciding on the changes of the previous
+//! ones, to test a flow without listing its intermediate events.
+//!
+//! The event listeners are tested with the `ListenerTestHarness`, delivering the events with the faults of the
+//! at-least-once delivery.
+mod listener;
+
+pub use listener::{ListenerDelivery, ListenerTestHarness};
+
 use std::fmt::Debug;
 
-use crate::{Decision, Event, IntoState, IntoStatePart, MultiState, PersistedEvent};
+use crate::{
+    all_the_tuples, Decision, Event, IntoState, IntoStatePart, MultiState, PersistedEvent,
+    StreamQuery,
+};
 
 /// Test harness for testing decisions.
 pub struct TestHarness;
@@ -22,26 +40,109 @@ impl TestHarness {
     pub fn given<E: Event + Clone>(history: impl Into<Vec<E>>) -> TestHarnessStep<E, Given> {
         TestHarnessStep {
             history: history.into(),
-            _step: Given,
+            concurrent: vec![],
+            _step: Given(()),
         }
     }
+
+    /// Sets up a snapshot of the state, e.g. `StatePart::new(3, state)`, or a tuple of state parts for a
+    /// multi-state.
+    ///
+    /// The events given with `and` are applied on top of the snapshot, with IDs following its version.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - The state parts restored from the snapshot, with their versions.
+    ///
+    /// # Returns
+    ///
+    /// A `TestHarnessStep` representing the "given" step.
+    pub fn given_snapshot<E: Event + Clone, SP>(
+        snapshot: SP,
+    ) -> TestHarnessStep<E, Given<Snapshot<SP>>> {
+        TestHarnessStep {
+            history: vec![],
+            concurrent: vec![],
+            _step: Given(Snapshot(snapshot)),
+        }
+    }
+}
+
+/// Represents the given step of the test harness, starting from a snapshot or from an empty state.
+pub struct Given<SN = ()>(SN);
+
+/// The state parts restored from a snapshot.
+pub struct Snapshot<SP>(SP);
+
+/// Provides the state parts a decision starts from, before the history is applied.
+pub trait InitialState<S, SP> {
+    /// Returns the initial state parts of the given state query.
+    fn into_state_parts(self, state_query: S) -> SP;
+}
+
+impl<S, SP> InitialState<S, SP> for ()
+where
+    S: IntoStatePart<i64, S, Target = SP>,
+{
+    fn into_state_parts(self, state_query: S) -> SP {
+        state_query.into_state_part()
+    }
 }
 
-/// Represents the given step of the test harness.
-pub struct Given;
+impl<S, SP> InitialState<S, SP> for Snapshot<SP> {
+    fn into_state_parts(self, _state_query: S) -> SP {
+        self.0
+    }
+}
 
 /// Represents when step of the test harness.
-pub struct When<R, ERR> {
+pub struct When<R: Event + Clone, ERR> {
     result: Result<Vec<R>, ERR>,
+    conflict: Option<&'static str>,
+    validation_query: Option<StreamQuery<i64, R>>,
+    version: i64,
 }
 
 pub struct TestHarnessStep<E, ST> {
     history: Vec<E>,
+    concurrent: Vec<E>,
     _step: ST,
 }
 
-impl<E: Event + Clone> TestHarnessStep<E, Given> {
-    /// Executes a decision on the state derived from the given history.
+impl<E: Event + Clone, SN> TestHarnessStep<E, Given<SN>> {
+    /// Adds events to the history, e.g. the events appended after a snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The events appended to the history.
+    ///
+    /// # Returns
+    ///
+    /// A `TestHarnessStep` representing the "given" step.
+    pub fn and(mut self, events: impl Into<Vec<E>>) -> Self {
+        self.history.extend(events.into());
+        self
+    }
+
+    /// Simulates events appended by a concurrent writer after the state of the decision has been loaded.
+    ///
+    /// The events do not change the state the decision is made on. If any of them matches the validation query
+    /// of the decision, or its state query when no validation query is provided, the changes are rejected with
+    /// a concurrency conflict, as the event store would do.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The events appended by the concurrent writer.
+    ///
+    /// # Returns
+    ///
+    /// A `TestHarnessStep` representing the "given" step.
+    pub fn concurrently(mut self, events: impl Into<Vec<E>>) -> Self {
+        self.concurrent.extend(events.into());
+        self
+    }
+
+    /// Executes a decision on the state derived from the given snapshot and history.
     ///
     /// # Arguments
     ///
@@ -53,32 +154,210 @@ impl<E: Event + Clone> TestHarnessStep<E, Given> {
     pub fn when<D, SP, S, ERR>(self, decision: D) -> TestHarnessStep<E, When<E, ERR>>
     where
         D: Decision<Event = E, Error = ERR, StateQuery = S>,
-        S: IntoStatePart<i64, S, Target = SP>,
+        SN: InitialState<S, SP>,
         SP: IntoState<S> + MultiState<i64, E>,
     {
-        let mut state = decision.state_query().into_state_part();
-        for event in self
-            .history
-            .iter()
-            .enumerate()
-            .map(|(id, event)| PersistedEvent::new((id + 1) as i64, event.clone()))
-        {
-            state.mutate_all(event);
+        let when = decide(&decision, self._step.0, &self.history);
+        TestHarnessStep {
+            _step: when.with_conflict(&self.concurrent),
+            history: self.history,
+            concurrent: self.concurrent,
         }
-        let result = decision.process(&state.into_state());
+    }
+}
+
+impl<E: Event + Clone> TestHarnessStep<E, Given> {
+    /// Executes a chain of decisions, e.g. `(OpenAccount::new(1), DepositAmount::new(1, 20))`, or an array of
+    /// decisions of the same type.
+    ///
+    /// The changes of each decision are appended to the history before the next decision runs, so the
+    /// assertions are made on the last decision without listing the intermediate events. The chain stops at
+    /// the first decision that fails, whose error is asserted by `then_err`. The concurrent events are
+    /// appended after the state of the last decision has been loaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `decisions` - The decisions to test, in order.
+    ///
+    /// # Returns
+    ///
+    /// A `TestHarnessStep` representing the "when" step.
+    pub fn when_chain<ERR>(
+        mut self,
+        decisions: impl DecisionChain<E, ERR>,
+    ) -> TestHarnessStep<E, When<E, ERR>> {
+        let when = decisions.decide_all(&mut self.history);
         TestHarnessStep {
+            _step: when.with_conflict(&self.concurrent),
             history: self.history,
-            _step: When { result },
+            concurrent: self.concurrent,
+        }
+    }
+}
+
+/// Executes a decision on the initial state parts updated with the history.
+fn decide<D, SN, SP, S, E, ERR>(decision: &D, initial: SN, history: &[E]) -> When<E, ERR>
+where
+    E: Event + Clone,
+    D: Decision<Event = E, Error = ERR, StateQuery = S>,
+    SN: InitialState<S, SP>,
+    SP: IntoState<S> + MultiState<i64, E>,
+{
+    let mut state = initial.into_state_parts(decision.state_query());
+    let first_id = state.version() + 1;
+    for (id, event) in history.iter().enumerate() {
+        state.mutate_all(PersistedEvent::new(first_id + id as i64, event.clone()));
+    }
+    let version = state.version();
+    let state_query = state.query_all();
+    let result = decision.process(&state.into_state());
+    let validation_query = result
+        .is_ok()
+        .then(|| decision.validation_query().unwrap_or(state_query));
+    When {
+        result,
+        conflict: None,
+        validation_query,
+        version,
+    }
+}
+
+impl<E: Event + Clone, ERR> When<E, ERR> {
+    /// Finds the first concurrent event invalidating the changes, appended after the loaded state.
+    fn with_conflict(self, concurrent: &[E]) -> Self {
+        let conflict = self.validation_query.as_ref().and_then(|validation_query| {
+            concurrent
+                .iter()
+                .enumerate()
+                .map(|(id, event)| PersistedEvent::new(self.version + id as i64 + 1, event.clone()))
+                .find(|event| validation_query.matches(event))
+                .map(|event| event.name())
+        });
+        Self { conflict, ..self }
+    }
+}
+
+/// A chain of decisions executed one after the other by `when_chain`.
+///
+/// It is implemented for the arrays of decisions of the same type, and for the tuples of decisions of
+/// different types sharing the event and the error types.
+pub trait DecisionChain<E: Event + Clone, ERR> {
+    /// Executes the decisions in order, appending the changes of each decision to the history.
+    ///
+    /// # Arguments
+    ///
+    /// * `history` - The history of events, extended with the changes of the decisions.
+    ///
+    /// # Returns
+    ///
+    /// The outcome of the last decision, or of the first decision that fails.
+    fn decide_all(self, history: &mut Vec<E>) -> When<E, ERR>;
+}
+
+impl<E, ERR, D, SP, S, const N: usize> DecisionChain<E, ERR> for [D; N]
+where
+    E: Event + Clone,
+    D: Decision<Event = E, Error = ERR, StateQuery = S>,
+    S: IntoStatePart<i64, S, Target = SP>,
+    SP: IntoState<S> + MultiState<i64, E>,
+{
+    fn decide_all(self, history: &mut Vec<E>) -> When<E, ERR> {
+        let mut decisions = self.into_iter().peekable();
+        loop {
+            let decision = decisions
+                .next()
+                .expect("the chain has at least one decision");
+            let when = decide(&decision, (), history);
+            if decisions.peek().is_none() {
+                return when;
+            }
+            match when.result {
+                Ok(changes) => history.extend(changes),
+                Err(_) => return when,
+            }
+        }
+    }
+}
+
+macro_rules! impl_decision_chain {
+    (
+        [$($ty:ident),*], $last:ident
+    ) => {
+        impl<E, ERR, $($ty,)* $last> DecisionChain<E, ERR> for ($($ty,)* $last,)
+        where
+            E: Event + Clone,
+            $(
+                $ty: Decision<Event = E, Error = ERR>,
+                <$ty as Decision>::StateQuery: IntoStatePart<i64, <$ty as Decision>::StateQuery>,
+                <<$ty as Decision>::StateQuery as IntoStatePart<i64, <$ty as Decision>::StateQuery>>::Target:
+                    IntoState<<$ty as Decision>::StateQuery> + MultiState<i64, E>,
+            )*
+            $last: Decision<Event = E, Error = ERR>,
+            <$last as Decision>::StateQuery: IntoStatePart<i64, <$last as Decision>::StateQuery>,
+            <<$last as Decision>::StateQuery as IntoStatePart<i64, <$last as Decision>::StateQuery>>::Target:
+                IntoState<<$last as Decision>::StateQuery> + MultiState<i64, E>,
+        {
+            fn decide_all(self, history: &mut Vec<E>) -> When<E, ERR> {
+                paste::paste! {
+                    let ($([<decision_ $ty:lower>],)* [<decision_ $last:lower>],) = self;
+                    $(
+                        let when = decide(&[<decision_ $ty:lower>], (), history);
+                        match when.result {
+                            Ok(changes) => history.extend(changes),
+                            Err(_) => return when,
+                        }
+                    )*
+                    decide(&[<decision_ $last:lower>], (), history)
+                }
+            }
         }
     }
 }
 
+all_the_tuples!(impl_decision_chain);
+
 impl<R, E, ERR> TestHarnessStep<E, When<R, ERR>>
 where
     E: Event + Clone + PartialEq,
-    R: Debug + PartialEq,
+    R: Event + Clone + Debug + PartialEq,
     ERR: Debug + PartialEq,
 {
+    /// Makes assertions about the query validating the changes when they are persisted: the validation query
+    /// of the decision, or the query of its state when no validation query is provided.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The expected validation query.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the action result is not `Ok`, as the changes are not persisted, or if the validation query
+    /// does not match the expected query.
+    #[track_caller]
+    pub fn then_expect_validation_query(self, expected: StreamQuery<i64, R>) -> Self {
+        let Some(validation_query) = &self._step.validation_query else {
+            panic!("the decision failed: {:?}", self._step.result);
+        };
+        assert_eq!(&expected, validation_query);
+        self
+    }
+
+    /// Makes assertions about the version the changes are persisted with, i.e. the ID of the last event
+    /// applied to the state.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The expected version.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the version does not match the expected version.
+    #[track_caller]
+    pub fn then_expect_version(self, expected: i64) -> Self {
+        assert_eq!(expected, self._step.version);
+        self
+    }
+
     /// Makes assertions about the changes.
     ///
     /// # Arguments
@@ -87,12 +366,34 @@ where
     ///
     /// # Panics
     ///
-    /// Panics if the action result is not `Ok` or if the changes do not match the expected changes.
+    /// Panics if the action result is not `Ok`, if the changes do not match the expected changes,
+    /// or if the changes conflict with the concurrent events.
     ///
     /// # Examples
     #[track_caller]
     pub fn then(self, expected: impl Into<Vec<R>>) {
         assert_eq!(Ok(expected.into()), self._step.result);
+        if let Some(event) = self._step.conflict {
+            panic!("the decision conflicts with the concurrent `{event}` event");
+        }
+    }
+
+    /// Makes assertions about a concurrency conflict with the concurrent events.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the action result is not `Ok` or if no concurrent event invalidates the decision.
+    #[track_caller]
+    pub fn then_conflict(self) {
+        assert!(
+            self._step.result.is_ok(),
+            "the decision failed: {:?}",
+            self._step.result
+        );
+        assert!(
+            self._step.conflict.is_some(),
+            "the decision does not conflict with the concurrent events"
+        );
     }
 
     /// Makes assertions about the expected error result.
@@ -117,6 +418,7 @@ mod tests {
 
     use super::*;
     use crate::utils::tests::*;
+    use crate::{query, StatePart, StateQuery};
 
     #[test]
     fn it_should_set_up_initial_state_and_apply_the_history() {
@@ -125,6 +427,10 @@ mod tests {
             .expect_state_query()
             .once()
             .return_once(|| cart("c1", []));
+        mock_add_item
+            .expect_validation_query()
+            .once()
+            .return_once(|| Option::<StreamQuery<i64, ShoppingCartEvent>>::None);
         mock_add_item
             .expect_process()
             .once()
@@ -177,4 +483,179 @@ mod tests {
             .when(mock_add_item)
             .then_err(CartError("Some error".to_string()));
     }
+
+    #[test]
+    fn it_should_assert_a_conflict_with_a_concurrent_event() {
+        let mut mock_add_item = MockDecision::new();
+        mock_add_item
+            .expect_state_query()
+            .once()
+            .return_once(|| cart("c1", []));
+        mock_add_item
+            .expect_validation_query()
+            .once()
+            .return_once(|| Option::<StreamQuery<i64, ShoppingCartEvent>>::None);
+        mock_add_item
+            .expect_process()
+            .once()
+            .return_once(|_| Ok(vec![item_added_event("p2", "c1")]));
+
+        TestHarness::given([item_added_event("p1", "c1")])
+            .concurrently([item_added_event("p3", "c1")])
+            .when(mock_add_item)
+            .then_conflict();
+    }
+
+    #[test]
+    fn it_should_tolerate_the_concurrent_events_outside_the_validation_query() {
+        let mut mock_add_item = MockDecision::new();
+        mock_add_item
+            .expect_state_query()
+            .once()
+            .return_once(|| cart("c1", []));
+        mock_add_item
+            .expect_validation_query()
+            .once()
+            .return_once(|| Option::<StreamQuery<i64, ShoppingCartEvent>>::None);
+        mock_add_item
+            .expect_process()
+            .once()
+            .return_once(|_| Ok(vec![item_added_event("p2", "c1")]));
+
+        TestHarness::given([item_added_event("p1", "c1")])
+            .concurrently([item_added_event("p3", "c2")])
+            .when(mock_add_item)
+            .then([item_added_event("p2", "c1")]);
+    }
+
+    #[test]
+    fn it_should_apply_the_history_on_top_of_a_snapshot() {
+        let mut mock_add_item = MockDecision::new();
+        mock_add_item
+            .expect_state_query()
+            .once()
+            .return_once(|| cart("c1", []));
+        mock_add_item
+            .expect_validation_query()
+            .once()
+            .return_once(|| Option::<StreamQuery<i64, ShoppingCartEvent>>::None);
+        mock_add_item
+            .expect_process()
+            .once()
+            .withf(|state| state == &cart("c1", ["p1".to_string(), "p2".to_string()]))
+            .return_once(|_| Ok(vec![item_added_event("p3", "c1")]));
+
+        TestHarness::given_snapshot(StatePart::new(3, cart("c1", ["p1".to_string()])))
+            .and([item_added_event("p2", "c1")])
+            .when(mock_add_item)
+            .then_expect_version(4)
+            .then_expect_validation_query(cart("c1", []).query().change_origin(4))
+            .then([item_added_event("p3", "c1")]);
+    }
+
+    #[test]
+    fn it_should_assert_the_validation_query_of_the_decision() {
+        let mut mock_add_item = MockDecision::new();
+        mock_add_item
+            .expect_state_query()
+            .once()
+            .return_once(|| cart("c1", []));
+        mock_add_item
+            .expect_validation_query::<i64>()
+            .once()
+            .return_once(|| Some(query!(ShoppingCartEvent; item_id == "p1")));
+        mock_add_item
+            .expect_process()
+            .once()
+            .return_once(|_| Ok(vec![item_removed_event("p1", "c1")]));
+
+        TestHarness::given([item_added_event("p1", "c1")])
+            .when(mock_add_item)
+            .then_expect_version(1)
+            .then_expect_validation_query(query!(ShoppingCartEvent; item_id == "p1"))
+            .then([item_removed_event("p1", "c1")]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn it_should_panic_when_the_validation_query_is_not_the_expected_one() {
+        let mut mock_add_item = MockDecision::new();
+        mock_add_item
+            .expect_state_query()
+            .once()
+            .return_once(|| cart("c1", []));
+        mock_add_item
+            .expect_validation_query()
+            .once()
+            .return_once(|| Option::<StreamQuery<i64, ShoppingCartEvent>>::None);
+        mock_add_item
+            .expect_process()
+            .once()
+            .return_once(|_| Ok(vec![item_added_event("p2", "c1")]));
+
+        TestHarness::given([item_added_event("p1", "c1")])
+            .when(mock_add_item)
+            .then_expect_validation_query(query!(ShoppingCartEvent; item_id == "p1"));
+    }
+
+    fn mock_add_item(state: Cart, item_id: &'static str) -> MockDecision {
+        let mut mock_add_item = MockDecision::new();
+        mock_add_item
+            .expect_state_query()
+            .once()
+            .return_once(|| cart("c1", []));
+        mock_add_item
+            .expect_validation_query()
+            .once()
+            .return_once(|| Option::<StreamQuery<i64, ShoppingCartEvent>>::None);
+        mock_add_item
+            .expect_process()
+            .once()
+            .withf(move |s| s == &state)
+            .return_once(move |_| Ok(vec![item_added_event(item_id, "c1")]));
+        mock_add_item
+    }
+
+    #[test]
+    fn it_should_fold_the_changes_of_a_chain_of_decisions() {
+        TestHarness::given([item_added_event("p1", "c1")])
+            .when_chain([
+                mock_add_item(cart("c1", ["p1".to_string()]), "p2"),
+                mock_add_item(cart("c1", ["p1".to_string(), "p2".to_string()]), "p3"),
+            ])
+            .then_expect_version(2)
+            .then([item_added_event("p3", "c1")]);
+    }
+
+    #[test]
+    fn it_should_stop_the_chain_at_the_first_error() {
+        let mut mock_remove_item = MockDecision::new();
+        mock_remove_item
+            .expect_state_query()
+            .once()
+            .return_once(|| cart("c1", []));
+        mock_remove_item
+            .expect_process()
+            .once()
+            .return_once(|_| Err(CartError("Some error".to_string())));
+
+        TestHarness::given([])
+            .when_chain((
+                mock_add_item(cart("c1", []), "p1"),
+                mock_remove_item,
+                MockDecision::new(),
+            ))
+            .then_err(CartError("Some error".to_string()));
+    }
+
+    #[test]
+    fn it_should_assert_a_conflict_with_the_last_decision_of_a_chain() {
+        TestHarness::given([])
+            .concurrently([item_added_event("p3", "c1")])
+            .when_chain([
+                mock_add_item(cart("c1", []), "p1"),
+                mock_add_item(cart("c1", ["p1".to_string()]), "p2"),
+            ])
+            .then_conflict();
+    }
 }
@@ -0,0 +1,307 @@
+//! Utility for testing an `EventListener` implementation against the at-least-once delivery.
+//!
+//! The listener test harness feeds a recorded sequence of events to an event listener as the event stores do,
+//! but with the faults allowed by the at-least-once delivery: duplicates, redeliveries and restarts in the
+//! middle of a batch. The read model built with each fault is compared with the one built when every event is
+//! delivered exactly once, so a listener that is not idempotent is caught without an event store.
+use std::fmt::Debug;
+use std::future::Future;
+
+use crate::{Event, EventListener, PersistedEvent};
+
+/// A way the events are delivered to an event listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenerDelivery {
+    /// Every event is delivered once, in order.
+    ExactlyOnce,
+    /// Every event is delivered twice in a row.
+    Duplicates,
+    /// The events are delivered again from the first one after they have all been handled, as when the
+    /// checkpoint of the listener is lost.
+    Redelivery,
+    /// The events are delivered in batches, and the listener restarts after handling the first half of each
+    /// batch, so the batch is delivered again from its first event.
+    Restarts {
+        /// The number of events of a batch.
+        batch_size: usize,
+    },
+}
+
+impl ListenerDelivery {
+    /// Returns the indexes of the events in the order they are delivered.
+    fn schedule(&self, len: usize) -> Vec<usize> {
+        match self {
+            ListenerDelivery::ExactlyOnce => (0..len).collect(),
+            ListenerDelivery::Duplicates => (0..len).flat_map(|index| [index, index]).collect(),
+            ListenerDelivery::Redelivery => (0..len).chain(0..len).collect(),
+            ListenerDelivery::Restarts { batch_size } => {
+                let batch_size = (*batch_size).max(1);
+                (0..len)
+                    .step_by(batch_size)
+                    .flat_map(|start| {
+                        let end = (start + batch_size).min(len);
+                        let restart = start + (end - start).div_ceil(2);
+                        (start..restart).chain(start..end)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Test harness for event listeners.
+///
+/// # Examples
+///
+/// ```ignore
+/// ListenerTestHarness::new(|| CartCounter::default())
+///     .given([item_added_event("p1", "c1"), item_added_event("p2", "c1")])
+///     .then_idempotent(|listener| async move { listener.count("c1") })
+///     .await;
+/// ```
+pub struct ListenerTestHarness<F, E> {
+    listener: F,
+    events: Vec<E>,
+    deliveries: Vec<ListenerDelivery>,
+}
+
+impl<F, L, E> ListenerTestHarness<F, E>
+where
+    F: Fn() -> L,
+    L: EventListener<i64, E>,
+    L::Error: Debug,
+    E: Event + Clone,
+{
+    /// Creates a new `ListenerTestHarness`.
+    ///
+    /// The events are delivered with duplicates, with a redelivery and with restarts in batches of 2 events.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - The function creating the listener, with an empty read model, for each delivery.
+    pub fn new(listener: F) -> Self {
+        Self {
+            listener,
+            events: vec![],
+            deliveries: vec![
+                ListenerDelivery::Duplicates,
+                ListenerDelivery::Redelivery,
+                ListenerDelivery::Restarts { batch_size: 2 },
+            ],
+        }
+    }
+
+    /// Sets the recorded events delivered to the listener.
+    ///
+    /// The events are persisted with the IDs from 1, and only those matching the query of the listener
+    /// are delivered.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The recorded events, in order.
+    ///
+    /// # Returns
+    ///
+    /// The `ListenerTestHarness` with the recorded events.
+    pub fn given(mut self, events: impl Into<Vec<E>>) -> Self {
+        self.events = events.into();
+        self
+    }
+
+    /// Replaces the faulty deliveries checked against the exactly-once delivery.
+    ///
+    /// # Arguments
+    ///
+    /// * `deliveries` - The deliveries of the events.
+    ///
+    /// # Returns
+    ///
+    /// The `ListenerTestHarness` with the given deliveries.
+    pub fn with_deliveries(mut self, deliveries: impl Into<Vec<ListenerDelivery>>) -> Self {
+        self.deliveries = deliveries.into();
+        self
+    }
+
+    /// Asserts that the read model is the expected one with the exactly-once delivery and with every
+    /// faulty delivery.
+    ///
+    /// # Arguments
+    ///
+    /// * `read_model` - The function reading the read model of a listener after the delivery.
+    /// * `expected` - The expected read model.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the listener fails to handle an event, or if the read model differs from the expected one.
+    pub async fn then<R, Fut, T>(self, read_model: R, expected: T)
+    where
+        R: Fn(L) -> Fut,
+        Fut: Future<Output = T>,
+        T: PartialEq + Debug,
+    {
+        for delivery in
+            std::iter::once(ListenerDelivery::ExactlyOnce).chain(self.deliveries.iter().copied())
+        {
+            let actual = read_model(self.deliver(delivery).await).await;
+            assert_eq!(
+                actual, expected,
+                "unexpected read model with the {delivery:?} delivery"
+            );
+        }
+    }
+
+    /// Asserts that the read model built with every faulty delivery is the one built with the
+    /// exactly-once delivery.
+    ///
+    /// # Arguments
+    ///
+    /// * `read_model` - The function reading the read model of a listener after the delivery.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the listener fails to handle an event, or if a read model differs from the one built with
+    /// the exactly-once delivery.
+    pub async fn then_idempotent<R, Fut, T>(self, read_model: R)
+    where
+        R: Fn(L) -> Fut,
+        Fut: Future<Output = T>,
+        T: PartialEq + Debug,
+    {
+        let expected = read_model(self.deliver(ListenerDelivery::ExactlyOnce).await).await;
+        for delivery in &self.deliveries {
+            let actual = read_model(self.deliver(*delivery).await).await;
+            assert_eq!(
+                actual, expected,
+                "the listener is not idempotent with the {delivery:?} delivery"
+            );
+        }
+    }
+
+    /// Delivers the events to a new listener.
+    async fn deliver(&self, delivery: ListenerDelivery) -> L {
+        let listener = (self.listener)();
+        let events: Vec<_> = self
+            .events
+            .iter()
+            .enumerate()
+            .map(|(index, event)| PersistedEvent::new(index as i64 + 1, event.clone()))
+            .filter(|event| listener.query().matches(event))
+            .collect();
+        for index in delivery.schedule(events.len()) {
+            let event = events[index].clone();
+            let event_id = event.id();
+            if let Err(err) = listener.handle(event).await {
+                panic!("the listener failed to handle the event {event_id} with the {delivery:?} delivery: {err:?}");
+            }
+        }
+        listener
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::utils::tests::*;
+    use crate::{query, StreamQuery};
+
+    struct CartItems {
+        query: StreamQuery<i64, ShoppingCartEvent>,
+        idempotent: bool,
+        handled: Mutex<BTreeSet<i64>>,
+        items: Mutex<BTreeMap<String, u32>>,
+    }
+
+    impl CartItems {
+        fn new(idempotent: bool) -> Self {
+            Self {
+                query: query!(ShoppingCartEvent; cart_id == "c1"),
+                idempotent,
+                handled: Mutex::new(BTreeSet::new()),
+                items: Mutex::new(BTreeMap::new()),
+            }
+        }
+
+        fn items(&self) -> BTreeMap<String, u32> {
+            self.items.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl EventListener<i64, ShoppingCartEvent> for CartItems {
+        type Error = std::convert::Infallible;
+
+        fn id(&self) -> &'static str {
+            "cart_items"
+        }
+
+        fn query(&self) -> &StreamQuery<i64, ShoppingCartEvent> {
+            &self.query
+        }
+
+        async fn handle(
+            &self,
+            event: PersistedEvent<i64, ShoppingCartEvent>,
+        ) -> Result<(), Self::Error> {
+            if !self.handled.lock().unwrap().insert(event.id()) && self.idempotent {
+                return Ok(());
+            }
+            let mut items = self.items.lock().unwrap();
+            match event.into_inner() {
+                ShoppingCartEvent::ItemAdded { item_id, .. } => {
+                    *items.entry(item_id).or_default() += 1;
+                }
+                ShoppingCartEvent::ItemRemoved { item_id, .. } => {
+                    *items.entry(item_id).or_default() -= 1;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_schedules_the_faulty_deliveries() {
+        assert_eq!(ListenerDelivery::ExactlyOnce.schedule(3), vec![0, 1, 2]);
+        assert_eq!(
+            ListenerDelivery::Duplicates.schedule(3),
+            vec![0, 0, 1, 1, 2, 2]
+        );
+        assert_eq!(
+            ListenerDelivery::Redelivery.schedule(3),
+            vec![0, 1, 2, 0, 1, 2]
+        );
+        assert_eq!(
+            ListenerDelivery::Restarts { batch_size: 3 }.schedule(5),
+            vec![0, 1, 0, 1, 2, 3, 3, 4]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_asserts_the_read_model_of_an_idempotent_listener() {
+        ListenerTestHarness::new(|| CartItems::new(true))
+            .given([
+                item_added_event("p1", "c1"),
+                item_added_event("p2", "c2"),
+                item_added_event("p1", "c1"),
+                item_removed_event("p1", "c1"),
+            ])
+            .then(
+                |listener| async move { listener.items() },
+                BTreeMap::from([("p1".to_string(), 1)]),
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "the listener is not idempotent with the Duplicates delivery")]
+    async fn it_rejects_a_listener_applying_the_duplicates() {
+        ListenerTestHarness::new(|| CartItems::new(false))
+            .given([item_added_event("p1", "c1"), item_added_event("p2", "c1")])
+            .then_idempotent(|listener| async move { listener.items() })
+            .await;
+    }
+}
@@ -259,10 +259,18 @@ pub mod tests {
                 &EventInfo {
                     name: "ItemAdded",
                     domain_identifiers: &[&ident!(#item_id), &ident!(#cart_id)],
+                    description: None,
+                    owner: None,
+                    aliases: &[],
+                    version: 1,
                 },
                 &EventInfo {
                     name: "ItemRemoved",
                     domain_identifiers: &[&ident!(#item_id), &ident!(#cart_id)],
+                    description: None,
+                    owner: None,
+                    aliases: &[],
+                    version: 1,
                 },
             ],
             domain_identifiers: &[
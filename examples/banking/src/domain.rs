@@ -445,6 +445,43 @@ mod test {
         }]);
     }
 
+    #[test]
+    fn it_withdraws_an_amount_when_a_deposit_is_made_concurrently() {
+        disintegrate::TestHarness::given([
+            DomainEvent::AccountOpened { account_id: 1 },
+            DomainEvent::AmountDeposited {
+                account_id: 1,
+                amount: 10,
+            },
+        ])
+        .concurrently([DomainEvent::AmountDeposited {
+            account_id: 1,
+            amount: 5,
+        }])
+        .when(WithdrawAmount::new(1, 10))
+        .then([DomainEvent::AmountWithdrawn {
+            account_id: 1,
+            amount: 10,
+        }]);
+    }
+
+    #[test]
+    fn it_should_not_withdraw_an_amount_when_a_withdrawal_is_made_concurrently() {
+        disintegrate::TestHarness::given([
+            DomainEvent::AccountOpened { account_id: 1 },
+            DomainEvent::AmountDeposited {
+                account_id: 1,
+                amount: 10,
+            },
+        ])
+        .concurrently([DomainEvent::AmountWithdrawn {
+            account_id: 1,
+            amount: 5,
+        }])
+        .when(WithdrawAmount::new(1, 10))
+        .then_conflict();
+    }
+
     #[test]
     fn it_should_not_withdraw_an_amount_from_an_account_that_does_not_exist() {
         disintegrate::TestHarness::given([])
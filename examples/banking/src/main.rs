@@ -121,8 +121,9 @@ impl error::ResponseError for Error {
     fn status_code(&self) -> StatusCode {
         match self.source {
             disintegrate::DecisionError::Domain(_) => StatusCode::BAD_REQUEST,
-            disintegrate::DecisionError::EventStore(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            disintegrate::DecisionError::StateStore(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            disintegrate::DecisionError::Invalid(_) => StatusCode::BAD_REQUEST,
+            disintegrate::DecisionError::Rejected(_) => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
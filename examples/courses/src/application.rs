@@ -9,16 +9,10 @@ pub type DecisionMaker =
     PgDecisionMaker<DomainEvent, Prost<DomainEvent, proto::Event>, WithPgSnapshot>;
 
 #[derive(Clone)]
-pub struct Application {
-    decision_maker: DecisionMaker,
-    read_model: read_model::Repository,
-}
+pub struct Application(disintegrate::Application<DecisionMaker, read_model::Repository>);
 
 impl Application {
     pub fn new(decision_maker: DecisionMaker, read_model: read_model::Repository) -> Self {
-        Self {
-            decision_maker,
-            read_model,
-        }
+        Self(disintegrate::Application::new(decision_maker, read_model))
     }
 }
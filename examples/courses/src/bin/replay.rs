@@ -0,0 +1,45 @@
+//! Replays the events of the courses read model.
+//!
+//! Usage: `cargo run --bin replay [origin]`. The read model is cleared when the replay starts from
+//! the beginning of the event stream.
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use disintegrate::{serde::prost::Prost, Projection, ProjectionListener};
+use disintegrate_postgres::{PgEventStore, PgReplayer, ReplayProgress};
+use sqlx::{postgres::PgConnectOptions, PgPool};
+
+use courses::{domain::DomainEvent, proto, read_model};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv::dotenv().unwrap();
+
+    let origin: i64 = match std::env::args().nth(1) {
+        Some(origin) => origin.parse().context("the origin must be an event ID")?,
+        None => 0,
+    };
+
+    let pool = PgPool::connect_with(PgConnectOptions::new()).await?;
+    let serde = Prost::<DomainEvent, proto::Event>::default();
+    let event_store = PgEventStore::new(pool.clone(), serde).await?;
+    let projection = Arc::new(read_model::ReadModelProjection::new(pool).await?);
+
+    let mut replayer = PgReplayer::new(event_store, ProjectionListener::new(projection.clone()))
+        .from(origin)
+        .max_events_per_second(1000)
+        .on_progress(500, |progress: &ReplayProgress| {
+            println!(
+                "replayed {} events, up to {} of {} in {:?}",
+                progress.replayed,
+                progress.last_event_id,
+                progress.target_event_id,
+                progress.elapsed
+            )
+        });
+    if origin == 0 {
+        replayer = replayer.reset_with(move || async move { projection.reset().await });
+    }
+    replayer.run().await?;
+    Ok(())
+}
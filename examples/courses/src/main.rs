@@ -3,7 +3,9 @@ use std::time::Duration;
 use anyhow::{anyhow, Ok, Result};
 use application::Application;
 use disintegrate::{serde::prost::Prost, WithSnapshot};
-use disintegrate_postgres::{PgEventListener, PgEventListenerConfig, PgEventStore, PgSnapshotter};
+use disintegrate_postgres::{
+    PgEventListenerConfig, PgEventStore, PgProjectionRunner, PgSnapshotter,
+};
 use sqlx::{postgres::PgConnectOptions, PgPool};
 use tokio::signal;
 use tracing_subscriber::{self, fmt::format::FmtSpan};
@@ -67,8 +69,8 @@ async fn grpc_server(app: Application) -> Result<()> {
 }
 
 async fn event_listener(pool: sqlx::PgPool, event_store: EventStore) -> Result<()> {
-    PgEventListener::builder(event_store)
-        .register_listener(
+    PgProjectionRunner::builder(event_store)
+        .register(
             read_model::ReadModelProjection::new(pool).await?,
             PgEventListenerConfig::poller(Duration::from_secs(5)).with_notifier(),
         )
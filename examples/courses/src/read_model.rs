@@ -1,6 +1,6 @@
 use crate::domain::{CourseId, DomainEvent};
 use async_trait::async_trait;
-use disintegrate::{query, EventListener, PersistedEvent, StreamQuery};
+use disintegrate::{query, IdempotencyKey, PersistedEvent, Projection, StreamQuery};
 use sqlx::{FromRow, PgPool};
 
 #[derive(Clone)]
@@ -57,9 +57,9 @@ impl ReadModelProjection {
 }
 
 #[async_trait]
-impl EventListener<i64, DomainEvent> for ReadModelProjection {
+impl Projection<i64, DomainEvent> for ReadModelProjection {
     type Error = sqlx::Error;
-    fn id(&self) -> &'static str {
+    fn name(&self) -> &'static str {
         "courses"
     }
 
@@ -67,8 +67,12 @@ impl EventListener<i64, DomainEvent> for ReadModelProjection {
         &self.query
     }
 
-    async fn handle(&self, event: PersistedEvent<i64, DomainEvent>) -> Result<(), Self::Error> {
-        let event_id = event.id();
+    async fn apply(
+        &self,
+        event: PersistedEvent<i64, DomainEvent>,
+        key: IdempotencyKey<i64>,
+    ) -> Result<(), Self::Error> {
+        let event_id = key.event_id();
         match event.into_inner() {
             DomainEvent::CourseCreated {
                 course_id,
@@ -129,4 +133,11 @@ impl EventListener<i64, DomainEvent> for ReadModelProjection {
         }
         Ok(())
     }
+
+    async fn reset(&self) -> Result<(), Self::Error> {
+        sqlx::query("DELETE FROM course")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
 }